@@ -1,7 +1,14 @@
 #![forbid(unsafe_code)]
 
 use frontend::ast::*;
+use frontend::consteval::ConstEnv;
+use frontend::names::expr_referenced_names;
 use frontend::parser::Parser;
+use frontend::reach::first_diverging;
+use frontend::target::{int_range, IntModel, Overflow, Target};
+use frontend::traits::drop_fn;
+use frontend::typecheck::TypeChecker;
+use frontend::typed::{ExprTypes, TypedProgram};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use thiserror::Error;
@@ -16,11 +23,54 @@ pub enum CgenError {
     Fmt(String),
     #[error("unknown identifier in codegen: {0}")]
     UnknownIdent(String),
+    #[error("recursive type declarations: {0}")]
+    TypeCycle(String),
+    #[error("trait error: {0}")]
+    Trait(String),
+    #[error("type error: {0}")]
+    Type(String),
+    /// Another error, raised while emitting the declaration at this source position.
+    #[error("at {0}: {1}")]
+    At(Span, Box<CgenError>),
 }
 
-#[derive(Debug, Clone)]
-struct FuncSig {
-    ret: Option<Type>,
+impl CgenError {
+    /// Attach the position of the declaration being emitted, unless the error has one.
+    fn at(self, span: Span) -> Self {
+        match self {
+            CgenError::At(..) => self,
+            _ if !span.is_known() => self,
+            _ => CgenError::At(span, Box::new(self)),
+        }
+    }
+}
+
+/// Where generated code puts values that outlive a single expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemoryModel {
+    /// Per-function bump arenas with scope marks; escaping values go to the heap.
+    #[default]
+    Arena,
+    /// No arenas: every allocation comes from the runtime heap, which is the Boehm
+    /// collector when runtime.c is built with `-DGAUT_GC` and linked with `-lgc`.
+    Gc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CgenOptions {
+    pub memory: MemoryModel,
+    /// Count call depth in every function and abort with the function name before the
+    /// native stack (which also holds each frame's arena buffer) runs out.
+    pub stack_guard: bool,
+    /// Width of the default integer; must match the model the program was checked with.
+    pub int_model: IntModel,
+    /// Whether `+ - * /` and negation abort on overflow or wrap like the interpreter's
+    /// `--overflow` setting.
+    pub overflow: Overflow,
+    /// Emit ISO C11 only, for MSVC and `-std=c11 -pedantic`: an expression that runs
+    /// statements, such as a block, is lowered into statements before the one that
+    /// uses it and a temporary holding its value, instead of GNU's `({ ... })`.
+    pub ansi: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -30,16 +80,44 @@ struct Counters {
 }
 
 #[derive(Debug, Clone)]
-struct TypeCtx {
+struct TypeCtx<'a> {
+    checked: &'a ExprTypes, // the typechecker's type for every expression of the program
     types: HashMap<String, Type>,
-    funcs: HashMap<String, FuncSig>,
-    scopes: Vec<HashMap<String, Type>>, // innermost last
+    funcs: HashSet<String>,                   // functions the program defines
+    params: HashMap<String, Vec<Type>>,       // declared parameter types of program functions
+    scopes: Vec<HashMap<String, Type>>,       // innermost last
+    fn_types: Vec<FuncType>, // function types in annotation order; typedef'd as gaut_fn_<index>
+    ref_receivers: HashMap<String, Receiver>, // functions taking a reference first; method calls pass &recv
+    memory: MemoryModel,
+    param_drops: Vec<(String, String)>, // (param, hook) for the function body being emitted
+    in_place: HashSet<String>, // record-returning functions that write through a leading `__out`
+    ret_out: bool,             // the function body being emitted returns through `__out`
+    stack_guard: bool,
+    global_init: bool,      // main calls gaut_init_globals before its body
+    expected: Option<Type>, // declared type of the value being emitted; see `emit_expr_as`
+    int_model: IntModel,
+    overflow: Overflow,
+    ansi: bool,
+    hoisted: String, // under `ansi`, statements to run before the C statement being emitted
 }
 
-impl TypeCtx {
-    fn new(program: &Program) -> Self {
+impl<'a> TypeCtx<'a> {
+    fn new(program: &Program, checked: &'a ExprTypes, int_model: IntModel) -> Self {
         let mut types = HashMap::new();
-        for name in ["i32", "i64", "u8", "bool", "Str", "Bytes", "Unit"] {
+        for name in [
+            "i32",
+            "i64",
+            "u8",
+            "bool",
+            "Str",
+            "Bytes",
+            "Unit",
+            "atomic_i64",
+            "Dyn",
+            "TcpListener",
+            "TcpConn",
+            "Chan",
+        ] {
             types.insert(name.to_string(), Type::Named(Ident(name.to_string())));
         }
         types.insert(
@@ -55,56 +133,112 @@ impl TypeCtx {
                 },
             ]),
         );
+        types.insert(
+            "CheckedInt".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("ok".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: Type::Named(Ident(int_model.type_name().into())),
+                },
+            ]),
+        );
+        types.insert(
+            "StrToIntResult".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("ok".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: Type::Named(Ident(int_model.type_name().into())),
+                },
+            ]),
+        );
+        types.insert(
+            "DynField".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("found".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: Type::Named(Ident("Str".into())),
+                },
+            ]),
+        );
 
-        let mut funcs = HashMap::new();
+        let mut funcs = HashSet::new();
+        let mut params = HashMap::new();
+        let ref_receivers: HashMap<String, Receiver> = [
+            "atomic_load",
+            "atomic_store",
+            "atomic_add",
+            "atomic_cas",
+            "dyn_set",
+            "dyn_has",
+            "dyn_get",
+            "tcp_port",
+            "tcp_accept",
+            "tcp_read",
+            "tcp_write",
+            "tcp_close",
+            "send",
+            "recv",
+        ]
+        .into_iter()
+        .map(|name| (name.to_string(), Receiver::Ref))
+        .collect();
         for decl in &program.decls {
             if let Decl::Func(f) = decl {
-                funcs.insert(f.name.0.clone(), FuncSig { ret: f.ret.clone() });
+                funcs.insert(f.name.0.clone());
+                params.insert(
+                    f.name.0.clone(),
+                    f.params.iter().map(|p| p.ty.clone()).collect(),
+                );
             }
             if let Decl::Type(t) = decl {
                 types.insert(t.name.0.clone(), t.ty.clone());
             }
         }
-        // Builtins
-        funcs.entry("print".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Str".into()))),
-        });
-        funcs.entry("println".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Str".into()))),
-        });
-        funcs.entry("read_file".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Str".into()))),
-        });
-        funcs.entry("write_file".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Unit".into()))),
-        });
-        funcs.entry("args".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Bytes".into()))),
-        });
-        funcs.entry("bytes_to_str".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Str".into()))),
-        });
-        funcs.entry("try_read_file".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("ReadFileResult".into()))),
-        });
-        funcs.entry("try_write_file".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("bool".into()))),
-        });
-        funcs.entry("str_len".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("i32".into()))),
-        });
-        funcs.entry("str_byte_at".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("i32".into()))),
-        });
-        funcs.entry("str_slice".into()).or_insert(FuncSig {
-            ret: Some(Type::Named(Ident("Str".into()))),
-        });
-
+        let mut fn_types = Vec::new();
+        collect_fn_types_in_program(program, &mut fn_types);
         let mut ctx = Self {
+            checked,
             types,
             funcs,
+            params,
             scopes: Vec::new(),
+            fn_types,
+            ref_receivers,
+            memory: MemoryModel::Arena,
+            param_drops: Vec::new(),
+            in_place: HashSet::new(),
+            ret_out: false,
+            stack_guard: false,
+            global_init: false,
+            expected: None,
+            int_model,
+            overflow: Overflow::default(),
+            ansi: false,
+            hoisted: String::new(),
         };
+        // receivers are decided on resolved types, so a method whose first parameter is
+        // an alias of a reference still takes `&recv`
+        for decl in &program.decls {
+            if let Decl::Func(f) = decl {
+                let first = f.params.first().map(|p| ctx.resolve_alias(&p.ty));
+                match Receiver::of(first.as_ref()) {
+                    Receiver::Value => ctx.ref_receivers.remove(&f.name.0),
+                    receiver => ctx.ref_receivers.insert(f.name.0.clone(), receiver),
+                };
+            }
+        }
         ctx.push_scope();
         for decl in &program.decls {
             if let Decl::Global(b) | Decl::Let(b) = decl {
@@ -144,11 +278,19 @@ impl TypeCtx {
                     return current;
                 }
                 Type::Ref(inner) => return Type::Ref(Box::new(self.resolve_alias(&inner))),
-                Type::Record(_) => return current,
+                Type::MutRef(inner) => return Type::MutRef(Box::new(self.resolve_alias(&inner))),
+                Type::Record(_) | Type::Func(_) => return current,
             }
         }
     }
 
+    /// `recv.f(args)` as a plain call to `f`, passing `&recv` when `f` takes a reference first.
+    fn desugar_method_call(&self, call: &FuncCall) -> Option<FuncCall> {
+        let method = call.method_name()?;
+        let receiver = self.ref_receivers.get(&method.0).copied();
+        call.desugar_method(receiver.unwrap_or(Receiver::Value))
+    }
+
     fn type_of_ident(&self, name: &str) -> Option<Type> {
         for scope in self.scopes.iter().rev() {
             if let Some(t) = scope.get(name) {
@@ -173,97 +315,35 @@ impl TypeCtx {
                 .iter()
                 .find(|f| f.name.0 == field)
                 .map(|f| f.ty.clone()),
-            Type::Ref(inner) => self.field_type(&inner, field),
+            Type::Ref(inner) | Type::MutRef(inner) => self.field_type(&inner, field),
             _ => None,
         }
     }
 
-    fn infer_expr_type(&self, expr: &Expr) -> Option<Type> {
-        match expr {
-            Expr::Literal(Literal::Int(_)) => Some(Type::Named(Ident("i32".into()))),
-            Expr::Literal(Literal::Bool(_)) => Some(Type::Named(Ident("bool".into()))),
-            Expr::Literal(Literal::Str(_)) => Some(Type::Named(Ident("Str".into()))),
-            Expr::Literal(Literal::Unit) => Some(Type::Named(Ident("Unit".into()))),
-            Expr::Path(p) => self.type_of_path(p),
-            Expr::Copy(inner) => self.infer_expr_type(inner),
-            Expr::Ref(inner) => self.infer_expr_type(inner).map(|t| Type::Ref(Box::new(t))),
-            Expr::FuncCall(fc) => {
-                let name = path_to_string(&fc.callee);
-                self.funcs.get(&name).and_then(|f| {
-                    f.ret
-                        .clone()
-                        .or_else(|| Some(Type::Named(Ident("Unit".into()))))
-                })
-            }
-            Expr::If(ife) => {
-                let then_ty = self.infer_expr_type(&ife.then_branch)?;
-                let else_ty = self.infer_expr_type(&ife.else_branch)?;
-                if then_ty == else_ty {
-                    Some(then_ty)
-                } else {
-                    Some(Type::Named(Ident("Unit".into())))
-                }
-            }
-            Expr::Block(b) => self.infer_block_type(b),
-            Expr::RecordLit(r) => {
-                let mut fields = Vec::new();
-                for f in &r.fields {
-                    let ty = self
-                        .infer_expr_type(&f.value)
-                        .unwrap_or(Type::Named(Ident("Unit".into())));
-                    fields.push(FieldType {
-                        name: f.name.clone(),
-                        ty,
-                    });
-                }
-                Some(Type::Record(fields))
-            }
-            Expr::Unary(u) => match u.op {
-                UnaryOp::Neg => Some(Type::Named(Ident("i32".into()))),
-                UnaryOp::Not => Some(Type::Named(Ident("bool".into()))),
-            },
-            Expr::Binary(b) => {
-                let lhs = self.infer_expr_type(&b.left)?;
-                let rhs = self.infer_expr_type(&b.right)?;
-                match b.op {
-                    BinaryOp::Lt | BinaryOp::Eq | BinaryOp::And | BinaryOp::Or => {
-                        Some(Type::Named(Ident("bool".into())))
-                    }
-                    BinaryOp::Add => {
-                        if self.is_str(&lhs) || self.is_str(&rhs) {
-                            Some(Type::Named(Ident("Str".into())))
-                        } else {
-                            Some(lhs)
-                        }
-                    }
-                    _ => Some(lhs),
-                }
-            }
+    /// The width of integer literal `expr`: that of the type it is declared as, or the
+    /// checker gave it from its context, so `x: i64 = 3000000000` is not truncated.
+    fn literal_model(&self, expr: &Expr, expected: Option<&Type>) -> IntModel {
+        let ty = expected
+            .or_else(|| self.checked.get(expr))
+            .map(|ty| self.resolve_alias(ty));
+        match ty {
+            Some(Type::Named(Ident(name))) => name.parse().unwrap_or(self.int_model),
+            _ => self.int_model,
         }
     }
 
-    fn infer_block_type(&self, block: &Block) -> Option<Type> {
-        let mut clone = self.clone();
-        clone.push_scope();
-        for stmt in &block.stmts {
-            clone.infer_stmt(stmt);
+    /// Whether `main` can return a value of `ty` as the process exit status: integers
+    /// and `bool` can, anything else makes `main` exit with 0.
+    fn is_exit_status(&self, ty: &Type) -> bool {
+        match self.resolve_alias(ty) {
+            Type::Named(Ident(name)) => name == "bool" || int_range(&name).is_some(),
+            _ => false,
         }
-        let tail_ty = block
-            .tail
-            .as_ref()
-            .map(|e| clone.infer_expr_type(e))
-            .flatten()
-            .unwrap_or(Type::Named(Ident("Unit".into())));
-        Some(tail_ty)
     }
 
-    fn infer_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Binding(b) => {
-                self.insert_var(b.name.0.clone(), b.ty.clone());
-            }
-            _ => {}
-        }
+    /// The type the checker resolved for `expr`.
+    fn expr_type(&self, expr: &Expr) -> Option<Type> {
+        self.checked.get(expr).cloned()
     }
 
     fn is_str(&self, ty: &Type) -> bool {
@@ -274,9 +354,24 @@ impl TypeCtx {
         matches!(self.resolve_alias(ty), Type::Named(Ident(ref n)) if n == "Bytes")
     }
 
+    /// The lowered `Drop` impl for values declared as `ty`, if the program has one.
+    fn drop_hook(&self, ty: &Type) -> Option<String> {
+        drop_fn(ty).filter(|f| self.funcs.contains(f))
+    }
+
     fn is_unit(&self, ty: &Type) -> bool {
         matches!(self.resolve_alias(ty), Type::Named(Ident(ref n)) if n == "Unit")
     }
+
+    /// The plain call behind `expr` when it calls a function that returns in place.
+    fn in_place_call(&self, expr: &Expr) -> Option<FuncCall> {
+        let Expr::FuncCall(fc) = expr else {
+            return None;
+        };
+        let call = self.desugar_method_call(fc).unwrap_or_else(|| fc.clone());
+        let name = path_to_string(&call.callee);
+        (self.in_place.contains(&name) && self.type_of_ident(&name).is_none()).then_some(call)
+    }
 }
 
 pub fn generate_c_from_source(src: &str) -> Result<String, CgenError> {
@@ -288,7 +383,40 @@ pub fn generate_c_from_source(src: &str) -> Result<String, CgenError> {
 }
 
 pub fn generate_c(program: &Program) -> Result<String, CgenError> {
-    let mut ctx = TypeCtx::new(program);
+    generate_c_with(program, CgenOptions::default())
+}
+
+/// Check `program` against the integer model, overflow mode and memory model of
+/// `options`, then generate C from the checked program.
+pub fn generate_c_with(program: &Program, options: CgenOptions) -> Result<String, CgenError> {
+    let mut checker = TypeChecker::with_target(Target {
+        int_model: options.int_model,
+        overflow: options.overflow,
+    });
+    checker.set_gc(options.memory == MemoryModel::Gc);
+    let typed = checker
+        .check_program(program)
+        .map_err(|e| CgenError::Type(e.to_string()))?;
+    generate_c_typed(&typed, options)
+}
+
+/// Generate C for a checked program, using the types the checker resolved. `options`
+/// must use the integer model the program was checked with.
+pub fn generate_c_typed(typed: &TypedProgram, options: CgenOptions) -> Result<String, CgenError> {
+    emit_program(typed.program(), typed.types(), options)
+}
+
+fn emit_program(
+    program: &Program,
+    checked: &ExprTypes,
+    options: CgenOptions,
+) -> Result<String, CgenError> {
+    let mut ctx = TypeCtx::new(program, checked, options.int_model);
+    ctx.memory = options.memory;
+    ctx.stack_guard = options.stack_guard;
+    ctx.overflow = options.overflow;
+    ctx.ansi = options.ansi;
+    ctx.in_place = in_place_functions(program, &ctx);
     let mut out = String::new();
     writeln!(out, "#include <stdint.h>").map_err(|e| CgenError::Fmt(e.to_string()))?;
     writeln!(out, "#include <stdbool.h>").map_err(|e| CgenError::Fmt(e.to_string()))?;
@@ -306,32 +434,183 @@ pub fn generate_c(program: &Program) -> Result<String, CgenError> {
             type_names.insert(t.name.0.clone());
         }
     }
-    emit_builtin_shims(&mut out, &func_names, &type_names)?;
-
-    // forward declare type aliases
-    for decl in &program.decls {
-        if let Decl::Type(t) = decl {
-            emit_type_decl(t, &mut out, &mut ctx)?;
-        }
+    emit_builtin_shims(&mut out, &func_names, &type_names, options)?;
+
+    // type aliases in dependency order; function typedefs right before their first user
+    let mut emitted_fn_types = HashSet::new();
+    for t in sort_type_decls(program)? {
+        let mut used = Vec::new();
+        collect_fn_types(&t.ty, &mut used);
+        emit_fn_typedefs(&mut out, &ctx, &used, &mut emitted_fn_types)?;
+        emit_type_decl(t, &mut out, &mut ctx).map_err(|e| e.at(t.span))?;
     }
-
-    // globals (let/global)
+    let remaining = ctx.fn_types.clone();
+    emit_fn_typedefs(&mut out, &ctx, &remaining, &mut emitted_fn_types)?;
+
+    // globals (let/global); lowering put them in dependency order
+    let mut consts = ConstEnv::new(Target {
+        int_model: options.int_model,
+        overflow: options.overflow,
+    });
+    let mut deferred = Vec::new();
     for decl in &program.decls {
         if let Decl::Global(b) | Decl::Let(b) = decl {
-            emit_global(b, &mut out, &mut ctx)?;
+            if !emit_global(b, &mut out, &mut ctx, &mut consts).map_err(|e| e.at(b.span))? {
+                deferred.push(b);
+            }
         }
     }
 
     emit_function_prototypes(program, &mut out, &mut ctx)?;
+    emit_global_init(&deferred, &mut out, &mut ctx)?;
 
     // functions
     for decl in &program.decls {
         if let Decl::Func(f) = decl {
-            emit_function(f, &mut out, &mut ctx)?;
+            emit_function(f, &mut out, &mut ctx).map_err(|e| e.at(f.span))?;
+        }
+    }
+
+    Ok(escape_non_ascii(&out))
+}
+
+/// Spell non-ASCII characters as universal character names (`\u540D`), so Unicode
+/// identifiers reach compilers that only take ASCII source. C's first translation phase
+/// does the same, so the program means the same, string literals included. C1 controls
+/// cannot be written as UCNs; they only occur in string literals, which spell them as
+/// byte escapes already.
+fn escape_non_ascii(c_src: &str) -> String {
+    if c_src.is_ascii() {
+        return c_src.to_string();
+    }
+    let mut out = String::with_capacity(c_src.len());
+    for ch in c_src.chars() {
+        match u32::from(ch) {
+            0..=0x9f => out.push(ch),
+            n @ 0xa0..=0xffff => out.push_str(&format!("\\u{n:04X}")),
+            n => out.push_str(&format!("\\U{n:08X}")),
+        }
+    }
+    out
+}
+
+/// Order type declarations so every declaration follows the types it names.
+/// Source order is kept where there is no dependency; cycles are reported with their path.
+fn sort_type_decls(program: &Program) -> Result<Vec<&TypeDecl>, CgenError> {
+    let decls: Vec<&TypeDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Type(t) => Some(t),
+            _ => None,
+        })
+        .collect();
+    let index: HashMap<&str, usize> = decls
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.name.0.as_str(), i))
+        .collect();
+
+    fn visit(
+        i: usize,
+        decls: &[&TypeDecl],
+        index: &HashMap<&str, usize>,
+        state: &mut [u8], // 0 = new, 1 = on the current path, 2 = done
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), CgenError> {
+        match state[i] {
+            2 => return Ok(()),
+            1 => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let mut names: Vec<&str> = path[start..]
+                    .iter()
+                    .map(|&p| decls[p].name.0.as_str())
+                    .collect();
+                names.push(decls[i].name.0.as_str());
+                return Err(CgenError::TypeCycle(names.join(" -> ")));
+            }
+            _ => {}
+        }
+        state[i] = 1;
+        path.push(i);
+        let mut deps = Vec::new();
+        type_decl_deps(&decls[i].ty, index, &mut deps);
+        for dep in deps {
+            visit(dep, decls, index, state, path, order)?;
+        }
+        path.pop();
+        state[i] = 2;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut state = vec![0u8; decls.len()];
+    let mut order = Vec::with_capacity(decls.len());
+    for i in 0..decls.len() {
+        visit(i, &decls, &index, &mut state, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order.into_iter().map(|i| decls[i]).collect())
+}
+
+fn type_decl_deps(ty: &Type, index: &HashMap<&str, usize>, out: &mut Vec<usize>) {
+    match ty {
+        Type::Named(id) => {
+            if let Some(&i) = index.get(id.0.as_str()) {
+                out.push(i);
+            }
+        }
+        // C typedefs of anonymous structs cannot be forward declared, so refs count too
+        Type::Ref(inner) | Type::MutRef(inner) => type_decl_deps(inner, index, out),
+        Type::Record(fields) => {
+            for f in fields {
+                type_decl_deps(&f.ty, index, out);
+            }
+        }
+        Type::Func(f) => {
+            for p in &f.params {
+                type_decl_deps(p, index, out);
+            }
+            type_decl_deps(&f.ret, index, out);
         }
     }
+}
 
-    Ok(out)
+/// Emit `gaut_fn_N` typedefs for the listed function types not emitted yet.
+fn emit_fn_typedefs(
+    out: &mut String,
+    ctx: &TypeCtx,
+    wanted: &[FuncType],
+    emitted: &mut HashSet<usize>,
+) -> Result<(), CgenError> {
+    let mut any = false;
+    for (i, f) in ctx.fn_types.iter().enumerate() {
+        if !wanted.contains(f) || !emitted.insert(i) {
+            continue;
+        }
+        any = true;
+        let mut params = Vec::new();
+        for p in &f.params {
+            params.push(map_value_type(p, ctx)?);
+        }
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        writeln!(
+            out,
+            "typedef {} (*gaut_fn_{})({});",
+            map_type(&f.ret, ctx)?,
+            i,
+            params
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if any {
+        writeln!(out).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
 }
 
 fn emit_function_prototypes(
@@ -347,41 +626,8 @@ fn emit_function_prototypes(
             continue;
         }
 
-        if func.name.0 == "print"
-            || func.name.0 == "println"
-            || func.name.0 == "read_file"
-            || func.name.0 == "write_file"
-            || func.name.0 == "args"
-            || func.name.0 == "bytes_to_str"
-            || func.name.0 == "try_read_file"
-            || func.name.0 == "try_write_file"
-            || func.name.0 == "str_len"
-            || func.name.0 == "str_byte_at"
-            || func.name.0 == "str_slice"
-        {
-            continue;
-        }
-
-        let mut infer_ctx = ctx.clone();
-        infer_ctx.push_scope();
-        for p in &func.params {
-            infer_ctx.insert_var(p.name.0.clone(), p.ty.clone());
-        }
-        let inferred_ret = infer_ctx
-            .infer_expr_type(&func.body)
-            .unwrap_or(Type::Named(Ident("Unit".into())));
-        let ret_ty = func.ret.clone().unwrap_or(inferred_ret);
-        let ret_cty = map_type(&ret_ty, ctx)?;
-
-        write!(out, "{} {}(", ret_cty, func.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-        for (i, p) in func.params.iter().enumerate() {
-            if i > 0 {
-                write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            }
-            let cty = map_value_type(&p.ty, ctx)?;
-            write!(out, "{} {}", cty, p.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-        }
-        writeln!(out, ");").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        emit_signature(func, out, ctx)?;
+        writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
     writeln!(out).map_err(|e| CgenError::Fmt(e.to_string()))
 }
@@ -390,7 +636,9 @@ fn emit_builtin_shims(
     out: &mut String,
     func_names: &HashSet<String>,
     type_names: &HashSet<String>,
+    options: CgenOptions,
 ) -> Result<(), CgenError> {
+    let int_model = options.int_model;
     if !type_names.contains("ReadFileResult") {
         writeln!(
             out,
@@ -398,6 +646,13 @@ fn emit_builtin_shims(
         )
         .map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
+    if !type_names.contains("DynField") {
+        writeln!(
+            out,
+            "typedef struct {{ bool found; char* value; }} DynField;"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
     if !func_names.contains("print") {
         writeln!(
             out,
@@ -457,6 +712,67 @@ fn emit_builtin_shims(
         )
         .map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
+    if !func_names.contains("int_to_str") {
+        writeln!(
+            out,
+            "char* int_to_str(int64_t n) {{ return gaut_int_to_str(n); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !type_names.contains("StrToIntResult") {
+        writeln!(
+            out,
+            "typedef struct {{ bool ok; {} value; }} StrToIntResult;",
+            int_c_type(int_model)
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("str_to_int") {
+        let (min, max) = match int_model {
+            IntModel::I32 => ("INT32_MIN", "INT32_MAX"),
+            IntModel::I64 => ("INT64_MIN", "INT64_MAX"),
+        };
+        writeln!(
+            out,
+            "StrToIntResult str_to_int(char* s) {{ int64_t v = 0; bool ok = gaut_str_to_int(s, &v) \
+             && v >= {min} && v <= {max}; StrToIntResult out = {{ .ok = ok, .value = ok ? v : 0 }}; \
+             return out; }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    emit_int_op_shims(out, func_names, type_names, int_model, options.ansi)?;
+    if !func_names.contains("arg_count") {
+        writeln!(
+            out,
+            "int32_t arg_count(void) {{ return gaut_arg_count(); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("arg_at") {
+        writeln!(out, "char* arg_at(int64_t i) {{ return gaut_arg_at(i); }}")
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("json_quote") {
+        writeln!(
+            out,
+            "char* json_quote(char* s) {{ return gaut_json_quote(s); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    for (name, ret) in [
+        ("json_get_int", "int64_t"),
+        ("json_get_bool", "bool"),
+        ("json_get_str", "char*"),
+        ("json_get_raw", "char*"),
+    ] {
+        if !func_names.contains(name) {
+            writeln!(
+                out,
+                "{ret} {name}(char** doc, char* key) {{ return gaut_{name}(*doc, key); }}"
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
     if !func_names.contains("str_len") {
         writeln!(
             out,
@@ -478,11 +794,222 @@ fn emit_builtin_shims(
         )
         .map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
+    let int = int_c_type(int_model);
+    let bytes_shims = [
+        (
+            "bytes_len",
+            format!("{int} bytes_len(gaut_bytes b) {{ return ({int})gaut_bytes_len(b); }}"),
+        ),
+        (
+            "bytes_at",
+            format!("{int} bytes_at(gaut_bytes b, {int} i) {{ return gaut_bytes_at(b, i); }}"),
+        ),
+        (
+            "bytes_slice",
+            format!(
+                "gaut_bytes bytes_slice(gaut_bytes b, {int} start, {int} len) {{ return gaut_bytes_slice_clamped(b, start, len); }}"
+            ),
+        ),
+        (
+            "bytes_from_str",
+            "gaut_bytes bytes_from_str(char* s) { return gaut_bytes_from_str(s); }".into(),
+        ),
+        (
+            "bytes_push",
+            format!("void bytes_push(gaut_bytes* b, {int} byte) {{ gaut_bytes_push(b, byte); }}"),
+        ),
+    ];
+    for (name, shim) in bytes_shims {
+        if !func_names.contains(name) {
+            writeln!(out, "{shim}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    if !func_names.contains("str_contains") {
+        writeln!(
+            out,
+            "bool str_contains(char* s, char* needle) {{ return gaut_str_contains(s, needle); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("str_find") {
+        writeln!(
+            out,
+            "{} str_find(char* s, char* needle) {{ return gaut_str_find(s, needle); }}",
+            int_c_type(int_model)
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    for name in ["str_trim", "str_to_upper", "str_to_lower"] {
+        if !func_names.contains(name) {
+            writeln!(out, "char* {name}(char* s) {{ return gaut_{name}(s); }}")
+                .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    if !func_names.contains("config_get") {
+        writeln!(
+            out,
+            "char* config_get(char* key) {{ return gaut_config_get(key); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("read_line") {
+        writeln!(out, "char* read_line(void) {{ return gaut_read_line(); }}")
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("read_stdin") {
+        writeln!(
+            out,
+            "gaut_bytes read_stdin(void) {{ return gaut_read_stdin(); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("now_millis") {
+        writeln!(
+            out,
+            "int64_t now_millis(void) {{ return gaut_now_millis(); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("sleep_ms") {
+        writeln!(
+            out,
+            "void sleep_ms({} ms) {{ gaut_sleep_ms(ms); }}",
+            int_c_type(int_model)
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("rand_i32") {
+        let int = int_c_type(int_model);
+        writeln!(
+            out,
+            "{int} rand_i32({int} lo, {int} hi) {{ return ({int})gaut_rand_range(lo, hi); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("seed_rand") {
+        writeln!(
+            out,
+            "void seed_rand({} seed) {{ gaut_seed_rand(seed); }}",
+            int_c_type(int_model)
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("flush") {
+        writeln!(out, "void flush(void) {{ gaut_flush(); }}")
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if !func_names.contains("set_print_buffer") {
+        writeln!(
+            out,
+            "bool set_print_buffer(char* mode) {{ return gaut_set_print_buffer(mode); }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    for level in ["info", "warn", "error"] {
+        if !func_names.contains(format!("log_{level}").as_str()) {
+            writeln!(
+                out,
+                "void log_{level}(char* msg) {{ gaut_log_{level}(msg); }}"
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    let dyn_shims = [
+        (
+            "dyn_new",
+            "gaut_dyn dyn_new(void) { return gaut_dyn_new(); }",
+        ),
+        (
+            "dyn_parse",
+            "gaut_dyn dyn_parse(char* json) { return gaut_dyn_parse(json); }",
+        ),
+        (
+            "dyn_set",
+            "void dyn_set(gaut_dyn* d, char* key, char* value) { gaut_dyn_set(*d, key, value); }",
+        ),
+        (
+            "dyn_has",
+            "bool dyn_has(gaut_dyn* d, char* key) { return gaut_dyn_get(*d, key) != NULL; }",
+        ),
+        (
+            "dyn_get",
+            "DynField dyn_get(gaut_dyn* d, char* key) { char* v = gaut_dyn_get(*d, key); \
+             DynField out = { .found = v != NULL, .value = v ? v : (char*)\"\" }; return out; }",
+        ),
+    ];
+    // static, so that `send` and `recv` do not take the place of the socket functions
+    // of the same names that the runtime links against
+    let thread_shims = [
+        (
+            "spawn",
+            "static void spawn(void (*f)(void)) { gaut_spawn(f); }",
+        ),
+        (
+            "channel",
+            "static gaut_chan channel(void) { return gaut_chan_new(); }",
+        ),
+        (
+            "send",
+            "static void send(gaut_chan* c, char* msg) { gaut_chan_send(*c, msg); }",
+        ),
+        (
+            "recv",
+            "static char* recv(gaut_chan* c) { return gaut_chan_recv(*c); }",
+        ),
+    ];
+    for (name, shim) in dyn_shims.into_iter().chain(thread_shims) {
+        if !func_names.contains(name) {
+            writeln!(out, "{shim}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    let tcp_shims = [
+        (
+            "tcp_listen",
+            "gaut_tcp_listener tcp_listen(int32_t port) { return gaut_tcp_listen(port); }",
+        ),
+        (
+            "tcp_port",
+            "int32_t tcp_port(gaut_tcp_listener* l) { return (int32_t)gaut_tcp_port(*l); }",
+        ),
+        (
+            "tcp_accept",
+            "gaut_tcp_conn tcp_accept(gaut_tcp_listener* l) { return gaut_tcp_accept(*l); }",
+        ),
+        (
+            "tcp_connect",
+            "gaut_tcp_conn tcp_connect(char* host, int32_t port) { return gaut_tcp_connect(host, port); }",
+        ),
+        (
+            "tcp_read",
+            "gaut_bytes tcp_read(gaut_tcp_conn* c) { return gaut_tcp_read(*c); }",
+        ),
+        (
+            "tcp_write",
+            "bool tcp_write(gaut_tcp_conn* c, gaut_bytes data) { return gaut_tcp_write(*c, data); }",
+        ),
+        (
+            "tcp_close",
+            "void tcp_close(gaut_tcp_conn* c) { gaut_tcp_close(*c); }",
+        ),
+    ];
+    let int_c = int_c_type(int_model);
+    for (name, shim) in tcp_shims {
+        if !func_names.contains(name) {
+            writeln!(out, "{}", shim.replace("int32_t", int_c))
+                .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
     writeln!(out).map_err(|e| CgenError::Fmt(e.to_string()))
 }
 
 fn emit_type_decl(ty: &TypeDecl, out: &mut String, ctx: &mut TypeCtx) -> Result<(), CgenError> {
-    match ctx.resolve_alias(&ty.ty) {
+    // an alias names its target's C type, so `type Pos = Point` is the same struct and
+    // `type PosRef = &Point` points at it
+    let target = match &ty.ty {
+        Type::Named(_) | Type::Ref(_) | Type::MutRef(_) => ty.ty.clone(),
+        other => ctx.resolve_alias(other),
+    };
+    match target {
         Type::Record(fields) => {
             writeln!(out, "typedef struct {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
             for f in fields {
@@ -501,12 +1028,52 @@ fn emit_type_decl(ty: &TypeDecl, out: &mut String, ctx: &mut TypeCtx) -> Result<
     writeln!(out).map_err(|e| CgenError::Fmt(e.to_string()))
 }
 
-fn emit_global(binding: &Binding, out: &mut String, ctx: &mut TypeCtx) -> Result<(), CgenError> {
+/// Define a global. An initializer that folds to a constant becomes a C static
+/// initializer; anything else is only declared here and returns `false`, to be assigned
+/// by [`emit_global_init`].
+fn emit_global(
+    binding: &Binding,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    consts: &mut ConstEnv,
+) -> Result<bool, CgenError> {
     let cty = map_value_type(&binding.ty, ctx)?;
+    let Some(value) = consts.define(binding) else {
+        writeln!(out, "{} {};\n", cty, binding.name.0)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        return Ok(false);
+    };
     write!(out, "{} {} = ", cty, binding.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
     let mut ctrs = Counters::default();
-    emit_expr(&binding.value, out, ctx, None, &mut ctrs)?;
-    writeln!(out, ";\n").map_err(|e| CgenError::Fmt(e.to_string()))
+    let value = Expr::Literal(value, binding.span);
+    emit_expr_as(&value, &binding.ty, out, ctx, None, &mut ctrs)?;
+    writeln!(out, ";\n").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    Ok(true)
+}
+
+/// `gaut_init_globals`, which main calls first: the non-literal initializers in order,
+/// allocating from the heap since they outlive every arena.
+fn emit_global_init(
+    bindings: &[&Binding],
+    out: &mut String,
+    ctx: &mut TypeCtx,
+) -> Result<(), CgenError> {
+    if bindings.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "static void gaut_init_globals(void) {{")
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    let mut ctrs = Counters::default();
+    for b in bindings {
+        emit_c_stmt(out, ctx, "  ", |out, ctx| {
+            write!(out, "  {} = ", b.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr_as(&b.value, &b.ty, out, ctx, None, &mut ctrs)?;
+            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))
+        })?;
+    }
+    writeln!(out, "}}\n").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    ctx.global_init = true;
+    Ok(())
 }
 
 fn emit_function(func: &FuncDecl, out: &mut String, ctx: &mut TypeCtx) -> Result<(), CgenError> {
@@ -514,53 +1081,41 @@ fn emit_function(func: &FuncDecl, out: &mut String, ctx: &mut TypeCtx) -> Result
         emit_builtin_print(func, out, ctx)?;
         return Ok(());
     }
-    if func.name.0 == "read_file"
-        || func.name.0 == "write_file"
-        || func.name.0 == "args"
-        || func.name.0 == "bytes_to_str"
-        || func.name.0 == "try_read_file"
-        || func.name.0 == "try_write_file"
-        || func.name.0 == "str_len"
-        || func.name.0 == "str_byte_at"
-        || func.name.0 == "str_slice"
-    {
+    if has_runtime_body(&func.name.0) {
         emit_builtin_io(func, out, ctx)?;
         return Ok(());
     }
 
-    let mut infer_ctx = ctx.clone();
-    infer_ctx.push_scope();
-    for p in &func.params {
-        infer_ctx.insert_var(p.name.0.clone(), p.ty.clone());
-    }
-    let inferred_ret = infer_ctx
-        .infer_expr_type(&func.body)
-        .unwrap_or(Type::Named(Ident("Unit".into())));
-    let ret_ty = func.ret.clone().unwrap_or(inferred_ret);
-    let returns_unit = ctx.is_unit(&ret_ty);
-    let ret_cty = if func.name.0 == "main" {
-        "int".to_string()
-    } else if returns_unit {
-        map_type(&ret_ty, ctx)?
-    } else {
-        map_type(&ret_ty, ctx)?
-    };
+    let ret_ty = return_type(func, ctx);
 
+    let arena = match ctx.memory {
+        MemoryModel::Arena => Some("__arena"),
+        MemoryModel::Gc => None,
+    };
     if func.name.0 == "main" {
         writeln!(out, "int main(int argc, char** argv) {{")
             .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        if arena.is_none() {
+            writeln!(out, "  gaut_gc_init();").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        writeln!(out, "  gaut_config_init();").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        if ctx.stack_guard {
+            // rough frame size: the arena buffer plus locals and spills
+            let frame = match arena {
+                Some(_) => "GAUT_DEFAULT_ARENA_CAP + 256",
+                None => "256",
+            };
+            writeln!(out, "  gaut_stack_guard_init({frame});")
+                .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
         writeln!(out, "  gaut_args_init(argc, argv);")
             .map_err(|e| CgenError::Fmt(e.to_string()))?;
-    } else {
-        write!(out, "{} {}(", ret_cty, func.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-        for (i, p) in func.params.iter().enumerate() {
-            if i > 0 {
-                write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            }
-            let cty = map_value_type(&p.ty, ctx)?;
-            write!(out, "{} {}", cty, p.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        if ctx.global_init {
+            writeln!(out, "  gaut_init_globals();").map_err(|e| CgenError::Fmt(e.to_string()))?;
         }
-        writeln!(out, ") {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    } else {
+        emit_signature(func, out, ctx)?;
+        writeln!(out, " {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
 
     ctx.push_scope();
@@ -568,30 +1123,51 @@ fn emit_function(func: &FuncDecl, out: &mut String, ctx: &mut TypeCtx) -> Result
         ctx.insert_var(p.name.0.clone(), p.ty.clone());
     }
 
-    writeln!(out, "  uint8_t __arena_buf[GAUT_DEFAULT_ARENA_CAP];")
-        .map_err(|e| CgenError::Fmt(e.to_string()))?;
-    writeln!(
-        out,
-        "  gaut_arena __arena = gaut_arena_from_buffer(__arena_buf, GAUT_DEFAULT_ARENA_CAP);"
-    )
-    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    match arena {
+        // GC mode: no arenas, every allocation comes from the collected heap
+        None => {}
+        Some(_) if func.name.0 == "main" => {
+            // main's arena lives for the whole program, so it follows GAUT_ARENA_CAP
+            writeln!(out, "  gaut_arena __arena = gaut_main_arena();")
+                .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        Some(_) => {
+            writeln!(out, "  uint8_t __arena_buf[GAUT_DEFAULT_ARENA_CAP];")
+                .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            writeln!(
+                out,
+                "  gaut_arena __arena = gaut_arena_from_buffer(__arena_buf, GAUT_DEFAULT_ARENA_CAP);"
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    if ctx.stack_guard && func.name.0 != "main" {
+        writeln!(out, "  gaut_stack_enter(\"{}\");", func.name.0)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
     writeln!(out).map_err(|e| CgenError::Fmt(e.to_string()))?;
 
+    ctx.param_drops = func
+        .params
+        .iter()
+        .filter_map(|p| Some((p.name.0.clone(), ctx.drop_hook(&p.ty)?)))
+        .collect();
+    ctx.ret_out = ctx.in_place.contains(&func.name.0);
     let mut counters = Counters::default();
     let body_block = match &func.body {
         Expr::Block(b) => b.clone(),
         other => Block {
             stmts: Vec::new(),
             tail: Some(Box::new(other.clone())),
+            span: other.span(),
         },
     };
     emit_block(
         &body_block,
         out,
         ctx,
-        1,
         &ret_ty,
-        Some("__arena"),
+        arena,
         func.name.0 == "main",
         &mut counters,
     )?;
@@ -600,6 +1176,150 @@ fn emit_function(func: &FuncDecl, out: &mut String, ctx: &mut TypeCtx) -> Result
     writeln!(out, "}}\n").map_err(|e| CgenError::Fmt(e.to_string()))
 }
 
+/// std declarations whose body is emitted as a call into the C runtime.
+fn has_runtime_body(name: &str) -> bool {
+    matches!(
+        name,
+        "print"
+            | "println"
+            | "read_file"
+            | "write_file"
+            | "args"
+            | "bytes_to_str"
+            | "try_read_file"
+            | "try_write_file"
+            | "str_len"
+            | "str_byte_at"
+            | "str_slice"
+    )
+}
+
+/// Declared return type, or the type inferred from the body when unannotated.
+fn return_type(func: &FuncDecl, ctx: &TypeCtx) -> Type {
+    if let Some(ret) = &func.ret {
+        return ret.clone();
+    }
+    let mut infer_ctx = ctx.clone();
+    infer_ctx.push_scope();
+    for p in &func.params {
+        infer_ctx.insert_var(p.name.0.clone(), p.ty.clone());
+    }
+    infer_ctx
+        .expr_type(&func.body)
+        .unwrap_or(Type::Named(Ident("Unit".into())))
+}
+
+/// `ret name(params)`; functions returning in place get `void name(T* __out, params)`.
+fn emit_signature(func: &FuncDecl, out: &mut String, ctx: &TypeCtx) -> Result<(), CgenError> {
+    let ret_ty = return_type(func, ctx);
+    let mut params = Vec::new();
+    if ctx.in_place.contains(&func.name.0) {
+        write!(out, "void {}(", func.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        params.push(format!("{}* __out", map_value_type(&ret_ty, ctx)?));
+    } else {
+        let ret_cty = map_type(&ret_ty, ctx)?;
+        write!(out, "{} {}(", ret_cty, func.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    for p in &func.params {
+        params.push(format!("{} {}", map_value_type(&p.ty, ctx)?, p.name.0));
+    }
+    write!(out, "{})", params.join(", ")).map_err(|e| CgenError::Fmt(e.to_string()))
+}
+
+/// Functions whose named record result is built directly in the caller's storage.
+/// Functions also used as values keep the plain signature their pointer type expects.
+fn in_place_functions(program: &Program, ctx: &TypeCtx) -> HashSet<String> {
+    let mut values = HashSet::new();
+    for decl in &program.decls {
+        match decl {
+            Decl::Global(b) | Decl::Let(b) => collect_fn_values(&b.value, &mut values),
+            Decl::Func(f) => collect_fn_values(&f.body, &mut values),
+            _ => {}
+        }
+    }
+    program
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Func(f)
+                if f.name.0 != "main"
+                    && !has_runtime_body(&f.name.0)
+                    && !values.contains(&f.name.0) =>
+            {
+                Some(f)
+            }
+            _ => None,
+        })
+        .filter(|f| {
+            let ret = return_type(f, ctx);
+            matches!(ret, Type::Named(_))
+                && matches!(ctx.resolve_alias(&ret), Type::Record(ref fields) if !fields.is_empty())
+        })
+        .map(|f| f.name.0.clone())
+        .collect()
+}
+
+/// Single-segment paths read as values; callees are not included.
+fn collect_fn_values(expr: &Expr, out: &mut HashSet<String>) {
+    match expr {
+        Expr::Literal(..) => {}
+        Expr::Path(p) => {
+            if let [name] = p.0.as_slice() {
+                out.insert(name.0.clone());
+            }
+        }
+        Expr::Copy(inner) | Expr::Ref(inner) | Expr::MutRef(inner) | Expr::Time(inner) => {
+            collect_fn_values(inner, out)
+        }
+        Expr::FuncCall(fc) => {
+            for a in &fc.args {
+                collect_fn_values(a, out);
+            }
+        }
+        Expr::If(ife) => {
+            collect_fn_values(&ife.cond, out);
+            collect_fn_values(&ife.then_branch, out);
+            collect_fn_values(&ife.else_branch, out);
+        }
+        Expr::Block(b) => {
+            for stmt in &b.stmts {
+                match stmt {
+                    Stmt::Binding(binding) => collect_fn_values(&binding.value, out),
+                    Stmt::Assign(a) => collect_fn_values(&a.value, out),
+                    Stmt::Expr(e) => collect_fn_values(e, out),
+                    Stmt::Func(_) => {}
+                }
+            }
+            if let Some(tail) = &b.tail {
+                collect_fn_values(tail, out);
+            }
+        }
+        Expr::RecordLit(r) => {
+            if let Some(base) = &r.base {
+                collect_fn_values(base, out);
+            }
+            for f in &r.fields {
+                collect_fn_values(&f.value, out);
+            }
+        }
+        Expr::Unary(u) => collect_fn_values(&u.expr, out),
+        Expr::Binary(b) => {
+            collect_fn_values(&b.left, out);
+            collect_fn_values(&b.right, out);
+        }
+        Expr::Index(ix) => {
+            collect_fn_values(&ix.base, out);
+            collect_fn_values(&ix.index, out);
+        }
+        Expr::Slice(sl) => {
+            collect_fn_values(&sl.base, out);
+            for bound in [&sl.start, &sl.end].into_iter().flatten() {
+                collect_fn_values(bound, out);
+            }
+        }
+    }
+}
+
 fn emit_builtin_print(func: &FuncDecl, out: &mut String, ctx: &TypeCtx) -> Result<(), CgenError> {
     let name = &func.name.0;
     let ret_cty = map_type(&Type::Named(Ident("Str".into())), ctx)?;
@@ -691,16 +1411,17 @@ fn emit_builtin_io(func: &FuncDecl, out: &mut String, ctx: &TypeCtx) -> Result<(
     }
 }
 
+/// Emit a function body, one level in, ending with the function's exit and return.
 fn emit_block(
     block: &Block,
     out: &mut String,
     ctx: &mut TypeCtx,
-    indent: usize,
     ret_ty: &Type,
     arena: Option<&str>,
     is_main: bool,
     ctrs: &mut Counters,
 ) -> Result<(), CgenError> {
+    let indent = 1;
     let pad = "  ".repeat(indent);
     ctx.push_scope();
     let scope_name = if let Some(a) = arena {
@@ -716,9 +1437,22 @@ fn emit_block(
     } else {
         None
     };
+    let param_drops = std::mem::take(&mut ctx.param_drops);
+    let ret_out = std::mem::take(&mut ctx.ret_out);
+    let exit_shadowed = ctx.params.contains_key("exit");
+    if let Some(at) = first_diverging(block, exit_shadowed) {
+        // the program ends at statement `at`; C needs no return after libc exit()
+        for stmt in &block.stmts[..=at] {
+            emit_stmt(stmt, out, ctx, indent, arena, ctrs)?;
+        }
+        ctx.pop_scope();
+        return Ok(());
+    }
     for stmt in &block.stmts {
         emit_stmt(stmt, out, ctx, indent, arena, ctrs)?;
     }
+    let drops = block_drops(block, param_drops, ctx);
+    let guard = ctx.stack_guard && !is_main;
     if let Some(expr) = &block.tail {
         let ret_expr_arena = if ctx.is_str(ret_ty) || ctx.is_bytes(ret_ty) {
             None
@@ -726,37 +1460,44 @@ fn emit_block(
             arena
         };
         if ctx.is_unit(ret_ty) {
-            write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_expr(expr, out, ctx, ret_expr_arena, ctrs)?;
-            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            if let (Some(a), Some(s)) = (arena, &scope_name) {
-                writeln!(out, "{}gaut_scope_leave(&{}, {});", pad, a, s)
-                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
-            }
+            emit_c_stmt(out, ctx, &pad, |out, ctx| {
+                write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr(expr, out, ctx, ret_expr_arena, ctrs)?;
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))
+            })?;
+            emit_function_exit(out, &pad, &drops, arena.zip(scope_name.as_deref()), guard)?;
             if is_main {
                 writeln!(out, "{}return 0;", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
             }
+        } else if ret_out {
+            emit_in_place(expr, "(*__out)", out, ctx, indent, ret_expr_arena, ctrs)?;
+            emit_function_exit(out, &pad, &drops, arena.zip(scope_name.as_deref()), guard)?;
+            writeln!(out, "{}return;", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
         } else {
             let cty = map_value_type(ret_ty, ctx)?;
             let tmp = format!("__ret{}", ctrs.tmp);
             ctrs.tmp += 1;
-            write!(out, "{}{} {} = ", pad, cty, tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_expr(expr, out, ctx, ret_expr_arena, ctrs)?;
-            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            if let (Some(a), Some(s)) = (arena, &scope_name) {
-                writeln!(out, "{}gaut_scope_leave(&{}, {});", pad, a, s)
+            emit_c_stmt(out, ctx, &pad, |out, ctx| {
+                write!(out, "{}{} {} = ", pad, cty, tmp)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr_as(expr, ret_ty, out, ctx, ret_expr_arena, ctrs)?;
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))
+            })?;
+            emit_function_exit(out, &pad, &drops, arena.zip(scope_name.as_deref()), guard)?;
+            if is_main && !ctx.is_exit_status(ret_ty) {
+                // the interpreter prints such a value; a process can only report a number
+                writeln!(out, "{pad}(void){tmp};\n{pad}return 0;")
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            } else {
+                writeln!(out, "{}return {};", pad, tmp)
                     .map_err(|e| CgenError::Fmt(e.to_string()))?;
             }
-            writeln!(out, "{}return {};", pad, tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
         }
     } else {
         if !ctx.is_unit(ret_ty) {
             return Err(CgenError::Unsupported("missing return expression".into()));
         }
-        if let (Some(a), Some(s)) = (arena, &scope_name) {
-            writeln!(out, "{}gaut_scope_leave(&{}, {});", pad, a, s)
-                .map_err(|e| CgenError::Fmt(e.to_string()))?;
-        }
+        emit_function_exit(out, &pad, &drops, arena.zip(scope_name.as_deref()), guard)?;
         if is_main {
             writeln!(out, "{}return 0;", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
         }
@@ -765,6 +1506,25 @@ fn emit_block(
     Ok(())
 }
 
+/// Leave a function body: drop hooks, then the arena scope, then the stack guard.
+fn emit_function_exit(
+    out: &mut String,
+    pad: &str,
+    drops: &[String],
+    scope: Option<(&str, &str)>,
+    guard: bool,
+) -> Result<(), CgenError> {
+    emit_drops(out, pad, drops)?;
+    if let Some((arena, scope)) = scope {
+        writeln!(out, "{}gaut_scope_leave(&{}, {});", pad, arena, scope)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if guard {
+        writeln!(out, "{pad}gaut_stack_leave();").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
+}
+
 fn emit_stmt(
     stmt: &Stmt,
     out: &mut String,
@@ -774,31 +1534,148 @@ fn emit_stmt(
     ctrs: &mut Counters,
 ) -> Result<(), CgenError> {
     let pad = "  ".repeat(indent);
-    match stmt {
-        Stmt::Binding(b) => {
-            let cty = map_value_type(&b.ty, ctx)?;
-            write!(out, "{}{} {} = ", pad, cty, b.name.0)
-                .map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_expr(&b.value, out, ctx, arena, ctrs)?;
-            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            ctx.insert_var(b.name.0.clone(), b.ty.clone());
-        }
-        Stmt::Assign(a) => {
-            write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_path(&a.target, out, Some(&*ctx))?;
-            write!(out, " = ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_expr(&a.value, out, ctx, arena, ctrs)?;
-            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
-        }
-        Stmt::Expr(e) => {
-            write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
-            emit_expr(e, out, ctx, arena, ctrs)?;
-            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    emit_c_stmt(out, ctx, &pad, |out, ctx| {
+        match stmt {
+            // a C declaration is in scope in its own initializer, so a binding that shadows
+            // an outer one it reads is built in a temporary first
+            Stmt::Binding(b) if expr_referenced_names(&b.value).contains(&b.name.0) => {
+                let cty = map_value_type(&b.ty, ctx)?;
+                let tmp = format!("__tmp{}", ctrs.tmp);
+                ctrs.tmp += 1;
+                if ctx.in_place_call(&b.value).is_some() {
+                    writeln!(out, "{pad}{cty} {tmp};")
+                        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                    emit_in_place(&b.value, &tmp, out, ctx, indent, arena, ctrs)?;
+                } else {
+                    write!(out, "{pad}{cty} {tmp} = ")
+                        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                    emit_expr_as(&b.value, &b.ty, out, ctx, arena, ctrs)?;
+                    writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                }
+                writeln!(out, "{pad}{cty} {} = {tmp};", b.name.0)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                ctx.insert_var(b.name.0.clone(), b.ty.clone());
+            }
+            Stmt::Binding(b) if ctx.in_place_call(&b.value).is_some() => {
+                let cty = map_value_type(&b.ty, ctx)?;
+                writeln!(out, "{}{} {};", pad, cty, b.name.0)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_in_place(&b.value, &b.name.0, out, ctx, indent, arena, ctrs)?;
+                ctx.insert_var(b.name.0.clone(), b.ty.clone());
+            }
+            Stmt::Binding(b) => {
+                let cty = map_value_type(&b.ty, ctx)?;
+                write!(out, "{}{} {} = ", pad, cty, b.name.0)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr_as(&b.value, &b.ty, out, ctx, arena, ctrs)?;
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                ctx.insert_var(b.name.0.clone(), b.ty.clone());
+            }
+            Stmt::Assign(a) => {
+                write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                // assigning to a `&mut T` binding stores into the referee
+                let through_ref = a.target.0.len() == 1
+                    && matches!(
+                        ctx.type_of_path(&a.target).map(|t| ctx.resolve_alias(&t)),
+                        Some(Type::MutRef(_))
+                    );
+                if through_ref {
+                    write!(out, "*").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                }
+                emit_path(&a.target, out, Some(&*ctx))?;
+                write!(out, " = ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                match ctx.type_of_path(&a.target) {
+                    Some(Type::MutRef(ty)) if a.target.0.len() == 1 => {
+                        emit_expr_as(&a.value, &ty, out, ctx, arena, ctrs)?
+                    }
+                    Some(ty) => emit_expr_as(&a.value, &ty, out, ctx, arena, ctrs)?,
+                    None => emit_expr(&a.value, out, ctx, arena, ctrs)?,
+                };
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            Stmt::Expr(e) => {
+                write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr(e, out, ctx, arena, ctrs)?;
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            // lifted to the top level by `lower_program`
+            Stmt::Func(_) => {}
         }
+        Ok(())
+    })
+}
+
+/// Emit one C statement with `emit`. Under `ansi`, the statements its expressions
+/// hoisted are written right before it.
+fn emit_c_stmt(
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    pad: &str,
+    emit: impl FnOnce(&mut String, &mut TypeCtx) -> Result<(), CgenError>,
+) -> Result<(), CgenError> {
+    if !ctx.ansi {
+        return emit(out, ctx);
+    }
+    let outer = std::mem::take(&mut ctx.hoisted);
+    let start = out.len();
+    let result = emit(out, ctx);
+    let hoisted = std::mem::replace(&mut ctx.hoisted, outer);
+    result?;
+    // what is left of a Unit expression whose work was all hoisted
+    if out[start..].trim() == "0;" {
+        out.truncate(start);
+    }
+    let mut lines = String::new();
+    for line in hoisted.lines() {
+        writeln!(lines, "{pad}{line}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    out.insert_str(start, &lines);
+    Ok(())
+}
+
+/// Start an expression that runs statements before its value, written with
+/// `push_stmt`: GNU C's `({`, or nothing under `ansi`, where they are hoisted.
+fn open_stmt_expr(out: &mut String, ctx: &TypeCtx) -> Result<(), CgenError> {
+    if !ctx.ansi {
+        write!(out, "({{ ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// A statement of the expression opened with `open_stmt_expr`. `stmt` must be fully
+/// emitted first, so that under `ansi` the statements its own expressions hoisted
+/// come before it.
+fn push_stmt(out: &mut String, ctx: &mut TypeCtx, stmt: &str) {
+    if ctx.ansi {
+        ctx.hoisted.push_str(stmt.trim_end());
+        ctx.hoisted.push('\n');
+    } else {
+        out.push_str(stmt);
+    }
+}
+
+/// End the expression opened with `open_stmt_expr`, after its value.
+fn close_stmt_expr(out: &mut String, ctx: &TypeCtx) -> Result<(), CgenError> {
+    if !ctx.ansi {
+        write!(out, "; }})").map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
     Ok(())
 }
 
+/// Emit `expr` where a value of declared type `ty` is expected, so record literals in it
+/// (through `if` branches and block tails) are built as `ty`'s struct.
+fn emit_expr_as(
+    expr: &Expr,
+    ty: &Type,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    ctx.expected = Some(ty.clone());
+    emit_expr(expr, out, ctx, arena, ctrs)
+}
+
 fn emit_expr(
     expr: &Expr,
     out: &mut String,
@@ -806,9 +1683,14 @@ fn emit_expr(
     arena: Option<&str>,
     ctrs: &mut Counters,
 ) -> Result<Type, CgenError> {
+    let expected = ctx.expected.take();
     match expr {
-        Expr::Literal(l) => match l {
-            Literal::Int(i) => write!(out, "{}", i).map_err(|e| CgenError::Fmt(e.to_string()))?,
+        Expr::Literal(l, _) => match l {
+            Literal::Int(i) => {
+                let model = ctx.literal_model(expr, expected.as_ref());
+                write!(out, "{}", int_literal(model.wrap(*i), model))
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?
+            }
             Literal::Bool(b) => write!(out, "{}", if *b { "true" } else { "false" })
                 .map_err(|e| CgenError::Fmt(e.to_string()))?,
             Literal::Str(s) => write!(out, "\"{}\"", escape_c_string(s))
@@ -823,46 +1705,179 @@ fn emit_expr(
         }
         Expr::Ref(inner) => {
             write!(out, "&").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            if let Some(Type::Ref(ty) | Type::MutRef(ty)) = expected {
+                return emit_expr_as(inner, &ty, out, ctx, arena, ctrs);
+            }
             return emit_expr(inner, out, ctx, arena, ctrs);
         }
+        Expr::MutRef(inner) => {
+            // `&mut r` of a `&mut T` binding passes the pointer it already holds
+            let reborrow = matches!(
+                ctx.expr_type(inner).map(|t| ctx.resolve_alias(&t)),
+                Some(Type::MutRef(_))
+            );
+            if !reborrow {
+                write!(out, "&").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            emit_expr(inner, out, ctx, arena, ctrs)?;
+        }
         Expr::FuncCall(fc) => {
-            emit_path(&fc.callee, out, None)?;
+            if let Some(desugared) = ctx.desugar_method_call(fc) {
+                return emit_expr(&Expr::FuncCall(desugared), out, ctx, arena, ctrs);
+            }
+            if let Some(call) = ctx.in_place_call(expr) {
+                // result used as a value: give the callee a temporary to build into
+                let ty = ctx
+                    .expr_type(expr)
+                    .unwrap_or(Type::Named(Ident("Unit".into())));
+                let tmp = format!("__tmp{}", ctrs.tmp);
+                ctrs.tmp += 1;
+                open_stmt_expr(out, ctx)?;
+                let decl = format!("{} {}; ", map_value_type(&ty, ctx)?, tmp);
+                push_stmt(out, ctx, &decl);
+                let mut stmt = String::new();
+                emit_in_place_call(&call, &format!("&{tmp}"), &mut stmt, ctx, arena, ctrs)?;
+                push_stmt(out, ctx, &format!("{stmt}; "));
+                write!(out, "{tmp}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                close_stmt_expr(out, ctx)?;
+                return Ok(ty);
+            }
+            if let [arg] = fc.args.as_slice() {
+                if path_to_string(&fc.callee) == "to_str" && !ctx.params.contains_key("to_str") {
+                    return emit_to_str(arg, out, ctx, arena, ctrs);
+                }
+            }
+            let (pre, args) = sequence_operands(&fc.args, ctx, arena, ctrs)?;
+            if !pre.is_empty() {
+                write!(out, "({{ {pre}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            let callee = path_to_string(&fc.callee);
+            match atomic_c_name(&callee, ctx) {
+                Some(name) => write!(out, "{name}").map_err(|e| CgenError::Fmt(e.to_string()))?,
+                None => emit_path(&fc.callee, out, None)?,
+            }
             write!(out, "(").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            for (i, arg) in fc.args.iter().enumerate() {
+            let params = match ctx.params.get(&callee) {
+                Some(params) => params.clone(),
+                None => atomic_param_types(&callee),
+            };
+            for (i, arg) in args.iter().enumerate() {
                 if i > 0 {
                     write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
                 }
-                emit_expr(arg, out, ctx, arena, ctrs)?;
+                match params.get(i) {
+                    Some(ty) => emit_expr_as(arg, ty, out, ctx, arena, ctrs)?,
+                    None => emit_expr(arg, out, ctx, arena, ctrs)?,
+                };
             }
             write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            if !pre.is_empty() {
+                ctx.pop_scope();
+                write!(out, "; }})").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+        }
+        Expr::If(ife) if ctx.ansi => {
+            return emit_if_ansi(expr, ife, expected, out, ctx, arena, ctrs);
         }
         Expr::If(ife) => {
             write!(out, "(").map_err(|e| CgenError::Fmt(e.to_string()))?;
             emit_expr(&ife.cond, out, ctx, arena, ctrs)?;
             write!(out, " ? ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            ctx.expected = expected.clone();
             emit_expr(&ife.then_branch, out, ctx, arena, ctrs)?;
             write!(out, " : ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            ctx.expected = expected;
             emit_expr(&ife.else_branch, out, ctx, arena, ctrs)?;
             write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
         }
         Expr::Block(b) => {
-            let ty = emit_block_expr(b, out, ctx, arena, ctrs)?;
+            let expected = expected.or_else(|| ctx.expr_type(expr));
+            let ty = emit_block_expr(b, expected, out, ctx, arena, ctrs)?;
             return Ok(ty);
         }
+        Expr::Time(body) => {
+            // wrapped like the interpreter's elapsed millis at the default width
+            let start = format!("__tmp{}", ctrs.tmp);
+            ctrs.tmp += 1;
+            open_stmt_expr(out, ctx)?;
+            push_stmt(out, ctx, &format!("int64_t {start} = gaut_now_millis(); "));
+            let mut run = String::from("(void)");
+            emit_expr(body, &mut run, ctx, arena, ctrs)?;
+            push_stmt(out, ctx, &format!("{run}; "));
+            write!(
+                out,
+                "({})(gaut_now_millis() - {start})",
+                int_c_type(ctx.int_model)
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            close_stmt_expr(out, ctx)?;
+        }
         Expr::RecordLit(r) => {
-            let ty = ctx
-                .infer_expr_type(expr)
-                .unwrap_or(Type::Record(Vec::new()));
-            let cty = find_record_alias(ctx, &ty).unwrap_or(map_value_type(&ty, ctx)?);
+            // a declared record type names the struct; a literal without one is matched
+            // to a type declaration by shape
+            let ty = match expected.filter(|t| matches!(ctx.resolve_alias(t), Type::Record(_))) {
+                Some(ty) => ty,
+                None => ctx.expr_type(expr).unwrap_or(Type::Record(Vec::new())),
+            };
+            let cty = match &ty {
+                Type::Named(_) => map_value_type(&ty, ctx)?,
+                _ => find_record_alias(ctx, &ty).unwrap_or(map_value_type(&ty, ctx)?),
+            };
+            if let Some(base) = &r.base {
+                // struct copy of the base, then overwrite the listed fields
+                let tmp = format!("__tmp{}", ctrs.tmp);
+                ctrs.tmp += 1;
+                open_stmt_expr(out, ctx)?;
+                let mut stmt = format!("{cty} {tmp} = ");
+                emit_expr(base, &mut stmt, ctx, arena, ctrs)?;
+                push_stmt(out, ctx, &format!("{stmt}; "));
+                for f in &r.fields {
+                    let mut stmt = format!("{tmp}.{} = ", f.name.0);
+                    ctx.expected = ctx.field_type(&ty, &f.name.0);
+                    emit_expr(&f.value, &mut stmt, ctx, arena, ctrs)?;
+                    push_stmt(out, ctx, &format!("{stmt}; "));
+                }
+                write!(out, "{tmp}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                close_stmt_expr(out, ctx)?;
+                return Ok(ty);
+            }
+            let values: Vec<Expr> = r.fields.iter().map(|f| f.value.clone()).collect();
+            let (pre, values) = sequence_operands(&values, ctx, arena, ctrs)?;
+            if !pre.is_empty() {
+                write!(out, "({{ {pre}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
             write!(out, "({}){{ ", cty).map_err(|e| CgenError::Fmt(e.to_string()))?;
-            for (i, f) in r.fields.iter().enumerate() {
+            for (i, (f, value)) in r.fields.iter().zip(&values).enumerate() {
                 if i > 0 {
                     write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
                 }
                 write!(out, ".{} = ", f.name.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                emit_expr(&f.value, out, ctx, arena, ctrs)?;
+                ctx.expected = ctx.field_type(&ty, &f.name.0);
+                emit_expr(value, out, ctx, arena, ctrs)?;
             }
             write!(out, " }}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            if !pre.is_empty() {
+                ctx.pop_scope();
+                write!(out, "; }})").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+        }
+        Expr::Unary(u) if matches!(u.op, UnaryOp::Neg) => {
+            // `-2147483648` is a negative literal, not MIN negated
+            if let Expr::Literal(Literal::Int(i), _) = *u.expr {
+                let model = ctx.literal_model(expr, expected.as_ref());
+                let v = model.wrap(i.wrapping_neg());
+                write!(out, "{}", int_literal(v, model))
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            } else {
+                let suffix = int_suffix(ctx.int_model);
+                let helper = match ctx.overflow {
+                    Overflow::Trap => format!("gaut_trap_neg_{}", suffix.to_lowercase()),
+                    Overflow::Wrap => format!("GAUT_NEG_{suffix}"),
+                };
+                write!(out, "{helper}(").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr(&u.expr, out, ctx, arena, ctrs)?;
+                write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
         }
         Expr::Unary(u) => {
             let op = match u.op {
@@ -879,117 +1894,618 @@ fn emit_expr(
                 write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
             }
         }
-        Expr::Binary(b) => {
-            let ty = ctx.infer_expr_type(expr);
-            if matches!(b.op, BinaryOp::Add) && ty.as_ref().is_some_and(|t| ctx.is_str(t)) {
-                let fn_name = if arena.is_some() {
-                    "gaut_str_concat_arena"
-                } else {
-                    "gaut_str_concat_heap"
-                };
-                if let Some(a) = arena {
-                    write!(out, "{}(&{}, ", fn_name, a)
-                        .map_err(|e| CgenError::Fmt(e.to_string()))?;
-                } else {
-                    write!(out, "{}(", fn_name).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                }
-                emit_expr(&b.left, out, ctx, arena, ctrs)?;
-                write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-                emit_expr(&b.right, out, ctx, arena, ctrs)?;
-                write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            } else if matches!(b.op, BinaryOp::Add) && ty.as_ref().is_some_and(|t| ctx.is_bytes(t))
-            {
-                let fn_name = if arena.is_some() {
-                    "gaut_bytes_concat_arena"
-                } else {
-                    "gaut_bytes_concat_heap"
-                };
-                if let Some(a) = arena {
-                    write!(out, "{}(&{}, ", fn_name, a)
-                        .map_err(|e| CgenError::Fmt(e.to_string()))?;
-                } else {
-                    write!(out, "{}(", fn_name).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                }
-                emit_expr(&b.left, out, ctx, arena, ctrs)?;
-                write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-                emit_expr(&b.right, out, ctx, arena, ctrs)?;
-                write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        Expr::Index(ix) => {
+            // both shims abort on an out-of-range index
+            let (is_bytes, deref) = indexed_base(&ix.base, ctx)?;
+            let shim = if is_bytes {
+                "gaut_bytes_index"
             } else {
-                let str_eq = matches!(b.op, BinaryOp::Eq)
-                    && ctx
-                        .infer_expr_type(&b.left)
-                        .as_ref()
-                        .is_some_and(|t| ctx.is_str(t));
-                if str_eq {
-                    write!(out, "(strcmp(").map_err(|e| CgenError::Fmt(e.to_string()))?;
-                    emit_expr(&b.left, out, ctx, arena, ctrs)?;
-                    write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-                    emit_expr(&b.right, out, ctx, arena, ctrs)?;
-                    write!(out, ") == 0)").map_err(|e| CgenError::Fmt(e.to_string()))?;
-                } else {
-                    emit_expr(&b.left, out, ctx, arena, ctrs)?;
-                    let op = match b.op {
-                        BinaryOp::Add => "+",
-                        BinaryOp::Sub => "-",
-                        BinaryOp::Mul => "*",
-                        BinaryOp::Div => "/",
-                        BinaryOp::Lt => "<",
-                        BinaryOp::Eq => "==",
-                        BinaryOp::And => "&&",
-                        BinaryOp::Or => "||",
-                    };
-                    write!(out, " {} ", op).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                    emit_expr(&b.right, out, ctx, arena, ctrs)?;
+                "gaut_str_index"
+            };
+            write!(out, "{shim}({deref}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&ix.base, out, ctx, arena, ctrs)?;
+            write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&ix.index, out, ctx, arena, ctrs)?;
+            write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        Expr::Slice(sl) => {
+            // the shims copy the range to the heap and abort unless 0 <= a <= b <= len
+            let (is_bytes, deref) = indexed_base(&sl.base, ctx)?;
+            let shim = if is_bytes {
+                "gaut_bytes_slice"
+            } else {
+                "gaut_str_slice_checked"
+            };
+            write!(out, "{shim}({deref}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&sl.base, out, ctx, arena, ctrs)?;
+            write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            match &sl.start {
+                Some(start) => {
+                    emit_expr(start, out, ctx, arena, ctrs)?;
+                }
+                None => write!(out, "0").map_err(|e| CgenError::Fmt(e.to_string()))?,
+            }
+            write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            match &sl.end {
+                Some(end) => {
+                    emit_expr(end, out, ctx, arena, ctrs)?;
+                    write!(out, ", false)").map_err(|e| CgenError::Fmt(e.to_string()))?;
                 }
+                None => write!(out, "0, true)").map_err(|e| CgenError::Fmt(e.to_string()))?,
+            }
+        }
+        Expr::Binary(b) if ctx.ansi && matches!(b.op, BinaryOp::And | BinaryOp::Or) => {
+            return emit_logical_ansi(expr, b, out, ctx, arena, ctrs);
+        }
+        // `&&` and `||` are already sequenced (and short-circuit) in C
+        Expr::Binary(b) if !matches!(b.op, BinaryOp::And | BinaryOp::Or) => {
+            let operands = [(*b.left).clone(), (*b.right).clone()];
+            let (pre, operands) = sequence_operands(&operands, ctx, arena, ctrs)?;
+            if pre.is_empty() && !ctx.ansi {
+                return emit_binary(expr, b, out, ctx, arena, ctrs);
+            }
+            let [left, right] = <[Expr; 2]>::try_from(operands)
+                .map_err(|_| CgenError::Unsupported("binary operand count".into()))?;
+            let seq = BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(left),
+                right: Box::new(right),
+                span: b.span,
+            };
+            if ctx.ansi {
+                return emit_binary(&Expr::Binary(seq.clone()), &seq, out, ctx, arena, ctrs);
             }
+            write!(out, "({{ {pre}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            let ty = emit_binary(&Expr::Binary(seq.clone()), &seq, out, ctx, arena, ctrs)?;
+            ctx.pop_scope();
+            write!(out, "; }})").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            return Ok(ty);
         }
+        Expr::Binary(b) => return emit_binary(expr, b, out, ctx, arena, ctrs),
     }
 
     Ok(ctx
-        .infer_expr_type(expr)
+        .expr_type(expr)
         .unwrap_or(Type::Named(Ident("Unit".into()))))
 }
 
-fn escape_c_string(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '\\' => out.push_str("\\\\"),
-            '"' => out.push_str("\\\""),
-            '\n' => out.push_str("\\n"),
-            '\r' => out.push_str("\\r"),
-            '\t' => out.push_str("\\t"),
-            c if c.is_control() => {
-                // Best-effort: encode as a byte escape when it fits in u8.
-                let mut buf = [0u8; 4];
-                let encoded = c.encode_utf8(&mut buf);
-                if encoded.len() == 1 {
-                    out.push_str(&format!("\\x{:02x}", buf[0]));
-                } else {
-                    out.push(c);
-                }
-            }
-            other => out.push(other),
-        }
-    }
-    out
-}
-
-fn emit_block_expr(
-    block: &Block,
+fn emit_binary(
+    expr: &Expr,
+    b: &BinaryExpr,
     out: &mut String,
     ctx: &mut TypeCtx,
     arena: Option<&str>,
     ctrs: &mut Counters,
 ) -> Result<Type, CgenError> {
-    let ty = ctx
-        .infer_block_type(block)
-        .unwrap_or(Type::Named(Ident("Unit".into())));
-    let tmp = format!("__tmp{}", ctrs.tmp);
-    ctrs.tmp += 1;
-    write!(out, "({{ ").map_err(|e| CgenError::Fmt(e.to_string()))?;
-    ctx.push_scope();
-    let scope_name = if let Some(a) = arena {
+    let ty = ctx.expr_type(expr);
+    if matches!(b.op, BinaryOp::Add) && ty.as_ref().is_some_and(|t| ctx.is_str(t)) {
+        let fn_name = if arena.is_some() {
+            "gaut_str_concat_arena"
+        } else {
+            "gaut_str_concat_heap"
+        };
+        if let Some(a) = arena {
+            write!(out, "{}(&{}, ", fn_name, a).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        } else {
+            write!(out, "{}(", fn_name).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        emit_expr(&b.left, out, ctx, arena, ctrs)?;
+        write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        emit_expr(&b.right, out, ctx, arena, ctrs)?;
+        write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    } else if matches!(b.op, BinaryOp::Add) && ty.as_ref().is_some_and(|t| ctx.is_bytes(t)) {
+        let fn_name = if arena.is_some() {
+            "gaut_bytes_concat_arena"
+        } else {
+            "gaut_bytes_concat_heap"
+        };
+        if let Some(a) = arena {
+            write!(out, "{}(&{}, ", fn_name, a).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        } else {
+            write!(out, "{}(", fn_name).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        emit_expr(&b.left, out, ctx, arena, ctrs)?;
+        write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        emit_expr(&b.right, out, ctx, arena, ctrs)?;
+        write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    } else {
+        let str_eq = matches!(b.op, BinaryOp::Eq | BinaryOp::Ne)
+            && ctx
+                .expr_type(&b.left)
+                .as_ref()
+                .is_some_and(|t| ctx.is_str(t));
+        if str_eq {
+            write!(out, "(strcmp(").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&b.left, out, ctx, arena, ctrs)?;
+            write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&b.right, out, ctx, arena, ctrs)?;
+            let cmp = if matches!(b.op, BinaryOp::Ne) {
+                "!="
+            } else {
+                "=="
+            };
+            write!(out, ") {} 0)", cmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        } else if let Some(helper) = int_op_helper(b, ctx.int_model, ctx.overflow) {
+            write!(out, "{helper}(").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&b.left, out, ctx, arena, ctrs)?;
+            write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            if matches!(b.op, BinaryOp::Shl) {
+                emit_shift_count(&b.right, out, ctx, arena, ctrs)?;
+            } else {
+                emit_expr(&b.right, out, ctx, arena, ctrs)?;
+            }
+            write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        } else if matches!(b.op, BinaryOp::Shr) {
+            emit_operand(&b.left, out, ctx, arena, ctrs)?;
+            write!(out, " >> ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_shift_count(&b.right, out, ctx, arena, ctrs)?;
+        } else {
+            emit_operand(&b.left, out, ctx, arena, ctrs)?;
+            let op = match b.op {
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div => "/",
+                BinaryOp::Lt => "<",
+                BinaryOp::Gt => ">",
+                BinaryOp::Le => "<=",
+                BinaryOp::Ge => ">=",
+                BinaryOp::Eq => "==",
+                BinaryOp::Ne => "!=",
+                BinaryOp::BitAnd => "&",
+                BinaryOp::BitOr => "|",
+                BinaryOp::BitXor => "^",
+                BinaryOp::Shl => "<<",
+                BinaryOp::Shr => ">>",
+                BinaryOp::And => "&&",
+                BinaryOp::Or => "||",
+            };
+            write!(out, " {} ", op).map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_operand(&b.right, out, ctx, arena, ctrs)?;
+        }
+    }
+
+    Ok(ctx
+        .expr_type(expr)
+        .unwrap_or(Type::Named(Ident("Unit".into()))))
+}
+
+/// Store `expr` into the lvalue `dest` without a returned temporary: calls to in-place
+/// functions get `&dest` as their destination, record literals are written field by
+/// field and `if` stores from each branch. Anything else is a plain assignment.
+fn emit_in_place(
+    expr: &Expr,
+    dest: &str,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    indent: usize,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(), CgenError> {
+    let pad = "  ".repeat(indent);
+    emit_c_stmt(out, ctx, &pad, |out, ctx| {
+        emit_in_place_stmts(expr, dest, out, ctx, indent, arena, ctrs)
+    })
+}
+
+fn emit_in_place_stmts(
+    expr: &Expr,
+    dest: &str,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    indent: usize,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(), CgenError> {
+    let pad = "  ".repeat(indent);
+    if let Some(call) = ctx.in_place_call(expr) {
+        write!(out, "{}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        let ptr = match dest.strip_prefix("(*").and_then(|d| d.strip_suffix(')')) {
+            Some(ptr) => ptr.to_string(),
+            None => format!("&{dest}"),
+        };
+        emit_in_place_call(&call, &ptr, out, ctx, arena, ctrs)?;
+        return writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()));
+    }
+    match expr {
+        Expr::RecordLit(r) => {
+            if let Some(base) = &r.base {
+                write!(out, "{}{} = ", pad, dest).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr(base, out, ctx, arena, ctrs)?;
+                writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            for f in &r.fields {
+                let field = format!("{dest}.{}", f.name.0);
+                emit_in_place(&f.value, &field, out, ctx, indent, arena, ctrs)?;
+            }
+        }
+        Expr::If(ife) => {
+            write!(out, "{}if (", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(&ife.cond, out, ctx, arena, ctrs)?;
+            writeln!(out, ") {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_in_place(&ife.then_branch, dest, out, ctx, indent + 1, arena, ctrs)?;
+            writeln!(out, "{}}} else {{", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_in_place(&ife.else_branch, dest, out, ctx, indent + 1, arena, ctrs)?;
+            writeln!(out, "{}}}", pad).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        Expr::Block(b) if b.stmts.is_empty() && b.tail.is_some() => {
+            if let Some(tail) = &b.tail {
+                emit_in_place(tail, dest, out, ctx, indent, arena, ctrs)?;
+            }
+        }
+        _ => {
+            write!(out, "{}{} = ", pad, dest).map_err(|e| CgenError::Fmt(e.to_string()))?;
+            emit_expr(expr, out, ctx, arena, ctrs)?;
+            writeln!(out, ";").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+fn emit_in_place_call(
+    call: &FuncCall,
+    ptr: &str,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(), CgenError> {
+    let (pre, args) = sequence_operands(&call.args, ctx, arena, ctrs)?;
+    if !pre.is_empty() {
+        write!(out, "({{ {pre}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    emit_path(&call.callee, out, None)?;
+    write!(out, "({}", ptr).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    for arg in &args {
+        write!(out, ", ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        emit_expr(arg, out, ctx, arena, ctrs)?;
+    }
+    write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    if !pre.is_empty() {
+        ctx.pop_scope();
+        write!(out, "; }})").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Operands (call arguments, binary operands, record fields) are evaluated left to
+/// right, but C leaves their order unspecified. An operand that must come before a
+/// later one (either of the two has side effects, and neither is a literal) is
+/// evaluated into a temporary first, in order. Returns the declarations and the
+/// operands rewritten to use them; when the declarations are not empty the
+/// temporaries live in a new `ctx` scope, which the caller pops after emitting the
+/// rewritten expression. Under `ansi` the declarations are hoisted instead and none
+/// are returned.
+fn sequence_operands(
+    operands: &[Expr],
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(String, Vec<Expr>), CgenError> {
+    let mut pre = String::new();
+    if operands.len() < 2 || !operands.iter().any(has_side_effects) {
+        return Ok((pre, operands.to_vec()));
+    }
+    let mut sequenced = Vec::with_capacity(operands.len());
+    let mut temps = Vec::new();
+    for (i, operand) in operands.iter().enumerate() {
+        let ty = ctx.expr_type(operand).map(|t| ctx.resolve_alias(&t));
+        let later = &operands[i + 1..];
+        let ordered = (has_side_effects(operand)
+            && later.iter().any(|e| !matches!(e, Expr::Literal(..))))
+            || later.iter().any(has_side_effects);
+        // literals cannot observe anything; references and function values are
+        // fixed addresses; Unit has no value to hold
+        let hoist = ordered
+            && !matches!(operand, Expr::Literal(..))
+            && !matches!(
+                &ty,
+                None | Some(Type::Ref(_) | Type::MutRef(_) | Type::Func(_))
+            )
+            && !matches!(&ty, Some(Type::Named(Ident(n))) if n == "Unit");
+        let Some(ty) = ty.filter(|_| hoist) else {
+            sequenced.push(operand.clone());
+            continue;
+        };
+        let tmp = format!("__tmp{}", ctrs.tmp);
+        ctrs.tmp += 1;
+        let mut decl = format!("{} {tmp} = ", map_value_type(&ty, ctx)?);
+        emit_expr(operand, &mut decl, ctx, arena, ctrs)?;
+        push_stmt(&mut pre, ctx, &format!("{decl}; "));
+        temps.push((tmp.clone(), ty));
+        sequenced.push(Expr::Path(Path(vec![Ident(tmp)], operand.span())));
+    }
+    if !temps.is_empty() {
+        // hoisted declarations belong to the scope of the statement being emitted
+        if !ctx.ansi {
+            ctx.push_scope();
+        }
+        for (tmp, ty) in temps {
+            ctx.insert_var(tmp, ty);
+        }
+    }
+    Ok((pre, sequenced))
+}
+
+/// Whether evaluating `expr` can be observed or can observe other evaluations: it
+/// calls a function, runs a block, or may abort (indexing and slicing).
+fn has_side_effects(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(..) | Expr::Path(_) => false,
+        Expr::FuncCall(_) | Expr::Block(_) | Expr::Time(_) | Expr::Index(_) | Expr::Slice(_) => {
+            true
+        }
+        Expr::Copy(inner) | Expr::Ref(inner) | Expr::MutRef(inner) => has_side_effects(inner),
+        Expr::If(ife) => {
+            has_side_effects(&ife.cond)
+                || has_side_effects(&ife.then_branch)
+                || has_side_effects(&ife.else_branch)
+        }
+        Expr::RecordLit(r) => {
+            r.base.as_deref().is_some_and(has_side_effects)
+                || r.fields.iter().any(|f| has_side_effects(&f.value))
+        }
+        Expr::Unary(u) => has_side_effects(&u.expr),
+        Expr::Binary(b) => has_side_effects(&b.left) || has_side_effects(&b.right),
+    }
+}
+
+// Nested binary operands are parenthesized: the source precedence (bitwise above
+// comparisons) differs from C's, and explicit grouping is lost in the AST.
+/// A C spelling of `v`; the minimum is written as the `<stdint.h>` macro because its
+/// magnitude does not fit the signed type as a literal.
+fn int_literal(v: i64, model: IntModel) -> String {
+    match model {
+        IntModel::I32 if v == i32::MIN as i64 => "INT32_MIN".into(),
+        IntModel::I64 if v == i64::MIN => "INT64_MIN".into(),
+        _ => v.to_string(),
+    }
+}
+
+fn int_suffix(model: IntModel) -> &'static str {
+    match model {
+        IntModel::I32 => "I32",
+        IntModel::I64 => "I64",
+    }
+}
+
+/// `wrapping_*`, `saturating_*` and `checked_*` over the compiler's overflow builtins,
+/// or under `ansi` the runtime's portable `gaut_*_overflow_*` equivalents.
+fn emit_int_op_shims(
+    out: &mut String,
+    func_names: &HashSet<String>,
+    type_names: &HashSet<String>,
+    int_model: IntModel,
+    ansi: bool,
+) -> Result<(), CgenError> {
+    let t = int_c_type(int_model);
+    let (min, max, width) = match int_model {
+        IntModel::I32 => ("INT32_MIN", "INT32_MAX", "i32"),
+        IntModel::I64 => ("INT64_MIN", "INT64_MAX", "i64"),
+    };
+    let overflow = |op: &str| {
+        if ansi {
+            format!("gaut_{op}_overflow_{width}")
+        } else {
+            format!("__builtin_{op}_overflow")
+        }
+    };
+    if !type_names.contains("CheckedInt") {
+        writeln!(out, "typedef struct {{ bool ok; {t} value; }} CheckedInt;")
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    // the value saturating_* picks on overflow
+    let clamp = [
+        ("add", "b < 0 ? MIN : MAX"),
+        ("sub", "b < 0 ? MAX : MIN"),
+        ("mul", "(a < 0) != (b < 0) ? MIN : MAX"),
+    ];
+    for (op, on_overflow) in clamp {
+        let on_overflow = on_overflow.replace("MIN", min).replace("MAX", max);
+        let check = overflow(op);
+        let shims = [
+            (
+                format!("wrapping_{op}"),
+                format!("{t} r; {check}(a, b, &r); return r;"),
+            ),
+            (
+                format!("saturating_{op}"),
+                format!("{t} r; return {check}(a, b, &r) ? {on_overflow} : r;"),
+            ),
+        ];
+        for (name, body) in shims {
+            if !func_names.contains(name.as_str()) {
+                writeln!(out, "{t} {name}({t} a, {t} b) {{ {body} }}")
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+        }
+        let name = format!("checked_{op}");
+        if !func_names.contains(name.as_str()) {
+            writeln!(
+                out,
+                "CheckedInt {name}({t} a, {t} b) {{ CheckedInt out; \
+                 out.ok = !{check}(a, b, &out.value); \
+                 if (!out.ok) out.value = 0; return out; }}"
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    if !func_names.contains("checked_div") {
+        writeln!(
+            out,
+            "CheckedInt checked_div({t} a, {t} b) {{ bool ok = b != 0 && !(a == {min} && b == -1); \
+             CheckedInt out = {{ .ok = ok, .value = ok ? a / b : 0 }}; return out; }}"
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    // libc already has abs (and the compiler a builtin pow), so the shims get gaut_ names
+    // and a macro points every use, calls and function values alike, at them
+    let (sub, mul) = (overflow("sub"), overflow("mul"));
+    let math = [
+        (
+            "abs",
+            "n",
+            format!("{t} r = n; if (n < 0) {sub}(({t})0, n, &r); return r;"),
+        ),
+        ("min", "a, b", "return a < b ? a : b;".to_string()),
+        ("max", "a, b", "return a > b ? a : b;".to_string()),
+        (
+            "pow",
+            "base, exp",
+            format!(
+                "if (exp < 0) return base == 1 ? 1 : base == -1 ? ((exp & 1) ? -1 : 1) : 0; \
+                 {t} r = 1; while (exp > 0) {{ if (exp & 1) {mul}(r, base, &r); \
+                 exp >>= 1; if (exp > 0) {mul}(base, base, &base); }} return r;"
+            ),
+        ),
+    ];
+    for (name, params, body) in math {
+        if func_names.contains(name) {
+            continue;
+        }
+        let params: Vec<String> = params.split(", ").map(|p| format!("{t} {p}")).collect();
+        writeln!(
+            out,
+            "static inline {t} gaut_{name}({}) {{ {body} }}\n#define {name} gaut_{name}",
+            params.join(", ")
+        )
+        .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    for (to, lo, hi) in [("i32", "INT32_MIN", "INT32_MAX"), ("u8", "0", "255")] {
+        let name = format!("checked_to_{to}");
+        if !func_names.contains(name.as_str()) {
+            writeln!(
+                out,
+                "CheckedInt {name}({t} n) {{ bool ok = (int64_t)n >= {lo} && (int64_t)n <= {hi}; \
+                 CheckedInt out = {{ .ok = ok, .value = ok ? n : 0 }}; return out; }}"
+            )
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+fn int_c_type(model: IntModel) -> &'static str {
+    match model {
+        IntModel::I32 => "int32_t",
+        IntModel::I64 => "int64_t",
+    }
+}
+
+/// The runtime.h helper that computes an integer operator without signed overflow: it
+/// either aborts (`gaut_trap_*`) or wraps (`GAUT_*`) per the overflow mode. Shifts always
+/// wrap, and division only needs a helper (which also aborts on a zero divisor) when the
+/// divisor might be zero or -1.
+fn int_op_helper(b: &BinaryExpr, model: IntModel, overflow: Overflow) -> Option<String> {
+    let suffix = int_suffix(model);
+    let lower = suffix.to_lowercase();
+    let name = |op: &str| match overflow {
+        Overflow::Trap => format!("gaut_trap_{}_{lower}", op.to_lowercase()),
+        Overflow::Wrap => format!("GAUT_{op}_{suffix}"),
+    };
+    match b.op {
+        BinaryOp::Add => Some(name("ADD")),
+        BinaryOp::Sub => Some(name("SUB")),
+        BinaryOp::Mul => Some(name("MUL")),
+        BinaryOp::Shl => Some(format!("GAUT_SHL_{suffix}")),
+        BinaryOp::Div if !matches!(*b.right, Expr::Literal(Literal::Int(n), _) if n > 0) => {
+            Some(match overflow {
+                Overflow::Trap => format!("gaut_trap_div_{lower}"),
+                Overflow::Wrap => format!("gaut_div_{lower}"),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A shift count, checked at run time against the default integer's width unless it
+/// is a literal already in range.
+fn emit_shift_count(
+    count: &Expr,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(), CgenError> {
+    let bits = ctx.int_model.bits();
+    if matches!(count, Expr::Literal(Literal::Int(n), _) if (0..i64::from(bits)).contains(n)) {
+        emit_operand(count, out, ctx, arena, ctrs)?;
+        return Ok(());
+    }
+    write!(out, "gaut_shift_count(").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    emit_expr(count, out, ctx, arena, ctrs)?;
+    write!(out, ", {bits})").map_err(|e| CgenError::Fmt(e.to_string()))
+}
+
+fn emit_operand(
+    expr: &Expr,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    if matches!(expr, Expr::Binary(_)) {
+        write!(out, "(").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        let ty = emit_expr(expr, out, ctx, arena, ctrs)?;
+        write!(out, ")").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        Ok(ty)
+    } else {
+        emit_expr(expr, out, ctx, arena, ctrs)
+    }
+}
+
+/// The body of a C string literal spelling `s`. Other control characters, C1 ones
+/// included, become `\xNN` escapes of their UTF-8 bytes; as C reads every hex digit
+/// that follows into the escape, the literal is split (`"\x01" "a"`) when one does.
+/// `??` is written `?\?` so that no trigraph forms under strict ISO modes.
+fn escape_c_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut after_hex = false;
+    let mut prev = '\0';
+    for ch in s.chars() {
+        if after_hex && ch.is_ascii_hexdigit() {
+            out.push_str("\" \"");
+        }
+        after_hex = false;
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '?' if prev == '?' => out.push_str("\\?"),
+            c if c.is_control() => {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    out.push_str(&format!("\\x{b:02x}"));
+                }
+                after_hex = true;
+            }
+            other => out.push(other),
+        }
+        prev = ch;
+    }
+    out
+}
+
+fn emit_block_expr(
+    block: &Block,
+    expected: Option<Type>,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    let ty = expected.unwrap_or(Type::Named(Ident("Unit".into())));
+    let tmp = format!("__tmp{}", ctrs.tmp);
+    ctrs.tmp += 1;
+    if ctx.ansi {
+        emit_block_stmts(block, &ty, &tmp, out, ctx, arena, ctrs)?;
+        return Ok(ty);
+    }
+    write!(out, "({{ ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    ctx.push_scope();
+    let scope_name = if let Some(a) = arena {
         let name = format!("__scope{}", ctrs.scope);
         ctrs.scope += 1;
         write!(out, "gaut_scope {} = gaut_scope_enter(&{}); ", name, a)
@@ -1001,273 +2517,1546 @@ fn emit_block_expr(
     for stmt in &block.stmts {
         emit_stmt(stmt, out, ctx, 0, arena, ctrs)?;
     }
+    let drops = block_drops(block, Vec::new(), ctx);
     let cty = map_value_type(&ty, ctx)?;
     if let Some(tail) = &block.tail {
         write!(out, "{} {} = ", cty, tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
-        emit_expr(tail, out, ctx, arena, ctrs)?;
+        emit_expr_as(tail, &ty, out, ctx, arena, ctrs)?;
         write!(out, "; ").map_err(|e| CgenError::Fmt(e.to_string()))?;
     } else {
         write!(out, "{} {} = 0; ", cty, tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
     }
-    if let (Some(a), Some(s)) = (arena, &scope_name) {
-        write!(out, "gaut_scope_leave(&{}, {}); ", a, s)
-            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    for call in &drops {
+        write!(out, "{call} ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    if let (Some(a), Some(s)) = (arena, &scope_name) {
+        write!(out, "gaut_scope_leave(&{}, {}); ", a, s)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    ctx.pop_scope();
+    write!(out, "{}; }})", tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    Ok(ty)
+}
+
+/// `emit_block_expr` under `ansi`: the block becomes a C block of its own, hoisted
+/// before the enclosing statement, that leaves its value in `tmp` (declared ahead of
+/// it); the expression is `tmp`, or `0` for a Unit block, whose value is never read.
+fn emit_block_stmts(
+    block: &Block,
+    ty: &Type,
+    tmp: &str,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<(), CgenError> {
+    let unit = ctx.is_unit(ty);
+    let mut body = String::new();
+    ctx.push_scope();
+    let scope_name = if let Some(a) = arena {
+        let name = format!("__scope{}", ctrs.scope);
+        ctrs.scope += 1;
+        writeln!(body, "  gaut_scope {} = gaut_scope_enter(&{});", name, a)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+        Some(name)
+    } else {
+        None
+    };
+    for stmt in &block.stmts {
+        emit_stmt(stmt, &mut body, ctx, 1, arena, ctrs)?;
+    }
+    let drops = block_drops(block, Vec::new(), ctx);
+    match &block.tail {
+        Some(tail) => emit_c_stmt(&mut body, ctx, "  ", |body, ctx| {
+            if unit {
+                write!(body, "  ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr(tail, body, ctx, arena, ctrs)?;
+            } else {
+                write!(body, "  {tmp} = ").map_err(|e| CgenError::Fmt(e.to_string()))?;
+                emit_expr_as(tail, ty, body, ctx, arena, ctrs)?;
+            }
+            writeln!(body, ";").map_err(|e| CgenError::Fmt(e.to_string()))
+        })?,
+        None if unit => {}
+        None => writeln!(body, "  {tmp} = 0;").map_err(|e| CgenError::Fmt(e.to_string()))?,
+    }
+    emit_drops(&mut body, "  ", &drops)?;
+    if let (Some(a), Some(s)) = (arena, &scope_name) {
+        writeln!(body, "  gaut_scope_leave(&{}, {});", a, s)
+            .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    ctx.pop_scope();
+    let decl = if unit {
+        String::new()
+    } else {
+        format!("{} {tmp};\n", map_value_type(ty, ctx)?)
+    };
+    push_stmt(out, ctx, &format!("{decl}{{\n{body}}}"));
+    let value = if unit { "0" } else { tmp };
+    write!(out, "{value}").map_err(|e| CgenError::Fmt(e.to_string()))
+}
+
+/// An `if` expression under `ansi`. Statements hoisted from a branch must run only
+/// when the branch is taken, so then the `if` becomes a statement storing into a
+/// temporary; otherwise it stays a conditional expression. A Unit `if` is always a
+/// statement, since ISO C rejects `void` operands of `?:`.
+fn emit_if_ansi(
+    expr: &Expr,
+    ife: &IfExpr,
+    expected: Option<Type>,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    let mut cond = String::new();
+    emit_expr(&ife.cond, &mut cond, ctx, arena, ctrs)?;
+    let outer = std::mem::take(&mut ctx.hoisted);
+    let mut then_c = String::new();
+    ctx.expected = expected.clone();
+    emit_expr(&ife.then_branch, &mut then_c, ctx, arena, ctrs)?;
+    let then_h = std::mem::take(&mut ctx.hoisted);
+    let mut else_c = String::new();
+    ctx.expected = expected.clone();
+    emit_expr(&ife.else_branch, &mut else_c, ctx, arena, ctrs)?;
+    let else_h = std::mem::replace(&mut ctx.hoisted, outer);
+    let ty = expected
+        .or_else(|| ctx.expr_type(expr))
+        .unwrap_or(Type::Named(Ident("Unit".into())));
+    let unit = ctx.is_unit(&ty);
+    if then_h.is_empty() && else_h.is_empty() && !unit {
+        write!(out, "({cond} ? {then_c} : {else_c})").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    } else if unit {
+        // a Unit branch's value is `0`, with nothing to run
+        let branch = |h: &str, c: &str| {
+            let stmt = if c == "0" {
+                String::new()
+            } else {
+                format!("{c};")
+            };
+            indented(&format!("{h}{stmt}"))
+        };
+        push_stmt(
+            out,
+            ctx,
+            &format!(
+                "if ({cond}) {{\n{}}} else {{\n{}}}",
+                branch(&then_h, &then_c),
+                branch(&else_h, &else_c)
+            ),
+        );
+        write!(out, "0").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    } else {
+        let tmp = format!("__tmp{}", ctrs.tmp);
+        ctrs.tmp += 1;
+        let cty = map_value_type(&ty, ctx)?;
+        push_stmt(
+            out,
+            ctx,
+            &format!(
+                "{cty} {tmp};\nif ({cond}) {{\n{}}} else {{\n{}}}",
+                indented(&format!("{then_h}{tmp} = {then_c};")),
+                indented(&format!("{else_h}{tmp} = {else_c};"))
+            ),
+        );
+        write!(out, "{tmp}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(ty)
+}
+
+/// `stmts`, one level deeper, each line ending in a newline.
+fn indented(stmts: &str) -> String {
+    stmts.lines().map(|line| format!("  {line}\n")).collect()
+}
+
+/// `&&` or `||` under `ansi`: like `if`, the right operand's hoisted statements run
+/// only when it is evaluated.
+fn emit_logical_ansi(
+    expr: &Expr,
+    b: &BinaryExpr,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    let outer = std::mem::take(&mut ctx.hoisted);
+    let mut right = String::new();
+    emit_expr(&b.right, &mut right, ctx, arena, ctrs)?;
+    let right_h = std::mem::replace(&mut ctx.hoisted, outer);
+    if right_h.is_empty() {
+        return emit_binary(expr, b, out, ctx, arena, ctrs);
+    }
+    let mut left = String::new();
+    emit_expr(&b.left, &mut left, ctx, arena, ctrs)?;
+    let tmp = format!("__tmp{}", ctrs.tmp);
+    ctrs.tmp += 1;
+    let test = if matches!(b.op, BinaryOp::And) {
+        tmp.clone()
+    } else {
+        format!("!{tmp}")
+    };
+    push_stmt(
+        out,
+        ctx,
+        &format!(
+            "bool {tmp} = {left};\nif ({test}) {{\n{}}}",
+            indented(&format!("{right_h}{tmp} = {right};"))
+        ),
+    );
+    write!(out, "{tmp}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    Ok(Type::Named(Ident("bool".into())))
+}
+
+/// Drop hook calls due when `block` ends, last binding first: one per binding of a
+/// type with a `Drop` impl that is never moved later in the block. The typechecker
+/// rejects moves on only one branch of an `if`, so any move here is unconditional.
+fn block_drops(block: &Block, params: Vec<(String, String)>, ctx: &TypeCtx) -> Vec<String> {
+    let mut owned: Vec<(String, String, usize)> = params
+        .into_iter()
+        .map(|(name, hook)| (name, hook, 0))
+        .collect();
+    for (i, stmt) in block.stmts.iter().enumerate() {
+        if let Stmt::Binding(b) = stmt {
+            if let Some(hook) = ctx.drop_hook(&b.ty) {
+                owned.push((b.name.0.clone(), hook, i + 1));
+            }
+        }
+    }
+    owned
+        .into_iter()
+        .rev()
+        .filter(|(name, _, from)| {
+            !block.stmts[*from..]
+                .iter()
+                .any(|stmt| stmt_moves(stmt, name, ctx))
+                && !block
+                    .tail
+                    .as_ref()
+                    .is_some_and(|t| expr_moves(t, name, ctx))
+        })
+        .map(|(name, hook, _)| format!("{hook}(&{name});"))
+        .collect()
+}
+
+fn emit_drops(out: &mut String, pad: &str, drops: &[String]) -> Result<(), CgenError> {
+    for call in drops {
+        writeln!(out, "{pad}{call}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn stmt_moves(stmt: &Stmt, name: &str, ctx: &TypeCtx) -> bool {
+    match stmt {
+        Stmt::Binding(b) => expr_moves(&b.value, name, ctx),
+        Stmt::Assign(a) => expr_moves(&a.value, name, ctx),
+        Stmt::Expr(e) => expr_moves(e, name, ctx),
+        Stmt::Func(_) => false,
+    }
+}
+
+/// Whether evaluating `expr` moves (part of) the binding `name`. Like the typechecker,
+/// using any path rooted at `name` by value moves the whole binding.
+fn expr_moves(expr: &Expr, name: &str, ctx: &TypeCtx) -> bool {
+    match expr {
+        Expr::Literal(..) | Expr::Copy(_) | Expr::Ref(_) | Expr::MutRef(_) => false,
+        Expr::Path(p) => p.0.first().is_some_and(|h| h.0 == name),
+        Expr::FuncCall(fc) => {
+            let by_value_receiver = match (fc.method_name(), fc.callee.0.first()) {
+                (Some(m), Some(head)) => head.0 == name && !ctx.ref_receivers.contains_key(&m.0),
+                _ => false,
+            };
+            by_value_receiver || fc.args.iter().any(|a| expr_moves(a, name, ctx))
+        }
+        Expr::If(ife) => {
+            expr_moves(&ife.cond, name, ctx)
+                || expr_moves(&ife.then_branch, name, ctx)
+                || expr_moves(&ife.else_branch, name, ctx)
+        }
+        Expr::Block(b) => {
+            for stmt in &b.stmts {
+                if stmt_moves(stmt, name, ctx) {
+                    return true;
+                }
+                // a shadowing binding hides `name` for the rest of the block
+                if matches!(stmt, Stmt::Binding(inner) if inner.name.0 == name) {
+                    return false;
+                }
+            }
+            b.tail.as_ref().is_some_and(|t| expr_moves(t, name, ctx))
+        }
+        Expr::RecordLit(r) => {
+            r.base.as_deref().is_some_and(|b| expr_moves(b, name, ctx))
+                || r.fields.iter().any(|f| expr_moves(&f.value, name, ctx))
+        }
+        Expr::Unary(u) => expr_moves(&u.expr, name, ctx),
+        Expr::Time(body) => expr_moves(body, name, ctx),
+        Expr::Binary(b) => expr_moves(&b.left, name, ctx) || expr_moves(&b.right, name, ctx),
+        // the indexed value is only borrowed
+        Expr::Index(ix) => expr_moves(&ix.index, name, ctx),
+        Expr::Slice(sl) => [&sl.start, &sl.end]
+            .into_iter()
+            .flatten()
+            .any(|b| expr_moves(b, name, ctx)),
+    }
+}
+
+/// Whether the base of `s[i]` / `s[a..b]` is `Bytes` (else `Str`), and the `*` that
+/// reads it through a reference.
+fn indexed_base(base: &Expr, ctx: &TypeCtx) -> Result<(bool, &'static str), CgenError> {
+    let ty = ctx
+        .expr_type(base)
+        .map(|t| ctx.resolve_alias(&t))
+        .ok_or_else(|| CgenError::Unsupported("index of unknown type".into()))?;
+    Ok(match ty {
+        Type::Ref(inner) | Type::MutRef(inner) => (ctx.is_bytes(&inner), "*"),
+        other => (ctx.is_bytes(&other), ""),
+    })
+}
+
+/// The runtime function an atomic builtin calls, so the generated names stay clear of
+/// `<stdatomic.h>`'s `atomic_load` and friends. `None` for other callees, including a
+/// program's own function of the same name.
+fn atomic_c_name(callee: &str, ctx: &TypeCtx) -> Option<&'static str> {
+    if ctx.params.contains_key(callee) {
+        return None;
+    }
+    match callee {
+        "atomic_new" => Some("gaut_atomic_new"),
+        "atomic_load" => Some("gaut_atomic_load"),
+        "atomic_store" => Some("gaut_atomic_store"),
+        "atomic_add" if ctx.overflow == Overflow::Trap => Some("gaut_atomic_add_trap"),
+        "atomic_add" => Some("gaut_atomic_add"),
+        "atomic_cas" => Some("gaut_atomic_cas"),
+        _ => None,
+    }
+}
+
+/// The i64 operands of an atomic builtin, so literals are emitted at that width under
+/// the i32 model.
+fn atomic_param_types(callee: &str) -> Vec<Type> {
+    let i64_ty = || Type::Named(Ident("i64".into()));
+    let cell = || Type::Ref(Box::new(Type::Named(Ident("atomic_i64".into()))));
+    match callee {
+        "atomic_new" => vec![i64_ty()],
+        "atomic_load" => vec![cell()],
+        "atomic_store" | "atomic_add" => vec![cell(), i64_ty()],
+        "atomic_cas" => vec![cell(), i64_ty(), i64_ty()],
+        _ => Vec::new(),
+    }
+}
+
+fn emit_path(path: &Path, out: &mut String, ctx: Option<&TypeCtx>) -> Result<(), CgenError> {
+    if let (Some(tc), Some((head, rest))) = (ctx, path.0.split_first()) {
+        let mut current = tc.type_of_ident(&head.0);
+        write!(out, "{}", head.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+        for field in rest {
+            if let Some(ref ty) = current {
+                let resolved = tc.resolve_alias(ty);
+                match resolved {
+                    Type::Ref(inner) | Type::MutRef(inner) => {
+                        write!(out, "->{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                        current = tc.field_type(&inner, &field.0);
+                    }
+                    _ => {
+                        write!(out, ".{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                        current = tc.field_type(ty, &field.0);
+                    }
+                }
+            } else {
+                write!(out, ".{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+                current = None;
+            }
+        }
+        return Ok(());
+    }
+
+    for (i, ident) in path.0.iter().enumerate() {
+        if i > 0 {
+            write!(out, ".").map_err(|e| CgenError::Fmt(e.to_string()))?;
+        }
+        write!(out, "{}", ident.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn is_builtin_name(name: &str) -> bool {
+    matches!(
+        name,
+        "i32"
+            | "i64"
+            | "u8"
+            | "bool"
+            | "Str"
+            | "Bytes"
+            | "Unit"
+            | "atomic_i64"
+            | "Dyn"
+            | "TcpListener"
+            | "TcpConn"
+            | "Chan"
+    )
+}
+
+/// The first declared record type, by name, with the fields of `ty`; used only for
+/// literals no declared type reaches.
+fn find_record_alias(ctx: &TypeCtx, ty: &Type) -> Option<String> {
+    let Type::Record(fields) = ctx.resolve_alias(ty) else {
+        return None;
+    };
+    let mut decls: Vec<(&String, &Type)> = ctx.types.iter().collect();
+    decls.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, aliased) in decls {
+        if is_builtin_name(name) {
+            continue;
+        }
+        if let Type::Record(alias_fields) = ctx.resolve_alias(aliased) {
+            if fields.len() != alias_fields.len() {
+                continue;
+            }
+            let mut same = true;
+            for (a, b) in fields.iter().zip(alias_fields.iter()) {
+                if a.name != b.name || ctx.resolve_alias(&a.ty) != ctx.resolve_alias(&b.ty) {
+                    same = false;
+                    break;
+                }
+            }
+            if same {
+                return Some(name.clone());
+            }
+        }
+    }
+    None
+}
+
+/// The builtin `to_str(v)`: `v` goes into a temporary that is rendered field by field,
+/// as the interpreter displays values, with record fields in declared order.
+fn emit_to_str(
+    arg: &Expr,
+    out: &mut String,
+    ctx: &mut TypeCtx,
+    arena: Option<&str>,
+    ctrs: &mut Counters,
+) -> Result<Type, CgenError> {
+    let ty = ctx
+        .expr_type(arg)
+        .ok_or_else(|| CgenError::Unsupported("to_str of a value of unknown type".into()))?;
+    let tmp = format!("__tmp{}", ctrs.tmp);
+    ctrs.tmp += 1;
+    open_stmt_expr(out, ctx)?;
+    let mut stmt = format!("{} {tmp} = ", map_value_type(&ty, ctx)?);
+    emit_expr_as(arg, &ty, &mut stmt, ctx, arena, ctrs)?;
+    push_stmt(out, ctx, &format!("{stmt}; "));
+    let rendered = match ctx.resolve_alias(&ty) {
+        Type::Ref(inner) | Type::MutRef(inner) => {
+            render_c_value(&format!("(*{tmp})"), &inner, ctx)?
+        }
+        _ => render_c_value(&tmp, &ty, ctx)?,
+    };
+    write!(out, "{rendered}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+    close_stmt_expr(out, ctx)?;
+    Ok(Type::Named(Ident("Str".into())))
+}
+
+/// A C expression building the `to_str` text of the value at `place`.
+fn render_c_value(place: &str, ty: &Type, ctx: &TypeCtx) -> Result<String, CgenError> {
+    match ctx.resolve_alias(ty) {
+        Type::Named(id) => match id.0.as_str() {
+            "i32" | "i64" | "u8" => Ok(format!("gaut_int_to_str((int64_t){place})")),
+            "bool" => Ok(format!("gaut_bool_to_str({place})")),
+            "Str" => Ok(format!("gaut_str_quote({place})")),
+            other => Err(CgenError::Unsupported(format!("to_str of {other}"))),
+        },
+        Type::Record(fields) if fields.is_empty() => Ok("\"{}\"".into()),
+        Type::Record(fields) => {
+            let mut rendered = "\"{ \"".to_string();
+            for (i, field) in fields.iter().enumerate() {
+                let sep = if i == 0 { "" } else { ", " };
+                let value = render_c_value(&format!("{place}.{}", field.name.0), &field.ty, ctx)?;
+                rendered = format!(
+                    "gaut_str_concat_heap(gaut_str_concat_heap({rendered}, \"{sep}{}: \"), {value})",
+                    field.name.0
+                );
+            }
+            Ok(format!("gaut_str_concat_heap({rendered}, \" }}\")"))
+        }
+        other => Err(CgenError::Unsupported(format!("to_str of {other:?}"))),
+    }
+}
+
+fn map_value_type(ty: &Type, ctx: &TypeCtx) -> Result<String, CgenError> {
+    match ty {
+        Type::Named(id) => {
+            let resolved = ctx.resolve_alias(ty);
+            if matches!(resolved, Type::Named(Ident(ref n)) if n == "Unit") {
+                return Ok("int".into());
+            }
+            match id.0.as_str() {
+                "i32" => Ok("int32_t".into()),
+                "i64" => Ok("int64_t".into()),
+                "u8" => Ok("uint8_t".into()),
+                "bool" => Ok("bool".into()),
+                "Str" => Ok("char*".into()),
+                "Bytes" => Ok("gaut_bytes".into()),
+                "atomic_i64" => Ok("gaut_atomic_i64".into()),
+                "Dyn" => Ok("gaut_dyn".into()),
+                "TcpListener" => Ok("gaut_tcp_listener".into()),
+                "TcpConn" => Ok("gaut_tcp_conn".into()),
+                "Chan" => Ok("gaut_chan".into()),
+                other => Ok(other.to_string()),
+            }
+        }
+        Type::Ref(inner) | Type::MutRef(inner) => Ok(format!("{}*", map_value_type(inner, ctx)?)),
+        Type::Func(f) => fn_type_name(f, ctx),
+        Type::Record(fields) => {
+            let mut tmp = String::new();
+            writeln!(tmp, "struct {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            for f in fields {
+                let cty = map_value_type(&f.ty, ctx)?;
+                writeln!(tmp, "  {} {};", cty, f.name.0)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            write!(tmp, "}}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            Ok(tmp)
+        }
+    }
+}
+
+fn map_type(ty: &Type, ctx: &TypeCtx) -> Result<String, CgenError> {
+    match ty {
+        Type::Named(id) => match id.0.as_str() {
+            "i32" => Ok("int32_t".into()),
+            "i64" => Ok("int64_t".into()),
+            "u8" => Ok("uint8_t".into()),
+            "bool" => Ok("bool".into()),
+            "Str" => Ok("char*".into()),
+            "Bytes" => Ok("gaut_bytes".into()),
+            "atomic_i64" => Ok("gaut_atomic_i64".into()),
+            "Dyn" => Ok("gaut_dyn".into()),
+            "TcpListener" => Ok("gaut_tcp_listener".into()),
+            "TcpConn" => Ok("gaut_tcp_conn".into()),
+            "Chan" => Ok("gaut_chan".into()),
+            "Unit" => Ok("void".into()),
+            other => Ok(other.to_string()),
+        },
+        Type::Ref(inner) | Type::MutRef(inner) => Ok(format!("{}*", map_type(inner, ctx)?)),
+        Type::Func(f) => fn_type_name(f, ctx),
+        Type::Record(fields) => {
+            let mut tmp = String::new();
+            writeln!(tmp, "struct {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            for f in fields {
+                let cty = map_type(&f.ty, ctx)?;
+                writeln!(tmp, "  {} {};", cty, f.name.0)
+                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+            }
+            write!(tmp, "}}").map_err(|e| CgenError::Fmt(e.to_string()))?;
+            Ok(tmp)
+        }
+    }
+}
+
+fn fn_type_name(f: &FuncType, ctx: &TypeCtx) -> Result<String, CgenError> {
+    ctx.fn_types
+        .iter()
+        .position(|t| t == f)
+        .map(|i| format!("gaut_fn_{i}"))
+        .ok_or_else(|| {
+            CgenError::Unsupported("function type that is not written in an annotation".into())
+        })
+}
+
+/// Gather function types from every type annotation, inner types first so typedefs can nest.
+fn collect_fn_types_in_program(program: &Program, out: &mut Vec<FuncType>) {
+    for decl in &program.decls {
+        match decl {
+            // traits are lowered to plain functions before codegen
+            Decl::Import(_) | Decl::Trait(_) | Decl::Impl(_) | Decl::Macro(_) => {}
+            Decl::Type(t) => collect_fn_types(&t.ty, out),
+            Decl::Global(b) | Decl::Let(b) => {
+                collect_fn_types(&b.ty, out);
+                collect_fn_types_in_expr(&b.value, out);
+            }
+            Decl::Func(f) => {
+                for p in &f.params {
+                    collect_fn_types(&p.ty, out);
+                }
+                if let Some(ret) = &f.ret {
+                    collect_fn_types(ret, out);
+                }
+                collect_fn_types_in_expr(&f.body, out);
+            }
+        }
+    }
+}
+
+fn collect_fn_types(ty: &Type, out: &mut Vec<FuncType>) {
+    match ty {
+        Type::Named(_) => {}
+        Type::Ref(inner) | Type::MutRef(inner) => collect_fn_types(inner, out),
+        Type::Record(fields) => {
+            for f in fields {
+                collect_fn_types(&f.ty, out);
+            }
+        }
+        Type::Func(f) => {
+            for p in &f.params {
+                collect_fn_types(p, out);
+            }
+            collect_fn_types(&f.ret, out);
+            if !out.contains(f) {
+                out.push(f.clone());
+            }
+        }
+    }
+}
+
+fn collect_fn_types_in_expr(expr: &Expr, out: &mut Vec<FuncType>) {
+    match expr {
+        Expr::Literal(..) | Expr::Path(_) => {}
+        Expr::Copy(inner) | Expr::Ref(inner) | Expr::MutRef(inner) | Expr::Time(inner) => {
+            collect_fn_types_in_expr(inner, out)
+        }
+        Expr::FuncCall(fc) => {
+            for a in &fc.args {
+                collect_fn_types_in_expr(a, out);
+            }
+        }
+        Expr::If(ife) => {
+            collect_fn_types_in_expr(&ife.cond, out);
+            collect_fn_types_in_expr(&ife.then_branch, out);
+            collect_fn_types_in_expr(&ife.else_branch, out);
+        }
+        Expr::Block(b) => {
+            for stmt in &b.stmts {
+                match stmt {
+                    Stmt::Binding(binding) => {
+                        collect_fn_types(&binding.ty, out);
+                        collect_fn_types_in_expr(&binding.value, out);
+                    }
+                    Stmt::Assign(a) => collect_fn_types_in_expr(&a.value, out),
+                    Stmt::Expr(e) => collect_fn_types_in_expr(e, out),
+                    Stmt::Func(_) => {}
+                }
+            }
+            if let Some(tail) = &b.tail {
+                collect_fn_types_in_expr(tail, out);
+            }
+        }
+        Expr::RecordLit(r) => {
+            if let Some(base) = &r.base {
+                collect_fn_types_in_expr(base, out);
+            }
+            for f in &r.fields {
+                collect_fn_types_in_expr(&f.value, out);
+            }
+        }
+        Expr::Unary(u) => collect_fn_types_in_expr(&u.expr, out),
+        Expr::Binary(b) => {
+            collect_fn_types_in_expr(&b.left, out);
+            collect_fn_types_in_expr(&b.right, out);
+        }
+        Expr::Index(ix) => {
+            collect_fn_types_in_expr(&ix.base, out);
+            collect_fn_types_in_expr(&ix.index, out);
+        }
+        Expr::Slice(sl) => {
+            collect_fn_types_in_expr(&sl.base, out);
+            for bound in [&sl.start, &sl.end].into_iter().flatten() {
+                collect_fn_types_in_expr(bound, out);
+            }
+        }
+    }
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.0
+        .iter()
+        .map(|i| i.0.clone())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_program() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = a + b
+
+        main() = {
+          x: i32 = 10
+          y: i32 = 20
+          add(x, y)
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("int32_t add"));
+        assert!(c.contains("int main(int argc, char** argv)"));
+        assert!(c.contains("gaut_arena __arena"));
+        assert!(c.contains("add(x, y)"));
+    }
+
+    #[test]
+    fn typed_programs_use_the_checked_types() {
+        // the branches only agree once the literal is checked against `P`
+        let src = r#"
+        type P = { x: i32 }
+        origin() -> P = { x: 0 }
+        pick(b: bool) = if b then origin() else { x: 5 }
+        main() = {
+          p: P = pick(false)
+          p.x
+        }
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let typed = frontend::typecheck::TypeChecker::new()
+            .check_program(&program)
+            .unwrap();
+        let c = generate_c_typed(&typed, CgenOptions::default()).unwrap();
+        assert!(c.contains("void pick(P* __out, bool b) {"), "{c}");
+        assert!(c.contains("pick(&p, false);"), "{c}");
+    }
+
+    #[test]
+    fn unicode_names_become_universal_character_names() {
+        let src =
+            "type 점 = { höhe: i32 }\n넓이(p: 점) -> i32 = copy p.höhe\nmain() = 넓이({ höhe: 7 })";
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.is_ascii());
+        assert!(c.contains("} \\uC810;"), "{c}");
+        assert!(c.contains("int32_t \\uB113\\uC774(\\uC810 p)"), "{c}");
+        assert!(c.contains("p.h\\u00F6he"), "{c}");
+    }
+
+    #[test]
+    fn record_literals_take_the_declared_type() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        type Size = { x: i32, y: i32 }
+        type Pos = Point
+
+        area(s: &Size) -> i32 = copy s.x * copy s.y
+
+        main() = {
+          p: Pos = { x: 1, y: 2 }
+          area(&{ x: 3, y: 4 }) + copy p.x
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("typedef Point Pos;"));
+        assert!(c.contains("Pos p = (Pos){ .x = 1, .y = 2 };"));
+        assert!(c.contains("area(&(Size){ .x = 3, .y = 4 })"));
+    }
+
+    #[test]
+    fn inferred_return_function_signature() {
+        let src = r#"
+        id(x: i32) = x
+
+        main() = {
+          out: i32 = id(7)
+          out
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("int32_t id(int32_t x);"));
+        assert!(c.contains("int32_t id(int32_t x) {"));
+    }
+
+    #[test]
+    fn string_concat_uses_runtime() {
+        let src = r#"
+        main() = {
+          msg: Str = "hello" + " world"
+          msg
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_str_concat"));
+    }
+
+    #[test]
+    fn record_ref_uses_arrow() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+
+        length_x(p: &Point) -> i32 = p.x
+
+        main() = {
+          origin: Point = { x: 0, y: 0 }
+          px: i32 = length_x(&origin)
+          px
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("p->x"));
+        assert!(c.contains("Point origin"));
+    }
+
+    #[test]
+    fn read_file_calls_runtime() {
+        let src = r#"
+        main() = {
+          content: Str = read_file("foo.txt")
+          content
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_read_file"));
+    }
+
+    #[test]
+    fn main_inits_argv() {
+        let src = r#"
+        main() = {
+          x: i32 = 0
+          x
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("int main(int argc, char** argv)"));
+        assert!(c.contains("gaut_args_init(argc, argv);"));
+    }
+
+    #[test]
+    fn main_reads_runtime_config() {
+        let src = r#"
+        main() = {
+          cap: Str = config_get("arena_cap")
+          0
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_config_init();"));
+        assert!(c.contains("gaut_arena __arena = gaut_main_arena();"));
+        assert!(c.contains("char* config_get(char* key) { return gaut_config_get(key); }"));
+    }
+
+    #[test]
+    fn bytes_to_str_uses_runtime() {
+        let src = r#"
+        main() = {
+          s: Str = bytes_to_str(args())
+          s
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_args()"));
+        assert!(c.contains("gaut_bytes_to_str"));
+    }
+
+    #[test]
+    fn try_read_file_uses_result_type() {
+        let src = r#"
+        main() = {
+          r: ReadFileResult = try_read_file("missing.txt")
+          r.data
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("typedef struct { bool ok; char* data; } ReadFileResult;"));
+        assert!(c.contains("ReadFileResult try_read_file"));
+    }
+
+    #[test]
+    fn comparison_operators_emit_c() {
+        let src = r#"
+        main() = {
+          a: i32 = 1
+          s: Str = "x"
+          ok: bool = copy a >= 0 && copy a != 2 && copy s != "y"
+          ok
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("a >= 0"));
+        assert!(c.contains("a != 2"));
+        assert!(c.contains("(strcmp(s, \"y\") != 0)"));
+    }
+
+    #[test]
+    fn bitwise_operators_keep_grouping() {
+        let src = r#"
+        main() = {
+          a: i32 = 6
+          ok: bool = copy a & 4 == 4
+          mask: i32 = (copy a + 1) << 2 | 1
+          high: i32 = copy mask >> a
+          ok
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("(a & 4) == 4"));
+        assert!(c.contains("(GAUT_SHL_I32(gaut_trap_add_i32(a, 1), 2)) | 1"));
+        assert!(c.contains("mask >> gaut_shift_count(a, 32)"));
+    }
+
+    #[test]
+    fn integer_ops_wrap_at_the_model_width() {
+        let src = r#"
+        half(n: i64) -> i64 = copy n / 2 - n / -1
+        main() = -half(5000000000) * 3
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let options = CgenOptions {
+            int_model: IntModel::I64,
+            overflow: Overflow::Wrap,
+            ..CgenOptions::default()
+        };
+        let c = generate_c_with(&program, options).unwrap();
+        assert!(c.contains("GAUT_SUB_I64(n / 2, gaut_div_i64(n, -1))"));
+        assert!(c.contains("GAUT_MUL_I64(GAUT_NEG_I64(half(5000000000)), 3)"));
+        // trapping is the default; a negative literal folds instead of being negated
+        let c = generate_c_with(
+            &program,
+            CgenOptions {
+                int_model: IntModel::I64,
+                ..CgenOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(c.contains("gaut_trap_sub_i64(n / 2, gaut_trap_div_i64(n, -1))"));
+        assert!(c.contains("gaut_trap_mul_i64(gaut_trap_neg_i64(half(5000000000)), 3)"));
+        // under the default model the literal still has the parameter's type, i64
+        let c = generate_c_from_source(
+            "keep(n: i64) -> i64 = copy n\nmain() = { k: i64 = keep(5000000000)\n 0 }",
+        )
+        .unwrap();
+        assert!(c.contains("keep(5000000000)"));
+    }
+
+    #[test]
+    fn atomics_map_to_runtime() {
+        let src = r#"
+        main() = {
+          counter: atomic_i64 = atomic_new(0)
+          prev: i64 = atomic_add(&counter, 1)
+          atomic_load(&counter)
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_atomic_i64 counter = gaut_atomic_new(0)"));
+        assert!(c.contains("int64_t prev = gaut_atomic_add_trap(&counter, 1)"));
+        assert!(!c.contains(" atomic_load("));
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let options = CgenOptions {
+            overflow: Overflow::Wrap,
+            ..CgenOptions::default()
+        };
+        let c = generate_c_with(&program, options).unwrap();
+        assert!(c.contains("gaut_atomic_add(&counter, 1)"));
+    }
+
+    #[test]
+    fn dyn_maps_to_the_runtime_table() {
+        let src = r#"
+        main() = {
+          d: Dyn = dyn_new()
+          f: DynField = dyn_get(&d, "k")
+          if copy f.found then 1 else 0
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("typedef struct { bool found; char* value; } DynField;"));
+        assert!(c.contains("gaut_dyn d = dyn_new()"), "{c}");
+        assert!(c.contains("DynField dyn_get(gaut_dyn* d, char* key)"));
+    }
+
+    #[test]
+    fn threads_and_channels_map_to_the_runtime() {
+        let src = r#"
+        global done: Chan = channel()
+        work() -> Unit = send(&done, "ok")
+        main() -> Str = {
+          spawn(work)
+          recv(&done)
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("gaut_chan done;"), "{c}");
+        assert!(c.contains("spawn(work);"), "{c}");
+        assert!(c.contains("static void send(gaut_chan* c, char* msg)"));
+    }
+
+    #[test]
+    fn flush_and_buffering_map_to_the_runtime() {
+        let c =
+            generate_c_from_source("main() = {\n  set_print_buffer(\"block\")\n  flush()\n  0\n}")
+                .unwrap();
+        assert!(c.contains("void flush(void) { gaut_flush(); }"));
+        assert!(c.contains("return gaut_set_print_buffer(mode);"));
+    }
+
+    #[test]
+    fn str_to_int_checks_the_int_range() {
+        let c =
+            generate_c_from_source("main() = {\n  r: StrToIntResult = str_to_int(\"1\")\n  0\n}")
+                .unwrap();
+        assert!(c.contains("typedef struct { bool ok; int32_t value; } StrToIntResult;"));
+        assert!(c.contains("v >= INT32_MIN && v <= INT32_MAX"), "{c}");
+    }
+
+    #[test]
+    fn side_effecting_operands_are_sequenced() {
+        let src = "f(a: i32, b: i32) -> i32 = copy a - copy b\nmain() -> i32 = f(f(1, 2), f(3, 4)) + f(5, 6) * 2";
+        let c = generate_c_from_source(src).unwrap();
+        assert!(
+            c.contains("({ int32_t __tmp2 = f(1, 2); f(__tmp2, f(3, 4)); })"),
+            "{c}"
+        );
+        assert!(c.contains("gaut_trap_mul_i32(f(5, 6), 2)"), "{c}");
+        let c =
+            generate_c_from_source("f(a: i32) -> i32 = copy a\nmain() -> i32 = f(1) + 2").unwrap();
+        assert!(!c.contains("__tmp"), "{c}");
+    }
+
+    #[test]
+    fn statements_after_exit_are_not_emitted() {
+        let c = generate_c_from_source(
+            "stop(code: i32) -> i32 = {\n  if copy code > 0 then exit(copy code) else exit(0)\n  println(\"late\")\n  copy code\n}\nmain() -> i32 = stop(2)",
+        )
+        .unwrap();
+        let body = &c[c.find("int32_t stop(int32_t code) {").unwrap()..];
+        let body = &body[..body.find("\n}\n").unwrap()];
+        assert!(body.contains("exit(code)"), "{body}");
+        assert!(!body.contains("late") && !body.contains("return"), "{body}");
+    }
+
+    #[test]
+    fn exit_and_stdin_builtins_reach_libc() {
+        let c = generate_c_from_source("main() -> i32 = {\n  exit(3)\n  0\n}").unwrap();
+        assert!(c.contains("exit(3);"), "{c}");
+        assert!(!c.contains("void exit("), "{c}");
+
+        let c = generate_c_from_source("main() = {\n  line: Str = read_line()\n  str_len(line)\n}")
+            .unwrap();
+        assert!(
+            c.contains("char* read_line(void) { return gaut_read_line(); }"),
+            "{c}"
+        );
+    }
+
+    #[test]
+    fn non_literal_globals_are_assigned_before_main() {
+        let c = generate_c_from_source(
+            "global total: i32 = copy base * seed()\nglobal base: i32 = 21\nseed() -> i32 = 2\nmain() -> i32 = copy total",
+        )
+        .unwrap();
+        assert!(c.contains("int32_t base = 21;"), "{c}");
+        assert!(c.contains("int32_t total;"), "{c}");
+        assert!(
+            c.contains("static void gaut_init_globals(void) {\n  total = "),
+            "{c}"
+        );
+        let init = c.find("gaut_init_globals();").unwrap();
+        assert!(init > c.find("gaut_args_init(argc, argv);").unwrap(), "{c}");
+    }
+
+    #[test]
+    fn functions_are_declared_before_any_definition() {
+        let c = generate_c_from_source(
+            "global step: (i32) -> i32 = inc\nmain() -> i32 = twice(1)\ntwice(n: i32) -> i32 = step(inc(copy n))\ninc(n: i32) -> i32 = copy n + 1",
+        )
+        .unwrap();
+        let first_body = c.find("int main(int argc, char** argv) {").unwrap();
+        for proto in ["int32_t twice(int32_t n);", "int32_t inc(int32_t n);"] {
+            let at = c
+                .find(proto)
+                .unwrap_or_else(|| panic!("{proto} missing: {c}"));
+            assert!(at < first_body, "{c}");
+        }
+        // a function named by a global initializer is assigned once it is declared
+        assert!(
+            c.find("step = inc;").unwrap() > c.find("int32_t inc(int32_t n);").unwrap(),
+            "{c}"
+        );
+    }
+
+    #[test]
+    fn constant_globals_are_folded_into_static_initializers() {
+        let c = generate_c_from_source(
+            "global total: i32 = -(copy base * 2)\nglobal base: i32 = 20 + 1\n\
+             global big: bool = !(copy total < 0)\nmain() -> i32 = copy total",
+        )
+        .unwrap();
+        assert!(c.contains("int32_t base = 21;"), "{c}");
+        assert!(c.contains("int32_t total = -42;"), "{c}");
+        assert!(c.contains("bool big = false;"), "{c}");
+        assert!(!c.contains("gaut_init_globals"), "{c}");
+    }
+
+    #[test]
+    fn integer_literals_keep_the_width_of_their_declared_type() {
+        let src = "global big: i64 = 3000000000\n\
+                   wide(n: i64) -> i64 = n\n\
+                   main() -> i32 = {\n  b: u8 = 255\n  w: i64 = wide(-4000000000)\n  \
+                   if copy w < 0 then 1 else 0\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let typed = frontend::typecheck::TypeChecker::new()
+            .check_program(&program)
+            .unwrap();
+        let c = generate_c_typed(&typed, CgenOptions::default()).unwrap();
+        assert!(c.contains("int64_t big = 3000000000;"), "{c}");
+        assert!(c.contains("uint8_t b = 255;"), "{c}");
+        assert!(c.contains("wide(-4000000000)"), "{c}");
+    }
+
+    #[test]
+    fn main_exits_with_its_value_only_when_it_is_a_number() {
+        let c = generate_c_from_source("main() -> i64 = 7").unwrap();
+        assert!(c.contains("  return __ret0;\n}"), "{c}");
+        let c = generate_c_from_source("main() = {\n  println(\"x\")\n}").unwrap();
+        assert!(c.contains("  (void)__ret0;\n  return 0;\n}"), "{c}");
+    }
+
+    #[test]
+    fn time_builtins_wrap_the_runtime_clock() {
+        let c = generate_c_from_source(
+            "main() = {\n  start: i64 = now_millis()\n  sleep_ms(5)\n  0\n}",
+        )
+        .unwrap();
+        assert!(
+            c.contains("int64_t now_millis(void) { return gaut_now_millis(); }"),
+            "{c}"
+        );
+        assert!(
+            c.contains("void sleep_ms(int32_t ms) { gaut_sleep_ms(ms); }"),
+            "{c}"
+        );
+    }
+
+    #[test]
+    fn rand_builtins_use_the_default_int() {
+        let c = generate_c_from_source("main() = {\n  seed_rand(1)\n  rand_i32(0, 6)\n}").unwrap();
+        assert!(
+            c.contains("int32_t rand_i32(int32_t lo, int32_t hi) { return (int32_t)gaut_rand_range(lo, hi); }"),
+            "{c}"
+        );
+        assert!(c.contains("seed_rand(1);"), "{c}");
     }
-    ctx.pop_scope();
-    write!(out, "{}; }})", tmp).map_err(|e| CgenError::Fmt(e.to_string()))?;
-    Ok(ty)
-}
 
-fn emit_path(path: &Path, out: &mut String, ctx: Option<&TypeCtx>) -> Result<(), CgenError> {
-    if let (Some(tc), Some((head, rest))) = (ctx, path.0.split_first()) {
-        let mut current = tc.type_of_ident(&head.0);
-        write!(out, "{}", head.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-        for field in rest {
-            if let Some(ref ty) = current {
-                let resolved = tc.resolve_alias(ty);
-                match resolved {
-                    Type::Ref(inner) => {
-                        write!(out, "->{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                        current = tc.field_type(&inner, &field.0);
-                    }
-                    _ => {
-                        write!(out, ".{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                        current = tc.field_type(ty, &field.0);
-                    }
-                }
-            } else {
-                write!(out, ".{}", field.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
-                current = None;
-            }
-        }
-        return Ok(());
+    #[test]
+    fn math_shims_are_renamed_away_from_libc() {
+        let c = generate_c_from_source("main() = max(abs(-3), pow(2, 3))").unwrap();
+        assert!(
+            c.contains("static inline int32_t gaut_abs(int32_t n)"),
+            "{c}"
+        );
+        assert!(c.contains("#define abs gaut_abs"), "{c}");
+        assert!(c.contains("#define pow gaut_pow"), "{c}");
+
+        let c =
+            generate_c_from_source("max(a: i32, b: i32) -> i32 = a\nmain() = max(1, 2)").unwrap();
+        assert!(!c.contains("#define max"), "{c}");
     }
 
-    for (i, ident) in path.0.iter().enumerate() {
-        if i > 0 {
-            write!(out, ".").map_err(|e| CgenError::Fmt(e.to_string()))?;
-        }
-        write!(out, "{}", ident.0).map_err(|e| CgenError::Fmt(e.to_string()))?;
+    #[test]
+    fn tcp_handles_pass_by_pointer() {
+        let c = generate_c_from_source(
+            "main() = {\n  l: TcpListener = tcp_listen(0)\n  c: TcpConn = tcp_accept(&l)\n  tcp_write(&c, tcp_read(&c))\n}",
+        )
+        .unwrap();
+        assert!(c.contains("gaut_tcp_listener l = tcp_listen(0);"), "{c}");
+        assert!(
+            c.contains(
+                "gaut_tcp_conn tcp_accept(gaut_tcp_listener* l) { return gaut_tcp_accept(*l); }"
+            ),
+            "{c}"
+        );
+        assert!(c.contains("tcp_write(&c, tcp_read(&c))"), "{c}");
     }
-    Ok(())
-}
 
-fn is_builtin_name(name: &str) -> bool {
-    matches!(
-        name,
-        "i32" | "i64" | "u8" | "bool" | "Str" | "Bytes" | "Unit"
-    )
-}
+    #[test]
+    fn bytes_push_takes_a_pointer() {
+        let c = generate_c_from_source(
+            "main() = {\n  mut b: Bytes = bytes_from_str(\"a\")\n  bytes_push(&mut b, 1)\n  bytes_len(b)\n}",
+        )
+        .unwrap();
+        assert!(c.contains("bytes_push(&b, 1);"), "{c}");
+        assert!(
+            c.contains(
+                "void bytes_push(gaut_bytes* b, int32_t byte) { gaut_bytes_push(b, byte); }"
+            ),
+            "{c}"
+        );
+    }
 
-fn find_record_alias(ctx: &TypeCtx, ty: &Type) -> Option<String> {
-    let Type::Record(fields) = ctx.resolve_alias(ty) else {
-        return None;
-    };
-    for (name, aliased) in &ctx.types {
-        if is_builtin_name(name) {
-            continue;
-        }
-        if let Type::Record(alias_fields) = ctx.resolve_alias(aliased) {
-            if fields.len() != alias_fields.len() {
-                continue;
-            }
-            let mut same = true;
-            for (a, b) in fields.iter().zip(alias_fields.iter()) {
-                if a.name != b.name || ctx.resolve_alias(&a.ty) != ctx.resolve_alias(&b.ty) {
-                    same = false;
-                    break;
-                }
-            }
-            if same {
-                return Some(name.clone());
-            }
-        }
+    #[test]
+    fn string_builtins_call_the_runtime() {
+        let c = generate_c_from_source("main() = str_find(str_trim(\" a \"), \"a\")").unwrap();
+        assert!(
+            c.contains(
+                "int32_t str_find(char* s, char* needle) { return gaut_str_find(s, needle); }"
+            ),
+            "{c}"
+        );
+        assert!(
+            c.contains("char* str_trim(char* s) { return gaut_str_trim(s); }"),
+            "{c}"
+        );
     }
-    None
-}
 
-fn map_value_type(ty: &Type, ctx: &TypeCtx) -> Result<String, CgenError> {
-    match ty {
-        Type::Named(id) => {
-            let resolved = ctx.resolve_alias(ty);
-            if matches!(resolved, Type::Named(Ident(ref n)) if n == "Unit") {
-                return Ok("int".into());
-            }
-            match id.0.as_str() {
-                "i32" => Ok("int32_t".into()),
-                "i64" => Ok("int64_t".into()),
-                "u8" => Ok("uint8_t".into()),
-                "bool" => Ok("bool".into()),
-                "Str" => Ok("char*".into()),
-                "Bytes" => Ok("gaut_bytes".into()),
-                other => Ok(other.to_string()),
-            }
-        }
-        Type::Ref(inner) => Ok(format!("{}*", map_value_type(inner, ctx)?)),
-        Type::Record(fields) => {
-            let mut tmp = String::new();
-            writeln!(tmp, "struct {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            for f in fields {
-                let cty = map_value_type(&f.ty, ctx)?;
-                writeln!(tmp, "  {} {};", cty, f.name.0)
-                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
-            }
-            write!(tmp, "}}").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            Ok(tmp)
+    #[test]
+    fn overflow_builtins_use_compiler_builtins() {
+        let c = generate_c_from_source("main() = saturating_add(1, 2)").unwrap();
+        assert!(c.contains(
+            "int32_t saturating_add(int32_t a, int32_t b) { int32_t r; return __builtin_add_overflow(a, b, &r) ? b < 0 ? INT32_MIN : INT32_MAX : r; }"
+        ), "{c}");
+        assert!(c.contains("typedef struct { bool ok; int32_t value; } CheckedInt;"));
+    }
+
+    #[test]
+    fn function_types_become_pointer_typedefs() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = copy a + copy b
+        apply(f: (i32, i32) -> i32) -> i32 = f(1, 2)
+        main() = {
+          op: (i32, i32) -> i32 = add
+          apply(op)
         }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("typedef int32_t (*gaut_fn_0)(int32_t, int32_t);"));
+        assert!(c.contains("int32_t apply(gaut_fn_0 f)"));
+        assert!(c.contains("gaut_fn_0 op = add;"));
     }
-}
 
-fn map_type(ty: &Type, ctx: &TypeCtx) -> Result<String, CgenError> {
-    match ty {
-        Type::Named(id) => match id.0.as_str() {
-            "i32" => Ok("int32_t".into()),
-            "i64" => Ok("int64_t".into()),
-            "u8" => Ok("uint8_t".into()),
-            "bool" => Ok("bool".into()),
-            "Str" => Ok("char*".into()),
-            "Bytes" => Ok("gaut_bytes".into()),
-            "Unit" => Ok("void".into()),
-            other => Ok(other.to_string()),
-        },
-        Type::Ref(inner) => Ok(format!("{}*", map_type(&inner, ctx)?)),
-        Type::Record(fields) => {
-            let mut tmp = String::new();
-            writeln!(tmp, "struct {{").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            for f in fields {
-                let cty = map_type(&f.ty, ctx)?;
-                writeln!(tmp, "  {} {};", cty, f.name.0)
-                    .map_err(|e| CgenError::Fmt(e.to_string()))?;
+    #[test]
+    fn type_decls_emit_after_their_dependencies_in_any_order() {
+        let decls = [
+            "type A = B",
+            "type B = { c: C, n: i32 }",
+            "type C = { x: i32 }",
+        ];
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        for order in orders {
+            let mut src = String::new();
+            for i in order {
+                src.push_str(decls[i]);
+                src.push('\n');
             }
-            write!(tmp, "}}").map_err(|e| CgenError::Fmt(e.to_string()))?;
-            Ok(tmp)
+            src.push_str("main() = 0\n");
+            let c = generate_c_from_source(&src).unwrap();
+            let pos = |needle: &str| c.find(needle).unwrap_or_else(|| panic!("missing {needle}"));
+            assert!(pos("} C;") < pos("} B;"), "order {order:?}");
+            assert!(pos("} B;") < pos("typedef B A;"), "order {order:?}");
         }
     }
-}
 
-fn path_to_string(path: &Path) -> String {
-    path.0
-        .iter()
-        .map(|i| i.0.clone())
-        .collect::<Vec<_>>()
-        .join(".")
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn recursive_type_decls_are_reported() {
+        let src = r#"
+        type A = { b: B }
+        type B = { a: &A }
+        main() = 0
+        "#;
+        let err = generate_c_from_source(src).unwrap_err();
+        assert_eq!(err.to_string(), "recursive type declarations: A -> B -> A");
+    }
 
     #[test]
-    fn simple_program() {
+    fn method_calls_lower_to_free_functions() {
         let src = r#"
-        add(a: i32, b: i32) -> i32 = a + b
+        type Point = { x: i32, y: i32 }
+        length_x(p: &Point) -> i32 = p.x
+        scale(p: Point, k: i32) -> i32 = p.x * copy k
+        main() = {
+          p: Point = { x: 3, y: 4 }
+          a: i32 = p.length_x()
+          a + p.scale(2)
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("int32_t a = length_x(&p);"));
+        assert!(c.contains("scale(p, 2)"));
+    }
 
+    #[test]
+    fn trait_impls_become_one_function_per_type() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        type Size = { w: i32 }
+        trait Area { area(&self) -> i32 }
+        impl Area for Point { area(&self) -> i32 = 0 }
+        impl Area for Size { area(&self) -> i32 = self.w * 5 }
         main() = {
-          x: i32 = 10
-          y: i32 = 20
-          add(x, y)
+          p: Point = { x: 3, y: 4 }
+          s: Size = { w: 5 }
+          p.area() + s.area()
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("int32_t add"));
-        assert!(c.contains("int main(int argc, char** argv)"));
-        assert!(c.contains("gaut_arena __arena"));
-        assert!(c.contains("add(x, y)"));
+        assert!(c.contains("int32_t Point__area(Point* self)"));
+        assert!(c.contains("int32_t Size__area(Size* self)"));
+        assert!(c.contains("Point__area(&p)") && c.contains("Size__area(&s)"));
     }
 
     #[test]
-    fn inferred_return_function_signature() {
+    fn gc_mode_skips_arenas() {
         let src = r#"
-        id(x: i32) = x
+        greet(name: Str) -> Str = "hi " + name
+        main() = {
+          s: Str = greet("gaut") + "!"
+          println(s)
+          0
+        }
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let options = CgenOptions {
+            memory: MemoryModel::Gc,
+            ..CgenOptions::default()
+        };
+        let c = generate_c_with(&program, options).unwrap();
+        assert!(c.contains("gaut_gc_init();"));
+        assert!(!c.contains("gaut_arena"));
+        assert!(c.contains("gaut_str_concat_heap"));
+    }
 
+    #[test]
+    fn drop_hooks_are_called_before_scope_leave() {
+        let src = r#"
+        type Handle = { name: Str }
+        impl Drop for Handle {
+          drop(&self) -> Unit = { done: Str = println(self.name) }
+        }
+        consume(h: Handle) -> i32 = 1
         main() = {
-          out: i32 = id(7)
-          out
+          a: Handle = { name: "a" }
+          b: Handle = { name: "b" }
+          c: Handle = { name: "c" }
+          n: i32 = consume(b)
+          n
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("int32_t id(int32_t x);"));
-        assert!(c.contains("int32_t id(int32_t x) {"));
+        assert!(c.contains("Handle__drop(&h);"));
+        let main = &c[c.rfind("int main(").unwrap()..];
+        let drop_c = main.find("Handle__drop(&c);").unwrap();
+        let drop_a = main.find("Handle__drop(&a);").unwrap();
+        let leave = main.find("gaut_scope_leave").unwrap();
+        assert!(drop_c < drop_a && drop_a < leave);
+        assert!(!main.contains("Handle__drop(&b);"));
     }
 
     #[test]
-    fn string_concat_uses_runtime() {
+    fn record_results_are_built_in_the_callers_storage() {
         let src = r#"
+        type Pt = { x: i32, y: i32 }
+        make(n: i32) -> Pt = { x: copy n, y: 2 }
+        pick(n: i32) -> Pt = if copy n > 0 then make(copy n) else make(1)
+        apply(f: (i32) -> Pt) -> i32 = 0
+        boxed(n: i32) -> Pt = make(copy n)
+        take(p: Pt) -> i32 = p.x
         main() = {
-          msg: Str = "hello" + " world"
-          msg
+          p: Pt = pick(3)
+          q: i32 = take(make(4))
+          r: i32 = apply(boxed)
+          copy p.x + q
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("gaut_str_concat"));
+        assert!(c.contains("void make(Pt* __out, int32_t n) {"));
+        assert!(c.contains("(*__out).x = n;"));
+        assert!(c.contains("make(__out, n);"));
+        assert!(c.contains("Pt p;\n  pick(&p, 3);"));
+        assert!(c.contains("take(({ Pt __tmp"));
+        // used as a function value, so it keeps the by-value signature
+        assert!(c.contains("Pt boxed(int32_t n) {"));
     }
 
     #[test]
-    fn record_ref_uses_arrow() {
+    fn stack_guard_wraps_every_function_body() {
         let src = r#"
-        type Point = { x: i32, y: i32 }
+        down(n: i32) -> i32 = if copy n == 0 then 0 else 1 + down(copy n - 1)
+        main() = down(3)
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let options = CgenOptions {
+            stack_guard: true,
+            ..CgenOptions::default()
+        };
+        let c = generate_c_with(&program, options).unwrap();
+        let down = &c[c.find("int32_t down(int32_t n) {").unwrap()..];
+        let enter = down.find("gaut_stack_enter(\"down\");").unwrap();
+        let leave = down.find("gaut_stack_leave();").unwrap();
+        assert!(enter < leave && leave < down.find("return __ret").unwrap());
+        assert!(c.contains("gaut_stack_guard_init(GAUT_DEFAULT_ARENA_CAP + 256);"));
+        assert!(!generate_c(&program).unwrap().contains("gaut_stack"));
+    }
 
-        length_x(p: &Point) -> i32 = p.x
+    #[test]
+    fn mut_refs_pass_pointers_and_assign_through_them() {
+        let src = r#"
+        type Counter = { hits: i32 }
+        bump(c: &mut Counter) = { c.hits = copy c.hits + 1 }
+        twice(c: &mut Counter) = {
+          bump(c)
+          bump(c)
+        }
+        set(x: &mut i32, v: i32) = { x = v }
+        main() = {
+          mut c: Counter = { hits: 0 }
+          twice(&mut c)
+          set(&mut c.hits, 7)
+          c.hits
+        }
+        "#;
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains("void bump(Counter* c) {"));
+        assert!(c.contains("c->hits = gaut_trap_add_i32(c->hits, 1);"));
+        assert!(c.contains("  bump(c);"));
+        assert!(c.contains("*x = v;"));
+        assert!(c.contains("twice(&c);"));
+        assert!(c.contains("set(&c.hits, 7);"));
+    }
 
+    #[test]
+    fn record_spread_copies_the_base_struct() {
+        let src = r#"
+        type Pt = { x: i32, y: i32 }
+        shift(p: Pt) -> Pt = { ..p, x: 10 }
         main() = {
-          origin: Point = { x: 0, y: 0 }
-          px: i32 = length_x(&origin)
-          px
+          p: Pt = { x: 1, y: 2 }
+          q: Pt = { ..copy p, y: 5 }
+          r: Pt = shift(copy q)
+          copy q.y + r.x
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("p->x"));
-        assert!(c.contains("Point origin"));
+        assert!(c.contains("(*__out) = p;\n  (*__out).x = 10;"));
+        assert!(c.contains("Pt q = ({ Pt __tmp0 = p; __tmp0.y = 5; __tmp0; });"));
     }
 
     #[test]
-    fn read_file_calls_runtime() {
+    fn derived_show_uses_the_int_to_str_shim() {
         let src = r#"
+        @derive(show)
+        type Pt = { x: i32, y: i32 }
         main() = {
-          content: Str = read_file("foo.txt")
-          content
+          p: Pt = { x: 1, y: 2 }
+          println(Pt_show(&p))
+          0
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("gaut_read_file"));
+        assert!(c.contains("char* int_to_str(int64_t n) { return gaut_int_to_str(n); }"));
+        assert!(c.contains("Pt_show(&p)"));
     }
 
     #[test]
-    fn main_inits_argv() {
+    fn indexing_calls_the_checked_runtime_shims() {
         let src = r#"
+        first(s: &Str) -> i32 = s[0]
         main() = {
-          x: i32 = 0
-          x
+          s: Str = "ab"
+          b: Bytes = args()
+          s[1] + first(&s) + b[0]
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("int main(int argc, char** argv)"));
-        assert!(c.contains("gaut_args_init(argc, argv);"));
+        assert!(c.contains("gaut_str_index(*s, 0)"));
+        assert!(c.contains("gaut_str_index(s, 1)"));
+        assert!(c.contains("gaut_bytes_index(b, 0)"));
     }
 
     #[test]
-    fn bytes_to_str_uses_runtime() {
+    fn derived_json_uses_the_runtime_json_shims() {
         let src = r#"
+        @derive(json)
+        type Pt = { x: i32, name: Str }
         main() = {
-          s: Str = bytes_to_str(args())
-          s
+          p: Pt = Pt_from_json("{\"x\": 1, \"name\": \"a\"}")
+          println(Pt_to_json(&p))
+          0
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("gaut_args()"));
-        assert!(c.contains("gaut_bytes_to_str"));
+        assert!(c.contains(
+            "int64_t json_get_int(char** doc, char* key) { return gaut_json_get_int(*doc, key); }"
+        ));
+        assert!(c.contains("json_get_str(&text, \"name\")"));
+        assert!(c.contains("json_quote("));
     }
 
     #[test]
-    fn try_read_file_uses_result_type() {
+    fn slices_call_the_checked_runtime_copies() {
         let src = r#"
+        tail(s: &Str) -> Str = s[1..]
         main() = {
-          r: ReadFileResult = try_read_file("missing.txt")
-          r.data
+          s: Str = "abc"
+          b: Bytes = args()
+          c: Bytes = b[..1]
+          str_len(s[0..2]) + str_len(tail(&s))
         }
         "#;
         let c = generate_c_from_source(src).unwrap();
-        assert!(c.contains("typedef struct { bool ok; char* data; } ReadFileResult;"));
-        assert!(c.contains("ReadFileResult try_read_file"));
+        assert!(c.contains("gaut_str_slice_checked(*s, 1, 0, true)"));
+        assert!(c.contains("gaut_str_slice_checked(s, 0, 2, false)"));
+        assert!(c.contains("gaut_bytes_slice(b, 0, 1, false)"));
+    }
+
+    #[test]
+    fn raw_strings_are_escaped_in_c() {
+        let src = "main() = {\n  println(r\"a\\b\")\n  println(\"\"\"\nx\n\"y\"\n\"\"\")\n  0\n}";
+        let c = generate_c_from_source(src).unwrap();
+        assert!(c.contains(r#"println("a\\b")"#), "{c}");
+        assert!(c.contains(r#"println("x\n\"y\"\n")"#), "{c}");
+    }
+
+    #[test]
+    fn string_escapes_stay_within_their_literal() {
+        assert_eq!(escape_c_string("tab\there"), "tab\\there");
+        assert_eq!(escape_c_string("\u{1}a\u{1}g"), "\\x01\" \"a\\x01g");
+        assert_eq!(escape_c_string("\u{85}"), "\\xc2\\x85");
+        assert_eq!(escape_c_string("what??!"), "what?\\?!");
+        assert_eq!(escape_c_string("\u{7f}"), "\\x7f");
+    }
+
+    #[test]
+    fn time_blocks_read_the_clock_twice() {
+        let c = generate_c_from_source("main() -> i32 = time { println(\"x\") }").unwrap();
+        assert!(
+            c.contains("int64_t __tmp1 = gaut_now_millis(); (void)"),
+            "{c}"
+        );
+        assert!(c.contains("(int32_t)(gaut_now_millis() - __tmp1)"), "{c}");
+    }
+
+    #[test]
+    fn ansi_mode_lowers_statement_expressions_to_statements() {
+        let src = r#"
+        f(x: i32) -> i32 = copy x + 1
+        main() -> i32 = {
+          ok: bool = f(1) > 1 && {
+            println("rhs")
+            f(2) > 4
+          }
+          if ok then 1 else time { println("x") }
+        }
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let options = CgenOptions {
+            ansi: true,
+            ..CgenOptions::default()
+        };
+        let c = generate_c_with(&program, options).unwrap();
+        assert!(!c.contains("({"), "{c}");
+        assert!(
+            c.contains("if (__tmp1) {\n    bool __tmp0;\n    {\n"),
+            "{c}"
+        );
+        assert!(c.contains("  __tmp1 = __tmp0;\n"), "{c}");
+        assert!(
+            c.contains("int32_t saturating_add(int32_t a, int32_t b) { int32_t r; return gaut_add_overflow_i32(a, b, &r)"),
+            "{c}"
+        );
     }
 }
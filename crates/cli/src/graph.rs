@@ -0,0 +1,152 @@
+#![forbid(unsafe_code)]
+
+use frontend::ast::{Decl, Program};
+use frontend::names::expr_referenced_names;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Output of `gaut graph --format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            other => Err(format!(
+                "unknown graph format '{other}' (expected dot or json)"
+            )),
+        }
+    }
+}
+
+/// The module import graph and the function call graph of one loaded program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepGraph {
+    /// Modules in load order, the entry module first.
+    pub modules: Vec<String>,
+    /// `(importer, imported)`, one edge per `import` line that was not a duplicate.
+    pub imports: Vec<(String, String)>,
+    /// Top-level functions by their resolved (`module__name`) names.
+    pub functions: Vec<String>,
+    /// `(caller, callee)` for every top-level function a body calls or names.
+    pub calls: Vec<(String, String)>,
+}
+
+impl DepGraph {
+    /// Build the graph from the resolved program and the loader's import edges.
+    pub fn new(entry: &str, imports: Vec<(String, String)>, program: &Program) -> Self {
+        let mut modules = vec![entry.to_string()];
+        for (_, to) in &imports {
+            if !modules.contains(to) {
+                modules.push(to.clone());
+            }
+        }
+        let functions: Vec<String> = program
+            .decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Func(f) => Some(f.name.0.clone()),
+                _ => None,
+            })
+            .collect();
+        let known: HashSet<&str> = functions.iter().map(String::as_str).collect();
+        let mut calls = Vec::new();
+        for decl in &program.decls {
+            let Decl::Func(f) = decl else { continue };
+            let mut callees: Vec<String> = expr_referenced_names(&f.body)
+                .into_iter()
+                .filter(|n| known.contains(n.as_str()))
+                .collect();
+            callees.sort();
+            calls.extend(callees.into_iter().map(|c| (f.name.0.clone(), c)));
+        }
+        Self {
+            modules,
+            imports,
+            functions,
+            calls,
+        }
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Both graphs as clusters of one digraph; node ids are prefixed so a module and a
+    /// function with the same name stay apart.
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph gaut {\n");
+        let _ = writeln!(out, "  subgraph cluster_modules {{\n    label=\"modules\";");
+        for m in &self.modules {
+            let _ = writeln!(out, "    \"mod:{m}\" [label=\"{m}\", shape=box];");
+        }
+        for (from, to) in &self.imports {
+            let _ = writeln!(out, "    \"mod:{from}\" -> \"mod:{to}\";");
+        }
+        let _ = writeln!(
+            out,
+            "  }}\n  subgraph cluster_functions {{\n    label=\"functions\";"
+        );
+        for f in &self.functions {
+            let _ = writeln!(out, "    \"fn:{f}\" [label=\"{f}\"];");
+        }
+        for (from, to) in &self.calls {
+            let _ = writeln!(out, "    \"fn:{from}\" -> \"fn:{to}\";");
+        }
+        out.push_str("  }\n}\n");
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let names = |list: &[String]| {
+            let quoted: Vec<String> = list.iter().map(|n| format!("\"{n}\"")).collect();
+            format!("[{}]", quoted.join(","))
+        };
+        let edges = |list: &[(String, String)]| {
+            let objs: Vec<String> = list
+                .iter()
+                .map(|(from, to)| format!("{{\"from\":\"{from}\",\"to\":\"{to}\"}}"))
+                .collect();
+            format!("[{}]", objs.join(","))
+        };
+        format!(
+            "{{\"modules\":{},\"imports\":{},\"functions\":{},\"calls\":{}}}\n",
+            names(&self.modules),
+            edges(&self.imports),
+            names(&self.functions),
+            edges(&self.calls)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frontend::parser::Parser;
+
+    #[test]
+    fn call_edges_only_name_top_level_functions() {
+        let src = "helper(x: i32) -> i32 = x\nmain() = { n: i32 = 2\n helper(n) + helper(1) }\n";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let graph = DepGraph::new("main", vec![("main".into(), "str".into())], &program);
+        assert_eq!(graph.modules, vec!["main", "str"]);
+        assert_eq!(graph.calls, vec![("main".into(), "helper".into())]);
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.contains("\"mod:main\" -> \"mod:str\";"));
+        assert!(dot.contains("\"fn:main\" -> \"fn:helper\";"));
+        assert_eq!(
+            graph.render(GraphFormat::Json),
+            "{\"modules\":[\"main\",\"str\"],\"imports\":[{\"from\":\"main\",\"to\":\"str\"}],\"functions\":[\"helper\",\"main\"],\"calls\":[{\"from\":\"main\",\"to\":\"helper\"}]}\n"
+        );
+    }
+}
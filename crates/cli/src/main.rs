@@ -1,59 +1,314 @@
 #![forbid(unsafe_code)]
 
-use cgen::generate_c;
+mod graph;
+mod project;
+mod stdlib;
+
+use cgen::{generate_c_typed, CgenOptions, MemoryModel};
 use frontend::ast::*;
+use frontend::lint::{Lint, LintConfig, LintLevel, Warning};
+use frontend::modules::{exported_names, resolve_module};
+use frontend::names::referenced_names;
 use frontend::parser::Parser;
+use frontend::target::Target;
 use frontend::typecheck::TypeChecker;
-use interp::Interpreter;
-#[cfg(test)]
-use interp::Value;
-use std::collections::HashSet;
+use frontend::typed::TypedProgram;
+use graph::{DepGraph, GraphFormat};
+use interp::debugger::{DebugAction, Debugger, Pause};
+use interp::trace::{decode as decode_trace, encode as encode_trace, TraceEvent};
+use interp::{Interpreter, RuntimeError, Value};
+use project::{init_project, Manifest};
+use runtime::Config;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io::Write;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use stdlib::{embedded_source, ModuleSource, StdPath, StdRoot};
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Error)]
 enum CliError {
     #[error("{0}")]
     Message(String),
 }
 
+// `main` reports its error with `Debug`; print the message itself so that multi-line
+// diagnostics stay readable
+impl std::fmt::Debug for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone)]
 enum Mode {
     Run {
         file: PathBuf,
+        output: OutputFormat,
+        watch: bool,
+        hot: bool,
+        /// `--record trace.bin`: write calls, returns and binding writes to this file.
+        record: Option<PathBuf>,
+        /// `--profile`: print calls and time per function to stderr after the run.
+        profile: bool,
+        /// `--int i32|i64`: the default integer model.
+        target: Target,
+        /// `--std-path dir` (repeatable): std directories searched before the rest.
+        std_paths: Vec<PathBuf>,
+        /// Everything after `--`, passed to the program after its own path.
+        program_args: Vec<String>,
+        /// `-W/-D/-A name` (repeatable): warn about, deny or allow a lint, or every lint
+        /// for `warnings`. Later flags override earlier ones.
+        lints: LintConfig,
+    },
+    /// `gaut replay trace.bin`: step through a recorded trace.
+    Replay { trace: PathBuf },
+    /// `gaut debug file.gaut [-- args...]`: run the interpreter under the debugger.
+    Debug {
+        file: PathBuf,
+        program_args: Vec<String>,
     },
     Emit {
         file: PathBuf,
         emit_c: PathBuf,
         build: Option<PathBuf>,
+        /// `--gc` selects the Boehm collector instead of arenas; `--stack-guard` adds
+        /// recursion depth checks to every function; `--ansi` avoids GNU extensions;
+        /// `--int` sets the integer model.
+        options: CgenOptions,
+        std_paths: Vec<PathBuf>,
+        lints: LintConfig,
+    },
+    /// `--emit-ast out.json`: write the parse tree of `file` as JSON without checking it.
+    EmitAst { file: PathBuf, out: PathBuf },
+    /// `gaut init [name]`: scaffold a project (into `name/`, or the current directory).
+    Init { name: Option<String> },
+    /// `gaut graph file.gaut [--format dot|json]`: print the import and call graphs.
+    Graph {
+        file: PathBuf,
+        format: GraphFormat,
+        std_paths: Vec<PathBuf>,
+    },
+    /// `gaut build` / `gaut run [-- args...]`: use the `gaut.toml` in the current directory.
+    Project {
+        build: bool,
+        program_args: Vec<String>,
     },
 }
 
 fn main() -> Result<(), CliError> {
+    // the interpreter recurses on the host stack; give it enough for its call-depth limit
+    let cli = thread::Builder::new()
+        .stack_size(interp::stack_size(interp::DEFAULT_MAX_CALL_DEPTH))
+        .spawn(run_cli)
+        .map_err(|e| CliError::Message(format!("cannot start the main thread: {e}")))?;
+    match cli.join() {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+fn run_cli() -> Result<(), CliError> {
     let mode = parse_args(env::args().skip(1).collect())?;
 
     match mode {
-        Mode::Run { file } => run_interpreter(&file),
+        Mode::Run {
+            file,
+            output,
+            watch: false,
+            record,
+            profile,
+            target,
+            std_paths,
+            program_args,
+            lints,
+            ..
+        } => run_interpreter(
+            &file,
+            RunReport {
+                output,
+                record: record.as_deref(),
+                profile,
+            },
+            target,
+            &StdPath::from_env(&std_paths, None)?,
+            &lints,
+            &program_args,
+        ),
+        Mode::Run {
+            file,
+            output,
+            hot,
+            target,
+            std_paths,
+            program_args,
+            lints,
+            ..
+        } => watch_and_run(
+            &file,
+            output,
+            hot,
+            target,
+            &StdPath::from_env(&std_paths, None)?,
+            &lints,
+            &program_args,
+        ),
         Mode::Emit {
             file,
             emit_c,
             build,
-        } => emit_and_maybe_build(&file, &emit_c, build.as_ref()),
+            options,
+            std_paths,
+            lints,
+        } => emit_and_maybe_build(
+            &file,
+            &emit_c,
+            build.as_ref(),
+            options,
+            &StdPath::from_env(&std_paths, None)?,
+            &lints,
+        ),
+        Mode::EmitAst { file, out } => emit_ast(&file, &out),
+        Mode::Init { name } => {
+            let cwd =
+                env::current_dir().map_err(|e| CliError::Message(format!("current dir: {e}")))?;
+            let (dir, name) = match name {
+                Some(name) => (cwd.join(&name), name),
+                None => {
+                    let name = cwd
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "app".into());
+                    (cwd, name)
+                }
+            };
+            init_project(&dir, &name)?;
+            println!("created project '{name}' in {}", dir.display());
+            Ok(())
+        }
+        Mode::Project {
+            build,
+            program_args,
+        } => {
+            let cwd =
+                env::current_dir().map_err(|e| CliError::Message(format!("current dir: {e}")))?;
+            let manifest = Manifest::load(&cwd)?;
+            let std = StdPath::from_env(&[], Some(&manifest))?;
+            if build {
+                let bin = manifest.binary_output();
+                emit_and_maybe_build(
+                    &manifest.entry,
+                    &manifest.c_output(),
+                    Some(&bin),
+                    CgenOptions::default(),
+                    &std,
+                    &manifest.lints,
+                )
+            } else {
+                run_interpreter(
+                    &manifest.entry,
+                    RunReport::default(),
+                    Target::default(),
+                    &std,
+                    &manifest.lints,
+                    &program_args,
+                )
+            }
+        }
+        Mode::Graph {
+            file,
+            format,
+            std_paths,
+        } => {
+            let loaded = load_with_imports(&file, &StdPath::from_env(&std_paths, None)?)?;
+            let graph = DepGraph::new(&entry_module_name(&file), loaded.imports, &loaded.program);
+            print!("{}", graph.render(format));
+            Ok(())
+        }
+        Mode::Replay { trace } => {
+            let bytes = fs::read(&trace)
+                .map_err(|e| CliError::Message(format!("read {}: {e}", trace.display())))?;
+            let events = decode_trace(&bytes)
+                .map_err(|e| CliError::Message(format!("{}: {e}", trace.display())))?;
+            replay(&events, std::io::stdin().lock(), std::io::stdout().lock())
+                .map_err(|e| CliError::Message(format!("replay: {e}")))
+        }
+        Mode::Debug { file, program_args } => {
+            debug_program(&file, &StdPath::from_env(&[], None)?, &program_args)
+        }
     }
 }
 
 fn parse_args(args: Vec<String>) -> Result<Mode, CliError> {
     if args.is_empty() {
-        eprintln!("usage: gaut [--emit-c out.c] [--build out_bin] <file.gaut>");
+        eprintln!(
+            "usage: gaut [--output text|json] [--watch [--hot]] [--emit-c out.c] [--emit-ast out.json] [--build out_bin] [--gc] [--stack-guard] [--ansi] [--int i32|i64] [--overflow trap|wrap] [--std-path dir]... [-W|-D|-A lint]... [--record trace.bin] [--profile] <file.gaut> [-- args...]\n       gaut init [name] | gaut build | gaut run [-- args...] | gaut replay trace.bin | gaut debug <file.gaut> [-- args...]\n       gaut graph [--format dot|json] [--std-path dir]... <file.gaut>"
+        );
         std::process::exit(1);
     }
+    match args[0].as_str() {
+        "init" if args.len() <= 2 => {
+            return Ok(Mode::Init {
+                name: args.get(1).cloned(),
+            })
+        }
+        "build" | "run" if args.len() == 1 => {
+            return Ok(Mode::Project {
+                build: args[0] == "build",
+                program_args: Vec::new(),
+            })
+        }
+        "run" if args[1] == "--" => {
+            return Ok(Mode::Project {
+                build: false,
+                program_args: args[2..].to_vec(),
+            })
+        }
+        "replay" if args.len() == 2 => {
+            return Ok(Mode::Replay {
+                trace: PathBuf::from(&args[1]),
+            })
+        }
+        "debug" if args.len() == 2 || (args.len() > 2 && args[2] == "--") => {
+            return Ok(Mode::Debug {
+                file: PathBuf::from(&args[1]),
+                program_args: args.get(3..).unwrap_or_default().to_vec(),
+            })
+        }
+        "graph" => return parse_graph_args(&args[1..]),
+        "init" | "build" | "run" | "replay" | "debug" => {
+            return Err(CliError::Message(format!(
+                "unexpected arguments after '{}'",
+                args[0]
+            )))
+        }
+        _ => {}
+    }
     let mut emit_c = None;
+    let mut emit_ast = None;
     let mut build = None;
+    let mut output = None;
+    let mut watch = false;
+    let mut hot = false;
+    let mut record = None;
+    let mut profile = false;
+    let mut options = CgenOptions::default();
+    let mut target = Target::default();
+    let mut std_paths = Vec::new();
+    let mut lints = LintConfig::default();
     let mut file = None;
+    let mut program_args = Vec::new();
 
     let mut iter = args.into_iter();
     while let Some(arg) = iter.next() {
@@ -64,12 +319,76 @@ fn parse_args(args: Vec<String>) -> Result<Mode, CliError> {
                     .ok_or_else(|| CliError::Message("expected path after --emit-c".into()))?;
                 emit_c = Some(PathBuf::from(path));
             }
+            "--emit-ast" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| CliError::Message("expected path after --emit-ast".into()))?;
+                emit_ast = Some(PathBuf::from(path));
+            }
             "--build" => {
                 let path = iter.next().ok_or_else(|| {
                     CliError::Message("expected binary path after --build".into())
                 })?;
                 build = Some(PathBuf::from(path));
             }
+            "--output" => {
+                let format = iter.next().ok_or_else(|| {
+                    CliError::Message("expected text or json after --output".into())
+                })?;
+                output = Some(match format.as_str() {
+                    "text" => OutputFormat::Text,
+                    "json" => OutputFormat::Json,
+                    other => {
+                        return Err(CliError::Message(format!(
+                            "unknown output format '{other}' (expected text or json)"
+                        )))
+                    }
+                });
+            }
+            "--record" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| CliError::Message("expected path after --record".into()))?;
+                record = Some(PathBuf::from(path));
+            }
+            "--int" => {
+                let model = iter
+                    .next()
+                    .ok_or_else(|| CliError::Message("expected i32 or i64 after --int".into()))?;
+                target.int_model = model.parse().map_err(CliError::Message)?;
+            }
+            "--overflow" => {
+                let mode = iter.next().ok_or_else(|| {
+                    CliError::Message("expected trap or wrap after --overflow".into())
+                })?;
+                target.overflow = mode.parse().map_err(CliError::Message)?;
+            }
+            "--std-path" => {
+                let dir = iter.next().ok_or_else(|| {
+                    CliError::Message("expected directory after --std-path".into())
+                })?;
+                std_paths.push(PathBuf::from(dir));
+            }
+            flag @ ("-W" | "-D" | "-A") => {
+                let name = iter
+                    .next()
+                    .ok_or_else(|| CliError::Message(format!("expected lint name after {flag}")))?;
+                let level = match flag {
+                    "-W" => LintLevel::Warn,
+                    "-D" => LintLevel::Deny,
+                    _ => LintLevel::Allow,
+                };
+                lints.set(&name, level).map_err(CliError::Message)?;
+            }
+            "--gc" => options.memory = MemoryModel::Gc,
+            "--stack-guard" => options.stack_guard = true,
+            "--ansi" => options.ansi = true,
+            "--profile" => profile = true,
+            "--watch" => watch = true,
+            "--hot" => hot = true,
+            "--" => {
+                program_args.extend(iter.by_ref());
+            }
             other if file.is_none() => {
                 file = Some(PathBuf::from(other));
             }
@@ -78,60 +397,556 @@ fn parse_args(args: Vec<String>) -> Result<Mode, CliError> {
     }
 
     let file = file.ok_or_else(|| CliError::Message("no input file provided".into()))?;
+    if let Some(out) = emit_ast {
+        if emit_c.is_some() || build.is_some() || output.is_some() || watch || record.is_some() {
+            return Err(CliError::Message(
+                "--emit-ast only parses; it cannot be combined with running or building".into(),
+            ));
+        }
+        return Ok(Mode::EmitAst { file, out });
+    }
     if emit_c.is_none() && build.is_some() {
         emit_c = Some(PathBuf::from("target/gaut_out.c"));
     }
 
+    if hot && !watch {
+        return Err(CliError::Message("--hot requires --watch".into()));
+    }
+
+    if record.is_some() && watch {
+        return Err(CliError::Message(
+            "--record cannot be combined with --watch".into(),
+        ));
+    }
+
+    if profile && watch {
+        return Err(CliError::Message(
+            "--profile cannot be combined with --watch".into(),
+        ));
+    }
+
     if let Some(out) = emit_c {
+        if output.is_some() || watch || record.is_some() || profile || !program_args.is_empty() {
+            return Err(CliError::Message(
+                "--output, --watch, --record, --profile and program arguments after -- only apply when running the interpreter".into(),
+            ));
+        }
         Ok(Mode::Emit {
             file,
             emit_c: out,
             build,
+            options: CgenOptions {
+                int_model: target.int_model,
+                overflow: target.overflow,
+                ..options
+            },
+            std_paths,
+            lints,
+        })
+    } else if options != CgenOptions::default() {
+        Err(CliError::Message(
+            "--gc, --stack-guard and --ansi only apply with --emit-c or --build".into(),
+        ))
+    } else {
+        Ok(Mode::Run {
+            file,
+            output: output.unwrap_or(OutputFormat::Text),
+            watch,
+            hot,
+            record,
+            profile,
+            target,
+            std_paths,
+            program_args,
+            lints,
         })
+    }
+}
+
+fn parse_graph_args(args: &[String]) -> Result<Mode, CliError> {
+    let mut format = GraphFormat::Dot;
+    let mut std_paths = Vec::new();
+    let mut file = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = iter.next().ok_or_else(|| {
+                    CliError::Message("expected dot or json after --format".into())
+                })?;
+                format = name.parse().map_err(CliError::Message)?;
+            }
+            "--std-path" => {
+                let dir = iter.next().ok_or_else(|| {
+                    CliError::Message("expected directory after --std-path".into())
+                })?;
+                std_paths.push(PathBuf::from(dir));
+            }
+            other if file.is_none() => file = Some(PathBuf::from(other)),
+            _ => {
+                return Err(CliError::Message(
+                    "unexpected arguments after 'graph'".into(),
+                ))
+            }
+        }
+    }
+    let file = file.ok_or_else(|| CliError::Message("no input file provided".into()))?;
+    Ok(Mode::Graph {
+        file,
+        format,
+        std_paths,
+    })
+}
+
+/// Parse `file` on its own (imports are not followed) and write the tree as JSON. Doc
+/// comments are kept; nothing is checked, so tools also get trees of ill-typed code.
+fn emit_ast(file: &Path, out: &Path) -> Result<(), CliError> {
+    let src = fs::read_to_string(file)
+        .map_err(|e| CliError::Message(format!("failed to read {}: {e}", file.display())))?;
+    let program = parse_source(&src, file, true)?;
+    let json = serde_json::to_string_pretty(&program)
+        .map_err(|e| CliError::Message(format!("serialize AST: {e}")))?;
+    fs::write(out, json + "\n")
+        .map_err(|e| CliError::Message(format!("write {}: {e}", out.display())))
+}
+
+/// Load, merge and check `file` with `tc`; also returns the std root it was built
+/// against.
+fn load_checked_program(
+    file: &Path,
+    mut tc: TypeChecker,
+    std: &StdPath,
+    lints: &LintConfig,
+) -> Result<Checked, CliError> {
+    let loaded = load_with_imports(file, std)?;
+    report_warnings(&loaded.warnings, lints)?;
+
+    let Ok(typed) = tc.check_program(&loaded.program) else {
+        let errors: Vec<String> = tc
+            .diagnostics()
+            .iter()
+            .map(|d| format!("type {d}"))
+            .collect();
+        return Err(CliError::Message(errors.join("\n")));
+    };
+    report_warnings(tc.warnings(), lints)?;
+    Ok(Checked {
+        program: typed,
+        std: loaded.std,
+        files: loaded.files,
+    })
+}
+
+/// Parse one module's source, reporting every parse error in it. `docs` keeps `///`
+/// comments in the tree.
+fn parse_source(src: &str, path: &Path, docs: bool) -> Result<Program, CliError> {
+    let parser = if docs {
+        Parser::with_doc_comments(src)
     } else {
-        Ok(Mode::Run { file })
+        Parser::new(src)
+    };
+    let mut parser = parser.map_err(|e| {
+        let at = e.span().map(|span| format!(":{span}")).unwrap_or_default();
+        CliError::Message(format!("parse error in {}{at}: {e}", path.display()))
+    })?;
+    parser.parse_program().map_err(|_| {
+        let errors: Vec<String> = parser
+            .diagnostics()
+            .iter()
+            .map(|d| {
+                format!(
+                    "parse error in {}:{}: {}",
+                    path.display(),
+                    d.span,
+                    d.message
+                )
+            })
+            .collect();
+        CliError::Message(errors.join("\n"))
+    })
+}
+
+/// `kind: e`, with the source position the error was raised at when it is known.
+fn located(kind: &str, span: Option<Span>, e: impl std::fmt::Display) -> CliError {
+    match span {
+        Some(span) => CliError::Message(format!("{kind} at {span}: {e}")),
+        None => CliError::Message(format!("{kind}: {e}")),
     }
 }
 
-fn run_interpreter(file: &Path) -> Result<(), CliError> {
-    let std_dir = std_dir();
-    let program = load_with_imports(file, &std_dir)?;
+/// Print every warning at its configured level; denied lints fail the build after all
+/// of them have been printed.
+fn report_warnings(warnings: &[Warning], lints: &LintConfig) -> Result<(), CliError> {
+    let mut denied = 0;
+    for w in warnings {
+        let at = if w.span.is_known() {
+            format!(" at {}", w.span)
+        } else {
+            String::new()
+        };
+        match lints.level(w.lint) {
+            LintLevel::Allow => {}
+            LintLevel::Warn => eprintln!("warning{at}: {w}"),
+            LintLevel::Deny => {
+                eprintln!("error{at}: {w}");
+                denied += 1;
+            }
+        }
+    }
+    if denied > 0 {
+        return Err(CliError::Message(format!(
+            "aborting due to {denied} denied warning(s)"
+        )));
+    }
+    Ok(())
+}
 
-    let mut decls = program.decls;
-    append_builtin_prints(&mut decls);
-    let program = Program { decls };
+/// What a single interpreter run reports besides its result.
+#[derive(Debug, Clone, Copy)]
+struct RunReport<'a> {
+    output: OutputFormat,
+    record: Option<&'a Path>,
+    profile: bool,
+}
 
-    let mut tc = TypeChecker::new();
-    tc.check_program(&program)
-        .map_err(|e| CliError::Message(format!("type error: {e}")))?;
+impl Default for RunReport<'_> {
+    fn default() -> Self {
+        Self {
+            output: OutputFormat::Text,
+            record: None,
+            profile: false,
+        }
+    }
+}
 
-    let mut interp = Interpreter::new(1024 * 1024);
-    interp
-        .load_program(&program)
-        .map_err(|e| CliError::Message(format!("interp load error: {e}")))?;
-    let result = interp
-        .run_main()
-        .map_err(|e| CliError::Message(format!("runtime error: {e}")))?;
-    println!("{result:?}");
+fn run_interpreter(
+    file: &Path,
+    report: RunReport<'_>,
+    target: Target,
+    std: &StdPath,
+    lints: &LintConfig,
+    program_args: &[String],
+) -> Result<(), CliError> {
+    let program = load_checked_program(file, TypeChecker::with_target(target), std, lints)?.program;
+    let mut interp = new_interpreter(target, file);
+    let start = Instant::now();
+    if let Err(e) = interp.load_typed(&program) {
+        return Err(located("interp load error", interp.error_span(), e));
+    }
+    if report.record.is_some() {
+        interp.record_trace();
+    }
+    if report.profile {
+        interp.enable_profiling();
+    }
+    let result = run_and_report(&mut interp, report.output, start, program_args);
+    if let Some(profile) = interp.profile_report() {
+        eprintln!("{profile}");
+    }
+    // the trace is written even when the run fails so the failure can be replayed
+    if let (Some(path), Some(events)) = (report.record, interp.take_trace()) {
+        fs::write(path, encode_trace(&events))
+            .map_err(|e| CliError::Message(format!("write {}: {e}", path.display())))?;
+    }
+    if let Some(code) = result? {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Interactive trace viewer: `n` (or empty line) steps forward, `p` steps back, `q` quits.
+fn replay(events: &[TraceEvent], input: impl BufRead, mut out: impl Write) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "trace: {} events (n = next, p = previous, q = quit)",
+        events.len()
+    )?;
+    if events.is_empty() {
+        return Ok(());
+    }
+    let mut pos = 0;
+    writeln!(out, "[1/{}] {}", events.len(), events[0])?;
+    for line in input.lines() {
+        match line?.trim() {
+            "" | "n" if pos + 1 < events.len() => pos += 1,
+            "" | "n" => {
+                writeln!(out, "(end of trace)")?;
+                continue;
+            }
+            "p" if pos > 0 => pos -= 1,
+            "p" => {
+                writeln!(out, "(start of trace)")?;
+                continue;
+            }
+            "q" => break,
+            other => {
+                writeln!(out, "unknown command '{other}'")?;
+                continue;
+            }
+        }
+        writeln!(out, "[{}/{}] {}", pos + 1, events.len(), events[pos])?;
+    }
+    Ok(())
+}
+
+/// Run `file` in the interpreter, stopping at its first statement for debugger commands.
+fn debug_program(file: &Path, std: &StdPath, program_args: &[String]) -> Result<(), CliError> {
+    let program =
+        load_checked_program(file, TypeChecker::new(), std, &LintConfig::default())?.program;
+    let source = fs::read_to_string(file)
+        .map_err(|e| CliError::Message(format!("read {}: {e}", file.display())))?;
+    let mut interp = new_interpreter(Target::default(), file);
+    if let Err(e) = interp.load_typed(&program) {
+        return Err(located("interp load error", interp.error_span(), e));
+    }
+    interp.set_entry_file(&file.display().to_string());
+    println!("{DEBUG_HELP}");
+    let debugger = CliDebugger::new(&source, std::io::stdin().lock(), std::io::stdout());
+    interp.set_debugger(Box::new(debugger), DebugAction::Step);
+    if let Some(code) = run_and_report(
+        &mut interp,
+        OutputFormat::Text,
+        Instant::now(),
+        program_args,
+    )? {
+        std::process::exit(code);
+    }
     Ok(())
 }
 
+const DEBUG_HELP: &str = "commands: s = step, n (or empty) = next, c = continue, b N / d N = set/delete a breakpoint on line N, l = locals, q = quit";
+
+/// Reads debugger commands from `input` at every stop of the program.
+struct CliDebugger<R, W> {
+    source: Vec<String>,
+    input: R,
+    out: W,
+}
+
+impl<R: BufRead, W: Write> CliDebugger<R, W> {
+    fn new(source: &str, input: R, out: W) -> Self {
+        Self {
+            source: source.lines().map(str::to_string).collect(),
+            input,
+            out,
+        }
+    }
+
+    /// Answer one command; `None` keeps the program stopped for another.
+    fn command(
+        &mut self,
+        line: &str,
+        pause: &mut Pause<'_>,
+    ) -> std::io::Result<Option<DebugAction>> {
+        let mut words = line.split_whitespace();
+        let action = match (words.next(), words.next().map(str::parse::<u32>)) {
+            (None | Some("n"), None) => Some(DebugAction::Next),
+            (Some("s"), None) => Some(DebugAction::Step),
+            (Some("c"), None) => Some(DebugAction::Continue),
+            (Some("q"), None) => std::process::exit(0),
+            (Some("l"), None) => {
+                for (name, value) in pause.locals() {
+                    writeln!(self.out, "  {name} = {value}")?;
+                }
+                None
+            }
+            (Some("b"), Some(Ok(n))) => {
+                let file = pause.file.to_string();
+                pause.set_breakpoint(&file, n);
+                writeln!(self.out, "breakpoint at {file}:{n}")?;
+                None
+            }
+            (Some("d"), Some(Ok(n))) => {
+                let file = pause.file.to_string();
+                pause.clear_breakpoint(&file, n);
+                None
+            }
+            _ => {
+                writeln!(self.out, "unknown command '{line}'; {DEBUG_HELP}")?;
+                None
+            }
+        };
+        Ok(action)
+    }
+
+    fn stop(&mut self, pause: &mut Pause<'_>) -> std::io::Result<DebugAction> {
+        let line = pause.span.line;
+        writeln!(
+            self.out,
+            "stopped in {} at {}:{}",
+            pause.func, pause.file, pause.span
+        )?;
+        if let Some(text) = (line as usize)
+            .checked_sub(1)
+            .and_then(|i| self.source.get(i))
+        {
+            writeln!(self.out, "{line:>4} | {text}")?;
+        }
+        loop {
+            write!(self.out, "(gaut) ")?;
+            self.out.flush()?;
+            let mut command = String::new();
+            // at the end of the input, let the program finish
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(DebugAction::Continue);
+            }
+            if let Some(action) = self.command(command.trim(), pause)? {
+                return Ok(action);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for CliDebugger<R, W> {
+    fn paused(&mut self, pause: &mut Pause<'_>) -> DebugAction {
+        // with stdin or stdout gone there is nobody to ask
+        self.stop(pause).unwrap_or(DebugAction::Continue)
+    }
+}
+
+/// An interpreter for `file`, with `file` as the program path `args()` starts with;
+/// `run_and_report` appends the program's arguments as a built binary would see them.
+fn new_interpreter(target: Target, file: &Path) -> Interpreter {
+    const DEFAULT_ARENA_CAP: usize = 1024 * 1024;
+    let mut interp = Interpreter::new(DEFAULT_ARENA_CAP);
+    interp.set_config(Config::from_env(DEFAULT_ARENA_CAP));
+    interp.set_target(target);
+    interp.set_args(vec![file.display().to_string()]);
+    interp
+}
+
+/// Re-run `file` whenever it or a file it imports changes. With `hot`, the interpreter is
+/// kept alive and reloaded in place so global state survives edits.
+fn watch_and_run(
+    file: &Path,
+    output: OutputFormat,
+    hot: bool,
+    target: Target,
+    std: &StdPath,
+    lints: &LintConfig,
+    program_args: &[String],
+) -> Result<(), CliError> {
+    let mut live: Option<Interpreter> = None;
+    // the last successful load's files; a failed load keeps watching the previous set
+    let mut watched = vec![file.to_path_buf()];
+    let mut last_modified = None;
+    loop {
+        let modified = modified_times(&watched);
+        if last_modified.as_ref() != Some(&modified) {
+            let outcome = load_checked_program(file, TypeChecker::with_target(target), std, lints)
+                .and_then(|checked| {
+                    watched = checked.files;
+                    let program = checked.program;
+                    let start = Instant::now();
+                    let interp = match live.take() {
+                        Some(mut interp) if hot => {
+                            let reloaded = interp.reload_typed(&program);
+                            // keep the previous program running state even if the reload is rejected
+                            live = Some(interp);
+                            let interp = live.as_mut().expect("interpreter kept for hot reload");
+                            if let Err(e) = reloaded {
+                                return Err(located("interp reload error", interp.error_span(), e));
+                            }
+                            interp
+                        }
+                        _ => {
+                            let mut interp = new_interpreter(target, file);
+                            if let Err(e) = interp.load_typed(&program) {
+                                return Err(located("interp load error", interp.error_span(), e));
+                            }
+                            live.insert(interp)
+                        }
+                    };
+                    run_and_report(interp, output, start, program_args)
+                });
+            last_modified = Some(modified_times(&watched));
+            match outcome {
+                // `exit()` ends this run, not the watcher
+                Ok(Some(code)) => eprintln!("program exited with status {code}"),
+                Ok(None) => {}
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+        thread::sleep(Duration::from_millis(300));
+    }
+}
+
+/// The modification time of each of `files`, `None` for one that cannot be read.
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Run `main` with `program_args` and print its result. Returns the status the program passed to `exit()`,
+/// if it called it; text output then prints no result and JSON output reports `null`.
+fn run_and_report(
+    interp: &mut Interpreter,
+    output: OutputFormat,
+    start: Instant,
+    program_args: &[String],
+) -> Result<Option<i32>, CliError> {
+    if output == OutputFormat::Json {
+        interp.capture_stdout();
+    }
+    let (result, exit_code) = match interp.run_main_with_args(program_args.to_vec()) {
+        Ok(value) => (Ok(value), None),
+        Err(RuntimeError::Exit(code)) => (Ok(Value::Unit), Some(code)),
+        Err(e) => {
+            let trace = interp
+                .backtrace()
+                .map(|b| format!("\n{b}"))
+                .unwrap_or_default();
+            let e = format!("{e}{trace}");
+            (Err(located("runtime error", interp.error_span(), e)), None)
+        }
+    };
+    let stdout = interp.take_captured_stdout().unwrap_or_default();
+    let result = result?;
+    match output {
+        OutputFormat::Text if exit_code.is_some() => {}
+        OutputFormat::Text => println!("{result}"),
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                run_report_json(&result, &stdout, start.elapsed().as_millis())
+            );
+        }
+    }
+    Ok(exit_code)
+}
+
+fn run_report_json(result: &Value, stdout: &str, duration_ms: u128) -> String {
+    format!(
+        "{{ \"result\": {}, \"stdout_captured\": {}, \"duration_ms\": {} }}",
+        result.to_json(),
+        Value::Str(stdout.to_string()).to_json(),
+        duration_ms
+    )
+}
+
 fn emit_and_maybe_build(
     file: &Path,
     c_out: &Path,
     build: Option<&PathBuf>,
+    options: CgenOptions,
+    std: &StdPath,
+    lints: &LintConfig,
 ) -> Result<(), CliError> {
-    let std_dir = std_dir();
-    let program = load_with_imports(file, &std_dir)?;
-    let mut decls = program.decls;
-    append_builtin_prints(&mut decls);
-    let program = Program { decls };
-
-    let mut tc = TypeChecker::new();
-    tc.check_program(&program)
-        .map_err(|e| CliError::Message(format!("type error: {e}")))?;
-
-    let c_src = generate_c(&program).map_err(|e| CliError::Message(format!("cgen error: {e}")))?;
+    let mut tc = TypeChecker::with_target(Target {
+        int_model: options.int_model,
+        overflow: options.overflow,
+    });
+    tc.set_gc(options.memory == MemoryModel::Gc);
+    let Checked {
+        program,
+        std: std_root,
+        ..
+    } = load_checked_program(file, tc, std, lints)?;
+    let c_src = generate_c_typed(&program, options)
+        .map_err(|e| CliError::Message(format!("cgen error: {e}")))?;
+    let c_src = format!("{}{c_src}", std_header(&std_root));
     if let Some(parent) = c_out.parent() {
         fs::create_dir_all(parent)
             .map_err(|e| CliError::Message(format!("create dir {}: {e}", parent.display())))?;
@@ -142,123 +957,260 @@ fn emit_and_maybe_build(
         .map_err(|e| CliError::Message(format!("write {}: {e}", c_out.display())))?;
 
     if let Some(bin) = build {
-        build_c_binary(c_out, bin)?;
+        build_c_binary(c_out, bin, &options)?;
     }
     Ok(())
 }
 
-fn build_c_binary(c_path: &Path, bin: &Path) -> Result<(), CliError> {
+/// Records the std a C file was generated against at its top, so a binary can be
+/// traced back to the std it was built with.
+fn std_header(root: &StdRoot) -> String {
+    format!(
+        "// gaut: built against {root}\n#define GAUT_STD_VERSION \"{}\"\n",
+        root.version.escape_default()
+    )
+}
+
+fn build_c_binary(c_path: &Path, bin: &Path, options: &CgenOptions) -> Result<(), CliError> {
     let runtime_dir = runtime_c_dir();
     let runtime_c = runtime_dir.join("runtime.c");
-    let status = Command::new("clang")
-        .arg("-std=gnu11")
-        .arg("-O2")
+    let cc = c_compiler(env::var_os("CC"), on_path);
+    let mut cmd = Command::new(&cc);
+    if options.ansi {
+        cmd.arg("-std=c11").arg("-D_POSIX_C_SOURCE=200809L");
+    } else {
+        cmd.arg("-std=gnu11");
+    }
+    cmd.arg("-O2")
         .arg("-I")
         .arg(&runtime_dir)
         .arg(c_path)
         .arg(&runtime_c)
         .arg("-o")
-        .arg(bin)
+        .arg(with_exe_suffix(bin, env::consts::EXE_SUFFIX));
+    if options.memory == MemoryModel::Gc {
+        cmd.arg("-DGAUT_GC").arg("-lgc");
+    }
+    cmd.args(platform_link_flags(cfg!(windows)));
+    let cc_name = cc.to_string_lossy();
+    let status = cmd
         .status()
-        .map_err(|e| CliError::Message(format!("failed to run clang: {e}")))?;
+        .map_err(|e| CliError::Message(format!("failed to run {cc_name}: {e}")))?;
 
     if !status.success() {
         return Err(CliError::Message(format!(
-            "clang failed with status {status}"
+            "{cc_name} failed with status {status}"
         )));
     }
     Ok(())
 }
 
-fn load_with_imports(entry: &Path, std_dir: &Path) -> Result<Program, CliError> {
-    let mut visited = HashSet::new();
-    let mut decls = Vec::new();
-    load_recursive(entry, std_dir, &mut visited, &mut decls)?;
-    Ok(Program { decls })
+/// The C compiler for `--build`: `$CC` when set, else the first of `cc` and `clang` found
+/// on `PATH` (`cc` if neither is, so the spawn error names it).
+fn c_compiler(cc_env: Option<OsString>, on_path: impl Fn(&str) -> bool) -> OsString {
+    if let Some(cc) = cc_env.filter(|cc| !cc.is_empty()) {
+        return cc;
+    }
+    ["cc", "clang"]
+        .into_iter()
+        .find(|name| on_path(name))
+        .unwrap_or("cc")
+        .into()
 }
 
-fn load_recursive(
-    path: &Path,
-    std_dir: &Path,
-    visited: &mut HashSet<PathBuf>,
-    out: &mut Vec<Decl>,
-) -> Result<(), CliError> {
-    let path = path
-        .canonicalize()
-        .map_err(|_| CliError::Message(format!("cannot canonicalize {}", path.display())))?;
-    if !visited.insert(path.clone()) {
-        return Ok(());
+fn on_path(name: &str) -> bool {
+    let Some(paths) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&paths).any(|dir| {
+        let candidate = with_exe_suffix(&dir.join(name), env::consts::EXE_SUFFIX);
+        candidate.is_file()
+    })
+}
+
+/// `bin` with the host's executable suffix (`.exe` on Windows) unless it already has an
+/// extension, so `--build out/app` runs natively everywhere.
+fn with_exe_suffix(bin: &Path, suffix: &str) -> PathBuf {
+    if suffix.is_empty() || bin.extension().is_some() {
+        return bin.to_path_buf();
     }
-    let src = fs::read_to_string(&path)
-        .map_err(|_| CliError::Message(format!("failed to read {}", path.display())))?;
-    let mut parser = Parser::new(&src)
-        .map_err(|e| CliError::Message(format!("parse error in {}: {e}", path.display())))?;
-    let program = parser
-        .parse_program()
-        .map_err(|e| CliError::Message(format!("parse error in {}: {e}", path.display())))?;
-
-    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
-    // process imports first
-    for decl in &program.decls {
-        if let Decl::Import(imp) = decl {
-            let mod_name = imp.module.0.clone();
-            let local_path = base_dir.join(format!("{}.gaut", mod_name));
-            let std_path = std_dir.join(format!("{}.gaut", mod_name));
-            let target = if local_path.exists() {
-                local_path
-            } else if std_path.exists() {
-                std_path
-            } else {
-                return Err(CliError::Message(format!(
-                    "module '{}' not found in {} or {}",
-                    mod_name,
-                    base_dir.display(),
-                    std_dir.display()
-                )));
-            };
-            load_recursive(&target, std_dir, visited, out)?;
-        }
+    let mut name = bin.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Libraries the runtime's platform layer needs: winsock for the net shims on Windows,
+/// pthreads for `spawn` elsewhere.
+fn platform_link_flags(windows: bool) -> &'static [&'static str] {
+    if windows {
+        &["-lws2_32"]
+    } else {
+        &["-pthread"]
     }
+}
 
-    out.extend(program.decls.into_iter());
-    Ok(())
+/// A loaded program that passed type checking.
+struct Checked {
+    program: TypedProgram,
+    /// The std root the program's std imports came from.
+    std: StdRoot,
+    /// Every source file merged into the program, entry first.
+    files: Vec<PathBuf>,
 }
 
-fn append_builtin_prints(decls: &mut Vec<Decl>) {
-    let names: HashSet<_> = decls
-        .iter()
-        .filter_map(|d| match d {
-            Decl::Func(f) => Some(f.name.0.clone()),
-            _ => None,
-        })
-        .collect();
-    let print_param = Param {
-        mutable: false,
-        name: Ident("msg".into()),
-        ty: Type::Named(Ident("Str".into())),
+/// One program loaded with its imports.
+struct Loaded {
+    program: Program,
+    warnings: Vec<Warning>,
+    /// `(importer, imported)` module names; the entry module is named after its file.
+    imports: Vec<(String, String)>,
+    /// The std root the program's std imports came from.
+    std: StdRoot,
+    /// Every source file merged into the program, entry first; embedded std modules
+    /// have none.
+    files: Vec<PathBuf>,
+}
+
+/// Load `entry` and everything it imports into one program.
+///
+/// Merge order is deterministic: a module's imports are loaded depth-first in the order the
+/// `import` lines appear (wherever they sit among the top-level decls), then the module's own
+/// decls follow. A module reached twice is merged only at its first import. Duplicate imports
+/// in one file and imports whose names the file never uses are reported as warnings.
+fn load_with_imports(entry: &Path, std: &StdPath) -> Result<Loaded, CliError> {
+    let mut loader = Loader {
+        std,
+        visited: HashMap::new(),
+        decls: Vec::new(),
+        warnings: Vec::new(),
+        imports: Vec::new(),
+        std_used: Vec::new(),
+        files: Vec::new(),
     };
-    if !names.contains("print") {
-        decls.push(Decl::Func(FuncDecl {
-            name: Ident("print".into()),
-            params: vec![print_param.clone()],
-            ret: Some(Type::Named(Ident("Str".into()))),
-            body: Expr::Path(Path(vec![Ident("msg".into())])),
-        }));
-    }
-    if !names.contains("println") {
-        decls.push(Decl::Func(FuncDecl {
-            name: Ident("println".into()),
-            params: vec![print_param],
-            ret: Some(Type::Named(Ident("Str".into()))),
-            body: Expr::Path(Path(vec![Ident("msg".into())])),
-        }));
+    loader.load(&ModuleSource::File(entry.to_path_buf()), None)?;
+    let built_against = std.built_against(&loader.std_used)?.clone();
+    Ok(Loaded {
+        program: Program {
+            decls: loader.decls,
+        },
+        warnings: loader.warnings,
+        imports: loader.imports,
+        std: built_against,
+        files: loader.files,
+    })
+}
+
+struct Loader<'a> {
+    std: &'a StdPath,
+    visited: HashMap<PathBuf, HashSet<String>>,
+    decls: Vec<Decl>,
+    warnings: Vec<Warning>,
+    imports: Vec<(String, String)>,
+    /// Every std import as (module, index into `std.roots`).
+    std_used: Vec<(String, usize)>,
+    files: Vec<PathBuf>,
+}
+
+impl Loader<'_> {
+    /// Load `source` and its imports, imports first. Each imported module's
+    /// declarations are namespaced as `module__name`; the entry module (`module` is
+    /// `None`) keeps its names. Returns the names the source exports to its importers.
+    fn load(
+        &mut self,
+        source: &ModuleSource,
+        module: Option<&str>,
+    ) -> Result<HashSet<String>, CliError> {
+        let path = match source {
+            ModuleSource::File(path) => path.canonicalize().map_err(|_| {
+                CliError::Message(format!("cannot canonicalize {}", path.display()))
+            })?,
+            ModuleSource::Embedded(name) => PathBuf::from(format!("<std>/{name}.gaut")),
+        };
+        if let Some(names) = self.visited.get(&path) {
+            return Ok(names.clone());
+        }
+        if let ModuleSource::File(_) = source {
+            self.files.push(path.clone());
+        }
+        let src = match source {
+            ModuleSource::File(_) => fs::read_to_string(&path)
+                .map_err(|_| CliError::Message(format!("failed to read {}", path.display())))?,
+            ModuleSource::Embedded(name) => embedded_source(name)
+                .ok_or_else(|| CliError::Message(format!("no embedded std module '{name}'")))?
+                .to_string(),
+        };
+        let program = parse_source(&src, &path, false)?;
+
+        let exported = exported_names(&program);
+        let used = referenced_names(&program);
+        // registered before recursing so import cycles terminate
+        self.visited.insert(path.clone(), exported.clone());
+
+        // embedded modules have no directory; their imports come from the std path
+        let base_dir = match source {
+            ModuleSource::File(_) => Some(path.parent().unwrap_or_else(|| Path::new("."))),
+            ModuleSource::Embedded(_) => None,
+        };
+        let mut imports: Vec<(String, HashSet<String>)> = Vec::new();
+        // process imports first
+        for decl in &program.decls {
+            if let Decl::Import(imp) = decl {
+                let mod_name = imp.module.0.clone();
+                if imports.iter().any(|(m, _)| *m == mod_name) {
+                    self.warnings.push(Warning::new(
+                        Lint::DuplicateImport,
+                        format!("{}: duplicate import '{mod_name}'", path.display()),
+                    ));
+                    continue;
+                }
+                let local_path = base_dir
+                    .map(|dir| dir.join(format!("{}.gaut", mod_name)))
+                    .filter(|p| p.exists());
+                let target = match (local_path, self.std.find(&mod_name)) {
+                    (Some(local), _) => ModuleSource::File(local),
+                    (None, Some((root, found))) => {
+                        self.std_used.push((mod_name.clone(), root));
+                        found
+                    }
+                    (None, None) => {
+                        let searched: Vec<String> =
+                            self.std.roots.iter().map(|r| r.to_string()).collect();
+                        return Err(CliError::Message(format!(
+                            "module '{}' not found in {} or the std path ({})",
+                            mod_name,
+                            base_dir.unwrap_or_else(|| Path::new("<std>")).display(),
+                            searched.join(", ")
+                        )));
+                    }
+                };
+                let importer = match module {
+                    Some(m) => m.to_string(),
+                    None => entry_module_name(&path),
+                };
+                self.imports.push((importer, mod_name.clone()));
+                let names = self.load(&target, Some(&mod_name))?;
+                if !used.contains(&mod_name) && names.is_disjoint(&used) {
+                    self.warnings.push(Warning::new(
+                        Lint::UnusedImport,
+                        format!("{}: unused import '{mod_name}'", path.display()),
+                    ));
+                }
+                imports.push((mod_name, names));
+            }
+        }
+
+        let resolved = resolve_module(&program, module, &imports)
+            .map_err(|e| CliError::Message(format!("{}: {e}", path.display())))?;
+        self.decls.extend(resolved.decls);
+        Ok(exported)
     }
 }
 
-fn std_dir() -> PathBuf {
-    env::var("GAUT_STD_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("std"))
+/// The entry module has no import name; graphs and edges call it after its file.
+fn entry_module_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".into())
 }
 
 fn runtime_c_dir() -> PathBuf {
@@ -277,14 +1229,23 @@ fn runtime_c_dir() -> PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cgen::generate_c;
+    use frontend::target::{IntModel, Overflow};
 
     #[test]
     fn run_calc() {
         let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let repo = manifest.parent().unwrap().parent().unwrap().to_path_buf();
         let root = repo.join("examples/calc.gaut");
-        let std_dir = repo.join("std");
-        let program = load_with_imports(&root, &std_dir).unwrap();
+        let std = StdPath::resolve(&[repo.join("std")], None, None).unwrap();
+        let Loaded {
+            program,
+            warnings,
+            std: built_against,
+            ..
+        } = load_with_imports(&root, &std).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(built_against, std.roots[0]);
         let mut tc = TypeChecker::new();
         tc.check_program(&program).unwrap();
         let mut interp = Interpreter::new(1024 * 1024);
@@ -292,4 +1253,528 @@ mod tests {
         let v = interp.run_main().unwrap();
         assert_eq!(v, Value::Int(30));
     }
+
+    #[test]
+    fn emitted_ast_round_trips_through_json() {
+        let dir = std::env::temp_dir().join(format!("gaut_emit_ast_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("a.gaut");
+        let out = dir.join("a.json");
+        fs::write(&src, "/// Entry.\nmain() = { x: i32 = 1 + 2\n x }\n").unwrap();
+        emit_ast(&src, &out).unwrap();
+        let json = fs::read_to_string(&out).unwrap();
+        let program: Program = serde_json::from_str(&json).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            program,
+            Parser::with_doc_comments("/// Entry.\nmain() = { x: i32 = 1 + 2\n x }\n")
+                .unwrap()
+                .parse_program()
+                .unwrap()
+        );
+        assert!(json.contains("\"Func\""), "{json}");
+        assert!(matches!(
+            parse_args(vec![
+                "--emit-ast".into(),
+                "a.json".into(),
+                "--watch".into(),
+                "a.gaut".into()
+            ]),
+            Err(CliError::Message(_))
+        ));
+    }
+
+    #[test]
+    fn parse_output_json() {
+        let mode = parse_args(vec!["--output".into(), "json".into(), "a.gaut".into()]).unwrap();
+        assert!(matches!(
+            mode,
+            Mode::Run {
+                output: OutputFormat::Json,
+                ..
+            }
+        ));
+        assert!(parse_args(vec!["--output".into(), "xml".into(), "a.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn parse_watch_hot() {
+        let mode = parse_args(vec!["--watch".into(), "--hot".into(), "a.gaut".into()]).unwrap();
+        assert!(matches!(
+            mode,
+            Mode::Run {
+                watch: true,
+                hot: true,
+                ..
+            }
+        ));
+        assert!(parse_args(vec!["--hot".into(), "a.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn parse_stack_guard() {
+        let mode = parse_args(vec![
+            "--stack-guard".into(),
+            "--emit-c".into(),
+            "out.c".into(),
+            "a.gaut".into(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            mode,
+            Mode::Emit {
+                options: CgenOptions {
+                    stack_guard: true,
+                    memory: MemoryModel::Arena,
+                    ..
+                },
+                ..
+            }
+        ));
+        assert!(parse_args(vec!["--stack-guard".into(), "a.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn parse_ansi() {
+        let mode = parse_args(vec![
+            "--ansi".into(),
+            "--build".into(),
+            "app".into(),
+            "a.gaut".into(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            mode,
+            Mode::Emit {
+                options: CgenOptions { ansi: true, .. },
+                ..
+            }
+        ));
+        assert!(parse_args(vec!["--ansi".into(), "a.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn parse_int_model_for_both_modes() {
+        let run = parse_args(vec!["--int".into(), "i64".into(), "a.gaut".into()]).unwrap();
+        assert!(matches!(
+            run,
+            Mode::Run { target, .. } if target.int_model == IntModel::I64
+        ));
+        let emit = parse_args(vec![
+            "--emit-c".into(),
+            "out.c".into(),
+            "--int".into(),
+            "i64".into(),
+            "a.gaut".into(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            emit,
+            Mode::Emit { options, .. } if options.int_model == IntModel::I64
+        ));
+        assert!(parse_args(vec!["--int".into(), "u8".into(), "a.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn parse_overflow_mode_for_both_modes() {
+        let run = parse_args(vec!["--overflow".into(), "wrap".into(), "a.gaut".into()]).unwrap();
+        assert!(matches!(
+            run,
+            Mode::Run { target, .. } if target.overflow == Overflow::Wrap
+        ));
+        let emit = parse_args(vec![
+            "--emit-c".into(),
+            "out.c".into(),
+            "--overflow".into(),
+            "wrap".into(),
+            "a.gaut".into(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            emit,
+            Mode::Emit { options, .. } if options.overflow == Overflow::Wrap
+        ));
+        assert!(parse_args(vec![
+            "--overflow".into(),
+            "saturate".into(),
+            "a.gaut".into()
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_project_commands() {
+        assert!(matches!(
+            parse_args(vec!["init".into(), "demo".into()]).unwrap(),
+            Mode::Init { name: Some(ref n) } if n == "demo"
+        ));
+        assert!(matches!(
+            parse_args(vec!["build".into()]).unwrap(),
+            Mode::Project { build: true, .. }
+        ));
+        assert!(parse_args(vec!["run".into(), "x.gaut".into()]).is_err());
+    }
+
+    #[test]
+    fn run_report_is_json() {
+        let report = run_report_json(&Value::Int(30), "hi\n", 5);
+        assert_eq!(
+            report,
+            r#"{ "result": 30, "stdout_captured": "hi\n", "duration_ms": 5 }"#
+        );
+    }
+
+    #[test]
+    fn import_merge_order_and_warnings() {
+        let dir = std::env::temp_dir().join(format!("gaut_imports_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base.gaut"), "base_val() -> i32 = 1\n").unwrap();
+        fs::write(
+            dir.join("mid.gaut"),
+            "import base\nmid_val() -> i32 = base_val() + 1\n",
+        )
+        .unwrap();
+        fs::write(dir.join("extra.gaut"), "extra_val() -> i32 = 3\n").unwrap();
+        fs::write(
+            dir.join("main.gaut"),
+            "import mid\nmain() = mid_val()\nimport extra\nimport mid\n",
+        )
+        .unwrap();
+
+        let std = StdPath::resolve(&[], None, None).unwrap();
+        let Loaded {
+            program,
+            warnings,
+            imports,
+            files,
+            ..
+        } = load_with_imports(&dir.join("main.gaut"), &std).unwrap();
+        // `--watch` polls every merged file, not just the entry
+        let names: Vec<_> = files.iter().filter_map(|f| f.file_name()).collect();
+        assert_eq!(names, ["main.gaut", "mid.gaut", "base.gaut", "extra.gaut"]);
+        let edge = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(
+            imports,
+            [
+                edge("main", "mid"),
+                edge("mid", "base"),
+                edge("main", "extra")
+            ]
+        );
+        let graph = DepGraph::new("main", imports, &program);
+        assert!(graph
+            .calls
+            .contains(&edge("mid__mid_val", "base__base_val")));
+        assert!(matches!(
+            parse_args(vec![
+                "graph".into(),
+                "--format".into(),
+                "json".into(),
+                "x.gaut".into()
+            ]),
+            Ok(Mode::Graph {
+                format: GraphFormat::Json,
+                ..
+            })
+        ));
+        assert!(parse_args(vec!["graph".into(), "--format".into(), "svg".into()]).is_err());
+        let names: Vec<&str> = program
+            .decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Func(f) => Some(f.name.0.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            ["base__base_val", "mid__mid_val", "extra__extra_val", "main"]
+        );
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert_eq!(warnings[0].lint, Lint::UnusedImport);
+        assert!(warnings[0].message.ends_with("unused import 'extra'"));
+        assert_eq!(warnings[1].lint, Lint::DuplicateImport);
+        assert!(warnings[1].message.ends_with("duplicate import 'mid'"));
+
+        let mut lints = LintConfig::default();
+        assert!(report_warnings(&warnings, &lints).is_ok());
+        lints.set("duplicate_import", LintLevel::Deny).unwrap();
+        let err = report_warnings(&warnings, &lints).unwrap_err();
+        assert!(format!("{err}").contains("1 denied warning"), "{err}");
+        lints.set("warnings", LintLevel::Allow).unwrap();
+        assert!(report_warnings(&warnings, &lints).is_ok());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn imports_are_namespaced() {
+        let dir = std::env::temp_dir().join(format!("gaut_modules_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("ints.gaut"),
+            "add(a: i32, b: i32) -> i32 = a + b
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("strs.gaut"),
+            "add(a: Str, b: Str) -> Str = a + b
+greet() -> Str = add(\"hi\", \"!\")
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.gaut"),
+            "import ints\nimport strs\nmain() = {\n  s: Str = strs.greet()\n  ints.add(2, 3)\n}\n",
+        )
+        .unwrap();
+
+        let std = StdPath::resolve(&[], None, None).unwrap();
+        let Loaded {
+            program, warnings, ..
+        } = load_with_imports(&dir.join("main.gaut"), &std).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        TypeChecker::new().check_program(&program).unwrap();
+        let mut interp = Interpreter::new(1024 * 1024);
+        interp.load_program(&program).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(5));
+        let c_src = generate_c(&program).unwrap();
+        assert!(c_src.contains("ints__add(2, 3)"));
+
+        fs::write(
+            dir.join("main.gaut"),
+            "import ints\nimport strs\nmain() = add(1, 2)\n",
+        )
+        .unwrap();
+        let err = load_with_imports(&dir.join("main.gaut"), &std)
+            .err()
+            .unwrap();
+        assert!(format!("{err:?}").contains("ambiguous name add"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn std_imports_fall_back_to_the_embedded_std() {
+        let dir = std::env::temp_dir().join(format!("gaut_std_embed_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("main.gaut"),
+            "import str\nmain() = str.len(\"abc\")\n",
+        )
+        .unwrap();
+        let std = StdPath::resolve(&[], None, None).unwrap();
+        let Loaded { program, std, .. } = load_with_imports(&dir.join("main.gaut"), &std).unwrap();
+        assert!(std.dir.is_none());
+        TypeChecker::new().check_program(&program).unwrap();
+        assert!(std_header(&std).contains(&format!("#define GAUT_STD_VERSION \"{}\"", std.version)));
+
+        let mode = parse_args(vec![
+            "--std-path".into(),
+            "a".into(),
+            "--std-path".into(),
+            "b".into(),
+            "x.gaut".into(),
+        ])
+        .unwrap();
+        assert!(matches!(mode, Mode::Run { std_paths, .. } if std_paths.len() == 2));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn windows_builds_get_exe_names_and_winsock() {
+        assert_eq!(
+            with_exe_suffix(Path::new("out/app"), ".exe"),
+            PathBuf::from("out/app.exe")
+        );
+        assert_eq!(
+            with_exe_suffix(Path::new("out/app.bin"), ".exe"),
+            PathBuf::from("out/app.bin")
+        );
+        assert_eq!(with_exe_suffix(Path::new("app"), ""), PathBuf::from("app"));
+        assert_eq!(platform_link_flags(true), ["-lws2_32"]);
+        assert_eq!(platform_link_flags(false), ["-pthread"]);
+    }
+
+    #[test]
+    fn c_compiler_prefers_cc_env_then_path() {
+        assert_eq!(c_compiler(Some("gcc-13".into()), |_| true), "gcc-13");
+        assert_eq!(c_compiler(Some("".into()), |name| name == "clang"), "clang");
+        assert_eq!(c_compiler(None, |_| true), "cc");
+        assert_eq!(c_compiler(None, |_| false), "cc");
+    }
+
+    #[test]
+    fn lint_flags_apply_in_order() {
+        let args = |flags: &[&str]| {
+            let mut v: Vec<String> = flags.iter().map(|f| f.to_string()).collect();
+            v.push("x.gaut".into());
+            parse_args(v)
+        };
+        let mode = args(&["-D", "warnings", "-A", "unused_import"]).unwrap();
+        let Mode::Run { lints, .. } = mode else {
+            panic!("expected run mode");
+        };
+        assert_eq!(lints.level(Lint::UnusedImport), LintLevel::Allow);
+        assert_eq!(lints.level(Lint::DuplicateImport), LintLevel::Deny);
+        assert!(args(&["-W", "no_such_lint"]).is_err());
+        assert!(args(&["-D"]).is_err());
+    }
+
+    #[test]
+    fn debugger_commands_step_and_show_locals() {
+        struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let src = "main() -> i32 = {\n  a: i32 = 1\n  b: i32 = copy a + 1\n  copy b\n}\n";
+        let out = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let debugger = CliDebugger::new(src, "\nl\nwhat\nc\n".as_bytes(), Shared(out.clone()));
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.set_entry_file("t.gaut");
+        interp.set_debugger(Box::new(debugger), DebugAction::Step);
+        assert_eq!(interp.run_main().unwrap(), Value::Int(2));
+        let out = String::from_utf8(out.borrow().clone()).unwrap();
+        assert!(out.starts_with("stopped in main at t.gaut:2:3\n   2 |   a: i32 = 1\n(gaut) "));
+        assert!(out.contains("stopped in main at t.gaut:3:3"));
+        assert!(out.contains("(gaut)   a = 1\n(gaut) unknown command 'what'"));
+        assert!(parse_args(vec![
+            "debug".into(),
+            "app.gaut".into(),
+            "--".into(),
+            "x".into()
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn replay_steps_both_ways() {
+        let events: Vec<TraceEvent> = ["a", "b"]
+            .iter()
+            .map(|t| TraceEvent::Write {
+                depth: 0,
+                target: t.to_string(),
+                value: "1".into(),
+            })
+            .collect();
+        let mut out = Vec::new();
+        replay(&events, "n\nn\np\np\nq\nn\n".as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "[1/2] set a = 1",
+                "[2/2] set b = 1",
+                "(end of trace)",
+                "[1/2] set a = 1",
+                "(start of trace)",
+            ]
+        );
+        assert!(parse_args(vec![
+            "--watch".into(),
+            "--record".into(),
+            "t".into(),
+            "a.gaut".into()
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn profile_only_applies_to_a_single_interpreter_run() {
+        assert!(matches!(
+            parse_args(vec!["--profile".into(), "a.gaut".into()]),
+            Ok(Mode::Run { profile: true, .. })
+        ));
+        for other in ["--watch", "--emit-c"] {
+            let mut args = vec!["--profile".to_string(), other.into()];
+            if other == "--emit-c" {
+                args.push("out.c".into());
+            }
+            args.push("a.gaut".into());
+            assert!(parse_args(args).is_err(), "--profile with {other}");
+        }
+    }
+
+    #[test]
+    fn program_args_reach_the_flags_module() {
+        let mode = parse_args(vec![
+            "a.gaut".into(),
+            "--".into(),
+            "--out".into(),
+            "x.bin".into(),
+        ])
+        .unwrap();
+        assert!(
+            matches!(&mode, Mode::Run { program_args, .. } if program_args == &["--out", "x.bin"])
+        );
+        let project = parse_args(vec!["run".into(), "--".into(), "-v".into()]).unwrap();
+        assert!(
+            matches!(project, Mode::Project { build: false, program_args } if program_args == ["-v"])
+        );
+        assert!(parse_args(vec![
+            "--emit-c".into(),
+            "o.c".into(),
+            "a.gaut".into(),
+            "--".into(),
+            "x".into()
+        ])
+        .is_err());
+
+        let dir = std::env::temp_dir().join(format!("gaut_flags_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.gaut");
+        fs::write(
+            &file,
+            "import flags\nmain() = if flags.has(\"v\") then str_len(flags.value(\"out\", \"-\")) else 0\n",
+        )
+        .unwrap();
+        let std = StdPath::resolve(&[], None, None).unwrap();
+        let program = load_checked_program(&file, TypeChecker::new(), &std, &LintConfig::default())
+            .unwrap()
+            .program;
+        let args = ["--v".to_string(), "--out".into(), "x.bin".into()];
+        let mut interp = new_interpreter(Target::default(), &file);
+        interp.load_typed(&program).unwrap();
+        assert_eq!(
+            interp.run_main_with_args(args.to_vec()).unwrap(),
+            Value::Int(5)
+        );
+        assert_eq!(
+            interp.run_main_with_args(Vec::new()).unwrap(),
+            Value::Int(0)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn json_run_reports_what_the_program_printed() {
+        let dir = std::env::temp_dir().join(format!("gaut_capture_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.gaut");
+        fs::write(
+            &file,
+            "main() = {\n  println(\"hi\")\n  print(\"!\")\n  30\n}\n",
+        )
+        .unwrap();
+        let std = StdPath::resolve(&[], None, None).unwrap();
+        let program = load_checked_program(&file, TypeChecker::new(), &std, &LintConfig::default())
+            .unwrap()
+            .program;
+        let mut interp = new_interpreter(Target::default(), &file);
+        interp.load_typed(&program).unwrap();
+        interp.capture_stdout();
+        let result = interp.run_main().unwrap();
+        let stdout = interp.take_captured_stdout().unwrap();
+        assert_eq!(
+            run_report_json(&result, &stdout, 5),
+            r#"{ "result": 30, "stdout_captured": "hi\n!", "duration_ms": 5 }"#
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
 }
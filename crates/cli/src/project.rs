@@ -0,0 +1,249 @@
+#![forbid(unsafe_code)]
+
+use crate::CliError;
+use frontend::lint::{LintConfig, LintLevel};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "gaut.toml";
+
+/// Project settings from `gaut.toml`. Only the `[package]`, `[std]` and `[lints]` tables
+/// are read:
+///
+/// ```toml
+/// [package]
+/// name = "hello"
+/// entry = "src/main.gaut"
+///
+/// [std]
+/// path = ["vendor/std"]   # searched before GAUT_STD_DIR and the embedded std
+/// version = "0.1.0"       # every std module used must have this version
+///
+/// [lints]
+/// warnings = "deny"       # every lint; later keys and -W/-D/-A flags override it
+/// unused_import = "allow"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub root: PathBuf,
+    pub name: String,
+    pub entry: PathBuf,
+    pub std_paths: Vec<PathBuf>,
+    pub std_version: Option<String>,
+    pub lints: LintConfig,
+}
+
+impl Manifest {
+    pub fn load(root: &Path) -> Result<Self, CliError> {
+        let path = root.join(MANIFEST_FILE);
+        let src = fs::read_to_string(&path)
+            .map_err(|e| CliError::Message(format!("read {}: {e}", path.display())))?;
+        Self::parse(root, &src).map_err(|e| CliError::Message(format!("{}: {e}", path.display())))
+    }
+
+    fn parse(root: &Path, src: &str) -> Result<Self, String> {
+        let mut table = String::new();
+        let mut name = None;
+        let mut entry = None;
+        let mut std_paths = Vec::new();
+        let mut std_version = None;
+        let mut lints = LintConfig::default();
+        for (i, raw) in src.lines().enumerate() {
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                table = line.to_string();
+                continue;
+            }
+            if table != "[package]" && table != "[std]" && table != "[lints]" {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected key = \"value\"", i + 1))?;
+            match (table.as_str(), key.trim()) {
+                ("[std]", "path") => {
+                    let list = value
+                        .trim()
+                        .strip_prefix('[')
+                        .and_then(|v| v.strip_suffix(']'))
+                        .ok_or_else(|| format!("line {}: std.path must be a list", i + 1))?;
+                    for item in list.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                        std_paths.push(root.join(quoted(item, i)?));
+                    }
+                }
+                ("[std]", "version") => std_version = Some(quoted(value, i)?.to_string()),
+                ("[package]", "name") => name = Some(quoted(value, i)?.to_string()),
+                ("[package]", "entry") => entry = Some(quoted(value, i)?.to_string()),
+                ("[lints]", lint) => {
+                    let level: LintLevel = quoted(value, i)?
+                        .parse()
+                        .map_err(|e| format!("line {}: {e}", i + 1))?;
+                    lints
+                        .set(lint, level)
+                        .map_err(|e| format!("line {}: {e}", i + 1))?;
+                }
+                _ => {}
+            }
+        }
+        let name = name.ok_or("missing package.name")?;
+        let entry = entry.unwrap_or_else(|| "src/main.gaut".to_string());
+        Ok(Self {
+            root: root.to_path_buf(),
+            name,
+            entry: root.join(entry),
+            std_paths,
+            std_version,
+            lints,
+        })
+    }
+
+    pub fn c_output(&self) -> PathBuf {
+        self.root.join("target").join(format!("{}.c", self.name))
+    }
+
+    pub fn binary_output(&self) -> PathBuf {
+        self.root.join("target").join(&self.name)
+    }
+}
+
+fn quoted(value: &str, line: usize) -> Result<&str, String> {
+    value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(|| format!("line {}: value must be a quoted string", line + 1))
+}
+
+/// Create a runnable project skeleton in `dir`. Nothing is written if any of its files
+/// already exists. `name` becomes the binary's file name, so it is limited to letters,
+/// digits, `-` and `_`.
+pub fn init_project(dir: &Path, name: &str) -> Result<(), CliError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(CliError::Message(format!(
+            "invalid project name '{name}': use letters, digits, '-' and '_'"
+        )));
+    }
+    let files = [
+        (
+            MANIFEST_FILE.to_string(),
+            format!("[package]\nname = \"{name}\"\nentry = \"src/main.gaut\"\n"),
+        ),
+        (
+            "src/main.gaut".to_string(),
+            "greeting() -> Str = \"hello from gaut\"\n\nmain() = {\n  println(greeting())\n  0\n}\n"
+                .to_string(),
+        ),
+        (
+            "tests/smoke.gaut".to_string(),
+            "// run with: gaut tests/smoke.gaut (prints true on success)\nmain() = 1 + 1 == 2\n"
+                .to_string(),
+        ),
+        (".gitignore".to_string(), "target/\n".to_string()),
+    ];
+    if let Some((rel, _)) = files.iter().find(|(rel, _)| dir.join(rel).exists()) {
+        return Err(CliError::Message(format!(
+            "{} already contains {rel}",
+            dir.display()
+        )));
+    }
+    for (rel, contents) in files {
+        let path = dir.join(rel);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| CliError::Message(format!("create dir {}: {e}", parent.display())))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|e| CliError::Message(format!("write {}: {e}", path.display())))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest_package_table() {
+        let src =
+            "# demo\n[package]\nname = \"demo\"\nentry = \"src/app.gaut\"\n[deps]\nname = \"x\"\n";
+        let m = Manifest::parse(Path::new("/p"), src).unwrap();
+        assert_eq!(m.name, "demo");
+        assert_eq!(m.entry, PathBuf::from("/p/src/app.gaut"));
+        assert_eq!(m.binary_output(), PathBuf::from("/p/target/demo"));
+        assert!(Manifest::parse(Path::new("/p"), "[package]\nentry = \"a.gaut\"\n").is_err());
+        assert!(m.std_paths.is_empty() && m.std_version.is_none());
+    }
+
+    #[test]
+    fn parse_manifest_std_table() {
+        let src = "[package]\nname = \"demo\"\n[std]\npath = [\"vendor/std\", \"/opt/std\"]\nversion = \"0.2.0\"\n";
+        let m = Manifest::parse(Path::new("/p"), src).unwrap();
+        assert_eq!(
+            m.std_paths,
+            [PathBuf::from("/p/vendor/std"), PathBuf::from("/opt/std")]
+        );
+        assert_eq!(m.std_version.as_deref(), Some("0.2.0"));
+        assert!(Manifest::parse(
+            Path::new("/p"),
+            "[package]\nname = \"d\"\n[std]\npath = \"x\"\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_manifest_lints_table() {
+        use frontend::lint::Lint;
+        let src =
+            "[package]\nname = \"demo\"\n[lints]\nwarnings = \"deny\"\nunused_import = \"allow\"\n";
+        let m = Manifest::parse(Path::new("/p"), src).unwrap();
+        assert_eq!(m.lints.level(Lint::UnusedImport), LintLevel::Allow);
+        assert_eq!(m.lints.level(Lint::DuplicateImport), LintLevel::Deny);
+        let err = Manifest::parse(
+            Path::new("/p"),
+            "[package]\nname = \"d\"\n[lints]\nunused_imports = \"deny\"\n",
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown lint 'unused_imports'"), "{err}");
+    }
+
+    #[test]
+    fn init_creates_runnable_skeleton() {
+        let dir = std::env::temp_dir().join(format!("gaut_init_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        init_project(&dir, "demo").unwrap();
+        let manifest = Manifest::load(&dir).unwrap();
+        assert_eq!(manifest.name, "demo");
+        assert!(manifest.entry.exists());
+        assert!(dir.join("tests/smoke.gaut").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join(".gitignore")).unwrap(),
+            "target/\n"
+        );
+        assert!(init_project(&dir, "demo").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn init_leaves_existing_files_alone() {
+        let dir = std::env::temp_dir().join(format!("gaut_init_existing_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.gaut"), "main() = 7\n").unwrap();
+        assert!(init_project(&dir, "demo").is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("src/main.gaut")).unwrap(),
+            "main() = 7\n"
+        );
+        assert!(!dir.join(MANIFEST_FILE).exists());
+        assert!(!dir.join(".gitignore").exists());
+        assert!(init_project(&dir.join("fresh"), "my app\"").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
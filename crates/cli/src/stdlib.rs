@@ -0,0 +1,241 @@
+#![forbid(unsafe_code)]
+
+use crate::project::Manifest;
+use crate::CliError;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `std/VERSION` of the tree the binary was built from.
+const EMBEDDED_STD_VERSION: &str = include_str!("../../../std/VERSION");
+
+/// Std modules compiled into the binary, the last entry of every search path.
+const EMBEDDED_STD: &[(&str, &str)] = &[
+    ("builtin", include_str!("../../../std/builtin.gaut")),
+    ("bytes", include_str!("../../../std/bytes.gaut")),
+    ("flags", include_str!("../../../std/flags.gaut")),
+    ("net", include_str!("../../../std/net.gaut")),
+    ("str", include_str!("../../../std/str.gaut")),
+];
+
+/// One line naming the std version of a directory; without it the version is `unversioned`.
+pub const VERSION_FILE: &str = "VERSION";
+
+/// Where a std root entered the search path, highest priority first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdOrigin {
+    Flag,
+    Manifest,
+    Env,
+    Embedded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdRoot {
+    pub origin: StdOrigin,
+    /// `None` for the embedded std.
+    pub dir: Option<PathBuf>,
+    pub version: String,
+}
+
+impl fmt::Display for StdRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.dir, self.origin) {
+            (None, _) => write!(f, "embedded std {}", self.version),
+            (Some(dir), origin) => {
+                let how = match origin {
+                    StdOrigin::Flag => "--std-path",
+                    StdOrigin::Manifest => "gaut.toml",
+                    StdOrigin::Env => "GAUT_STD_DIR",
+                    StdOrigin::Embedded => "embedded",
+                };
+                write!(f, "std {} at {} ({how})", self.version, dir.display())
+            }
+        }
+    }
+}
+
+/// Where a module's source comes from: a file (the entry, a sibling import or a std
+/// directory) or the embedded std.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleSource {
+    File(PathBuf),
+    Embedded(&'static str),
+}
+
+/// Ordered std lookup: `--std-path` flags, then the manifest's `[std] path`, then
+/// every directory in `GAUT_STD_DIR` (a platform path list), then the embedded std.
+/// The first root that has a module wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StdPath {
+    pub roots: Vec<StdRoot>,
+    /// `[std] version` from the manifest: every std module used must come from it.
+    pub required: Option<String>,
+}
+
+impl StdPath {
+    pub fn resolve(
+        flag_dirs: &[PathBuf],
+        manifest: Option<&Manifest>,
+        env_dirs: Option<OsString>,
+    ) -> Result<Self, CliError> {
+        let mut roots = Vec::new();
+        for dir in flag_dirs {
+            roots.push(dir_root(dir, StdOrigin::Flag)?);
+        }
+        if let Some(m) = manifest {
+            for dir in &m.std_paths {
+                roots.push(dir_root(dir, StdOrigin::Manifest)?);
+            }
+        }
+        if let Some(list) = env_dirs {
+            for dir in std::env::split_paths(&list).filter(|d| !d.as_os_str().is_empty()) {
+                roots.push(dir_root(&dir, StdOrigin::Env)?);
+            }
+        }
+        roots.push(StdRoot {
+            origin: StdOrigin::Embedded,
+            dir: None,
+            version: EMBEDDED_STD_VERSION.trim().to_string(),
+        });
+        Ok(Self {
+            roots,
+            required: manifest.and_then(|m| m.std_version.clone()),
+        })
+    }
+
+    /// [`StdPath::resolve`] with `GAUT_STD_DIR` from the process environment.
+    pub fn from_env(flag_dirs: &[PathBuf], manifest: Option<&Manifest>) -> Result<Self, CliError> {
+        Self::resolve(flag_dirs, manifest, std::env::var_os("GAUT_STD_DIR"))
+    }
+
+    /// The first root providing `module`, as an index into `roots`.
+    pub fn find(&self, module: &str) -> Option<(usize, ModuleSource)> {
+        self.roots.iter().enumerate().find_map(|(i, root)| {
+            let found = match &root.dir {
+                Some(dir) => {
+                    let path = dir.join(format!("{module}.gaut"));
+                    path.exists().then_some(ModuleSource::File(path))
+                }
+                None => EMBEDDED_STD
+                    .iter()
+                    .find(|(name, _)| *name == module)
+                    .map(|(name, _)| ModuleSource::Embedded(name)),
+            };
+            found.map(|m| (i, m))
+        })
+    }
+
+    /// The version a program that used `used` (module, root index) pairs was built
+    /// against. Modules from different std versions, or from a version other than the
+    /// manifest's `[std] version`, are a conflict.
+    pub fn built_against(&self, used: &[(String, usize)]) -> Result<&StdRoot, CliError> {
+        let mut picked: Option<(&str, &StdRoot)> = None;
+        for (module, i) in used {
+            let Some(root) = self.roots.get(*i) else {
+                continue;
+            };
+            if let Some(required) = &self.required {
+                if root.version != *required {
+                    return Err(CliError::Message(format!(
+                        "std module '{module}' comes from {root}, but gaut.toml requires std {required}"
+                    )));
+                }
+            }
+            match picked {
+                Some((first, other)) if other.version != root.version => {
+                    return Err(CliError::Message(format!(
+                        "std version conflict: '{first}' comes from {other}, '{module}' from {root}"
+                    )));
+                }
+                Some(_) => {}
+                None => picked = Some((module, root)),
+            }
+        }
+        match picked {
+            Some((_, root)) => Ok(root),
+            None => self
+                .roots
+                .first()
+                .ok_or_else(|| CliError::Message("empty std search path".into())),
+        }
+    }
+}
+
+pub fn embedded_source(module: &str) -> Option<&'static str> {
+    EMBEDDED_STD
+        .iter()
+        .find(|(name, _)| *name == module)
+        .map(|(_, src)| *src)
+}
+
+fn dir_root(dir: &Path, origin: StdOrigin) -> Result<StdRoot, CliError> {
+    if !dir.is_dir() {
+        return Err(CliError::Message(format!(
+            "std directory {} does not exist",
+            dir.display()
+        )));
+    }
+    let version = fs::read_to_string(dir.join(VERSION_FILE))
+        .map(|v| v.trim().to_string())
+        .unwrap_or_else(|_| "unversioned".to_string());
+    Ok(StdRoot {
+        origin,
+        dir: Some(dir.to_path_buf()),
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_order_and_version_conflicts() {
+        let base = std::env::temp_dir().join(format!("gaut_std_{}", std::process::id()));
+        let (flag, env) = (base.join("flag"), base.join("env"));
+        fs::create_dir_all(&flag).unwrap();
+        fs::create_dir_all(&env).unwrap();
+        fs::write(flag.join("str.gaut"), "x() -> i32 = 1\n").unwrap();
+        fs::write(flag.join(VERSION_FILE), "9.0.0\n").unwrap();
+        fs::write(env.join("str.gaut"), "x() -> i32 = 2\n").unwrap();
+        fs::write(env.join("extra.gaut"), "y() -> i32 = 2\n").unwrap();
+
+        let path = StdPath::resolve(
+            std::slice::from_ref(&flag),
+            None,
+            Some(env.clone().into_os_string()),
+        )
+        .unwrap();
+        let origins: Vec<StdOrigin> = path.roots.iter().map(|r| r.origin).collect();
+        assert_eq!(
+            origins,
+            [StdOrigin::Flag, StdOrigin::Env, StdOrigin::Embedded]
+        );
+        assert_eq!(
+            path.find("str"),
+            Some((0, ModuleSource::File(flag.join("str.gaut"))))
+        );
+        assert_eq!(path.find("extra").map(|(i, _)| i), Some(1));
+        assert_eq!(
+            path.find("bytes"),
+            Some((2, ModuleSource::Embedded("bytes")))
+        );
+        assert!(path.find("nope").is_none());
+
+        assert_eq!(path.built_against(&[]).unwrap().version, "9.0.0");
+        let used = [("str".to_string(), 0), ("bytes".to_string(), 2)];
+        let err = path.built_against(&used).unwrap_err();
+        assert!(format!("{err}").contains("std version conflict"), "{err}");
+
+        let pinned = StdPath {
+            required: Some("1.0.0".into()),
+            ..path
+        };
+        let err = pinned.built_against(&used[..1]).unwrap_err();
+        assert!(format!("{err}").contains("requires std 1.0.0"), "{err}");
+        assert!(StdPath::resolve(&[base.join("missing")], None, None).is_err());
+        fs::remove_dir_all(&base).ok();
+    }
+}
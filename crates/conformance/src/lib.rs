@@ -0,0 +1,462 @@
+#![forbid(unsafe_code)]
+
+//! Cross-backend conformance suite. Every `spec/*.gaut` program carries its expected
+//! outcome in leading comments:
+//!
+//! ```text
+//! // value: 30          main's result (or exit's code) as a process exit status (ints mod 256, bools 0/1)
+//! // stdout: hello      one line of expected output; repeat for more lines
+//! // error: value moved the program must be rejected with a message containing this
+//! ```
+//!
+//! Stdout is compared exactly, so a case without `stdout:` lines must print nothing.
+//! A backend passes when [`run_suite`] reports no failures.
+
+use frontend::parser::Parser;
+use frontend::typecheck::TypeChecker;
+use frontend::typed::TypedProgram;
+use interp::{Interpreter, RuntimeError, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConformanceError {
+    #[error("read {path}: {message}")]
+    Io { path: PathBuf, message: String },
+    #[error("{case}: {message}")]
+    BadAnnotation { case: String, message: String },
+}
+
+/// What a spec program must do on every backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expectation {
+    pub value: Option<u8>,
+    pub stdout: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecCase {
+    pub name: String,
+    pub source: String,
+    pub expect: Expectation,
+}
+
+/// Observable result of running `main`: its value as an exit status, plus stdout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: u8,
+    pub stdout: String,
+}
+
+/// An execution strategy for checked programs. Parsing and typechecking are shared,
+/// so a backend only sees programs the frontend accepted.
+pub trait Backend {
+    fn name(&self) -> &str;
+    fn run(&self, case: &str, program: &TypedProgram) -> Result<Outcome, String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub case: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuiteReport {
+    pub passed: Vec<String>,
+    pub failures: Vec<Failure>,
+}
+
+impl SuiteReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The tree-walking interpreter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterpBackend;
+
+impl Backend for InterpBackend {
+    fn name(&self) -> &str {
+        "interp"
+    }
+
+    fn run(&self, _case: &str, program: &TypedProgram) -> Result<Outcome, String> {
+        let mut interp = Interpreter::new(1024 * 1024);
+        interp.load_typed(program).map_err(|e| e.to_string())?;
+        interp.capture_stdout();
+        let value = match interp.run_main() {
+            Err(RuntimeError::Exit(code)) => Value::Int(i64::from(code)),
+            other => other.map_err(|e| e.to_string())?,
+        };
+        let status = match value {
+            Value::Int(n) => n.rem_euclid(256) as u8,
+            Value::Bool(b) => u8::from(b),
+            Value::Unit => 0,
+            other => return Err(format!("main returned {other}, not i32, bool or Unit")),
+        };
+        Ok(Outcome {
+            status,
+            stdout: interp.take_captured_stdout().unwrap_or_default(),
+        })
+    }
+}
+
+/// Generated C built with `cc` against the runtime in `runtime_dir`; `main`'s result
+/// is the process exit status. With `ansi` the C is generated in `--ansi` mode and
+/// built as strict C11; with `gc` it is generated in `--gc` mode and linked with libgc.
+#[derive(Debug, Clone)]
+pub struct CBackend {
+    pub cc: String,
+    pub runtime_dir: PathBuf,
+    pub work_dir: PathBuf,
+    pub ansi: bool,
+    pub gc: bool,
+}
+
+impl Default for CBackend {
+    fn default() -> Self {
+        Self {
+            cc: std::env::var("CC").unwrap_or_else(|_| "cc".into()),
+            runtime_dir: repo_root().join("runtime/c"),
+            work_dir: std::env::temp_dir().join(format!("gaut_conformance_{}", std::process::id())),
+            ansi: false,
+            gc: false,
+        }
+    }
+}
+
+impl CBackend {
+    /// The default backend in `--ansi` mode, building in a directory of its own.
+    pub fn ansi() -> Self {
+        let c = Self::default();
+        Self {
+            work_dir: c
+                .work_dir
+                .with_file_name(format!("gaut_conformance_ansi_{}", std::process::id())),
+            ansi: true,
+            ..c
+        }
+    }
+
+    /// The default backend in `--gc` mode, building in a directory of its own.
+    pub fn gc() -> Self {
+        let c = Self::default();
+        Self {
+            work_dir: c
+                .work_dir
+                .with_file_name(format!("gaut_conformance_gc_{}", std::process::id())),
+            gc: true,
+            ..c
+        }
+    }
+
+    /// Whether the configured C compiler can be started at all, and under `gc` whether
+    /// it finds libgc's header and library.
+    pub fn available(&self) -> bool {
+        Command::new(&self.cc).arg("--version").output().is_ok() && (!self.gc || self.has_libgc())
+    }
+
+    fn has_libgc(&self) -> bool {
+        let probe = self.work_dir.join("gc_probe.c");
+        fs::create_dir_all(&self.work_dir).is_ok()
+            && fs::write(
+                &probe,
+                "#include <gc.h>\nint main(void) { GC_INIT(); return 0; }\n",
+            )
+            .is_ok()
+            && Command::new(&self.cc)
+                .arg(&probe)
+                .arg("-lgc")
+                .arg("-o")
+                .arg(self.work_dir.join("gc_probe"))
+                .output()
+                .is_ok_and(|out| out.status.success())
+    }
+}
+
+impl Backend for CBackend {
+    fn name(&self) -> &str {
+        match (self.ansi, self.gc) {
+            (true, _) => "c-ansi",
+            (false, true) => "c-gc",
+            (false, false) => "c",
+        }
+    }
+
+    fn run(&self, case: &str, program: &TypedProgram) -> Result<Outcome, String> {
+        let options = cgen::CgenOptions {
+            ansi: self.ansi,
+            memory: if self.gc {
+                cgen::MemoryModel::Gc
+            } else {
+                cgen::MemoryModel::Arena
+            },
+            ..Default::default()
+        };
+        let c_src = cgen::generate_c_typed(program, options).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&self.work_dir)
+            .map_err(|e| format!("create {}: {e}", self.work_dir.display()))?;
+        let c_path = self.work_dir.join(format!("{case}.c"));
+        let bin = self.work_dir.join(case);
+        fs::write(&c_path, c_src).map_err(|e| format!("write {}: {e}", c_path.display()))?;
+        let mut build = Command::new(&self.cc);
+        if self.ansi {
+            build
+                .arg("-std=c11")
+                .arg("-pedantic-errors")
+                .arg("-D_POSIX_C_SOURCE=200809L");
+        } else {
+            build.arg("-std=gnu11");
+        }
+        if self.gc {
+            build.arg("-DGAUT_GC");
+        }
+        let build = build
+            .arg("-w")
+            .arg("-I")
+            .arg(&self.runtime_dir)
+            .arg(&c_path)
+            .arg(self.runtime_dir.join("runtime.c"))
+            .arg("-pthread")
+            .args(self.gc.then_some("-lgc"))
+            .arg("-o")
+            .arg(&bin)
+            .output()
+            .map_err(|e| format!("run {}: {e}", self.cc))?;
+        if !build.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                self.cc,
+                String::from_utf8_lossy(&build.stderr)
+            ));
+        }
+        let run = Command::new(&bin)
+            .output()
+            .map_err(|e| format!("run {}: {e}", bin.display()))?;
+        let status = run.status.code().ok_or_else(|| {
+            // a runtime abort: its stderr line is what error specs match on
+            format!(
+                "terminated by {}: {}",
+                run.status,
+                String::from_utf8_lossy(&run.stderr).trim()
+            )
+        })?;
+        Ok(Outcome {
+            status: status.rem_euclid(256) as u8,
+            stdout: String::from_utf8_lossy(&run.stdout).into_owned(),
+        })
+    }
+}
+
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+/// `GAUT_SPEC_DIR`, or the repository's `spec/` directory.
+pub fn spec_dir() -> PathBuf {
+    std::env::var("GAUT_SPEC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| repo_root().join("spec"))
+}
+
+/// Read the leading `// key: value` comments of a spec program.
+pub fn parse_case(name: &str, source: &str) -> Result<SpecCase, ConformanceError> {
+    let bad = |message: String| ConformanceError::BadAnnotation {
+        case: name.to_string(),
+        message,
+    };
+    let mut expect = Expectation::default();
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line.strip_prefix("//") else {
+            break;
+        };
+        let Some((key, value)) = comment.split_once(':') else {
+            continue;
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match key.trim() {
+            "value" => {
+                let status = match value.trim() {
+                    "true" => 1,
+                    "false" => 0,
+                    n => n
+                        .parse::<i64>()
+                        .map_err(|_| bad(format!("value must be an integer or bool, got '{n}'")))?
+                        .rem_euclid(256) as u8,
+                };
+                expect.value = Some(status);
+            }
+            "stdout" => {
+                expect.stdout.push_str(value);
+                expect.stdout.push('\n');
+            }
+            "error" => expect.error = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if expect.value.is_some() == expect.error.is_some() {
+        return Err(bad("expected exactly one of `value:` or `error:`".into()));
+    }
+    Ok(SpecCase {
+        name: name.to_string(),
+        source: source.to_string(),
+        expect,
+    })
+}
+
+/// Every `*.gaut` file in `dir`, sorted by name.
+pub fn load_suite(dir: &Path) -> Result<Vec<SpecCase>, ConformanceError> {
+    let io = |e: std::io::Error| ConformanceError::Io {
+        path: dir.to_path_buf(),
+        message: e.to_string(),
+    };
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(io)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "gaut"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let source = fs::read_to_string(path).map_err(|e| ConformanceError::Io {
+                path: path.clone(),
+                message: e.to_string(),
+            })?;
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            parse_case(&name, &source)
+        })
+        .collect()
+}
+
+/// Check one case: frontend errors are matched first, then the backend's outcome.
+pub fn run_case(backend: &dyn Backend, case: &SpecCase) -> Result<(), String> {
+    let checked = Parser::new(&case.source)
+        .and_then(|mut p| p.parse_program())
+        .map_err(|e| e.to_string())
+        .and_then(|program| {
+            TypeChecker::new()
+                .check_program(&program)
+                .map_err(|e| e.to_string())
+        });
+    let result = checked.and_then(|program| backend.run(&case.name, &program));
+    match (&case.expect.error, result) {
+        (Some(want), Err(got)) if got.contains(want.as_str()) => Ok(()),
+        (Some(want), Err(got)) => Err(format!("expected error '{want}', got '{got}'")),
+        (Some(want), Ok(_)) => Err(format!("expected error '{want}', but it ran")),
+        (None, Err(got)) => Err(got),
+        (None, Ok(outcome)) => {
+            if Some(outcome.status) != case.expect.value {
+                return Err(format!(
+                    "expected value {:?}, got {}",
+                    case.expect.value, outcome.status
+                ));
+            }
+            if outcome.stdout != case.expect.stdout {
+                return Err(format!(
+                    "expected stdout {:?}, got {:?}",
+                    case.expect.stdout, outcome.stdout
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn run_cases(backend: &dyn Backend, cases: &[SpecCase]) -> SuiteReport {
+    let mut report = SuiteReport::default();
+    for case in cases {
+        match run_case(backend, case) {
+            Ok(()) => report.passed.push(case.name.clone()),
+            Err(reason) => report.failures.push(Failure {
+                case: case.name.clone(),
+                reason,
+            }),
+        }
+    }
+    report
+}
+
+/// Run the whole `spec/` suite on `backend`.
+pub fn run_suite(backend: &dyn Backend) -> Result<SuiteReport, ConformanceError> {
+    let cases = load_suite(&spec_dir())?;
+    Ok(run_cases(backend, &cases))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_passes(backend: &dyn Backend) {
+        let report = run_suite(backend).unwrap();
+        assert!(!report.passed.is_empty());
+        assert!(
+            report.is_ok(),
+            "{} backend failures: {:#?}",
+            backend.name(),
+            report.failures
+        );
+    }
+
+    #[test]
+    fn annotations_are_parsed() {
+        let case = parse_case(
+            "x",
+            "// value: 300\n// stdout: a\n// stdout:\nmain() = 300\n",
+        );
+        let case = case.unwrap();
+        assert_eq!(case.expect.value, Some(44));
+        assert_eq!(case.expect.stdout, "a\n\n");
+        assert!(parse_case("y", "main() = 1\n").is_err());
+    }
+
+    #[test]
+    fn interpreter_passes_the_suite() {
+        assert_passes(&InterpBackend);
+    }
+
+    #[test]
+    fn c_backend_passes_the_suite() {
+        let backend = CBackend::default();
+        if !backend.available() {
+            eprintln!("skipping: C compiler '{}' not found", backend.cc);
+            return;
+        }
+        assert_passes(&backend);
+        fs::remove_dir_all(&backend.work_dir).ok();
+    }
+
+    #[test]
+    fn c_backend_passes_the_suite_in_ansi_mode() {
+        let backend = CBackend::ansi();
+        if !backend.available() {
+            eprintln!("skipping: C compiler '{}' not found", backend.cc);
+            return;
+        }
+        assert_passes(&backend);
+        fs::remove_dir_all(&backend.work_dir).ok();
+    }
+
+    #[test]
+    fn c_backend_passes_the_suite_with_gc() {
+        let backend = CBackend::gc();
+        if !backend.available() {
+            eprintln!("skipping: C compiler '{}' or libgc not found", backend.cc);
+            fs::remove_dir_all(&backend.work_dir).ok();
+            return;
+        }
+        assert_passes(&backend);
+        fs::remove_dir_all(&backend.work_dir).ok();
+    }
+}
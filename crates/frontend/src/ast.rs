@@ -1,50 +1,197 @@
 #![forbid(unsafe_code)]
 
+use std::fmt;
+
+pub mod fold;
+pub mod visit;
+
+pub use fold::Fold;
+pub use visit::Visit;
+
+/// Where a node or token was written: byte offsets `start..end` into its source file,
+/// and the 1-based line and column (in chars) of `start`. Nodes the compiler makes up
+/// while lowering carry `Span::default()`, whose line is 0.
+///
+/// Spans never take part in equality: trees that differ only in where they were
+/// written compare equal, so lowering passes and tests can compare them directly.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub col: u32,
+    /// Which expression of a checked program the span belongs to, so that its type can
+    /// be found by node; see [`crate::typed::number_nodes`]. 0 everywhere else.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub node: u32,
+}
+
+impl Span {
+    /// Whether the span points into source text rather than being `Span::default()`.
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+
+    /// From the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+/// `line:col`, the form editors jump to.
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub decls: Vec<Decl>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Decl {
     Import(ImportDecl),
     Global(Binding),
     Let(Binding),
     Type(TypeDecl),
     Func(FuncDecl),
+    Trait(TraitDecl),
+    Impl(ImplDecl),
+    Macro(MacroDecl),
+}
+
+impl Decl {
+    pub fn span(&self) -> Span {
+        match self {
+            Decl::Import(i) => i.span,
+            Decl::Global(b) | Decl::Let(b) => b.span,
+            Decl::Type(t) => t.span,
+            Decl::Func(f) => f.span,
+            Decl::Trait(t) => t.span,
+            Decl::Impl(i) => i.span,
+            Decl::Macro(m) => m.span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImportDecl {
     pub module: Ident,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ident(pub String);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Binding {
     pub mutable: bool,
     pub name: Ident,
     pub ty: Type,
     pub value: Expr,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeDecl {
     pub name: Ident,
     pub ty: Type,
+    /// `@derive(show, json)` before the declaration; expanded by `lower::expand_derives`.
+    pub derives: Vec<Ident>,
+    /// Text of the `///` comment before the declaration, when the parser captured it.
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+impl TypeDecl {
+    /// Functions the derives generate: `Point_show` for `@derive(show) type Point`,
+    /// `Point_to_json` and `Point_from_json` for `@derive(json)`.
+    pub fn derived_fn_names(&self) -> impl Iterator<Item = String> + '_ {
+        let ty = &self.name.0;
+        self.derives.iter().flat_map(move |d| match d.0.as_str() {
+            "json" => vec![format!("{ty}_to_json"), format!("{ty}_from_json")],
+            other => vec![format!("{ty}_{other}")],
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncDecl {
     pub name: Ident,
     pub params: Vec<Param>,
     pub ret: Option<Type>,
     pub body: Expr, // block or expression
+    /// Text of the `///` comment before the declaration, when the parser captured it.
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+/// `macro name(a, b) = body`: every call `name(x, y)` is replaced by `body` with the
+/// parameters substituted, by `macros::expand_macros` before anything else is lowered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MacroDecl {
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    pub body: Expr,
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+/// `trait Name { method(self, ...) -> T }`; `self` has type `Self` (or `&Self`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraitDecl {
+    pub name: Ident,
+    pub methods: Vec<TraitMethod>,
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraitMethod {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub ret: Type,
+    pub doc: Option<String>,
+    pub span: Span,
 }
 
+/// `impl Trait for Type { method(self, ...) -> T = body }`
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImplDecl {
+    pub trait_name: Ident,
+    pub ty: Ident,
+    pub methods: Vec<FuncDecl>,
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
     pub mutable: bool,
     pub name: Ident,
@@ -52,94 +199,236 @@ pub struct Param {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
     Binding(Binding),
     Assign(Assign),
     Expr(Expr),
+    /// Function local to the enclosing block; lifted to the top level by `lower::lower_program`.
+    Func(FuncDecl),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Binding(b) => b.span,
+            Stmt::Assign(a) => a.span,
+            Stmt::Expr(e) => e.span(),
+            Stmt::Func(f) => f.span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assign {
     pub target: Path,
     pub value: Expr,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     pub stmts: Vec<Stmt>,
     pub tail: Option<Box<Expr>>, // if None, unit is implied
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
-    Literal(Literal),
+    Literal(Literal, Span),
     Path(Path),
     Copy(Box<Expr>),
     Ref(Box<Expr>),
+    MutRef(Box<Expr>),
     FuncCall(FuncCall),
     If(Box<IfExpr>),
     Block(Block),
     RecordLit(RecordLit),
     Unary(UnaryExpr),
     Binary(BinaryExpr),
+    Index(IndexExpr),
+    Slice(SliceExpr),
+    /// `time { ... }`: runs the block (always an `Expr::Block`), drops its value and
+    /// yields the elapsed wall-clock milliseconds as the default integer.
+    Time(Box<Expr>),
+}
+
+impl Expr {
+    /// Where the expression was written. `copy`, `&`, `&mut` and `time` report their
+    /// operand's span.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(_, span) => *span,
+            Expr::Path(p) => p.1,
+            Expr::Copy(e) | Expr::Ref(e) | Expr::MutRef(e) | Expr::Time(e) => e.span(),
+            Expr::FuncCall(c) => c.span,
+            Expr::If(i) => i.span,
+            Expr::Block(b) => b.span,
+            Expr::RecordLit(r) => r.span,
+            Expr::Unary(u) => u.span,
+            Expr::Binary(b) => b.span,
+            Expr::Index(i) => i.span,
+            Expr::Slice(s) => s.span,
+        }
+    }
+}
+
+/// How a method call passes its receiver, from the callee's first parameter type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Receiver {
+    Value,
+    Ref,
+    MutRef,
+}
+
+impl Receiver {
+    pub fn of(first_param: Option<&Type>) -> Self {
+        match first_param {
+            Some(Type::Ref(_)) => Receiver::Ref,
+            Some(Type::MutRef(_)) => Receiver::MutRef,
+            _ => Receiver::Value,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncCall {
     pub callee: Path,
     pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+impl FuncCall {
+    /// Method name of a `recv.f(args)` call; `None` for plain `f(args)` calls.
+    pub fn method_name(&self) -> Option<&Ident> {
+        match self.callee.0.as_slice() {
+            [_, .., name] => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Rewrite `recv.f(args)` to `f(recv, args)`, or `f(&recv, args)` / `f(&mut recv, args)`
+    /// when the function takes its first parameter by reference.
+    pub fn desugar_method(&self, receiver: Receiver) -> Option<FuncCall> {
+        let (name, path) = self.callee.0.split_last()?;
+        if path.is_empty() {
+            return None;
+        }
+        let path = Expr::Path(Path(path.to_vec(), self.callee.1));
+        let receiver = match receiver {
+            Receiver::Value => path,
+            Receiver::Ref => Expr::Ref(Box::new(path)),
+            Receiver::MutRef => Expr::MutRef(Box::new(path)),
+        };
+        let mut args = Vec::with_capacity(self.args.len() + 1);
+        args.push(receiver);
+        args.extend(self.args.iter().cloned());
+        Some(FuncCall {
+            callee: Path(vec![name.clone()], self.callee.1),
+            args,
+            span: self.span,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IfExpr {
     pub cond: Expr,
     pub then_branch: Expr,
     pub else_branch: Expr,
+    pub span: Span,
 }
 
+/// `{ x: 1, y: 2 }`, or `{ ..base, x: 1 }` taking every field not listed from `base`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RecordLit {
+    pub base: Option<Box<Expr>>,
     pub fields: Vec<FieldInit>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FieldInit {
     pub name: Ident,
     pub value: Expr,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnaryExpr {
     pub op: UnaryOp,
     pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+/// `s[i]`: the byte at `i` of a `Str` or `Bytes`, bounds-checked at run time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexExpr {
+    pub base: Box<Expr>,
+    pub index: Box<Expr>,
+    pub span: Span,
+}
+
+/// `s[a..b]`: bytes `a` up to (not including) `b` of a `Str` or `Bytes`, as a new
+/// value of the same type. Either bound may be left out: `s[..b]`, `s[a..]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SliceExpr {
+    pub base: Box<Expr>,
+    pub start: Option<Box<Expr>>,
+    pub end: Option<Box<Expr>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryExpr {
     pub left: Box<Expr>,
     pub op: BinaryOp,
     pub right: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Neg,
     Not,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     Mul,
     Div,
     Add,
     Sub,
     Lt,
+    Gt,
+    Le,
+    Ge,
     Eq,
+    Ne,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     And,
     Or,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Literal {
     Int(i64),
     Bool(bool),
@@ -148,17 +437,63 @@ pub enum Literal {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Named(Ident),
     Ref(Box<Type>),
+    /// `&mut T`: exclusive reference; assignments through it reach the referee.
+    MutRef(Box<Type>),
     Record(Vec<FieldType>),
+    Func(FuncType),
 }
 
+/// Function type `(i32, i32) -> i32`; values are top-level functions passed by name.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FuncType {
+    pub params: Vec<Type>,
+    pub ret: Box<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FieldType {
     pub name: Ident,
     pub ty: Type,
 }
 
+/// Source syntax: `{ x: i32, next: &Node }`, `(i32) -> Str`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(id) => f.write_str(&id.0),
+            Type::Ref(inner) => write!(f, "&{inner}"),
+            Type::MutRef(inner) => write!(f, "&mut {inner}"),
+            Type::Record(fields) => {
+                f.write_str("{ ")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", field.name.0, field.ty)?;
+                }
+                f.write_str(" }")
+            }
+            Type::Func(ft) => {
+                f.write_str("(")?;
+                for (i, p) in ft.params.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{p}")?;
+                }
+                write!(f, ") -> {}", ft.ret)
+            }
+        }
+    }
+}
+
+/// `a.b.c` and where it was written.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Path(pub Vec<Ident>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Path(pub Vec<Ident>, pub Span);
@@ -0,0 +1,257 @@
+#![forbid(unsafe_code)]
+
+//! Rewriting traversal. Implement [`Fold`], override the methods for the nodes to
+//! rewrite and call the matching `walk_*` function from an override to rebuild the
+//! children first. The walks keep every span. Lowering passes can fail, so each method
+//! returns `Result`; a fold that cannot fail uses `std::convert::Infallible`.
+
+use super::*;
+
+pub trait Fold {
+    type Error;
+
+    fn fold_decl(&mut self, decl: Decl) -> Result<Decl, Self::Error> {
+        walk_decl(self, decl)
+    }
+
+    /// Top-level functions, impl methods and block-local functions.
+    fn fold_func(&mut self, func: FuncDecl) -> Result<FuncDecl, Self::Error> {
+        walk_func(self, func)
+    }
+
+    /// Globals, top-level `let`s and block bindings.
+    fn fold_binding(&mut self, binding: Binding) -> Result<Binding, Self::Error> {
+        walk_binding(self, binding)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Result<Stmt, Self::Error> {
+        walk_stmt(self, stmt)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Result<Block, Self::Error> {
+        walk_block(self, block)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Result<Expr, Self::Error> {
+        walk_expr(self, expr)
+    }
+
+    fn fold_type(&mut self, ty: Type) -> Result<Type, Self::Error> {
+        Ok(ty)
+    }
+}
+
+pub fn walk_program<F: Fold + ?Sized>(f: &mut F, program: Program) -> Result<Program, F::Error> {
+    Ok(Program {
+        decls: program
+            .decls
+            .into_iter()
+            .map(|d| f.fold_decl(d))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+pub fn walk_decl<F: Fold + ?Sized>(f: &mut F, decl: Decl) -> Result<Decl, F::Error> {
+    Ok(match decl {
+        Decl::Import(_) => decl,
+        Decl::Global(b) => Decl::Global(f.fold_binding(b)?),
+        Decl::Let(b) => Decl::Let(f.fold_binding(b)?),
+        Decl::Type(t) => Decl::Type(TypeDecl {
+            ty: f.fold_type(t.ty)?,
+            ..t
+        }),
+        Decl::Func(func) => Decl::Func(f.fold_func(func)?),
+        Decl::Trait(t) => Decl::Trait(TraitDecl {
+            methods: t
+                .methods
+                .into_iter()
+                .map(|m| {
+                    Ok(TraitMethod {
+                        params: fold_params(f, m.params)?,
+                        ret: f.fold_type(m.ret)?,
+                        ..m
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            ..t
+        }),
+        Decl::Impl(imp) => Decl::Impl(ImplDecl {
+            methods: imp
+                .methods
+                .into_iter()
+                .map(|m| f.fold_func(m))
+                .collect::<Result<_, _>>()?,
+            ..imp
+        }),
+        Decl::Macro(m) => Decl::Macro(MacroDecl {
+            body: f.fold_expr(m.body)?,
+            ..m
+        }),
+    })
+}
+
+pub fn walk_func<F: Fold + ?Sized>(f: &mut F, func: FuncDecl) -> Result<FuncDecl, F::Error> {
+    Ok(FuncDecl {
+        params: fold_params(f, func.params)?,
+        ret: func.ret.map(|t| f.fold_type(t)).transpose()?,
+        body: f.fold_expr(func.body)?,
+        ..func
+    })
+}
+
+pub fn walk_binding<F: Fold + ?Sized>(f: &mut F, binding: Binding) -> Result<Binding, F::Error> {
+    Ok(Binding {
+        ty: f.fold_type(binding.ty)?,
+        value: f.fold_expr(binding.value)?,
+        ..binding
+    })
+}
+
+pub fn walk_stmt<F: Fold + ?Sized>(f: &mut F, stmt: Stmt) -> Result<Stmt, F::Error> {
+    Ok(match stmt {
+        Stmt::Binding(b) => Stmt::Binding(f.fold_binding(b)?),
+        Stmt::Assign(a) => Stmt::Assign(Assign {
+            value: f.fold_expr(a.value)?,
+            ..a
+        }),
+        Stmt::Expr(e) => Stmt::Expr(f.fold_expr(e)?),
+        Stmt::Func(func) => Stmt::Func(f.fold_func(func)?),
+    })
+}
+
+pub fn walk_block<F: Fold + ?Sized>(f: &mut F, block: Block) -> Result<Block, F::Error> {
+    Ok(Block {
+        stmts: block
+            .stmts
+            .into_iter()
+            .map(|s| f.fold_stmt(s))
+            .collect::<Result<_, _>>()?,
+        tail: fold_boxed(f, block.tail)?,
+        span: block.span,
+    })
+}
+
+pub fn walk_expr<F: Fold + ?Sized>(f: &mut F, expr: Expr) -> Result<Expr, F::Error> {
+    Ok(match expr {
+        Expr::Literal(..) | Expr::Path(_) => expr,
+        Expr::Copy(inner) => Expr::Copy(Box::new(f.fold_expr(*inner)?)),
+        Expr::Ref(inner) => Expr::Ref(Box::new(f.fold_expr(*inner)?)),
+        Expr::MutRef(inner) => Expr::MutRef(Box::new(f.fold_expr(*inner)?)),
+        Expr::Time(inner) => Expr::Time(Box::new(f.fold_expr(*inner)?)),
+        Expr::FuncCall(call) => Expr::FuncCall(FuncCall {
+            args: call
+                .args
+                .into_iter()
+                .map(|a| f.fold_expr(a))
+                .collect::<Result<_, _>>()?,
+            ..call
+        }),
+        Expr::If(ife) => {
+            let IfExpr {
+                cond,
+                then_branch,
+                else_branch,
+                span,
+            } = *ife;
+            Expr::If(Box::new(IfExpr {
+                cond: f.fold_expr(cond)?,
+                then_branch: f.fold_expr(then_branch)?,
+                else_branch: f.fold_expr(else_branch)?,
+                span,
+            }))
+        }
+        Expr::Block(b) => Expr::Block(f.fold_block(b)?),
+        Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+            base: fold_boxed(f, r.base)?,
+            fields: r
+                .fields
+                .into_iter()
+                .map(|fi| {
+                    Ok(FieldInit {
+                        value: f.fold_expr(fi.value)?,
+                        ..fi
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            span: r.span,
+        }),
+        Expr::Unary(u) => Expr::Unary(UnaryExpr {
+            expr: Box::new(f.fold_expr(*u.expr)?),
+            ..u
+        }),
+        Expr::Binary(b) => Expr::Binary(BinaryExpr {
+            left: Box::new(f.fold_expr(*b.left)?),
+            right: Box::new(f.fold_expr(*b.right)?),
+            ..b
+        }),
+        Expr::Index(ix) => Expr::Index(IndexExpr {
+            base: Box::new(f.fold_expr(*ix.base)?),
+            index: Box::new(f.fold_expr(*ix.index)?),
+            span: ix.span,
+        }),
+        Expr::Slice(sl) => Expr::Slice(SliceExpr {
+            base: Box::new(f.fold_expr(*sl.base)?),
+            start: fold_boxed(f, sl.start)?,
+            end: fold_boxed(f, sl.end)?,
+            span: sl.span,
+        }),
+    })
+}
+
+fn fold_params<F: Fold + ?Sized>(f: &mut F, params: Vec<Param>) -> Result<Vec<Param>, F::Error> {
+    params
+        .into_iter()
+        .map(|p| {
+            Ok(Param {
+                ty: f.fold_type(p.ty)?,
+                ..p
+            })
+        })
+        .collect()
+}
+
+fn fold_boxed<F: Fold + ?Sized>(
+    f: &mut F,
+    expr: Option<Box<Expr>>,
+) -> Result<Option<Box<Expr>>, F::Error> {
+    expr.map(|e| f.fold_expr(*e).map(Box::new)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use std::convert::Infallible;
+
+    /// Doubles every integer literal.
+    struct Double;
+
+    impl Fold for Double {
+        type Error = Infallible;
+
+        fn fold_expr(&mut self, expr: Expr) -> Result<Expr, Infallible> {
+            match walk_expr(self, expr)? {
+                Expr::Literal(Literal::Int(n), span) => {
+                    Ok(Expr::Literal(Literal::Int(n * 2), span))
+                }
+                other => Ok(other),
+            }
+        }
+    }
+
+    #[test]
+    fn fold_reaches_nested_expressions_and_keeps_spans() {
+        let parse = |src| Parser::new(src).unwrap().parse_program().unwrap();
+        let program =
+            parse("g: i32 = 1\nf() = {\n  h() = { 2 }\n  if h() > 3 then s[4..] else s[5]\n}");
+        let Ok(doubled) = walk_program(&mut Double, program);
+        assert_eq!(
+            doubled,
+            parse("g: i32 = 2\nf() = {\n  h() = { 4 }\n  if h() > 6 then s[8..] else s[10]\n}")
+        );
+        let Decl::Let(g) = &doubled.decls[0] else {
+            panic!("expected binding");
+        };
+        assert_eq!((g.value.span().line, g.value.span().col), (1, 10));
+    }
+}
@@ -0,0 +1,177 @@
+#![forbid(unsafe_code)]
+
+//! Read-only traversal. Implement [`Visit`], override the methods for the nodes of
+//! interest and call the matching `walk_*` function from an override to keep going
+//! into its children; leaving it out skips the subtree.
+
+use super::*;
+
+pub trait Visit {
+    fn visit_decl(&mut self, decl: &Decl) {
+        walk_decl(self, decl);
+    }
+
+    /// Top-level functions, impl methods and block-local functions.
+    fn visit_func(&mut self, func: &FuncDecl) {
+        walk_func(self, func);
+    }
+
+    /// Globals, top-level `let`s and block bindings.
+    fn visit_binding(&mut self, binding: &Binding) {
+        walk_binding(self, binding);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_call(&mut self, call: &FuncCall) {
+        walk_call(self, call);
+    }
+
+    /// Paths read by expressions, assignment targets and callees.
+    fn visit_path(&mut self, _path: &Path) {}
+
+    /// Types written in declarations, parameters and bindings.
+    fn visit_type(&mut self, ty: &Type) {
+        walk_type(self, ty);
+    }
+}
+
+pub fn walk_program<V: Visit + ?Sized>(v: &mut V, program: &Program) {
+    for decl in &program.decls {
+        v.visit_decl(decl);
+    }
+}
+
+pub fn walk_decl<V: Visit + ?Sized>(v: &mut V, decl: &Decl) {
+    match decl {
+        Decl::Import(_) => {}
+        Decl::Global(b) | Decl::Let(b) => v.visit_binding(b),
+        Decl::Type(t) => v.visit_type(&t.ty),
+        Decl::Func(f) => v.visit_func(f),
+        Decl::Trait(t) => {
+            for m in &t.methods {
+                for p in &m.params {
+                    v.visit_type(&p.ty);
+                }
+                v.visit_type(&m.ret);
+            }
+        }
+        Decl::Impl(imp) => {
+            for m in &imp.methods {
+                v.visit_func(m);
+            }
+        }
+        Decl::Macro(m) => v.visit_expr(&m.body),
+    }
+}
+
+pub fn walk_func<V: Visit + ?Sized>(v: &mut V, func: &FuncDecl) {
+    for p in &func.params {
+        v.visit_type(&p.ty);
+    }
+    if let Some(ret) = &func.ret {
+        v.visit_type(ret);
+    }
+    v.visit_expr(&func.body);
+}
+
+pub fn walk_binding<V: Visit + ?Sized>(v: &mut V, binding: &Binding) {
+    v.visit_type(&binding.ty);
+    v.visit_expr(&binding.value);
+}
+
+pub fn walk_stmt<V: Visit + ?Sized>(v: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Binding(b) => v.visit_binding(b),
+        Stmt::Assign(a) => {
+            v.visit_path(&a.target);
+            v.visit_expr(&a.value);
+        }
+        Stmt::Expr(e) => v.visit_expr(e),
+        Stmt::Func(f) => v.visit_func(f),
+    }
+}
+
+pub fn walk_block<V: Visit + ?Sized>(v: &mut V, block: &Block) {
+    for stmt in &block.stmts {
+        v.visit_stmt(stmt);
+    }
+    if let Some(tail) = &block.tail {
+        v.visit_expr(tail);
+    }
+}
+
+pub fn walk_expr<V: Visit + ?Sized>(v: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Literal(..) => {}
+        Expr::Path(p) => v.visit_path(p),
+        Expr::Copy(inner) | Expr::Ref(inner) | Expr::MutRef(inner) | Expr::Time(inner) => {
+            v.visit_expr(inner)
+        }
+        Expr::FuncCall(call) => v.visit_call(call),
+        Expr::If(ife) => {
+            v.visit_expr(&ife.cond);
+            v.visit_expr(&ife.then_branch);
+            v.visit_expr(&ife.else_branch);
+        }
+        Expr::Block(b) => v.visit_block(b),
+        Expr::RecordLit(r) => {
+            if let Some(base) = &r.base {
+                v.visit_expr(base);
+            }
+            for f in &r.fields {
+                v.visit_expr(&f.value);
+            }
+        }
+        Expr::Unary(u) => v.visit_expr(&u.expr),
+        Expr::Binary(b) => {
+            v.visit_expr(&b.left);
+            v.visit_expr(&b.right);
+        }
+        Expr::Index(ix) => {
+            v.visit_expr(&ix.base);
+            v.visit_expr(&ix.index);
+        }
+        Expr::Slice(sl) => {
+            v.visit_expr(&sl.base);
+            for bound in [&sl.start, &sl.end].into_iter().flatten() {
+                v.visit_expr(bound);
+            }
+        }
+    }
+}
+
+pub fn walk_call<V: Visit + ?Sized>(v: &mut V, call: &FuncCall) {
+    v.visit_path(&call.callee);
+    for arg in &call.args {
+        v.visit_expr(arg);
+    }
+}
+
+pub fn walk_type<V: Visit + ?Sized>(v: &mut V, ty: &Type) {
+    match ty {
+        Type::Named(_) => {}
+        Type::Ref(inner) | Type::MutRef(inner) => v.visit_type(inner),
+        Type::Record(fields) => {
+            for f in fields {
+                v.visit_type(&f.ty);
+            }
+        }
+        Type::Func(f) => {
+            for p in &f.params {
+                v.visit_type(p);
+            }
+            v.visit_type(&f.ret);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+#![forbid(unsafe_code)]
+
+//! Compile-time evaluation of global initializers. A backend that cannot run code
+//! before `main` folds each initializer it can into a literal, in declaration order
+//! (lowering already sorted globals by dependency), and evaluates the rest at startup.
+
+use crate::ast::*;
+use crate::target::{Overflow, Target};
+use std::collections::HashMap;
+
+/// The globals folded so far, for later initializers to read.
+#[derive(Debug, Clone, Default)]
+pub struct ConstEnv {
+    target: Target,
+    values: HashMap<String, Literal>,
+}
+
+impl ConstEnv {
+    /// Integer operators fold as they would run under `target`: at its width, and not
+    /// at all when they would trap.
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Fold the initializer of `binding` and, if it is constant, remember its value.
+    pub fn define(&mut self, binding: &Binding) -> Option<Literal> {
+        let value = self.eval(&binding.value)?;
+        self.values.insert(binding.name.0.clone(), value.clone());
+        Some(value)
+    }
+
+    /// The value of `expr` if it only combines literals and folded globals with
+    /// operators and `if`, and evaluating it cannot fail.
+    pub fn eval(&self, expr: &Expr) -> Option<Literal> {
+        match expr {
+            Expr::Literal(lit, _) => Some(lit.clone()),
+            Expr::Path(Path(segments, _)) => match segments.as_slice() {
+                [name] => self.values.get(&name.0).cloned(),
+                _ => None,
+            },
+            Expr::Copy(inner) => self.eval(inner),
+            Expr::Block(b) if b.stmts.is_empty() => self.eval(b.tail.as_deref()?),
+            Expr::If(ife) => match self.eval(&ife.cond)? {
+                Literal::Bool(true) => self.eval(&ife.then_branch),
+                Literal::Bool(false) => self.eval(&ife.else_branch),
+                _ => None,
+            },
+            Expr::Unary(u) => match (&u.op, self.eval(&u.expr)?) {
+                (UnaryOp::Neg, Literal::Int(n)) => self.int(-i128::from(n)),
+                (UnaryOp::Not, Literal::Bool(b)) => Some(Literal::Bool(!b)),
+                _ => None,
+            },
+            Expr::Binary(b) => self.binary(&b.op, self.eval(&b.left)?, self.eval(&b.right)?),
+            _ => None,
+        }
+    }
+
+    fn binary(&self, op: &BinaryOp, l: Literal, r: Literal) -> Option<Literal> {
+        use Literal::{Bool, Int, Str};
+        let model = self.target.int_model;
+        Some(match (op, l, r) {
+            (BinaryOp::Add, Int(a), Int(b)) => self.int(i128::from(a) + i128::from(b))?,
+            (BinaryOp::Sub, Int(a), Int(b)) => self.int(i128::from(a) - i128::from(b))?,
+            (BinaryOp::Mul, Int(a), Int(b)) => self.int(i128::from(a) * i128::from(b))?,
+            (BinaryOp::Div, Int(_), Int(0)) => return None,
+            (BinaryOp::Div, Int(a), Int(b)) => self.int(i128::from(a) / i128::from(b))?,
+            (BinaryOp::Add, Str(a), Str(b)) => Str(a + &b),
+            (BinaryOp::Lt, Int(a), Int(b)) => Bool(a < b),
+            (BinaryOp::Gt, Int(a), Int(b)) => Bool(a > b),
+            (BinaryOp::Le, Int(a), Int(b)) => Bool(a <= b),
+            (BinaryOp::Ge, Int(a), Int(b)) => Bool(a >= b),
+            (BinaryOp::Eq, a, b) => Bool(a == b),
+            (BinaryOp::Ne, a, b) => Bool(a != b),
+            (BinaryOp::BitAnd, Int(a), Int(b)) => Int(a & b),
+            (BinaryOp::BitOr, Int(a), Int(b)) => Int(a | b),
+            (BinaryOp::BitXor, Int(a), Int(b)) => Int(a ^ b),
+            (BinaryOp::Shl | BinaryOp::Shr, Int(a), Int(b)) => {
+                if !(0..i64::from(model.bits())).contains(&b) {
+                    return None;
+                }
+                match op {
+                    BinaryOp::Shl => Int(model.wrap(a.wrapping_shl(b as u32))),
+                    _ => Int(a >> b),
+                }
+            }
+            (BinaryOp::And, Bool(a), Bool(b)) => Bool(a && b),
+            (BinaryOp::Or, Bool(a), Bool(b)) => Bool(a || b),
+            _ => return None,
+        })
+    }
+
+    /// An exact integer result at the target's width; `None` where it would trap.
+    fn int(&self, exact: i128) -> Option<Literal> {
+        let model = self.target.int_model;
+        if (i128::from(model.min())..=i128::from(model.max())).contains(&exact) {
+            return Some(Literal::Int(exact as i64));
+        }
+        match self.target.overflow {
+            Overflow::Wrap => Some(Literal::Int(model.wrap(exact as i64))),
+            Overflow::Trap => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::target::IntModel;
+
+    fn globals(src: &str) -> Vec<Binding> {
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        program
+            .decls
+            .into_iter()
+            .filter_map(|d| match d {
+                Decl::Global(b) | Decl::Let(b) => Some(b),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn initializers_fold_through_earlier_globals() {
+        let mut env = ConstEnv::new(Target::default());
+        let folded: Vec<Option<Literal>> = globals(
+            "global base: i32 = 20 + 1\n\
+             global total: i32 = if copy base > 3 then copy base * 2 else 0\n\
+             global name: Str = \"a\" + \"b\"\n\
+             global big: i32 = 2147483647 + 1\n\
+             global later: i32 = now() + copy total",
+        )
+        .iter()
+        .map(|b| env.define(b))
+        .collect();
+        assert_eq!(
+            folded,
+            [
+                Some(Literal::Int(21)),
+                Some(Literal::Int(42)),
+                Some(Literal::Str("ab".into())),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn overflow_folds_only_where_it_wraps() {
+        let [g] = <[Binding; 1]>::try_from(globals("global g: i32 = 2147483647 + 1")).unwrap();
+        let wrap = Target {
+            int_model: IntModel::I32,
+            overflow: Overflow::Wrap,
+        };
+        assert_eq!(
+            ConstEnv::new(wrap).define(&g),
+            Some(Literal::Int(i32::MIN as i64))
+        );
+        let wide = Target {
+            int_model: IntModel::I64,
+            overflow: Overflow::Trap,
+        };
+        assert_eq!(
+            ConstEnv::new(wide).define(&g),
+            Some(Literal::Int(2147483648))
+        );
+    }
+}
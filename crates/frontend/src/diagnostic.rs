@@ -0,0 +1,91 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// One problem found in a program. The parser and the typechecker collect these instead
+/// of stopping at the first error, so one run reports everything it can.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Unknown (`line == 0`) when the problem is in a node without a source position.
+    pub span: Span,
+    pub message: String,
+    /// Extra context, such as the declaration the problem was found in.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// `error at 3:12: message`, then one indented `note:` line per note.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.span.is_known() {
+            write!(f, "{} at {}: {}", self.severity, self.span, self.message)?;
+        } else {
+            write!(f, "{}: {}", self.severity, self.message)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  note: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_puts_the_position_first_and_notes_last() {
+        let span = Span {
+            start: 4,
+            end: 9,
+            line: 2,
+            col: 3,
+            node: 0,
+        };
+        let d = Diagnostic::error(span, "type mismatch").with_note("in function `main`");
+        assert_eq!(
+            d.to_string(),
+            "error at 2:3: type mismatch\n  note: in function `main`"
+        );
+        assert_eq!(
+            Diagnostic::error(Span::default(), "oops").to_string(),
+            "error: oops"
+        );
+    }
+}
@@ -0,0 +1,360 @@
+#![forbid(unsafe_code)]
+
+//! Incremental re-parsing for editors: after an edit, only the declarations around it
+//! are lexed and parsed again. See [`Parser::reparse`].
+
+use crate::ast::fold::{self, Fold};
+use crate::ast::*;
+use crate::parser::{Parser, ParserError};
+use std::convert::Infallible;
+
+/// One text change, in byte offsets: `start..old_end` of the old source was replaced
+/// by what is now `start..new_end` of the new source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+pub(crate) fn reparse(old: &Program, edit: &Edit, new_src: &str) -> Result<Program, ParserError> {
+    let from_scratch = || Parser::with_doc_comments(new_src)?.parse_program();
+    let decls = &old.decls;
+    let valid = edit.start <= edit.old_end
+        && edit.start <= edit.new_end
+        && new_src.is_char_boundary(edit.new_end)
+        && decls.iter().all(|d| d.span().is_known());
+    if !valid {
+        return from_scratch();
+    }
+
+    // a declaration stays when the one after it also ends before the edit: neither it
+    // nor the tokens the parser looked at to find its end were touched
+    let kept = decls
+        .windows(2)
+        .take_while(|pair| pair[1].span().end < edit.start)
+        .count();
+    let region_start = match kept {
+        0 => 0,
+        n => decls[n - 1].span().end,
+    };
+
+    // the first declaration after the edit to reuse: the text between it and the one
+    // before it (where its doc comment is) must be after the edit too, and it must
+    // start on a later line than the edit ends on, so only its line number moves
+    let moved = |offset: usize| (offset + edit.new_end).checked_sub(edit.old_end);
+    let resync = (kept + 1..decls.len()).find_map(|j| {
+        let start = moved(decls[j].span().start)?;
+        let gap_after_edit = decls[j - 1].span().end >= edit.old_end;
+        let next_line = new_src
+            .get(edit.new_end..start)
+            .is_some_and(|between| between.contains('\n'));
+        (gap_after_edit && next_line).then_some((j, start))
+    });
+    let region_end = resync.map_or(new_src.len(), |(_, start)| start);
+    if region_start > edit.start || !new_src.is_char_boundary(region_end) {
+        return from_scratch();
+    }
+
+    let mut parser = match Parser::for_region(new_src, position(new_src, region_start), region_end)
+    {
+        Ok(parser) => parser,
+        Err(_) => return from_scratch(),
+    };
+    let Ok(region) = parser.parse_program() else {
+        return from_scratch();
+    };
+
+    let mut out: Vec<Decl> = decls[..kept].to_vec();
+    out.extend(region.decls);
+    if let Some((j, start)) = resync {
+        let old_span = decls[j].span();
+        let new_pos = position(new_src, start);
+        if new_pos.col != old_span.col {
+            return from_scratch();
+        }
+        let mut shift = Shift {
+            bytes: start as isize - old_span.start as isize,
+            lines: i64::from(new_pos.line) - i64::from(old_span.line),
+        };
+        for decl in &decls[j..] {
+            let Ok(decl) = shift.fold_decl(decl.clone());
+            out.push(decl);
+        }
+    }
+    Ok(Program { decls: out })
+}
+
+/// The empty span at byte `offset` of `src`.
+fn position(src: &str, offset: usize) -> Span {
+    let before = &src[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Span {
+        start: offset,
+        end: offset,
+        line: before.matches('\n').count() as u32 + 1,
+        col: before[line_start..].chars().count() as u32 + 1,
+        node: 0,
+    }
+}
+
+/// Moves every span by a number of bytes and lines; columns stay.
+struct Shift {
+    bytes: isize,
+    lines: i64,
+}
+
+impl Shift {
+    fn span(&self, span: Span) -> Span {
+        if !span.is_known() {
+            return span;
+        }
+        Span {
+            start: span.start.saturating_add_signed(self.bytes),
+            end: span.end.saturating_add_signed(self.bytes),
+            line: u32::try_from(i64::from(span.line) + self.lines).unwrap_or(span.line),
+            ..span
+        }
+    }
+
+    fn path(&self, path: Path) -> Path {
+        Path(path.0, self.span(path.1))
+    }
+}
+
+impl Fold for Shift {
+    type Error = Infallible;
+
+    fn fold_decl(&mut self, decl: Decl) -> Result<Decl, Infallible> {
+        Ok(match fold::walk_decl(self, decl)? {
+            Decl::Import(i) => Decl::Import(ImportDecl {
+                span: self.span(i.span),
+                ..i
+            }),
+            Decl::Type(t) => Decl::Type(TypeDecl {
+                span: self.span(t.span),
+                ..t
+            }),
+            Decl::Trait(t) => Decl::Trait(TraitDecl {
+                methods: t
+                    .methods
+                    .into_iter()
+                    .map(|m| TraitMethod {
+                        span: self.span(m.span),
+                        ..m
+                    })
+                    .collect(),
+                span: self.span(t.span),
+                ..t
+            }),
+            Decl::Impl(i) => Decl::Impl(ImplDecl {
+                span: self.span(i.span),
+                ..i
+            }),
+            Decl::Macro(m) => Decl::Macro(MacroDecl {
+                span: self.span(m.span),
+                ..m
+            }),
+            // spans moved by fold_binding / fold_func
+            other @ (Decl::Global(_) | Decl::Let(_) | Decl::Func(_)) => other,
+        })
+    }
+
+    fn fold_func(&mut self, func: FuncDecl) -> Result<FuncDecl, Infallible> {
+        let func = fold::walk_func(self, func)?;
+        Ok(FuncDecl {
+            span: self.span(func.span),
+            ..func
+        })
+    }
+
+    fn fold_binding(&mut self, binding: Binding) -> Result<Binding, Infallible> {
+        let binding = fold::walk_binding(self, binding)?;
+        Ok(Binding {
+            span: self.span(binding.span),
+            ..binding
+        })
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Result<Stmt, Infallible> {
+        Ok(match fold::walk_stmt(self, stmt)? {
+            Stmt::Assign(a) => Stmt::Assign(Assign {
+                target: self.path(a.target),
+                span: self.span(a.span),
+                ..a
+            }),
+            other => other,
+        })
+    }
+
+    fn fold_block(&mut self, block: Block) -> Result<Block, Infallible> {
+        let block = fold::walk_block(self, block)?;
+        Ok(Block {
+            span: self.span(block.span),
+            ..block
+        })
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Result<Expr, Infallible> {
+        Ok(match fold::walk_expr(self, expr)? {
+            Expr::Literal(lit, span) => Expr::Literal(lit, self.span(span)),
+            Expr::Path(p) => Expr::Path(self.path(p)),
+            Expr::FuncCall(c) => Expr::FuncCall(FuncCall {
+                callee: self.path(c.callee),
+                span: self.span(c.span),
+                ..c
+            }),
+            Expr::If(i) => Expr::If(Box::new(IfExpr {
+                span: self.span(i.span),
+                ..*i
+            })),
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                span: self.span(r.span),
+                ..r
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                span: self.span(u.span),
+                ..u
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                span: self.span(b.span),
+                ..b
+            }),
+            Expr::Index(i) => Expr::Index(IndexExpr {
+                span: self.span(i.span),
+                ..i
+            }),
+            Expr::Slice(s) => Expr::Slice(SliceExpr {
+                span: self.span(s.span),
+                ..s
+            }),
+            // moved by fold_block, or no span of their own
+            other @ (Expr::Block(_)
+            | Expr::Copy(_)
+            | Expr::Ref(_)
+            | Expr::MutRef(_)
+            | Expr::Time(_)) => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::visit::{self, Visit};
+
+    /// Every span in a program, in walk order; `Span`'s `PartialEq` ignores them.
+    #[derive(Default)]
+    struct Spans(Vec<(usize, usize, u32, u32)>);
+
+    impl Spans {
+        fn of(program: &Program) -> Vec<(usize, usize, u32, u32)> {
+            let mut spans = Spans::default();
+            visit::walk_program(&mut spans, program);
+            spans.0
+        }
+
+        fn push(&mut self, s: Span) {
+            self.0.push((s.start, s.end, s.line, s.col));
+        }
+    }
+
+    impl Visit for Spans {
+        fn visit_decl(&mut self, decl: &Decl) {
+            self.push(decl.span());
+            visit::walk_decl(self, decl);
+        }
+
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            self.push(stmt.span());
+            visit::walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.push(expr.span());
+            visit::walk_expr(self, expr);
+        }
+
+        fn visit_path(&mut self, path: &Path) {
+            self.push(path.1);
+        }
+    }
+
+    const SRC: &str = "/// one\n\
+        one() -> i32 = 1\n\
+        two() -> i32 = one() + 1\n\
+        /// three\n\
+        three() = {\n  n: i32 = two()\n  n = n * 3\n  if n > 2 then s[n] else s[1..]\n}\n\
+        four: i32 = 4\n";
+
+    fn full(src: &str) -> Program {
+        Parser::with_doc_comments(src)
+            .unwrap()
+            .parse_program()
+            .unwrap()
+    }
+
+    /// Replace `old` (found once in `src`) by `new`.
+    fn edit(src: &str, old: &str, new: &str) -> (Edit, String) {
+        let start = src.find(old).unwrap();
+        let edit = Edit {
+            start,
+            old_end: start + old.len(),
+            new_end: start + new.len(),
+        };
+        (edit, src.replacen(old, new, 1))
+    }
+
+    #[test]
+    fn reparse_matches_a_full_parse() {
+        let old = full(SRC);
+        for (from, to) in [
+            ("one() + 1", "one()\n  + 100"),
+            ("= 1\n", "= 12345\n"),
+            ("n * 3", "n*3"),
+            ("four: i32 = 4", "four: i32 = 4\nfive() = 5"),
+            ("/// three", "/// drei\n/// trois"),
+        ] {
+            let (edit, new_src) = edit(SRC, from, to);
+            let reparsed = Parser::reparse(&old, &edit, &new_src).unwrap();
+            let expected = full(&new_src);
+            assert_eq!(reparsed, expected, "{from:?} -> {to:?}");
+            assert_eq!(
+                Spans::of(&reparsed),
+                Spans::of(&expected),
+                "{from:?} -> {to:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn declarations_away_from_the_edit_are_reused() {
+        let mut old = full(SRC);
+        // a reused declaration keeps whatever the old tree had
+        for i in [0, 3] {
+            let marker = Expr::Literal(Literal::Int(7), Span::default());
+            match &mut old.decls[i] {
+                Decl::Func(f) => f.body = marker,
+                Decl::Let(b) => b.value = marker,
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        let (edit, new_src) = edit(SRC, "n * 3", "n * 4");
+        let reparsed = Parser::reparse(&old, &edit, &new_src).unwrap();
+        assert_eq!(reparsed.decls[0], old.decls[0]);
+        assert_eq!(reparsed.decls[3], old.decls[3]);
+        assert_eq!(reparsed.decls[2], full(&new_src).decls[2]);
+    }
+
+    #[test]
+    fn a_broken_edit_reports_the_full_parse_error() {
+        let old = full(SRC);
+        let (edit, new_src) = edit(SRC, "one() + 1", "one() +");
+        let err = Parser::reparse(&old, &edit, &new_src).unwrap_err();
+        let expected = Parser::with_doc_comments(&new_src)
+            .unwrap()
+            .parse_program()
+            .unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string());
+    }
+}
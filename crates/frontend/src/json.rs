@@ -0,0 +1,595 @@
+#![forbid(unsafe_code)]
+
+//! `to_json(value)` and `from_json(text)` for any JSON-shaped type. The language has no
+//! generics, so both are resolved during lowering from declared types: for `to_json`, the
+//! argument's binding or parameter type, or else the type the checker gives it; for
+//! `from_json`, the annotation of the binding it initializes or the return type of the
+//! function it is the body of (or an explicit `from_json(T, text)`). Records become calls
+//! to the `@derive(json)` functions, which are derived on demand; integers, `bool` and
+//! `Str` use the `json_*` builtins directly.
+//!
+//! Lowering to ordinary Gaut functions, rather than encoding values in each backend the
+//! way serde does on the Rust side, keeps the interpreter and the C output from drifting
+//! apart: both run the same derived code, and the C runtime needs no reflection.
+
+use crate::ast::*;
+use crate::lower::{call, concat, record_fields, str_lit};
+use crate::typecheck::TypeError;
+use std::collections::{BTreeSet, HashMap};
+
+/// Checked types of `to_json` arguments, keyed by the byte range of the call; see
+/// [`lower_program_with`](crate::lower::lower_program_with).
+pub type JsonArgTypes = HashMap<(usize, usize), Type>;
+
+pub const TO_JSON: &str = "to_json";
+pub const FROM_JSON: &str = "from_json";
+
+/// Rewrite `to_json`/`from_json` calls and add the `json` derive to every record type
+/// they reach. A program that declares its own function of either name keeps calling it.
+/// With `checked`, a `to_json` argument whose type no declaration gives is looked up
+/// there, and the call is left as it is when it is missing.
+pub fn expand_json(
+    program: &Program,
+    checked: Option<&JsonArgTypes>,
+) -> Result<Program, TypeError> {
+    let declared = |name: &str| {
+        program
+            .decls
+            .iter()
+            .any(|d| matches!(d, Decl::Func(f) if f.name.0 == name))
+    };
+    let mut globals = HashMap::new();
+    let mut aliases = HashMap::new();
+    for decl in &program.decls {
+        match decl {
+            Decl::Global(b) | Decl::Let(b) => {
+                globals.insert(b.name.0.clone(), b.ty.clone());
+            }
+            Decl::Type(t) => {
+                aliases.insert(t.name.0.clone(), t.ty.clone());
+            }
+            _ => {}
+        }
+    }
+    let mut rw = Rewriter {
+        aliases: &aliases,
+        scopes: vec![globals],
+        checked,
+        to_json: !declared(TO_JSON),
+        from_json: !declared(FROM_JSON),
+        records: BTreeSet::new(),
+        scalars: BTreeSet::new(),
+        owned: BTreeSet::new(),
+    };
+    if !rw.to_json && !rw.from_json {
+        return Ok(program.clone());
+    }
+
+    let mut decls = Vec::with_capacity(program.decls.len());
+    for decl in &program.decls {
+        decls.push(match decl {
+            Decl::Func(f) => Decl::Func(rw.func(f)?),
+            Decl::Impl(imp) => Decl::Impl(ImplDecl {
+                methods: imp
+                    .methods
+                    .iter()
+                    .map(|m| rw.func(m))
+                    .collect::<Result<_, _>>()?,
+                ..imp.clone()
+            }),
+            Decl::Global(b) => Decl::Global(rw.binding(b)?),
+            Decl::Let(b) => Decl::Let(rw.binding(b)?),
+            other => other.clone(),
+        });
+    }
+
+    // nested records need the derive too, since `T_to_json` calls `Inner_to_json`
+    let mut pending: Vec<String> = rw.records.iter().cloned().collect();
+    let mut derive = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        if !derive.insert(name.clone()) {
+            continue;
+        }
+        for field in record_fields(&type_map(&aliases), &name).unwrap_or_default() {
+            if let Type::Named(n) = &field.ty {
+                if record_fields(&type_map(&aliases), &n.0).is_some() {
+                    pending.push(n.0.clone());
+                }
+            }
+        }
+    }
+    for decl in &mut decls {
+        if let Decl::Type(t) = decl {
+            if derive.contains(&t.name.0) && !t.derives.iter().any(|d| d.0 == "json") {
+                t.derives.push(Ident("json".into()));
+            }
+        }
+    }
+    let generated = rw
+        .scalars
+        .iter()
+        .map(|scalar| scalar_from_json(scalar))
+        .chain(rw.owned.iter().map(|record| owned_to_json(record)));
+    for func in generated.collect::<Vec<_>>() {
+        if decls
+            .iter()
+            .any(|d| matches!(d, Decl::Func(f) if f.name == func.name))
+        {
+            return Err(TypeError::DeriveConflict(func.name.0));
+        }
+        decls.push(Decl::Func(func));
+    }
+    Ok(Program { decls })
+}
+
+fn type_map(aliases: &HashMap<String, Type>) -> HashMap<&str, &Type> {
+    aliases.iter().map(|(n, t)| (n.as_str(), t)).collect()
+}
+
+/// How a JSON-shaped type is encoded.
+enum Shape {
+    Int,
+    Bool,
+    Str,
+    Record(String),
+}
+
+/// `i32_from_json(text: Str) -> i32`: the top-level value is read as the member of a
+/// one-key object, so scalars share the object parser of the `json_get_*` builtins.
+fn scalar_from_json(scalar: &str) -> FuncDecl {
+    let getter = match scalar {
+        "bool" => "json_get_bool",
+        "Str" => "json_get_str",
+        _ => "json_get_int",
+    };
+    let text = Expr::Path(Path(vec![Ident("text".into())], Span::default()));
+    let doc = concat(vec![str_lit("{\"v\":"), text, str_lit("}")]);
+    FuncDecl {
+        name: Ident(format!("{scalar}_from_json")),
+        params: vec![Param {
+            mutable: false,
+            name: Ident("text".into()),
+            ty: Type::Named(Ident("Str".into())),
+        }],
+        ret: Some(Type::Named(Ident(scalar.into()))),
+        body: Expr::Block(Block {
+            stmts: vec![Stmt::Binding(Binding {
+                mutable: false,
+                name: Ident("doc".into()),
+                ty: Type::Named(Ident("Str".into())),
+                value: doc,
+                span: Span::default(),
+            })],
+            tail: Some(Box::new(call(
+                getter,
+                vec![
+                    Expr::Ref(Box::new(Expr::Path(Path(
+                        vec![Ident("doc".into())],
+                        Span::default(),
+                    )))),
+                    str_lit("v"),
+                ],
+            ))),
+            span: Span::default(),
+        }),
+        doc: None,
+        span: Span::default(),
+    }
+}
+
+/// `T_to_json_owned(value: T) -> Str`, for a record argument that is not a place:
+/// `T_to_json` borrows, so the value is bound to a parameter first.
+fn owned_to_json(record: &str) -> FuncDecl {
+    let value = Expr::Path(Path(vec![Ident("value".into())], Span::default()));
+    FuncDecl {
+        name: Ident(format!("{record}_to_json_owned")),
+        params: vec![Param {
+            mutable: false,
+            name: Ident("value".into()),
+            ty: Type::Named(Ident(record.into())),
+        }],
+        ret: Some(Type::Named(Ident("Str".into()))),
+        body: call(
+            &format!("{record}_to_json"),
+            vec![Expr::Ref(Box::new(value))],
+        ),
+        doc: None,
+        span: Span::default(),
+    }
+}
+
+/// Rewrites the calls using the annotated types of bindings and parameters in scope,
+/// collecting the record types and scalars whose functions must be generated.
+struct Rewriter<'a> {
+    aliases: &'a HashMap<String, Type>,
+    scopes: Vec<HashMap<String, Type>>,
+    checked: Option<&'a JsonArgTypes>,
+    to_json: bool,
+    from_json: bool,
+    records: BTreeSet<String>,
+    scalars: BTreeSet<String>,
+    owned: BTreeSet<String>,
+}
+
+impl Rewriter<'_> {
+    fn func(&mut self, f: &FuncDecl) -> Result<FuncDecl, TypeError> {
+        self.scopes.push(
+            f.params
+                .iter()
+                .map(|p| (p.name.0.clone(), p.ty.clone()))
+                .collect(),
+        );
+        let body = self.expr(&f.body, f.ret.as_ref());
+        self.scopes.pop();
+        Ok(FuncDecl {
+            body: body?,
+            ..f.clone()
+        })
+    }
+
+    fn binding(&mut self, b: &Binding) -> Result<Binding, TypeError> {
+        Ok(Binding {
+            value: self.expr(&b.value, Some(&b.ty))?,
+            ..b.clone()
+        })
+    }
+
+    /// `expected` is the declared type `expr` must have, if any: the annotation of the
+    /// binding it initializes or the return type of the function it is the body of.
+    fn expr(&mut self, expr: &Expr, expected: Option<&Type>) -> Result<Expr, TypeError> {
+        Ok(match expr {
+            Expr::Literal(..) | Expr::Path(_) => expr.clone(),
+            Expr::Copy(inner) => Expr::Copy(Box::new(self.expr(inner, None)?)),
+            Expr::Time(body) => Expr::Time(Box::new(self.expr(body, None)?)),
+            Expr::Ref(inner) => Expr::Ref(Box::new(self.expr(inner, None)?)),
+            Expr::MutRef(inner) => Expr::MutRef(Box::new(self.expr(inner, None)?)),
+            Expr::FuncCall(fc) => {
+                let call = FuncCall {
+                    callee: fc.callee.clone(),
+                    args: fc
+                        .args
+                        .iter()
+                        .map(|a| self.expr(a, None))
+                        .collect::<Result<_, _>>()?,
+                    span: fc.span,
+                };
+                self.rewrite(call, expected)?
+            }
+            Expr::If(ife) => Expr::If(Box::new(IfExpr {
+                cond: self.expr(&ife.cond, None)?,
+                then_branch: self.expr(&ife.then_branch, expected)?,
+                else_branch: self.expr(&ife.else_branch, expected)?,
+                span: ife.span,
+            })),
+            Expr::Block(b) => {
+                self.scopes.push(HashMap::new());
+                let block = self.block(b, expected);
+                self.scopes.pop();
+                Expr::Block(block?)
+            }
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                base: match &r.base {
+                    Some(b) => Some(Box::new(self.expr(b, None)?)),
+                    None => None,
+                },
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldInit {
+                            name: f.name.clone(),
+                            value: self.expr(&f.value, None)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                span: r.span,
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                op: u.op.clone(),
+                expr: Box::new(self.expr(&u.expr, None)?),
+                span: u.span,
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(self.expr(&b.left, None)?),
+                right: Box::new(self.expr(&b.right, None)?),
+                span: b.span,
+            }),
+            Expr::Index(ix) => Expr::Index(IndexExpr {
+                base: Box::new(self.expr(&ix.base, None)?),
+                index: Box::new(self.expr(&ix.index, None)?),
+                span: ix.span,
+            }),
+            Expr::Slice(sl) => Expr::Slice(SliceExpr {
+                base: Box::new(self.expr(&sl.base, None)?),
+                start: match &sl.start {
+                    Some(e) => Some(Box::new(self.expr(e, None)?)),
+                    None => None,
+                },
+                end: match &sl.end {
+                    Some(e) => Some(Box::new(self.expr(e, None)?)),
+                    None => None,
+                },
+                span: sl.span,
+            }),
+        })
+    }
+
+    fn block(&mut self, b: &Block, expected: Option<&Type>) -> Result<Block, TypeError> {
+        let mut stmts = Vec::with_capacity(b.stmts.len());
+        for stmt in &b.stmts {
+            stmts.push(match stmt {
+                Stmt::Binding(binding) => {
+                    let binding = self.binding(binding)?;
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(binding.name.0.clone(), binding.ty.clone());
+                    }
+                    Stmt::Binding(binding)
+                }
+                Stmt::Assign(a) => Stmt::Assign(Assign {
+                    target: a.target.clone(),
+                    value: self.expr(&a.value, None)?,
+                    span: a.span,
+                }),
+                Stmt::Expr(e) => Stmt::Expr(self.expr(e, None)?),
+                // already lifted by `lower_program`
+                Stmt::Func(f) => Stmt::Func(f.clone()),
+            });
+        }
+        let tail = match &b.tail {
+            Some(t) => Some(Box::new(self.expr(t, expected)?)),
+            None => None,
+        };
+        Ok(Block {
+            stmts,
+            tail,
+            span: b.span,
+        })
+    }
+
+    fn rewrite(&mut self, call: FuncCall, expected: Option<&Type>) -> Result<Expr, TypeError> {
+        let [name] = call.callee.0.as_slice() else {
+            return Ok(Expr::FuncCall(call));
+        };
+        match name.0.as_str() {
+            TO_JSON if self.to_json => self.encode(call),
+            FROM_JSON if self.from_json => self.decode(call.args, expected, call.span),
+            _ => Ok(Expr::FuncCall(call)),
+        }
+    }
+
+    fn encode(&mut self, fc: FuncCall) -> Result<Expr, TypeError> {
+        let span = fc.span;
+        let [arg] = fc.args.as_slice() else {
+            return Err(TypeError::ArityMismatch {
+                expected: 1,
+                found: fc.args.len(),
+            });
+        };
+        let arg = arg.clone();
+        let place = match &arg {
+            Expr::Path(p) => Some(p.clone()),
+            Expr::Copy(inner) | Expr::Ref(inner) => match inner.as_ref() {
+                Expr::Path(p) => Some(p.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let declared = match (&arg, &place) {
+            (Expr::Literal(Literal::Int(_), _), _) => Some(Type::Named(Ident("i32".into()))),
+            (Expr::Literal(Literal::Bool(_), _), _) => Some(Type::Named(Ident("bool".into()))),
+            (Expr::Literal(Literal::Str(_), _), _) => Some(Type::Named(Ident("Str".into()))),
+            (_, Some(p)) => self.path_type(&p.0),
+            _ => None,
+        };
+        let ty = match (declared, self.checked) {
+            (Some(ty), _) => ty,
+            (None, Some(checked)) if span.is_known() => {
+                match checked.get(&(span.start, span.end)) {
+                    Some(ty) => ty.clone(),
+                    None => return Ok(Expr::FuncCall(fc)),
+                }
+            }
+            (None, _) => {
+                return Err(TypeError::JsonTarget {
+                    builtin: TO_JSON,
+                    what: place.map_or("an expression".into(), |p| path_name(&p)),
+                    span,
+                })
+            }
+        };
+        // `T_to_json` borrows its argument; scalars are read by value
+        let (ty, by_ref) = match ty {
+            Type::Ref(inner) | Type::MutRef(inner) => (*inner, true),
+            ty => (ty, false),
+        };
+        let arg = match (&place, by_ref) {
+            (Some(p), false) if matches!(arg, Expr::Ref(_)) => {
+                Expr::Copy(Box::new(Expr::Path(p.clone())))
+            }
+            _ => arg,
+        };
+        Ok(match self.shape(&ty, span)? {
+            Shape::Record(name) => {
+                self.records.insert(name.clone());
+                let encode = format!("{name}_to_json");
+                match arg {
+                    Expr::Path(p) if !by_ref => {
+                        call(&encode, vec![Expr::Ref(Box::new(Expr::Path(p)))])
+                    }
+                    Expr::Copy(inner) => call(&encode, vec![Expr::Ref(inner)]),
+                    arg if by_ref => call(&encode, vec![arg]),
+                    // a temporary has no place to borrow, so it is passed to a wrapper
+                    arg => {
+                        self.owned.insert(name.clone());
+                        call(&format!("{name}_to_json_owned"), vec![arg])
+                    }
+                }
+            }
+            _ if by_ref => {
+                return Err(TypeError::JsonUnsupported {
+                    ty: Type::Ref(Box::new(ty)),
+                    span,
+                })
+            }
+            Shape::Int => call("int_to_str", vec![arg]),
+            Shape::Bool => Expr::If(Box::new(IfExpr {
+                cond: arg,
+                then_branch: str_lit("true"),
+                else_branch: str_lit("false"),
+                span: Span::default(),
+            })),
+            Shape::Str => call("json_quote", vec![arg]),
+        })
+    }
+
+    fn decode(
+        &mut self,
+        mut args: Vec<Expr>,
+        expected: Option<&Type>,
+        span: Span,
+    ) -> Result<Expr, TypeError> {
+        let unknown = |what: String| TypeError::JsonTarget {
+            builtin: FROM_JSON,
+            what,
+            span,
+        };
+        let ty = match (args.len(), expected) {
+            (2, _) => match args.remove(0) {
+                Expr::Path(Path(segs, _)) if segs.len() == 1 => Type::Named(segs[0].clone()),
+                Expr::Path(p) => return Err(unknown(path_name(&p))),
+                _ => return Err(unknown("an expression".into())),
+            },
+            (1, Some(ty)) => ty.clone(),
+            (1, None) => {
+                return Err(unknown(
+                    "its result outside an annotated binding or function body".into(),
+                ))
+            }
+            (n, _) => {
+                return Err(TypeError::ArityMismatch {
+                    expected: 1,
+                    found: n,
+                })
+            }
+        };
+        let name = match self.shape(&ty, span)? {
+            Shape::Record(name) => {
+                self.records.insert(name.clone());
+                name
+            }
+            _ => {
+                let scalar = ty.to_string();
+                self.scalars.insert(scalar.clone());
+                scalar
+            }
+        };
+        Ok(call(&format!("{name}_from_json"), args))
+    }
+
+    fn shape(&self, ty: &Type, span: Span) -> Result<Shape, TypeError> {
+        match ty {
+            Type::Named(n) if n.0 == "i32" || n.0 == "i64" => Ok(Shape::Int),
+            Type::Named(n) if n.0 == "bool" => Ok(Shape::Bool),
+            Type::Named(n) if n.0 == "Str" => Ok(Shape::Str),
+            Type::Named(n) if record_fields(&type_map(self.aliases), &n.0).is_some() => {
+                Ok(Shape::Record(n.0.clone()))
+            }
+            other => Err(TypeError::JsonUnsupported {
+                ty: other.clone(),
+                span,
+            }),
+        }
+    }
+
+    /// Declared type of `a.b.c`, following record fields through type aliases.
+    fn path_type(&self, segs: &[Ident]) -> Option<Type> {
+        let (head, fields) = segs.split_first()?;
+        let mut ty = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(&head.0))?
+            .clone();
+        let types = type_map(self.aliases);
+        for field in fields {
+            let inner = match &ty {
+                Type::Ref(inner) | Type::MutRef(inner) => inner.as_ref(),
+                other => other,
+            };
+            let record = match inner {
+                Type::Record(fields) => fields.as_slice(),
+                Type::Named(n) => record_fields(&types, &n.0)?,
+                _ => return None,
+            };
+            ty = record.iter().find(|f| &f.name == field)?.ty.clone();
+        }
+        Some(ty)
+    }
+}
+
+fn path_name(p: &Path) -> String {
+    p.0.iter()
+        .map(|s| s.0.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{assert_type_error, parse};
+    use crate::typecheck::TypeChecker;
+
+    #[test]
+    fn calls_resolve_to_derived_functions() {
+        let src = "type P = { x: i32 }\ntype Q = { p: P, ok: bool }\nload(text: Str) -> Q = from_json(text)\nmain() = {\n  q: Q = load(\"{}\")\n  n: i32 = from_json(\"1\")\n  to_json(&q) + to_json(copy q.ok) + to_json(copy n)\n}";
+        let lowered = expand_json(&parse(src).unwrap(), None).unwrap();
+        let derives: Vec<_> = lowered
+            .decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Type(t) => Some(t.derives.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(derives, vec![vec![Ident("json".into())]; 2]);
+        let text = format!("{lowered:?}");
+        for generated in ["Q_from_json", "Q_to_json", "i32_from_json", "int_to_str"] {
+            assert!(text.contains(generated), "{generated} missing");
+        }
+        assert!(!text.contains("\"to_json\"") && !text.contains("\"from_json\""));
+    }
+
+    #[test]
+    fn arguments_take_their_checked_type() {
+        let src = "type P = { x: i32 }\nmk() -> P = { x: 1 }\nmain() = to_json(mk()) + to_json(int_to_str(2))";
+        let program = parse(src).unwrap();
+        let typed = TypeChecker::new().check_program(&program).unwrap();
+        let text = format!("{:?}", typed.program());
+        assert!(text.contains("P_to_json") && text.contains("json_quote"));
+        assert!(!text.contains("\"to_json\""));
+        let err = expand_json(&program, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "to_json cannot tell the JSON type of an expression; pass a variable, field or literal, or type-check the program so its type is known"
+        );
+    }
+
+    #[test]
+    fn untyped_arguments_are_rejected() {
+        assert_type_error(
+            "main() = str_len(from_json(\"1\"))",
+            "from_json cannot tell the JSON type of its result outside an annotated binding",
+        );
+        assert_type_error(
+            "main() = {\n  b: Bytes = bytes_new()\n  to_json(&b)\n}",
+            "Bytes has no JSON form",
+        );
+        let mut checker = TypeChecker::new();
+        let src = "mk() -> Bytes = bytes_from_str(\"\")\nmain() = str_len(to_json(mk()))";
+        assert!(checker.check_program(&parse(src).unwrap()).is_err());
+        let span = checker.error_span().unwrap();
+        assert_eq!((span.line, span.col), (2, 18));
+    }
+}
@@ -0,0 +1,569 @@
+#![forbid(unsafe_code)]
+
+//! Tokenizer. [`Lexer`] yields tokens one at a time with their source spans, so
+//! highlighters and other tools can tokenize without parsing.
+
+use crate::ast::Span;
+use crate::parser::ParserError;
+use std::iter::Peekable;
+use std::str::Chars;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    /// The text after `//`, up to the end of the line. Only produced by
+    /// [`Lexer::with_comments`].
+    Comment(String),
+
+    KwImport,
+    KwGlobal,
+    KwMut,
+    KwType,
+    KwIf,
+    KwThen,
+    KwElse,
+    KwCopy,
+    KwTrait,
+    KwImpl,
+
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Colon,
+    Comma,
+    Dot,
+    DotDot,
+    Assign,
+    Arrow,
+    Amp,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    EqEq,
+    BangEq,
+    AndAnd,
+    OrOr,
+    Bang,
+    Pipe,
+    Caret,
+    Shl,
+    Shr,
+    At,
+
+    Eof,
+}
+
+/// A token and the source it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// Streaming tokenizer over a source string. It yields [`Token::Eof`], with an empty
+/// span at the end of the input, as its last token; after an error it yields nothing.
+pub struct Lexer<'a> {
+    chars: Cursor<'a>,
+    comments: bool,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    /// A lexer that drops comments.
+    pub fn new(src: &'a str) -> Self {
+        Self {
+            chars: Cursor::new(src),
+            comments: false,
+            done: false,
+        }
+    }
+
+    /// A lexer that yields each `//` comment as a [`Token::Comment`].
+    pub fn with_comments(src: &'a str) -> Self {
+        Self {
+            comments: true,
+            ..Self::new(src)
+        }
+    }
+
+    /// A comment-yielding lexer over `src` from `at.start` on, which must be a token
+    /// boundary; `at` gives that position's line and column. Used to re-lex one region
+    /// of an edited file.
+    pub(crate) fn resume(src: &'a str, at: Span) -> Self {
+        Self {
+            chars: Cursor {
+                chars: src[at.start..].chars().peekable(),
+                offset: at.start,
+                line: at.line,
+                col: at.col,
+            },
+            comments: true,
+            done: false,
+        }
+    }
+
+    /// The next token starting at `start`, or `None` for whitespace and dropped comments.
+    fn token(&mut self, ch: char, start: Span) -> Result<Option<Token>, ParserError> {
+        let comments = self.comments;
+        let chars = &mut self.chars;
+        Ok(match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+                None
+            }
+            '/' => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    let mut line = String::new();
+                    while let Some(c) = chars.next_if(|&c| c != '\n') {
+                        line.push(c);
+                    }
+                    comments.then_some(Token::Comment(line))
+                } else {
+                    Some(Token::Slash)
+                }
+            }
+            '{' => {
+                chars.next();
+                Some(Token::LBrace)
+            }
+            '}' => {
+                chars.next();
+                Some(Token::RBrace)
+            }
+            '(' => {
+                chars.next();
+                Some(Token::LParen)
+            }
+            '[' => {
+                chars.next();
+                Some(Token::LBracket)
+            }
+            ']' => {
+                chars.next();
+                Some(Token::RBracket)
+            }
+            ')' => {
+                chars.next();
+                Some(Token::RParen)
+            }
+            ':' => {
+                chars.next();
+                Some(Token::Colon)
+            }
+            ',' => {
+                chars.next();
+                Some(Token::Comma)
+            }
+            '.' => {
+                chars.next();
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    Some(Token::DotDot)
+                } else {
+                    Some(Token::Dot)
+                }
+            }
+            '+' => {
+                chars.next();
+                Some(Token::Plus)
+            }
+            '*' => {
+                chars.next();
+                Some(Token::Star)
+            }
+            '<' => {
+                chars.next();
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Some(Token::LtEq)
+                } else if chars.peek() == Some(&'<') {
+                    chars.next();
+                    Some(Token::Shl)
+                } else {
+                    Some(Token::Lt)
+                }
+            }
+            '>' => {
+                chars.next();
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Some(Token::GtEq)
+                } else if chars.peek() == Some(&'>') {
+                    chars.next();
+                    Some(Token::Shr)
+                } else {
+                    Some(Token::Gt)
+                }
+            }
+            '!' => {
+                chars.next();
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Some(Token::BangEq)
+                } else {
+                    Some(Token::Bang)
+                }
+            }
+            '=' => {
+                chars.next();
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Some(Token::EqEq)
+                } else if chars.peek() == Some(&'>') {
+                    // not in grammar, ignore
+                    None
+                } else {
+                    Some(Token::Assign)
+                }
+            }
+            '-' => {
+                chars.next();
+                if chars.peek() == Some(&'>') {
+                    chars.next();
+                    Some(Token::Arrow)
+                } else {
+                    Some(Token::Minus)
+                }
+            }
+            '|' => {
+                chars.next();
+                if chars.peek() == Some(&'|') {
+                    chars.next();
+                    Some(Token::OrOr)
+                } else {
+                    Some(Token::Pipe)
+                }
+            }
+            '^' => {
+                chars.next();
+                Some(Token::Caret)
+            }
+            '@' => {
+                chars.next();
+                Some(Token::At)
+            }
+            '&' => {
+                chars.next();
+                if chars.peek() == Some(&'&') {
+                    chars.next();
+                    Some(Token::AndAnd)
+                } else {
+                    Some(Token::Amp)
+                }
+            }
+            '"' if src_ahead(chars, "\"\"\"") => {
+                for _ in 0..3 {
+                    chars.next();
+                }
+                // a newline right after the opening quotes is not part of the text
+                if chars.peek() == Some(&'\r') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                Some(Token::Str(lex_raw(chars, "\"\"\"")?))
+            }
+            'r' if src_ahead(chars, "r\"") => {
+                chars.next();
+                chars.next();
+                Some(Token::Str(lex_raw(chars, "\"")?))
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        closed = true;
+                        break;
+                    }
+                    if c == '\\' {
+                        let Some(esc) = chars.next() else {
+                            return Err(ParserError::Lexer(
+                                "unterminated string escape".into(),
+                                start,
+                            ));
+                        };
+                        match esc {
+                            'n' => s.push('\n'),
+                            't' => s.push('\t'),
+                            'r' => s.push('\r'),
+                            '"' => s.push('"'),
+                            '\\' => s.push('\\'),
+                            other => s.push(other),
+                        }
+                        continue;
+                    }
+                    s.push(c);
+                }
+                if !closed {
+                    return Err(ParserError::Lexer(
+                        "unterminated string literal".into(),
+                        start,
+                    ));
+                }
+                Some(Token::Str(s))
+            }
+            '0'..='9' => {
+                let mut num = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() {
+                        num.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let val: i64 = num
+                    .parse()
+                    .map_err(|_| ParserError::InvalidNumber(num.clone(), start))?;
+                Some(Token::Int(val))
+            }
+            c if is_ident_start(c) => {
+                let mut ident = String::new();
+                ident.push(c);
+                chars.next();
+                while let Some(&c2) = chars.peek() {
+                    if is_ident_continue(c2) {
+                        ident.push(c2);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                // `é` typed as one code point or as `e` plus a combining accent is the
+                // same name
+                if !ident.is_ascii() {
+                    ident = ident.nfc().collect();
+                }
+                let tok = match ident.as_str() {
+                    "import" => Token::KwImport,
+                    "global" => Token::KwGlobal,
+                    "mut" => Token::KwMut,
+                    "type" => Token::KwType,
+                    "if" => Token::KwIf,
+                    "then" => Token::KwThen,
+                    "else" => Token::KwElse,
+                    "copy" => Token::KwCopy,
+                    "trait" => Token::KwTrait,
+                    "impl" => Token::KwImpl,
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => Token::Ident(ident),
+                };
+                Some(tok)
+            }
+            c => {
+                return Err(ParserError::Lexer(
+                    format!("unexpected char '{}'", c),
+                    start,
+                ))
+            }
+        })
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<SpannedToken, ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let start = self.chars.here();
+            let Some(&ch) = self.chars.peek() else {
+                self.done = true;
+                return Some(Ok(SpannedToken {
+                    token: Token::Eof,
+                    span: start,
+                }));
+            };
+            match self.token(ch, start) {
+                Ok(Some(token)) => {
+                    let span = Span {
+                        end: self.chars.offset,
+                        ..start
+                    };
+                    return Some(Ok(SpannedToken { token, span }));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+/// The unread source and where it starts, so each token gets a [`Span`].
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+    line: u32,
+    col: u32,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            chars: src.chars().peekable(),
+            offset: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn next_if(&mut self, f: impl FnOnce(&char) -> bool) -> Option<char> {
+        if f(self.chars.peek()?) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    /// An empty span at the next character.
+    fn here(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset,
+            line: self.line,
+            col: self.col,
+            node: 0,
+        }
+    }
+}
+
+/// Whether the unread input starts with `s`.
+fn src_ahead(chars: &Cursor<'_>, s: &str) -> bool {
+    let mut ahead = chars.clone();
+    s.chars().all(|c| ahead.next() == Some(c))
+}
+
+/// The text of a raw string up to `close`, taken verbatim: backslashes and newlines
+/// are kept as written.
+fn lex_raw(chars: &mut Cursor<'_>, close: &str) -> Result<String, ParserError> {
+    let start = chars.here();
+    let mut s = String::new();
+    loop {
+        if src_ahead(chars, close) {
+            for _ in 0..close.len() {
+                chars.next();
+            }
+            return Ok(s);
+        }
+        let Some(c) = chars.next() else {
+            return Err(ParserError::Lexer(
+                "unterminated raw string literal".into(),
+                start,
+            ));
+        };
+        s.push(c);
+    }
+}
+
+/// Identifiers follow Unicode's default syntax (UAX #31): an `XID_Start` character or
+/// `_`, then `XID_Continue` characters.
+fn is_ident_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+fn is_ident_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lexer_streams_spanned_tokens_and_comments() {
+        let src = "x // note\n/// doc\ny >= 1";
+        let tokens: Vec<(Token, u32, u32)> = Lexer::with_comments(src)
+            .map(|t| t.map(|t| (t.token, t.span.line, t.span.col)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            [
+                (Token::Ident("x".into()), 1, 1),
+                (Token::Comment(" note".into()), 1, 3),
+                (Token::Comment("/ doc".into()), 2, 1),
+                (Token::Ident("y".into()), 3, 1),
+                (Token::GtEq, 3, 3),
+                (Token::Int(1), 3, 6),
+                (Token::Eof, 3, 7),
+            ]
+        );
+        assert_eq!(Lexer::new(src).count(), 5);
+    }
+
+    #[test]
+    fn identifiers_are_unicode_and_nfc_normalized() {
+        let idents: Vec<Token> = Lexer::new("naïve 名前 _x1 cafe\u{301} café")
+            .map(|t| t.unwrap().token)
+            .collect();
+        let ident = |s: &str| Token::Ident(s.into());
+        assert_eq!(
+            idents,
+            [
+                ident("naïve"),
+                ident("名前"),
+                ident("_x1"),
+                ident("café"),
+                ident("café"),
+                Token::Eof
+            ]
+        );
+        // symbols and combining marks cannot start a name
+        assert!(Lexer::new("→").next().unwrap().is_err());
+        assert!(Lexer::new("\u{301}a").next().unwrap().is_err());
+    }
+
+    #[test]
+    fn lexer_stops_after_an_error() {
+        let mut lexer = Lexer::new("a $ b");
+        assert!(matches!(
+            lexer.next(),
+            Some(Ok(SpannedToken {
+                token: Token::Ident(_),
+                ..
+            }))
+        ));
+        let err = lexer.next().unwrap().unwrap_err();
+        assert_eq!(err.span().map(|s| s.col), Some(3));
+        assert!(lexer.next().is_none());
+    }
+}
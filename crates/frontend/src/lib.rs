@@ -1,5 +1,21 @@
 #![forbid(unsafe_code)]
 
 pub mod ast;
+pub mod consteval;
+pub mod diagnostic;
+pub mod incremental;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod lower;
+pub mod macros;
+pub mod modules;
+pub mod names;
 pub mod parser;
+pub mod printer;
+pub mod reach;
+pub mod target;
+pub mod testutil;
+pub mod traits;
 pub mod typecheck;
+pub mod typed;
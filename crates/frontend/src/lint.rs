@@ -0,0 +1,169 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Every warning the toolchain reports, named as in `-W`/`-D`/`-A` and `[lints]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedImport,
+    DuplicateImport,
+    /// A block binding that is never mentioned again.
+    UnusedVariable,
+    /// A top-level function of the entry module that nothing calls or names.
+    UnusedFunction,
+    /// Statements after one that ends the program.
+    UnreachableCode,
+    /// A block binding with the name of a global, a parameter or an outer binding.
+    /// Allowed unless enabled, since shadowing is often deliberate.
+    Shadowing,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UnusedImport,
+        Lint::DuplicateImport,
+        Lint::UnusedVariable,
+        Lint::UnusedFunction,
+        Lint::UnreachableCode,
+        Lint::Shadowing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedImport => "unused_import",
+            Lint::DuplicateImport => "duplicate_import",
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedFunction => "unused_function",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::Shadowing => "shadowing",
+        }
+    }
+
+    /// The level of a lint nobody configured.
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Lint::Shadowing => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What happens when a lint fires.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    #[default]
+    Warn,
+    Deny,
+}
+
+impl FromStr for LintLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LintLevel::Allow),
+            "warn" => Ok(LintLevel::Warn),
+            "deny" => Ok(LintLevel::Deny),
+            other => Err(format!(
+                "unknown lint level '{other}' (expected allow, warn or deny)"
+            )),
+        }
+    }
+}
+
+/// One reported problem that does not stop compilation on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    /// Unknown (`line == 0`) for warnings about a whole file, such as its imports.
+    pub span: Span,
+}
+
+impl Warning {
+    pub fn new(lint: Lint, message: impl Into<String>) -> Self {
+        Self {
+            lint,
+            message: message.into(),
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.lint)
+    }
+}
+
+/// Per-lint levels; unconfigured lints are at their [`Lint::default_level`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: HashMap<Lint, LintLevel>,
+}
+
+impl LintConfig {
+    /// Set the level of the lint called `name`, or of every lint for `warnings`.
+    /// Later calls override earlier ones, so apply the manifest before flags.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == "warnings" {
+            for lint in Lint::ALL {
+                self.levels.insert(lint, level);
+            }
+            return Ok(());
+        }
+        let lint = Lint::ALL
+            .into_iter()
+            .find(|l| l.name() == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Lint::ALL.iter().map(|l| l.name()).collect();
+                format!(
+                    "unknown lint '{name}' (expected warnings, {})",
+                    known.join(", ")
+                )
+            })?;
+        self.levels.insert(lint, level);
+        Ok(())
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels
+            .get(&lint)
+            .copied()
+            .unwrap_or(lint.default_level())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_settings_override_earlier_ones() {
+        let mut lints = LintConfig::default();
+        assert_eq!(lints.level(Lint::UnusedImport), LintLevel::Warn);
+        assert_eq!(lints.level(Lint::Shadowing), LintLevel::Allow);
+        lints.set("warnings", LintLevel::Deny).unwrap();
+        lints.set("unused_import", LintLevel::Allow).unwrap();
+        assert_eq!(lints.level(Lint::UnusedImport), LintLevel::Allow);
+        assert_eq!(lints.level(Lint::DuplicateImport), LintLevel::Deny);
+        assert_eq!(lints.level(Lint::Shadowing), LintLevel::Deny);
+        assert!(lints.set("nope", LintLevel::Warn).is_err());
+        assert!("forbid".parse::<LintLevel>().is_err());
+    }
+}
@@ -0,0 +1,1000 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::*;
+use crate::json::{expand_json, JsonArgTypes};
+use crate::macros::expand_macros;
+use crate::names::{bound_names, expr_referenced_names};
+use crate::traits::lower_traits;
+use crate::typecheck::TypeError;
+use std::collections::{HashMap, HashSet};
+
+/// Desugar what the checker and backends do not handle directly: macros are
+/// expanded, block-local functions are lifted to the top level, `to_json`/`from_json` are
+/// resolved to derived functions, derives generate functions, reflection
+/// builtins become literals, trait code becomes plain functions, and globals are put in
+/// initialization order.
+pub fn lower_program(program: &Program) -> Result<Program, TypeError> {
+    lower_program_with(program, None)
+}
+
+/// [`lower_program`] with the checked types of `to_json` arguments that declarations do
+/// not give; a call whose argument is in neither is left for the checker to type.
+pub fn lower_program_with(
+    program: &Program,
+    json_args: Option<&JsonArgTypes>,
+) -> Result<Program, TypeError> {
+    let program = lift_local_functions(&expand_macros(program)?)?;
+    let program = expand_derives(&expand_json(&program, json_args)?)?;
+    order_globals(&lower_traits(&expand_reflection(&program)?)?)
+}
+
+/// Reorder top-level bindings so each initializer runs after the globals it reads,
+/// directly or through the functions it calls. Bindings keep their slots among the other
+/// declarations and their source order where there is no dependency; a cycle is an error
+/// naming its path. Every backend initializes globals in the resulting order.
+pub fn order_globals(program: &Program) -> Result<Program, TypeError> {
+    let bindings: Vec<&Binding> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Global(b) | Decl::Let(b) => Some(b),
+            _ => None,
+        })
+        .collect();
+    let index: HashMap<&str, usize> = bindings
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.name.0.as_str(), i))
+        .collect();
+    let funcs: HashMap<&str, &FuncDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Func(f) => Some((f.name.0.as_str(), f)),
+            _ => None,
+        })
+        .collect();
+
+    // the globals an initializer reads, following calls through every reachable function
+    let global_deps = |value: &Expr| -> Vec<usize> {
+        let free = |expr: &Expr, params: &[Param]| -> Vec<String> {
+            let bound = bound_names(expr);
+            let mut names: Vec<String> = expr_referenced_names(expr)
+                .into_iter()
+                .filter(|n| !bound.contains(n) && !params.iter().any(|p| p.name.0 == *n))
+                .collect();
+            names.sort();
+            names
+        };
+        let mut deps = Vec::new();
+        let mut seen_funcs = HashSet::new();
+        let mut pending = free(value, &[]);
+        while let Some(name) = pending.pop() {
+            if let Some(&i) = index.get(name.as_str()) {
+                if !deps.contains(&i) {
+                    deps.push(i);
+                }
+            } else if let Some(f) = funcs.get(name.as_str()) {
+                if seen_funcs.insert(f.name.0.as_str()) {
+                    pending.extend(free(&f.body, &f.params));
+                }
+            }
+        }
+        deps.sort();
+        deps
+    };
+
+    fn visit(
+        i: usize,
+        bindings: &[&Binding],
+        deps: &[Vec<usize>],
+        state: &mut [u8], // 0 = new, 1 = on the current path, 2 = done
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), TypeError> {
+        match state[i] {
+            2 => return Ok(()),
+            1 => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let mut names: Vec<&str> = path[start..]
+                    .iter()
+                    .map(|&p| bindings[p].name.0.as_str())
+                    .collect();
+                names.push(bindings[i].name.0.as_str());
+                return Err(TypeError::GlobalCycle(names.join(" -> ")));
+            }
+            _ => {}
+        }
+        state[i] = 1;
+        path.push(i);
+        for &dep in &deps[i] {
+            visit(dep, bindings, deps, state, path, order)?;
+        }
+        path.pop();
+        state[i] = 2;
+        order.push(i);
+        Ok(())
+    }
+
+    let deps: Vec<Vec<usize>> = bindings.iter().map(|b| global_deps(&b.value)).collect();
+    let mut state = vec![0u8; bindings.len()];
+    let mut order = Vec::with_capacity(bindings.len());
+    for i in 0..bindings.len() {
+        visit(i, &bindings, &deps, &mut state, &mut Vec::new(), &mut order)?;
+    }
+
+    let slots: Vec<&Decl> = program
+        .decls
+        .iter()
+        .filter(|d| matches!(d, Decl::Global(_) | Decl::Let(_)))
+        .collect();
+    let mut ordered = order.into_iter().map(|i| slots[i]);
+    let decls = program
+        .decls
+        .iter()
+        .map(|d| match d {
+            Decl::Global(_) | Decl::Let(_) => ordered.next().unwrap_or(d).clone(),
+            other => other.clone(),
+        })
+        .collect();
+    Ok(Program { decls })
+}
+
+/// Generate the functions `@derive(...)` asks for. `@derive(show) type Point` adds
+/// `Point_show(v: &Point) -> Str`, rendering `Point { x: 1, label: "a" }`.
+/// `@derive(json)` adds `Point_to_json(v: &Point) -> Str` and
+/// `Point_from_json(text: Str) -> Point` over the `json_*` builtins. Fields may be
+/// integers, `bool`, `Str` or record types with the same derive.
+pub fn expand_derives(program: &Program) -> Result<Program, TypeError> {
+    let derived: Vec<&TypeDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Type(t) if !t.derives.is_empty() => Some(t),
+            _ => None,
+        })
+        .collect();
+    if derived.is_empty() {
+        return Ok(program.clone());
+    }
+    let mut taken: HashSet<String> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Func(f) => Some(f.name.0.clone()),
+            _ => None,
+        })
+        .collect();
+    let types: HashMap<&str, &TypeDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Type(t) => Some((t.name.0.as_str(), t)),
+            _ => None,
+        })
+        .collect();
+    let mut decls = program.decls.clone();
+    for t in derived {
+        for derive in &t.derives {
+            let funcs = match derive.0.as_str() {
+                "show" => vec![derive_show(t, &types)?],
+                "json" => derive_json(t, &types)?,
+                other => return Err(TypeError::UnknownDerive(other.to_string())),
+            };
+            for f in funcs {
+                if !taken.insert(f.name.0.clone()) {
+                    return Err(TypeError::DeriveConflict(f.name.0));
+                }
+                decls.push(Decl::Func(f));
+            }
+        }
+    }
+    Ok(Program { decls })
+}
+
+/// How a derive renders one field.
+enum FieldKind {
+    Int,
+    Bool,
+    Str,
+    /// A record type that carries the same derive.
+    Derived(String),
+}
+
+/// The fields of derived type `t`, classified; anything else is `DeriveUnsupported`.
+fn derive_fields(
+    t: &TypeDecl,
+    derive: &str,
+    types: &HashMap<&str, &TypeDecl>,
+) -> Result<Vec<(Ident, FieldKind)>, TypeError> {
+    let unsupported = |field: &str, field_ty: &Type| TypeError::DeriveUnsupported {
+        derive: derive.into(),
+        ty: t.name.0.clone(),
+        field: field.into(),
+        field_ty: field_ty.clone(),
+    };
+    let Some(fields) = record_fields(&types.iter().map(|(n, t)| (*n, &t.ty)).collect(), &t.name.0)
+    else {
+        return Err(unsupported("(not a record)", &t.ty));
+    };
+    fields
+        .iter()
+        .map(|f| {
+            let kind = match &f.ty {
+                Type::Named(n) if n.0 == "i32" || n.0 == "i64" => FieldKind::Int,
+                Type::Named(n) if n.0 == "bool" => FieldKind::Bool,
+                Type::Named(n) if n.0 == "Str" => FieldKind::Str,
+                Type::Named(n)
+                    if types
+                        .get(n.0.as_str())
+                        .is_some_and(|inner| inner.derives.iter().any(|d| d.0 == derive)) =>
+                {
+                    FieldKind::Derived(n.0.clone())
+                }
+                other => return Err(unsupported(&f.name.0, other)),
+            };
+            Ok((f.name.clone(), kind))
+        })
+        .collect()
+}
+
+pub(crate) fn str_lit(s: &str) -> Expr {
+    Expr::Literal(Literal::Str(s.to_string()), Span::default())
+}
+
+pub(crate) fn call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::FuncCall(FuncCall {
+        callee: Path(vec![Ident(name.to_string())], Span::default()),
+        args,
+        span: Span::default(),
+    })
+}
+
+/// `a + b + ...` over string parts.
+pub(crate) fn concat(parts: Vec<Expr>) -> Expr {
+    parts
+        .into_iter()
+        .reduce(|acc, part| {
+            Expr::Binary(BinaryExpr {
+                left: Box::new(acc),
+                op: BinaryOp::Add,
+                right: Box::new(part),
+                span: Span::default(),
+            })
+        })
+        .unwrap_or_else(|| str_lit(""))
+}
+
+/// `v.field` of the `v: &T` parameter the generated renderers take.
+fn field_of_v(field: &Ident) -> Path {
+    Path(vec![Ident("v".into()), field.clone()], Span::default())
+}
+
+fn ref_param(t: &TypeDecl) -> Param {
+    Param {
+        mutable: false,
+        name: Ident("v".into()),
+        ty: Type::Ref(Box::new(Type::Named(t.name.clone()))),
+    }
+}
+
+fn derive_show(t: &TypeDecl, types: &HashMap<&str, &TypeDecl>) -> Result<FuncDecl, TypeError> {
+    let fields = derive_fields(t, "show", types)?;
+    // shown under its source name, without the `module__` prefix of imported types
+    let shown = t.name.0.rsplit("__").next().unwrap_or(&t.name.0);
+    let mut parts = vec![str_lit(&format!("{shown} {{"))];
+    for (i, (name, kind)) in fields.iter().enumerate() {
+        let sep = if i == 0 { " " } else { ", " };
+        parts.push(str_lit(&format!("{sep}{}: ", name.0)));
+        let path = field_of_v(name);
+        let copied = Expr::Copy(Box::new(Expr::Path(path.clone())));
+        match kind {
+            FieldKind::Int => parts.push(call("int_to_str", vec![copied])),
+            FieldKind::Bool => parts.push(Expr::If(Box::new(IfExpr {
+                cond: copied,
+                then_branch: str_lit("true"),
+                else_branch: str_lit("false"),
+                span: Span::default(),
+            }))),
+            FieldKind::Str => {
+                parts.push(str_lit("\""));
+                parts.push(copied);
+                parts.push(str_lit("\""));
+            }
+            FieldKind::Derived(inner) => parts.push(call(
+                &format!("{inner}_show"),
+                vec![Expr::Ref(Box::new(Expr::Path(path)))],
+            )),
+        }
+    }
+    parts.push(str_lit(if fields.is_empty() { "}" } else { " }" }));
+    Ok(FuncDecl {
+        name: Ident(format!("{}_show", t.name.0)),
+        params: vec![ref_param(t)],
+        ret: Some(Type::Named(Ident("Str".into()))),
+        body: concat(parts),
+        doc: None,
+        span: t.span,
+    })
+}
+
+/// `T_to_json` writes the fields in declaration order; `T_from_json` looks each one
+/// up by name, so key order and extra keys in the input do not matter.
+fn derive_json(t: &TypeDecl, types: &HashMap<&str, &TypeDecl>) -> Result<Vec<FuncDecl>, TypeError> {
+    let fields = derive_fields(t, "json", types)?;
+    let mut parts = Vec::new();
+    let mut inits = Vec::new();
+    for (i, (name, kind)) in fields.iter().enumerate() {
+        let sep = if i == 0 { "{" } else { "," };
+        parts.push(str_lit(&format!("{sep}\"{}\":", name.0)));
+        let path = field_of_v(name);
+        let copied = Expr::Copy(Box::new(Expr::Path(path.clone())));
+        let text = Expr::Ref(Box::new(Expr::Path(Path(
+            vec![Ident("text".into())],
+            Span::default(),
+        ))));
+        let key = str_lit(&name.0);
+        let (rendered, value) = match kind {
+            FieldKind::Int => (
+                call("int_to_str", vec![copied]),
+                call("json_get_int", vec![text, key]),
+            ),
+            FieldKind::Bool => (
+                Expr::If(Box::new(IfExpr {
+                    cond: copied,
+                    then_branch: str_lit("true"),
+                    else_branch: str_lit("false"),
+                    span: Span::default(),
+                })),
+                call("json_get_bool", vec![text, key]),
+            ),
+            FieldKind::Str => (
+                call("json_quote", vec![copied]),
+                call("json_get_str", vec![text, key]),
+            ),
+            FieldKind::Derived(inner) => (
+                call(
+                    &format!("{inner}_to_json"),
+                    vec![Expr::Ref(Box::new(Expr::Path(path)))],
+                ),
+                call(
+                    &format!("{inner}_from_json"),
+                    vec![call("json_get_raw", vec![text, key])],
+                ),
+            ),
+        };
+        parts.push(rendered);
+        inits.push(FieldInit {
+            name: name.clone(),
+            value,
+        });
+    }
+    parts.push(str_lit(if fields.is_empty() { "{}" } else { "}" }));
+    let to_json = FuncDecl {
+        name: Ident(format!("{}_to_json", t.name.0)),
+        params: vec![ref_param(t)],
+        ret: Some(Type::Named(Ident("Str".into()))),
+        body: concat(parts),
+        doc: None,
+        span: t.span,
+    };
+    let from_json = FuncDecl {
+        name: Ident(format!("{}_from_json", t.name.0)),
+        params: vec![Param {
+            mutable: false,
+            name: Ident("text".into()),
+            ty: Type::Named(Ident("Str".into())),
+        }],
+        ret: Some(Type::Named(t.name.clone())),
+        body: Expr::RecordLit(RecordLit {
+            base: None,
+            fields: inits,
+            span: Span::default(),
+        }),
+        doc: None,
+        span: t.span,
+    };
+    Ok(vec![to_json, from_json])
+}
+
+/// Compile-time reflection builtin: `type_fields(T)` is the field list of record type
+/// `T` as a string in declaration order, `"name: Str, age: i32"`.
+pub const TYPE_FIELDS: &str = "type_fields";
+
+/// Replace every `type_fields(T)` call with its string literal. A program that
+/// declares its own `type_fields` function keeps calling it.
+pub fn expand_reflection(program: &Program) -> Result<Program, TypeError> {
+    let mut types = HashMap::new();
+    for decl in &program.decls {
+        match decl {
+            Decl::Type(t) => {
+                types.insert(t.name.0.as_str(), &t.ty);
+            }
+            Decl::Func(f) if f.name.0 == TYPE_FIELDS => return Ok(program.clone()),
+            _ => {}
+        }
+    }
+    let mut expand = |call: &FuncCall| -> Result<Option<Expr>, TypeError> {
+        if call.callee.0.len() != 1 || call.callee.0[0].0 != TYPE_FIELDS {
+            return Ok(None);
+        }
+        let fields = match call.args.as_slice() {
+            [Expr::Path(Path(segs, _))] if segs.len() == 1 => record_fields(&types, &segs[0].0),
+            _ => None,
+        };
+        let Some(fields) = fields else {
+            let found = match call.args.as_slice() {
+                [Expr::Path(p)] => {
+                    p.0.iter()
+                        .map(|s| s.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join(".")
+                }
+                [_] => "an expression".to_string(),
+                args => format!("{} arguments", args.len()),
+            };
+            return Err(TypeError::ReflectionTarget(found));
+        };
+        let desc: Vec<String> = fields
+            .iter()
+            .map(|f| format!("{}: {}", f.name.0, f.ty))
+            .collect();
+        Ok(Some(Expr::Literal(
+            Literal::Str(desc.join(", ")),
+            call.span,
+        )))
+    };
+    let decls = program
+        .decls
+        .iter()
+        .map(|decl| {
+            Ok(match decl {
+                Decl::Func(f) => Decl::Func(FuncDecl {
+                    body: map_calls(&f.body, &mut expand)?,
+                    ..f.clone()
+                }),
+                Decl::Impl(imp) => Decl::Impl(ImplDecl {
+                    methods: imp
+                        .methods
+                        .iter()
+                        .map(|m| {
+                            Ok(FuncDecl {
+                                body: map_calls(&m.body, &mut expand)?,
+                                ..m.clone()
+                            })
+                        })
+                        .collect::<Result<_, TypeError>>()?,
+                    ..imp.clone()
+                }),
+                Decl::Global(b) => Decl::Global(Binding {
+                    value: map_calls(&b.value, &mut expand)?,
+                    ..b.clone()
+                }),
+                Decl::Let(b) => Decl::Let(Binding {
+                    value: map_calls(&b.value, &mut expand)?,
+                    ..b.clone()
+                }),
+                Decl::Import(_) | Decl::Type(_) | Decl::Trait(_) | Decl::Macro(_) => decl.clone(),
+            })
+        })
+        .collect::<Result<_, TypeError>>()?;
+    Ok(Program { decls })
+}
+
+/// Fields of the record type named `name`, following aliases.
+pub(crate) fn record_fields<'a>(
+    types: &HashMap<&str, &'a Type>,
+    name: &str,
+) -> Option<&'a [FieldType]> {
+    let mut ty = *types.get(name)?;
+    // bounded so alias cycles cannot loop
+    for _ in 0..=types.len() {
+        match ty {
+            Type::Record(fields) => return Some(fields),
+            Type::Named(next) => ty = types.get(next.0.as_str())?,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Rebuild `expr` with every call for which `f` returns a replacement swapped out.
+/// Arguments are rewritten first; bodies of block-local functions are rewritten too.
+pub fn map_calls(
+    expr: &Expr,
+    f: &mut impl FnMut(&FuncCall) -> Result<Option<Expr>, TypeError>,
+) -> Result<Expr, TypeError> {
+    struct Calls<'f, F>(&'f mut F);
+
+    impl<F: FnMut(&FuncCall) -> Result<Option<Expr>, TypeError>> Fold for Calls<'_, F> {
+        type Error = TypeError;
+
+        fn fold_expr(&mut self, expr: Expr) -> Result<Expr, TypeError> {
+            match fold::walk_expr(self, expr)? {
+                Expr::FuncCall(call) => Ok((self.0)(&call)?.unwrap_or(Expr::FuncCall(call))),
+                other => Ok(other),
+            }
+        }
+    }
+
+    Calls(f).fold_expr(expr.clone())
+}
+
+/// Move every function declared inside a block to the top level as
+/// `outer__name` (numbered when taken) and point the references in its scope at it.
+/// Local functions cannot capture: using a binding or parameter of an enclosing
+/// function is an error, so lifting never adds parameters.
+pub fn lift_local_functions(program: &Program) -> Result<Program, TypeError> {
+    if !program.decls.iter().any(decl_has_local_funcs) {
+        return Ok(program.clone());
+    }
+    let mut lifter = Lifter {
+        taken: program
+            .decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Func(f) => Some(f.name.0.clone()),
+                Decl::Global(b) | Decl::Let(b) => Some(b.name.0.clone()),
+                Decl::Type(t) => Some(t.name.0.clone()),
+                _ => None,
+            })
+            .collect(),
+        lifted: Vec::new(),
+        frames: Vec::new(),
+    };
+    let mut decls = Vec::with_capacity(program.decls.len());
+    for decl in &program.decls {
+        decls.push(match decl {
+            Decl::Func(f) => Decl::Func(lifter.func(f, &f.name.0)?),
+            Decl::Impl(imp) => Decl::Impl(ImplDecl {
+                methods: imp
+                    .methods
+                    .iter()
+                    .map(|m| lifter.func(m, &format!("{}__{}", imp.ty.0, m.name.0)))
+                    .collect::<Result<_, _>>()?,
+                ..imp.clone()
+            }),
+            Decl::Global(b) => Decl::Global(lifter.binding(b)?),
+            Decl::Let(b) => Decl::Let(lifter.binding(b)?),
+            Decl::Import(_) | Decl::Type(_) | Decl::Trait(_) | Decl::Macro(_) => decl.clone(),
+        });
+    }
+    decls.extend(lifter.lifted.into_iter().map(Decl::Func));
+    Ok(Program { decls })
+}
+
+fn decl_has_local_funcs(decl: &Decl) -> bool {
+    match decl {
+        Decl::Func(f) => expr_has_local_funcs(&f.body),
+        Decl::Impl(imp) => imp.methods.iter().any(|m| expr_has_local_funcs(&m.body)),
+        Decl::Global(b) | Decl::Let(b) => expr_has_local_funcs(&b.value),
+        Decl::Import(_) | Decl::Type(_) | Decl::Trait(_) | Decl::Macro(_) => false,
+    }
+}
+
+fn expr_has_local_funcs(expr: &Expr) -> bool {
+    struct LocalFuncs(bool);
+
+    impl Visit for LocalFuncs {
+        fn visit_func(&mut self, _: &FuncDecl) {
+            self.0 = true;
+        }
+    }
+
+    let mut found = LocalFuncs(false);
+    found.visit_expr(expr);
+    found.0
+}
+
+/// One lexical scope. `Some(lifted)` names a local function, `None` a binding or
+/// parameter. `func` is set on the outermost scope of each function body.
+struct Frame {
+    names: HashMap<String, Option<String>>,
+    func: Option<String>,
+}
+
+struct Lifter {
+    taken: HashSet<String>,
+    lifted: Vec<FuncDecl>,
+    frames: Vec<Frame>,
+}
+
+impl Lifter {
+    fn func(&mut self, f: &FuncDecl, lifted_name: &str) -> Result<FuncDecl, TypeError> {
+        self.frames.push(Frame {
+            names: f.params.iter().map(|p| (p.name.0.clone(), None)).collect(),
+            func: Some(lifted_name.to_string()),
+        });
+        let body = self.expr(&f.body, lifted_name);
+        self.frames.pop();
+        Ok(FuncDecl {
+            body: body?,
+            ..f.clone()
+        })
+    }
+
+    fn binding(&mut self, b: &Binding) -> Result<Binding, TypeError> {
+        Ok(Binding {
+            value: self.expr(&b.value, &b.name.0)?,
+            ..b.clone()
+        })
+    }
+
+    fn fresh_name(&mut self, base: String) -> String {
+        let mut name = base.clone();
+        let mut n = 2;
+        while !self.taken.insert(name.clone()) {
+            name = format!("{base}{n}");
+            n += 1;
+        }
+        name
+    }
+
+    /// The lifted name `name` refers to here, `None` for locals and top-level names.
+    fn resolve(&self, name: &str) -> Result<Option<String>, TypeError> {
+        let mut inside: Option<&str> = None;
+        for frame in self.frames.iter().rev() {
+            match frame.names.get(name) {
+                Some(Some(lifted)) => return Ok(Some(lifted.clone())),
+                Some(None) => {
+                    return match inside {
+                        Some(func) => Err(TypeError::LocalFunctionCapture {
+                            func: func.to_string(),
+                            name: name.to_string(),
+                        }),
+                        None => Ok(None),
+                    }
+                }
+                None => {}
+            }
+            if inside.is_none() {
+                inside = frame.func.as_deref();
+            }
+        }
+        Ok(None)
+    }
+
+    fn path(&self, path: &Path) -> Result<Path, TypeError> {
+        let mut segs = path.0.clone();
+        if let Some(head) = segs.first_mut() {
+            if let Some(lifted) = self.resolve(&head.0)? {
+                head.0 = lifted;
+            }
+        }
+        Ok(Path(segs, path.1))
+    }
+
+    fn declare_local(&mut self, name: &str) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.names.insert(name.to_string(), None);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, owner: &str) -> Result<Expr, TypeError> {
+        Ok(match expr {
+            Expr::Literal(..) => expr.clone(),
+            Expr::Path(p) => Expr::Path(self.path(p)?),
+            Expr::Copy(inner) => Expr::Copy(Box::new(self.expr(inner, owner)?)),
+            Expr::Time(body) => Expr::Time(Box::new(self.expr(body, owner)?)),
+            Expr::Ref(inner) => Expr::Ref(Box::new(self.expr(inner, owner)?)),
+            Expr::MutRef(inner) => Expr::MutRef(Box::new(self.expr(inner, owner)?)),
+            Expr::FuncCall(fc) => {
+                let mut callee = self.path(&fc.callee)?;
+                // `recv.f(args)` may call a local `f` through UFCS
+                if callee.0.len() > 1 {
+                    if let Some(last) = callee.0.last_mut() {
+                        if let Some(lifted) = self.resolve(&last.0).ok().flatten() {
+                            last.0 = lifted;
+                        }
+                    }
+                }
+                Expr::FuncCall(FuncCall {
+                    callee,
+                    args: fc
+                        .args
+                        .iter()
+                        .map(|a| self.expr(a, owner))
+                        .collect::<Result<_, _>>()?,
+                    span: fc.span,
+                })
+            }
+            Expr::If(ife) => Expr::If(Box::new(IfExpr {
+                cond: self.expr(&ife.cond, owner)?,
+                then_branch: self.expr(&ife.then_branch, owner)?,
+                else_branch: self.expr(&ife.else_branch, owner)?,
+                span: ife.span,
+            })),
+            Expr::Block(b) => {
+                self.frames.push(Frame {
+                    names: HashMap::new(),
+                    func: None,
+                });
+                let block = self.block(b, owner);
+                self.frames.pop();
+                Expr::Block(block?)
+            }
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                base: match &r.base {
+                    Some(b) => Some(Box::new(self.expr(b, owner)?)),
+                    None => None,
+                },
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldInit {
+                            name: f.name.clone(),
+                            value: self.expr(&f.value, owner)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                span: r.span,
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                op: u.op.clone(),
+                expr: Box::new(self.expr(&u.expr, owner)?),
+                span: u.span,
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(self.expr(&b.left, owner)?),
+                right: Box::new(self.expr(&b.right, owner)?),
+                span: b.span,
+            }),
+            Expr::Index(ix) => Expr::Index(IndexExpr {
+                base: Box::new(self.expr(&ix.base, owner)?),
+                index: Box::new(self.expr(&ix.index, owner)?),
+                span: ix.span,
+            }),
+            Expr::Slice(sl) => Expr::Slice(SliceExpr {
+                base: Box::new(self.expr(&sl.base, owner)?),
+                start: sl
+                    .start
+                    .as_deref()
+                    .map(|e| self.expr(e, owner).map(Box::new))
+                    .transpose()?,
+                end: sl
+                    .end
+                    .as_deref()
+                    .map(|e| self.expr(e, owner).map(Box::new))
+                    .transpose()?,
+                span: sl.span,
+            }),
+        })
+    }
+
+    fn block(&mut self, b: &Block, owner: &str) -> Result<Block, TypeError> {
+        // every local function is visible in the whole block, so they can call each other
+        let mut lifted_names = HashMap::new();
+        for stmt in &b.stmts {
+            if let Stmt::Func(f) = stmt {
+                let lifted = self.fresh_name(format!("{owner}__{}", f.name.0));
+                lifted_names.insert(f.name.0.clone(), lifted.clone());
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.names.insert(f.name.0.clone(), Some(lifted));
+                }
+            }
+        }
+        let mut stmts = Vec::with_capacity(b.stmts.len());
+        for stmt in &b.stmts {
+            match stmt {
+                Stmt::Func(f) => {
+                    let lifted = lifted_names
+                        .get(&f.name.0)
+                        .cloned()
+                        .unwrap_or_else(|| f.name.0.clone());
+                    let mut func = self.func(f, &lifted)?;
+                    func.name = Ident(lifted);
+                    self.lifted.push(func);
+                }
+                Stmt::Binding(binding) => {
+                    let value = self.expr(&binding.value, owner)?;
+                    self.declare_local(&binding.name.0);
+                    stmts.push(Stmt::Binding(Binding {
+                        value,
+                        ..binding.clone()
+                    }));
+                }
+                Stmt::Assign(a) => stmts.push(Stmt::Assign(Assign {
+                    target: self.path(&a.target)?,
+                    value: self.expr(&a.value, owner)?,
+                    span: a.span,
+                })),
+                Stmt::Expr(e) => stmts.push(Stmt::Expr(self.expr(e, owner)?)),
+            }
+        }
+        let tail = match &b.tail {
+            Some(t) => Some(Box::new(self.expr(t, owner)?)),
+            None => None,
+        };
+        Ok(Block {
+            stmts,
+            tail,
+            span: b.span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(src: &str) -> Program {
+        Parser::new(src).unwrap().parse_program().unwrap()
+    }
+
+    #[test]
+    fn local_functions_are_lifted_and_scoped() {
+        let src = r#"
+        helper() -> i32 = 100
+        main() = {
+          a: i32 = helper()
+          b: i32 = {
+            helper(x: i32) -> i32 = twice(copy x) + 1
+            twice(x: i32) -> i32 = copy x * 2
+            helper(3)
+          }
+          a + b
+        }
+        "#;
+        let lowered = lower_program(&parse(src)).unwrap();
+        let names: Vec<&str> = lowered
+            .decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Func(f) => Some(f.name.0.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["helper", "main", "main__helper", "main__twice"]);
+        let refs = crate::names::referenced_names(&lowered);
+        assert!(refs.contains("helper") && refs.contains("main__helper"));
+        assert!(refs.contains("main__twice"));
+    }
+
+    #[test]
+    fn type_fields_expands_to_the_field_list() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        type Pt = Point
+        type User = { name: Str, home: &Point, score: (i32) -> i32 }
+        main() = {
+          a: Str = type_fields(User)
+          type_fields(Pt)
+        }
+        "#;
+        let lowered = lower_program(&parse(src)).unwrap();
+        let Some(Decl::Func(main)) = lowered.decls.last() else {
+            panic!("expected main");
+        };
+        let Expr::Block(body) = &main.body else {
+            panic!("expected block");
+        };
+        assert!(matches!(
+            &body.stmts[0],
+            Stmt::Binding(b) if b.value == Expr::Literal(Literal::Str(
+                "name: Str, home: &Point, score: (i32) -> i32".into()
+            ), Span::default())
+        ));
+        assert_eq!(
+            body.tail.as_deref(),
+            Some(&Expr::Literal(
+                Literal::Str("x: i32, y: i32".into()),
+                Span::default()
+            ))
+        );
+        assert!(matches!(
+            lower_program(&parse("main() = type_fields(i32)")),
+            Err(TypeError::ReflectionTarget(_))
+        ));
+    }
+
+    #[test]
+    fn local_functions_cannot_capture() {
+        let src = "main() = {\n n: i32 = 1\n peek() -> i32 = copy n\n peek()\n}";
+        assert_eq!(
+            lower_program(&parse(src)),
+            Err(TypeError::LocalFunctionCapture {
+                func: "main__peek".into(),
+                name: "n".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn derive_show_generates_a_formatter() {
+        let src = r#"
+        @derive(show)
+        type Inner = { ok: bool }
+        @derive(show)
+        type Outer = { n: i32, s: Str, inner: Inner }
+        main() = 0
+        "#;
+        let lowered = lower_program(&parse(src)).unwrap();
+        let show = lowered
+            .decls
+            .iter()
+            .find_map(|d| match d {
+                Decl::Func(f) if f.name.0 == "Outer_show" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+        assert!(matches!(&show.params[0].ty, Type::Ref(_)));
+        let refs = crate::names::expr_referenced_names(&show.body);
+        assert!(refs.contains("int_to_str") && refs.contains("Inner_show"));
+
+        let bad = "@derive(show)\ntype F = { f: (i32) -> i32 }\nmain() = 0";
+        assert!(matches!(
+            lower_program(&parse(bad)),
+            Err(TypeError::DeriveUnsupported { .. })
+        ));
+        let unknown = "@derive(hash)\ntype P = { x: i32 }\nmain() = 0";
+        assert!(matches!(
+            lower_program(&parse(unknown)),
+            Err(TypeError::UnknownDerive(_))
+        ));
+    }
+
+    #[test]
+    fn derive_json_generates_both_directions() {
+        let src = r#"
+        @derive(json)
+        type Inner = { ok: bool }
+        @derive(json)
+        type Outer = { n: i32, s: Str, inner: Inner }
+        main() = 0
+        "#;
+        let lowered = lower_program(&parse(src)).unwrap();
+        let func = |name: &str| {
+            lowered.decls.iter().find_map(|d| match d {
+                Decl::Func(f) if f.name.0 == name => Some(f.clone()),
+                _ => None,
+            })
+        };
+        let to_json = func("Outer_to_json").unwrap();
+        let refs = crate::names::expr_referenced_names(&to_json.body);
+        assert!(refs.contains("json_quote") && refs.contains("Inner_to_json"));
+        let from_json = func("Outer_from_json").unwrap();
+        assert_eq!(from_json.ret, Some(Type::Named(Ident("Outer".into()))));
+        let refs = crate::names::expr_referenced_names(&from_json.body);
+        for name in [
+            "json_get_int",
+            "json_get_str",
+            "json_get_raw",
+            "Inner_from_json",
+        ] {
+            assert!(refs.contains(name), "missing {name}");
+        }
+
+        // nested records need the same derive
+        let plain = "type P = { x: i32 }\n@derive(json)\ntype Q = { p: P }\nmain() = 0";
+        assert!(matches!(
+            lower_program(&parse(plain)),
+            Err(TypeError::DeriveUnsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn globals_follow_their_dependencies() {
+        let src = "global a: i32 = copy b + f()\nf(x: i32) -> i32 = { c: i32 = 1\n copy c + copy d }\nglobal b: i32 = 1\nglobal d: i32 = 2\nmain() = copy a";
+        let lowered = order_globals(&parse(src)).unwrap();
+        let names: Vec<&str> = lowered
+            .decls
+            .iter()
+            .map(|d| match d {
+                Decl::Global(b) => b.name.0.as_str(),
+                Decl::Func(f) => f.name.0.as_str(),
+                _ => "",
+            })
+            .collect();
+        // the local `c` and parameter `x` are not globals; b and d keep their source order
+        assert_eq!(names, vec!["b", "f", "d", "a", "main"]);
+
+        let cycle = "global a: i32 = g()\ng() -> i32 = copy b\nglobal b: i32 = copy a\nmain() = 0";
+        match order_globals(&parse(cycle)) {
+            Err(TypeError::GlobalCycle(path)) => assert_eq!(path, "a -> b -> a"),
+            other => panic!("expected a cycle, got {other:?}"),
+        }
+    }
+}
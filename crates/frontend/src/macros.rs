@@ -0,0 +1,384 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::*;
+use crate::lower::map_calls;
+use crate::typecheck::TypeError;
+use std::collections::HashMap;
+
+/// How deeply expansions may call further macros before expansion gives up.
+pub const MACRO_DEPTH_LIMIT: usize = 64;
+
+/// Replace every call `name(args)` of a `macro name(params) = body` with `body`, and
+/// drop the macro declarations.
+///
+/// Expansion is syntactic: each parameter becomes its argument expression, so an
+/// argument used twice is evaluated twice. Bindings and local functions the body
+/// declares are renamed in every expansion, so they neither capture nor shadow the
+/// caller's names; other names in the body are top-level names. Expansions may call
+/// other macros up to [`MACRO_DEPTH_LIMIT`] levels deep, and errors raised inside an
+/// expansion name the chain of macros that produced it.
+pub fn expand_macros(program: &Program) -> Result<Program, TypeError> {
+    let macros: HashMap<&str, &MacroDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Macro(m) => Some((m.name.0.as_str(), m)),
+            _ => None,
+        })
+        .collect();
+    if macros.is_empty() {
+        return Ok(program.clone());
+    }
+    let mut ex = Expander {
+        macros,
+        stack: Vec::new(),
+        expansions: 0,
+    };
+    let mut decls = Vec::with_capacity(program.decls.len());
+    for decl in &program.decls {
+        decls.push(match decl {
+            Decl::Macro(_) => continue,
+            Decl::Func(f) => Decl::Func(FuncDecl {
+                body: ex.expr(&f.body)?,
+                ..f.clone()
+            }),
+            Decl::Impl(imp) => Decl::Impl(ImplDecl {
+                methods: imp
+                    .methods
+                    .iter()
+                    .map(|m| {
+                        Ok(FuncDecl {
+                            body: ex.expr(&m.body)?,
+                            ..m.clone()
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                ..imp.clone()
+            }),
+            Decl::Global(b) => Decl::Global(Binding {
+                value: ex.expr(&b.value)?,
+                ..b.clone()
+            }),
+            Decl::Let(b) => Decl::Let(Binding {
+                value: ex.expr(&b.value)?,
+                ..b.clone()
+            }),
+            Decl::Import(_) | Decl::Type(_) | Decl::Trait(_) => decl.clone(),
+        });
+    }
+    Ok(Program { decls })
+}
+
+struct Expander<'a> {
+    macros: HashMap<&'a str, &'a MacroDecl>,
+    /// Macros whose expansion is being expanded, outermost first.
+    stack: Vec<String>,
+    /// Expansions so far; numbers the renamed bindings.
+    expansions: usize,
+}
+
+impl<'a> Expander<'a> {
+    fn expr(&mut self, expr: &Expr) -> Result<Expr, TypeError> {
+        map_calls(expr, &mut |call| self.call(call))
+    }
+
+    fn call(&mut self, call: &FuncCall) -> Result<Option<Expr>, TypeError> {
+        let [name] = call.callee.0.as_slice() else {
+            return Ok(None);
+        };
+        let Some(&mac) = self.macros.get(name.0.as_str()) else {
+            return Ok(None);
+        };
+        if call.args.len() != mac.params.len() {
+            return Err(TypeError::MacroArity {
+                name: name.0.clone(),
+                expected: mac.params.len(),
+                found: call.args.len(),
+                expansion: self.expansion(),
+            });
+        }
+        if self.stack.len() >= MACRO_DEPTH_LIMIT {
+            return Err(TypeError::MacroRecursion {
+                name: name.0.clone(),
+                limit: MACRO_DEPTH_LIMIT,
+                expansion: self.expansion(),
+            });
+        }
+        self.expansions += 1;
+        let mut renames = HashMap::new();
+        declared_names(&mac.body, &mut |local| {
+            let fresh = format!("{local}__{}{}", mac.name.0, self.expansions);
+            renames.insert(local.to_string(), fresh);
+        });
+        let template = Template {
+            name: &mac.name.0,
+            args: mac
+                .params
+                .iter()
+                .map(|p| p.0.as_str())
+                .zip(&call.args)
+                .collect(),
+            renames,
+            expansion: self.expansion(),
+        };
+        let body = template.expr(&mac.body)?;
+        self.stack.push(name.0.clone());
+        let expanded = self.expr(&body);
+        self.stack.pop();
+        expanded.map(Some)
+    }
+
+    /// Where an error sits, innermost macro first: ` (in expansion of b, from a)`.
+    fn expansion(&self) -> String {
+        const SHOWN: usize = 4;
+        if self.stack.is_empty() {
+            return String::new();
+        }
+        let mut chain: Vec<&str> = self
+            .stack
+            .iter()
+            .rev()
+            .take(SHOWN)
+            .map(String::as_str)
+            .collect();
+        if self.stack.len() > SHOWN {
+            chain.push("...");
+        }
+        format!(" (in expansion of {})", chain.join(", from "))
+    }
+}
+
+/// Call `f` with every binding, local function and local function parameter `expr`
+/// declares, at any depth.
+fn declared_names(expr: &Expr, f: &mut impl FnMut(&str)) {
+    struct Declared<'f, F>(&'f mut F);
+
+    impl<F: FnMut(&str)> Visit for Declared<'_, F> {
+        fn visit_binding(&mut self, binding: &Binding) {
+            (self.0)(&binding.name.0);
+            self.visit_expr(&binding.value);
+        }
+
+        fn visit_func(&mut self, func: &FuncDecl) {
+            (self.0)(&func.name.0);
+            func.params.iter().for_each(|p| (self.0)(&p.name.0));
+            self.visit_expr(&func.body);
+        }
+    }
+
+    Declared(f).visit_expr(expr);
+}
+
+/// One expansion of a macro body: parameters become the call's arguments and the
+/// body's own names get their fresh ones.
+struct Template<'a> {
+    name: &'a str,
+    args: HashMap<&'a str, &'a Expr>,
+    renames: HashMap<String, String>,
+    expansion: String,
+}
+
+impl Template<'_> {
+    fn ident(&self, id: &Ident) -> Ident {
+        match self.renames.get(&id.0) {
+            Some(fresh) => Ident(fresh.clone()),
+            None => id.clone(),
+        }
+    }
+
+    /// A path whose head is a parameter continues the argument, which must then be a
+    /// path itself: `p.x` with `p` bound to `a.b` becomes `a.b.x`.
+    fn path(&self, path: &Path) -> Result<Path, TypeError> {
+        let Some((head, rest)) = path.0.split_first() else {
+            return Ok(path.clone());
+        };
+        match self.args.get(head.0.as_str()) {
+            Some(Expr::Path(arg)) => {
+                let mut segs = arg.0.clone();
+                segs.extend(rest.iter().cloned());
+                Ok(Path(segs, path.1))
+            }
+            Some(_) => Err(TypeError::MacroArgNotPath {
+                name: self.name.to_string(),
+                param: head.0.clone(),
+                expansion: self.expansion.clone(),
+            }),
+            None => {
+                let mut segs = vec![self.ident(head)];
+                segs.extend(rest.iter().cloned());
+                Ok(Path(segs, path.1))
+            }
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> Result<Expr, TypeError> {
+        Ok(match expr {
+            Expr::Literal(..) => expr.clone(),
+            Expr::Path(Path(segs, span)) if segs.len() == 1 => {
+                match self.args.get(segs[0].0.as_str()) {
+                    Some(arg) => (*arg).clone(),
+                    None => Expr::Path(Path(vec![self.ident(&segs[0])], *span)),
+                }
+            }
+            Expr::Path(p) => Expr::Path(self.path(p)?),
+            Expr::Copy(inner) => Expr::Copy(Box::new(self.expr(inner)?)),
+            Expr::Time(body) => Expr::Time(Box::new(self.expr(body)?)),
+            Expr::Ref(inner) => Expr::Ref(Box::new(self.expr(inner)?)),
+            Expr::MutRef(inner) => Expr::MutRef(Box::new(self.expr(inner)?)),
+            Expr::FuncCall(fc) => Expr::FuncCall(FuncCall {
+                callee: self.path(&fc.callee)?,
+                args: fc
+                    .args
+                    .iter()
+                    .map(|a| self.expr(a))
+                    .collect::<Result<_, _>>()?,
+                span: fc.span,
+            }),
+            Expr::If(ife) => Expr::If(Box::new(IfExpr {
+                cond: self.expr(&ife.cond)?,
+                then_branch: self.expr(&ife.then_branch)?,
+                else_branch: self.expr(&ife.else_branch)?,
+                span: ife.span,
+            })),
+            Expr::Block(b) => Expr::Block(self.block(b)?),
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                base: match &r.base {
+                    Some(b) => Some(Box::new(self.expr(b)?)),
+                    None => None,
+                },
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldInit {
+                            name: f.name.clone(),
+                            value: self.expr(&f.value)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                span: r.span,
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                op: u.op.clone(),
+                expr: Box::new(self.expr(&u.expr)?),
+                span: u.span,
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(self.expr(&b.left)?),
+                right: Box::new(self.expr(&b.right)?),
+                span: b.span,
+            }),
+            Expr::Index(ix) => Expr::Index(IndexExpr {
+                base: Box::new(self.expr(&ix.base)?),
+                index: Box::new(self.expr(&ix.index)?),
+                span: ix.span,
+            }),
+            Expr::Slice(sl) => Expr::Slice(SliceExpr {
+                base: Box::new(self.expr(&sl.base)?),
+                start: sl
+                    .start
+                    .as_deref()
+                    .map(|e| self.expr(e).map(Box::new))
+                    .transpose()?,
+                end: sl
+                    .end
+                    .as_deref()
+                    .map(|e| self.expr(e).map(Box::new))
+                    .transpose()?,
+                span: sl.span,
+            }),
+        })
+    }
+
+    fn block(&self, b: &Block) -> Result<Block, TypeError> {
+        let stmts = b
+            .stmts
+            .iter()
+            .map(|stmt| {
+                Ok(match stmt {
+                    Stmt::Binding(binding) => Stmt::Binding(Binding {
+                        name: self.ident(&binding.name),
+                        value: self.expr(&binding.value)?,
+                        ..binding.clone()
+                    }),
+                    Stmt::Assign(a) => Stmt::Assign(Assign {
+                        target: self.path(&a.target)?,
+                        value: self.expr(&a.value)?,
+                        span: a.span,
+                    }),
+                    Stmt::Expr(e) => Stmt::Expr(self.expr(e)?),
+                    Stmt::Func(f) => Stmt::Func(FuncDecl {
+                        name: self.ident(&f.name),
+                        params: f
+                            .params
+                            .iter()
+                            .map(|p| Param {
+                                name: self.ident(&p.name),
+                                ..p.clone()
+                            })
+                            .collect(),
+                        body: self.expr(&f.body)?,
+                        ..f.clone()
+                    }),
+                })
+            })
+            .collect::<Result<_, TypeError>>()?;
+        let tail = match &b.tail {
+            Some(t) => Some(Box::new(self.expr(t)?)),
+            None => None,
+        };
+        Ok(Block {
+            stmts,
+            tail,
+            span: b.span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn expand(src: &str) -> Result<Program, TypeError> {
+        expand_macros(&Parser::new(src).unwrap().parse_program().unwrap())
+    }
+
+    #[test]
+    fn expands_with_fresh_bindings() {
+        let program = expand(
+            "macro twice(e) = { e e }\n\
+             macro swap_add(a, b) = { t: i32 = copy a\n a + b + t }\n\
+             main() = { t: i32 = 1\n twice(println(\"hi\"))\n swap_add(t, 2) }",
+        )
+        .unwrap();
+        assert_eq!(program.decls.len(), 1);
+        let expected = Parser::new(
+            "main() = { t: i32 = 1\n { println(\"hi\") println(\"hi\") }\n \
+             { t__swap_add2: i32 = copy t\n t + 2 + t__swap_add2 } }",
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn expansion_errors_name_the_macro_chain() {
+        let err =
+            expand("macro one(a) = a\nmacro outer(x) = one(x, x)\nmain() = outer(1)").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("takes 1 arguments, found 2 (in expansion of outer)"),
+            "{err}"
+        );
+        let err = expand("macro again(x) = again(x)\nmain() = again(1)").unwrap_err();
+        assert!(
+            matches!(err, TypeError::MacroRecursion { ref name, .. } if name == "again"),
+            "{err}"
+        );
+        let err = expand("macro get(r) = r.x\nmain() = get(1 + 2)").unwrap_err();
+        assert!(matches!(err, TypeError::MacroArgNotPath { .. }), "{err}");
+    }
+}
@@ -0,0 +1,423 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::*;
+use crate::typecheck::TypeError;
+use std::collections::HashSet;
+
+/// Name a declaration of module `module` is emitted under once imports are merged.
+pub fn mangle(module: &str, name: &str) -> String {
+    format!("{module}__{name}")
+}
+
+/// Names a module exports to its importers: functions (including derived ones), types,
+/// globals, traits and macros.
+pub fn exported_names(program: &Program) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for decl in &program.decls {
+        match decl {
+            Decl::Global(b) | Decl::Let(b) => {
+                names.insert(b.name.0.clone());
+            }
+            Decl::Type(t) => {
+                names.insert(t.name.0.clone());
+                // derived functions are generated after resolution, under the mangled type name
+                names.extend(t.derived_fn_names());
+            }
+            Decl::Func(f) => {
+                names.insert(f.name.0.clone());
+            }
+            Decl::Trait(t) => {
+                names.insert(t.name.0.clone());
+            }
+            Decl::Macro(m) => {
+                names.insert(m.name.0.clone());
+            }
+            Decl::Import(_) | Decl::Impl(_) => {}
+        }
+    }
+    names
+}
+
+/// Give a module's declarations their namespaced names and resolve its references.
+///
+/// `module` is `None` for the entry file, whose declarations keep their names, and
+/// `Some(name)` for an imported module, whose declarations become `name__decl`.
+/// `imports` lists the modules this file imports with their exported names. A
+/// reference `m.x` resolves to `m__x`; an unqualified `x` resolves to the file's own
+/// declaration, or to the single imported module exporting it. Locals shadow both.
+pub fn resolve_module(
+    program: &Program,
+    module: Option<&str>,
+    imports: &[(String, HashSet<String>)],
+) -> Result<Program, TypeError> {
+    let resolver = Resolver {
+        own: exported_names(program),
+        module,
+        imports,
+    };
+    let mut scopes = Vec::new();
+    let mut decls = Vec::with_capacity(program.decls.len());
+    for decl in &program.decls {
+        let decl = match decl {
+            Decl::Import(_) => decl.clone(),
+            Decl::Global(b) => Decl::Global(resolver.top_binding(b, &mut scopes)?),
+            Decl::Let(b) => Decl::Let(resolver.top_binding(b, &mut scopes)?),
+            Decl::Type(t) => Decl::Type(TypeDecl {
+                name: resolver.own_name(&t.name),
+                ty: resolver.ty(&t.ty)?,
+                derives: t.derives.clone(),
+                doc: t.doc.clone(),
+                span: t.span,
+            }),
+            Decl::Func(f) => {
+                let mut func = resolver.func(f, &mut scopes)?;
+                func.name = resolver.own_name(&f.name);
+                Decl::Func(func)
+            }
+            Decl::Trait(t) => Decl::Trait(TraitDecl {
+                name: resolver.own_name(&t.name),
+                methods: t
+                    .methods
+                    .iter()
+                    .map(|m| {
+                        Ok(TraitMethod {
+                            name: m.name.clone(),
+                            params: resolver.params(&m.params)?,
+                            ret: resolver.ty(&m.ret)?,
+                            doc: m.doc.clone(),
+                            span: m.span,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                doc: t.doc.clone(),
+                span: t.span,
+            }),
+            Decl::Impl(imp) => Decl::Impl(ImplDecl {
+                trait_name: resolver.name(&imp.trait_name)?,
+                ty: resolver.name(&imp.ty)?,
+                methods: imp
+                    .methods
+                    .iter()
+                    .map(|m| resolver.func(m, &mut scopes))
+                    .collect::<Result<_, _>>()?,
+                doc: imp.doc.clone(),
+                span: imp.span,
+            }),
+            Decl::Macro(m) => {
+                // parameters are substituted later, so they shadow like locals
+                scopes.push(m.params.iter().map(|p| p.0.clone()).collect());
+                let body = resolver.expr(&m.body, &mut scopes);
+                scopes.pop();
+                Decl::Macro(MacroDecl {
+                    name: resolver.own_name(&m.name),
+                    params: m.params.clone(),
+                    body: body?,
+                    doc: m.doc.clone(),
+                    span: m.span,
+                })
+            }
+        };
+        decls.push(decl);
+    }
+    Ok(Program { decls })
+}
+
+struct Resolver<'a> {
+    own: HashSet<String>,
+    module: Option<&'a str>,
+    imports: &'a [(String, HashSet<String>)],
+}
+
+type Scopes = Vec<HashSet<String>>;
+
+impl Resolver<'_> {
+    fn own_name(&self, name: &Ident) -> Ident {
+        match self.module {
+            Some(m) => Ident(mangle(m, &name.0)),
+            None => name.clone(),
+        }
+    }
+
+    /// Resolve an unqualified top-level name; unknown names (builtins) are kept.
+    fn name(&self, name: &Ident) -> Result<Ident, TypeError> {
+        if self.own.contains(&name.0) {
+            return Ok(self.own_name(name));
+        }
+        let mut owners = self
+            .imports
+            .iter()
+            .filter(|(_, names)| names.contains(&name.0))
+            .map(|(m, _)| m.as_str());
+        match (owners.next(), owners.next()) {
+            (Some(m), None) => Ok(Ident(mangle(m, &name.0))),
+            (Some(a), Some(b)) => Err(TypeError::AmbiguousName {
+                name: name.0.clone(),
+                modules: format!("{a}, {b}"),
+            }),
+            _ => Ok(name.clone()),
+        }
+    }
+
+    /// Resolve a path head: locals first, then `module.name`, then top-level names.
+    fn path(&self, path: &Path, scopes: &Scopes) -> Result<Path, TypeError> {
+        let Some(head) = path.0.first() else {
+            return Ok(path.clone());
+        };
+        if scopes.iter().any(|s| s.contains(&head.0)) {
+            return Ok(path.clone());
+        }
+        if let Some((module, names)) = self.imports.iter().find(|(m, _)| *m == head.0) {
+            if let Some(member) = path.0.get(1) {
+                if !names.contains(&member.0) {
+                    return Err(TypeError::UnknownIdent(format!("{}.{}", head.0, member.0)));
+                }
+                let mut segs = vec![Ident(mangle(module, &member.0))];
+                segs.extend(path.0[2..].iter().cloned());
+                return Ok(Path(segs, path.1));
+            }
+        }
+        let mut segs = path.0.clone();
+        segs[0] = self.name(head)?;
+        Ok(Path(segs, path.1))
+    }
+
+    fn ty(&self, ty: &Type) -> Result<Type, TypeError> {
+        Ok(match ty {
+            Type::Named(n) => Type::Named(self.name(n)?),
+            Type::Ref(inner) => Type::Ref(Box::new(self.ty(inner)?)),
+            Type::MutRef(inner) => Type::MutRef(Box::new(self.ty(inner)?)),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldType {
+                            name: f.name.clone(),
+                            ty: self.ty(&f.ty)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+            ),
+            Type::Func(f) => Type::Func(FuncType {
+                params: f
+                    .params
+                    .iter()
+                    .map(|p| self.ty(p))
+                    .collect::<Result<_, _>>()?,
+                ret: Box::new(self.ty(&f.ret)?),
+            }),
+        })
+    }
+
+    fn params(&self, params: &[Param]) -> Result<Vec<Param>, TypeError> {
+        params
+            .iter()
+            .map(|p| {
+                Ok(Param {
+                    ty: self.ty(&p.ty)?,
+                    ..p.clone()
+                })
+            })
+            .collect()
+    }
+
+    fn func(&self, f: &FuncDecl, scopes: &mut Scopes) -> Result<FuncDecl, TypeError> {
+        scopes.push(f.params.iter().map(|p| p.name.0.clone()).collect());
+        let body = self.expr(&f.body, scopes);
+        scopes.pop();
+        Ok(FuncDecl {
+            name: f.name.clone(),
+            params: self.params(&f.params)?,
+            ret: f.ret.as_ref().map(|r| self.ty(r)).transpose()?,
+            body: body?,
+            doc: f.doc.clone(),
+            span: f.span,
+        })
+    }
+
+    fn top_binding(&self, b: &Binding, scopes: &mut Scopes) -> Result<Binding, TypeError> {
+        Ok(Binding {
+            mutable: b.mutable,
+            name: self.own_name(&b.name),
+            ty: self.ty(&b.ty)?,
+            value: self.expr(&b.value, scopes)?,
+            span: b.span,
+        })
+    }
+
+    fn expr(&self, expr: &Expr, scopes: &mut Scopes) -> Result<Expr, TypeError> {
+        Ok(match expr {
+            Expr::Literal(..) => expr.clone(),
+            Expr::Path(p) => Expr::Path(self.path(p, scopes)?),
+            Expr::Copy(inner) => Expr::Copy(Box::new(self.expr(inner, scopes)?)),
+            Expr::Time(body) => Expr::Time(Box::new(self.expr(body, scopes)?)),
+            Expr::Ref(inner) => Expr::Ref(Box::new(self.expr(inner, scopes)?)),
+            Expr::MutRef(inner) => Expr::MutRef(Box::new(self.expr(inner, scopes)?)),
+            Expr::FuncCall(fc) => {
+                let mut callee = self.path(&fc.callee, scopes)?;
+                // `recv.f(args)` lowers to `f(recv, args)`, so `f` is a top-level name too
+                if callee.0.len() > 1 {
+                    if let Some(last) = callee.0.last_mut() {
+                        *last = self.name(last)?;
+                    }
+                }
+                Expr::FuncCall(FuncCall {
+                    callee,
+                    args: fc
+                        .args
+                        .iter()
+                        .map(|a| self.expr(a, scopes))
+                        .collect::<Result<_, _>>()?,
+                    span: fc.span,
+                })
+            }
+            Expr::If(ife) => Expr::If(Box::new(IfExpr {
+                cond: self.expr(&ife.cond, scopes)?,
+                then_branch: self.expr(&ife.then_branch, scopes)?,
+                else_branch: self.expr(&ife.else_branch, scopes)?,
+                span: ife.span,
+            })),
+            Expr::Block(b) => {
+                scopes.push(HashSet::new());
+                let block = self.block(b, scopes);
+                scopes.pop();
+                Expr::Block(block?)
+            }
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                base: match &r.base {
+                    Some(b) => Some(Box::new(self.expr(b, scopes)?)),
+                    None => None,
+                },
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldInit {
+                            name: f.name.clone(),
+                            value: self.expr(&f.value, scopes)?,
+                        })
+                    })
+                    .collect::<Result<_, TypeError>>()?,
+                span: r.span,
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                op: u.op.clone(),
+                expr: Box::new(self.expr(&u.expr, scopes)?),
+                span: u.span,
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(self.expr(&b.left, scopes)?),
+                right: Box::new(self.expr(&b.right, scopes)?),
+                span: b.span,
+            }),
+            Expr::Index(ix) => Expr::Index(IndexExpr {
+                base: Box::new(self.expr(&ix.base, scopes)?),
+                index: Box::new(self.expr(&ix.index, scopes)?),
+                span: ix.span,
+            }),
+            Expr::Slice(sl) => Expr::Slice(SliceExpr {
+                base: Box::new(self.expr(&sl.base, scopes)?),
+                start: sl
+                    .start
+                    .as_deref()
+                    .map(|e| self.expr(e, scopes).map(Box::new))
+                    .transpose()?,
+                end: sl
+                    .end
+                    .as_deref()
+                    .map(|e| self.expr(e, scopes).map(Box::new))
+                    .transpose()?,
+                span: sl.span,
+            }),
+        })
+    }
+
+    fn block(&self, b: &Block, scopes: &mut Scopes) -> Result<Block, TypeError> {
+        // local functions are visible in the whole block and shadow top-level names
+        if let Some(scope) = scopes.last_mut() {
+            for stmt in &b.stmts {
+                if let Stmt::Func(f) = stmt {
+                    scope.insert(f.name.0.clone());
+                }
+            }
+        }
+        let mut stmts = Vec::with_capacity(b.stmts.len());
+        for stmt in &b.stmts {
+            stmts.push(match stmt {
+                Stmt::Binding(binding) => {
+                    let value = self.expr(&binding.value, scopes)?;
+                    if let Some(scope) = scopes.last_mut() {
+                        scope.insert(binding.name.0.clone());
+                    }
+                    Stmt::Binding(Binding {
+                        mutable: binding.mutable,
+                        name: binding.name.clone(),
+                        ty: self.ty(&binding.ty)?,
+                        value,
+                        span: binding.span,
+                    })
+                }
+                Stmt::Assign(a) => Stmt::Assign(Assign {
+                    target: self.path(&a.target, scopes)?,
+                    value: self.expr(&a.value, scopes)?,
+                    span: a.span,
+                }),
+                Stmt::Expr(e) => Stmt::Expr(self.expr(e, scopes)?),
+                Stmt::Func(f) => Stmt::Func(self.func(f, scopes)?),
+            });
+        }
+        let tail = match &b.tail {
+            Some(t) => Some(Box::new(self.expr(t, scopes)?)),
+            None => None,
+        };
+        Ok(Block {
+            stmts,
+            tail,
+            span: b.span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(src: &str) -> Program {
+        Parser::new(src).unwrap().parse_program().unwrap()
+    }
+
+    #[test]
+    fn qualified_and_unqualified_references_resolve() {
+        let math =
+            parse("add(a: i32, b: i32) -> i32 = a + b\ntwice(x: i32) -> i32 = add(copy x, x)");
+        let math_names = exported_names(&math);
+        let lowered = resolve_module(&math, Some("math"), &[]).unwrap();
+        let Decl::Func(twice) = &lowered.decls[1] else {
+            panic!("function expected");
+        };
+        assert_eq!(twice.name.0, "math__twice");
+        assert!(matches!(&twice.body, Expr::FuncCall(c) if c.callee.0[0].0 == "math__add"));
+
+        let main = parse("main() = { math: i32 = 1\n math.add(2) + twice(3) }");
+        let imports = [("math".to_string(), math_names)];
+        let main = resolve_module(&main, None, &imports).unwrap();
+        let names = crate::names::referenced_names(&main);
+        // the local `math` shadows the module, so `math.add(2)` stays a method call
+        assert!(names.contains("math") && names.contains("math__add"));
+        assert!(names.contains("math__twice"));
+    }
+
+    #[test]
+    fn ambiguous_unqualified_name_is_rejected() {
+        let names: HashSet<String> = ["add".to_string()].into();
+        let imports = [("a".to_string(), names.clone()), ("b".to_string(), names)];
+        let ok = parse("main() = a.add(1, 2) + b.add(3, 4)");
+        assert!(resolve_module(&ok, None, &imports).is_ok());
+        let bad = parse("main() = add(1, 2)");
+        assert!(matches!(
+            resolve_module(&bad, None, &imports),
+            Err(TypeError::AmbiguousName { .. })
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::*;
+use std::collections::HashSet;
+
+/// Every identifier a module refers to: path heads, callees and named types.
+/// Locals are included too; callers only intersect this with declared names.
+pub fn referenced_names(program: &Program) -> HashSet<String> {
+    let mut names = Referenced::default();
+    visit::walk_program(&mut names, program);
+    names.0
+}
+
+/// Identifiers one expression refers to, with the same rules as [`referenced_names`].
+pub fn expr_referenced_names(expr: &Expr) -> HashSet<String> {
+    let mut names = Referenced::default();
+    names.visit_expr(expr);
+    names.0
+}
+
+/// Identifiers one declaration refers to, with the same rules as [`referenced_names`].
+pub fn decl_referenced_names(decl: &Decl) -> HashSet<String> {
+    let mut names = Referenced::default();
+    names.visit_decl(decl);
+    names.0
+}
+
+/// Names `expr` binds in its blocks. Taking these (and a function's parameters) out of
+/// [`expr_referenced_names`] approximates the globals and functions a body refers to.
+pub fn bound_names(expr: &Expr) -> HashSet<String> {
+    let mut names = Bound::default();
+    names.visit_expr(expr);
+    names.0
+}
+
+#[derive(Default)]
+struct Referenced(HashSet<String>);
+
+impl Visit for Referenced {
+    fn visit_decl(&mut self, decl: &Decl) {
+        if let Decl::Impl(imp) = decl {
+            self.0.insert(imp.trait_name.0.clone());
+            self.0.insert(imp.ty.0.clone());
+        }
+        visit::walk_decl(self, decl);
+    }
+
+    fn visit_call(&mut self, call: &FuncCall) {
+        if let Some(method) = call.method_name() {
+            self.0.insert(method.0.clone());
+        }
+        visit::walk_call(self, call);
+    }
+
+    fn visit_path(&mut self, path: &Path) {
+        if let Some(head) = path.0.first() {
+            self.0.insert(head.0.clone());
+        }
+    }
+
+    fn visit_type(&mut self, ty: &Type) {
+        if let Type::Named(id) = ty {
+            self.0.insert(id.0.clone());
+        }
+        visit::walk_type(self, ty);
+    }
+}
+
+/// Binding and local function names; the bodies of local functions are their own scope.
+#[derive(Default)]
+struct Bound(HashSet<String>);
+
+impl Visit for Bound {
+    fn visit_binding(&mut self, binding: &Binding) {
+        self.0.insert(binding.name.0.clone());
+        self.visit_expr(&binding.value);
+    }
+
+    fn visit_func(&mut self, func: &FuncDecl) {
+        self.0.insert(func.name.0.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn collects_referenced_names() {
+        let src = r#"
+        type Pair = { a: Point, b: i32 }
+        main() = {
+          p: Pair = make()
+          helper(copy p.b)
+        }
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let names = referenced_names(&program);
+        for n in ["Point", "Pair", "make", "helper", "p", "i32"] {
+            assert!(names.contains(n), "missing {n}");
+        }
+    }
+}
@@ -1,6 +1,12 @@
 #![forbid(unsafe_code)]
 
 use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::incremental;
+pub use crate::incremental::Edit;
+pub use crate::lexer::Token;
+use crate::lexer::{Lexer, SpannedToken};
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
@@ -11,80 +17,175 @@ pub enum ParserError {
     UnexpectedToken {
         expected: &'static str,
         found: Token,
+        span: Span,
     },
     #[error("invalid number literal: {0}")]
-    InvalidNumber(String),
+    InvalidNumber(String, Span),
     #[error("lexer error: {0}")]
-    Lexer(String),
+    Lexer(String, Span),
+    #[error("import is only allowed at the top level of a module")]
+    NestedImport(Span),
+    #[error("method {0} must take self as its first parameter")]
+    MissingSelf(String, Span),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Token {
-    Ident(String),
-    Int(i64),
-    Str(String),
-    Bool(bool),
-
-    KwImport,
-    KwGlobal,
-    KwMut,
-    KwType,
-    KwIf,
-    KwThen,
-    KwElse,
-    KwCopy,
-
-    LBrace,
-    RBrace,
-    LParen,
-    RParen,
-    Colon,
-    Comma,
-    Dot,
-    Assign,
-    Arrow,
-    Amp,
-    Plus,
-    Minus,
-    Star,
-    Slash,
-    Lt,
-    EqEq,
-    AndAnd,
-    OrOr,
-    Bang,
+impl ParserError {
+    /// Where the error was found; `None` for running out of input.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParserError::Eof => None,
+            ParserError::UnexpectedToken { span, .. }
+            | ParserError::InvalidNumber(_, span)
+            | ParserError::Lexer(_, span)
+            | ParserError::NestedImport(span)
+            | ParserError::MissingSelf(_, span) => Some(*span),
+        }
+    }
+}
 
-    Eof,
+/// Syntax the parser accepts beyond the strict grammar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Allow a comma before the closing `)` of parameter lists and call arguments and
+    /// before the closing `}` of record types and record literals, as generated code
+    /// and formatters like to write them.
+    pub trailing_commas: bool,
 }
 
 pub struct Parser<'a> {
     tokens: Vec<Token>,
+    spans: Vec<Span>, // one per token
     pos: usize,
+    /// `///` comment text keyed by the index of the token that follows it; empty
+    /// unless the parser was built with [`Parser::with_doc_comments`].
+    docs: HashMap<usize, String>,
+    diagnostics: Vec<Diagnostic>, // every error of the last parse_program
+    options: ParserOptions,
     _src: &'a str,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Result<Self, ParserError> {
-        let tokens = lex(source)?;
+        let (tokens, spans) = lex(source, None)?;
+        Ok(Self {
+            tokens,
+            spans,
+            pos: 0,
+            docs: HashMap::new(),
+            diagnostics: Vec::new(),
+            options: ParserOptions::default(),
+            _src: source,
+        })
+    }
+
+    /// Like [`Parser::new`], but `///` comments right before a declaration end up in
+    /// its `doc` field instead of being dropped.
+    pub fn with_doc_comments(source: &'a str) -> Result<Self, ParserError> {
+        let mut docs = HashMap::new();
+        let (tokens, spans) = lex(source, Some(&mut docs))?;
         Ok(Self {
             tokens,
+            spans,
             pos: 0,
+            docs,
+            diagnostics: Vec::new(),
+            options: ParserOptions::default(),
             _src: source,
         })
     }
 
+    pub fn with_options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// A doc-comment-keeping parser over `src` from `start` (a token boundary, with its
+    /// line and column) up to byte `end`; see [`Parser::reparse`].
+    pub(crate) fn for_region(src: &'a str, start: Span, end: usize) -> Result<Self, ParserError> {
+        let mut docs = HashMap::new();
+        let (tokens, spans) = collect_tokens(Lexer::resume(&src[..end], start), Some(&mut docs))?;
+        Ok(Self {
+            tokens,
+            spans,
+            pos: 0,
+            docs,
+            diagnostics: Vec::new(),
+            options: ParserOptions::default(),
+            _src: src,
+        })
+    }
+
+    /// Parse `new_src`, the source of `old` after `edit`, re-lexing and re-parsing only
+    /// the declarations around the edit. Declarations before it are reused as they
+    /// are and those after it with their spans moved. Doc comments are kept, as with
+    /// [`Parser::with_doc_comments`]. The result is the same as parsing `new_src` from
+    /// scratch, which is what happens when the edited region does not parse.
+    pub fn reparse(old: &Program, edit: &Edit, new_src: &str) -> Result<Program, ParserError> {
+        incremental::reparse(old, edit, new_src)
+    }
+
+    fn doc_here(&self) -> Option<String> {
+        self.docs.get(&self.pos).cloned()
+    }
+
+    /// Parse the whole input. A declaration that fails to parse is recorded in
+    /// [`Parser::diagnostics`] and skipped, so later declarations are still checked;
+    /// the first such error is returned.
     pub fn parse_program(&mut self) -> Result<Program, ParserError> {
+        self.diagnostics.clear();
         let mut decls = Vec::new();
+        let mut first_error = None;
         while !self.check(Token::Eof) {
-            decls.push(self.parse_decl()?);
+            let decl_start = self.pos;
+            match self.parse_decl() {
+                Ok(decl) => decls.push(decl),
+                Err(e) => {
+                    let span = e.span().unwrap_or_else(|| self.here());
+                    self.diagnostics
+                        .push(Diagnostic::error(span, e.to_string()));
+                    first_error.get_or_insert(e);
+                    self.skip_to_next_decl(decl_start);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(Program { decls }),
+        }
+    }
+
+    /// Parse the whole input as a single expression, such as a line typed at a REPL.
+    pub fn parse_expression(&mut self) -> Result<Expr, ParserError> {
+        let expr = self.parse_expr()?;
+        self.expect(&Token::Eof, "end of input after the expression")?;
+        Ok(expr)
+    }
+
+    /// Errors found by the last [`Parser::parse_program`], in source order.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Error recovery: move to the next token at the start of a line in column 1, where
+    /// top-level declarations begin. A `}` there closes the broken declaration.
+    fn skip_to_next_decl(&mut self, decl_start: usize) {
+        if self.pos == decl_start {
+            self.advance();
+        }
+        while !self.check(Token::Eof) && (self.here().col != 1 || self.check(Token::RBrace)) {
+            self.advance();
         }
-        Ok(Program { decls })
     }
 
     fn parse_decl(&mut self) -> Result<Decl, ParserError> {
+        let doc = self.doc_here();
+        let start = self.here();
         if self.matches(&[Token::KwImport]) {
             let module = self.expect_ident("module name")?;
-            return Ok(Decl::Import(ImportDecl { module }));
+            return Ok(Decl::Import(ImportDecl {
+                module,
+                span: self.since(start),
+            }));
         }
 
         if self.matches(&[Token::KwGlobal]) {
@@ -92,45 +193,206 @@ impl<'a> Parser<'a> {
             return Ok(Decl::Global(binding));
         }
 
+        let mut derives = Vec::new();
+        while self.matches(&[Token::At]) {
+            let attr = self.expect_ident("attribute name")?;
+            if attr.0 != "derive" {
+                return Err(ParserError::UnexpectedToken {
+                    expected: "derive attribute",
+                    found: Token::Ident(attr.0),
+                    span: self.prev_span(),
+                });
+            }
+            self.expect(&Token::LParen, "'(' after derive")?;
+            loop {
+                derives.push(self.expect_ident("derive name")?);
+                if !self.matches(&[Token::Comma]) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen, "')' after derive list")?;
+            if !self.check(Token::At) && !self.check(Token::KwType) {
+                return Err(ParserError::UnexpectedToken {
+                    expected: "type declaration after @derive",
+                    found: self.peek().clone(),
+                    span: self.here(),
+                });
+            }
+        }
+
         if self.matches(&[Token::KwType]) {
             let name = self.expect_ident("type name")?;
             self.expect(&Token::Assign, "'=' after type name")?;
             let ty = self.parse_type()?;
-            return Ok(Decl::Type(TypeDecl { name, ty }));
+            return Ok(Decl::Type(TypeDecl {
+                name,
+                ty,
+                derives,
+                doc,
+                span: self.since(start),
+            }));
         }
 
-        // function vs let binding: lookahead for '('
-        if self.peek_is_ident() && self.peek_next_is(Token::LParen) {
-            let name = self.expect_ident("function name")?;
-            self.expect(&Token::LParen, "'(' after function name")?;
-            let params = if self.check(Token::RParen) {
-                Vec::new()
-            } else {
-                self.parse_params()?
-            };
-            self.expect(&Token::RParen, "')' after params")?;
-            let ret = if self.matches(&[Token::Arrow]) {
-                Some(self.parse_type()?)
-            } else {
-                None
-            };
-            self.expect(&Token::Assign, "'=' before function body")?;
+        if self.matches(&[Token::KwTrait]) {
+            let name = self.expect_ident("trait name")?;
+            self.expect(&Token::LBrace, "'{' after trait name")?;
+            let mut methods = Vec::new();
+            while !self.matches(&[Token::RBrace]) {
+                let doc = self.doc_here();
+                let method_start = self.here();
+                let name = self.expect_ident("trait method name")?;
+                self.expect(&Token::LParen, "'(' after method name")?;
+                let params = if self.check(Token::RParen) {
+                    Vec::new()
+                } else {
+                    self.parse_params()?
+                };
+                self.expect(&Token::RParen, "')' after params")?;
+                self.expect(&Token::Arrow, "'->' in trait method signature")?;
+                let ret = self.parse_type()?;
+                let span = self.since(method_start);
+                ensure_self_param(&name, &params, span)?;
+                methods.push(TraitMethod {
+                    name,
+                    params,
+                    ret,
+                    doc,
+                    span,
+                });
+            }
+            return Ok(Decl::Trait(TraitDecl {
+                name,
+                methods,
+                doc,
+                span: self.since(start),
+            }));
+        }
+
+        if self.matches(&[Token::KwImpl]) {
+            let trait_name = self.expect_ident("trait name")?;
+            match self.advance() {
+                Token::Ident(kw) if kw == "for" => {}
+                other => {
+                    return Err(ParserError::UnexpectedToken {
+                        expected: "'for' after trait name",
+                        found: other,
+                        span: self.prev_span(),
+                    })
+                }
+            }
+            let ty = self.expect_ident("type name")?;
+            self.expect(&Token::LBrace, "'{' after impl type")?;
+            let mut methods = Vec::new();
+            while !self.matches(&[Token::RBrace]) {
+                let method = self.parse_func()?;
+                ensure_self_param(&method.name, &method.params, method.span)?;
+                methods.push(method);
+            }
+            return Ok(Decl::Impl(ImplDecl {
+                trait_name,
+                ty,
+                methods,
+                doc,
+                span: self.since(start),
+            }));
+        }
+
+        // `macro` is contextual: only a declaration when a name follows
+        if matches!(self.peek(), Token::Ident(kw) if kw == "macro")
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(_)))
+        {
+            self.advance();
+            let name = self.expect_ident("macro name")?;
+            self.expect(&Token::LParen, "'(' after macro name")?;
+            let mut params = Vec::new();
+            while !self.matches(&[Token::RParen]) {
+                params.push(self.expect_ident("macro parameter")?);
+                if !self.matches(&[Token::Comma]) {
+                    self.expect(&Token::RParen, "')' after macro parameters")?;
+                    break;
+                }
+            }
+            self.expect(&Token::Assign, "'=' before macro body")?;
             let body = self.parse_expr()?;
-            return Ok(Decl::Func(FuncDecl {
+            return Ok(Decl::Macro(MacroDecl {
                 name,
                 params,
-                ret,
                 body,
+                doc,
+                span: self.since(start),
             }));
         }
 
+        // function vs let binding: lookahead for '('
+        if self.peek_is_ident() && self.peek_next_is(Token::LParen) {
+            return Ok(Decl::Func(self.parse_func()?));
+        }
+
         let binding = self.parse_binding()?;
         Ok(Decl::Let(binding))
     }
 
+    fn parse_func(&mut self) -> Result<FuncDecl, ParserError> {
+        let doc = self.doc_here();
+        let start = self.here();
+        let name = self.expect_ident("function name")?;
+        self.expect(&Token::LParen, "'(' after function name")?;
+        let params = if self.check(Token::RParen) {
+            Vec::new()
+        } else {
+            self.parse_params()?
+        };
+        self.expect(&Token::RParen, "')' after params")?;
+        let ret = if self.matches(&[Token::Arrow]) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Assign, "'=' before function body")?;
+        let body = self.parse_expr()?;
+        Ok(FuncDecl {
+            name,
+            params,
+            ret,
+            body,
+            doc,
+            span: self.since(start),
+        })
+    }
+
     fn parse_params(&mut self) -> Result<Vec<Param>, ParserError> {
         let mut params = Vec::new();
         loop {
+            if !params.is_empty() && self.trailing_comma(Token::RParen) {
+                break;
+            }
+            // `self` / `&self` receivers of trait and impl methods carry no annotation
+            if params.is_empty() {
+                let by_ref = self.check(Token::Amp);
+                let by_mut = by_ref && self.tokens.get(self.pos + 1) == Some(&Token::KwMut);
+                let at = self.pos + usize::from(by_ref) + usize::from(by_mut);
+                let is_self = matches!(self.tokens.get(at), Some(Token::Ident(n)) if n == "self")
+                    && self.tokens.get(at + 1) != Some(&Token::Colon);
+                if is_self {
+                    self.pos = at + 1;
+                    let this = Type::Named(Ident("Self".to_string()));
+                    params.push(Param {
+                        mutable: false,
+                        name: Ident("self".to_string()),
+                        ty: if by_mut {
+                            Type::MutRef(Box::new(this))
+                        } else if by_ref {
+                            Type::Ref(Box::new(this))
+                        } else {
+                            this
+                        },
+                    });
+                    if !self.matches(&[Token::Comma]) {
+                        break;
+                    }
+                    continue;
+                }
+            }
             let mutable = self.matches(&[Token::KwMut]);
             let name = self.expect_ident("parameter name")?;
             self.expect(&Token::Colon, "':' after parameter name")?;
@@ -144,6 +406,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_binding(&mut self) -> Result<Binding, ParserError> {
+        let start = self.here();
         let mutable = self.matches(&[Token::KwMut]);
         let name = self.expect_ident("binding name")?;
         self.expect(&Token::Colon, "':' after binding name")?;
@@ -155,13 +418,19 @@ impl<'a> Parser<'a> {
             name,
             ty,
             value,
+            span: self.since(start),
         })
     }
 
     fn parse_type(&mut self) -> Result<Type, ParserError> {
         if self.matches(&[Token::Amp]) {
+            let mutable = self.matches(&[Token::KwMut]);
             let inner = self.parse_type()?;
-            return Ok(Type::Ref(Box::new(inner)));
+            return Ok(if mutable {
+                Type::MutRef(Box::new(inner))
+            } else {
+                Type::Ref(Box::new(inner))
+            });
         }
 
         if self.matches(&[Token::LBrace]) {
@@ -172,7 +441,7 @@ impl<'a> Parser<'a> {
                     self.expect(&Token::Colon, "':' after field name")?;
                     let ty = self.parse_type()?;
                     fields.push(FieldType { name, ty });
-                    if self.matches(&[Token::Comma]) {
+                    if self.matches(&[Token::Comma]) && !self.trailing_comma(Token::RBrace) {
                         continue;
                     }
                     self.expect(&Token::RBrace, "'}' to close record type")?;
@@ -182,11 +451,32 @@ impl<'a> Parser<'a> {
             return Ok(Type::Record(fields));
         }
 
+        if self.matches(&[Token::LParen]) {
+            let mut params = Vec::new();
+            if !self.matches(&[Token::RParen]) {
+                loop {
+                    params.push(self.parse_type()?);
+                    if self.matches(&[Token::Comma]) {
+                        continue;
+                    }
+                    self.expect(&Token::RParen, "')' to close function parameter types")?;
+                    break;
+                }
+            }
+            self.expect(&Token::Arrow, "'->' in function type")?;
+            let ret = self.parse_type()?;
+            return Ok(Type::Func(FuncType {
+                params,
+                ret: Box::new(ret),
+            }));
+        }
+
         let name = self.expect_ident("type name")?;
         Ok(Type::Named(name))
     }
 
     fn parse_block(&mut self) -> Result<Block, ParserError> {
+        let start = self.here();
         self.expect(&Token::LBrace, "'{' to start block")?;
         let mut stmts = Vec::new();
         let mut tail = None;
@@ -199,6 +489,9 @@ impl<'a> Parser<'a> {
             if self.check(Token::Eof) {
                 return Err(ParserError::Eof);
             }
+            if self.check(Token::KwImport) {
+                return Err(ParserError::NestedImport(self.here()));
+            }
             let stmt = self.parse_stmt()?;
             if self.check(Token::RBrace) {
                 if let Stmt::Expr(e) = stmt {
@@ -212,40 +505,35 @@ impl<'a> Parser<'a> {
             stmts.push(stmt);
         }
 
-        Ok(Block { stmts, tail })
+        Ok(Block {
+            stmts,
+            tail,
+            span: self.since(start),
+        })
     }
 
     fn parse_stmt(&mut self) -> Result<Stmt, ParserError> {
         // binding starts with mut or ident followed by ':'
-        if self.matches(&[Token::KwMut]) {
-            // binding
-            let name = self.expect_ident("binding name")?;
-            self.expect(&Token::Colon, "':' after binding name")?;
-            let ty = self.parse_type()?;
-            self.expect(&Token::Assign, "'=' after binding type")?;
-            let value = self.parse_expr()?;
-            return Ok(Stmt::Binding(Binding {
-                mutable: true,
-                name,
-                ty,
-                value,
-            }));
-        }
-
-        if self.peek_is_ident() && self.peek_next_is(Token::Colon) {
+        if self.check(Token::KwMut) || (self.peek_is_ident() && self.peek_next_is(Token::Colon)) {
             let binding = self.parse_binding()?;
             return Ok(Stmt::Binding(binding));
         }
 
+        if self.looks_like_func_decl() {
+            return Ok(Stmt::Func(self.parse_func()?));
+        }
+
         // assignment: Path '=' Expr (but not '==')
         if self.peek_is_ident() {
             let save = self.pos;
             if let Ok(path) = self.try_parse_path() {
                 if self.matches(&[Token::Assign]) {
                     let value = self.parse_expr()?;
+                    let span = path.1.to(value.span());
                     return Ok(Stmt::Assign(Assign {
                         target: path,
                         value,
+                        span,
                     }));
                 }
             }
@@ -266,6 +554,7 @@ impl<'a> Parser<'a> {
         while self.matches(&[Token::OrOr]) {
             let right = self.parse_and()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 op: BinaryOp::Or,
                 right: Box::new(right),
@@ -279,6 +568,7 @@ impl<'a> Parser<'a> {
         while self.matches(&[Token::AndAnd]) {
             let right = self.parse_eq()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 op: BinaryOp::And,
                 right: Box::new(right),
@@ -289,11 +579,19 @@ impl<'a> Parser<'a> {
 
     fn parse_eq(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.parse_rel()?;
-        while self.matches(&[Token::EqEq]) {
+        loop {
+            let op = if self.matches(&[Token::EqEq]) {
+                BinaryOp::Eq
+            } else if self.matches(&[Token::BangEq]) {
+                BinaryOp::Ne
+            } else {
+                break;
+            };
             let right = self.parse_rel()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
-                op: BinaryOp::Eq,
+                op,
                 right: Box::new(right),
             });
         }
@@ -301,12 +599,89 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_rel(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.parse_bitor()?;
+        loop {
+            let op = if self.matches(&[Token::Lt]) {
+                BinaryOp::Lt
+            } else if self.matches(&[Token::Gt]) {
+                BinaryOp::Gt
+            } else if self.matches(&[Token::LtEq]) {
+                BinaryOp::Le
+            } else if self.matches(&[Token::GtEq]) {
+                BinaryOp::Ge
+            } else {
+                break;
+            };
+            let right = self.parse_bitor()?;
+            expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    // bitwise operators bind tighter than comparisons: |, ^, &, then shifts
+    fn parse_bitor(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.parse_bitxor()?;
+        while self.matches(&[Token::Pipe]) {
+            let right = self.parse_bitxor()?;
+            expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                op: BinaryOp::BitOr,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn parse_bitxor(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.parse_bitand()?;
+        while self.matches(&[Token::Caret]) {
+            let right = self.parse_bitand()?;
+            expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                op: BinaryOp::BitXor,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn parse_bitand(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.parse_shift()?;
+        // '&' after an operand is bitwise and; in prefix position it stays a reference
+        while self.matches(&[Token::Amp]) {
+            let right = self.parse_shift()?;
+            expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                op: BinaryOp::BitAnd,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expr, ParserError> {
         let mut expr = self.parse_add()?;
-        while self.matches(&[Token::Lt]) {
+        loop {
+            let op = if self.matches(&[Token::Shl]) {
+                BinaryOp::Shl
+            } else if self.matches(&[Token::Shr]) {
+                BinaryOp::Shr
+            } else {
+                break;
+            };
             let right = self.parse_add()?;
             expr = Expr::Binary(BinaryExpr {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
-                op: BinaryOp::Lt,
+                op,
                 right: Box::new(right),
             });
         }
@@ -319,6 +694,7 @@ impl<'a> Parser<'a> {
             if self.matches(&[Token::Plus]) {
                 let right = self.parse_mul()?;
                 expr = Expr::Binary(BinaryExpr {
+                    span: expr.span().to(right.span()),
                     left: Box::new(expr),
                     op: BinaryOp::Add,
                     right: Box::new(right),
@@ -326,6 +702,7 @@ impl<'a> Parser<'a> {
             } else if self.matches(&[Token::Minus]) {
                 let right = self.parse_mul()?;
                 expr = Expr::Binary(BinaryExpr {
+                    span: expr.span().to(right.span()),
                     left: Box::new(expr),
                     op: BinaryOp::Sub,
                     right: Box::new(right),
@@ -343,6 +720,7 @@ impl<'a> Parser<'a> {
             if self.matches(&[Token::Star]) {
                 let right = self.parse_unary()?;
                 expr = Expr::Binary(BinaryExpr {
+                    span: expr.span().to(right.span()),
                     left: Box::new(expr),
                     op: BinaryOp::Mul,
                     right: Box::new(right),
@@ -350,6 +728,7 @@ impl<'a> Parser<'a> {
             } else if self.matches(&[Token::Slash]) {
                 let right = self.parse_unary()?;
                 expr = Expr::Binary(BinaryExpr {
+                    span: expr.span().to(right.span()),
                     left: Box::new(expr),
                     op: BinaryOp::Div,
                     right: Box::new(right),
@@ -362,11 +741,13 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_unary(&mut self) -> Result<Expr, ParserError> {
+        let start = self.here();
         if self.matches(&[Token::Minus]) {
             let expr = self.parse_unary()?;
             return Ok(Expr::Unary(UnaryExpr {
                 op: UnaryOp::Neg,
                 expr: Box::new(expr),
+                span: self.since(start),
             }));
         }
         if self.matches(&[Token::Bang]) {
@@ -374,6 +755,7 @@ impl<'a> Parser<'a> {
             return Ok(Expr::Unary(UnaryExpr {
                 op: UnaryOp::Not,
                 expr: Box::new(expr),
+                span: self.since(start),
             }));
         }
         if self.matches(&[Token::KwCopy]) {
@@ -381,13 +763,19 @@ impl<'a> Parser<'a> {
             return Ok(Expr::Copy(Box::new(expr)));
         }
         if self.matches(&[Token::Amp]) {
+            let mutable = self.matches(&[Token::KwMut]);
             let expr = self.parse_unary()?;
-            return Ok(Expr::Ref(Box::new(expr)));
+            return Ok(if mutable {
+                Expr::MutRef(Box::new(expr))
+            } else {
+                Expr::Ref(Box::new(expr))
+            });
         }
         self.parse_if()
     }
 
     fn parse_if(&mut self) -> Result<Expr, ParserError> {
+        let start = self.here();
         if self.matches(&[Token::KwIf]) {
             let cond = self.parse_expr()?;
             self.expect(&Token::KwThen, "'then' in if expression")?;
@@ -398,6 +786,7 @@ impl<'a> Parser<'a> {
                 cond,
                 then_branch,
                 else_branch,
+                span: self.since(start),
             })));
         }
         self.parse_postfix()
@@ -414,6 +803,7 @@ impl<'a> Parser<'a> {
                     return Err(ParserError::UnexpectedToken {
                         expected: "callable path",
                         found: self.prev().clone(),
+                        span: self.prev_span(),
                     });
                 };
                 let args = if self.matches(&[Token::RParen]) {
@@ -422,7 +812,7 @@ impl<'a> Parser<'a> {
                     let mut args = Vec::new();
                     loop {
                         args.push(self.parse_expr()?);
-                        if self.matches(&[Token::Comma]) {
+                        if self.matches(&[Token::Comma]) && !self.trailing_comma(Token::RParen) {
                             continue;
                         }
                         self.expect(&Token::RParen, "')' after call args")?;
@@ -430,7 +820,45 @@ impl<'a> Parser<'a> {
                     }
                     args
                 };
-                expr = Expr::FuncCall(FuncCall { callee: path, args });
+                let span = self.since(path.1);
+                expr = Expr::FuncCall(FuncCall {
+                    callee: path,
+                    args,
+                    span,
+                });
+                continue;
+            }
+            if self.matches(&[Token::LBracket]) {
+                let start = if self.check(Token::DotDot) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                if !self.matches(&[Token::DotDot]) {
+                    self.expect(&Token::RBracket, "']' after index")?;
+                    expr = Expr::Index(IndexExpr {
+                        span: self.since(expr.span()),
+                        base: Box::new(expr),
+                        index: start.ok_or(ParserError::UnexpectedToken {
+                            expected: "index",
+                            found: Token::RBracket,
+                            span: self.prev_span(),
+                        })?,
+                    });
+                    continue;
+                }
+                let end = if self.check(Token::RBracket) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expr()?))
+                };
+                self.expect(&Token::RBracket, "']' after slice")?;
+                expr = Expr::Slice(SliceExpr {
+                    span: self.since(expr.span()),
+                    base: Box::new(expr),
+                    start,
+                    end,
+                });
                 continue;
             }
             break;
@@ -439,21 +867,27 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_primary(&mut self) -> Result<Expr, ParserError> {
+        let start = self.here();
         match self.advance() {
+            // `time` is only a keyword right before a block, so it stays usable as a name
+            Token::Ident(name) if name == "time" && self.check(Token::LBrace) => {
+                let body = self.parse_block()?;
+                Ok(Expr::Time(Box::new(Expr::Block(body))))
+            }
             Token::Ident(name) => {
                 let mut idents = vec![Ident(name)];
                 while self.matches(&[Token::Dot]) {
                     let seg = self.expect_ident("path segment")?;
                     idents.push(seg);
                 }
-                Ok(Expr::Path(Path(idents)))
+                Ok(Expr::Path(Path(idents, self.since(start))))
             }
-            Token::Int(v) => Ok(Expr::Literal(Literal::Int(v))),
-            Token::Str(s) => Ok(Expr::Literal(Literal::Str(s))),
-            Token::Bool(b) => Ok(Expr::Literal(Literal::Bool(b))),
+            Token::Int(v) => Ok(Expr::Literal(Literal::Int(v), start)),
+            Token::Str(s) => Ok(Expr::Literal(Literal::Str(s), start)),
+            Token::Bool(b) => Ok(Expr::Literal(Literal::Bool(b), start)),
             Token::LParen => {
                 if self.matches(&[Token::RParen]) {
-                    return Ok(Expr::Literal(Literal::Unit));
+                    return Ok(Expr::Literal(Literal::Unit, self.since(start)));
                 }
                 let expr = self.parse_expr()?;
                 self.expect(&Token::RParen, "')' after expression")?;
@@ -466,22 +900,41 @@ impl<'a> Parser<'a> {
                     return Ok(Expr::Block(Block {
                         stmts: Vec::new(),
                         tail: None,
+                        span: self.since(start),
                     }));
                 }
-                if self.looks_like_record_literal() {
+                let base = if self.matches(&[Token::DotDot]) {
+                    let base = self.parse_expr()?;
+                    if !self.matches(&[Token::Comma]) || self.trailing_comma(Token::RBrace) {
+                        self.expect(&Token::RBrace, "'}' after record base")?;
+                        return Ok(Expr::RecordLit(RecordLit {
+                            base: Some(Box::new(base)),
+                            fields: Vec::new(),
+                            span: self.since(start),
+                        }));
+                    }
+                    Some(Box::new(base))
+                } else {
+                    None
+                };
+                if base.is_some() || self.looks_like_record_literal() {
                     let mut fields = Vec::new();
                     loop {
                         let name = self.expect_ident("field name")?;
                         self.expect(&Token::Colon, "':' after field name")?;
                         let value = self.parse_expr()?;
                         fields.push(FieldInit { name, value });
-                        if self.matches(&[Token::Comma]) {
+                        if self.matches(&[Token::Comma]) && !self.trailing_comma(Token::RBrace) {
                             continue;
                         }
                         self.expect(&Token::RBrace, "'}' after record literal")?;
                         break;
                     }
-                    Ok(Expr::RecordLit(RecordLit { fields }))
+                    Ok(Expr::RecordLit(RecordLit {
+                        base,
+                        fields,
+                        span: self.since(start),
+                    }))
                 } else {
                     // rewind by one to let parse_block consume '{'
                     self.pos -= 1;
@@ -491,12 +944,14 @@ impl<'a> Parser<'a> {
             other => Err(ParserError::UnexpectedToken {
                 expected: "expression",
                 found: other,
+                span: start,
             }),
         }
     }
 
     // --- path helper ---
     fn try_parse_path(&mut self) -> Result<Path, ParserError> {
+        let start = self.here();
         let mut idents = Vec::new();
         let first = self.expect_ident("path start")?;
         idents.push(first);
@@ -504,7 +959,7 @@ impl<'a> Parser<'a> {
             let ident = self.expect_ident("path segment")?;
             idents.push(ident);
         }
-        Ok(Path(idents))
+        Ok(Path(idents, self.since(start)))
     }
 
     // --- token helpers ---
@@ -518,6 +973,12 @@ impl<'a> Parser<'a> {
         false
     }
 
+    /// After a comma: whether it was a trailing one, right before `close`, and those are
+    /// allowed. The closing token is left for the caller to expect.
+    fn trailing_comma(&self, close: Token) -> bool {
+        self.options.trailing_commas && self.check(close)
+    }
+
     fn check(&self, token: Token) -> bool {
         self.peek() == &token
     }
@@ -548,6 +1009,27 @@ impl<'a> Parser<'a> {
             .unwrap_or(&Token::Eof)
     }
 
+    /// Span of the next token; the end of the input once every token is read.
+    fn here(&self) -> Span {
+        self.spans
+            .get(self.pos)
+            .or(self.spans.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn prev_span(&self) -> Span {
+        self.spans
+            .get(self.pos.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// From `start` to the end of the last token read.
+    fn since(&self, start: Span) -> Span {
+        start.to(self.prev_span())
+    }
+
     fn expect(&mut self, token: &Token, msg: &'static str) -> Result<(), ParserError> {
         if self.check(token.clone()) {
             self.advance();
@@ -556,6 +1038,7 @@ impl<'a> Parser<'a> {
             Err(ParserError::UnexpectedToken {
                 expected: msg,
                 found: self.peek().clone(),
+                span: self.here(),
             })
         }
     }
@@ -566,10 +1049,36 @@ impl<'a> Parser<'a> {
             other => Err(ParserError::UnexpectedToken {
                 expected: msg,
                 found: other,
+                span: self.prev_span(),
             }),
         }
     }
 
+    /// `name(...)` followed by `->` or `=` declares a function; otherwise it is a call.
+    fn looks_like_func_decl(&self) -> bool {
+        if !self.peek_is_ident() || !self.peek_next_is(Token::LParen) {
+            return false;
+        }
+        let mut depth = 0usize;
+        for (i, tok) in self.tokens.iter().enumerate().skip(self.pos + 1) {
+            match tok {
+                Token::LParen => depth += 1,
+                Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(
+                            self.tokens.get(i + 1),
+                            Some(Token::Arrow | Token::Assign)
+                        );
+                    }
+                }
+                Token::Eof => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
     fn looks_like_record_literal(&self) -> bool {
         // Assumes current position is just after '{'
         let mut idx = self.pos;
@@ -587,22 +1096,18 @@ impl<'a> Parser<'a> {
             return false;
         }
         idx += 2;
-        let mut depth_paren = 0usize;
-        let mut depth_brace = 0usize;
+        // the first field's value ends at a ',' or '}' outside any brackets; a block
+        // binding `name: Type = ...` reaches its '=' first. A trailing comma is just
+        // the first kind of end.
+        let mut depth = 0usize;
         while let Some(tok) = self.tokens.get(idx) {
             match tok {
-                Token::LParen => depth_paren += 1,
-                Token::RParen => depth_paren = depth_paren.saturating_sub(1),
-                Token::LBrace => depth_brace += 1,
-                Token::RBrace => {
-                    if depth_brace == 0 && depth_paren == 0 {
-                        // reached end of first field
-                        return true;
-                    }
-                    depth_brace = depth_brace.saturating_sub(1);
-                }
-                Token::Comma if depth_brace == 0 && depth_paren == 0 => return true,
-                Token::Assign if depth_brace == 0 && depth_paren == 0 => return false,
+                Token::LParen | Token::LBrace | Token::LBracket => depth += 1,
+                Token::RBrace if depth == 0 => return true,
+                Token::RParen | Token::RBrace | Token::RBracket => depth = depth.saturating_sub(1),
+                Token::Comma if depth == 0 => return true,
+                Token::Assign if depth == 0 => return false,
+                Token::Eof => return false,
                 _ => {}
             }
             idx += 1;
@@ -611,196 +1116,54 @@ impl<'a> Parser<'a> {
     }
 }
 
-// --- lexer ---
-fn lex(src: &str) -> Result<Vec<Token>, ParserError> {
-    let mut tokens = Vec::new();
-    let mut chars = src.chars().peekable();
+/// Tokenize `src` into tokens and their spans. With `docs`, the text of each run of
+/// `///` lines is recorded under the index of the token after it; other comments are
+/// always dropped.
+fn lex(
+    src: &str,
+    docs: Option<&mut HashMap<usize, String>>,
+) -> Result<(Vec<Token>, Vec<Span>), ParserError> {
+    let lexer = match docs {
+        Some(_) => Lexer::with_comments(src),
+        None => Lexer::new(src),
+    };
+    collect_tokens(lexer, docs)
+}
 
-    while let Some(&ch) = chars.peek() {
-        match ch {
-            c if c.is_whitespace() => {
-                chars.next();
-            }
-            '/' => {
-                chars.next();
-                if chars.peek() == Some(&'/') {
-                    while let Some(&c) = chars.peek() {
-                        chars.next();
-                        if c == '\n' {
-                            break;
-                        }
-                    }
-                } else {
-                    tokens.push(Token::Slash);
-                }
-            }
-            '{' => {
-                chars.next();
-                tokens.push(Token::LBrace);
-            }
-            '}' => {
-                chars.next();
-                tokens.push(Token::RBrace);
-            }
-            '(' => {
-                chars.next();
-                tokens.push(Token::LParen);
-            }
-            ')' => {
-                chars.next();
-                tokens.push(Token::RParen);
-            }
-            ':' => {
-                chars.next();
-                tokens.push(Token::Colon);
-            }
-            ',' => {
-                chars.next();
-                tokens.push(Token::Comma);
-            }
-            '.' => {
-                chars.next();
-                tokens.push(Token::Dot);
-            }
-            '+' => {
-                chars.next();
-                tokens.push(Token::Plus);
-            }
-            '*' => {
-                chars.next();
-                tokens.push(Token::Star);
-            }
-            '<' => {
-                chars.next();
-                tokens.push(Token::Lt);
-            }
-            '!' => {
-                chars.next();
-                tokens.push(Token::Bang);
-            }
-            '=' => {
-                chars.next();
-                if chars.peek() == Some(&'=') {
-                    chars.next();
-                    tokens.push(Token::EqEq);
-                } else if chars.peek() == Some(&'>') {
-                    // not in grammar, ignore
-                } else {
-                    tokens.push(Token::Assign);
-                }
-            }
-            '-' => {
-                chars.next();
-                if chars.peek() == Some(&'>') {
-                    chars.next();
-                    tokens.push(Token::Arrow);
-                } else {
-                    tokens.push(Token::Minus);
-                }
-            }
-            '|' => {
-                chars.next();
-                if chars.peek() == Some(&'|') {
-                    chars.next();
-                    tokens.push(Token::OrOr);
-                } else {
-                    return Err(ParserError::Lexer("unexpected '|'".into()));
-                }
-            }
-            '&' => {
-                chars.next();
-                if chars.peek() == Some(&'&') {
-                    chars.next();
-                    tokens.push(Token::AndAnd);
-                } else {
-                    tokens.push(Token::Amp);
-                }
-            }
-            '"' => {
-                chars.next();
-                let mut s = String::new();
-                let mut closed = false;
-                while let Some(c) = chars.next() {
-                    if c == '"' {
-                        closed = true;
-                        break;
-                    }
-                    if c == '\\' {
-                        let Some(esc) = chars.next() else {
-                            return Err(ParserError::Lexer("unterminated string escape".into()));
-                        };
-                        match esc {
-                            'n' => s.push('\n'),
-                            't' => s.push('\t'),
-                            'r' => s.push('\r'),
-                            '"' => s.push('"'),
-                            '\\' => s.push('\\'),
-                            other => s.push(other),
-                        }
-                        continue;
-                    }
-                    s.push(c);
-                }
-                if !closed {
-                    return Err(ParserError::Lexer("unterminated string literal".into()));
-                }
-                tokens.push(Token::Str(s));
-            }
-            '0'..='9' => {
-                let mut num = String::new();
-                while let Some(&c) = chars.peek() {
-                    if c.is_ascii_digit() {
-                        num.push(c);
-                        chars.next();
-                    } else {
-                        break;
-                    }
-                }
-                let val: i64 = num
-                    .parse()
-                    .map_err(|_| ParserError::InvalidNumber(num.clone()))?;
-                tokens.push(Token::Int(val));
-            }
-            c if is_ident_start(c) => {
-                let mut ident = String::new();
-                ident.push(c);
-                chars.next();
-                while let Some(&c2) = chars.peek() {
-                    if is_ident_continue(c2) {
-                        ident.push(c2);
-                        chars.next();
-                    } else {
-                        break;
-                    }
-                }
-                let tok = match ident.as_str() {
-                    "import" => Token::KwImport,
-                    "global" => Token::KwGlobal,
-                    "mut" => Token::KwMut,
-                    "type" => Token::KwType,
-                    "if" => Token::KwIf,
-                    "then" => Token::KwThen,
-                    "else" => Token::KwElse,
-                    "copy" => Token::KwCopy,
-                    "true" => Token::Bool(true),
-                    "false" => Token::Bool(false),
-                    _ => Token::Ident(ident),
-                };
-                tokens.push(tok);
+fn collect_tokens(
+    lexer: Lexer<'_>,
+    mut docs: Option<&mut HashMap<usize, String>>,
+) -> Result<(Vec<Token>, Vec<Span>), ParserError> {
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    for spanned in lexer {
+        let SpannedToken { token, span } = spanned?;
+        if let Token::Comment(line) = token {
+            if let (Some(docs), Some(text)) = (
+                docs.as_deref_mut(),
+                line.strip_prefix('/').filter(|t| !t.starts_with('/')),
+            ) {
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                docs.entry(tokens.len())
+                    .and_modify(|d: &mut String| {
+                        d.push('\n');
+                        d.push_str(text);
+                    })
+                    .or_insert_with(|| text.to_string());
             }
-            c => return Err(ParserError::Lexer(format!("unexpected char '{}'", c))),
+            continue;
         }
+        tokens.push(token);
+        spans.push(span);
     }
-    tokens.push(Token::Eof);
-    Ok(tokens)
+    Ok((tokens, spans))
 }
 
-fn is_ident_start(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
-}
-
-fn is_ident_continue(c: char) -> bool {
-    c.is_ascii_alphanumeric() || c == '_'
+fn ensure_self_param(method: &Ident, params: &[Param], span: Span) -> Result<(), ParserError> {
+    match params.first() {
+        Some(p) if p.name.0 == "self" => Ok(()),
+        _ => Err(ParserError::MissingSelf(method.0.clone(), span)),
+    }
 }
 
 #[cfg(test)]
@@ -878,4 +1241,314 @@ mod tests {
         let err = parser.parse_program().unwrap_err();
         assert!(matches!(err, ParserError::UnexpectedToken { .. }));
     }
+
+    #[test]
+    fn parse_comparison_operators() {
+        let src = "main() = a + 1 >= b != c <= d";
+        let program = parse_ok(src);
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        let Expr::Binary(ne) = &f.body else {
+            panic!("expected binary");
+        };
+        assert_eq!(ne.op, BinaryOp::Ne);
+        assert!(matches!(&*ne.left, Expr::Binary(b) if b.op == BinaryOp::Ge));
+        assert!(matches!(&*ne.right, Expr::Binary(b) if b.op == BinaryOp::Le));
+    }
+
+    #[test]
+    fn parse_bitwise_vs_ref() {
+        let src = "main() = f(&x) | a & b << 2 ^ 1";
+        let program = parse_ok(src);
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        let Expr::Binary(or) = &f.body else {
+            panic!("expected binary");
+        };
+        assert_eq!(or.op, BinaryOp::BitOr);
+        assert!(matches!(&*or.left, Expr::FuncCall(c) if matches!(c.args[0], Expr::Ref(_))));
+        let Expr::Binary(xor) = &*or.right else {
+            panic!("expected xor");
+        };
+        assert_eq!(xor.op, BinaryOp::BitXor);
+        assert!(matches!(&*xor.left, Expr::Binary(b) if b.op == BinaryOp::BitAnd));
+    }
+
+    #[test]
+    fn parse_function_type() {
+        let src = "apply(f: (i32, i32) -> i32, g: () -> Unit) -> i32 = f(1, 2)";
+        let program = parse_ok(src);
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        let Type::Func(ft) = &f.params[0].ty else {
+            panic!("expected function type");
+        };
+        assert_eq!(ft.params.len(), 2);
+        assert_eq!(*ft.ret, Type::Named(Ident("i32".into())));
+        assert!(matches!(&f.params[1].ty, Type::Func(g) if g.params.is_empty()));
+    }
+
+    #[test]
+    fn parse_mut_refs() {
+        let program = parse_ok("bump(c: &mut Counter, n: &i32) = f(&mut c.hits, &x)");
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        assert_eq!(
+            f.params[0].ty,
+            Type::MutRef(Box::new(Type::Named(Ident("Counter".into()))))
+        );
+        assert!(matches!(f.params[1].ty, Type::Ref(_)));
+        let Expr::FuncCall(call) = &f.body else {
+            panic!("expected call");
+        };
+        assert!(
+            matches!(&call.args[0], Expr::MutRef(p) if matches!(&**p, Expr::Path(p) if p.0.len() == 2))
+        );
+        assert!(matches!(call.args[1], Expr::Ref(_)));
+    }
+
+    #[test]
+    fn import_only_at_top_level() {
+        let program = parse_ok("main() = 0\nimport later");
+        assert!(matches!(program.decls[1], Decl::Import(_)));
+        let err = Parser::new("main() = {\n  import foo\n  0\n}")
+            .unwrap()
+            .parse_program()
+            .unwrap_err();
+        assert!(matches!(err, ParserError::NestedImport(_)));
+    }
+
+    #[test]
+    fn parse_record_spread() {
+        let program = parse_ok("main() = { ..copy p, x: 5 }\nf() = { ..q }");
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        let Expr::RecordLit(r) = &f.body else {
+            panic!("expected record literal");
+        };
+        assert!(matches!(r.base.as_deref(), Some(Expr::Copy(_))));
+        assert_eq!(r.fields.len(), 1);
+        assert!(matches!(&program.decls[1], Decl::Func(g)
+            if matches!(&g.body, Expr::RecordLit(r) if r.base.is_some() && r.fields.is_empty())));
+    }
+
+    #[test]
+    fn parse_derive_attribute() {
+        let program = parse_ok("@derive(show, eq)\ntype P = { x: i32 }");
+        let Decl::Type(t) = &program.decls[0] else {
+            panic!("expected type");
+        };
+        let names: Vec<String> = t.derived_fn_names().collect();
+        assert_eq!(names, ["P_show", "P_eq"]);
+        assert!(Parser::new("@derive(show)\nmain() = 0")
+            .unwrap()
+            .parse_program()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_index() {
+        let program = parse_ok("main() = -s[i + 1]");
+        let Decl::Func(f) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        let Expr::Unary(u) = &f.body else {
+            panic!("expected negation");
+        };
+        assert!(matches!(&*u.expr, Expr::Index(ix)
+            if matches!(&*ix.base, Expr::Path(_)) && matches!(&*ix.index, Expr::Binary(_))));
+    }
+
+    #[test]
+    fn parse_slices() {
+        let program = parse_ok("a() = s[1..n + 1]\nb() = s[..2]\nc() = s[i..]\nd() = s[..]");
+        let bounds: Vec<(bool, bool)> = program
+            .decls
+            .iter()
+            .map(|d| match d {
+                Decl::Func(f) => match &f.body {
+                    Expr::Slice(sl) => (sl.start.is_some(), sl.end.is_some()),
+                    other => panic!("expected slice, got {other:?}"),
+                },
+                _ => panic!("expected function"),
+            })
+            .collect();
+        assert_eq!(
+            bounds,
+            [(true, true), (false, true), (true, false), (false, false)]
+        );
+        assert!(Parser::new("main() = s[]")
+            .unwrap()
+            .parse_program()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_time_block() {
+        let program = parse_ok(
+            "a() = time { f() }
+b() = time + 1",
+        );
+        let Decl::Func(a) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        assert!(matches!(&a.body, Expr::Time(body) if matches!(&**body, Expr::Block(_))));
+        let Decl::Func(b) = &program.decls[1] else {
+            panic!("expected function");
+        };
+        assert!(matches!(&b.body, Expr::Binary(_)));
+    }
+
+    #[test]
+    fn tokens_and_nodes_carry_spans() {
+        let (tokens, spans) = lex("a\n  \"é\" + 10", None).unwrap();
+        assert_eq!(tokens.len(), spans.len());
+        let at = |i: usize| (spans[i].start, spans[i].end, spans[i].line, spans[i].col);
+        assert_eq!(at(0), (0, 1, 1, 1));
+        assert_eq!(at(1), (4, 8, 2, 3));
+        // columns count chars, offsets count bytes
+        assert_eq!(at(2), (9, 10, 2, 7));
+        assert_eq!(at(3), (11, 13, 2, 9));
+
+        let program = parse_ok("main() = {\n  x: i32 = 1\n  f(copy x, 2) + 3\n}");
+        let Decl::Func(main) = &program.decls[0] else {
+            panic!("expected function");
+        };
+        assert_eq!((main.span.line, main.span.start, main.span.end), (1, 0, 44));
+        let Expr::Block(body) = &main.body else {
+            panic!("expected block");
+        };
+        let stmt = body.stmts[0].span();
+        assert_eq!((stmt.line, stmt.col, stmt.end - stmt.start), (2, 3, 10));
+        let Some(Expr::Binary(sum)) = body.tail.as_deref() else {
+            panic!("expected binary tail");
+        };
+        assert_eq!((sum.span.line, sum.span.col), (3, 3));
+        assert_eq!(sum.left.span().end - sum.left.span().start, 12);
+        assert_eq!(sum.right.span().col, 18);
+
+        let err = Parser::new("main() = {\n  1 +\n}")
+            .unwrap()
+            .parse_program()
+            .unwrap_err();
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((3, 1)));
+        assert_eq!(
+            err.to_string(),
+            "unexpected token: expected expression, found RBrace"
+        );
+    }
+
+    #[test]
+    fn parse_program_reports_every_broken_declaration() {
+        let src = "a() = {\n  1 +\n}\nb() = 2\nc( = 3\nd() = 4 )\n";
+        let mut parser = Parser::new(src).unwrap();
+        assert!(parser.parse_program().is_err());
+        let found: Vec<(u32, u32)> = parser
+            .diagnostics()
+            .iter()
+            .map(|d| (d.span.line, d.span.col))
+            .collect();
+        assert_eq!(found, [(3, 1), (5, 4), (6, 9)]);
+        assert!(parser.diagnostics()[1].message.contains("parameter"));
+
+        let mut ok = Parser::new("main() = 1").unwrap();
+        ok.parse_program().unwrap();
+        assert!(ok.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn lex_raw_strings() {
+        let src = "r\"a\\n\" \"\"\"\nx \"y\"\n\\z\"\"\" r";
+        assert_eq!(
+            lex(src, None).unwrap().0,
+            [
+                Token::Str("a\\n".into()),
+                Token::Str("x \"y\"\n\\z".into()),
+                Token::Ident("r".into()),
+                Token::Eof,
+            ]
+        );
+        assert!(lex("r\"open", None).is_err());
+        assert!(lex("\"\"\"open\"\"", None).is_err());
+    }
+
+    #[test]
+    fn doc_comments_attach_when_requested() {
+        let src = "/// A point.\n/// Two ints.\n@derive(show)\ntype P = { x: i32 }\n\
+                   trait T {\n  /// Name it.\n  name(&self) -> Str\n}\n\
+                   // plain\nf() = 0\n//// banner\n/// Entry.\nmain() = {\n  /// Local.\n  g() = 1\n  g()\n}";
+        let program = Parser::with_doc_comments(src)
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let Decl::Type(t) = &program.decls[0] else {
+            panic!("expected type");
+        };
+        assert_eq!(t.doc.as_deref(), Some("A point.\nTwo ints."));
+        let Decl::Trait(tr) = &program.decls[1] else {
+            panic!("expected trait");
+        };
+        assert_eq!(tr.methods[0].doc.as_deref(), Some("Name it."));
+        let Decl::Func(f) = &program.decls[2] else {
+            panic!("expected function");
+        };
+        assert_eq!(f.doc, None);
+        let Decl::Func(main) = &program.decls[3] else {
+            panic!("expected function");
+        };
+        assert_eq!(main.doc.as_deref(), Some("Entry."));
+        let Expr::Block(b) = &main.body else {
+            panic!("expected block");
+        };
+        assert!(matches!(&b.stmts[0], Stmt::Func(g) if g.doc.as_deref() == Some("Local.")));
+
+        let plain = parse_ok(src);
+        assert!(matches!(&plain.decls[0], Decl::Type(t) if t.doc.is_none()));
+    }
+
+    #[test]
+    fn trailing_commas_are_accepted_when_enabled() {
+        let lenient = |src| {
+            Parser::new(src)
+                .unwrap()
+                .with_options(ParserOptions {
+                    trailing_commas: true,
+                })
+                .parse_program()
+        };
+        let src = "type P = { x: i32, y: i32, }
+\
+            mk(x: i32, y: i32,) -> P = { x: x, y: y, }\n\
+            main() = {\n  p: P = mk(1, 2,)\n  q: P = { ..p, }\n  copy q.x\n}";
+        let strict = "type P = { x: i32, y: i32 }\n\
+            mk(x: i32, y: i32) -> P = { x: x, y: y }\n\
+            main() = {\n  p: P = mk(1, 2)\n  q: P = { ..p }\n  copy q.x\n}";
+        assert_eq!(lenient(src).unwrap(), parse_ok(strict));
+        assert!(Parser::new(src).unwrap().parse_program().is_err());
+        // only one comma, and only right before the closing token
+        assert!(lenient("f() = g(1,,)").is_err());
+        assert!(lenient("f(,) = 1").is_err());
+    }
+
+    #[test]
+    fn record_literals_and_blocks_are_told_apart_with_trailing_commas() {
+        let program = Parser::new("f() = { a: g(1, s[0]), }\ng() = { p: { a: i32, } = h()\n p }")
+            .unwrap()
+            .with_options(ParserOptions {
+                trailing_commas: true,
+            })
+            .parse_program()
+            .unwrap();
+        let body = |i: usize| match &program.decls[i] {
+            Decl::Func(f) => &f.body,
+            other => panic!("expected a function, got {other:?}"),
+        };
+        assert!(matches!(body(0), Expr::RecordLit(_)));
+        assert!(matches!(body(1), Expr::Block(_)));
+    }
 }
@@ -0,0 +1,485 @@
+#![forbid(unsafe_code)]
+
+//! Renders an AST back to gaut source in one canonical layout: two-space indentation,
+//! one statement per line, blocks always broken over lines, and parentheses only where
+//! precedence needs them. Parsing the output gives back an equal tree.
+//!
+//! `///` doc comments are kept when the program was parsed with
+//! `Parser::with_doc_comments`; other comments are not part of the AST and are lost.
+
+use crate::ast::*;
+
+const INDENT: &str = "  ";
+
+/// Source for `program`, ending in a newline.
+pub fn print_program(program: &Program) -> String {
+    let mut p = Printer::default();
+    let mut prev: Option<&Decl> = None;
+    for decl in &program.decls {
+        if let Some(prev) = prev {
+            // runs of imports and of top-level bindings stay together
+            let grouped = matches!(
+                (prev, decl),
+                (Decl::Import(_), Decl::Import(_))
+                    | (
+                        Decl::Global(_) | Decl::Let(_),
+                        Decl::Global(_) | Decl::Let(_)
+                    )
+            );
+            if !grouped {
+                p.out.push('\n');
+            }
+        }
+        p.decl(decl);
+        prev = Some(decl);
+    }
+    p.out
+}
+
+/// Source for a single expression, laid out as it would be at the top level.
+pub fn print_expr(expr: &Expr) -> String {
+    let mut p = Printer::default();
+    p.expr(expr);
+    p.out
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn line_start(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn doc(&mut self, doc: &Option<String>) {
+        let Some(doc) = doc else {
+            return;
+        };
+        for line in doc.split('\n') {
+            self.line_start();
+            if line.is_empty() {
+                self.out.push_str("///\n");
+            } else {
+                self.out.push_str("/// ");
+                self.out.push_str(line);
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Import(i) => {
+                self.out.push_str("import ");
+                self.out.push_str(&i.module.0);
+                self.out.push('\n');
+            }
+            Decl::Global(b) => {
+                self.out.push_str("global ");
+                self.binding(b);
+                self.out.push('\n');
+            }
+            Decl::Let(b) => {
+                self.binding(b);
+                self.out.push('\n');
+            }
+            Decl::Type(t) => {
+                self.doc(&t.doc);
+                if !t.derives.is_empty() {
+                    let names: Vec<&str> = t.derives.iter().map(|d| d.0.as_str()).collect();
+                    self.out
+                        .push_str(&format!("@derive({})\n", names.join(", ")));
+                }
+                self.out
+                    .push_str(&format!("type {} = {}\n", t.name.0, t.ty));
+            }
+            Decl::Func(f) => self.func(f),
+            Decl::Trait(t) => {
+                self.doc(&t.doc);
+                self.out.push_str(&format!("trait {} {{\n", t.name.0));
+                self.depth += 1;
+                for m in &t.methods {
+                    self.doc(&m.doc);
+                    self.line_start();
+                    self.out.push_str(&m.name.0);
+                    self.params(&m.params);
+                    self.out.push_str(&format!(" -> {}\n", m.ret));
+                }
+                self.depth -= 1;
+                self.out.push_str("}\n");
+            }
+            Decl::Impl(i) => {
+                self.doc(&i.doc);
+                self.out
+                    .push_str(&format!("impl {} for {} {{\n", i.trait_name.0, i.ty.0));
+                self.depth += 1;
+                for m in &i.methods {
+                    self.func(m);
+                }
+                self.depth -= 1;
+                self.out.push_str("}\n");
+            }
+            Decl::Macro(m) => {
+                self.doc(&m.doc);
+                let params: Vec<&str> = m.params.iter().map(|p| p.0.as_str()).collect();
+                self.out
+                    .push_str(&format!("macro {}({}) = ", m.name.0, params.join(", ")));
+                self.expr(&m.body);
+                self.out.push('\n');
+            }
+        }
+    }
+
+    /// A function declaration on its own line(s) at the current depth.
+    fn func(&mut self, f: &FuncDecl) {
+        self.doc(&f.doc);
+        self.line_start();
+        self.out.push_str(&f.name.0);
+        self.params(&f.params);
+        if let Some(ret) = &f.ret {
+            self.out.push_str(&format!(" -> {ret}"));
+        }
+        self.out.push_str(" = ");
+        self.expr(&f.body);
+        self.out.push('\n');
+    }
+
+    fn params(&mut self, params: &[Param]) {
+        let rendered: Vec<String> = params
+            .iter()
+            .map(|p| {
+                if p.name.0 == "self" {
+                    // receivers are written without their `Self` annotation
+                    match &p.ty {
+                        Type::Ref(_) => return "&self".to_string(),
+                        Type::MutRef(_) => return "&mut self".to_string(),
+                        Type::Named(id) if id.0 == "Self" => return "self".to_string(),
+                        _ => {}
+                    }
+                }
+                let m = if p.mutable { "mut " } else { "" };
+                format!("{m}{}: {}", p.name.0, p.ty)
+            })
+            .collect();
+        self.out.push('(');
+        self.out.push_str(&rendered.join(", "));
+        self.out.push(')');
+    }
+
+    fn binding(&mut self, b: &Binding) {
+        if b.mutable {
+            self.out.push_str("mut ");
+        }
+        self.out.push_str(&format!("{}: {} = ", b.name.0, b.ty));
+        self.expr(&b.value);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Binding(b) => {
+                self.line_start();
+                self.binding(b);
+            }
+            Stmt::Assign(a) => {
+                self.line_start();
+                self.out.push_str(&path(&a.target));
+                self.out.push_str(" = ");
+                self.expr(&a.value);
+            }
+            Stmt::Expr(e) => {
+                self.line_start();
+                self.expr(e);
+            }
+            Stmt::Func(f) => {
+                self.func(f);
+                return;
+            }
+        }
+        self.out.push('\n');
+    }
+
+    fn block(&mut self, block: &Block) {
+        if block.stmts.is_empty() && block.tail.is_none() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.depth += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.line_start();
+            self.expr(tail);
+            self.out.push('\n');
+        }
+        self.depth -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(l, _) => self.literal(l),
+            Expr::Path(p) => self.out.push_str(&path(p)),
+            Expr::Copy(inner) => {
+                self.out.push_str("copy ");
+                self.operand(inner, UNARY);
+            }
+            Expr::Ref(inner) | Expr::MutRef(inner) => {
+                self.out.push_str(if matches!(expr, Expr::Ref(_)) {
+                    "&"
+                } else {
+                    "&mut "
+                });
+                // `& &x` must not run together into `&&`
+                if matches!(**inner, Expr::Ref(_) | Expr::MutRef(_)) {
+                    self.parens(inner);
+                } else {
+                    self.operand(inner, UNARY);
+                }
+            }
+            Expr::FuncCall(call) => {
+                self.out.push_str(&path(&call.callee));
+                self.out.push('(');
+                for (i, arg) in call.args.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expr(arg);
+                }
+                self.out.push(')');
+            }
+            Expr::If(i) => {
+                self.out.push_str("if ");
+                self.expr(&i.cond);
+                self.out.push_str(" then ");
+                self.expr(&i.then_branch);
+                self.out.push_str(" else ");
+                self.expr(&i.else_branch);
+            }
+            Expr::Block(b) => self.block(b),
+            Expr::RecordLit(r) => {
+                self.out.push_str("{ ");
+                let mut first = true;
+                if let Some(base) = &r.base {
+                    self.out.push_str("..");
+                    self.expr(base);
+                    first = false;
+                }
+                for field in &r.fields {
+                    if !first {
+                        self.out.push_str(", ");
+                    }
+                    first = false;
+                    self.out.push_str(&field.name.0);
+                    self.out.push_str(": ");
+                    self.expr(&field.value);
+                }
+                self.out.push_str(" }");
+            }
+            Expr::Unary(u) => {
+                self.out.push(match u.op {
+                    UnaryOp::Neg => '-',
+                    UnaryOp::Not => '!',
+                });
+                self.operand(&u.expr, UNARY);
+            }
+            Expr::Binary(b) => {
+                let prec = binary_prec(&b.op);
+                // operators are left-associative: a right operand at the same level
+                // needs parentheses
+                self.operand(&b.left, prec);
+                self.out.push(' ');
+                self.out.push_str(binary_op(&b.op));
+                self.out.push(' ');
+                self.operand(&b.right, prec + 1);
+            }
+            Expr::Index(i) => {
+                self.operand(&i.base, POSTFIX);
+                self.out.push('[');
+                self.expr(&i.index);
+                self.out.push(']');
+            }
+            Expr::Slice(s) => {
+                self.operand(&s.base, POSTFIX);
+                self.out.push('[');
+                if let Some(start) = &s.start {
+                    self.expr(start);
+                }
+                self.out.push_str("..");
+                if let Some(end) = &s.end {
+                    self.expr(end);
+                }
+                self.out.push(']');
+            }
+            Expr::Time(body) => {
+                self.out.push_str("time ");
+                self.expr(body);
+            }
+        }
+    }
+
+    /// `expr` where the grammar needs at least precedence `min`.
+    fn operand(&mut self, expr: &Expr, min: u8) {
+        if expr_prec(expr) < min {
+            self.parens(expr);
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    fn parens(&mut self, expr: &Expr) {
+        self.out.push('(');
+        self.expr(expr);
+        self.out.push(')');
+    }
+
+    fn literal(&mut self, l: &Literal) {
+        match l {
+            Literal::Int(v) => self.out.push_str(&v.to_string()),
+            Literal::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Literal::Str(s) => {
+                self.out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '\n' => self.out.push_str("\\n"),
+                        '\t' => self.out.push_str("\\t"),
+                        '\r' => self.out.push_str("\\r"),
+                        '"' => self.out.push_str("\\\""),
+                        '\\' => self.out.push_str("\\\\"),
+                        other => self.out.push(other),
+                    }
+                }
+                self.out.push('"');
+            }
+            Literal::Unit => self.out.push_str("()"),
+        }
+    }
+}
+
+// Precedence levels, loosest first, matching the parser's `parse_or` .. `parse_postfix`.
+// `if` sits below all of them: its `else` branch would swallow a following operator.
+const IF: u8 = 0;
+const UNARY: u8 = 11;
+const POSTFIX: u8 = 12;
+
+fn binary_prec(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Eq | BinaryOp::Ne => 3,
+        BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => 4,
+        BinaryOp::BitOr => 5,
+        BinaryOp::BitXor => 6,
+        BinaryOp::BitAnd => 7,
+        BinaryOp::Shl | BinaryOp::Shr => 8,
+        BinaryOp::Add | BinaryOp::Sub => 9,
+        BinaryOp::Mul | BinaryOp::Div => 10,
+    }
+}
+
+fn binary_op(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Le => "<=",
+        BinaryOp::Ge => ">=",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
+
+fn expr_prec(expr: &Expr) -> u8 {
+    match expr {
+        Expr::If(_) => IF,
+        Expr::Binary(b) => binary_prec(&b.op),
+        Expr::Unary(_) | Expr::Copy(_) | Expr::Ref(_) | Expr::MutRef(_) => UNARY,
+        // negative literals only come from lowering and read back as `-n`
+        Expr::Literal(Literal::Int(v), _) if *v < 0 => UNARY,
+        _ => POSTFIX,
+    }
+}
+
+fn path(p: &Path) -> String {
+    let segs: Vec<&str> = p.0.iter().map(|s| s.0.as_str()).collect();
+    segs.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn round_trip(src: &str) -> String {
+        let program = Parser::with_doc_comments(src)
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let printed = print_program(&program);
+        let reparsed = Parser::with_doc_comments(&printed)
+            .unwrap()
+            .parse_program()
+            .unwrap_or_else(|e| panic!("{e}\n{printed}"));
+        assert_eq!(reparsed, program, "{printed}");
+        assert_eq!(print_program(&reparsed), printed);
+        printed
+    }
+
+    #[test]
+    fn prints_canonical_layout() {
+        let src = "import math\nimport str\nglobal  n:i32=1\nlimit: i32 = 2\n/// A point.\n@derive(show)\ntype Point={x:i32,y:i32}\nmain()={p:Point={x:1,y:2}\nmut q:Point={..p,y:3}\nq.x=(1+2)*3\nif q.x>1 then \"a\\n\" else \"b\"}";
+        assert_eq!(
+            round_trip(src),
+            "import math\nimport str\n\nglobal n: i32 = 1\nlimit: i32 = 2\n\n/// A point.\n@derive(show)\ntype Point = { x: i32, y: i32 }\n\nmain() = {\n  p: Point = { x: 1, y: 2 }\n  mut q: Point = { ..p, y: 3 }\n  q.x = (1 + 2) * 3\n  if q.x > 1 then \"a\\n\" else \"b\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_every_construct() {
+        round_trip(
+            r#"
+trait Show {
+  /// Render it.
+  show(&self) -> Str
+  take(self, n: i32) -> i32
+  bump(&mut self) -> Unit
+}
+impl Show for Point {
+  show(&self) -> Str = "p"
+  take(self, n: i32) -> i32 = n
+  bump(&mut self) -> Unit = ()
+}
+macro twice(e) = { e e }
+apply(f: (i32) -> i32, mut x: i32, r: &mut Point) -> i32 = {
+  helper(y: i32) = y - (1 - 2)
+  a: i32 = -(-x) * !true - (if x > 1 then 2 else 3) + copy x
+  b: &Point = &r
+  s: Str = "q\"\\"[0..2]
+  t: i32 = s[..1][0] << 2 | 1 ^ 3 & 4 >> 1
+  ms: i32 = time { f(x) }
+  ok: bool = a == 1 || b.x != 2 && !(a < 3)
+  {}
+  x = if ok then { 1 } else 2
+  x
+}
+"#,
+        );
+    }
+}
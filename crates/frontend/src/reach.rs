@@ -0,0 +1,82 @@
+#![forbid(unsafe_code)]
+
+//! Reachability inside a block. Gaut has no `return` or `break`; the only expression that
+//! does not finish is a call to the `exit` builtin, so a statement is unreachable when
+//! an earlier statement of its block always ends up calling `exit`. The typechecker
+//! warns about such statements and cgen does not emit them.
+
+use crate::ast::*;
+
+/// The index of the first statement of `block` that always ends the program; every
+/// statement after it, and the tail, is unreachable. `exit_shadowed` is set when the
+/// program declares its own function named `exit`, which is an ordinary call.
+pub fn first_diverging(block: &Block, exit_shadowed: bool) -> Option<usize> {
+    block
+        .stmts
+        .iter()
+        .position(|s| stmt_diverges(s, exit_shadowed))
+}
+
+pub fn stmt_diverges(stmt: &Stmt, exit_shadowed: bool) -> bool {
+    match stmt {
+        Stmt::Binding(b) => diverges(&b.value, exit_shadowed),
+        Stmt::Assign(a) => diverges(&a.value, exit_shadowed),
+        Stmt::Expr(e) => diverges(e, exit_shadowed),
+        Stmt::Func(_) => false,
+    }
+}
+
+/// Whether evaluating `expr` always ends the program: a call to the `exit` builtin,
+/// or something that cannot finish without making one.
+pub fn diverges(expr: &Expr, exit_shadowed: bool) -> bool {
+    let diverges = |e: &Expr| diverges(e, exit_shadowed);
+    match expr {
+        Expr::FuncCall(call) => {
+            let is_exit =
+                matches!(call.callee.0.as_slice(), [name] if name.0 == "exit") && !exit_shadowed;
+            is_exit || call.args.iter().any(diverges)
+        }
+        Expr::Block(b) => {
+            first_diverging(b, exit_shadowed).is_some() || b.tail.as_deref().is_some_and(diverges)
+        }
+        Expr::If(ife) => {
+            diverges(&ife.cond) || (diverges(&ife.then_branch) && diverges(&ife.else_branch))
+        }
+        Expr::Copy(inner) | Expr::Ref(inner) | Expr::MutRef(inner) | Expr::Time(inner) => {
+            diverges(inner)
+        }
+        Expr::Unary(u) => diverges(&u.expr),
+        // `&&` and `||` may skip their right operand
+        Expr::Binary(b) => diverges(&b.left),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn body(src: &str) -> Block {
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        match program.decls.into_iter().next() {
+            Some(Decl::Func(FuncDecl {
+                body: Expr::Block(b),
+                ..
+            })) => b,
+            other => panic!("expected a function with a block body, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn statements_after_a_certain_exit_are_unreachable() {
+        let src = "main() -> i32 = {\n  \
+                   a: i32 = 1\n  \
+                   if copy a > 0 then exit(1) else println(\"no\")\n  \
+                   n: i32 = { exit(2)\n 3 }\n  \
+                   copy n\n}";
+        let block = body(src);
+        assert_eq!(first_diverging(&block, false), Some(2));
+        assert_eq!(first_diverging(&block, true), None);
+    }
+}
@@ -0,0 +1,145 @@
+#![forbid(unsafe_code)]
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Width of the default integer: the type of integer literals, arithmetic and the
+/// integer builtins. What an operator does when its result does not fit is the
+/// target's [`Overflow`] mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IntModel {
+    #[default]
+    I32,
+    I64,
+}
+
+impl IntModel {
+    /// The source-level type name of the default integer.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            IntModel::I32 => "i32",
+            IntModel::I64 => "i64",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntModel::I32 => 32,
+            IntModel::I64 => 64,
+        }
+    }
+
+    /// The smallest value of the default integer type.
+    pub fn min(self) -> i64 {
+        match self {
+            IntModel::I32 => i32::MIN as i64,
+            IntModel::I64 => i64::MIN,
+        }
+    }
+
+    /// The largest value of the default integer type.
+    pub fn max(self) -> i64 {
+        match self {
+            IntModel::I32 => i32::MAX as i64,
+            IntModel::I64 => i64::MAX,
+        }
+    }
+
+    /// Truncate `v` to the model's width and sign-extend it back.
+    pub fn wrap(self, v: i64) -> i64 {
+        match self {
+            IntModel::I32 => v as i32 as i64,
+            IntModel::I64 => v,
+        }
+    }
+}
+
+impl fmt::Display for IntModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.type_name())
+    }
+}
+
+impl FromStr for IntModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(IntModel::I32),
+            "i64" => Ok(IntModel::I64),
+            other => Err(format!(
+                "unknown integer model '{other}' (expected i32 or i64)"
+            )),
+        }
+    }
+}
+
+/// What `+ - *`, unary `-` and `/` do when the exact result does not fit the default
+/// integer. Shifts and the `wrapping_*` builtins always wrap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Stop: a runtime error in the interpreter, an abort naming the operation in C.
+    #[default]
+    Trap,
+    /// Wrap in two's complement at the model's width.
+    Wrap,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Overflow::Trap => "trap",
+            Overflow::Wrap => "wrap",
+        })
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trap" => Ok(Overflow::Trap),
+            "wrap" => Ok(Overflow::Wrap),
+            other => Err(format!(
+                "unknown overflow mode '{other}' (expected trap or wrap)"
+            )),
+        }
+    }
+}
+
+/// The values of the builtin integer type `name`, which an integer literal of that
+/// type must be one of; `None` for any other type.
+pub fn int_range(name: &str) -> Option<RangeInclusive<i64>> {
+    match name {
+        "i32" => Some(i64::from(i32::MIN)..=i64::from(i32::MAX)),
+        "i64" => Some(i64::MIN..=i64::MAX),
+        "u8" => Some(0..=i64::from(u8::MAX)),
+        _ => None,
+    }
+}
+
+/// Settings every execution mode must agree on. The typechecker, the interpreter and
+/// the C generator each take one so a program means the same thing under all three.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Target {
+    pub int_model: IntModel,
+    pub overflow: Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_truncates_to_the_model_width() {
+        assert_eq!(IntModel::I32.wrap(i32::MAX as i64 + 1), i32::MIN as i64);
+        assert_eq!(IntModel::I32.wrap(-1), -1);
+        assert_eq!(IntModel::I64.wrap(i32::MAX as i64 + 1), 2147483648);
+        assert_eq!("i64".parse::<IntModel>(), Ok(IntModel::I64));
+        assert!("u8".parse::<IntModel>().is_err());
+        assert_eq!("wrap".parse::<Overflow>(), Ok(Overflow::Wrap));
+        assert_eq!(Overflow::default(), Overflow::Trap);
+    }
+}
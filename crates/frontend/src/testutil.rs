@@ -0,0 +1,224 @@
+#![forbid(unsafe_code)]
+
+//! Helpers for tests written against the frontend from outside it: linters, formatters
+//! and course materials build ASTs with the functions below instead of spelling out
+//! every struct, parse and check a source string in one call, and assert on the
+//! diagnostic they expect. The assertion helpers panic, so they belong in tests only.
+
+use crate::ast::*;
+use crate::parser::{Parser, ParserError};
+use crate::target::Target;
+use crate::typecheck::{TypeChecker, TypeError};
+use thiserror::Error;
+
+/// Why [`parse_and_check`] rejected a source string.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CheckError {
+    #[error("parse error: {0}")]
+    Parse(#[from] ParserError),
+    #[error("type error: {0}")]
+    Type(#[from] TypeError),
+}
+
+pub fn parse(src: &str) -> Result<Program, ParserError> {
+    Parser::new(src)?.parse_program()
+}
+
+/// Parse `src` and type-check it for the default target, returning the parsed program.
+pub fn parse_and_check(src: &str) -> Result<Program, CheckError> {
+    parse_and_check_with(src, Target::default())
+}
+
+pub fn parse_and_check_with(src: &str, target: Target) -> Result<Program, CheckError> {
+    let program = parse(src)?;
+    TypeChecker::with_target(target).check_program(&program)?;
+    Ok(program)
+}
+
+/// The program, or a panic showing the error.
+pub fn assert_checks(src: &str) -> Program {
+    match parse_and_check(src) {
+        Ok(program) => program,
+        Err(e) => panic!("expected the program to check, got {e}\n{src}"),
+    }
+}
+
+/// The parse error of `src`, which must mention `needle`.
+pub fn assert_parse_error(src: &str, needle: &str) -> ParserError {
+    match parse(src) {
+        Ok(_) => panic!("expected a parse error mentioning {needle:?}\n{src}"),
+        Err(e) if e.to_string().contains(needle) => e,
+        Err(e) => panic!("expected a parse error mentioning {needle:?}, got {e}"),
+    }
+}
+
+/// The type error of `src`, which must parse and whose message must mention `needle`.
+pub fn assert_type_error(src: &str, needle: &str) -> TypeError {
+    match parse_and_check(src) {
+        Err(CheckError::Type(e)) if e.to_string().contains(needle) => e,
+        Err(e) => panic!("expected a type error mentioning {needle:?}, got {e}"),
+        Ok(_) => panic!("expected a type error mentioning {needle:?}\n{src}"),
+    }
+}
+
+pub fn ident(name: &str) -> Ident {
+    Ident(name.into())
+}
+
+/// A type by name: `ty("i32")`, `ty("Str")`.
+pub fn ty(name: &str) -> Type {
+    Type::Named(ident(name))
+}
+
+pub fn int(value: i64) -> Expr {
+    Expr::Literal(Literal::Int(value), Span::default())
+}
+
+pub fn boolean(value: bool) -> Expr {
+    Expr::Literal(Literal::Bool(value), Span::default())
+}
+
+pub fn string(value: &str) -> Expr {
+    Expr::Literal(Literal::Str(value.into()), Span::default())
+}
+
+/// `a` or a field path `a.b.c`.
+pub fn var(path: &str) -> Expr {
+    Expr::Path(path_of(path))
+}
+
+pub fn copy(expr: Expr) -> Expr {
+    Expr::Copy(Box::new(expr))
+}
+
+pub fn borrow(expr: Expr) -> Expr {
+    Expr::Ref(Box::new(expr))
+}
+
+/// `callee(args)`; a dotted callee is a method call `recv.f(args)`.
+pub fn call(callee: &str, args: Vec<Expr>) -> Expr {
+    Expr::FuncCall(FuncCall {
+        callee: path_of(callee),
+        args,
+        span: Span::default(),
+    })
+}
+
+pub fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+    Expr::Binary(BinaryExpr {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+        span: Span::default(),
+    })
+}
+
+pub fn if_else(cond: Expr, then_branch: Expr, else_branch: Expr) -> Expr {
+    Expr::If(Box::new(IfExpr {
+        cond,
+        then_branch,
+        else_branch,
+        span: Span::default(),
+    }))
+}
+
+pub fn block(stmts: Vec<Stmt>, tail: Option<Expr>) -> Expr {
+    Expr::Block(Block {
+        stmts,
+        tail: tail.map(Box::new),
+        span: Span::default(),
+    })
+}
+
+pub fn binding(name: &str, ty: Type, value: Expr) -> Binding {
+    Binding {
+        mutable: false,
+        name: ident(name),
+        ty,
+        value,
+        span: Span::default(),
+    }
+}
+
+/// `name: ty = value` as a block statement.
+pub fn let_stmt(name: &str, ty: Type, value: Expr) -> Stmt {
+    Stmt::Binding(binding(name, ty, value))
+}
+
+pub fn param(name: &str, ty: Type) -> Param {
+    Param {
+        mutable: false,
+        name: ident(name),
+        ty,
+    }
+}
+
+pub fn func(name: &str, params: Vec<Param>, ret: Option<Type>, body: Expr) -> Decl {
+    Decl::Func(FuncDecl {
+        name: ident(name),
+        params,
+        ret,
+        body,
+        doc: None,
+        span: Span::default(),
+    })
+}
+
+pub fn global(name: &str, ty: Type, value: Expr) -> Decl {
+    Decl::Global(binding(name, ty, value))
+}
+
+pub fn program(decls: Vec<Decl>) -> Program {
+    Program { decls }
+}
+
+fn path_of(path: &str) -> Path {
+    Path(path.split('.').map(ident).collect(), Span::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builders_match_the_parser() {
+        let src = "global base: i32 = 40\nadd(a: i32, b: i32) -> i32 = copy a + b\nmain() = {\n  n: i32 = add(copy base, 2)\n  if copy n == 42 then n else 0\n}";
+        let built = program(vec![
+            global("base", ty("i32"), int(40)),
+            func(
+                "add",
+                vec![param("a", ty("i32")), param("b", ty("i32"))],
+                Some(ty("i32")),
+                binary(copy(var("a")), BinaryOp::Add, var("b")),
+            ),
+            func(
+                "main",
+                vec![],
+                None,
+                block(
+                    vec![let_stmt(
+                        "n",
+                        ty("i32"),
+                        call("add", vec![copy(var("base")), int(2)]),
+                    )],
+                    Some(if_else(
+                        binary(copy(var("n")), BinaryOp::Eq, int(42)),
+                        var("n"),
+                        int(0),
+                    )),
+                ),
+            ),
+        ]);
+        assert_eq!(assert_checks(src), built);
+    }
+
+    #[test]
+    fn diagnostics_are_matched_by_message() {
+        assert_type_error("main() = {\n  n: i32 = true\n  n\n}", "type mismatch");
+        assert_parse_error("main( = 0", "unexpected token");
+        assert!(matches!(
+            parse_and_check("main() = missing"),
+            Err(CheckError::Type(TypeError::UnknownIdent(_)))
+        ));
+    }
+}
@@ -0,0 +1,507 @@
+#![forbid(unsafe_code)]
+
+use crate::ast::*;
+use crate::typecheck::TypeError;
+use std::collections::{HashMap, HashSet};
+
+/// Built-in trait whose `drop(&self)` runs when an owning binding leaves scope un-moved.
+pub const DROP_TRAIT: &str = "Drop";
+
+/// Name of the free function an `impl` method lowers to.
+pub fn mangle_method(ty: &str, method: &str) -> String {
+    format!("{ty}__{method}")
+}
+
+/// Lowered drop hook for values declared with type `ty`, if `ty` names a type.
+/// Callers check whether the program actually defines it.
+pub fn drop_fn(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Named(n) => Some(mangle_method(&n.0, "drop")),
+        _ => None,
+    }
+}
+
+/// `trait Drop { drop(&self) -> Unit }`, available without being declared.
+fn builtin_drop_trait() -> TraitDecl {
+    TraitDecl {
+        name: Ident(DROP_TRAIT.to_string()),
+        methods: vec![TraitMethod {
+            name: Ident("drop".to_string()),
+            params: vec![Param {
+                mutable: false,
+                name: Ident("self".to_string()),
+                ty: Type::Ref(Box::new(Type::Named(Ident("Self".to_string())))),
+            }],
+            ret: Type::Named(Ident("Unit".to_string())),
+            doc: None,
+            span: Span::default(),
+        }],
+        doc: None,
+        span: Span::default(),
+    }
+}
+
+/// Check every `impl` against its trait and lower trait code to plain functions.
+/// Impl methods become `Type__method` functions, and `recv.method(args)` calls whose
+/// receiver has a statically known implementing type are rewritten to call them
+/// directly. Remaining method calls are left to the usual UFCS lowering.
+pub fn lower_traits(program: &Program) -> Result<Program, TypeError> {
+    if !program
+        .decls
+        .iter()
+        .any(|d| matches!(d, Decl::Trait(_) | Decl::Impl(_)))
+    {
+        return Ok(program.clone());
+    }
+
+    let drop_trait = builtin_drop_trait();
+    let mut traits: HashMap<&str, &TraitDecl> = HashMap::new();
+    traits.insert(DROP_TRAIT, &drop_trait);
+    for decl in &program.decls {
+        if let Decl::Trait(t) = decl {
+            traits.insert(t.name.0.as_str(), t);
+        }
+    }
+
+    let mut globals = HashMap::new();
+    let mut aliases = HashMap::new();
+    for decl in &program.decls {
+        match decl {
+            Decl::Global(b) | Decl::Let(b) => {
+                globals.insert(b.name.0.clone(), b.ty.clone());
+            }
+            Decl::Type(t) => {
+                aliases.insert(t.name.0.clone(), t.ty.clone());
+            }
+            _ => {}
+        }
+    }
+
+    // (type, method) -> how the method takes `self`
+    let mut methods: HashMap<(String, String), Receiver> = HashMap::new();
+    let mut impls = HashSet::new();
+    for decl in &program.decls {
+        let Decl::Impl(imp) = decl else {
+            continue;
+        };
+        let tr = traits
+            .get(imp.trait_name.0.as_str())
+            .ok_or_else(|| TypeError::UnknownTrait(imp.trait_name.0.clone()))?;
+        if !impls.insert((imp.trait_name.0.as_str(), imp.ty.0.as_str())) {
+            return Err(TypeError::DuplicateImpl {
+                trait_name: imp.trait_name.0.clone(),
+                ty: imp.ty.0.clone(),
+            });
+        }
+        check_conformance(tr, imp, &aliases)?;
+        for m in &imp.methods {
+            let first = m.params.first().map(|p| resolve_aliases(&p.ty, &aliases));
+            let receiver = Receiver::of(first.as_ref());
+            if methods
+                .insert((imp.ty.0.clone(), m.name.0.clone()), receiver)
+                .is_some()
+            {
+                return Err(TypeError::AmbiguousMethod {
+                    ty: imp.ty.0.clone(),
+                    method: m.name.0.clone(),
+                });
+            }
+        }
+    }
+
+    let mut rw = Rewriter {
+        methods: &methods,
+        aliases: &aliases,
+        scopes: vec![globals],
+    };
+
+    let mut decls = Vec::with_capacity(program.decls.len());
+    for decl in &program.decls {
+        match decl {
+            Decl::Trait(_) => {}
+            Decl::Impl(imp) => {
+                let this = Type::Named(imp.ty.clone());
+                for m in &imp.methods {
+                    let func = FuncDecl {
+                        name: Ident(mangle_method(&imp.ty.0, &m.name.0)),
+                        params: m
+                            .params
+                            .iter()
+                            .map(|p| Param {
+                                ty: subst_self(&p.ty, &this),
+                                ..p.clone()
+                            })
+                            .collect(),
+                        ret: m.ret.as_ref().map(|r| subst_self(r, &this)),
+                        body: m.body.clone(),
+                        doc: m.doc.clone(),
+                        span: m.span,
+                    };
+                    decls.push(Decl::Func(rw.func(&func)));
+                }
+            }
+            Decl::Func(f) => decls.push(Decl::Func(rw.func(f))),
+            Decl::Global(b) => decls.push(Decl::Global(rw.top_binding(b))),
+            Decl::Let(b) => decls.push(Decl::Let(rw.top_binding(b))),
+            other => decls.push(other.clone()),
+        }
+    }
+    Ok(Program { decls })
+}
+
+/// Whether `imp` implements exactly the methods of `tr`. Signatures are compared after
+/// substituting `Self` and expanding aliases, so `&PointRef` and `&&Point` agree.
+fn check_conformance(
+    tr: &TraitDecl,
+    imp: &ImplDecl,
+    aliases: &HashMap<String, Type>,
+) -> Result<(), TypeError> {
+    let this = Type::Named(imp.ty.clone());
+    let same = |a: &Type, b: &Type| {
+        resolve_aliases(&subst_self(a, &this), aliases)
+            == resolve_aliases(&subst_self(b, &this), aliases)
+    };
+    let names = |method: &Ident| (imp.trait_name.0.clone(), imp.ty.0.clone(), method.0.clone());
+    for m in &imp.methods {
+        if !tr.methods.iter().any(|t| t.name == m.name) {
+            let (trait_name, ty, method) = names(&m.name);
+            return Err(TypeError::NotTraitMethod {
+                trait_name,
+                ty,
+                method,
+            });
+        }
+    }
+    for tm in &tr.methods {
+        let Some(m) = imp.methods.iter().find(|m| m.name == tm.name) else {
+            let (trait_name, ty, method) = names(&tm.name);
+            return Err(TypeError::MissingTraitMethod {
+                trait_name,
+                ty,
+                method,
+            });
+        };
+        let params_match = m.params.len() == tm.params.len()
+            && m.params
+                .iter()
+                .zip(&tm.params)
+                .all(|(a, b)| same(&a.ty, &b.ty));
+        let ret_match = m.ret.as_ref().is_some_and(|r| same(r, &tm.ret));
+        if !(params_match && ret_match) {
+            let (trait_name, ty, method) = names(&tm.name);
+            return Err(TypeError::TraitMethodMismatch {
+                trait_name,
+                ty,
+                method,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `ty` with aliases expanded the way the typechecker does: an alias is replaced by what
+/// it names, while a declared record type keeps its name because records are nominal.
+/// Unknown names and alias cycles are left for the typechecker to report.
+fn resolve_aliases(ty: &Type, aliases: &HashMap<String, Type>) -> Type {
+    fn go(ty: &Type, aliases: &HashMap<String, Type>, seen: &mut Vec<String>) -> Type {
+        match ty {
+            Type::Named(n) => match aliases.get(&n.0) {
+                Some(Type::Record(_)) | None => ty.clone(),
+                Some(_) if seen.contains(&n.0) => ty.clone(),
+                Some(target) => {
+                    seen.push(n.0.clone());
+                    let out = go(target, aliases, seen);
+                    seen.pop();
+                    out
+                }
+            },
+            Type::Ref(inner) => Type::Ref(Box::new(go(inner, aliases, seen))),
+            Type::MutRef(inner) => Type::MutRef(Box::new(go(inner, aliases, seen))),
+            Type::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|f| FieldType {
+                        name: f.name.clone(),
+                        ty: go(&f.ty, aliases, seen),
+                    })
+                    .collect(),
+            ),
+            Type::Func(f) => Type::Func(FuncType {
+                params: f.params.iter().map(|p| go(p, aliases, seen)).collect(),
+                ret: Box::new(go(&f.ret, aliases, seen)),
+            }),
+        }
+    }
+    go(ty, aliases, &mut Vec::new())
+}
+
+fn subst_self(ty: &Type, this: &Type) -> Type {
+    match ty {
+        Type::Named(id) if id.0 == "Self" => this.clone(),
+        Type::Named(_) => ty.clone(),
+        Type::Ref(inner) => Type::Ref(Box::new(subst_self(inner, this))),
+        Type::MutRef(inner) => Type::MutRef(Box::new(subst_self(inner, this))),
+        Type::Record(fields) => Type::Record(
+            fields
+                .iter()
+                .map(|f| FieldType {
+                    name: f.name.clone(),
+                    ty: subst_self(&f.ty, this),
+                })
+                .collect(),
+        ),
+        Type::Func(f) => Type::Func(FuncType {
+            params: f.params.iter().map(|p| subst_self(p, this)).collect(),
+            ret: Box::new(subst_self(&f.ret, this)),
+        }),
+    }
+}
+
+/// Rewrites method calls using the annotated types of bindings and parameters in scope.
+struct Rewriter<'a> {
+    methods: &'a HashMap<(String, String), Receiver>,
+    aliases: &'a HashMap<String, Type>,
+    scopes: Vec<HashMap<String, Type>>,
+}
+
+impl Rewriter<'_> {
+    fn func(&mut self, f: &FuncDecl) -> FuncDecl {
+        self.scopes.push(
+            f.params
+                .iter()
+                .map(|p| (p.name.0.clone(), p.ty.clone()))
+                .collect(),
+        );
+        let body = self.expr(&f.body);
+        self.scopes.pop();
+        FuncDecl { body, ..f.clone() }
+    }
+
+    fn top_binding(&mut self, b: &Binding) -> Binding {
+        Binding {
+            value: self.expr(&b.value),
+            ..b.clone()
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        match expr {
+            Expr::Literal(..) | Expr::Path(_) => expr.clone(),
+            Expr::Copy(inner) => Expr::Copy(Box::new(self.expr(inner))),
+            Expr::Time(body) => Expr::Time(Box::new(self.expr(body))),
+            Expr::Ref(inner) => Expr::Ref(Box::new(self.expr(inner))),
+            Expr::MutRef(inner) => Expr::MutRef(Box::new(self.expr(inner))),
+            Expr::FuncCall(fc) => {
+                let call = FuncCall {
+                    callee: fc.callee.clone(),
+                    args: fc.args.iter().map(|a| self.expr(a)).collect(),
+                    span: fc.span,
+                };
+                Expr::FuncCall(self.dispatch(call))
+            }
+            Expr::If(ife) => Expr::If(Box::new(IfExpr {
+                cond: self.expr(&ife.cond),
+                then_branch: self.expr(&ife.then_branch),
+                else_branch: self.expr(&ife.else_branch),
+                span: ife.span,
+            })),
+            Expr::Block(b) => {
+                self.scopes.push(HashMap::new());
+                let stmts = b
+                    .stmts
+                    .iter()
+                    .map(|stmt| match stmt {
+                        Stmt::Binding(binding) => {
+                            let value = self.expr(&binding.value);
+                            if let Some(scope) = self.scopes.last_mut() {
+                                scope.insert(binding.name.0.clone(), binding.ty.clone());
+                            }
+                            Stmt::Binding(Binding {
+                                value,
+                                ..binding.clone()
+                            })
+                        }
+                        Stmt::Assign(a) => Stmt::Assign(Assign {
+                            target: a.target.clone(),
+                            value: self.expr(&a.value),
+                            span: a.span,
+                        }),
+                        Stmt::Expr(e) => Stmt::Expr(self.expr(e)),
+                        // already lifted by `lower_program`
+                        Stmt::Func(f) => Stmt::Func(f.clone()),
+                    })
+                    .collect();
+                let tail = b.tail.as_ref().map(|t| Box::new(self.expr(t)));
+                self.scopes.pop();
+                Expr::Block(Block {
+                    stmts,
+                    tail,
+                    span: b.span,
+                })
+            }
+            Expr::RecordLit(r) => Expr::RecordLit(RecordLit {
+                base: r.base.as_ref().map(|b| Box::new(self.expr(b))),
+                fields: r
+                    .fields
+                    .iter()
+                    .map(|f| FieldInit {
+                        name: f.name.clone(),
+                        value: self.expr(&f.value),
+                    })
+                    .collect(),
+                span: r.span,
+            }),
+            Expr::Unary(u) => Expr::Unary(UnaryExpr {
+                op: u.op.clone(),
+                expr: Box::new(self.expr(&u.expr)),
+                span: u.span,
+            }),
+            Expr::Binary(b) => Expr::Binary(BinaryExpr {
+                op: b.op.clone(),
+                left: Box::new(self.expr(&b.left)),
+                right: Box::new(self.expr(&b.right)),
+                span: b.span,
+            }),
+            Expr::Index(ix) => Expr::Index(IndexExpr {
+                base: Box::new(self.expr(&ix.base)),
+                index: Box::new(self.expr(&ix.index)),
+                span: ix.span,
+            }),
+            Expr::Slice(sl) => Expr::Slice(SliceExpr {
+                base: Box::new(self.expr(&sl.base)),
+                start: sl.start.as_deref().map(|e| Box::new(self.expr(e))),
+                end: sl.end.as_deref().map(|e| Box::new(self.expr(e))),
+                span: sl.span,
+            }),
+        }
+    }
+
+    fn dispatch(&self, call: FuncCall) -> FuncCall {
+        let Some(method) = call.method_name() else {
+            return call;
+        };
+        let receiver = &call.callee.0[..call.callee.0.len() - 1];
+        let (ty_name, receiver_is_ref) = match self.path_type(receiver) {
+            Some(Type::Named(n)) => (n.0, false),
+            Some(Type::Ref(inner) | Type::MutRef(inner)) => match *inner {
+                Type::Named(n) => (n.0, true),
+                _ => return call,
+            },
+            _ => return call,
+        };
+        let Some(&receiver) = self.methods.get(&(ty_name.clone(), method.0.clone())) else {
+            return call;
+        };
+        let func = Ident(mangle_method(&ty_name, &method.0));
+        // a receiver that already is a reference is passed as it is
+        let receiver = if receiver_is_ref {
+            Receiver::Value
+        } else {
+            receiver
+        };
+        match call.desugar_method(receiver) {
+            Some(mut lowered) => {
+                lowered.callee = Path(vec![func], lowered.callee.1);
+                lowered
+            }
+            None => call,
+        }
+    }
+
+    /// Declared type of `a.b.c`, following record fields through type aliases.
+    fn path_type(&self, segs: &[Ident]) -> Option<Type> {
+        let (head, fields) = segs.split_first()?;
+        let mut ty = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(&head.0))?
+            .clone();
+        for field in fields {
+            let mut cur = &ty;
+            let mut seen = HashSet::new();
+            let record = loop {
+                match cur {
+                    Type::Ref(inner) | Type::MutRef(inner) => cur = inner,
+                    Type::Named(n) if seen.insert(n.0.as_str()) => cur = self.aliases.get(&n.0)?,
+                    Type::Record(fields) => break fields,
+                    _ => return None,
+                }
+            };
+            ty = record.iter().find(|f| &f.name == field)?.ty.clone();
+        }
+        Some(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn lower(src: &str) -> Result<Program, TypeError> {
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        lower_traits(&program)
+    }
+
+    #[test]
+    fn method_calls_dispatch_to_impl() {
+        let program = lower(
+            r#"
+            type Point = { x: i32, y: i32 }
+            trait Printable { to_str(&self) -> Str }
+            impl Printable for Point { to_str(&self) -> Str = "point" }
+            main() = {
+              p: Point = { x: 1, y: 2 }
+              p.to_str()
+            }
+            "#,
+        )
+        .unwrap();
+        assert!(program
+            .decls
+            .iter()
+            .any(|d| matches!(d, Decl::Func(f) if f.name.0 == "Point__to_str"
+                && f.params[0].ty == Type::Ref(Box::new(Type::Named(Ident("Point".into())))))));
+        let Some(Decl::Func(main)) = program.decls.last() else {
+            panic!("main missing");
+        };
+        let Expr::Block(body) = &main.body else {
+            panic!("block expected");
+        };
+        let Some(tail) = &body.tail else {
+            panic!("tail expected");
+        };
+        let Expr::FuncCall(call) = tail.as_ref() else {
+            panic!("call expected");
+        };
+        assert_eq!(call.callee.0[0].0, "Point__to_str");
+        assert!(matches!(call.args[0], Expr::Ref(_)));
+    }
+
+    #[test]
+    fn impl_must_conform_to_trait() {
+        let base = "type P = { x: i32 }\ntrait Show { show(self) -> Str }\n";
+        let missing = format!("{base}impl Show for P {{ }}");
+        assert!(matches!(
+            lower(&missing),
+            Err(TypeError::MissingTraitMethod { .. })
+        ));
+        let wrong_ret = format!("{base}impl Show for P {{ show(self) -> i32 = 1 }}");
+        assert!(matches!(
+            lower(&wrong_ret),
+            Err(TypeError::TraitMethodMismatch { .. })
+        ));
+        let unknown = "type P = { x: i32 }\nimpl Nope for P { show(self) -> Str = \"p\" }";
+        assert!(matches!(lower(unknown), Err(TypeError::UnknownTrait(_))));
+        // signatures match after aliases are expanded; nominal records still differ
+        let aliased =
+            format!("type Text = Str\n{base}impl Show for P {{ show(self) -> Text = \"p\" }}");
+        assert!(lower(&aliased).is_ok());
+        let other = format!("type Q = {{ x: i32 }}\ntrait Mk {{ mk(self) -> P }}\n{base}impl Mk for P {{ mk(self) -> Q = {{ x: 1 }} }}");
+        assert!(matches!(
+            lower(&other),
+            Err(TypeError::TraitMethodMismatch { .. })
+        ));
+    }
+}
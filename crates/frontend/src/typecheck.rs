@@ -1,6 +1,17 @@
 #![forbid(unsafe_code)]
 
+use crate::ast::visit::{self, Visit};
 use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::json::{JsonArgTypes, FROM_JSON, TO_JSON};
+use crate::lint::{Lint, Warning};
+use crate::lower::lower_program_with;
+use crate::names::{bound_names, decl_referenced_names, expr_referenced_names};
+use crate::reach::first_diverging;
+use crate::target::{int_range, Target};
+use crate::traits::drop_fn;
+use crate::typed::{number_nodes, ExprTypes, TypedProgram};
+use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
@@ -14,18 +25,154 @@ pub enum TypeError {
     UnknownFunc(String),
     #[error("cannot infer return type for function {0} yet")]
     UnknownFuncReturn(String),
-    #[error("type mismatch: expected {expected:?}, found {found:?}")]
+    #[error("cannot infer the return types of {0}: no branch returns without going through the cycle; annotate one of them")]
+    ReturnCycle(String),
+    #[error("type mismatch: expected {expected}, found {found}")]
     TypeMismatch { expected: Type, found: Type },
+    #[error("integer literal {value} does not fit {ty}")]
+    IntLiteralRange { value: i64, ty: Type },
     #[error("function arity mismatch: expected {expected}, found {found}")]
     ArityMismatch { expected: usize, found: usize },
+    #[error("{0} is already declared in this block; use another name or an inner block")]
+    Redeclared(String),
     #[error("value moved: {0}")]
     Moved(String),
     #[error("assignment to immutable binding: {0}")]
     NotMutable(String),
     #[error("value escapes its defining block")]
     Escape,
-    #[error("main must not take parameters")]
+    #[error(
+        "main must not take parameters; read command-line arguments with arg_count() and arg_at(i)"
+    )]
     MainHasParams,
+    #[error("unknown trait {0}")]
+    UnknownTrait(String),
+    #[error("impl {trait_name} for {ty} is missing method {method}")]
+    MissingTraitMethod {
+        trait_name: String,
+        ty: String,
+        method: String,
+    },
+    #[error("method {method} in impl {trait_name} for {ty} does not match the trait signature")]
+    TraitMethodMismatch {
+        trait_name: String,
+        ty: String,
+        method: String,
+    },
+    #[error("method {method} in impl {trait_name} for {ty} is not declared by the trait")]
+    NotTraitMethod {
+        trait_name: String,
+        ty: String,
+        method: String,
+    },
+    #[error("{trait_name} is implemented more than once for {ty}")]
+    DuplicateImpl { trait_name: String, ty: String },
+    #[error("method {method} is implemented by more than one trait for {ty}")]
+    AmbiguousMethod { ty: String, method: String },
+    #[error("{0} has a drop hook and cannot be moved inside an if expression")]
+    DropConditionalMove(String),
+    #[error("assigning to {0} would skip its drop hook; move the old value out first")]
+    DropOverwrite(String),
+    #[error("ambiguous name {name}: exported by modules {modules}; qualify it as module.{name}")]
+    AmbiguousName { name: String, modules: String },
+    #[error("local function {func} cannot use {name} from an enclosing function; pass it as a parameter")]
+    LocalFunctionCapture { func: String, name: String },
+    #[error("{name} is borrowed by {by} until the end of its scope")]
+    Borrowed { name: String, by: String },
+    #[error("{0} is passed as &mut and cannot be used by another argument of the same call")]
+    MutBorrowConflict(String),
+    #[error("&mut needs a binding or a field of one")]
+    MutRefTarget,
+    #[error("cannot assign through shared reference {0}; take it as &mut")]
+    AssignThroughRef(String),
+    #[error("type_fields expects the name of a record type, found {0}")]
+    ReflectionTarget(String),
+    #[error("to_str cannot render a value of type {0}")]
+    NotRenderable(Type),
+    #[error("..{0} in a record literal must be a record value")]
+    SpreadNotRecord(Type),
+    #[error("{ty} has no field {field}")]
+    UnknownField { ty: Type, field: String },
+    #[error("{ty} literal is missing {fields}")]
+    MissingFields { ty: Type, fields: String },
+    #[error("field {field} of {ty} has type {expected}, found {found}")]
+    FieldMismatch {
+        ty: Type,
+        field: String,
+        expected: Type,
+        found: Type,
+    },
+    #[error("field {0} is given more than once")]
+    DuplicateField(String),
+    #[error("unknown derive {0}; expected show or json")]
+    UnknownDerive(String),
+    #[error("cannot derive {derive} for {ty}: field {field} has type {field_ty}")]
+    DeriveUnsupported {
+        derive: String,
+        ty: String,
+        field: String,
+        field_ty: Type,
+    },
+    #[error("derived function {0} is already declared")]
+    DeriveConflict(String),
+    #[error(
+        "{builtin} cannot tell the JSON type of {what}; {}",
+        json_hint(builtin)
+    )]
+    JsonTarget {
+        builtin: &'static str,
+        what: String,
+        span: Span,
+    },
+    #[error("{ty} has no JSON form; expected an integer, bool, Str or a record of those")]
+    JsonUnsupported { ty: Type, span: Span },
+    #[error("cannot index or slice {0}; only Str and Bytes are indexable")]
+    NotIndexable(Type),
+    #[error("macro {name} takes {expected} arguments, found {found}{expansion}")]
+    MacroArity {
+        name: String,
+        expected: usize,
+        found: usize,
+        expansion: String,
+    },
+    #[error("argument {param} of macro {name} is used as a path, so it must be a name{expansion}")]
+    MacroArgNotPath {
+        name: String,
+        param: String,
+        expansion: String,
+    },
+    #[error("type aliases refer to each other: {0}")]
+    AliasCycle(String),
+    #[error("global initializers depend on each other: {0}")]
+    GlobalCycle(String),
+    #[error("{func} runs on a spawned thread and uses global mut {global}; share state between threads through a Chan or atomic_i64")]
+    SpawnedGlobalMut { func: String, global: String },
+    #[error("macro {name} expands more than {limit} levels deep{expansion}")]
+    MacroRecursion {
+        name: String,
+        limit: usize,
+        expansion: String,
+    },
+}
+
+impl TypeError {
+    /// Where lowering found the error, for the few lowering errors that know it.
+    fn lowering_span(&self) -> Option<Span> {
+        match self {
+            TypeError::JsonTarget { span, .. } | TypeError::JsonUnsupported { span, .. } => {
+                Some(*span).filter(Span::is_known)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn json_hint(builtin: &str) -> &'static str {
+    if builtin == FROM_JSON {
+        "write from_json(T, text) with the type to read"
+    } else {
+        "pass a variable, field or literal, or type-check the program so its type is known"
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +181,18 @@ struct BindingInfo {
     mutable: bool,
     moved: bool,
     origin_depth: usize,
+    drop: bool,       // owning binding of a type with a drop hook
+    loans: Vec<Loan>, // the bindings the references in this value point into, for its whole scope
+    used: Cell<bool>, // set by every lookup of the name
+    span: Span,       // block bindings only; others are never reported unused
+}
+
+/// A reference binding's referee: `name` declared at scope `depth`.
+#[derive(Debug, Clone)]
+struct Loan {
+    name: String,
+    depth: usize,
+    mutable: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -47,11 +206,31 @@ struct FuncSig {
     ret: Option<Type>,
 }
 
+/// The type of a function the host provides, such as one registered with the
+/// interpreter; see [`TypeChecker::register_signature`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
 pub struct TypeChecker {
     types: HashMap<String, Type>,
     funcs: HashMap<String, FuncSig>,
     scopes: Vec<Scope>,
     builtins: HashSet<String>,
+    user_funcs: HashSet<String>, // functions declared in the program; only these can be used as values
+    int_ty: Type,                // type of integer literals and arithmetic under the target's model
+    span: Span,                  // innermost declaration/statement/expression being checked
+    diagnostics: Vec<Diagnostic>, // every error of the last check_program
+    first_error: Option<TypeError>,
+    expr_types: ExprTypes,     // type of every expression checked so far
+    warnings: Vec<Warning>,    // lints found by the last check_program
+    base_case_only: bool,      // an `if` takes the type of its one branch with a known type
+    expected: Option<Type>,    // declared type of the expression being checked; see `check_expr_as`
+    body_scope: Option<usize>, // scope of the function body's block, which shares its parameters' names
+    json_args: JsonArgTypes,   // types of the `to_json` arguments lowering left to the checker
+    gc: bool,                  // checking for the C backend's `--gc` heap; see `set_gc`
 }
 
 #[derive(Debug, Clone)]
@@ -61,10 +240,36 @@ struct TyInfo {
     escapable: bool, // whether this value may legally escape its origin block (when refs are absent)
 }
 
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeChecker {
     pub fn new() -> Self {
+        Self::with_target(Target::default())
+    }
+
+    /// A checker whose integer literals, arithmetic and integer builtins use the
+    /// target's default integer type.
+    pub fn with_target(target: Target) -> Self {
+        let int = named(target.int_model.type_name());
         let mut types = HashMap::new();
-        for name in ["i32", "i64", "u8", "bool", "Str", "Bytes", "Unit"] {
+        for name in [
+            "i32",
+            "i64",
+            "u8",
+            "bool",
+            "Str",
+            "Bytes",
+            "Unit",
+            "atomic_i64",
+            "Dyn",
+            "TcpListener",
+            "TcpConn",
+            "Chan",
+        ] {
             types.insert(name.to_string(), Type::Named(Ident(name.to_string())));
         }
         types.insert(
@@ -80,6 +285,45 @@ impl TypeChecker {
                 },
             ]),
         );
+        types.insert(
+            "CheckedInt".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("ok".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: int.clone(),
+                },
+            ]),
+        );
+        types.insert(
+            "StrToIntResult".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("ok".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: int.clone(),
+                },
+            ]),
+        );
+        types.insert(
+            "DynField".into(),
+            Type::Record(vec![
+                FieldType {
+                    name: Ident("found".into()),
+                    ty: Type::Named(Ident("bool".into())),
+                },
+                FieldType {
+                    name: Ident("value".into()),
+                    ty: Type::Named(Ident("Str".into())),
+                },
+            ]),
+        );
         let builtins = types.keys().cloned().collect();
 
         let mut funcs = HashMap::new();
@@ -181,6 +425,122 @@ impl TypeChecker {
                 ret: Some(Type::Named(Ident("bool".into()))),
             },
         );
+        funcs.insert(
+            "int_to_str".into(),
+            FuncSig {
+                params: vec![Param {
+                    mutable: false,
+                    name: Ident("n".into()),
+                    ty: int.clone(),
+                }],
+                ret: Some(Type::Named(Ident("Str".into()))),
+            },
+        );
+        // `ok` is false (and `value` 0) unless the whole string is a decimal integer
+        // that fits the default integer type
+        funcs.insert(
+            "str_to_int".into(),
+            builtin_sig(&[("s", named("Str"))], named("StrToIntResult")),
+        );
+        // explicit overflow behaviour at the default integer width: wrapping_* wraps like the
+        // operators, saturating_* clamps to MIN/MAX, and checked_* reports overflow (and
+        // division by zero) as `ok: false, value: 0`
+        for op in ["add", "sub", "mul"] {
+            for mode in ["wrapping", "saturating"] {
+                funcs.insert(
+                    format!("{mode}_{op}"),
+                    builtin_sig(&[("a", int.clone()), ("b", int.clone())], int.clone()),
+                );
+            }
+        }
+        for op in ["add", "sub", "mul", "div"] {
+            funcs.insert(
+                format!("checked_{op}"),
+                builtin_sig(
+                    &[("a", int.clone()), ("b", int.clone())],
+                    named("CheckedInt"),
+                ),
+            );
+        }
+        // narrowing checks: `ok` when the value fits the narrower type, which it keeps
+        for to in ["i32", "u8"] {
+            funcs.insert(
+                format!("checked_to_{to}"),
+                builtin_sig(&[("n", int.clone())], named("CheckedInt")),
+            );
+        }
+        // math at the default integer width; abs and pow wrap like the operators, and a
+        // negative exponent gives the truncated quotient (0 unless the base is 1 or -1)
+        funcs.insert(
+            "abs".into(),
+            builtin_sig(&[("n", int.clone())], int.clone()),
+        );
+        for name in ["min", "max"] {
+            funcs.insert(
+                name.into(),
+                builtin_sig(&[("a", int.clone()), ("b", int.clone())], int.clone()),
+            );
+        }
+        funcs.insert(
+            "pow".into(),
+            builtin_sig(&[("base", int.clone()), ("exp", int.clone())], int.clone()),
+        );
+        funcs.insert(
+            "arg_count".into(),
+            FuncSig {
+                params: Vec::new(),
+                ret: Some(int.clone()),
+            },
+        );
+        funcs.insert(
+            "arg_at".into(),
+            FuncSig {
+                params: vec![Param {
+                    mutable: false,
+                    name: Ident("i".into()),
+                    ty: int.clone(),
+                }],
+                ret: Some(Type::Named(Ident("Str".into()))),
+            },
+        );
+        funcs.insert(
+            "json_quote".into(),
+            FuncSig {
+                params: vec![Param {
+                    mutable: false,
+                    name: Ident("s".into()),
+                    ty: Type::Named(Ident("Str".into())),
+                }],
+                ret: Some(Type::Named(Ident("Str".into()))),
+            },
+        );
+        // field lookups in a JSON object; a missing key or a value of the wrong kind
+        // is a runtime error
+        for (name, ret) in [
+            ("json_get_int", int.clone()),
+            ("json_get_bool", Type::Named(Ident("bool".into()))),
+            ("json_get_str", Type::Named(Ident("Str".into()))),
+            ("json_get_raw", Type::Named(Ident("Str".into()))),
+        ] {
+            funcs.insert(
+                name.into(),
+                FuncSig {
+                    params: vec![
+                        Param {
+                            mutable: false,
+                            name: Ident("doc".into()),
+                            ty: Type::Ref(Box::new(Type::Named(Ident("Str".into())))),
+                        },
+                        Param {
+                            mutable: false,
+                            name: Ident("key".into()),
+                            ty: Type::Named(Ident("Str".into())),
+                        },
+                    ],
+                    ret: Some(ret),
+                },
+            );
+        }
         funcs.insert(
             "str_len".into(),
             FuncSig {
@@ -189,7 +549,7 @@ impl TypeChecker {
                     name: Ident("s".into()),
                     ty: Type::Named(Ident("Str".into())),
                 }],
-                ret: Some(Type::Named(Ident("i32".into()))),
+                ret: Some(int.clone()),
             },
         );
         funcs.insert(
@@ -204,10 +564,10 @@ impl TypeChecker {
                     Param {
                         mutable: false,
                         name: Ident("i".into()),
-                        ty: Type::Named(Ident("i32".into())),
+                        ty: int.clone(),
                     },
                 ],
-                ret: Some(Type::Named(Ident("i32".into()))),
+                ret: Some(int.clone()),
             },
         );
         funcs.insert(
@@ -222,27 +582,393 @@ impl TypeChecker {
                     Param {
                         mutable: false,
                         name: Ident("start".into()),
-                        ty: Type::Named(Ident("i32".into())),
+                        ty: int.clone(),
                     },
                     Param {
                         mutable: false,
                         name: Ident("len".into()),
-                        ty: Type::Named(Ident("i32".into())),
+                        ty: int.clone(),
                     },
                 ],
                 ret: Some(Type::Named(Ident("Str".into()))),
             },
         );
+        for name in ["log_info", "log_warn", "log_error"] {
+            funcs.insert(
+                name.into(),
+                builtin_sig(&[("msg", named("Str"))], named("Unit")),
+            );
+        }
+        funcs.insert(
+            "config_get".into(),
+            builtin_sig(&[("key", named("Str"))], named("Str")),
+        );
+        // byte-oriented like str_len: str_find is a byte offset (-1 when absent), and
+        // trimming and case mapping only touch ASCII
+        funcs.insert(
+            "str_contains".into(),
+            builtin_sig(
+                &[("s", named("Str")), ("needle", named("Str"))],
+                named("bool"),
+            ),
+        );
+        funcs.insert(
+            "str_find".into(),
+            builtin_sig(
+                &[("s", named("Str")), ("needle", named("Str"))],
+                int.clone(),
+            ),
+        );
+        for name in ["str_trim", "str_to_upper", "str_to_lower"] {
+            funcs.insert(
+                name.into(),
+                builtin_sig(&[("s", named("Str"))], named("Str")),
+            );
+        }
+        // Bytes mirrors the str_ helpers: out-of-range reads give 0 and slices clamp;
+        // bytes_push appends the low 8 bits of `byte`
+        funcs.insert(
+            "bytes_len".into(),
+            builtin_sig(&[("b", named("Bytes"))], int.clone()),
+        );
+        funcs.insert(
+            "bytes_at".into(),
+            builtin_sig(&[("b", named("Bytes")), ("i", int.clone())], int.clone()),
+        );
+        funcs.insert(
+            "bytes_slice".into(),
+            builtin_sig(
+                &[
+                    ("b", named("Bytes")),
+                    ("start", int.clone()),
+                    ("len", int.clone()),
+                ],
+                named("Bytes"),
+            ),
+        );
+        funcs.insert(
+            "bytes_from_str".into(),
+            builtin_sig(&[("s", named("Str"))], named("Bytes")),
+        );
+        funcs.insert(
+            "bytes_push".into(),
+            builtin_sig(
+                &[
+                    ("b", Type::MutRef(Box::new(named("Bytes")))),
+                    ("byte", int.clone()),
+                ],
+                named("Unit"),
+            ),
+        );
+        // stdin: the next line with its "\n" ("" at end of input), or everything left
+        funcs.insert("read_line".into(), builtin_sig(&[], named("Str")));
+        funcs.insert("read_stdin".into(), builtin_sig(&[], named("Bytes")));
+        // wall-clock milliseconds since the Unix epoch (i64 under every int model, since
+        // i32 overflows), and a pause of at least `ms`
+        funcs.insert("now_millis".into(), builtin_sig(&[], named("i64")));
+        funcs.insert(
+            "sleep_ms".into(),
+            builtin_sig(&[("ms", int.clone())], named("Unit")),
+        );
+        // a number in lo..hi (hi excluded), from a generator seeded by the clock until
+        // seed_rand fixes the sequence
+        funcs.insert(
+            "rand_i32".into(),
+            builtin_sig(&[("lo", int.clone()), ("hi", int.clone())], int.clone()),
+        );
+        funcs.insert(
+            "seed_rand".into(),
+            builtin_sig(&[("seed", int.clone())], named("Unit")),
+        );
+        // stdout buffering: "line" flushes after every print, "block" only when full
+        funcs.insert("flush".into(), builtin_sig(&[], named("Unit")));
+        // ends the program with this process exit status, flushing stdout first
+        funcs.insert(
+            "exit".into(),
+            builtin_sig(&[("code", int.clone())], named("Unit")),
+        );
+        funcs.insert(
+            "set_print_buffer".into(),
+            builtin_sig(&[("mode", named("Str"))], named("bool")),
+        );
+        // atomics: shared i64 cells, read and written as i64 whatever the default integer is
+        let atomic_ref = Type::Ref(Box::new(named("atomic_i64")));
+        funcs.insert(
+            "atomic_new".into(),
+            builtin_sig(&[("value", named("i64"))], named("atomic_i64")),
+        );
+        funcs.insert(
+            "atomic_load".into(),
+            builtin_sig(&[("a", atomic_ref.clone())], named("i64")),
+        );
+        funcs.insert(
+            "atomic_store".into(),
+            builtin_sig(
+                &[("a", atomic_ref.clone()), ("value", named("i64"))],
+                named("Unit"),
+            ),
+        );
+        funcs.insert(
+            "atomic_add".into(),
+            builtin_sig(
+                &[("a", atomic_ref.clone()), ("delta", named("i64"))],
+                named("i64"),
+            ),
+        );
+        funcs.insert(
+            "atomic_cas".into(),
+            builtin_sig(
+                &[
+                    ("a", atomic_ref),
+                    ("expected", named("i64")),
+                    ("desired", named("i64")),
+                ],
+                named("bool"),
+            ),
+        );
+        // TCP: opaque socket handles taken by reference. Failing to listen, accept or
+        // connect stops the program; reads return empty bytes at end of stream
+        let listener_ref = Type::Ref(Box::new(named("TcpListener")));
+        let conn_ref = Type::Ref(Box::new(named("TcpConn")));
+        funcs.insert(
+            "tcp_listen".into(),
+            builtin_sig(&[("port", int.clone())], named("TcpListener")),
+        );
+        funcs.insert(
+            "tcp_port".into(),
+            builtin_sig(&[("l", listener_ref.clone())], int.clone()),
+        );
+        funcs.insert(
+            "tcp_accept".into(),
+            builtin_sig(&[("l", listener_ref)], named("TcpConn")),
+        );
+        funcs.insert(
+            "tcp_connect".into(),
+            builtin_sig(
+                &[("host", named("Str")), ("port", int.clone())],
+                named("TcpConn"),
+            ),
+        );
+        funcs.insert(
+            "tcp_read".into(),
+            builtin_sig(&[("c", conn_ref.clone())], named("Bytes")),
+        );
+        funcs.insert(
+            "tcp_write".into(),
+            builtin_sig(
+                &[("c", conn_ref.clone()), ("data", named("Bytes"))],
+                named("bool"),
+            ),
+        );
+        funcs.insert(
+            "tcp_close".into(),
+            builtin_sig(&[("c", conn_ref)], named("Unit")),
+        );
+        // Dyn: a shared string-keyed map for data without a declared shape. Reads are
+        // fallible, so `dyn_get` returns a `DynField` to check before using the value.
+        let dyn_ref = Type::Ref(Box::new(named("Dyn")));
+        funcs.insert("dyn_new".into(), builtin_sig(&[], named("Dyn")));
+        funcs.insert(
+            "dyn_parse".into(),
+            builtin_sig(&[("json", named("Str"))], named("Dyn")),
+        );
+        funcs.insert(
+            "dyn_set".into(),
+            builtin_sig(
+                &[
+                    ("d", dyn_ref.clone()),
+                    ("key", named("Str")),
+                    ("value", named("Str")),
+                ],
+                named("Unit"),
+            ),
+        );
+        funcs.insert(
+            "dyn_has".into(),
+            builtin_sig(
+                &[("d", dyn_ref.clone()), ("key", named("Str"))],
+                named("bool"),
+            ),
+        );
+        funcs.insert(
+            "dyn_get".into(),
+            builtin_sig(&[("d", dyn_ref), ("key", named("Str"))], named("DynField")),
+        );
+        // Threads: `spawn` runs a function on a new thread; threads talk through
+        // channels of Str messages, shared by every copy of the handle
+        let chan_ref = Type::Ref(Box::new(named("Chan")));
+        funcs.insert(
+            "spawn".into(),
+            builtin_sig(
+                &[(
+                    "f",
+                    Type::Func(FuncType {
+                        params: Vec::new(),
+                        ret: Box::new(named("Unit")),
+                    }),
+                )],
+                named("Unit"),
+            ),
+        );
+        funcs.insert("channel".into(), builtin_sig(&[], named("Chan")));
+        funcs.insert(
+            "send".into(),
+            builtin_sig(
+                &[("c", chan_ref.clone()), ("msg", named("Str"))],
+                named("Unit"),
+            ),
+        );
+        funcs.insert("recv".into(), builtin_sig(&[("c", chan_ref)], named("Str")));
 
         Self {
             types,
             funcs,
             scopes: Vec::new(),
             builtins,
+            user_funcs: HashSet::new(),
+            int_ty: int,
+            span: Span::default(),
+            diagnostics: Vec::new(),
+            first_error: None,
+            expr_types: ExprTypes::default(),
+            warnings: Vec::new(),
+            base_case_only: false,
+            expected: None,
+            body_scope: None,
+            json_args: JsonArgTypes::new(),
+            gc: false,
+        }
+    }
+
+    /// Check for the C backend's garbage-collected memory model (`--gc`). Nothing is
+    /// allocated in a block's arena there, so a value may leave the block that made it
+    /// unless it holds a reference; moves and borrows are checked as in arena mode.
+    pub fn set_gc(&mut self, gc: bool) {
+        self.gc = gc;
+    }
+
+    /// Let programs call the host function `name` as a builtin of type `sig`. A
+    /// function the program declares with the same name still takes its place.
+    pub fn register_signature(&mut self, name: &str, sig: &Signature) {
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| Param {
+                mutable: false,
+                name: Ident(format!("arg{i}")),
+                ty: ty.clone(),
+            })
+            .collect();
+        self.funcs.insert(
+            name.to_string(),
+            FuncSig {
+                params,
+                ret: Some(sig.ret.clone()),
+            },
+        );
+    }
+
+    /// Where the first `check_program` error was found: the innermost declaration,
+    /// statement or expression being checked. `None` when the failing node has no
+    /// source position (errors from lowering, or nodes synthesized by it).
+    pub fn error_span(&self) -> Option<Span> {
+        self.diagnostics
+            .first()
+            .map(|d| d.span)
+            .filter(Span::is_known)
+    }
+
+    /// Every error found by the last `check_program`, in the order they were found.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Unused variables and functions and unreachable statements found by the last
+    /// `check_program`, in source order within each function. Only meaningful when
+    /// it succeeded.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Record `err` at the current location and keep checking.
+    fn report(&mut self, err: TypeError) {
+        self.diagnostics
+            .push(Diagnostic::error(self.span, err.to_string()));
+        self.first_error.get_or_insert(err);
+    }
+
+    /// Runs `check` with `span` as the current location. A failure leaves it in place so
+    /// that `error_span` points at the innermost node; success restores the outer one.
+    fn at<T>(
+        &mut self,
+        span: Span,
+        check: impl FnOnce(&mut Self) -> Result<T, TypeError>,
+    ) -> Result<T, TypeError> {
+        if !span.is_known() {
+            return check(self);
+        }
+        let outer = std::mem::replace(&mut self.span, span);
+        let result = check(self);
+        if result.is_ok() {
+            self.span = outer;
+        }
+        result
+    }
+
+    /// Check every declaration. An error is recorded in [`TypeChecker::diagnostics`]
+    /// and checking carries on with the next statement, global or function; the first
+    /// error is returned. On success the lowered program comes back with the type of
+    /// each of its expressions, for the backends to use instead of inferring their own.
+    pub fn check_program(&mut self, program: &Program) -> Result<TypedProgram, TypeError> {
+        let (types, funcs, user_funcs) = (
+            self.types.clone(),
+            self.funcs.clone(),
+            self.user_funcs.clone(),
+        );
+        let mut json_args = JsonArgTypes::new();
+        loop {
+            self.span = Span::default();
+            self.diagnostics.clear();
+            self.first_error = None;
+            self.expr_types = ExprTypes::default();
+            self.json_args.clear();
+            self.warnings = unused_functions(program);
+            let lowered = match lower_program_with(program, Some(&json_args)) {
+                Ok(lowered) => number_nodes(lowered),
+                Err(e) => {
+                    if let Some(span) = e.lowering_span() {
+                        self.span = span;
+                    }
+                    self.report(e.clone());
+                    return Err(e);
+                }
+            };
+            self.check_lowered(&lowered)?;
+            if self.json_args.is_empty() {
+                let types = std::mem::take(&mut self.expr_types);
+                return Ok(TypedProgram::new(program.clone(), lowered, types));
+            }
+            if !json_args.is_empty() {
+                // lowered with every type the checker found, yet a call was left unresolved
+                let err = TypeError::JsonTarget {
+                    builtin: TO_JSON,
+                    what: "an expression".into(),
+                    span: Span::default(),
+                };
+                self.report(err.clone());
+                return Err(err);
+            }
+            // lower again now that the arguments' types are known, and check the result
+            json_args = std::mem::take(&mut self.json_args);
+            self.types = types.clone();
+            self.funcs = funcs.clone();
+            self.user_funcs = user_funcs.clone();
+            self.scopes.clear();
         }
     }
 
-    pub fn check_program(&mut self, program: &Program) -> Result<(), TypeError> {
+    fn check_lowered(&mut self, program: &Program) -> Result<(), TypeError> {
         // pass 1: collect type aliases and function signatures
         for decl in &program.decls {
             match decl {
@@ -250,6 +976,7 @@ impl TypeChecker {
                     self.types.insert(t.name.0.clone(), t.ty.clone());
                 }
                 Decl::Func(f) => {
+                    self.user_funcs.insert(f.name.0.clone());
                     let ret = f.ret.clone();
                     self.funcs.insert(
                         f.name.0.clone(),
@@ -263,49 +990,205 @@ impl TypeChecker {
             }
         }
 
+        // an alias cycle would send resolve_type around it forever, so stop here
+        let mut in_cycle = HashSet::new();
+        for decl in &program.decls {
+            let Decl::Type(t) = decl else {
+                continue;
+            };
+            if in_cycle.contains(&t.name.0) {
+                continue;
+            }
+            if let Some(cycle) = self.alias_cycle(&t.name.0) {
+                in_cycle.extend(cycle.iter().cloned());
+                self.span = t.span;
+                self.report(TypeError::AliasCycle(cycle.join(" -> ")));
+            }
+        }
+        if let Some(err) = self.first_error.clone() {
+            return Err(err);
+        }
+
         // global scope
         self.push_scope();
 
-        let mut funcs_to_check: Vec<FuncDecl> = Vec::new();
+        let mut funcs_to_check: Vec<&FuncDecl> = Vec::new();
         for decl in &program.decls {
             match decl {
                 Decl::Import(_) => {}
                 Decl::Type(_) => {}
-                Decl::Func(f) => funcs_to_check.push(f.clone()),
+                // removed by `lower_traits` and `expand_macros`
+                Decl::Trait(_) | Decl::Impl(_) | Decl::Macro(_) => {}
+                Decl::Func(f) => funcs_to_check.push(f),
                 Decl::Global(b) | Decl::Let(b) => {
-                    self.check_binding(b, 0)?;
+                    let outer = self.span;
+                    if let Err(e) = self.at(b.span, |tc| tc.check_binding(b, 0)) {
+                        self.report(e);
+                        self.span = outer;
+                        self.declare_failed_binding(b, 0);
+                        let note = format!("in the initializer of `{}`", b.name.0);
+                        if let Some(d) = self.diagnostics.last_mut() {
+                            d.notes.push(note);
+                        }
+                    }
                 }
             }
         }
 
         let mut pending = funcs_to_check;
         while !pending.is_empty() {
-            let mut deferred: Vec<FuncDecl> = Vec::new();
+            let mut deferred: Vec<&FuncDecl> = Vec::new();
             let mut progressed = false;
             for func in pending {
                 let scopes_before = self.scopes.clone();
                 let funcs_before = self.funcs.clone();
-                match self.check_func(&func) {
+                let reported_before = self.diagnostics.len();
+                let warned_before = self.warnings.len();
+                let first_before = self.first_error.clone();
+                let outer = self.span;
+                match self.at(func.span, |tc| tc.check_func(func)) {
                     Ok(()) => progressed = true,
                     Err(TypeError::UnknownFuncReturn(_)) => {
+                        // checked again once its callees' return types are known
                         self.scopes = scopes_before;
                         self.funcs = funcs_before;
+                        self.diagnostics.truncate(reported_before);
+                        self.warnings.truncate(warned_before);
+                        self.first_error = first_before;
+                        self.span = outer;
                         deferred.push(func);
+                        continue;
+                    }
+                    Err(err) => {
+                        self.report(err);
+                        self.scopes = scopes_before;
+                        self.span = outer;
+                        progressed = true;
                     }
-                    Err(err) => return Err(err),
+                }
+                let note = format!("in function `{}`", func.name.0);
+                for d in &mut self.diagnostics[reported_before..] {
+                    d.notes.push(note.clone());
                 }
             }
             if !progressed {
-                let unresolved = deferred
-                    .first()
-                    .map(|f| f.name.0.clone())
-                    .unwrap_or_else(|| "<unknown>".to_string());
-                return Err(TypeError::UnknownFuncReturn(unresolved));
+                // recursion: guess a return type from a branch that does not recurse,
+                // then check everything again with it
+                if let Some((name, ret)) = self.infer_base_case(&deferred) {
+                    if let Some(sig) = self.funcs.get_mut(&name) {
+                        sig.ret = Some(ret);
+                    }
+                    pending = deferred;
+                    continue;
+                }
+                // callers of a function that failed never learn its return type; only
+                // report them when nothing else went wrong
+                if self.first_error.is_none() {
+                    if let Some(f) = deferred.first() {
+                        self.span = f.span;
+                        let err = match return_cycle(&deferred) {
+                            Some(cycle) => TypeError::ReturnCycle(cycle),
+                            None => TypeError::UnknownFuncReturn(f.name.0.clone()),
+                        };
+                        self.report(err);
+                    }
+                }
+                break;
             }
             pending = deferred;
         }
 
-        Ok(())
+        if self.first_error.is_none() && !self.user_funcs.contains("spawn") {
+            if let Some((span, err)) = spawned_global_mut(program) {
+                self.span = span;
+                self.report(err);
+            }
+        }
+
+        match self.first_error.clone() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// `[A, B, A]` when alias `A` leads back to itself through other aliases, looking
+    /// inside references, function types and record literals but not into declared
+    /// records, which are nominal and never expanded.
+    fn alias_cycle(&self, start: &str) -> Option<Vec<String>> {
+        let mut path = vec![start.to_string()];
+        self.alias_cycle_from(&mut path).then_some(path)
+    }
+
+    fn alias_cycle_from(&self, path: &mut Vec<String>) -> bool {
+        let Some(current) = path.last() else {
+            return false;
+        };
+        let Some(ty) = self.types.get(current) else {
+            return false;
+        };
+        if self.builtins.contains(current) || matches!(ty, Type::Record(_)) {
+            return false;
+        }
+        let mut targets = Vec::new();
+        alias_targets(ty, &mut targets);
+        for target in targets {
+            if target == path[0] {
+                path.push(target);
+                return true;
+            }
+            if path.contains(&target) {
+                continue;
+            }
+            path.push(target);
+            if self.alias_cycle_from(path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    /// The return type of the first of `funcs` without one whose body has a type when
+    /// every `if` branch that needs an unknown return type is skipped: `n` for
+    /// `fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2)`. Nothing the trial
+    /// checks leave behind is kept.
+    fn infer_base_case(&mut self, funcs: &[&FuncDecl]) -> Option<(String, Type)> {
+        for func in funcs {
+            if self
+                .funcs
+                .get(&func.name.0)
+                .is_none_or(|sig| sig.ret.is_some())
+            {
+                continue;
+            }
+            let scopes = self.scopes.clone();
+            let sigs = self.funcs.clone();
+            let (reported, warned) = (self.diagnostics.len(), self.warnings.len());
+            let (first, span) = (self.first_error.clone(), self.span);
+            self.base_case_only = true;
+            let checked = self.check_func(func);
+            self.base_case_only = false;
+            let ret = self.funcs.get(&func.name.0).and_then(|sig| sig.ret.clone());
+            self.scopes = scopes;
+            self.funcs = sigs;
+            self.diagnostics.truncate(reported);
+            self.warnings.truncate(warned);
+            self.first_error = first;
+            self.span = span;
+            if let (Ok(()), Some(ret)) = (checked, ret) {
+                return Some((func.name.0.clone(), ret));
+            }
+        }
+        None
+    }
+
+    /// After `binding` failed to check, declare it with its annotated type anyway so
+    /// later uses of the name are checked against that instead of failing too.
+    fn declare_failed_binding(&mut self, binding: &Binding, depth: usize) {
+        if let Ok(ty) = self.resolve_type(&binding.ty) {
+            let drop = depth > 0 && self.has_drop_hook(&binding.ty);
+            self.insert_var(binding.name.0.clone(), ty, binding.mutable, depth, drop);
+        }
     }
 
     fn check_func(&mut self, func: &FuncDecl) -> Result<(), TypeError> {
@@ -319,15 +1202,29 @@ impl TypeChecker {
             .ok_or_else(|| TypeError::UnknownFunc(func.name.0.clone()))?;
 
         self.push_scope();
+        self.body_scope = None;
         let result = (|| {
             let depth = self.current_depth();
             for p in &sig.params {
                 let ty = self.resolve_type(&p.ty)?;
-                self.insert_var(p.name.0.clone(), ty, p.mutable, depth);
+                let drop = self.has_drop_hook(&p.ty);
+                self.insert_var(p.name.0.clone(), ty, p.mutable, depth, drop);
             }
+            let ret = match &sig.ret {
+                Some(ret) => Some(self.resolve_type(ret)?),
+                None => None,
+            };
             let body_info = match &func.body {
-                Expr::Block(b) => self.check_block(b, true)?,
-                other => self.check_expr(other, ValueMode::Move)?,
+                Expr::Block(b) => {
+                    self.expected = ret;
+                    let info = self.check_block(b, true)?;
+                    self.expr_types.record(&func.body, &info.ty);
+                    info
+                }
+                other => match ret {
+                    Some(ret) => self.check_expr_as(other, &ret, ValueMode::Move)?,
+                    None => self.check_expr(other, ValueMode::Move)?,
+                },
             };
             self.ensure_not_escape(&body_info, depth)?;
 
@@ -348,36 +1245,238 @@ impl TypeChecker {
     }
 
     fn check_binding(&mut self, binding: &Binding, depth: usize) -> Result<(), TypeError> {
+        if depth > 0 {
+            self.check_shadowing(&binding.name.0, depth, binding.span)?;
+        }
         let ty_ann = self.resolve_type(&binding.ty)?;
-        let value = self.check_expr(&binding.value, ValueMode::Move)?;
+        let value = self.check_expr_as(&binding.value, &ty_ann, ValueMode::Move)?;
         self.ensure_not_escape(&value, depth)?;
         self.ensure_type(&ty_ann, &value.ty)?;
-        self.insert_var(binding.name.0.clone(), ty_ann, binding.mutable, depth);
+        // globals live for the whole program and are never dropped
+        let drop = depth > 0 && self.has_drop_hook(&binding.ty);
+        let loans = self.loans_taken_by(&binding.value);
+        self.insert_var(binding.name.0.clone(), ty_ann, binding.mutable, depth, drop);
+        if let Some(info) = self
+            .scopes
+            .last_mut()
+            .and_then(|s| s.vars.get_mut(&binding.name.0))
+        {
+            info.loans = loans;
+            if depth > 0 {
+                info.span = binding.span;
+            }
+        }
         Ok(())
     }
 
-    fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), TypeError> {
-        match stmt {
-            Stmt::Binding(b) => {
-                let depth = self.current_depth();
-                self.check_binding(b, depth)
-            }
-            Stmt::Assign(a) => self.check_assign(a),
-            Stmt::Expr(e) => {
-                self.check_expr(e, ValueMode::Move)?;
+    /// A block binding may shadow a global, a parameter or a binding of an enclosing
+    /// block: its initializer still reads the outer one, and the outer one, with its own
+    /// moves and loans, is visible again once the block ends. It may not redeclare a name
+    /// of its own block, or a parameter at the top of the function body, since both
+    /// would be the same scope in C. Shadowing is legal but can be linted.
+    fn check_shadowing(&mut self, name: &str, depth: usize, span: Span) -> Result<(), TypeError> {
+        let same_scope = |d: usize| {
+            self.scopes
+                .get(d)
+                .is_some_and(|s| s.vars.contains_key(name))
+        };
+        if same_scope(depth) || (self.body_scope == Some(depth) && same_scope(depth - 1)) {
+            return Err(TypeError::Redeclared(name.to_string()));
+        }
+        let Some(outer) = (0..depth).rev().find(|&d| same_scope(d)) else {
+            return Ok(());
+        };
+        let what = if outer == 0 { "the global" } else { "an outer" };
+        self.warnings.push(
+            Warning::new(Lint::Shadowing, format!("{name} shadows {what} {name}")).with_span(span),
+        );
+        Ok(())
+    }
+
+    /// The bindings `value` borrows from, which stay borrowed while the binding that
+    /// ends up holding it is in scope: `&x...` / `&mut x...` borrows `x`, and a value
+    /// built from references (another reference binding, a record with a reference
+    /// field, a call returning a reference) borrows whatever those do.
+    fn loans_taken_by(&self, value: &Expr) -> Vec<Loan> {
+        let mut loans = Vec::new();
+        self.collect_loans(value, &mut loans);
+        loans
+    }
+
+    fn collect_loans(&self, value: &Expr, loans: &mut Vec<Loan>) {
+        match value {
+            Expr::Ref(inner) | Expr::MutRef(inner) => {
+                let Expr::Path(path) = &**inner else {
+                    return self.collect_loans(inner, loans);
+                };
+                let mutable = matches!(value, Expr::MutRef(_));
+                self.borrow_head(path, mutable, loans);
+            }
+            // a reference, or a record holding some, copied or moved out of a binding
+            Expr::Path(path) => {
+                if let Some((_, info)) = self.head_binding(path) {
+                    loans.extend(info.loans);
+                }
+            }
+            Expr::Copy(inner) => self.collect_loans(inner, loans),
+            Expr::RecordLit(r) => {
+                for part in r
+                    .base
+                    .iter()
+                    .map(|b| &**b)
+                    .chain(r.fields.iter().map(|f| &f.value))
+                {
+                    self.collect_loans(part, loans);
+                }
+            }
+            // a returned reference can point into anything the arguments borrow
+            Expr::FuncCall(call) => {
+                let returns_ref = self
+                    .expr_types
+                    .get(value)
+                    .is_some_and(|ty| self.contains_ref(ty));
+                if !returns_ref {
+                    return;
+                }
+                if call.method_name().is_some() {
+                    self.borrow_head(&call.callee, false, loans);
+                }
+                for arg in &call.args {
+                    self.collect_loans(arg, loans);
+                }
+            }
+            Expr::If(ife) => {
+                self.collect_loans(&ife.then_branch, loans);
+                self.collect_loans(&ife.else_branch, loans);
+            }
+            Expr::Block(b) => {
+                if let Some(tail) = &b.tail {
+                    self.collect_loans(tail, loans);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Borrow the binding at the head of `path`, and through it whatever it borrows.
+    fn borrow_head(&self, path: &Path, mutable: bool, loans: &mut Vec<Loan>) {
+        let Some((depth, info)) = self.head_binding(path) else {
+            return;
+        };
+        if let Some(head) = path.0.first() {
+            loans.push(Loan {
+                name: head.0.clone(),
+                depth,
+                mutable,
+            });
+        }
+        loans.extend(info.loans);
+    }
+
+    fn head_binding(&self, path: &Path) -> Option<(usize, BindingInfo)> {
+        let head = path.0.first()?;
+        self.lookup_binding(&Path(vec![head.clone()], path.1)).ok()
+    }
+
+    /// Reject a use of the binding at the head of `path` while a reference binding
+    /// holds it: any use under a `&mut` loan, and everything but reads under a `&` loan.
+    fn check_loans(&self, path: &Path, read_only: bool) -> Result<(), TypeError> {
+        let Some(head) = path.0.first() else {
+            return Ok(());
+        };
+        let Ok((depth, _)) = self.lookup_binding(&Path(vec![head.clone()], path.1)) else {
+            return Ok(());
+        };
+        for scope in &self.scopes {
+            for (by, info) in &scope.vars {
+                let conflict = info.loans.iter().any(|loan| {
+                    loan.name == head.0 && loan.depth == depth && (loan.mutable || !read_only)
+                });
+                if conflict {
+                    return Err(TypeError::Borrowed {
+                        name: head.0.clone(),
+                        by: by.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `&mut place`: the place's binding must be `mut`, or itself a `&mut` reference.
+    fn check_mut_ref(&mut self, inner: &Expr) -> Result<TyInfo, TypeError> {
+        let Expr::Path(path) = inner else {
+            return Err(TypeError::MutRefTarget);
+        };
+        let head = path.0.first().ok_or(TypeError::MutRefTarget)?;
+        let (_, root) = self.lookup_binding(&Path(vec![head.clone()], path.1))?;
+        let through_ref = matches!(self.resolve_type(&root.ty)?, Type::MutRef(_));
+        if !root.mutable && !through_ref {
+            return Err(TypeError::NotMutable(path_to_string(path)));
+        }
+        if root.moved {
+            return Err(TypeError::Moved(path_to_string(path)));
+        }
+        self.check_loans(path, false)?;
+        let (_, info) = self.lookup_binding(path)?;
+        // `&mut r` of a `&mut T` binding reborrows it as `&mut T`
+        let ty = match self.resolve_type(&info.ty)? {
+            Type::MutRef(inner) => *inner,
+            _ => info.ty,
+        };
+        Ok(TyInfo {
+            ty: Type::MutRef(Box::new(ty)),
+            origin_depth: info.origin_depth,
+            escapable: false,
+        })
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), TypeError> {
+        self.at(stmt.span(), |tc| tc.check_stmt_kind(stmt))
+    }
+
+    fn check_stmt_kind(&mut self, stmt: &Stmt) -> Result<(), TypeError> {
+        match stmt {
+            Stmt::Binding(b) => {
+                let depth = self.current_depth();
+                self.check_binding(b, depth)
+            }
+            Stmt::Assign(a) => self.check_assign(a),
+            Stmt::Expr(e) => {
+                self.check_expr(e, ValueMode::Move)?;
                 Ok(())
             }
+            // lifted to the top level by `lower_program` before checking
+            Stmt::Func(_) => Ok(()),
         }
     }
 
     fn check_assign(&mut self, assign: &Assign) -> Result<(), TypeError> {
         let (binding_depth, info) = self.lookup_binding(&assign.target)?;
-        if !info.mutable {
-            return Err(TypeError::NotMutable(path_to_string(&assign.target)));
+        let head = Path(assign.target.0[..1].to_vec(), assign.target.1);
+        let (_, root) = self.lookup_binding(&head)?;
+        // assigning to a `&mut T` binding, or a field through it, writes to the referee
+        let target_ty = match self.resolve_type(&root.ty)? {
+            Type::MutRef(inner) if assign.target.0.len() == 1 => *inner,
+            Type::MutRef(_) => info.ty.clone(),
+            Type::Ref(_) if assign.target.0.len() > 1 => {
+                return Err(TypeError::AssignThroughRef(path_to_string(&assign.target)));
+            }
+            _ if !info.mutable => {
+                return Err(TypeError::NotMutable(path_to_string(&assign.target)));
+            }
+            _ => info.ty.clone(),
+        };
+        if assign.target.0.len() == 1 && info.drop && !info.moved {
+            return Err(TypeError::DropOverwrite(path_to_string(&assign.target)));
         }
-        let value = self.check_expr(&assign.value, ValueMode::Move)?;
+        self.check_loans(&assign.target, false)?;
+        let value = self.check_expr_as(&assign.value, &target_ty, ValueMode::Move)?;
         self.ensure_not_escape(&value, binding_depth)?;
-        self.ensure_type(&info.ty, &value.ty)?;
+        self.ensure_type(&target_ty, &value.ty)?;
+        // the old value's loans are kept: the binding may still hold them on another path
+        let loans = self.loans_taken_by(&assign.value);
+        self.add_loans(&head, loans);
         // mark the binding as refreshed (not moved)
         self.set_moved(&assign.target, false)?;
         Ok(())
@@ -388,27 +1487,47 @@ impl TypeChecker {
         block: &Block,
         allow_escape_values: bool,
     ) -> Result<TyInfo, TypeError> {
+        let expected = self.expected.take();
         self.push_scope();
         let depth = self.current_depth();
+        if allow_escape_values {
+            self.body_scope = Some(depth);
+        }
+        self.warn_unreachable(block);
         for stmt in &block.stmts {
-            self.check_stmt(stmt)?;
+            let (outer, scopes) = (self.span, self.scopes.len());
+            match self.check_stmt(stmt) {
+                Ok(()) => {}
+                Err(e @ TypeError::UnknownFuncReturn(_)) => return Err(e),
+                Err(e) => {
+                    self.report(e);
+                    self.span = outer;
+                    self.scopes.truncate(scopes);
+                    if let Stmt::Binding(b) = stmt {
+                        self.declare_failed_binding(b, depth);
+                    }
+                }
+            }
         }
         let tail_ty = if let Some(expr) = &block.tail {
+            self.expected = expected;
             let info = self.check_expr(expr, ValueMode::Move)?;
+            // under `--gc` no block has an arena its value could be left in
+            let leaves = allow_escape_values || self.gc;
             if info.origin_depth > depth {
-                if !allow_escape_values || type_contains_ref(&info.ty) || !info.escapable {
+                if !leaves || self.contains_ref(&info.ty) || !(info.escapable || self.gc) {
                     return Err(TypeError::Escape);
                 }
             } else {
                 self.ensure_not_escape(&info, depth)?;
             }
-            if allow_escape_values {
+            if leaves {
                 // normalize origin to this depth; escapable only if it has no refs
                 let ty_clone = info.ty.clone();
                 TyInfo {
                     ty: info.ty,
                     origin_depth: depth,
-                    escapable: !type_contains_ref(&ty_clone),
+                    escapable: !self.contains_ref(&ty_clone),
                 }
             } else {
                 // value produced in an inner block expression should not be allowed to escape further
@@ -429,10 +1548,65 @@ impl TypeChecker {
         Ok(tail_ty)
     }
 
+    /// Borrow the base of `s[i]` / `s[a..b]` and return its `Str` or `Bytes` type.
+    fn check_indexable(&mut self, base: &Expr) -> Result<Type, TypeError> {
+        // indexing only reads the base, so it is never moved
+        let base = self.check_expr(base, ValueMode::Borrow)?;
+        let elem = match self.resolve_type(&base.ty)? {
+            Type::Ref(inner) | Type::MutRef(inner) => self.resolve_type(&inner)?,
+            other => other,
+        };
+        if !matches!(&elem, Type::Named(Ident(n)) if n == "Str" || n == "Bytes") {
+            return Err(TypeError::NotIndexable(base.ty));
+        }
+        Ok(elem)
+    }
+
     fn check_expr(&mut self, expr: &Expr, mode: ValueMode) -> Result<TyInfo, TypeError> {
+        let info = self.at(expr.span(), |tc| tc.check_expr_kind(expr, mode))?;
+        self.expr_types.record(expr, &info.ty);
+        Ok(info)
+    }
+
+    /// [`Self::check_expr`] where a value of `ty` is expected. The expectation flows
+    /// into `if` branches, block tails and record literal fields, so an integer literal
+    /// there gets type `ty` (`x: i64 = 5`, `b: u8 = 255`) instead of the default integer.
+    fn check_expr_as(
+        &mut self,
+        expr: &Expr,
+        ty: &Type,
+        mode: ValueMode,
+    ) -> Result<TyInfo, TypeError> {
+        self.expected = Some(ty.clone());
+        self.check_expr(expr, mode)
+    }
+
+    /// The type of integer literal `value`: the expected type when that is an integer
+    /// type, else the default integer. The value must fit it.
+    fn int_literal(&self, value: i64, expected: Option<Type>) -> Result<TyInfo, TypeError> {
+        let ty = match expected {
+            Some(ty @ Type::Named(_)) if int_range(&ty.to_string()).is_some() => ty,
+            _ => self.int_ty.clone(),
+        };
+        if !int_range(&ty.to_string()).is_some_and(|range| range.contains(&value)) {
+            return Err(TypeError::IntLiteralRange { value, ty });
+        }
+        Ok(TyInfo {
+            ty,
+            origin_depth: self.current_depth(),
+            escapable: true,
+        })
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr, mode: ValueMode) -> Result<TyInfo, TypeError> {
+        let expected = match self.expected.take() {
+            Some(ty) => Some(self.resolve_type(&ty)?),
+            None => None,
+        };
         match expr {
-            Expr::Literal(l) => Ok(TyInfo {
-                ty: literal_type(l),
+            Expr::Literal(Literal::Int(v), _) => self.int_literal(*v, expected),
+            Expr::Literal(l, _) => Ok(TyInfo {
+                ty: literal_type(&self.int_ty, l),
                 origin_depth: self.current_depth(),
                 escapable: true,
             }),
@@ -449,26 +1623,117 @@ impl TypeChecker {
                     escapable: info.escapable,
                 })
             }
+            Expr::MutRef(inner) => self.check_mut_ref(inner),
             Expr::FuncCall(fc) => self.eval_call(fc),
             Expr::If(ifexpr) => {
+                // a drop hook must run exactly when the binding is still owned, so
+                // droppable values may not be moved on just one path
+                let live_drops = self.live_drop_bindings();
                 let cond = self.check_expr(&ifexpr.cond, ValueMode::Move)?;
                 self.ensure_type(&Type::Named(Ident("bool".into())), &cond.ty)?;
-                let t = self.check_expr(&ifexpr.then_branch, ValueMode::Move)?;
-                let e = self.check_expr(&ifexpr.else_branch, ValueMode::Move)?;
+                // each branch starts from the state after the condition; afterwards a
+                // binding is moved if either branch moved it
+                let before = self.scopes.clone();
+                self.expected = expected.clone();
+                let t = match self.check_expr(&ifexpr.then_branch, ValueMode::Move) {
+                    Err(TypeError::UnknownFuncReturn(f)) if self.base_case_only => {
+                        Err(TypeError::UnknownFuncReturn(f))
+                    }
+                    Err(err) => return Err(err),
+                    ok => ok,
+                };
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                // without a declared type, the else branch is expected to match the then
+                // branch: `if c then wide else 0`
+                self.expected = expected.or_else(|| t.as_ref().ok().map(|t| t.ty.clone()));
+                let e = self.check_expr(&ifexpr.else_branch, ValueMode::Move);
+                let (t, e) = match (t, e) {
+                    (Ok(t), Ok(e)) => (t, e),
+                    (Err(TypeError::UnknownFuncReturn(_)), Ok(known))
+                    | (Ok(known), Err(TypeError::UnknownFuncReturn(_)))
+                        if self.base_case_only =>
+                    {
+                        (known.clone(), known)
+                    }
+                    (Err(err), _) | (_, Err(err)) => return Err(err),
+                };
+                self.merge_branch(after_then);
                 self.ensure_type(&t.ty, &e.ty)?;
+                for (scope, name) in live_drops {
+                    if self.scopes[scope].vars.get(&name).is_some_and(|b| b.moved) {
+                        return Err(TypeError::DropConditionalMove(name));
+                    }
+                }
                 Ok(TyInfo {
                     ty: t.ty,
                     origin_depth: std::cmp::max(t.origin_depth, e.origin_depth),
                     escapable: t.escapable && e.escapable,
                 })
             }
-            Expr::Block(b) => self.check_block(b, false),
+            Expr::Block(b) => {
+                self.expected = expected;
+                self.check_block(b, false)
+            }
+            Expr::Time(body) => {
+                // the block's value is dropped, so only its moves and errors matter
+                self.check_expr(body, ValueMode::Move)?;
+                Ok(TyInfo {
+                    ty: self.int_ty.clone(),
+                    origin_depth: self.current_depth(),
+                    escapable: true,
+                })
+            }
             Expr::RecordLit(r) => {
-                let mut fields = Vec::new();
+                // the base is evaluated first, in source order
+                let base = match &r.base {
+                    Some(b) => Some(self.check_expr(b, ValueMode::Move)?),
+                    None => None,
+                };
+                // the record type the literal must build: the base's, or the declared
+                // type it is checked against; its fields are matched by name
+                let owner = match (&base, expected) {
+                    (Some(base), _) => match self.record_fields(&base.ty)? {
+                        Some(fields) => Some((base.ty.clone(), fields)),
+                        None => return Err(TypeError::SpreadNotRecord(base.ty.clone())),
+                    },
+                    (None, Some(ty)) => self.record_fields(&ty)?.map(|fields| (ty, fields)),
+                    (None, None) => None,
+                };
+                let mut fields: Vec<FieldType> = Vec::new();
                 let mut max_depth = self.current_depth();
                 let mut escapable = true;
                 for f in &r.fields {
+                    if fields.iter().any(|seen| seen.name == f.name) {
+                        return Err(TypeError::DuplicateField(f.name.0.clone()));
+                    }
+                    let declared = match &owner {
+                        Some((ty, declared)) => match declared.iter().find(|d| d.name == f.name) {
+                            Some(d) => Some(d.ty.clone()),
+                            None => {
+                                return self.at(f.value.span(), |_| {
+                                    Err(TypeError::UnknownField {
+                                        ty: ty.clone(),
+                                        field: f.name.0.clone(),
+                                    })
+                                })
+                            }
+                        },
+                        None => None,
+                    };
+                    self.expected = declared.clone();
                     let val = self.check_expr(&f.value, ValueMode::Move)?;
+                    if let (Some((ty, _)), Some(declared)) = (&owner, declared) {
+                        if !self.type_eq(&declared, &val.ty)? {
+                            return self.at(f.value.span(), |_| {
+                                Err(TypeError::FieldMismatch {
+                                    ty: ty.clone(),
+                                    field: f.name.0.clone(),
+                                    expected: declared,
+                                    found: val.ty,
+                                })
+                            });
+                        }
+                    }
                     max_depth = max_depth.max(val.origin_depth);
                     escapable = escapable && val.escapable;
                     fields.push(FieldType {
@@ -476,16 +1741,66 @@ impl TypeChecker {
                         ty: val.ty,
                     });
                 }
+                if let Some(base) = base {
+                    // `{ ..base, f: v }` has the base's type
+                    return Ok(TyInfo {
+                        ty: base.ty,
+                        origin_depth: max_depth.max(base.origin_depth),
+                        escapable: escapable && base.escapable,
+                    });
+                }
+                if let Some((ty, declared)) = &owner {
+                    let missing: Vec<&str> = declared
+                        .iter()
+                        .filter(|d| !fields.iter().any(|f| f.name == d.name))
+                        .map(|d| d.name.0.as_str())
+                        .collect();
+                    if !missing.is_empty() {
+                        return Err(TypeError::MissingFields {
+                            ty: ty.clone(),
+                            fields: missing.join(", "),
+                        });
+                    }
+                }
                 Ok(TyInfo {
                     ty: Type::Record(fields),
                     origin_depth: max_depth,
                     escapable,
                 })
             }
+            Expr::Index(ix) => {
+                self.check_indexable(&ix.base)?;
+                let index = self.check_expr(&ix.index, ValueMode::Move)?;
+                self.ensure_type(&self.int_ty.clone(), &index.ty)?;
+                Ok(TyInfo {
+                    ty: self.int_ty.clone(),
+                    origin_depth: self.current_depth(),
+                    escapable: true,
+                })
+            }
+            Expr::Slice(sl) => {
+                let ty = self.check_indexable(&sl.base)?;
+                for bound in [&sl.start, &sl.end].into_iter().flatten() {
+                    let b = self.check_expr(bound, ValueMode::Move)?;
+                    self.ensure_type(&self.int_ty.clone(), &b.ty)?;
+                }
+                // a slice is a fresh copy of the bytes, not a view into the base
+                Ok(TyInfo {
+                    ty,
+                    origin_depth: self.current_depth(),
+                    escapable: true,
+                })
+            }
             Expr::Unary(u) => {
+                // `-128` is a negative literal, so it is checked as one
+                if let (UnaryOp::Neg, Expr::Literal(Literal::Int(v), _)) = (&u.op, &*u.expr) {
+                    let info = self.int_literal(v.wrapping_neg(), expected)?;
+                    self.expr_types.record(&u.expr, &info.ty);
+                    return Ok(info);
+                }
                 let val = self.check_expr(&u.expr, ValueMode::Move)?;
                 match u.op {
-                    UnaryOp::Neg => self.ensure_type(&Type::Named(Ident("i32".into())), &val.ty)?,
+                    UnaryOp::Neg => self.ensure_type(&self.int_ty.clone(), &val.ty)?,
                     UnaryOp::Not => {
                         self.ensure_type(&Type::Named(Ident("bool".into())), &val.ty)?
                     }
@@ -493,19 +1808,41 @@ impl TypeChecker {
                 Ok(val)
             }
             Expr::Binary(b) => {
-                let l = self.check_expr(&b.left, ValueMode::Move)?;
-                let r = self.check_expr(&b.right, ValueMode::Move)?;
+                let comparison = matches!(
+                    b.op,
+                    BinaryOp::Lt
+                        | BinaryOp::Gt
+                        | BinaryOp::Le
+                        | BinaryOp::Ge
+                        | BinaryOp::Eq
+                        | BinaryOp::Ne
+                );
+                let (l, r) = if comparison && is_int_literal(&b.left) {
+                    // `0 < copy wide`: the literal takes the other operand's type; it has
+                    // no effects, so checking it second changes nothing else
+                    let r = self.check_expr(&b.right, ValueMode::Move)?;
+                    let l = self.check_expr_as(&b.left, &r.ty, ValueMode::Move)?;
+                    (l, r)
+                } else {
+                    let l = self.check_expr(&b.left, ValueMode::Move)?;
+                    let r = if comparison {
+                        self.check_expr_as(&b.right, &l.ty, ValueMode::Move)?
+                    } else {
+                        self.check_expr(&b.right, ValueMode::Move)?
+                    };
+                    (l, r)
+                };
                 match b.op {
                     BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
-                        // allow i32 math, and Str + Str as concatenation (other combos are errors)
+                        // allow integer math, and Str + Str as concatenation (other combos are errors)
                         let escapable = l.escapable && r.escapable;
-                        if self.type_eq(&l.ty, &Type::Named(Ident("i32".into())))?
-                            && self.type_eq(&r.ty, &Type::Named(Ident("i32".into())))?
-                        {
+                        let int = self.int_ty.clone();
+                        if self.type_eq(&l.ty, &int)? && self.type_eq(&r.ty, &int)? {
+                            // a fresh scalar never lives in an arena, so it may always escape
                             Ok(TyInfo {
-                                ty: Type::Named(Ident("i32".into())),
+                                ty: int,
                                 origin_depth: std::cmp::max(l.origin_depth, r.origin_depth),
-                                escapable,
+                                escapable: true,
                             })
                         } else if self.type_eq(&l.ty, &Type::Named(Ident("Str".into())))?
                             && self.type_eq(&r.ty, &Type::Named(Ident("Str".into())))?
@@ -522,12 +1859,32 @@ impl TypeChecker {
                             })
                         }
                     }
-                    BinaryOp::Lt | BinaryOp::Eq => {
+                    BinaryOp::BitAnd
+                    | BinaryOp::BitOr
+                    | BinaryOp::BitXor
+                    | BinaryOp::Shl
+                    | BinaryOp::Shr => {
+                        // bitwise operators and shifts are integer-only
+                        let int = self.int_ty.clone();
+                        self.ensure_type(&int, &l.ty)?;
+                        self.ensure_type(&int, &r.ty)?;
+                        Ok(TyInfo {
+                            ty: int,
+                            origin_depth: std::cmp::max(l.origin_depth, r.origin_depth),
+                            escapable: true,
+                        })
+                    }
+                    BinaryOp::Lt
+                    | BinaryOp::Gt
+                    | BinaryOp::Le
+                    | BinaryOp::Ge
+                    | BinaryOp::Eq
+                    | BinaryOp::Ne => {
                         self.ensure_type(&l.ty, &r.ty)?;
                         Ok(TyInfo {
                             ty: Type::Named(Ident("bool".into())),
                             origin_depth: std::cmp::max(l.origin_depth, r.origin_depth),
-                            escapable: l.escapable && r.escapable,
+                            escapable: true,
                         })
                     }
                     BinaryOp::And | BinaryOp::Or => {
@@ -536,7 +1893,7 @@ impl TypeChecker {
                         Ok(TyInfo {
                             ty: Type::Named(Ident("bool".into())),
                             origin_depth: std::cmp::max(l.origin_depth, r.origin_depth),
-                            escapable: l.escapable && r.escapable,
+                            escapable: true,
                         })
                     }
                 }
@@ -545,7 +1902,15 @@ impl TypeChecker {
     }
 
     fn eval_path(&mut self, path: &Path, mode: ValueMode) -> Result<TyInfo, TypeError> {
-        let (_depth, info) = self.lookup_binding(path)?;
+        let (_depth, info) = match self.lookup_binding(path) {
+            Err(TypeError::UnknownIdent(name))
+                if path.0.len() == 1 && self.user_funcs.contains(&name) =>
+            {
+                return self.func_value(&name);
+            }
+            other => other?,
+        };
+        self.check_loans(path, !matches!(mode, ValueMode::Move))?;
         match mode {
             ValueMode::Move => {
                 if info.moved {
@@ -566,11 +1931,69 @@ impl TypeChecker {
         })
     }
 
+    /// Type of a top-level function named as a value; function values never hold refs.
+    fn func_value(&self, name: &str) -> Result<TyInfo, TypeError> {
+        let sig = &self.funcs[name];
+        let ret = sig
+            .ret
+            .clone()
+            .ok_or_else(|| TypeError::UnknownFuncReturn(name.to_string()))?;
+        Ok(TyInfo {
+            ty: Type::Func(FuncType {
+                params: sig.params.iter().map(|p| p.ty.clone()).collect(),
+                ret: Box::new(ret),
+            }),
+            origin_depth: 0,
+            escapable: true,
+        })
+    }
+
     fn eval_call(&mut self, call: &FuncCall) -> Result<TyInfo, TypeError> {
+        if let Some(method) = call.method_name() {
+            let receiver = match self.funcs.get(&method.0).and_then(|s| s.params.first()) {
+                Some(first) => Receiver::of(Some(&self.resolve_type(&first.ty)?)),
+                None => Receiver::Value,
+            };
+            if let Some(desugared) = call.desugar_method(receiver) {
+                return self.eval_call(&desugared);
+            }
+        }
         if call.callee.0.len() != 1 {
             return Err(TypeError::UnknownFunc(path_to_string(&call.callee)));
         }
         let name = call.callee.0[0].0.clone();
+        if name == "to_str" && !self.user_funcs.contains(&name) {
+            return self.check_to_str(call);
+        }
+        if name == TO_JSON && !self.user_funcs.contains(&name) {
+            return self.check_to_json(call);
+        }
+        // a binding of function type shadows top-level functions of the same name
+        if let Ok((_, info)) = self.lookup_binding(&call.callee) {
+            if let Type::Func(f) = self.resolve_type(&info.ty)? {
+                if info.moved {
+                    return Err(TypeError::Moved(name));
+                }
+                if f.params.len() != call.args.len() {
+                    return Err(TypeError::ArityMismatch {
+                        expected: f.params.len(),
+                        found: call.args.len(),
+                    });
+                }
+                self.check_exclusive_args(&call.args)?;
+                for (arg_expr, pty) in call.args.iter().zip(f.params.iter()) {
+                    self.expected = Some(pty.clone());
+                    let arg = self.check_arg(arg_expr)?;
+                    self.ensure_type(pty, &arg.ty)?;
+                }
+                let ret_ty = *f.ret;
+                return Ok(TyInfo {
+                    escapable: !self.contains_ref(&ret_ty),
+                    ty: ret_ty,
+                    origin_depth: self.current_depth(),
+                });
+            }
+        }
         let sig = self
             .funcs
             .get(&name)
@@ -582,9 +2005,11 @@ impl TypeChecker {
                 found: call.args.len(),
             });
         }
+        self.check_exclusive_args(&call.args)?;
         for (arg_expr, param) in call.args.iter().zip(sig.params.iter()) {
-            let arg = self.check_expr(arg_expr, ValueMode::Move)?;
             let pty = self.resolve_type(&param.ty)?;
+            self.expected = Some(pty.clone());
+            let arg = self.check_arg(arg_expr)?;
             self.ensure_type(&pty, &arg.ty)?;
         }
         let ret_ty = sig
@@ -594,10 +2019,111 @@ impl TypeChecker {
         Ok(TyInfo {
             ty: ret_ty.clone(),
             origin_depth: self.current_depth(),
-            escapable: !type_contains_ref(&ret_ty),
+            escapable: !self.contains_ref(&ret_ty),
+        })
+    }
+
+    /// `to_str(v) -> Str` takes a value of any type both backends can render: integers,
+    /// `bool`, `Str`, records of those, or a reference to one.
+    fn check_to_str(&mut self, call: &FuncCall) -> Result<TyInfo, TypeError> {
+        let [arg] = call.args.as_slice() else {
+            return Err(TypeError::ArityMismatch {
+                expected: 1,
+                found: call.args.len(),
+            });
+        };
+        let arg = self.check_arg(arg)?;
+        let inner = match self.resolve_type(&arg.ty)? {
+            Type::Ref(inner) | Type::MutRef(inner) => *inner,
+            other => other,
+        };
+        if !self.renderable(&inner)? {
+            return Err(TypeError::NotRenderable(arg.ty));
+        }
+        Ok(TyInfo {
+            ty: named("Str"),
+            origin_depth: self.current_depth(),
+            escapable: true,
+        })
+    }
+
+    /// A `to_json(v)` that lowering could not resolve from declarations: the type of `v` is
+    /// recorded so that `check_program` can lower the program again with it.
+    fn check_to_json(&mut self, call: &FuncCall) -> Result<TyInfo, TypeError> {
+        let [arg] = call.args.as_slice() else {
+            return Err(TypeError::ArityMismatch {
+                expected: 1,
+                found: call.args.len(),
+            });
+        };
+        let arg = self.check_arg(arg)?;
+        let ty = self.resolve_type(&arg.ty)?;
+        self.json_args.insert((call.span.start, call.span.end), ty);
+        Ok(TyInfo {
+            ty: named("Str"),
+            origin_depth: self.current_depth(),
+            escapable: true,
         })
     }
 
+    fn renderable(&self, ty: &Type) -> Result<bool, TypeError> {
+        let fields = match self.resolve_type(ty)? {
+            Type::Named(id) => match self.types.get(&id.0) {
+                Some(Type::Record(fields)) => fields.clone(),
+                _ => {
+                    return Ok(matches!(
+                        id.0.as_str(),
+                        "i32" | "i64" | "u8" | "bool" | "Str"
+                    ))
+                }
+            },
+            Type::Record(fields) => fields,
+            Type::Ref(_) | Type::MutRef(_) | Type::Func(_) => return Ok(false),
+        };
+        for f in &fields {
+            if !self.renderable(&f.ty)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// A call argument. Passing a `&mut T` binding lends it to the callee for the call
+    /// instead of moving it.
+    fn check_arg(&mut self, arg: &Expr) -> Result<TyInfo, TypeError> {
+        if let Expr::Path(path) = arg {
+            if let Ok((_, info)) = self.lookup_binding(path) {
+                if matches!(self.resolve_type(&info.ty)?, Type::MutRef(_)) {
+                    return self.check_expr(arg, ValueMode::Borrow);
+                }
+            }
+        }
+        self.check_expr(arg, ValueMode::Move)
+    }
+
+    /// A binding passed as `&mut` may not appear in any other argument of the call.
+    fn check_exclusive_args(&self, args: &[Expr]) -> Result<(), TypeError> {
+        for (i, arg) in args.iter().enumerate() {
+            let Expr::MutRef(inner) = arg else {
+                continue;
+            };
+            let Expr::Path(path) = &**inner else {
+                continue;
+            };
+            let Some(head) = path.0.first() else {
+                continue;
+            };
+            let clash = args
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && expr_referenced_names(other).contains(&head.0));
+            if clash {
+                return Err(TypeError::MutBorrowConflict(head.0.clone()));
+            }
+        }
+        Ok(())
+    }
+
     fn ensure_type(&self, expected: &Type, found: &Type) -> Result<(), TypeError> {
         if self.type_eq(expected, found)? {
             Ok(())
@@ -610,20 +2136,32 @@ impl TypeChecker {
     }
 
     fn ensure_not_escape(&self, info: &TyInfo, target_depth: usize) -> Result<(), TypeError> {
-        if info.origin_depth > target_depth {
-            if !info.escapable || type_contains_ref(&info.ty) {
-                return Err(TypeError::Escape);
-            }
+        let arena_bound = !info.escapable && !self.gc;
+        if info.origin_depth > target_depth && (arena_bound || self.contains_ref(&info.ty)) {
+            return Err(TypeError::Escape);
         }
         Ok(())
     }
 
+    /// Declared record types are nominal: two of them are equal only by name. A record
+    /// literal's structural type matches a declared one with the same fields.
     fn type_eq(&self, a: &Type, b: &Type) -> Result<bool, TypeError> {
         let ra = self.resolve_type(a)?;
         let rb = self.resolve_type(b)?;
         Ok(match (ra, rb) {
             (Type::Named(x), Type::Named(y)) => x == y,
-            (Type::Ref(ax), Type::Ref(bx)) => self.type_eq(&ax, &bx)?,
+            (named @ Type::Named(_), Type::Record(fields))
+            | (Type::Record(fields), named @ Type::Named(_)) => {
+                match self.record_fields(&named)? {
+                    Some(declared) => {
+                        self.type_eq(&Type::Record(declared), &Type::Record(fields))?
+                    }
+                    None => false,
+                }
+            }
+            (Type::Ref(ax), Type::Ref(bx)) | (Type::MutRef(ax), Type::MutRef(bx)) => {
+                self.type_eq(&ax, &bx)?
+            }
             (Type::Record(af), Type::Record(bf)) => {
                 if af.len() != bf.len() {
                     false
@@ -633,16 +2171,29 @@ impl TypeChecker {
                     })
                 }
             }
+            (Type::Func(af), Type::Func(bf)) => {
+                af.params.len() == bf.params.len()
+                    && self.type_eq(&af.ret, &bf.ret)?
+                    && af
+                        .params
+                        .iter()
+                        .zip(bf.params.iter())
+                        .all(|(a, b)| self.type_eq(a, b).unwrap_or(false))
+            }
             _ => false,
         })
     }
 
+    /// Expand aliases. A declared record type stays `Named` under the name of the
+    /// declaration that spells out its fields; [`Self::record_fields`] expands it.
     fn resolve_type(&self, ty: &Type) -> Result<Type, TypeError> {
         match ty {
             Type::Named(id) => {
                 if let Some(t) = self.types.get(&id.0) {
                     if self.builtins.contains(&id.0) {
                         Ok(t.clone())
+                    } else if matches!(t, Type::Record(_)) {
+                        Ok(ty.clone())
                     } else {
                         // expand aliases
                         Ok(self.resolve_type(t)?)
@@ -652,6 +2203,7 @@ impl TypeChecker {
                 }
             }
             Type::Ref(inner) => Ok(Type::Ref(Box::new(self.resolve_type(inner)?))),
+            Type::MutRef(inner) => Ok(Type::MutRef(Box::new(self.resolve_type(inner)?))),
             Type::Record(fields) => {
                 let mut out = Vec::new();
                 for f in fields {
@@ -662,6 +2214,56 @@ impl TypeChecker {
                 }
                 Ok(Type::Record(out))
             }
+            Type::Func(f) => {
+                let mut params = Vec::new();
+                for p in &f.params {
+                    params.push(self.resolve_type(p)?);
+                }
+                Ok(Type::Func(FuncType {
+                    params,
+                    ret: Box::new(self.resolve_type(&f.ret)?),
+                }))
+            }
+        }
+    }
+
+    /// Fields of a record type, declared or structural, with their types resolved;
+    /// `None` for anything else.
+    fn record_fields(&self, ty: &Type) -> Result<Option<Vec<FieldType>>, TypeError> {
+        let fields = match self.resolve_type(ty)? {
+            Type::Record(fields) => fields,
+            Type::Named(id) => match self.types.get(&id.0) {
+                Some(Type::Record(fields)) => fields.clone(),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        fields
+            .into_iter()
+            .map(|f| {
+                Ok(FieldType {
+                    ty: self.resolve_type(&f.ty)?,
+                    name: f.name,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    fn contains_ref(&self, ty: &Type) -> bool {
+        self.contains_ref_in(ty, &mut HashSet::new())
+    }
+
+    /// `seen` holds the declared records already entered, so recursive types end.
+    fn contains_ref_in<'a>(&'a self, ty: &'a Type, seen: &mut HashSet<&'a str>) -> bool {
+        match ty {
+            Type::Ref(_) | Type::MutRef(_) => true,
+            Type::Record(fields) => fields.iter().any(|f| self.contains_ref_in(&f.ty, seen)),
+            Type::Named(id) if !self.builtins.contains(&id.0) && seen.insert(&id.0) => self
+                .types
+                .get(&id.0)
+                .is_some_and(|t| self.contains_ref_in(t, seen)),
+            _ => false,
         }
     }
 
@@ -671,15 +2273,94 @@ impl TypeChecker {
         });
     }
 
+    /// Join the scopes after one `if` branch into the current ones, left by the other:
+    /// moved on either path means moved, and a name used or borrowed on either path is
+    /// used or borrowed.
+    fn merge_branch(&mut self, other: Vec<Scope>) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, info) in &mut scope.vars {
+                if let Some(o) = other.vars.get(name) {
+                    info.moved |= o.moved;
+                    info.used.set(info.used.get() || o.used.get());
+                    info.loans.extend(o.loans.iter().cloned());
+                }
+            }
+        }
+    }
+
+    /// Record that the binding at the head of `path` now also holds `loans`.
+    fn add_loans(&mut self, path: &Path, loans: Vec<Loan>) {
+        let Some(head) = path.0.first() else {
+            return;
+        };
+        if let Some(info) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|s| s.vars.get_mut(&head.0))
+        {
+            info.loans.extend(loans);
+        }
+    }
+
+    /// Drop the innermost scope, warning about its block bindings nothing looked up.
+    /// Bindings with a drop hook are used by it running at the end of the scope.
     fn pop_scope(&mut self) {
-        self.scopes.pop();
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let mut unused: Vec<(&String, Span)> = scope
+            .vars
+            .iter()
+            .filter(|(name, info)| {
+                info.span.is_known() && !info.used.get() && !info.drop && !name.starts_with('_')
+            })
+            .map(|(name, info)| (name, info.span))
+            .collect();
+        unused.sort_by_key(|(_, span)| span.start);
+        for (name, span) in unused {
+            self.warnings.push(
+                Warning::new(Lint::UnusedVariable, format!("unused variable '{name}'"))
+                    .with_span(span),
+            );
+        }
+    }
+
+    /// Warn once per block about the statements after the first one that ends the
+    /// program.
+    fn warn_unreachable(&mut self, block: &Block) {
+        let exit_shadowed = self.user_funcs.contains("exit");
+        let Some(at) = first_diverging(block, exit_shadowed) else {
+            return;
+        };
+        let next = match block.stmts.get(at + 1) {
+            Some(stmt) => stmt.span(),
+            None => match &block.tail {
+                Some(tail) => tail.span(),
+                None => return,
+            },
+        };
+        self.warnings.push(
+            Warning::new(
+                Lint::UnreachableCode,
+                "unreachable code after a call to exit",
+            )
+            .with_span(next),
+        );
     }
 
     fn current_depth(&self) -> usize {
         self.scopes.len().saturating_sub(1)
     }
 
-    fn insert_var(&mut self, name: String, ty: Type, mutable: bool, origin_depth: usize) {
+    fn insert_var(
+        &mut self,
+        name: String,
+        ty: Type,
+        mutable: bool,
+        origin_depth: usize,
+        drop: bool,
+    ) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.vars.insert(
                 name,
@@ -688,11 +2369,33 @@ impl TypeChecker {
                     mutable,
                     moved: false,
                     origin_depth,
+                    drop,
+                    loans: Vec::new(),
+                    used: Cell::new(false),
+                    span: Span::default(),
                 },
             );
         }
     }
 
+    /// Whether values declared as `ty` have a `Drop` impl (lowered to `T__drop`).
+    fn has_drop_hook(&self, ty: &Type) -> bool {
+        drop_fn(ty).is_some_and(|f| self.user_funcs.contains(&f))
+    }
+
+    /// Droppable bindings that are still live, as (scope index, name).
+    fn live_drop_bindings(&self) -> Vec<(usize, String)> {
+        let mut out = Vec::new();
+        for (i, scope) in self.scopes.iter().enumerate() {
+            for (name, info) in &scope.vars {
+                if info.drop && !info.moved {
+                    out.push((i, name.clone()));
+                }
+            }
+        }
+        out
+    }
+
     fn lookup_binding(&self, path: &Path) -> Result<(usize, BindingInfo), TypeError> {
         let (head, rest) = path
             .0
@@ -700,29 +2403,19 @@ impl TypeChecker {
             .ok_or_else(|| TypeError::UnknownIdent("".into()))?;
         for (depth_rev, scope) in self.scopes.iter().rev().enumerate() {
             if let Some(info) = scope.vars.get(&head.0) {
+                info.used.set(true);
                 let depth = self.scopes.len().saturating_sub(1) - depth_rev;
                 let mut ty = info.ty.clone();
                 for field in rest {
                     // unwrap references transparently during field access
-                    loop {
-                        match ty {
-                            Type::Ref(inner) => {
-                                ty = *inner.clone();
-                                continue;
-                            }
-                            _ => break,
-                        }
+                    while let Type::Ref(inner) | Type::MutRef(inner) = ty {
+                        ty = *inner;
                     }
 
-                    match ty {
-                        Type::Record(ref fields) => {
-                            if let Some(ft) = fields.iter().find(|f| f.name == *field) {
-                                ty = ft.ty.clone();
-                            } else {
-                                return Err(TypeError::UnknownIdent(field.0.clone()));
-                            }
-                        }
-                        _ => return Err(TypeError::UnknownIdent(field.0.clone())),
+                    let fields = self.record_fields(&ty)?.unwrap_or_default();
+                    match fields.into_iter().find(|f| f.name == *field) {
+                        Some(ft) => ty = ft.ty,
+                        None => return Err(TypeError::UnknownIdent(field.0.clone())),
                     }
                 }
                 return Ok((
@@ -732,6 +2425,10 @@ impl TypeChecker {
                         mutable: info.mutable,
                         moved: info.moved,
                         origin_depth: info.origin_depth,
+                        drop: info.drop && rest.is_empty(),
+                        loans: info.loans.clone(),
+                        used: info.used.clone(),
+                        span: info.span,
                     },
                 ));
             }
@@ -766,19 +2463,40 @@ enum ValueMode {
     Borrow,
 }
 
-fn literal_type(lit: &Literal) -> Type {
+fn named(name: &str) -> Type {
+    Type::Named(Ident(name.into()))
+}
+
+fn builtin_sig(params: &[(&str, Type)], ret: Type) -> FuncSig {
+    FuncSig {
+        params: params
+            .iter()
+            .map(|(name, ty)| Param {
+                mutable: false,
+                name: Ident((*name).into()),
+                ty: ty.clone(),
+            })
+            .collect(),
+        ret: Some(ret),
+    }
+}
+
+fn literal_type(int: &Type, lit: &Literal) -> Type {
     match lit {
-        Literal::Int(_) => Type::Named(Ident("i32".into())),
+        Literal::Int(_) => int.clone(),
         Literal::Bool(_) => Type::Named(Ident("bool".into())),
         Literal::Str(_) => Type::Named(Ident("Str".into())),
         Literal::Unit => Type::Named(Ident("Unit".into())),
     }
 }
 
-fn type_contains_ref(ty: &Type) -> bool {
-    match ty {
-        Type::Ref(_) => true,
-        Type::Record(fields) => fields.iter().any(|f| type_contains_ref(&f.ty)),
+/// An integer literal, possibly negated.
+fn is_int_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Int(_), _) => true,
+        Expr::Unary(u) => {
+            matches!(u.op, UnaryOp::Neg) && matches!(*u.expr, Expr::Literal(Literal::Int(_), _))
+        }
         _ => false,
     }
 }
@@ -791,6 +2509,174 @@ fn path_to_string(path: &Path) -> String {
         .join(".")
 }
 
+/// Every type name `ty` mentions, in order.
+fn alias_targets(ty: &Type, out: &mut Vec<String>) {
+    match ty {
+        Type::Named(id) => out.push(id.0.clone()),
+        Type::Ref(inner) | Type::MutRef(inner) => alias_targets(inner, out),
+        Type::Record(fields) => {
+            for f in fields {
+                alias_targets(&f.ty, out);
+            }
+        }
+        Type::Func(f) => {
+            for p in &f.params {
+                alias_targets(p, out);
+            }
+            alias_targets(&f.ret, out);
+        }
+    }
+}
+
+/// `a -> b -> a`: a cycle of calls among `funcs`, which are stuck on each other's return
+/// types, starting from the first.
+fn return_cycle(funcs: &[&FuncDecl]) -> Option<String> {
+    let stuck: HashMap<&str, &FuncDecl> = funcs.iter().map(|f| (f.name.0.as_str(), *f)).collect();
+    let mut path: Vec<&str> = vec![funcs.first()?.name.0.as_str()];
+    loop {
+        let current = stuck.get(path.last()?)?;
+        let mut callees: Vec<String> = expr_referenced_names(&current.body)
+            .into_iter()
+            .filter(|n| stuck.contains_key(n.as_str()))
+            .collect();
+        callees.sort();
+        let next = stuck.get_key_value(callees.first()?.as_str())?.0;
+        if let Some(start) = path.iter().position(|n| n == next) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(next);
+            return Some(cycle.join(" -> "));
+        }
+        path.push(next);
+    }
+}
+
+/// The first function that can run on a spawned thread, found by following calls and
+/// function values from every `spawn` argument, and a `global mut` it reads or writes.
+/// Each interpreter thread gets its own copy of the globals while C threads share one,
+/// so neither could be relied on. An argument that names no function (a parameter of
+/// function type, say) could be any function used as a value.
+fn spawned_global_mut(program: &Program) -> Option<(Span, TypeError)> {
+    let funcs: HashMap<&str, &FuncDecl> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Func(f) => Some((f.name.0.as_str(), f)),
+            _ => None,
+        })
+        .collect();
+    let mutable: HashSet<&str> = program
+        .decls
+        .iter()
+        .filter_map(|d| match d {
+            Decl::Global(b) if b.mutable => Some(b.name.0.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut sites = SpawnSites::default();
+    visit::walk_program(&mut sites, program);
+    let mut pending = Vec::new();
+    for names in sites.args {
+        let named: Vec<String> = names
+            .into_iter()
+            .filter(|n| funcs.contains_key(n.as_str()))
+            .collect();
+        if named.is_empty() {
+            pending.extend(
+                sites
+                    .values
+                    .iter()
+                    .filter(|n| funcs.contains_key(n.as_str()))
+                    .cloned(),
+            );
+        }
+        pending.extend(named);
+    }
+    pending.sort();
+    pending.reverse();
+    let mut seen = HashSet::new();
+    while let Some(name) = pending.pop() {
+        let Some(f) = funcs.get(name.as_str()) else {
+            continue;
+        };
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let bound = bound_names(&f.body);
+        let mut free: Vec<String> = expr_referenced_names(&f.body)
+            .into_iter()
+            .filter(|n| !bound.contains(n) && !f.params.iter().any(|p| p.name.0 == *n))
+            .collect();
+        free.sort();
+        if let Some(global) = free.iter().find(|n| mutable.contains(n.as_str())) {
+            let err = TypeError::SpawnedGlobalMut {
+                func: name,
+                global: global.clone(),
+            };
+            return Some((f.span, err));
+        }
+        pending.extend(free.into_iter().rev());
+    }
+    None
+}
+
+/// Names referred to by each `spawn` argument, and every name used as a value rather
+/// than called.
+#[derive(Default)]
+struct SpawnSites {
+    args: Vec<HashSet<String>>,
+    values: HashSet<String>,
+}
+
+impl Visit for SpawnSites {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Path(p) = expr {
+            if let Some(head) = p.0.first() {
+                self.values.insert(head.0.clone());
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_call(&mut self, call: &FuncCall) {
+        if let [name] = call.callee.0.as_slice() {
+            if name.0 == "spawn" {
+                self.args
+                    .extend(call.args.iter().map(expr_referenced_names));
+            }
+        }
+        visit::walk_call(self, call);
+    }
+}
+
+/// Top-level functions of the program as written that no other declaration names.
+/// `main`, `_`-prefixed names, imported modules' functions (`module__name`, which
+/// importers may use) and functions without a source position are left out.
+fn unused_functions(program: &Program) -> Vec<Warning> {
+    let referenced: Vec<HashSet<String>> =
+        program.decls.iter().map(decl_referenced_names).collect();
+    let mut warnings = Vec::new();
+    for (i, decl) in program.decls.iter().enumerate() {
+        let Decl::Func(f) = decl else {
+            continue;
+        };
+        let name = &f.name.0;
+        if name == "main" || name.starts_with('_') || name.contains("__") || !f.span.is_known() {
+            continue;
+        }
+        let used = referenced
+            .iter()
+            .enumerate()
+            .any(|(j, names)| j != i && names.contains(name));
+        if !used {
+            warnings.push(
+                Warning::new(Lint::UnusedFunction, format!("unused function '{name}'"))
+                    .with_span(f.span),
+            );
+        }
+    }
+    warnings
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -810,6 +2696,19 @@ mod tests {
         tc.check_program(&program).expect_err("expected type error")
     }
 
+    #[test]
+    fn record_types_are_nominal() {
+        let decls =
+            "type Point = { x: i32, y: i32 }\ntype Size = { x: i32, y: i32 }\ntype Pos = Point\n";
+        check_ok(&format!(
+            "{decls}main() = {{\n  p: Point = {{ x: 1, y: 2 }}\n  q: Pos = p\n  s: Size = {{ x: 3, y: 4 }}\n  copy q.x + copy s.y\n}}"
+        ));
+        let err = check_err(&format!(
+            "{decls}area(s: Size) -> i32 = copy s.x\nmain() = {{\n  p: Point = {{ x: 1, y: 2 }}\n  area(p)\n}}"
+        ));
+        assert_eq!(err.to_string(), "type mismatch: expected Size, found Point");
+    }
+
     #[test]
     fn success_hello() {
         let src = r#"
@@ -922,6 +2821,23 @@ mod tests {
         assert!(matches!(err, TypeError::Escape));
     }
 
+    #[test]
+    fn gc_heap_values_leave_their_blocks_but_references_do_not() {
+        let program = |src: &str| Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        tc.set_gc(true);
+        let ok = "main() = {\n  s: Str = { t: Str = \"a\" + \"b\"\n t }\n  str_len(s)\n}";
+        assert!(tc.check_program(&program(ok)).is_ok());
+        let arena = TypeChecker::new().check_program(&program(ok)).unwrap_err();
+        assert!(matches!(arena, TypeError::Escape), "{arena}");
+        let err = tc
+            .check_program(&program(
+                "main() = {\n  r: &i32 = { y: i32 = 2\n  ry: &i32 = &y\n  ry }\n  0\n}",
+            ))
+            .unwrap_err();
+        assert!(matches!(err, TypeError::Escape), "{err}");
+    }
+
     #[test]
     fn fail_type_mismatch() {
         let src = r#"
@@ -935,4 +2851,499 @@ mod tests {
         let err = check_err(src);
         assert!(matches!(err, TypeError::TypeMismatch { .. }));
     }
+
+    #[test]
+    fn success_higher_order_function() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = copy a + copy b
+        apply(f: (i32, i32) -> i32, x: i32) -> i32 = f(copy x, 1)
+        main() = {
+          op: (i32, i32) -> i32 = add
+          apply(op, 2)
+        }
+        "#;
+        check_ok(src);
+    }
+
+    #[test]
+    fn fail_function_type_mismatch() {
+        let src = r#"
+        neg(a: i32) -> i32 = -a
+        apply(f: (i32, i32) -> i32) -> i32 = f(1, 2)
+        main() = apply(neg)
+        "#;
+        let err = check_err(src);
+        assert!(matches!(err, TypeError::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn method_call_borrows_or_moves_receiver() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        length_x(p: &Point) -> i32 = p.x
+        scale(p: Point, k: i32) -> i32 = p.x * copy k
+        main() = {
+          p: Point = { x: 3, y: 4 }
+          a: i32 = p.length_x()
+          a + p.scale(2)
+        }
+        "#;
+        check_ok(src);
+        // scale takes the receiver by value, so a second call sees it moved
+        let moved = src.replace("a + p.scale(2)", "p.scale(2) + p.scale(3)");
+        assert!(matches!(check_err(&moved), TypeError::Moved(_)));
+    }
+
+    #[test]
+    fn trait_impl_methods_are_checked() {
+        check_ok(
+            r#"
+            type Name = { len: i32 }
+            trait Show { show(self) -> Str  width(&self, pad: i32) -> i32 }
+            impl Show for Name {
+              show(self) -> Str = "name"
+              width(&self, pad: i32) -> i32 = self.len + copy pad
+            }
+            main() = {
+              n: Name = { len: 3 }
+              w: i32 = n.width(2)
+              n.show()
+            }
+            "#,
+        );
+        let err = check_err(
+            r#"
+            type Name = { len: i32 }
+            trait Show { show(self) -> Str }
+            impl Show for Name { show(self) -> Str = self.len }
+            main() = 0
+            "#,
+        );
+        assert!(matches!(err, TypeError::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn drop_obligations_are_enforced() {
+        let base = r#"
+            type Handle = { id: i32 }
+            impl Drop for Handle { drop(&self) -> Unit = () }
+            consume(h: Handle) -> i32 = 1
+        "#;
+        let conditional = format!(
+            "{base}main() = {{\n h: Handle = {{ id: 1 }}\n if true then consume(h) else 0\n}}"
+        );
+        assert!(matches!(
+            check_err(&conditional),
+            TypeError::DropConditionalMove(_)
+        ));
+        let overwrite =
+            format!("{base}main() = {{\n mut h: Handle = {{ id: 1 }}\n h = {{ id: 2 }}\n 0\n}}");
+        assert!(matches!(check_err(&overwrite), TypeError::DropOverwrite(_)));
+        check_ok(&format!(
+            "{base}main() = {{\n mut h: Handle = {{ id: 1 }}\n n: i32 = consume(h)\n h = {{ id: 2 }}\n n\n}}"
+        ));
+    }
+
+    #[test]
+    fn fail_log_non_str() {
+        let src = r#"
+        main() = {
+          log_warn(1)
+        }
+        "#;
+        check_err(src);
+    }
+
+    #[test]
+    fn success_atomic_builtins() {
+        let src = r#"
+        main() = {
+          counter: atomic_i64 = atomic_new(0)
+          prev: i64 = atomic_add(&counter, 2)
+          swapped: bool = atomic_cas(&counter, 2, 5)
+          atomic_load(&counter)
+        }
+        "#;
+        check_ok(src);
+    }
+
+    #[test]
+    fn dyn_reads_must_check_presence() {
+        check_ok(
+            r#"
+        main() = {
+          d: Dyn = dyn_parse("{}")
+          dyn_set(&d, "k", "v")
+          f: DynField = dyn_get(&d, "k")
+          if copy f.found then str_len(copy f.value) else 0
+        }
+        "#,
+        );
+        let err = check_err(
+            r#"
+        main() = {
+          d: Dyn = dyn_new()
+          s: Str = dyn_get(&d, "k")
+          0
+        }
+        "#,
+        );
+        assert!(matches!(err, TypeError::TypeMismatch { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn fail_bitwise_on_bool() {
+        let src = r#"
+        main() = {
+          flag: bool = true
+          flag | false
+        }
+        "#;
+        let err = check_err(src);
+        assert!(matches!(err, TypeError::TypeMismatch { .. }));
+    }
+
+    #[test]
+    fn mut_refs_write_through_and_stay_exclusive() {
+        let base = r#"
+            type Counter = { hits: i32 }
+            bump(c: &mut Counter) = { c.hits = copy c.hits + 1 }
+            peek(c: &Counter) -> i32 = copy c.hits
+            both(a: &mut Counter, b: &Counter) = ()
+        "#;
+        check_ok(&format!(
+            "{base}main() = {{\n mut c: Counter = {{ hits: 0 }}\n bump(&mut c)\n c.bump()\n peek(&c)\n}}"
+        ));
+        let not_mut = format!("{base}main() = {{\n c: Counter = {{ hits: 0 }}\n bump(&mut c)\n}}");
+        assert!(matches!(check_err(&not_mut), TypeError::NotMutable(_)));
+        let aliased =
+            format!("{base}main() = {{\n mut c: Counter = {{ hits: 0 }}\n both(&mut c, &c)\n}}");
+        assert!(matches!(
+            check_err(&aliased),
+            TypeError::MutBorrowConflict(_)
+        ));
+        let held = format!(
+            "{base}main() = {{\n mut c: Counter = {{ hits: 0 }}\n r: &mut Counter = &mut c\n peek(&c)\n}}"
+        );
+        assert!(matches!(check_err(&held), TypeError::Borrowed { .. }));
+        let shared = "set(c: &Counter) = { c.hits = 1 }\ntype Counter = { hits: i32 }\nmain() = 0";
+        assert!(matches!(check_err(shared), TypeError::AssignThroughRef(_)));
+    }
+
+    #[test]
+    fn references_stored_anywhere_keep_their_referee_borrowed() {
+        let base = r#"
+            type Pt = { x: i32 }
+            type Holder = { r: &Pt }
+            take(p: Pt) -> i32 = 1
+            pick(a: &Pt, b: &Pt) -> &Pt = a
+            size(a: &Pt) -> i32 = 1
+        "#;
+        let borrowed = |body: &str| {
+            let src = format!(
+                "{base}main() -> i32 = {{\n p: Pt = {{ x: 1 }}\n q: Pt = {{ x: 2 }}\n{body}\n}}"
+            );
+            match check_err(&src) {
+                TypeError::Borrowed { name, by } => (name, by),
+                other => panic!("expected a borrow error, got {other}"),
+            }
+        };
+        let field = borrowed(" h: Holder = { r: &p }\n take(p)");
+        assert_eq!(field, ("p".to_string(), "h".to_string()));
+        let copied = borrowed(" r: &Pt = &p\n h: Holder = { r: r }\n take(p)");
+        assert_eq!(copied.0, "p");
+        let returned = borrowed(" r: &Pt = pick(&q, &p)\n take(p)");
+        assert_eq!(returned, ("p".to_string(), "r".to_string()));
+        // a call whose result holds no reference borrows nothing
+        check_ok(&format!(
+            "{base}main() -> i32 = {{\n p: Pt = {{ x: 1 }}\n n: i32 = size(&p)\n take(p)\n}}"
+        ));
+    }
+
+    #[test]
+    fn record_literals_are_checked_field_by_field_against_their_type() {
+        let base = "type Pt = { x: i32, y: i32 }\nshow(p: Pt) -> i32 = copy p.x\n";
+        let err = |body: &str| check_err(&format!("{base}main() -> i32 = {body}")).to_string();
+        assert_eq!(
+            err("{\n p: Pt = { x: 0 }\n copy p.x\n}"),
+            "Pt literal is missing y"
+        );
+        assert_eq!(err("show({ x: 0, y: 1, z: 2 })"), "Pt has no field z");
+        assert_eq!(
+            err("show({ x: 0, y: \"one\" })"),
+            "field y of Pt has type i32, found Str"
+        );
+        let mut tc = TypeChecker::new();
+        let program = Parser::new(&format!("{base}main() -> i32 = show({{ y: true }})"))
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        assert!(tc.check_program(&program).is_err());
+        // the error points at the offending field's value
+        assert_eq!(tc.error_span().map(|s| (s.line, s.col)), Some((3, 27)));
+    }
+
+    #[test]
+    fn record_spread_keeps_the_base_type() {
+        let base = "type Pt = { x: i32, y: i32 }\n";
+        check_ok(&format!(
+            "{base}main() = {{\n p: Pt = {{ x: 1, y: 2 }}\n q: Pt = {{ ..p, y: 3 }}\n q.y\n}}"
+        ));
+        let unknown = format!("{base}main() = {{\n p: Pt = {{ x: 1, y: 2 }}\n {{ ..p, z: 3 }}\n}}");
+        assert!(matches!(
+            check_err(&unknown),
+            TypeError::UnknownField { .. }
+        ));
+        let wrong =
+            format!("{base}main() = {{\n p: Pt = {{ x: 1, y: 2 }}\n {{ ..p, x: true }}\n}}");
+        assert!(matches!(check_err(&wrong), TypeError::FieldMismatch { .. }));
+        let twice =
+            format!("{base}main() = {{\n p: Pt = {{ x: 1, y: 2 }}\n {{ ..p, x: 1, x: 2 }}\n}}");
+        assert!(matches!(check_err(&twice), TypeError::DuplicateField(_)));
+        assert!(matches!(
+            check_err("main() = { ..1, x: 2 }"),
+            TypeError::SpreadNotRecord(_)
+        ));
+        let moved =
+            format!("{base}main() = {{\n p: Pt = {{ x: 1, y: 2 }}\n q: Pt = {{ ..p }}\n p.x\n}}");
+        assert!(matches!(check_err(&moved), TypeError::Moved(_)));
+    }
+
+    #[test]
+    fn unicode_names_are_compared_after_normalization() {
+        // the field is declared precomposed and used decomposed
+        check_ok("type 점 = { höhe: i32 }\n높이(p: 점) -> i32 = copy p.ho\u{308}he\nmain() = 높이({ höhe: 1 })");
+        assert_eq!(
+            check_err("main() = { 합계: i32 = 1\n 함계 }"),
+            TypeError::UnknownIdent("함계".into())
+        );
+    }
+
+    #[test]
+    fn indexing_borrows_str_and_bytes() {
+        check_ok(
+            "first(s: &Str) -> i32 = s[0]\nmain() = {\n s: Str = \"ab\"\n s[1] + first(&s)\n}",
+        );
+        check_ok("main() = {\n b: Bytes = args()\n b[0]\n}");
+        assert!(matches!(
+            check_err("main() = {\n n: i32 = 3\n n[0]\n}"),
+            TypeError::NotIndexable(_)
+        ));
+        assert!(matches!(
+            check_err("main() = {\n s: Str = \"ab\"\n s[true]\n}"),
+            TypeError::TypeMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn slices_keep_the_base_type() {
+        check_ok("tail(s: &Str) -> Str = s[1..]\nmain() = {\n s: Str = \"ab\"\n t: Str = s[..1]\n str_len(tail(&s)) + str_len(t)\n}");
+        check_ok("main() = {\n b: Bytes = args()\n c: Bytes = b[1..2]\n 0\n}");
+        assert!(matches!(
+            check_err("main() = {\n s: Str = \"ab\"\n t: Bytes = s[0..1]\n 0\n}"),
+            TypeError::TypeMismatch { .. }
+        ));
+        assert!(matches!(
+            check_err("main() = {\n n: i32 = 3\n n[0..1]\n}"),
+            TypeError::NotIndexable(_)
+        ));
+    }
+
+    #[test]
+    fn errors_point_at_the_innermost_node() {
+        let src = "main() = {\n  x: i32 = 1\n  y: i32 = x + \"a\"\n  y\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        assert!(tc.check_program(&program).is_err());
+        let span = tc.error_span().expect("span");
+        assert_eq!((span.line, span.col), (3, 12));
+        assert_eq!(&src[span.start..span.end], "x + \"a\"");
+
+        let ok = Parser::new("main() = 1").unwrap().parse_program().unwrap();
+        tc.check_program(&ok).unwrap();
+        assert_eq!(tc.error_span(), None);
+    }
+
+    #[test]
+    fn check_program_collects_every_error() {
+        let src = "g: i32 = true\nf() -> i32 = {\n  a: i32 = \"x\"\n  b: Str = copy a\n  a\n}\nmain() = {\n  q: bool = 1\n  f() + g\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        let first = tc.check_program(&program).unwrap_err();
+        let found: Vec<(u32, &str)> = tc
+            .diagnostics()
+            .iter()
+            .map(|d| (d.span.line, d.notes[0].as_str()))
+            .collect();
+        // `a` keeps its declared type after its initializer fails, so `a` itself is fine
+        assert_eq!(
+            found,
+            [
+                (1, "in the initializer of `g`"),
+                (3, "in function `f`"),
+                (4, "in function `f`"),
+                (8, "in function `main`"),
+            ]
+        );
+        assert_eq!(tc.diagnostics()[0].message, first.to_string());
+    }
+
+    #[test]
+    fn unused_names_and_unreachable_statements_are_warned_about() {
+        let src = "helper() -> i32 = 1\n\
+            _spare() -> i32 = 2\n\
+            used() -> i32 = 3\n\
+            main() -> i32 = {\n  x: i32 = used()\n  _y: i32 = 4\n  z: i32 = 5\n  exit(copy z)\n  x\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        tc.check_program(&program).unwrap();
+        let found: Vec<(Lint, u32, &str)> = tc
+            .warnings()
+            .iter()
+            .map(|w| (w.lint, w.span.line, w.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Lint::UnusedFunction, 1, "unused function 'helper'"),
+                (
+                    Lint::UnreachableCode,
+                    9,
+                    "unreachable code after a call to exit"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_bindings_are_reported_as_their_scopes_close() {
+        let src =
+            "main() -> i32 = {\n  a: i32 = 1\n  if true then {\n    c: i32 = 2\n  } else {}\n  3\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        tc.check_program(&program).unwrap();
+        let found: Vec<(u32, &str)> = tc
+            .warnings()
+            .iter()
+            .map(|w| (w.span.line, w.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [(4, "unused variable 'c'"), (2, "unused variable 'a'")]
+        );
+    }
+
+    #[test]
+    fn moves_in_one_if_branch_do_not_leak_into_the_other() {
+        let take = "take(s: Str) -> i32 = 1\n";
+        check_ok(&format!(
+            "{take}main() -> i32 = {{\n  s: Str = \"x\"\n  if true then take(s) else take(s)\n}}"
+        ));
+        // still moved afterwards when only one branch moved it
+        let err = check_err(&format!(
+            "{take}main() -> i32 = {{\n  s: Str = \"x\"\n  n: i32 = if true then take(s) else 0\n  take(s)\n}}"
+        ));
+        assert_eq!(err, TypeError::Moved("s".into()));
+    }
+
+    #[test]
+    fn recursive_returns_are_inferred_from_a_base_case() {
+        check_ok(
+            "even(n: i32) = if copy n == 0 then true else odd(n - 1)\n\
+             odd(n: i32) = if copy n == 0 then false else even(n - 1)\n\
+             main() = even(4)",
+        );
+        let err = check_err("a(n: i32) = b(n)\nb(n: i32) = a(n)\nmain() = a(1)");
+        assert_eq!(err, TypeError::ReturnCycle("a -> b -> a".into()));
+    }
+
+    #[test]
+    fn integer_literals_take_the_type_their_context_expects() {
+        check_ok(
+            "type Px = { r: u8, g: u8 }\n\
+             wide(n: i64) -> i64 = if copy n < 0 then 0 else n\n\
+             main() -> i32 = {\n  b: u8 = 255\n  p: Px = { r: 0, g: 128 }\n  \
+             w: i64 = if copy b == 255 then wide(3000000000) else -5\n  \
+             if 0 < copy w && copy p.g == 128 then 1 else 0\n}",
+        );
+        assert_eq!(
+            check_err("main() -> i32 = {\n  b: u8 = 256\n  0\n}").to_string(),
+            "integer literal 256 does not fit u8"
+        );
+        assert_eq!(
+            check_err("main() -> i32 = -2147483649").to_string(),
+            "integer literal -2147483649 does not fit i32"
+        );
+    }
+
+    #[test]
+    fn bindings_may_shadow_outer_names_but_not_their_own_block() {
+        let src = "global n: i32 = 1\n\
+                   f(a: i32) -> i32 = {\n  \
+                   if copy a > 0 then {\n    a: i32 = copy a + copy n\n    n: i32 = copy a\n    \
+                   println(int_to_str(copy n))\n  } else println(\"\")\n  copy a\n}\n\
+                   main() -> i32 = f(1)";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut tc = TypeChecker::new();
+        tc.check_program(&program).unwrap();
+        let shadows: Vec<String> = tc
+            .warnings()
+            .iter()
+            .filter(|w| w.lint == Lint::Shadowing)
+            .map(|w| w.message.clone())
+            .collect();
+        assert_eq!(shadows, ["a shadows an outer a", "n shadows the global n"]);
+
+        for src in [
+            "main() -> i32 = {\n  x: i32 = 1\n  x: i32 = copy x\n  copy x\n}",
+            "f(a: i32) -> i32 = {\n  a: i32 = 2\n  copy a\n}\nmain() -> i32 = f(1)",
+        ] {
+            let err = check_err(src);
+            assert!(matches!(err, TypeError::Redeclared(_)), "{err}");
+        }
+    }
+
+    #[test]
+    fn spawned_functions_may_not_use_mutable_globals() {
+        let err = check_err(
+            "global mut hits: i32 = 0\nbump() -> Unit = { hits = copy hits + 1 }\nwork() -> Unit = bump()\nmain() = spawn(work)",
+        );
+        assert_eq!(
+            err,
+            TypeError::SpawnedGlobalMut {
+                func: "bump".into(),
+                global: "hits".into(),
+            }
+        );
+        // through a function value, and reads count too
+        let err = check_err("global mut hits: i32 = 0\nread() -> Unit = { shown: Str = println(int_to_str(copy hits)) }\nrun(f: () -> Unit) -> Unit = spawn(f)\nmain() = run(read)");
+        assert!(matches!(err, TypeError::SpawnedGlobalMut { .. }), "{err}");
+        check_ok("global jobs: Chan = channel()\nglobal mut hits: i32 = 0\nwork() -> Unit = send(&jobs, \"x\")\nmain() = {\n  spawn(work)\n  hits = copy hits + 1\n}");
+    }
+
+    #[test]
+    fn main_takes_no_parameters() {
+        let err = check_err("main(args: Str) -> i32 = 0");
+        assert!(matches!(err, TypeError::MainHasParams), "{err}");
+    }
+
+    #[test]
+    fn alias_cycles_are_reported_with_their_chain() {
+        let err = check_err("type A = B\ntype B = &C\ntype C = A\nmain() = 1");
+        assert_eq!(err, TypeError::AliasCycle("A -> B -> C -> A".into()));
+        // declared records are nominal, so a record may refer to itself
+        check_ok("type Node = { next: &Node }\ntype N = Node\nmain() = 1");
+    }
+
+    #[test]
+    fn to_str_takes_scalars_and_records_of_them() {
+        check_ok(
+            "type P = { x: i32, name: Str }\n\
+             main() = { p: P = { x: 1, name: \"a\" }\n s: Str = to_str(&p)\n to_str(true) }",
+        );
+        let err = check_err("f() = 1\nmain() = { to_str(f)\n 0 }");
+        assert!(matches!(err, TypeError::NotRenderable(_)), "{err}");
+        let err = check_err("type Q = { b: Bytes }\nshow(q: Q) -> Str = to_str(q)\nmain() = 0");
+        assert!(matches!(err, TypeError::NotRenderable(_)), "{err}");
+    }
 }
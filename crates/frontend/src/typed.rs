@@ -0,0 +1,195 @@
+#![forbid(unsafe_code)]
+
+//! The output of [`TypeChecker::check_program`](crate::typecheck::TypeChecker::check_program):
+//! the lowered program the checker walked, with the type it resolved for each expression.
+
+use crate::ast::fold::{self, Fold};
+use crate::ast::*;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::mem::Discriminant;
+
+/// Resolved expression types, keyed by node: the number [`number_nodes`] put in each
+/// expression's span, with the kind of expression, since `copy`, `&`, `&mut` and `time`
+/// share the span of their operand. Two structurally equal expressions in different
+/// places (a macro body expanded twice, say) have different numbers, so they can have
+/// different types; copies of a node, such as a desugared method call, share its type.
+#[derive(Debug, Default)]
+pub struct ExprTypes(HashMap<NodeKey, Type>);
+
+type NodeKey = (u32, Discriminant<Expr>);
+
+impl ExprTypes {
+    /// The type of `expr`, if it is a node of the checked program (or a copy of one)
+    /// that the checker reached.
+    pub fn get(&self, expr: &Expr) -> Option<&Type> {
+        self.0.get(&node_key(expr)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn record(&mut self, expr: &Expr, ty: &Type) {
+        if let Some(key) = node_key(expr) {
+            self.0.insert(key, ty.clone());
+        }
+    }
+}
+
+/// A type-checked program. It owns the lowered tree its node numbers belong to; there
+/// is no way to change it, which could give two nodes the same number.
+#[derive(Debug)]
+pub struct TypedProgram {
+    source: Program,
+    program: Program,
+    types: ExprTypes,
+}
+
+impl TypedProgram {
+    pub(crate) fn new(source: Program, program: Program, types: ExprTypes) -> Self {
+        Self {
+            source,
+            program,
+            types,
+        }
+    }
+
+    /// The program as it was given to the checker, before lowering.
+    pub fn source(&self) -> &Program {
+        &self.source
+    }
+
+    /// The program after lowering: macros expanded, local functions lifted, traits
+    /// turned into plain functions and globals in dependency order.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn types(&self) -> &ExprTypes {
+        &self.types
+    }
+
+    /// Shorthand for `self.types().get(expr)`.
+    pub fn type_of(&self, expr: &Expr) -> Option<&Type> {
+        self.types.get(expr)
+    }
+}
+
+/// Give every expression of `program` its own number, from 1, in `Span::node`; the
+/// checker records types under them.
+pub(crate) fn number_nodes(program: Program) -> Program {
+    let Ok(program) = fold::walk_program(&mut Numbering(0), program);
+    program
+}
+
+fn node_key(expr: &Expr) -> Option<NodeKey> {
+    let node = expr.span().node;
+    (node != 0).then(|| (node, std::mem::discriminant(expr)))
+}
+
+struct Numbering(u32);
+
+impl Fold for Numbering {
+    type Error = Infallible;
+
+    fn fold_expr(&mut self, expr: Expr) -> Result<Expr, Infallible> {
+        let mut expr = fold::walk_expr(self, expr)?;
+        let span = match &mut expr {
+            Expr::Literal(_, span) => span,
+            Expr::Path(p) => &mut p.1,
+            Expr::FuncCall(c) => &mut c.span,
+            Expr::If(i) => &mut i.span,
+            Expr::Block(b) => &mut b.span,
+            Expr::RecordLit(r) => &mut r.span,
+            Expr::Unary(u) => &mut u.span,
+            Expr::Binary(b) => &mut b.span,
+            Expr::Index(i) => &mut i.span,
+            Expr::Slice(s) => &mut s.span,
+            // numbered through their operand
+            Expr::Copy(_) | Expr::Ref(_) | Expr::MutRef(_) | Expr::Time(_) => return Ok(expr),
+        };
+        self.0 += 1;
+        span.node = self.0;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::typecheck::TypeChecker;
+
+    #[test]
+    fn every_expression_of_the_lowered_program_has_its_type() {
+        let src =
+            "type P = { x: i32 }\nmk() -> P = { x: 1 }\nmain() = {\n  p: P = mk()\n  p.x > 0\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let typed = TypeChecker::new().check_program(&program).unwrap();
+        let Decl::Func(main) = &typed.program().decls[2] else {
+            panic!("expected main");
+        };
+        let Expr::Block(body) = &main.body else {
+            panic!("expected a block");
+        };
+        let Stmt::Binding(p) = &body.stmts[0] else {
+            panic!("expected a binding");
+        };
+        let named = |n: &str| Type::Named(Ident(n.into()));
+        assert_eq!(typed.type_of(&p.value), Some(&named("P")));
+        assert_eq!(typed.type_of(&main.body), Some(&named("bool")));
+        let tail = body.tail.as_deref().unwrap();
+        let Expr::Binary(cmp) = tail else {
+            panic!("expected a comparison");
+        };
+        assert_eq!(typed.type_of(&cmp.left), Some(&named("i32")));
+        // a copy is the same node; an expression built elsewhere is none of them
+        assert_eq!(typed.type_of(&tail.clone()), Some(&named("bool")));
+        let Expr::Path(x) = cmp.left.as_ref() else {
+            panic!("expected p.x");
+        };
+        let elsewhere = Expr::Path(Path(x.0.clone(), Span::default()));
+        assert_eq!(typed.type_of(&elsewhere), None);
+    }
+
+    #[test]
+    fn each_expansion_of_a_macro_has_its_own_types() {
+        struct Ifs<'a>(Vec<&'a Expr>);
+        impl<'a> Ifs<'a> {
+            fn collect(&mut self, expr: &'a Expr) {
+                match expr {
+                    Expr::If(_) => self.0.push(expr),
+                    Expr::Block(b) => {
+                        for stmt in &b.stmts {
+                            if let Stmt::Binding(binding) = stmt {
+                                self.collect(&binding.value);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let src = "macro pick(e) = if true then e else e\nmain() = {\n  a: Str = pick(\"x\")\n  n: i64 = pick(1)\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let typed = TypeChecker::new().check_program(&program).unwrap();
+        let main = typed.program().decls.iter().find_map(|d| match d {
+            Decl::Func(f) if f.name.0 == "main" => Some(f),
+            _ => None,
+        });
+        let mut ifs = Ifs(Vec::new());
+        ifs.collect(&main.unwrap().body);
+        let [str_if, int_if] = ifs.0.as_slice() else {
+            panic!("expected two expansions");
+        };
+        assert_eq!(str_if.span().start, int_if.span().start);
+        let named = |n: &str| Type::Named(Ident(n.into()));
+        assert_eq!(typed.type_of(str_if), Some(&named("Str")));
+        assert_eq!(typed.type_of(int_if), Some(&named("i64")));
+    }
+}
@@ -0,0 +1,100 @@
+#![forbid(unsafe_code)]
+
+use frontend::ast::Span;
+use std::fmt;
+
+/// A function that was running when a runtime error happened, and where in it
+/// execution was: the failing expression for the innermost frame, the call into the
+/// next frame for the others. `at` is unknown (`line == 0`) inside synthesized code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub func: String,
+    pub at: Span,
+}
+
+/// The calls active when a runtime error happened, innermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+}
+
+impl Backtrace {
+    /// `calls` holds each active call, outermost first, with the span of its call
+    /// site; `error_at` is where the innermost one failed.
+    pub(crate) fn new(calls: &[(String, Span)], error_at: Span) -> Self {
+        let frames = calls
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (func, _))| Frame {
+                func: func.clone(),
+                at: calls.get(i + 1).map_or(error_at, |(_, site)| *site),
+            })
+            .collect();
+        Self { frames }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    /// One line per frame; a run of frames of the same function, as in deep
+    /// recursion, shows its first frame and a count of the rest.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("backtrace (innermost call first):")?;
+        let mut i = 0;
+        while let Some(frame) = self.frames.get(i) {
+            write!(f, "\n  in {}", frame.func)?;
+            if frame.at.is_known() {
+                write!(f, " at {}", frame.at)?;
+            }
+            let repeats = self.frames[i + 1..]
+                .iter()
+                .take_while(|next| next.func == frame.func)
+                .count();
+            if repeats > 1 {
+                write!(f, "\n  ... {repeats} more calls to {}", frame.func)?;
+                i += repeats;
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: u32, col: u32) -> Span {
+        Span {
+            start: 0,
+            end: 0,
+            line,
+            col,
+            node: 0,
+        }
+    }
+
+    #[test]
+    fn frames_point_at_the_call_into_the_next_one() {
+        let calls = [
+            ("main".to_string(), Span::default()),
+            ("walk".to_string(), span(6, 3)),
+            ("walk".to_string(), span(3, 10)),
+            ("walk".to_string(), span(3, 10)),
+            ("walk".to_string(), span(3, 10)),
+            ("leaf".to_string(), span(3, 10)),
+        ];
+        let trace = Backtrace::new(&calls, span(1, 20));
+        assert_eq!(trace.frames[0].func, "leaf");
+        assert_eq!(trace.frames[0].at.line, 1);
+        assert_eq!(trace.frames[5].at.line, 6);
+        assert_eq!(
+            trace.to_string(),
+            "backtrace (innermost call first):\n  \
+             in leaf at 1:20\n  \
+             in walk at 3:10\n  \
+             ... 3 more calls to walk\n  \
+             in main at 6:3"
+        );
+    }
+}
@@ -0,0 +1,977 @@
+#![forbid(unsafe_code)]
+
+//! The functions scripts call without declaring them: the interpreter's own, and those
+//! the embedding program registers with [`Interpreter::register_builtin`]. Both live in
+//! one table, looked up by name after the program's functions; the call site checks the
+//! argument count and evaluates the arguments before calling the entry.
+
+use crate::{
+    json_field, json_members, json_unquote, write_json_str, AtomicHandle, ChanHandle, ConnHandle,
+    DynHandle, Env, Interpreter, ListenerHandle, RuntimeError, Value,
+};
+use frontend::target::Overflow;
+use frontend::typecheck::Signature;
+use indexmap::IndexMap;
+use runtime::{Conn, Listener, LogLevel, PrintBuffer, Rng};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Read};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A function the embedding program provides; see [`Interpreter::register_builtin`].
+pub(crate) type HostFn = dyn Fn(&[Value]) -> Result<Value, RuntimeError>;
+
+/// One of the interpreter's own builtins. It gets its name, so one function can serve a
+/// family such as `log_info`/`log_warn`/`log_error`, the interpreter for its streams and
+/// settings, and the caller's environment for the places `&mut` arguments name.
+type NativeFn = fn(&str, &[Value], &mut Interpreter, &mut Env) -> Result<Value, RuntimeError>;
+
+#[derive(Clone)]
+pub(crate) struct Builtin {
+    pub(crate) arity: usize,
+    pub(crate) call: BuiltinFn,
+}
+
+#[derive(Clone)]
+pub(crate) enum BuiltinFn {
+    Native(NativeFn),
+    Host(Signature, Rc<HostFn>),
+}
+
+impl Builtin {
+    pub(crate) fn host(sig: Signature, call: Rc<HostFn>) -> Self {
+        Self {
+            arity: sig.params.len(),
+            call: BuiltinFn::Host(sig, call),
+        }
+    }
+}
+
+/// The interpreter's own builtins by name, as every interpreter starts with them.
+pub(crate) fn natives() -> HashMap<String, Builtin> {
+    let table: &[(&str, usize, NativeFn)] = &[
+        ("print", 1, print),
+        ("println", 1, print),
+        ("read_line", 0, read_line),
+        ("read_stdin", 0, read_stdin),
+        ("now_millis", 0, now_millis),
+        ("sleep_ms", 1, sleep_ms),
+        ("seed_rand", 1, seed_rand),
+        ("rand_i32", 2, rand_i32),
+        ("flush", 0, flush),
+        ("exit", 1, exit),
+        ("set_print_buffer", 1, set_print_buffer),
+        ("args", 0, args),
+        ("arg_count", 0, arg_count),
+        ("arg_at", 1, arg_at),
+        ("bytes_to_str", 1, bytes_to_str),
+        ("try_read_file", 1, try_read_file),
+        ("read_file", 1, read_file),
+        ("try_write_file", 2, write_file),
+        ("write_file", 2, write_file),
+        ("str_len", 1, str_len),
+        ("str_byte_at", 2, str_byte_at),
+        ("str_slice", 3, str_slice),
+        ("bytes_len", 1, bytes_len),
+        ("bytes_at", 2, bytes_at),
+        ("bytes_slice", 3, bytes_slice),
+        ("bytes_from_str", 1, bytes_from_str),
+        ("bytes_push", 2, bytes_push),
+        ("str_contains", 2, str_find),
+        ("str_find", 2, str_find),
+        ("str_trim", 1, str_case),
+        ("str_to_upper", 1, str_case),
+        ("str_to_lower", 1, str_case),
+        ("log_info", 1, log),
+        ("log_warn", 1, log),
+        ("log_error", 1, log),
+        ("int_to_str", 1, int_to_str),
+        ("to_str", 1, to_str),
+        ("str_to_int", 1, str_to_int),
+        ("abs", 1, int_math),
+        ("min", 2, int_math),
+        ("max", 2, int_math),
+        ("pow", 2, int_math),
+        ("wrapping_add", 2, explicit_overflow),
+        ("wrapping_sub", 2, explicit_overflow),
+        ("wrapping_mul", 2, explicit_overflow),
+        ("saturating_add", 2, explicit_overflow),
+        ("saturating_sub", 2, explicit_overflow),
+        ("saturating_mul", 2, explicit_overflow),
+        ("checked_add", 2, explicit_overflow),
+        ("checked_sub", 2, explicit_overflow),
+        ("checked_mul", 2, explicit_overflow),
+        ("checked_div", 2, explicit_overflow),
+        ("checked_to_i32", 1, checked_narrow),
+        ("checked_to_u8", 1, checked_narrow),
+        ("json_quote", 1, json_quote),
+        ("json_get_int", 2, json_get),
+        ("json_get_bool", 2, json_get),
+        ("json_get_str", 2, json_get),
+        ("json_get_raw", 2, json_get),
+        ("config_get", 1, config_get),
+        ("atomic_new", 1, atomic_new),
+        ("atomic_load", 1, atomic),
+        ("atomic_store", 2, atomic),
+        ("atomic_add", 2, atomic),
+        ("atomic_cas", 3, atomic),
+        ("tcp_listen", 1, tcp_listen),
+        ("tcp_port", 1, tcp_accept),
+        ("tcp_accept", 1, tcp_accept),
+        ("tcp_connect", 2, tcp_connect),
+        ("tcp_read", 1, tcp_conn),
+        ("tcp_write", 2, tcp_conn),
+        ("tcp_close", 1, tcp_conn),
+        ("dyn_new", 0, dyn_new),
+        ("dyn_parse", 1, dyn_parse),
+        ("dyn_set", 3, dyn_access),
+        ("dyn_has", 2, dyn_access),
+        ("dyn_get", 2, dyn_access),
+        ("channel", 0, channel),
+        ("send", 2, chan_send),
+        ("recv", 1, chan_recv),
+        ("spawn", 1, spawn),
+    ];
+    table
+        .iter()
+        .map(|&(name, arity, call)| {
+            let builtin = Builtin {
+                arity,
+                call: BuiltinFn::Native(call),
+            };
+            (name.to_string(), builtin)
+        })
+        .collect()
+}
+
+fn type_error(msg: impl Into<String>) -> RuntimeError {
+    RuntimeError::Type(msg.into())
+}
+
+fn print(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let s = match &args[0] {
+        Value::Str(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let newline = if name == "println" { "\n" } else { "" };
+    interp.write_stdout(&format!("{s}{newline}"));
+    Ok(Value::Str(s))
+}
+
+fn read_line(
+    _: &str,
+    _: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    // flushed so a prompt printed just before shows while we wait
+    interp.flush_stdout();
+    // like C's fgetc, a read error ends the input
+    let mut line = Vec::new();
+    interp.stdin.read_until(b'\n', &mut line).ok();
+    Ok(Value::Str(String::from_utf8_lossy(&line).into_owned()))
+}
+
+fn read_stdin(
+    _: &str,
+    _: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    interp.flush_stdout();
+    let mut data = Vec::new();
+    interp.stdin.read_to_end(&mut data).ok();
+    Ok(Value::Bytes(data))
+}
+
+fn now_millis(
+    _: &str,
+    _: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    // a clock before 1970 reads as 0, like the C runtime's failed timespec_get
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+        .unwrap_or(0);
+    Ok(Value::Int(millis))
+}
+
+fn sleep_ms(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(ms)] = args else {
+        return Err(type_error("sleep_ms expects an integer"));
+    };
+    // negative durations do not sleep
+    thread::sleep(Duration::from_millis(u64::try_from(*ms).unwrap_or(0)));
+    Ok(Value::Unit)
+}
+
+fn seed_rand(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(seed)] = args else {
+        return Err(type_error("seed_rand expects an integer"));
+    };
+    interp.rng = Rng::new(*seed);
+    Ok(Value::Unit)
+}
+
+fn rand_i32(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(lo), Value::Int(hi)] = args else {
+        return Err(type_error("rand_i32 expects integers"));
+    };
+    Ok(Value::Int(interp.rng.range(*lo, *hi)))
+}
+
+fn flush(
+    _: &str,
+    _: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    interp.flush_stdout();
+    Ok(Value::Unit)
+}
+
+fn exit(_: &str, args: &[Value], _: &mut Interpreter, _: &mut Env) -> Result<Value, RuntimeError> {
+    let [Value::Int(code)] = args else {
+        return Err(type_error("exit expects an integer"));
+    };
+    // truncated like the C `int` argument of exit()
+    Err(RuntimeError::Exit(*code as i32))
+}
+
+fn set_print_buffer(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(mode)] = args else {
+        return Err(type_error("set_print_buffer expects Str"));
+    };
+    let Ok(mode) = mode.parse::<PrintBuffer>() else {
+        return Ok(Value::Bool(false));
+    };
+    interp.flush_stdout();
+    interp.config.print_buffer = mode;
+    Ok(Value::Bool(true))
+}
+
+fn args(
+    _: &str,
+    _: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Bytes(interp.args.join("\n").into_bytes()))
+}
+
+fn arg_count(
+    _: &str,
+    _: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Int(interp.args.len() as i64))
+}
+
+fn arg_at(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(i)] = args else {
+        return Err(type_error("arg_at expects an integer"));
+    };
+    // like str_byte_at, an index outside the arguments reads as empty
+    let arg = usize::try_from(*i)
+        .ok()
+        .and_then(|i| interp.args.get(i))
+        .cloned()
+        .unwrap_or_default();
+    Ok(Value::Str(arg))
+}
+
+fn bytes_to_str(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Bytes(bytes)] = args else {
+        return Err(type_error("bytes_to_str expects Bytes"));
+    };
+    Ok(Value::Str(String::from_utf8_lossy(bytes).to_string()))
+}
+
+fn try_read_file(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(path)] = args else {
+        return Err(type_error("try_read_file expects Str"));
+    };
+    let read = fs::read_to_string(path);
+    let mut map = IndexMap::new();
+    map.insert("ok".into(), Value::Bool(read.is_ok()));
+    map.insert("data".into(), Value::Str(read.unwrap_or_default()));
+    Ok(Value::Record(map))
+}
+
+fn read_file(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(path)] = args else {
+        return Err(type_error("read_file expects Str"));
+    };
+    Ok(Value::Str(fs::read_to_string(path).unwrap_or_default()))
+}
+
+/// `write_file`, which ignores a failure, and `try_write_file`, which reports it.
+fn write_file(
+    name: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(path), Value::Str(data)] = args else {
+        return Err(type_error(format!("{name} expects Str path and data")));
+    };
+    let ok = fs::write(path, data).is_ok();
+    Ok(if name == "try_write_file" {
+        Value::Bool(ok)
+    } else {
+        Value::Unit
+    })
+}
+
+fn str_len(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s)] = args else {
+        return Err(type_error("str_len expects Str"));
+    };
+    Ok(Value::Int(s.len() as i64))
+}
+
+fn str_byte_at(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s), Value::Int(i)] = args else {
+        return Err(type_error("str_byte_at expects Str and an index"));
+    };
+    let byte = usize::try_from(*i)
+        .ok()
+        .and_then(|i| s.as_bytes().get(i).copied());
+    Ok(Value::Int(byte.map_or(0, i64::from)))
+}
+
+fn str_slice(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s), Value::Int(start), Value::Int(len)] = args else {
+        return Err(type_error("str_slice expects Str, start and len"));
+    };
+    let bytes = s.as_bytes();
+    // a negative start or len gives the empty string
+    let (start, len) = match (usize::try_from(*start), usize::try_from(*len)) {
+        (Ok(start), Ok(len)) => (start.min(bytes.len()), len),
+        _ => (0, 0),
+    };
+    let end = start.saturating_add(len).min(bytes.len());
+    Ok(Value::Str(
+        String::from_utf8_lossy(&bytes[start..end]).to_string(),
+    ))
+}
+
+fn bytes_len(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Bytes(b)] = args else {
+        return Err(type_error("bytes_len expects Bytes"));
+    };
+    Ok(Value::Int(b.len() as i64))
+}
+
+fn bytes_at(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Bytes(b), Value::Int(i)] = args else {
+        return Err(type_error("bytes_at expects Bytes and an index"));
+    };
+    let byte = usize::try_from(*i).ok().and_then(|i| b.get(i).copied());
+    Ok(Value::Int(byte.map_or(0, i64::from)))
+}
+
+fn bytes_slice(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Bytes(b), Value::Int(start), Value::Int(len)] = args else {
+        return Err(type_error("bytes_slice expects Bytes, start and len"));
+    };
+    // clamped like str_slice; a negative start or len gives empty Bytes
+    let (start, len) = match (usize::try_from(*start), usize::try_from(*len)) {
+        (Ok(start), Ok(len)) => (start.min(b.len()), len),
+        _ => (0, 0),
+    };
+    let end = start.saturating_add(len).min(b.len());
+    Ok(Value::Bytes(b[start..end].to_vec()))
+}
+
+fn bytes_from_str(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s)] = args else {
+        return Err(type_error("bytes_from_str expects Str"));
+    };
+    Ok(Value::Bytes(s.clone().into_bytes()))
+}
+
+fn bytes_push(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    env: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::MutRef(place), Value::Int(byte)] = args else {
+        return Err(type_error(
+            "bytes_push expects &mut Bytes and an integer byte",
+        ));
+    };
+    let Value::Bytes(b) = env.place_mut(place)? else {
+        return Err(type_error("bytes_push expects &mut Bytes"));
+    };
+    // the low 8 bits, like the C runtime's uint8_t store
+    b.push(*byte as u8);
+    Ok(Value::Unit)
+}
+
+fn str_find(
+    name: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s), Value::Str(needle)] = args else {
+        return Err(type_error(format!("{name} expects Str")));
+    };
+    let at = s.find(needle.as_str());
+    Ok(if name == "str_contains" {
+        Value::Bool(at.is_some())
+    } else {
+        Value::Int(at.map_or(-1, |i| i as i64))
+    })
+}
+
+fn str_case(
+    name: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s)] = args else {
+        return Err(type_error(format!("{name} expects Str")));
+    };
+    // ASCII only, like the C runtime
+    Ok(Value::Str(match name {
+        "str_trim" => s.trim_ascii().to_string(),
+        "str_to_upper" => s.to_ascii_uppercase(),
+        _ => s.to_ascii_lowercase(),
+    }))
+}
+
+fn log(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(msg)] = args else {
+        return Err(type_error(format!("{name} expects Str")));
+    };
+    let level = match name {
+        "log_info" => LogLevel::Info,
+        "log_warn" => LogLevel::Warn,
+        _ => LogLevel::Error,
+    };
+    interp
+        .config
+        .logger
+        .log_to(&mut *interp.stderr.lock(), level, msg);
+    Ok(Value::Unit)
+}
+
+fn int_to_str(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(n)] = args else {
+        return Err(type_error("int_to_str expects an integer"));
+    };
+    Ok(Value::Str(n.to_string()))
+}
+
+fn to_str(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    env: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let shown = match &args[0] {
+        Value::MutRef(place) => env.read_place(place)?.to_string(),
+        other => other.to_string(),
+    };
+    Ok(Value::Str(shown))
+}
+
+fn str_to_int(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s)] = args else {
+        return Err(type_error("str_to_int expects Str"));
+    };
+    let model = interp.target.int_model;
+    let parsed = s.parse::<i64>().ok().filter(|&v| model.wrap(v) == v);
+    let mut map = IndexMap::new();
+    map.insert("ok".into(), Value::Bool(parsed.is_some()));
+    map.insert("value".into(), Value::Int(parsed.unwrap_or(0)));
+    Ok(Value::Record(map))
+}
+
+/// `abs`, `min`, `max` and `pow` at the default integer width.
+fn int_math(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let (a, b) = match args {
+        [Value::Int(a)] => (*a, 0),
+        [Value::Int(a), Value::Int(b)] => (*a, *b),
+        _ => return Err(type_error(format!("{name} expects integers"))),
+    };
+    let model = interp.target.int_model;
+    Ok(Value::Int(match name {
+        "abs" => model.wrap(a.wrapping_abs()),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        _ => int_pow(a, b, |x, y| model.wrap(x.wrapping_mul(y))),
+    }))
+}
+
+/// `wrapping_*`, `saturating_*` and `checked_*` arithmetic at the default integer width.
+fn explicit_overflow(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(a), Value::Int(b)] = args else {
+        return Err(type_error(format!("{name} expects integers")));
+    };
+    let (mode, op) = name.split_once('_').unwrap_or((name, ""));
+    // the exact result, or None for division by zero
+    let (a, b) = (i128::from(*a), i128::from(*b));
+    let exact = match op {
+        "add" => Some(a + b),
+        "sub" => Some(a - b),
+        "mul" => Some(a * b),
+        _ => (b != 0).then(|| a / b),
+    };
+    let model = interp.target.int_model;
+    let (min, max) = (i128::from(model.min()), i128::from(model.max()));
+    Ok(match (mode, exact) {
+        ("wrapping", Some(v)) => Value::Int(model.wrap(v as i64)),
+        ("saturating", Some(v)) => Value::Int(v.clamp(min, max) as i64),
+        _ => {
+            let fits = exact.filter(|v| (min..=max).contains(v));
+            checked_int(fits.map(|v| v as i64))
+        }
+    })
+}
+
+fn checked_narrow(
+    name: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(n)] = args else {
+        return Err(type_error(format!("{name} expects an integer")));
+    };
+    let fits = if name == "checked_to_u8" {
+        u8::try_from(*n).is_ok()
+    } else {
+        i32::try_from(*n).is_ok()
+    };
+    Ok(checked_int(fits.then_some(*n)))
+}
+
+fn json_quote(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(s)] = args else {
+        return Err(type_error("json_quote expects Str"));
+    };
+    let mut out = String::new();
+    write_json_str(s, &mut out);
+    Ok(Value::Str(out))
+}
+
+fn json_get(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(doc), Value::Str(key)] = args else {
+        return Err(type_error(format!("{name} expects &Str and Str")));
+    };
+    let raw = json_field(doc, key)?;
+    let wrong = |kind: &str| RuntimeError::Json(format!("field \"{key}\" is not {kind}: {raw}"));
+    Ok(match name {
+        "json_get_int" => Value::Int(
+            interp
+                .target
+                .int_model
+                .wrap(raw.parse().map_err(|_| wrong("an integer"))?),
+        ),
+        "json_get_bool" => match raw {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => return Err(wrong("a bool")),
+        },
+        "json_get_str" => Value::Str(json_unquote(raw).ok_or_else(|| wrong("a string"))?),
+        _ => Value::Str(raw.to_string()),
+    })
+}
+
+fn config_get(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(key)] = args else {
+        return Err(type_error("config_get expects Str"));
+    };
+    Ok(Value::Str(interp.config.get(key)))
+}
+
+fn atomic_new(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Int(v)] = args else {
+        return Err(type_error("atomic_new expects i64"));
+    };
+    Ok(Value::Atomic(AtomicHandle::new(*v)))
+}
+
+/// `atomic_load`, `atomic_store`, `atomic_add` and `atomic_cas` on an `&atomic_i64`.
+fn atomic(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let Some((Value::Atomic(cell), operands)) = args.split_first() else {
+        return Err(type_error(format!("{name} expects &atomic_i64")));
+    };
+    let mut ints = Vec::with_capacity(operands.len());
+    for operand in operands {
+        let Value::Int(v) = operand else {
+            return Err(type_error(format!("{name} expects i64 operands")));
+        };
+        ints.push(*v);
+    }
+    let cell = &cell.0;
+    Ok(match name {
+        "atomic_load" => Value::Int(cell.load(Ordering::SeqCst)),
+        "atomic_store" => {
+            cell.store(ints[0], Ordering::SeqCst);
+            Value::Unit
+        }
+        "atomic_add" if interp.target.overflow == Overflow::Trap => {
+            let delta = ints[0];
+            let prev = cell
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_add(delta))
+                .map_err(|v| RuntimeError::Overflow(format!("{v} + {delta}")))?;
+            Value::Int(prev)
+        }
+        "atomic_add" => Value::Int(cell.fetch_add(ints[0], Ordering::SeqCst)),
+        _ => Value::Bool(
+            cell.compare_exchange(ints[0], ints[1], Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok(),
+        ),
+    })
+}
+
+fn tcp_listen(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let port = tcp_port_arg(&args[0])?;
+    let listener = Listener::listen(("0.0.0.0", port))
+        .map_err(|e| RuntimeError::Net(format!("cannot listen on port {port}: {e}")))?;
+    Ok(Value::Listener(ListenerHandle(Arc::new(listener))))
+}
+
+/// `tcp_port` and `tcp_accept` on an `&TcpListener`.
+fn tcp_accept(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Listener(l)] = args else {
+        return Err(type_error(format!("{name} expects &TcpListener")));
+    };
+    if name == "tcp_port" {
+        return Ok(Value::Int(l.0.local_port().map_or(0, i64::from)));
+    }
+    interp.flush_stdout();
+    let conn =
+        l.0.accept()
+            .map_err(|e| RuntimeError::Net(format!("accept failed: {e}")))?;
+    Ok(Value::Conn(ConnHandle(Arc::new(Mutex::new(conn)))))
+}
+
+fn tcp_connect(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(host), port] = args else {
+        return Err(type_error("tcp_connect expects a Str host"));
+    };
+    let port = tcp_port_arg(port)?;
+    let conn = Conn::connect((host.as_str(), port))
+        .map_err(|e| RuntimeError::Net(format!("cannot connect to {host}:{port}: {e}")))?;
+    Ok(Value::Conn(ConnHandle(Arc::new(Mutex::new(conn)))))
+}
+
+/// `tcp_read`, `tcp_write` and `tcp_close` on an `&TcpConn`.
+fn tcp_conn(
+    name: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let Some((Value::Conn(c), rest)) = args.split_first() else {
+        return Err(type_error(format!("{name} expects &TcpConn")));
+    };
+    Ok(match (name, rest) {
+        ("tcp_read", _) => {
+            interp.flush_stdout();
+            // like read_line, an error reads as the end of the stream
+            Value::Bytes(c.conn().read().unwrap_or_default())
+        }
+        ("tcp_write", [Value::Bytes(data)]) => Value::Bool(c.conn().write(data).is_ok()),
+        ("tcp_write", _) => return Err(type_error("tcp_write expects Bytes")),
+        _ => {
+            c.conn().shutdown().ok();
+            Value::Unit
+        }
+    })
+}
+
+fn dyn_new(_: &str, _: &[Value], _: &mut Interpreter, _: &mut Env) -> Result<Value, RuntimeError> {
+    Ok(Value::Dyn(DynHandle::default()))
+}
+
+fn dyn_parse(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Str(doc)] = args else {
+        return Err(type_error("dyn_parse expects Str"));
+    };
+    // string members are stored decoded, everything else as its JSON text
+    let d = DynHandle::default();
+    for (key, raw) in json_members(doc)? {
+        let value = json_unquote(raw).unwrap_or_else(|| raw.to_string());
+        d.set(key, value);
+    }
+    Ok(Value::Dyn(d))
+}
+
+/// `dyn_set`, `dyn_has` and `dyn_get` on an `&Dyn`.
+fn dyn_access(
+    name: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let Some((Value::Dyn(d), rest)) = args.split_first() else {
+        return Err(type_error(format!("{name} expects &Dyn")));
+    };
+    Ok(match rest {
+        [Value::Str(key), Value::Str(value)] => {
+            d.set(key.clone(), value.clone());
+            Value::Unit
+        }
+        [Value::Str(key)] if name == "dyn_has" => Value::Bool(d.get(key).is_some()),
+        [Value::Str(key)] => {
+            let value = d.get(key);
+            let mut map = IndexMap::new();
+            map.insert("found".into(), Value::Bool(value.is_some()));
+            map.insert("value".into(), Value::Str(value.unwrap_or_default()));
+            Value::Record(map)
+        }
+        _ => return Err(type_error(format!("{name} expects Str keys and values"))),
+    })
+}
+
+fn channel(_: &str, _: &[Value], _: &mut Interpreter, _: &mut Env) -> Result<Value, RuntimeError> {
+    Ok(Value::Chan(ChanHandle::new()))
+}
+
+fn chan_send(
+    _: &str,
+    args: &[Value],
+    _: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Chan(c), Value::Str(msg)] = args else {
+        return Err(type_error("send expects &Chan and Str"));
+    };
+    c.send(msg.clone());
+    Ok(Value::Unit)
+}
+
+fn chan_recv(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    _: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Chan(c)] = args else {
+        return Err(type_error("recv expects &Chan"));
+    };
+    interp.flush_stdout();
+    Ok(Value::Str(c.recv()))
+}
+
+fn spawn(
+    _: &str,
+    args: &[Value],
+    interp: &mut Interpreter,
+    env: &mut Env,
+) -> Result<Value, RuntimeError> {
+    let [Value::Func(target)] = args else {
+        return Err(type_error("spawn expects a function"));
+    };
+    if !interp.funcs.contains_key(target) {
+        return Err(RuntimeError::UnknownIdent(target.clone()));
+    }
+    interp.flush_stdout();
+    interp.spawn(target, env)?;
+    Ok(Value::Unit)
+}
+
+/// `base` to the power `exp` by squaring, multiplying with `mul` so the result wraps at
+/// the target width. A negative exponent truncates `1 / base^-exp` toward zero.
+fn int_pow(mut base: i64, mut exp: i64, mul: impl Fn(i64, i64) -> i64) -> i64 {
+    if exp < 0 {
+        return match base {
+            1 => 1,
+            -1 if exp & 1 == 1 => -1,
+            -1 => 1,
+            _ => 0,
+        };
+    }
+    let mut result = 1;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul(result, base);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mul(base, base);
+        }
+    }
+    result
+}
+
+/// A port argument of the tcp builtins, rejected outside 0..=65535 like the C runtime.
+fn tcp_port_arg(value: &Value) -> Result<u16, RuntimeError> {
+    let Value::Int(port) = value else {
+        return Err(type_error("tcp port must be an integer"));
+    };
+    u16::try_from(*port).map_err(|_| RuntimeError::Net(format!("port out of range: {port}")))
+}
+
+/// A `CheckedInt` record: `ok` with the value, or not ok with 0.
+fn checked_int(value: Option<i64>) -> Value {
+    let mut map = IndexMap::new();
+    map.insert("ok".into(), Value::Bool(value.is_some()));
+    map.insert("value".into(), Value::Int(value.unwrap_or(0)));
+    Value::Record(map)
+}
@@ -0,0 +1,105 @@
+#![forbid(unsafe_code)]
+
+use crate::Value;
+use frontend::ast::Span;
+use indexmap::IndexMap;
+
+/// A source line to stop at. `file` is compared with the file the interpreter was told
+/// the running function came from; see
+/// [`Interpreter::set_entry_file`](crate::Interpreter::set_entry_file).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub file: String,
+    pub line: u32,
+}
+
+/// How to carry on after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Stop at the next statement, entering calls.
+    Step,
+    /// Stop at the next statement of this function or a caller, running calls through.
+    Next,
+    /// Run until a breakpoint.
+    Continue,
+}
+
+/// The program stopped before a statement or a block's final expression. It stays
+/// stopped while [`Debugger::paused`] runs.
+pub struct Pause<'a> {
+    pub func: &'a str,
+    pub file: &'a str,
+    pub span: Span,
+    /// The number of active calls.
+    pub depth: u32,
+    pub(crate) locals: IndexMap<String, Value>,
+    pub(crate) breakpoints: &'a mut Vec<Breakpoint>,
+}
+
+impl Pause<'_> {
+    /// The parameters and bindings of the running function that are visible here and
+    /// not moved out, by name; an inner binding hides an outer one.
+    pub fn locals(&self) -> &IndexMap<String, Value> {
+        &self.locals
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        self.breakpoints
+    }
+
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) {
+        add_breakpoint(self.breakpoints, file, line);
+    }
+
+    pub fn clear_breakpoint(&mut self, file: &str, line: u32) {
+        remove_breakpoint(self.breakpoints, file, line);
+    }
+}
+
+pub(crate) fn add_breakpoint(breakpoints: &mut Vec<Breakpoint>, file: &str, line: u32) {
+    let bp = Breakpoint {
+        file: file.to_string(),
+        line,
+    };
+    if !breakpoints.contains(&bp) {
+        breakpoints.push(bp);
+    }
+}
+
+pub(crate) fn remove_breakpoint(breakpoints: &mut Vec<Breakpoint>, file: &str, line: u32) {
+    breakpoints.retain(|bp| !(bp.file == file && bp.line == line));
+}
+
+/// A debugger front-end, installed with
+/// [`Interpreter::set_debugger`](crate::Interpreter::set_debugger). The interpreter
+/// calls it whenever it stops and resumes as it answers.
+pub trait Debugger {
+    fn paused(&mut self, pause: &mut Pause<'_>) -> DebugAction;
+}
+
+/// Where the next pause happens, from the last [`DebugAction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Resume {
+    Step,
+    /// At a call depth of at most this.
+    Next(u32),
+    Continue,
+}
+
+impl Resume {
+    pub(crate) fn after(action: DebugAction, depth: u32) -> Self {
+        match action {
+            DebugAction::Step => Resume::Step,
+            DebugAction::Next => Resume::Next(depth),
+            DebugAction::Continue => Resume::Continue,
+        }
+    }
+
+    pub(crate) fn stops_at(self, depth: u32) -> bool {
+        match self {
+            Resume::Step => true,
+            Resume::Next(max) => depth <= max,
+            Resume::Continue => false,
+        }
+    }
+}
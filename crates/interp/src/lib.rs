@@ -1,14 +1,38 @@
 #![forbid(unsafe_code)]
 
 use frontend::ast::*;
+use frontend::lower::lower_program;
 use frontend::parser::Parser;
+use frontend::target::{Overflow, Target};
+use frontend::traits::drop_fn;
+use frontend::typecheck::{Signature, TypeChecker};
+use frontend::typed::TypedProgram;
 use indexmap::IndexMap;
-use runtime::Arena;
+use runtime::{Config, Conn, Listener, Logger, PrintBuffer, Rng};
 use std::collections::HashMap;
-use std::fs;
-use std::io::{self, Write};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Instant;
 use thiserror::Error;
 
+pub mod backtrace;
+mod builtins;
+pub mod debugger;
+pub mod observer;
+pub mod profile;
+pub mod trace;
+
+use backtrace::Backtrace;
+use builtins::{Builtin, BuiltinFn};
+use debugger::{Breakpoint, DebugAction, Debugger, Pause, Resume};
+use observer::ExecObserver;
+use profile::{ProfileReport, Profiler};
+use trace::TraceEvent;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i64),
@@ -16,9 +40,445 @@ pub enum Value {
     Str(String),
     Bytes(Vec<u8>),
     Record(IndexMap<String, Value>),
+    Atomic(AtomicHandle),
+    Dyn(DynHandle),
+    Listener(ListenerHandle),
+    Conn(ConnHandle),
+    Chan(ChanHandle),
+    Func(String), // top-level function passed by name
+    Ref(Place),   // `&x` of a binding or a field of one; reads see writes made after it
+    MutRef(Place),
     Unit,
 }
 
+/// What a `&` or `&mut` reference points at: a binding in the shared environment, by scope
+/// index, and a field path inside it. The typechecker keeps references from outliving
+/// their referee, so the scope index stays valid while the reference exists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    scope: usize,
+    name: String,
+    fields: Vec<String>,
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for field in &self.fields {
+            write!(f, ".{field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared `atomic_i64` cell; clones alias the same storage.
+#[derive(Debug, Clone)]
+pub struct AtomicHandle(Arc<AtomicI64>);
+
+impl AtomicHandle {
+    pub fn new(value: i64) -> Self {
+        Self(Arc::new(AtomicI64::new(value)))
+    }
+
+    pub fn load(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl PartialEq for AtomicHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Shared `Dyn` map from keys to string values; clones alias the same storage.
+#[derive(Debug, Clone, Default)]
+pub struct DynHandle(Arc<Mutex<IndexMap<String, String>>>);
+
+impl DynHandle {
+    fn map(&self) -> MutexGuard<'_, IndexMap<String, String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.map().get(key).cloned()
+    }
+
+    pub fn set(&self, key: String, value: String) {
+        self.map().insert(key, value);
+    }
+
+    /// The entries in insertion order.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        self.map()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+}
+
+impl PartialEq for DynHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// `TcpListener` socket; clones alias the same listener.
+#[derive(Debug, Clone)]
+pub struct ListenerHandle(Arc<Listener>);
+
+impl PartialEq for ListenerHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// `TcpConn` socket; clones alias the same connection.
+#[derive(Debug, Clone)]
+pub struct ConnHandle(Arc<Mutex<Conn>>);
+
+impl ConnHandle {
+    fn conn(&self) -> MutexGuard<'_, Conn> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PartialEq for ConnHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// `Chan` message queue; clones, also those copied into spawned threads, send to and
+/// receive from the same queue.
+#[derive(Debug, Clone)]
+pub struct ChanHandle {
+    tx: mpsc::Sender<String>,
+    rx: Arc<Mutex<mpsc::Receiver<String>>>,
+}
+
+impl ChanHandle {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx,
+            rx: Arc::new(Mutex::new(rx)),
+        }
+    }
+
+    fn send(&self, msg: String) {
+        // the handle holds the receiver too, so the queue is never disconnected
+        self.tx.send(msg).ok();
+    }
+
+    /// Wait for the next message. Receivers take turns: one waits on the queue while
+    /// the others wait for it.
+    fn recv(&self) -> String {
+        let rx = self.rx.lock().unwrap_or_else(PoisonError::into_inner);
+        rx.recv().unwrap_or_default()
+    }
+}
+
+impl PartialEq for ChanHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.rx, &other.rx)
+    }
+}
+
+impl fmt::Display for Value {
+    /// Render the value using language syntax (`{ x: 0, y: 1 }`, `"quoted"`, `()`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Str(s) => write!(f, "\"{}\"", escape_str(s)),
+            Value::Bytes(bytes) => {
+                write!(f, "b\"")?;
+                for &b in bytes {
+                    match b {
+                        b'"' => write!(f, "\\\"")?,
+                        b'\\' => write!(f, "\\\\")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\t' => write!(f, "\\t")?,
+                        b'\r' => write!(f, "\\r")?,
+                        0x20..=0x7e => write!(f, "{}", b as char)?,
+                        other => write!(f, "\\x{other:02x}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Record(fields) => {
+                if fields.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{ ")?;
+                for (i, (name, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{name}: {v}")?;
+                }
+                write!(f, " }}")
+            }
+            Value::Atomic(a) => write!(f, "atomic({})", a.load()),
+            Value::Dyn(d) => {
+                write!(f, "dyn {{")?;
+                for (i, (key, v)) in d.entries().iter().enumerate() {
+                    let sep = if i > 0 { "," } else { "" };
+                    write!(f, "{sep} {key}: \"{}\"", escape_str(v))?;
+                }
+                write!(f, " }}")
+            }
+            Value::Listener(_) => write!(f, "<tcp listener>"),
+            Value::Conn(_) => write!(f, "<tcp conn>"),
+            Value::Chan(_) => write!(f, "<chan>"),
+            Value::Func(name) => write!(f, "<fn {name}>"),
+            Value::Ref(place) => write!(f, "&{place}"),
+            Value::MutRef(place) => write!(f, "&mut {place}"),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl Value {
+    /// Encode the value as a JSON document for scripting consumers.
+    /// Records become objects (field order preserved), bytes become arrays of numbers and unit becomes `null`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Value::Int(i) => out.push_str(&i.to_string()),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Str(s) => write_json_str(s, out),
+            Value::Bytes(bytes) => {
+                out.push('[');
+                for (i, b) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&b.to_string());
+                }
+                out.push(']');
+            }
+            Value::Record(fields) => {
+                out.push('{');
+                for (i, (name, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_str(name, out);
+                    out.push(':');
+                    v.write_json(out);
+                }
+                out.push('}');
+            }
+            Value::Atomic(a) => out.push_str(&a.load().to_string()),
+            Value::Dyn(d) => {
+                out.push('{');
+                for (i, (key, v)) in d.entries().iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_str(key, out);
+                    out.push(':');
+                    write_json_str(v, out);
+                }
+                out.push('}');
+            }
+            Value::Listener(_) => write_json_str("<tcp listener>", out),
+            Value::Conn(_) => write_json_str("<tcp conn>", out),
+            Value::Chan(_) => write_json_str("<chan>", out),
+            Value::Func(name) => write_json_str(name, out),
+            Value::Ref(place) => write_json_str(&format!("&{place}"), out),
+            Value::MutRef(place) => write_json_str(&format!("&mut {place}"), out),
+            Value::Unit => out.push_str("null"),
+        }
+    }
+}
+
+fn escape_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn write_json_str(s: &str, out: &mut String) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+}
+
+/// The raw text of `key`'s value in the JSON object `doc`.
+fn json_field<'a>(doc: &'a str, key: &str) -> Result<&'a str, RuntimeError> {
+    let b = doc.as_bytes();
+    let malformed = || RuntimeError::Json(format!("malformed object looking up \"{key}\""));
+    let mut i = json_ws(b, 0);
+    if b.get(i) != Some(&b'{') {
+        return Err(malformed());
+    }
+    i = json_ws(b, i + 1);
+    if b.get(i) == Some(&b'}') {
+        return Err(RuntimeError::Json(format!("missing field \"{key}\"")));
+    }
+    loop {
+        let name_end = json_skip(b, i).ok_or_else(malformed)?;
+        let name = json_unquote(&doc[i..name_end]).ok_or_else(malformed)?;
+        i = json_ws(b, name_end);
+        if b.get(i) != Some(&b':') {
+            return Err(malformed());
+        }
+        let start = json_ws(b, i + 1);
+        let end = json_skip(b, start).ok_or_else(malformed)?;
+        if name == key {
+            return Ok(&doc[start..end]);
+        }
+        i = json_ws(b, end);
+        match b.get(i) {
+            Some(b',') => i = json_ws(b, i + 1),
+            Some(b'}') => return Err(RuntimeError::Json(format!("missing field \"{key}\""))),
+            _ => return Err(malformed()),
+        }
+    }
+}
+
+/// The members of the object `doc` in order, each value as its raw JSON text.
+fn json_members(doc: &str) -> Result<Vec<(String, &str)>, RuntimeError> {
+    let b = doc.as_bytes();
+    let malformed = || RuntimeError::Json("malformed object for a Dyn".into());
+    let mut members = Vec::new();
+    let mut i = json_ws(b, 0);
+    if b.get(i) != Some(&b'{') {
+        return Err(malformed());
+    }
+    i = json_ws(b, i + 1);
+    if b.get(i) == Some(&b'}') {
+        return Ok(members);
+    }
+    loop {
+        let name_end = json_skip(b, i).ok_or_else(malformed)?;
+        let name = json_unquote(&doc[i..name_end]).ok_or_else(malformed)?;
+        i = json_ws(b, name_end);
+        if b.get(i) != Some(&b':') {
+            return Err(malformed());
+        }
+        let start = json_ws(b, i + 1);
+        let end = json_skip(b, start).ok_or_else(malformed)?;
+        members.push((name, &doc[start..end]));
+        i = json_ws(b, end);
+        match b.get(i) {
+            Some(b',') => i = json_ws(b, i + 1),
+            Some(b'}') => return Ok(members),
+            _ => return Err(malformed()),
+        }
+    }
+}
+
+fn json_ws(b: &[u8], mut i: usize) -> usize {
+    while b.get(i).is_some_and(|c| c.is_ascii_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+/// One past the end of the JSON value starting at `i`.
+fn json_skip(b: &[u8], i: usize) -> Option<usize> {
+    match *b.get(i)? {
+        b'"' => {
+            let mut j = i + 1;
+            loop {
+                match *b.get(j)? {
+                    b'\\' => j += 2,
+                    b'"' => return Some(j + 1),
+                    _ => j += 1,
+                }
+            }
+        }
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            let mut j = i;
+            loop {
+                match *b.get(j)? {
+                    b'"' => {
+                        j = json_skip(b, j)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(j + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                j += 1;
+            }
+        }
+        _ => {
+            let mut j = i;
+            while b
+                .get(j)
+                .is_some_and(|c| !matches!(c, b',' | b'}' | b']') && !c.is_ascii_whitespace())
+            {
+                j += 1;
+            }
+            (j > i).then_some(j)
+        }
+    }
+}
+
+/// The contents of a JSON string literal, escapes decoded.
+fn json_unquote(raw: &str) -> Option<String> {
+    let inner = raw.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum RuntimeError {
     #[error("unknown identifier {0}")]
@@ -31,6 +491,38 @@ pub enum RuntimeError {
     FieldNotFound(String),
     #[error("type error: {0}")]
     Type(String),
+    #[error("global {0} changed type; cannot reload")]
+    IncompatibleGlobal(String),
+    #[error("index {index} out of bounds for length {len}")]
+    IndexOutOfBounds { index: i64, len: usize },
+    #[error("slice {start}..{end} out of bounds for length {len}")]
+    SliceOutOfBounds { start: i64, end: i64, len: usize },
+    #[error("json: {0}")]
+    Json(String),
+    /// An operator's result does not fit the default integer under `Overflow::Trap`.
+    #[error("integer overflow: {0}")]
+    Overflow(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    /// A shift count was negative or not below the default integer's width.
+    #[error("shift amount {amount} out of range for {bits}-bit integers")]
+    ShiftOutOfRange { amount: i64, bits: u32 },
+    /// `tcp_listen`, `tcp_accept` or `tcp_connect` failed; the C runtime aborts instead.
+    #[error("net: {0}")]
+    Net(String),
+    /// `spawn` could not start a thread; the C runtime aborts instead.
+    #[error("thread: {0}")]
+    Thread(String),
+    /// More calls were active at once than [`Interpreter::set_max_call_depth`] allows.
+    #[error("stack overflow: more than {0} nested calls")]
+    StackOverflow(u32),
+    /// The run used up the fuel given by [`Interpreter::set_fuel`] or went past the
+    /// deadline given by [`Interpreter::set_deadline`].
+    #[error("execution fuel exhausted")]
+    FuelExhausted,
+    /// `exit(code)` was called; the run ends here and the embedder sets the exit status.
+    #[error("program exited with status {0}")]
+    Exit(i32),
 }
 
 #[derive(Debug, Clone)]
@@ -46,118 +538,959 @@ enum EvalMode {
     Borrow,
 }
 
-/// Interpreter with simple block-scoped environment and bump arena per top-level run.
+/// The name a declaration binds, which a later declaration of the same name replaces.
+fn decl_name(decl: &Decl) -> Option<&str> {
+    match decl {
+        Decl::Global(b) | Decl::Let(b) => Some(&b.name.0),
+        Decl::Type(t) => Some(&t.name.0),
+        Decl::Func(f) => Some(&f.name.0),
+        Decl::Trait(t) => Some(&t.name.0),
+        Decl::Macro(m) => Some(&m.name.0),
+        Decl::Import(_) | Decl::Impl(_) => None,
+    }
+}
+
+/// A function call in progress.
+#[derive(Debug, Clone)]
+struct ActiveCall {
+    func: String,
+    site: Span,   // the call expression
+    scope: usize, // index of the scope holding the parameters
+}
+
+/// How many calls may be active at once unless [`Interpreter::set_max_call_depth`]
+/// says otherwise. Gaut has no loops, so iteration is recursion and this is generous.
+pub const DEFAULT_MAX_CALL_DEPTH: u32 = 10_000;
+
+/// Host stack one interpreted call may take in an unoptimized build, with room for
+/// the expressions nested inside it.
+const STACK_PER_CALL: usize = 64 * 1024;
+
+/// Stack size for a thread that runs the interpreter with `max_call_depth`, so that it
+/// reports [`RuntimeError::StackOverflow`] rather than overflowing the host stack.
+pub fn stack_size(max_call_depth: u32) -> usize {
+    (max_call_depth as usize + 1).saturating_mul(STACK_PER_CALL)
+}
+
+/// How often, in evaluated expressions, the interpreter reads the clock to enforce a
+/// deadline; reading it for every expression would dominate simple arithmetic.
+const DEADLINE_CHECK_EVERY: u64 = 1024;
+
+/// Bytes of block-buffered print output held before it is written, like C's BUFSIZ.
+const STDOUT_BLOCK_SIZE: usize = 8192;
+
+/// Bytes of each function's arena in generated C, `GAUT_DEFAULT_ARENA_CAP`; `main`'s
+/// follows `arena_cap` instead.
+pub const FUNCTION_ARENA_CAP: usize = 64 * 1024;
+
+/// Where the last [`Interpreter::run_main`] placed the strings and byte buffers built
+/// with `+`, counted the way generated C allocates them: each call has an arena and a
+/// result that does not fit goes to the heap. Freed bytes are not subtracted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUse {
+    pub arena_bytes: usize,
+    pub heap_bytes: usize,
+}
+
+/// Interpreter with a block-scoped environment and an arena per call, as in generated C.
 pub struct Interpreter {
     globals: HashMap<String, Binding>,
+    global_types: HashMap<String, Type>,
     funcs: HashMap<String, FuncDecl>,
-    arena_cap: usize,
+    type_aliases: HashMap<String, Type>, // declared types, so method receivers see through aliases
+    config: Config,
+    stdout_capture: Option<(CaptureBuffer, Sink)>, // the buffer installed as stdout and the sink it replaced
+    pending_stdout: Vec<u8>, // printed but not yet written, under block buffering
+    trace: Option<Vec<TraceEvent>>, // Some while calls and writes are being recorded
+    observers: Vec<Box<dyn ExecObserver>>,
+    profiler: Option<Profiler>, // Some while calls are being counted and timed
+    call_depth: u32,
+    max_call_depth: u32,
+    fuel: Option<u64>,         // expressions left to evaluate; None is unlimited
+    deadline: Option<Instant>, // when evaluation stops, checked every DEADLINE_CHECK_EVERY expressions
+    evaluated: u64,            // expressions evaluated so far, for pacing the deadline checks
+    calls: Vec<ActiveCall>,    // kept as they were when a run fails, for the backtrace
+    debugger: Option<Box<dyn Debugger>>,
+    breakpoints: Vec<Breakpoint>,
+    resume: Resume,                        // when the debugger is next called
+    entry_file: String,                    // file of functions not from an imported module
+    module_files: HashMap<String, String>, // imported module name -> its file
+    target: Target,
+    args: Vec<String>,       // argv as args()/arg_at see it, the program path first
+    stdin: Box<dyn BufRead>, // what read_line/read_stdin consume
+    stdout: Sink,            // where print/println output goes
+    stderr: Sink,            // where log_info/log_warn/log_error records go
+    rng: Rng,                // rand_i32's generator, reseeded by seed_rand
+    span: Span,              // innermost statement/expression being evaluated
+    source: Vec<Decl>,       // unlowered declarations loaded so far, for eval_decl/eval_expr_str
+    builtins: HashMap<String, Builtin>, // the interpreter's own and the host's
+    memory: MemoryUse,       // of the last run_main
+}
+
+/// An output sink, shared with the interpreters of spawned threads.
+#[derive(Clone)]
+struct Sink(Arc<Mutex<dyn Write + Send>>);
+
+impl Sink {
+    fn new(out: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(out)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, dyn Write + Send + 'static> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The stdout sink of [`Interpreter::capture_stdout`], read back by
+/// [`Interpreter::take_captured_stdout`].
+#[derive(Clone, Default)]
+struct CaptureBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CaptureBuffer {
+    fn bytes(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes()).into_owned()
+    }
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        self.flush_stdout();
+    }
 }
 
 impl Interpreter {
     pub fn new(arena_cap: usize) -> Self {
         Self {
             globals: HashMap::new(),
+            global_types: HashMap::new(),
             funcs: HashMap::new(),
-            arena_cap,
+            type_aliases: HashMap::new(),
+            config: Config::new(arena_cap),
+            stdout_capture: None,
+            pending_stdout: Vec::new(),
+            trace: None,
+            observers: Vec::new(),
+            profiler: None,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: None,
+            deadline: None,
+            evaluated: 0,
+            calls: Vec::new(),
+            debugger: None,
+            breakpoints: Vec::new(),
+            resume: Resume::Continue,
+            entry_file: String::new(),
+            module_files: HashMap::new(),
+            target: Target::default(),
+            args: std::env::args().collect(),
+            stdin: Box::new(io::BufReader::new(io::stdin())),
+            stdout: Sink::new(io::stdout()),
+            stderr: Sink::new(io::stderr()),
+            rng: Rng::from_clock(),
+            span: Span::default(),
+            source: Vec::new(),
+            builtins: builtins::natives(),
+            memory: MemoryUse::default(),
         }
     }
 
-    pub fn from_source(src: &str) -> Result<Self, RuntimeError> {
-        let mut parser = Parser::new(src).map_err(|e| RuntimeError::Type(e.to_string()))?;
-        let program = parser
-            .parse_program()
-            .map_err(|e| RuntimeError::Type(e.to_string()))?;
-        let mut interp = Interpreter::new(1024 * 1024);
-        interp.load_program(&program)?;
-        Ok(interp)
+    /// Let scripts call `name`, implemented by `call` in the host. Arguments are
+    /// evaluated and passed in order; only their number is checked against `sig`, so
+    /// hand the signatures to the typechecker too (see [`Interpreter::host_signatures`]).
+    /// A function the script declares with the same name takes its place, and a host
+    /// function replaces the interpreter's builtin of that name, except `print` and
+    /// `println`, which stay the program's output and are not registered.
+    pub fn register_builtin(
+        &mut self,
+        name: &str,
+        sig: Signature,
+        call: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        if matches!(name, "print" | "println") {
+            return;
+        }
+        self.builtins
+            .insert(name.to_string(), Builtin::host(sig, Rc::new(call)));
     }
 
-    pub fn load_program(&mut self, program: &Program) -> Result<(), RuntimeError> {
-        // collect functions
-        for decl in &program.decls {
-            if let Decl::Func(f) = decl {
-                self.funcs.insert(f.name.0.clone(), f.clone());
-            }
-        }
-        // evaluate globals and lets at top level
-        for decl in &program.decls {
-            match decl {
-                Decl::Global(b) | Decl::Let(b) => {
-                    let val = self.eval_expr(
-                        &b.value,
-                        &mut Env::new_with_arena(self.arena_cap),
-                        EvalMode::Move,
-                    )?;
-                    self.globals.insert(
-                        b.name.0.clone(),
-                        Binding {
-                            mutable: b.mutable,
-                            value: Some(val),
-                        },
-                    );
-                }
-                _ => {}
-            }
-        }
-        Ok(())
+    /// Every function registered with [`Interpreter::register_builtin`], for
+    /// `TypeChecker::register_signature`.
+    pub fn host_signatures(&self) -> impl Iterator<Item = (&str, &Signature)> {
+        self.builtins.iter().filter_map(|(name, b)| match &b.call {
+            BuiltinFn::Host(sig, _) => Some((name.as_str(), sig)),
+            BuiltinFn::Native(_) => None,
+        })
     }
 
-    /// Evaluate `main()` and return its result value.
-    pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
-        let Some(main_fn) = self.funcs.get("main").cloned() else {
-            return Err(RuntimeError::UnknownIdent("main".into()));
+    /// Where the last runtime error was raised: the innermost statement or expression
+    /// being evaluated, or `None` when it has no source position.
+    pub fn error_span(&self) -> Option<Span> {
+        self.span.is_known().then_some(self.span)
+    }
+
+    /// The functions that were running when the last run or load failed, innermost
+    /// first. `None` when the error happened outside any function call.
+    pub fn backtrace(&self) -> Option<Backtrace> {
+        let calls: Vec<(String, Span)> = self
+            .calls
+            .iter()
+            .map(|c| (c.func.clone(), c.site))
+            .collect();
+        (!calls.is_empty()).then(|| Backtrace::new(&calls, self.span))
+    }
+
+    /// Stop the program where `debugger` asks and at breakpoints, and let it look
+    /// around. `start` says where the first stop is: `Step` stops at the first
+    /// statement, `Continue` at the first breakpoint.
+    pub fn set_debugger(&mut self, debugger: Box<dyn Debugger>, start: DebugAction) {
+        self.debugger = Some(debugger);
+        self.resume = Resume::after(start, 0);
+    }
+
+    /// Stop before any statement on `line` of `file`, when a debugger is installed.
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) {
+        debugger::add_breakpoint(&mut self.breakpoints, file, line);
+    }
+
+    pub fn clear_breakpoint(&mut self, file: &str, line: u32) {
+        debugger::remove_breakpoint(&mut self.breakpoints, file, line);
+    }
+
+    /// The file breakpoints name for the functions the entry file declares.
+    pub fn set_entry_file(&mut self, file: &str) {
+        self.entry_file = file.to_string();
+    }
+
+    /// The file breakpoints name for the functions of the imported module `module`,
+    /// which are loaded as `module__name`.
+    pub fn set_module_file(&mut self, module: &str, file: &str) {
+        self.module_files
+            .insert(module.to_string(), file.to_string());
+    }
+
+    fn file_of(&self, func: &str) -> &str {
+        func.split_once("__")
+            .and_then(|(module, _)| self.module_files.get(module))
+            .unwrap_or(&self.entry_file)
+    }
+
+    /// Hand control to the debugger if the program should stop before the statement
+    /// or expression at `span`.
+    fn debug_point(&mut self, span: Span, env: &Env) {
+        if self.debugger.is_none() || !span.is_known() {
+            return;
+        }
+        let depth = self.call_depth;
+        let func = self
+            .calls
+            .last()
+            .map(|c| c.func.clone())
+            .unwrap_or_default();
+        let file = self.file_of(&func).to_string();
+        let at_breakpoint = self
+            .breakpoints
+            .iter()
+            .any(|bp| bp.line == span.line && bp.file == file);
+        if !at_breakpoint && !self.resume.stops_at(depth) {
+            return;
+        }
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
         };
-        let mut env = Env::new_with_arena(self.arena_cap);
-        env.init_globals(&self.globals);
-        self.call_function(&main_fn, vec![], &mut env)
+        // what the program printed so far shows before the debugger's prompt
+        self.flush_stdout();
+        let base = self.calls.last().map_or(env.scopes.len(), |c| c.scope);
+        let mut pause = Pause {
+            func: &func,
+            file: &file,
+            span,
+            depth,
+            locals: env.locals_from(base),
+            breakpoints: &mut self.breakpoints,
+        };
+        let action = debugger.paused(&mut pause);
+        self.debugger = Some(debugger);
+        self.resume = Resume::after(action, depth);
     }
 
-    fn call_function(
+    /// Runs `eval` with `span` as the current location, restoring the outer one on
+    /// success so that a later failure is reported at the node that raised it.
+    fn at<T>(
         &mut self,
-        func: &FuncDecl,
-        args: Vec<Value>,
-        env: &mut Env,
-    ) -> Result<Value, RuntimeError> {
-        if func.params.len() != args.len() {
-            return Err(RuntimeError::Type("arity mismatch".into()));
+        span: Span,
+        eval: impl FnOnce(&mut Self) -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        if !span.is_known() {
+            return eval(self);
         }
-        env.push_scope();
-        for (param, arg) in func.params.iter().zip(args.into_iter()) {
-            env.insert_binding(
-                param.name.0.clone(),
-                Binding {
-                    mutable: param.mutable,
-                    value: Some(arg),
-                },
-            );
+        let outer = std::mem::replace(&mut self.span, span);
+        let result = eval(self);
+        if result.is_ok() {
+            self.span = outer;
         }
+        result
+    }
 
-        let result = match &func.body {
-            Expr::Block(b) => self.eval_block(b, env)?,
-            other => self.eval_expr(other, env, EvalMode::Move)?,
-        };
-        env.pop_scope();
-        Ok(result)
+    /// Set the command line the program sees: `[program, arg...]`. Defaults to the
+    /// host process's own arguments.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
     }
 
-    fn eval_block(&mut self, block: &Block, env: &mut Env) -> Result<Value, RuntimeError> {
-        env.push_scope();
-        for stmt in &block.stmts {
-            self.eval_stmt(stmt, env)?;
-        }
-        let result = if let Some(expr) = &block.tail {
-            self.eval_expr(expr, env, EvalMode::Move)?
-        } else {
-            Value::Unit
-        };
-        env.pop_scope();
-        Ok(result)
+    /// Read `read_line`/`read_stdin` input from `input` instead of the process's stdin.
+    pub fn set_stdin(&mut self, input: impl BufRead + 'static) {
+        self.stdin = Box::new(input);
+    }
+
+    /// Write print/println output to `out` instead of the process's stdout. Output
+    /// still held by block buffering is flushed to the old sink first. Threads started
+    /// with `spawn` afterwards write to `out` too.
+    pub fn set_stdout(&mut self, out: impl Write + Send + 'static) {
+        self.flush_stdout();
+        self.stdout = Sink::new(out);
+    }
+
+    /// Write log records, and errors of spawned threads, to `out` instead of the
+    /// process's stderr.
+    pub fn set_stderr(&mut self, out: impl Write + Send + 'static) {
+        self.stderr = Sink::new(out);
+    }
+
+    /// Fail with [`RuntimeError::StackOverflow`] instead of making a call when `max`
+    /// calls are already active. Each call recurses on the host stack, so the thread
+    /// running the interpreter needs [`stack_size`]`(max)` bytes of it.
+    pub fn set_max_call_depth(&mut self, max: u32) {
+        self.max_call_depth = max;
+    }
+
+    /// Allow `fuel` more expressions to be evaluated, over however many runs, before
+    /// failing with [`RuntimeError::FuelExhausted`]; `None` lifts the limit. Meant for
+    /// running untrusted programs.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// The fuel left, or `None` when there is no limit.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Fail with [`RuntimeError::FuelExhausted`] once `deadline` has passed; `None`
+    /// removes it. A builtin that blocks, such as `sleep_ms` or `read_line`, is not
+    /// interrupted, so the run ends at the first expression after it returns.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Use `target`'s integer model: arithmetic wraps at its width, as in generated C.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    /// Replace the settings reported by config_get (arena capacity, logging).
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// Configure the minimum level and format used by log_info/log_warn/log_error.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.config.logger = logger;
+    }
+
+    /// Capture print/println output in memory instead of writing it to the stdout
+    /// sink: a buffer becomes the sink until [`Interpreter::take_captured_stdout`].
+    pub fn capture_stdout(&mut self) {
+        if self.stdout_capture.is_none() {
+            let buffer = CaptureBuffer::default();
+            self.flush_stdout();
+            let previous = std::mem::replace(&mut self.stdout, Sink::new(buffer.clone()));
+            self.stdout_capture = Some((buffer, previous));
+        }
+    }
+
+    /// Return the output captured so far and stop capturing, putting back the sink
+    /// that was set before.
+    pub fn take_captured_stdout(&mut self) -> Option<String> {
+        self.flush_stdout();
+        let (buffer, previous) = self.stdout_capture.take()?;
+        self.stdout = previous;
+        Some(buffer.text())
+    }
+
+    /// Write out print/println output held back by block buffering.
+    pub fn flush_stdout(&mut self) {
+        if self.pending_stdout.is_empty() {
+            return;
+        }
+        let mut out = self.stdout.lock();
+        out.write_all(&self.pending_stdout).ok();
+        out.flush().ok();
+        drop(out);
+        self.pending_stdout.clear();
+    }
+
+    fn write_stdout(&mut self, s: &str) {
+        self.pending_stdout.extend_from_slice(s.as_bytes());
+        if self.config.print_buffer == PrintBuffer::Line
+            || self.pending_stdout.len() >= STDOUT_BLOCK_SIZE
+        {
+            self.flush_stdout();
+        }
+    }
+
+    /// Record every function call/return and binding write until `take_trace`.
+    pub fn record_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Return the events recorded so far and stop recording.
+    pub fn take_trace(&mut self) -> Option<Vec<TraceEvent>> {
+        self.trace.take()
+    }
+
+    /// Notify every observer added with `add_observer` from now on; see [`ExecObserver`].
+    pub fn add_observer(&mut self, observer: Box<dyn ExecObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Remove every observer.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Count and time every function call from now on, discarding earlier counts.
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::default());
+    }
+
+    /// Calls and time per function since `enable_profiling`; `None` if it was not
+    /// called. See [`ProfileReport`].
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        self.profiler.as_ref().map(Profiler::report)
+    }
+
+    fn observe(&mut self, mut event: impl FnMut(&mut dyn ExecObserver, u32)) {
+        let depth = self.call_depth;
+        for observer in &mut self.observers {
+            event(observer.as_mut(), depth);
+        }
+    }
+
+    fn record(&mut self, event: impl FnOnce(u32) -> TraceEvent) {
+        let depth = self.call_depth;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(event(depth));
+        }
+    }
+
+    pub fn from_source(src: &str) -> Result<Self, RuntimeError> {
+        let mut parser = Parser::new(src).map_err(|e| RuntimeError::Type(e.to_string()))?;
+        let program = parser
+            .parse_program()
+            .map_err(|e| RuntimeError::Type(e.to_string()))?;
+        let mut interp = Interpreter::new(1024 * 1024);
+        interp.load_program(&program)?;
+        Ok(interp)
+    }
+
+    pub fn load_program(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        let lowered = lower_program(program).map_err(|e| RuntimeError::Type(e.to_string()))?;
+        self.load_lowered(&lowered)?;
+        self.source = program.decls.clone();
+        Ok(())
+    }
+
+    /// Load a checked program as is; it was lowered by the typechecker. Values carry
+    /// their own types at runtime, so the resolved expression types are not needed.
+    pub fn load_typed(&mut self, typed: &TypedProgram) -> Result<(), RuntimeError> {
+        self.load_lowered(typed.program())?;
+        self.source = typed.source().decls.clone();
+        Ok(())
+    }
+
+    fn load_lowered(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.span = Span::default();
+        self.calls.clear();
+        // collect functions
+        for decl in &program.decls {
+            match decl {
+                Decl::Func(f) => {
+                    self.funcs.insert(f.name.0.clone(), f.clone());
+                }
+                Decl::Type(t) => {
+                    self.type_aliases.insert(t.name.0.clone(), t.ty.clone());
+                }
+                _ => {}
+            }
+        }
+        // evaluate globals and lets at top level; lowering put them in dependency order, so
+        // every global an initializer reads is already set
+        for decl in &program.decls {
+            match decl {
+                Decl::Global(b) | Decl::Let(b) => {
+                    let mut env = Env::new(Some(self.config.arena_cap));
+                    env.init_globals(&self.globals);
+                    let val = self.eval_expr(&b.value, &mut env, EvalMode::Move)?;
+                    self.globals.insert(
+                        b.name.0.clone(),
+                        Binding {
+                            mutable: b.mutable,
+                            value: Some(val),
+                        },
+                    );
+                    self.global_types.insert(b.name.0.clone(), b.ty.clone());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap in the functions of `program` while keeping the current values of globals.
+    /// Globals that survive the reload must keep their declared type; new globals are
+    /// initialized and globals missing from `program` are dropped. On error nothing changes.
+    pub fn reload_program(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        let lowered = lower_program(program).map_err(|e| RuntimeError::Type(e.to_string()))?;
+        self.reload_lowered(&lowered)?;
+        self.source = program.decls.clone();
+        Ok(())
+    }
+
+    /// [`Interpreter::reload_program`] for a checked program.
+    pub fn reload_typed(&mut self, typed: &TypedProgram) -> Result<(), RuntimeError> {
+        self.reload_lowered(typed.program())?;
+        self.source = typed.source().decls.clone();
+        Ok(())
+    }
+
+    fn reload_lowered(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.span = Span::default();
+        self.calls.clear();
+        for decl in &program.decls {
+            if let Decl::Global(b) | Decl::Let(b) = decl {
+                if let Some(old_ty) = self.global_types.get(&b.name.0) {
+                    if old_ty != &b.ty {
+                        return Err(RuntimeError::IncompatibleGlobal(b.name.0.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut funcs = HashMap::new();
+        let mut type_aliases = HashMap::new();
+        for decl in &program.decls {
+            match decl {
+                Decl::Func(f) => {
+                    funcs.insert(f.name.0.clone(), f.clone());
+                }
+                Decl::Type(t) => {
+                    type_aliases.insert(t.name.0.clone(), t.ty.clone());
+                }
+                _ => {}
+            }
+        }
+        // new globals may call the reloaded functions, so evaluate them against the new set
+        let old_funcs = std::mem::replace(&mut self.funcs, funcs);
+        let old_aliases = std::mem::replace(&mut self.type_aliases, type_aliases);
+        let mut globals = HashMap::new();
+        let mut global_types = HashMap::new();
+        for decl in &program.decls {
+            let (Decl::Global(b) | Decl::Let(b)) = decl else {
+                continue;
+            };
+            let value = match self.globals.get(&b.name.0) {
+                Some(existing) => existing.value.clone(),
+                None => {
+                    let mut env = Env::new(Some(self.config.arena_cap));
+                    env.init_globals(&globals);
+                    let evaluated = self.eval_expr(&b.value, &mut env, EvalMode::Move);
+                    match evaluated {
+                        Ok(v) => Some(v),
+                        Err(e) => {
+                            self.funcs = old_funcs;
+                            self.type_aliases = old_aliases;
+                            return Err(e);
+                        }
+                    }
+                }
+            };
+            globals.insert(
+                b.name.0.clone(),
+                Binding {
+                    mutable: b.mutable,
+                    value,
+                },
+            );
+            global_types.insert(b.name.0.clone(), b.ty.clone());
+        }
+        self.globals = globals;
+        self.global_types = global_types;
+        Ok(())
+    }
+
+    /// Add the declarations in `src` to the loaded program, as a REPL does with each
+    /// entry. A declaration replaces an earlier one with the same name; its globals are
+    /// (re)initialized and every other global keeps its current value. Macros and traits
+    /// declared by earlier calls or by [`Interpreter::load_program`] stay usable. The
+    /// declarations are type-checked with the loaded ones first, and a type error leaves
+    /// the program as it was.
+    pub fn eval_decl(&mut self, src: &str) -> Result<(), RuntimeError> {
+        let mut parser = Parser::new(src).map_err(|e| RuntimeError::Type(e.to_string()))?;
+        let added = parser
+            .parse_program()
+            .map_err(|e| RuntimeError::Type(e.to_string()))?
+            .decls;
+        let mut source: Vec<Decl> = self
+            .source
+            .iter()
+            .filter(|old| {
+                let name = decl_name(old);
+                name.is_none() || !added.iter().any(|new| decl_name(new) == name)
+            })
+            .cloned()
+            .collect();
+        source.extend(added.iter().cloned());
+        let lowered = self.check_source(source.clone())?;
+
+        self.span = Span::default();
+        self.calls.clear();
+        let mut funcs = self.funcs.clone();
+        for decl in &lowered.decls {
+            if let Decl::Func(f) = decl {
+                funcs.insert(f.name.0.clone(), f.clone());
+            }
+        }
+        // globals are evaluated against the new functions, in dependency order; only the
+        // ones this call declares are (re)computed
+        let old_funcs = std::mem::replace(&mut self.funcs, funcs);
+        let mut globals = self.globals.clone();
+        let mut global_types = self.global_types.clone();
+        for decl in &lowered.decls {
+            let (Decl::Global(b) | Decl::Let(b)) = decl else {
+                continue;
+            };
+            let declared_now = added
+                .iter()
+                .any(|d| matches!(d, Decl::Global(a) | Decl::Let(a) if a.name == b.name));
+            if !declared_now && globals.contains_key(&b.name.0) {
+                continue;
+            }
+            let mut env = Env::new(Some(self.config.arena_cap));
+            env.init_globals(&globals);
+            let value = match self.eval_expr(&b.value, &mut env, EvalMode::Move) {
+                Ok(v) => v,
+                Err(e) => {
+                    self.funcs = old_funcs;
+                    return Err(e);
+                }
+            };
+            globals.insert(
+                b.name.0.clone(),
+                Binding {
+                    mutable: b.mutable,
+                    value: Some(value),
+                },
+            );
+            global_types.insert(b.name.0.clone(), b.ty.clone());
+        }
+        self.globals = globals;
+        self.global_types = global_types;
+        self.source = source;
+        Ok(())
+    }
+
+    /// Type-check `decls`, the loaded declarations with those of a REPL entry, as the
+    /// loaded program was: at this interpreter's target, with the host functions'
+    /// signatures. Returns them lowered.
+    fn check_source(&self, decls: Vec<Decl>) -> Result<Program, RuntimeError> {
+        let mut checker = TypeChecker::with_target(self.target);
+        for (name, sig) in self.host_signatures() {
+            checker.register_signature(name, sig);
+        }
+        let typed = checker
+            .check_program(&Program { decls })
+            .map_err(|e| RuntimeError::Type(e.to_string()))?;
+        Ok(typed.program().clone())
+    }
+
+    /// Evaluate the expression in `src` against the loaded program and return its value.
+    /// It is type-checked against the loaded declarations before it runs. Assignments to
+    /// mutable globals persist into later calls.
+    pub fn eval_expr_str(&mut self, src: &str) -> Result<Value, RuntimeError> {
+        const ENTRY: &str = "__repl_expr";
+        let mut parser = Parser::new(src).map_err(|e| RuntimeError::Type(e.to_string()))?;
+        let body = parser
+            .parse_expression()
+            .map_err(|e| RuntimeError::Type(e.to_string()))?;
+        let span = body.span();
+        let mut decls = self.source.clone();
+        decls.push(Decl::Func(FuncDecl {
+            name: Ident(ENTRY.into()),
+            params: Vec::new(),
+            ret: None,
+            body,
+            doc: None,
+            span,
+        }));
+        let lowered = self.check_source(decls)?;
+        // the entry and the local functions lifted out of it are only needed for this call
+        let added: Vec<FuncDecl> = lowered
+            .decls
+            .into_iter()
+            .filter_map(|d| match d {
+                Decl::Func(f) if !self.funcs.contains_key(&f.name.0) => Some(f),
+                _ => None,
+            })
+            .collect();
+        let Some(entry) = added.iter().find(|f| f.name.0 == ENTRY).cloned() else {
+            return Err(RuntimeError::UnknownIdent(ENTRY.into()));
+        };
+        for f in &added {
+            self.funcs.insert(f.name.0.clone(), f.clone());
+        }
+
+        let mut env = Env::new(Some(self.config.arena_cap));
+        env.init_globals(&self.globals);
+        self.span = Span::default();
+        self.calls.clear();
+        let result = self.call_function(&entry, vec![], &mut env);
+        self.flush_stdout();
+        for f in &added {
+            self.funcs.remove(&f.name.0);
+        }
+        let value = result?;
+        if let Some(scope) = env.scopes.first() {
+            for (name, binding) in scope {
+                if let Some(global) = self.globals.get_mut(name) {
+                    *global = binding.clone();
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// How the last `run_main` placed the values it built with `+`.
+    pub fn memory_use(&self) -> MemoryUse {
+        self.memory
+    }
+
+    /// Evaluate `main()` and return its result value.
+    pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
+        let Some(main_fn) = self.funcs.get("main").cloned() else {
+            return Err(RuntimeError::UnknownIdent("main".into()));
+        };
+        let mut env = Env::new(Some(self.config.arena_cap));
+        env.init_globals(&self.globals);
+        self.span = Span::default();
+        self.calls.clear();
+        let result = self.call_function(&main_fn, vec![], &mut env);
+        self.flush_stdout();
+        self.memory = env.memory;
+        result
+    }
+
+    /// Evaluate `main()` with `args` as the program's arguments. They follow the
+    /// program path already set, so `args()` reads `path\narg...` like `gaut_args()` in
+    /// a compiled binary run with the same arguments.
+    pub fn run_main_with_args(&mut self, args: Vec<String>) -> Result<Value, RuntimeError> {
+        let program = self.args.first().cloned().unwrap_or_default();
+        self.args = std::iter::once(program).chain(args).collect();
+        self.run_main()
+    }
+
+    /// Run `func` on a new thread with its own interpreter, which starts from the
+    /// functions, settings and current global values of this one. Globals are copied,
+    /// so threads share state only through handles such as `Chan` and `atomic_i64`.
+    /// Host builtins and the stdin set with `set_stdin` stay with this interpreter;
+    /// the stdout and stderr sinks, including a `capture_stdout` buffer, are shared.
+    /// The thread is not joined; an error in it is written to the stderr sink.
+    fn spawn(&mut self, func: &str, env: &Env) -> Result<(), RuntimeError> {
+        let funcs = self.funcs.clone();
+        let type_aliases = self.type_aliases.clone();
+        let globals = env.scopes.first().cloned().unwrap_or_default();
+        let config = self.config.clone();
+        let target = self.target;
+        let args = self.args.clone();
+        let max_call_depth = self.max_call_depth;
+        let (stdout, stderr) = (self.stdout.clone(), self.stderr.clone());
+        let func = func.to_string();
+        thread::Builder::new()
+            .stack_size(stack_size(max_call_depth))
+            .spawn(move || {
+                let mut interp = Interpreter::new(config.arena_cap);
+                interp.funcs = funcs;
+                interp.type_aliases = type_aliases;
+                interp.globals = globals;
+                interp.config = config;
+                interp.target = target;
+                interp.args = args;
+                interp.max_call_depth = max_call_depth;
+                interp.stdout = stdout;
+                interp.stderr = stderr;
+                let Some(decl) = interp.funcs.get(&func).cloned() else {
+                    return;
+                };
+                let mut env = Env::new(None);
+                env.init_globals(&interp.globals);
+                match interp.call_function(&decl, vec![], &mut env) {
+                    // exit() in a thread ends only that thread
+                    Ok(_) | Err(RuntimeError::Exit(_)) => {}
+                    Err(e) => {
+                        interp.flush_stdout();
+                        writeln!(interp.stderr.lock(), "error in thread {func}: {e}").ok();
+                    }
+                }
+            })
+            .map(drop)
+            .map_err(|e| RuntimeError::Thread(e.to_string()))
+    }
+
+    fn call_function(
+        &mut self,
+        func: &FuncDecl,
+        args: Vec<Value>,
+        env: &mut Env,
+    ) -> Result<Value, RuntimeError> {
+        if func.params.len() != args.len() {
+            return Err(RuntimeError::Type("arity mismatch".into()));
+        }
+        if self.call_depth >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow(self.max_call_depth));
+        }
+        self.record(|depth| TraceEvent::Call {
+            depth,
+            func: func.name.0.clone(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        });
+        self.observe(|o, depth| o.on_call(depth, &func.name.0, &args));
+        let scope = env.scopes.len();
+        env.enter_call();
+        env.push_scope();
+        for (param, arg) in func.params.iter().zip(args) {
+            env.insert_binding(
+                param.name.0.clone(),
+                Binding {
+                    mutable: param.mutable,
+                    value: Some(arg),
+                },
+            );
+        }
+
+        self.calls.push(ActiveCall {
+            func: func.name.0.clone(),
+            site: self.span,
+            scope,
+        });
+        self.call_depth += 1;
+        let started = self.profiler.as_mut().map(|p| p.enter(&func.name.0));
+        let result = match &func.body {
+            Expr::Block(b) => self.eval_block(b, env),
+            other => {
+                self.debug_point(other.span(), env);
+                self.eval_expr(other, env, EvalMode::Move)
+            }
+        };
+        if let (Some(profiler), Some(start)) = (self.profiler.as_mut(), started) {
+            profiler.exit(&func.name.0, start);
+        }
+        self.call_depth -= 1;
+        let result = result?;
+        let drops = func
+            .params
+            .iter()
+            .filter_map(|p| Some((p.name.0.clone(), self.drop_hook(&p.ty)?)))
+            .collect();
+        self.run_drops(drops, env)?;
+        env.pop_scope();
+        env.leave_call();
+        self.calls.pop();
+        self.record(|depth| TraceEvent::Return {
+            depth,
+            func: func.name.0.clone(),
+            value: result.to_string(),
+        });
+        self.observe(|o, depth| o.on_return(depth, &func.name.0, &result));
+        Ok(result)
+    }
+
+    fn eval_block(&mut self, block: &Block, env: &mut Env) -> Result<Value, RuntimeError> {
+        let mark = env.arena_mark();
+        env.push_scope();
+        let mut drops = Vec::new();
+        for stmt in &block.stmts {
+            self.eval_stmt(stmt, env)?;
+            if let Stmt::Binding(b) = stmt {
+                if let Some(hook) = self.drop_hook(&b.ty) {
+                    drops.push((b.name.0.clone(), hook));
+                }
+            }
+        }
+        let result = if let Some(expr) = &block.tail {
+            self.debug_point(expr.span(), env);
+            self.eval_expr(expr, env, EvalMode::Move)?
+        } else {
+            Value::Unit
+        };
+        self.run_drops(drops, env)?;
+        env.pop_scope();
+        env.release(mark);
+        Ok(result)
+    }
+
+    /// The `Drop` impl for values declared as `ty`, if there is one.
+    fn drop_hook(&self, ty: &Type) -> Option<FuncDecl> {
+        drop_fn(ty).and_then(|name| self.funcs.get(&name).cloned())
+    }
+
+    /// Call drop hooks, last binding first, for bindings of the innermost scope
+    /// that still own their value.
+    fn run_drops(
+        &mut self,
+        drops: Vec<(String, FuncDecl)>,
+        env: &mut Env,
+    ) -> Result<(), RuntimeError> {
+        for (name, hook) in drops.into_iter().rev() {
+            if let Some(value) = env.take_local(&name) {
+                self.call_function(&hook, vec![value], env)?;
+            }
+        }
+        Ok(())
     }
 
     fn eval_stmt(&mut self, stmt: &Stmt, env: &mut Env) -> Result<(), RuntimeError> {
+        if !matches!(stmt, Stmt::Func(_)) {
+            self.debug_point(stmt.span(), env);
+        }
+        self.observe(|o, depth| o.on_stmt(depth, stmt.span()));
+        self.at(stmt.span(), |it| it.eval_stmt_kind(stmt, env))
+    }
+
+    fn eval_stmt_kind(&mut self, stmt: &Stmt, env: &mut Env) -> Result<(), RuntimeError> {
         match stmt {
             Stmt::Binding(b) => {
                 let val = self.eval_expr(&b.value, env, EvalMode::Move)?;
+                self.record(|depth| TraceEvent::Write {
+                    depth,
+                    target: b.name.0.clone(),
+                    value: val.to_string(),
+                });
+                self.observe(|o, depth| o.on_write(depth, &b.name.0, &val));
                 env.insert_binding(
                     b.name.0.clone(),
                     Binding {
@@ -169,12 +1502,23 @@ impl Interpreter {
             }
             Stmt::Assign(a) => {
                 let val = self.eval_expr(&a.value, env, EvalMode::Move)?;
+                self.record(|depth| TraceEvent::Write {
+                    depth,
+                    target: path_to_string(&a.target),
+                    value: val.to_string(),
+                });
+                if !self.observers.is_empty() {
+                    let target = path_to_string(&a.target);
+                    self.observe(|o, depth| o.on_write(depth, &target, &val));
+                }
                 env.assign_path(&a.target, val)
             }
             Stmt::Expr(e) => {
                 let _ = self.eval_expr(e, env, EvalMode::Move)?;
                 Ok(())
             }
+            // lifted to the top level by `lower_program` when the program is loaded
+            Stmt::Func(_) => Ok(()),
         }
     }
 
@@ -183,34 +1527,100 @@ impl Interpreter {
         expr: &Expr,
         env: &mut Env,
         mode: EvalMode,
+    ) -> Result<Value, RuntimeError> {
+        self.burn_fuel()?;
+        self.at(expr.span(), |it| it.eval_expr_kind(expr, env, mode))
+    }
+
+    /// Account for one evaluated expression against the fuel and the deadline.
+    fn burn_fuel(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = self.fuel.as_mut() {
+            *fuel = fuel.checked_sub(1).ok_or(RuntimeError::FuelExhausted)?;
+        }
+        self.evaluated = self.evaluated.wrapping_add(1);
+        if let Some(deadline) = self.deadline {
+            if self.evaluated.is_multiple_of(DEADLINE_CHECK_EVERY) && Instant::now() >= deadline {
+                return Err(RuntimeError::FuelExhausted);
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_expr_kind(
+        &mut self,
+        expr: &Expr,
+        env: &mut Env,
+        mode: EvalMode,
     ) -> Result<Value, RuntimeError> {
         match expr {
-            Expr::Literal(l) => Ok(match l {
+            Expr::Literal(l, _) => Ok(match l {
+                // the checker typed the literal from its context and made sure it fits
                 Literal::Int(v) => Value::Int(*v),
                 Literal::Bool(b) => Value::Bool(*b),
                 Literal::Str(s) => Value::Str(s.clone()),
                 Literal::Unit => Value::Unit,
             }),
-            Expr::Path(p) => env.resolve_path(p, mode),
+            Expr::Path(p) => match env.resolve_path(p, mode) {
+                Err(RuntimeError::UnknownIdent(name))
+                    if p.0.len() == 1 && self.funcs.contains_key(&name) =>
+                {
+                    Ok(Value::Func(name))
+                }
+                other => other,
+            },
             Expr::Copy(inner) => {
                 let v = self.eval_expr(inner, env, EvalMode::Copy)?;
                 Ok(v)
             }
-            Expr::Ref(inner) => {
-                // For now, treat ref as borrow-copy (no mutation through ref in 1st version).
-                let v = self.eval_expr(inner, env, EvalMode::Borrow)?;
-                Ok(v)
-            }
+            Expr::Ref(inner) => match &**inner {
+                Expr::Path(p) => Ok(Value::Ref(env.place_of(p)?)),
+                // a temporary has no other name to be seen through, so it is its own referee
+                _ => self.eval_expr(inner, env, EvalMode::Borrow),
+            },
+            Expr::MutRef(inner) => match &**inner {
+                Expr::Path(p) => Ok(Value::MutRef(env.place_of(p)?)),
+                _ => Err(RuntimeError::Type(
+                    "&mut needs a binding or a field of one".into(),
+                )),
+            },
             Expr::FuncCall(fc) => {
-                let func_name = path_to_string(&fc.callee);
+                if let Some(method) = fc.method_name() {
+                    let receiver = Receiver::of(
+                        self.funcs
+                            .get(&method.0)
+                            .and_then(|f| f.params.first())
+                            .map(|p| self.resolve_alias(&p.ty)),
+                    );
+                    if let Some(desugared) = fc.desugar_method(receiver) {
+                        return self.eval_expr(&Expr::FuncCall(desugared), env, mode);
+                    }
+                }
+                let mut func_name = path_to_string(&fc.callee);
+                // calling a binding of function type goes to the function it names
+                if let Ok(Value::Func(target)) = env.resolve_path(&fc.callee, EvalMode::Borrow) {
+                    func_name = target;
+                }
                 if let Some(func) = self.funcs.get(&func_name).cloned() {
                     let mut args = Vec::new();
                     for a in &fc.args {
                         args.push(self.eval_expr(a, env, EvalMode::Move)?);
                     }
                     self.call_function(&func, args, env)
-                } else if let Some(res) = eval_builtin(&func_name, &fc.args, self, env)? {
-                    Ok(res)
+                } else if let Some(builtin) = self.builtins.get(&func_name).cloned() {
+                    if builtin.arity != fc.args.len() {
+                        return Err(RuntimeError::Type(format!(
+                            "{func_name} expects {} arguments",
+                            builtin.arity
+                        )));
+                    }
+                    let mut args = Vec::new();
+                    for a in &fc.args {
+                        args.push(self.eval_read(a, env, EvalMode::Move)?);
+                    }
+                    match builtin.call {
+                        BuiltinFn::Native(call) => call(&func_name, &args, self, env),
+                        BuiltinFn::Host(_, call) => call(&args),
+                    }
                 } else {
                     Err(RuntimeError::UnknownIdent(func_name))
                 }
@@ -224,8 +1634,20 @@ impl Interpreter {
                 }
             }
             Expr::Block(b) => self.eval_block(b, env),
+            Expr::Time(body) => {
+                let start = Instant::now();
+                self.eval_expr(body, env, EvalMode::Move)?;
+                let millis = i64::try_from(start.elapsed().as_millis()).unwrap_or(i64::MAX);
+                Ok(Value::Int(self.target.int_model.wrap(millis)))
+            }
             Expr::RecordLit(r) => {
-                let mut map = IndexMap::new();
+                let mut map = match &r.base {
+                    Some(base) => match self.eval_expr(base, env, EvalMode::Move)? {
+                        Value::Record(map) => map,
+                        _ => return Err(RuntimeError::Type("record base must be a record".into())),
+                    },
+                    None => IndexMap::new(),
+                };
                 for f in &r.fields {
                     let v = self.eval_expr(&f.value, env, EvalMode::Move)?;
                     map.insert(f.name.0.clone(), v);
@@ -233,9 +1655,15 @@ impl Interpreter {
                 Ok(Value::Record(map))
             }
             Expr::Unary(u) => {
+                // `-2147483648` is a negative literal, not MIN negated
+                if let (UnaryOp::Neg, Expr::Literal(Literal::Int(v), _)) = (&u.op, &*u.expr) {
+                    return Ok(Value::Int(v.wrapping_neg()));
+                }
                 let v = self.eval_expr(&u.expr, env, EvalMode::Move)?;
                 match (u.op.clone(), v) {
-                    (UnaryOp::Neg, Value::Int(i)) => Ok(Value::Int(-i)),
+                    (UnaryOp::Neg, Value::Int(i)) => {
+                        self.int_result(-i128::from(i), || format!("-({i})"))
+                    }
                     (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
                     _ => Err(RuntimeError::Type("invalid unary operand".into())),
                 }
@@ -243,15 +1671,137 @@ impl Interpreter {
             Expr::Binary(b) => {
                 let l = self.eval_expr(&b.left, env, EvalMode::Move)?;
                 let r = self.eval_expr(&b.right, env, EvalMode::Move)?;
-                self.eval_binary(&l, &r, b.op.clone())
+                if let (Value::Ref(lp), Value::Ref(rp), BinaryOp::Eq | BinaryOp::Ne) =
+                    (&l, &r, &b.op)
+                {
+                    // references compare by what they point at, like pointers in C
+                    let same = env.resolve_place(lp)? == env.resolve_place(rp)?;
+                    return Ok(Value::Bool(same == matches!(b.op, BinaryOp::Eq)));
+                }
+                let l = env.read_through(l)?;
+                let r = env.read_through(r)?;
+                let value = self.eval_binary(&l, &r, b.op.clone())?;
+                env.charge(&value);
+                Ok(value)
+            }
+            Expr::Index(ix) => {
+                let base = self.eval_indexable(&ix.base, env)?;
+                let Value::Int(i) = self.eval_expr(&ix.index, env, EvalMode::Move)? else {
+                    return Err(RuntimeError::Type("index must be an integer".into()));
+                };
+                let bytes = match &base {
+                    Value::Str(s) => s.as_bytes(),
+                    Value::Bytes(b) => b.as_slice(),
+                    _ => {
+                        return Err(RuntimeError::Type(
+                            "only Str and Bytes are indexable".into(),
+                        ))
+                    }
+                };
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|idx| bytes.get(idx))
+                    .map(|b| Value::Int(i64::from(*b)))
+                    .ok_or(RuntimeError::IndexOutOfBounds {
+                        index: i,
+                        len: bytes.len(),
+                    })
+            }
+            Expr::Slice(sl) => {
+                let base = self.eval_indexable(&sl.base, env)?;
+                let mut bound = |e: &Option<Box<Expr>>| -> Result<Option<i64>, RuntimeError> {
+                    match e {
+                        Some(e) => match self.eval_expr(e, env, EvalMode::Move)? {
+                            Value::Int(i) => Ok(Some(i)),
+                            _ => Err(RuntimeError::Type("slice bounds must be integers".into())),
+                        },
+                        None => Ok(None),
+                    }
+                };
+                let (start, end) = (bound(&sl.start)?, bound(&sl.end)?);
+                let range = |len: usize| {
+                    let (start, end) = (start.unwrap_or(0), end.unwrap_or(len as i64));
+                    usize::try_from(start)
+                        .ok()
+                        .zip(usize::try_from(end).ok())
+                        .filter(|(s, e)| s <= e && *e <= len)
+                        .map(|(s, e)| s..e)
+                        .ok_or(RuntimeError::SliceOutOfBounds { start, end, len })
+                };
+                let value = match base {
+                    Value::Str(s) => {
+                        Value::Str(String::from_utf8_lossy(&s.as_bytes()[range(s.len())?]).into())
+                    }
+                    Value::Bytes(b) => Value::Bytes(b[range(b.len())?].to_vec()),
+                    _ => {
+                        return Err(RuntimeError::Type(
+                            "only Str and Bytes are indexable".into(),
+                        ))
+                    }
+                };
+                Ok(value)
+            }
+        }
+    }
+
+    /// The `Str` or `Bytes` under `s[..]`, borrowed and read through a `&mut`.
+    fn eval_indexable(&mut self, base: &Expr, env: &mut Env) -> Result<Value, RuntimeError> {
+        Ok(match self.eval_read(base, env, EvalMode::Borrow)? {
+            Value::MutRef(place) => env.read_place(&place)?.clone(),
+            v => v,
+        })
+    }
+
+    /// `ty` with aliases followed to the type they name. An alias cycle is a type error,
+    /// so the walk is only bounded, not reported.
+    fn resolve_alias<'a>(&'a self, mut ty: &'a Type) -> &'a Type {
+        for _ in 0..=self.type_aliases.len() {
+            let Type::Named(name) = ty else {
+                break;
+            };
+            match self.type_aliases.get(&name.0) {
+                Some(target) => ty = target,
+                None => break,
             }
         }
+        ty
+    }
+
+    /// `expr`'s value, read through a `&` reference. Builtins and operators work on
+    /// values, while `&mut` stays a place for the builtins that write through it.
+    fn eval_read(
+        &mut self,
+        expr: &Expr,
+        env: &mut Env,
+        mode: EvalMode,
+    ) -> Result<Value, RuntimeError> {
+        let value = self.eval_expr(expr, env, mode)?;
+        env.read_through(value)
+    }
+
+    /// The exact result of an integer operator, checked against the target's width:
+    /// out of range, it wraps or is an `Overflow` error naming `op`, as in generated C.
+    fn int_result(&self, exact: i128, op: impl FnOnce() -> String) -> Result<Value, RuntimeError> {
+        let model = self.target.int_model;
+        if (i128::from(model.min())..=i128::from(model.max())).contains(&exact) {
+            return Ok(Value::Int(exact as i64));
+        }
+        match self.target.overflow {
+            Overflow::Wrap => Ok(Value::Int(model.wrap(exact as i64))),
+            Overflow::Trap => Err(RuntimeError::Overflow(op())),
+        }
     }
 
     fn eval_binary(&self, l: &Value, r: &Value, op: BinaryOp) -> Result<Value, RuntimeError> {
+        let model = self.target.int_model;
+        let exact = |a: &i64, b: &i64, f: fn(i128, i128) -> i128, sym: &str| {
+            self.int_result(f(i128::from(*a), i128::from(*b)), || {
+                format!("{a} {sym} {b}")
+            })
+        };
         match op {
             BinaryOp::Add => match (l, r) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
+                (Value::Int(a), Value::Int(b)) => exact(a, b, |a, b| a + b, "+"),
                 (Value::Str(a), Value::Str(b)) => Ok(Value::Str(format!("{}{}", a, b))),
                 (Value::Bytes(a), Value::Bytes(b)) => {
                     let mut out = Vec::with_capacity(a.len() + b.len());
@@ -262,22 +1812,64 @@ impl Interpreter {
                 _ => Err(RuntimeError::Type("invalid operands for +".into())),
             },
             BinaryOp::Sub => match (l, r) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
+                (Value::Int(a), Value::Int(b)) => exact(a, b, |a, b| a - b, "-"),
                 _ => Err(RuntimeError::Type("invalid operands for -".into())),
             },
             BinaryOp::Mul => match (l, r) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
+                (Value::Int(a), Value::Int(b)) => exact(a, b, |a, b| a * b, "*"),
                 _ => Err(RuntimeError::Type("invalid operands for *".into())),
             },
             BinaryOp::Div => match (l, r) {
-                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
+                (Value::Int(_), Value::Int(0)) => Err(RuntimeError::DivisionByZero),
+                (Value::Int(a), Value::Int(b)) => exact(a, b, |a, b| a / b, "/"),
                 _ => Err(RuntimeError::Type("invalid operands for /".into())),
             },
             BinaryOp::Lt => match (l, r) {
                 (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
                 _ => Err(RuntimeError::Type("invalid operands for <".into())),
             },
+            BinaryOp::Gt => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+                _ => Err(RuntimeError::Type("invalid operands for >".into())),
+            },
+            BinaryOp::Le => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+                _ => Err(RuntimeError::Type("invalid operands for <=".into())),
+            },
+            BinaryOp::Ge => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+                _ => Err(RuntimeError::Type("invalid operands for >=".into())),
+            },
             BinaryOp::Eq => Ok(Value::Bool(l == r)),
+            BinaryOp::Ne => Ok(Value::Bool(l != r)),
+            BinaryOp::BitAnd => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a & b)),
+                _ => Err(RuntimeError::Type("invalid operands for &".into())),
+            },
+            BinaryOp::BitOr => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a | b)),
+                _ => Err(RuntimeError::Type("invalid operands for |".into())),
+            },
+            BinaryOp::BitXor => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a ^ b)),
+                _ => Err(RuntimeError::Type("invalid operands for ^".into())),
+            },
+            BinaryOp::Shl | BinaryOp::Shr => match (l, r) {
+                (Value::Int(a), Value::Int(b)) => {
+                    if !(0..i64::from(model.bits())).contains(b) {
+                        return Err(RuntimeError::ShiftOutOfRange {
+                            amount: *b,
+                            bits: model.bits(),
+                        });
+                    }
+                    if matches!(op, BinaryOp::Shl) {
+                        Ok(Value::Int(model.wrap(a.wrapping_shl(*b as u32))))
+                    } else {
+                        Ok(Value::Int(a >> b))
+                    }
+                }
+                _ => Err(RuntimeError::Type("invalid operands for shift".into())),
+            },
             BinaryOp::And => match (l, r) {
                 (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a && *b)),
                 _ => Err(RuntimeError::Type("invalid operands for &&".into())),
@@ -290,196 +1882,51 @@ impl Interpreter {
     }
 }
 
-fn eval_builtin(
-    name: &str,
-    args: &[Expr],
-    interp: &mut Interpreter,
-    env: &mut Env,
-) -> Result<Option<Value>, RuntimeError> {
-    match name {
-        "print" | "println" => {
-            if args.len() != 1 {
-                return Err(RuntimeError::Type(
-                    "print/println expects one argument".into(),
-                ));
-            }
-            let val = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let s = match val {
-                Value::Str(ref s) => s.clone(),
-                other => format!("{other:?}"),
-            };
-            if name == "print" {
-                print!("{}", s);
-                io::stdout().flush().ok();
-            } else {
-                println!("{}", s);
-            }
-            Ok(Some(Value::Str(s)))
-        }
-        "args" => {
-            if !args.is_empty() {
-                return Err(RuntimeError::Type("args expects no arguments".into()));
-            }
-            let parts: Vec<String> = std::env::args().collect();
-            let joined = parts.join("\n");
-            Ok(Some(Value::Bytes(joined.into_bytes())))
-        }
-        "bytes_to_str" => {
-            if args.len() != 1 {
-                return Err(RuntimeError::Type(
-                    "bytes_to_str expects one argument".into(),
-                ));
-            }
-            let val = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let Value::Bytes(bytes) = val else {
-                return Err(RuntimeError::Type("bytes_to_str expects Bytes".into()));
-            };
-            let s = String::from_utf8_lossy(&bytes).to_string();
-            Ok(Some(Value::Str(s)))
-        }
-        "try_read_file" => {
-            if args.len() != 1 {
-                return Err(RuntimeError::Type(
-                    "try_read_file expects one argument".into(),
-                ));
-            }
-            let val = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let Value::Str(path) = val else {
-                return Err(RuntimeError::Type("try_read_file expects Str".into()));
-            };
-            let mut map = IndexMap::new();
-            match fs::read_to_string(&path) {
-                Ok(data) => {
-                    map.insert("ok".into(), Value::Bool(true));
-                    map.insert("data".into(), Value::Str(data));
-                }
-                Err(_) => {
-                    map.insert("ok".into(), Value::Bool(false));
-                    map.insert("data".into(), Value::Str(String::new()));
-                }
-            }
-            Ok(Some(Value::Record(map)))
-        }
-        "read_file" => {
-            if args.len() != 1 {
-                return Err(RuntimeError::Type("read_file expects one argument".into()));
-            }
-            let val = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let Value::Str(path) = val else {
-                return Err(RuntimeError::Type("read_file expects Str".into()));
-            };
-            let data = fs::read_to_string(&path).unwrap_or_default();
-            Ok(Some(Value::Str(data)))
-        }
-        "try_write_file" => {
-            if args.len() != 2 {
-                return Err(RuntimeError::Type(
-                    "try_write_file expects two arguments".into(),
-                ));
-            }
-            let path = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let data = interp.eval_expr(&args[1], env, EvalMode::Move)?;
-            let Value::Str(path) = path else {
-                return Err(RuntimeError::Type("try_write_file expects Str path".into()));
-            };
-            let Value::Str(data) = data else {
-                return Err(RuntimeError::Type("try_write_file expects Str data".into()));
-            };
-            let ok = fs::write(path, data).is_ok();
-            Ok(Some(Value::Bool(ok)))
-        }
-        "write_file" => {
-            if args.len() != 2 {
-                return Err(RuntimeError::Type(
-                    "write_file expects two arguments".into(),
-                ));
-            }
-            let path = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let data = interp.eval_expr(&args[1], env, EvalMode::Move)?;
-            let Value::Str(path) = path else {
-                return Err(RuntimeError::Type("write_file expects Str path".into()));
-            };
-            let Value::Str(data) = data else {
-                return Err(RuntimeError::Type("write_file expects Str data".into()));
-            };
-            let _ = fs::write(path, data);
-            Ok(Some(Value::Unit))
-        }
-        "str_len" => {
-            if args.len() != 1 {
-                return Err(RuntimeError::Type("str_len expects one argument".into()));
-            }
-            let val = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let Value::Str(s) = val else {
-                return Err(RuntimeError::Type("str_len expects Str".into()));
-            };
-            Ok(Some(Value::Int(s.as_bytes().len() as i64)))
-        }
-        "str_byte_at" => {
-            if args.len() != 2 {
-                return Err(RuntimeError::Type(
-                    "str_byte_at expects two arguments".into(),
-                ));
-            }
-            let s = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let i = interp.eval_expr(&args[1], env, EvalMode::Move)?;
-            let Value::Str(s) = s else {
-                return Err(RuntimeError::Type("str_byte_at expects Str".into()));
-            };
-            let Value::Int(i) = i else {
-                return Err(RuntimeError::Type("str_byte_at expects i32 index".into()));
-            };
-            if i < 0 {
-                return Ok(Some(Value::Int(0)));
-            }
-            let idx = i as usize;
-            let b = s.as_bytes().get(idx).copied().unwrap_or(0);
-            Ok(Some(Value::Int(b as i64)))
-        }
-        "str_slice" => {
-            if args.len() != 3 {
-                return Err(RuntimeError::Type(
-                    "str_slice expects three arguments".into(),
-                ));
-            }
-            let s = interp.eval_expr(&args[0], env, EvalMode::Move)?;
-            let start = interp.eval_expr(&args[1], env, EvalMode::Move)?;
-            let len = interp.eval_expr(&args[2], env, EvalMode::Move)?;
-            let Value::Str(s) = s else {
-                return Err(RuntimeError::Type("str_slice expects Str".into()));
-            };
-            let Value::Int(start) = start else {
-                return Err(RuntimeError::Type("str_slice expects i32 start".into()));
-            };
-            let Value::Int(len) = len else {
-                return Err(RuntimeError::Type("str_slice expects i32 len".into()));
-            };
-            if start < 0 || len < 0 {
-                return Ok(Some(Value::Str(String::new())));
-            }
-            let st = start as usize;
-            let ln = len as usize;
-            let bytes = s.as_bytes();
-            let st = st.min(bytes.len());
-            let end = (st + ln).min(bytes.len());
-            let out = String::from_utf8_lossy(&bytes[st..end]).to_string();
-            Ok(Some(Value::Str(out)))
-        }
-        _ => Ok(None),
-    }
-}
-
 #[derive(Debug)]
 struct Env {
     scopes: Vec<HashMap<String, Binding>>, // innermost at end
-    arena: Arena,
+    arenas: Vec<ArenaFrame>,               // one per active call, innermost at end
+    main_arena: Option<usize>,             // capacity of the next call's arena, when it is main
+    memory: MemoryUse,
+}
+
+/// Bytes in use out of one call's arena.
+#[derive(Debug)]
+struct ArenaFrame {
+    cap: usize,
+    used: usize,
 }
 
 impl Env {
-    fn new_with_arena(cap: usize) -> Self {
+    /// `main_arena` is the capacity of `main`'s arena when the first call is `main`;
+    /// every other call gets [`FUNCTION_ARENA_CAP`] bytes.
+    fn new(main_arena: Option<usize>) -> Self {
         Self {
             scopes: Vec::new(),
-            arena: Arena::with_capacity(cap),
+            arenas: Vec::new(),
+            main_arena,
+            memory: MemoryUse::default(),
+        }
+    }
+
+    fn enter_call(&mut self) {
+        let cap = self.main_arena.take().unwrap_or(FUNCTION_ARENA_CAP);
+        self.arenas.push(ArenaFrame { cap, used: 0 });
+    }
+
+    fn leave_call(&mut self) {
+        self.arenas.pop();
+    }
+
+    /// Bytes used in the running call's arena; pass it to `release` when the block
+    /// ends, like `gaut_scope_enter`/`gaut_scope_leave`.
+    fn arena_mark(&self) -> usize {
+        self.arenas.last().map_or(0, |a| a.used)
+    }
+
+    fn release(&mut self, mark: usize) {
+        if let Some(arena) = self.arenas.last_mut() {
+            arena.used = arena.used.min(mark);
         }
     }
 
@@ -496,9 +1943,52 @@ impl Env {
         self.scopes.push(HashMap::new());
     }
 
+    /// Every binding of the scopes from `base` inward that still holds its value; an
+    /// inner binding hides an outer one. Names within a scope are sorted.
+    fn locals_from(&self, base: usize) -> IndexMap<String, Value> {
+        let mut locals = IndexMap::new();
+        for scope in self.scopes.iter().skip(base) {
+            let mut names: Vec<&String> = scope.keys().collect();
+            names.sort();
+            for name in names {
+                match scope.get(name).and_then(|b| b.value.as_ref()) {
+                    Some(value) => {
+                        locals.insert(name.clone(), value.clone());
+                    }
+                    None => {
+                        locals.shift_remove(name);
+                    }
+                }
+            }
+        }
+        locals
+    }
+
     fn pop_scope(&mut self) {
         self.scopes.pop();
-        self.arena.reset();
+    }
+
+    /// Place the result of `+` on `Str` or `Bytes` where generated C would: in the
+    /// running call's arena while it has room, else on the heap. A `Str` also takes its
+    /// NUL. As in C, a full arena is not an error.
+    fn charge(&mut self, value: &Value) {
+        let size = match value {
+            Value::Str(s) => s.len() + 1,
+            Value::Bytes(b) => b.len(),
+            _ => return,
+        };
+        match self.arenas.last_mut() {
+            Some(arena) if size <= arena.cap - arena.used => {
+                arena.used += size;
+                self.memory.arena_bytes += size;
+            }
+            _ => self.memory.heap_bytes += size,
+        }
+    }
+
+    /// Move the value out of a binding in the innermost scope; `None` if already moved.
+    fn take_local(&mut self, name: &str) -> Option<Value> {
+        self.scopes.last_mut()?.get_mut(name)?.value.take()
     }
 
     fn insert_binding(&mut self, name: String, binding: Binding) {
@@ -529,6 +2019,16 @@ impl Env {
         };
         let scope = self.scopes.get_mut(scope_idx).unwrap();
         let binding = scope.get_mut(&head.0).unwrap();
+        // a reference binding is copied or lent, never consumed; its fields are read
+        // through it
+        if let Some(reference @ (Value::Ref(place) | Value::MutRef(place))) = &binding.value {
+            if rest.is_empty() {
+                return Ok(reference.clone());
+            }
+            let mut place = place.clone();
+            place.fields.extend(rest.iter().map(|f| f.0.clone()));
+            return Ok(self.read_place(&place)?.clone());
+        }
 
         match mode {
             EvalMode::Move => {
@@ -537,213 +2037,1486 @@ impl Env {
                     .take()
                     .ok_or_else(|| RuntimeError::Moved(head.0.clone()))?;
                 for field in rest {
+                    if let Value::Ref(place) | Value::MutRef(place) = &val {
+                        val = self.read_place(place)?.clone();
+                    }
                     val = extract_field(val, &field.0)?;
                 }
                 Ok(val)
             }
             EvalMode::Copy | EvalMode::Borrow => {
-                let val = binding
-                    .value
-                    .as_ref()
-                    .ok_or_else(|| RuntimeError::Moved(head.0.clone()))?;
-                let mut out = val.clone();
-                for field in rest {
-                    out = extract_field(out, &field.0)?;
+                let place = Place {
+                    scope: scope_idx,
+                    name: head.0.clone(),
+                    fields: rest.iter().map(|f| f.0.clone()).collect(),
+                };
+                Ok(self.read_place(&place)?.clone())
+            }
+        }
+    }
+
+    /// The place `path` names. Through a reference binding this is the referee's place.
+    fn place_of(&self, path: &Path) -> Result<Place, RuntimeError> {
+        let (head, rest) = path
+            .0
+            .split_first()
+            .ok_or_else(|| RuntimeError::UnknownIdent("".into()))?;
+        let (scope, binding) = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, s)| s.get(&head.0).map(|b| (i, b)))
+            .ok_or_else(|| RuntimeError::UnknownIdent(head.0.clone()))?;
+        let rest = rest.iter().map(|f| f.0.clone());
+        match &binding.value {
+            None => Err(RuntimeError::Moved(head.0.clone())),
+            Some(Value::Ref(place) | Value::MutRef(place)) => {
+                let mut place = place.clone();
+                place.fields.extend(rest);
+                Ok(place)
+            }
+            Some(_) => Ok(Place {
+                scope,
+                name: head.0.clone(),
+                fields: rest.collect(),
+            }),
+        }
+    }
+
+    /// The value at `place`, following the references stored in record fields on the
+    /// way; a reference at the end of the path is returned as it is.
+    fn read_place(&self, place: &Place) -> Result<&Value, RuntimeError> {
+        let mut value = self
+            .scopes
+            .get(place.scope)
+            .and_then(|s| s.get(&place.name))
+            .and_then(|b| b.value.as_ref())
+            .ok_or_else(|| RuntimeError::Moved(place.name.clone()))?;
+        for (i, field) in place.fields.iter().enumerate() {
+            value = match value {
+                Value::Record(m) => m
+                    .get(field)
+                    .ok_or_else(|| RuntimeError::FieldNotFound(field.clone()))?,
+                Value::Ref(inner) | Value::MutRef(inner) => {
+                    let mut through = inner.clone();
+                    through.fields.extend_from_slice(&place.fields[i..]);
+                    return self.read_place(&through);
+                }
+                _ => return Err(RuntimeError::Type("field access on non-record".into())),
+            };
+        }
+        Ok(value)
+    }
+
+    /// `value`, or what it points at if it is a `&` reference.
+    fn read_through(&self, value: Value) -> Result<Value, RuntimeError> {
+        match value {
+            Value::Ref(place) => Ok(self.read_place(&place)?.clone()),
+            v => Ok(v),
+        }
+    }
+
+    /// `place` with the references stored along its path followed, so it names the
+    /// binding that holds the value.
+    fn resolve_place(&self, place: &Place) -> Result<Place, RuntimeError> {
+        let mut value = self
+            .scopes
+            .get(place.scope)
+            .and_then(|s| s.get(&place.name))
+            .and_then(|b| b.value.as_ref())
+            .ok_or_else(|| RuntimeError::Moved(place.name.clone()))?;
+        for (i, field) in place.fields.iter().enumerate() {
+            value = match value {
+                Value::Record(m) => m
+                    .get(field)
+                    .ok_or_else(|| RuntimeError::FieldNotFound(field.clone()))?,
+                Value::Ref(inner) | Value::MutRef(inner) => {
+                    let mut through = inner.clone();
+                    through.fields.extend_from_slice(&place.fields[i..]);
+                    return self.resolve_place(&through);
+                }
+                _ => return Err(RuntimeError::Type("field access on non-record".into())),
+            };
+        }
+        Ok(place.clone())
+    }
+
+    fn place_mut(&mut self, place: &Place) -> Result<&mut Value, RuntimeError> {
+        let place = &self.resolve_place(place)?;
+        let mut value = self
+            .scopes
+            .get_mut(place.scope)
+            .and_then(|s| s.get_mut(&place.name))
+            .and_then(|b| b.value.as_mut())
+            .ok_or_else(|| RuntimeError::Moved(place.name.clone()))?;
+        for field in &place.fields {
+            value = match value {
+                Value::Record(m) => m
+                    .get_mut(field)
+                    .ok_or_else(|| RuntimeError::FieldNotFound(field.clone()))?,
+                _ => return Err(RuntimeError::Type("field access on non-record".into())),
+            };
+        }
+        Ok(value)
+    }
+
+    fn assign_path(&mut self, path: &Path, value: Value) -> Result<(), RuntimeError> {
+        let (head, rest) = path
+            .0
+            .split_first()
+            .ok_or_else(|| RuntimeError::UnknownIdent("".into()))?;
+        let mut idx = self.scopes.len();
+        let mut binding_idx = None;
+        while idx > 0 {
+            idx -= 1;
+            if self.scopes[idx].contains_key(&head.0) {
+                binding_idx = Some(idx);
+                break;
+            }
+        }
+        let Some(scope_idx) = binding_idx else {
+            return Err(RuntimeError::UnknownIdent(head.0.clone()));
+        };
+        let scope = self.scopes.get_mut(scope_idx).unwrap();
+        let binding = scope.get_mut(&head.0).unwrap();
+        if let Some(Value::MutRef(place)) = &binding.value {
+            let mut place = place.clone();
+            place.fields.extend(rest.iter().map(|f| f.0.clone()));
+            *self.place_mut(&place)? = value;
+            return Ok(());
+        }
+        if !binding.mutable {
+            return Err(RuntimeError::NotMutable(head.0.clone()));
+        }
+        let Some(slot) = binding.value.as_mut() else {
+            return Err(RuntimeError::Moved(head.0.clone()));
+        };
+
+        if rest.is_empty() {
+            *slot = value;
+            return Ok(());
+        }
+
+        set_field(slot, rest, value)
+    }
+}
+
+fn extract_field(val: Value, field: &str) -> Result<Value, RuntimeError> {
+    match val {
+        Value::Record(mut m) => m
+            .shift_remove(field)
+            .ok_or_else(|| RuntimeError::FieldNotFound(field.into())),
+        _ => Err(RuntimeError::Type("field access on non-record".into())),
+    }
+}
+
+fn set_field(target: &mut Value, path: &[Ident], value: Value) -> Result<(), RuntimeError> {
+    if path.is_empty() {
+        *target = value;
+        return Ok(());
+    }
+    match target {
+        Value::Record(ref mut m) => {
+            let key = path[0].0.clone();
+            if path.len() == 1 {
+                if let Some(slot) = m.get_mut(&key) {
+                    *slot = value;
+                    Ok(())
+                } else {
+                    Err(RuntimeError::FieldNotFound(key))
                 }
-                Ok(out)
+            } else {
+                let next = m
+                    .get_mut(&key)
+                    .ok_or_else(|| RuntimeError::FieldNotFound(key.clone()))?;
+                set_field(next, &path[1..], value)
             }
         }
+        _ => Err(RuntimeError::Type(
+            "assignment into non-record field".into(),
+        )),
+    }
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.0
+        .iter()
+        .map(|i| i.0.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::LogLevel;
+    use std::time::Duration;
+
+    fn run(src: &str) -> Value {
+        let mut parser = Parser::new(src).unwrap();
+        let program = parser.parse_program().unwrap();
+        let mut interp = Interpreter::new(1024 * 1024);
+        interp.load_program(&program).unwrap();
+        interp.run_main().unwrap()
+    }
+
+    #[test]
+    fn calc_example() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = a + b
+
+        main() = {
+          x: i32 = 10
+          y: i32 = 20
+          add(x, y)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(30));
+    }
+
+    #[test]
+    fn record_ref_example() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+
+        length_x(p: &Point) -> i32 = p.x
+
+        main() = {
+          origin: Point = { x: 0, y: 0 }
+          length_x(&origin)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(0));
+    }
+
+    #[test]
+    fn inferred_return_function_eval() {
+        let src = r#"
+        id(x: i32) = x
+
+        main() = {
+          id(42)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(42));
+    }
+
+    #[test]
+    fn if_block_and_move() {
+        let src = r#"
+        main() = {
+          x: i32 = 1
+          y: i32 = if x < 0 then 10 else 5
+          y
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(5));
+    }
+
+    #[test]
+    fn builtin_file_io_roundtrip() {
+        let unique = format!(
+            "gaut_interp_{}_{}.txt",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        let path_buf = std::env::temp_dir().join(unique);
+        let path = path_buf
+            .to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('\"', "\\\"");
+
+        let src = format!(
+            r#"
+            main() = {{
+              _ok: bool = try_write_file("{path}", "hello")
+              res: ReadFileResult = try_read_file("{path}")
+              res.data
+            }}
+            "#
+        );
+        let v = run(&src);
+        assert_eq!(v, Value::Str("hello".into()));
+        let _ = std::fs::remove_file(path_buf);
+    }
+
+    #[test]
+    fn builtin_plain_file_io_and_string_access() {
+        let unique = format!(
+            "gaut_interp_plain_{}_{}.txt",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        let path_buf = std::env::temp_dir().join(unique);
+        let path = path_buf
+            .to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('\"', "\\\"");
+
+        let src = format!(
+            r#"
+            main() = {{
+              write_file("{path}", "gaut")
+              s: Str = read_file("{path}")
+              b: Bytes = bytes_from_str(copy s)
+              t: Str = bytes_to_str(b)
+              str_len(copy t) * 1000 + str_byte_at(t, 1)
+            }}
+            "#
+        );
+        let v = run(&src);
+        assert_eq!(v, Value::Int(4000 + i64::from(b'a')));
+        let _ = std::fs::remove_file(path_buf);
+    }
+
+    #[test]
+    fn builtin_str_slice() {
+        let src = r#"
+        main() = {
+          s: Str = "hello"
+          x: Str = str_slice(s, 1, 3)
+          x
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Str("ell".into()));
+    }
+
+    #[test]
+    fn builtin_log_levels_filter_and_format_records() {
+        let src = r#"
+        main() = {
+          log_info("starting")
+          log_warn("slow")
+          log_error("failed \"x\"")
+        }
+        "#;
+        let mut interp = Interpreter::from_source(src).unwrap();
+        let err = CaptureBuffer::default();
+        interp.set_stderr(err.clone());
+        // what GAUT_LOG_LEVEL=error GAUT_LOG_FORMAT=json configure
+        interp.set_logger(Logger {
+            min_level: LogLevel::Error,
+            json: true,
+        });
+        assert_eq!(interp.run_main().unwrap(), Value::Unit);
+        let text = err.text();
+        let record = text.strip_prefix("{\"ts\":").and_then(|r| {
+            r.strip_suffix(",\"level\":\"error\",\"message\":\"failed \\\"x\\\"\"}\n")
+        });
+        assert!(
+            record.is_some_and(|ts| !ts.is_empty() && ts.bytes().all(|b| b.is_ascii_digit())),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn print_and_log_output_go_to_the_configured_sinks() {
+        let src = r#"
+        main() = {
+          print("a")
+          println("b")
+          log_warn("careful")
+          log_info("hidden")
+        }
+        "#;
+        let mut interp = Interpreter::from_source(src).unwrap();
+        let (out, err) = (CaptureBuffer::default(), CaptureBuffer::default());
+        interp.set_stdout(out.clone());
+        interp.set_stderr(err.clone());
+        interp.set_logger(Logger {
+            min_level: LogLevel::Warn,
+            json: false,
+        });
+        interp.run_main().unwrap();
+        assert_eq!(out.text(), "ab\n");
+        assert_eq!(err.text(), "[warn] careful\n");
+        // capturing borrows the sink and gives it back
+        interp.capture_stdout();
+        interp.run_main().unwrap();
+        assert_eq!(interp.take_captured_stdout().as_deref(), Some("ab\n"));
+        interp.run_main().unwrap();
+        assert_eq!(out.text(), "ab\nab\n");
+    }
+
+    #[test]
+    fn builtin_config_get_reports_arena_cap() {
+        let src = r#"
+        main() = config_get("arena_cap")
+        "#;
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.set_config(Config::new(2048));
+        assert_eq!(interp.run_main().unwrap(), Value::Str("2048".into()));
+    }
+
+    #[test]
+    fn higher_order_functions() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = copy a + copy b
+        mul(a: i32, b: i32) -> i32 = copy a * copy b
+        apply(f: (i32, i32) -> i32, x: i32, y: i32) -> i32 = f(copy x, copy y)
+        main() = {
+          op: (i32, i32) -> i32 = mul
+          apply(add, 2, 3) + apply(op, 4, 5)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(25));
+    }
+
+    #[test]
+    fn method_call_syntax() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        length_x(p: &Point) -> i32 = p.x
+        scale(p: Point, k: i32) -> i32 = p.x * copy k
+        main() = {
+          p: Point = { x: 3, y: 4 }
+          a: i32 = p.length_x()
+          a + p.scale(2)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(9));
+    }
+
+    #[test]
+    fn method_receivers_see_through_aliases() {
+        // `bump` takes `&mut Counter` under another name, so `c.bump()` must pass `&mut c`
+        let src = r#"
+        type Counter = { n: i32 }
+        type CounterRef = &mut Counter
+        bump(c: CounterRef) = {
+          c.n = copy c.n + 1
+        }
+        main() = {
+          mut c: Counter = { n: 1 }
+          c.bump()
+          c.bump()
+          copy c.n
+        }
+        "#;
+        assert_eq!(run(src), Value::Int(3));
+    }
+
+    #[test]
+    fn trace_records_calls_and_writes() {
+        let src = r#"
+        add(a: i32, b: i32) -> i32 = a + b
+        main() = {
+          mut x: i32 = add(1, 2)
+          x = 7
+          x
+        }
+        "#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        interp.record_trace();
+        interp.run_main().unwrap();
+        let lines: Vec<String> = interp
+            .take_trace()
+            .unwrap()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "call main()",
+                "  call add(1, 2)",
+                "  return add = 3",
+                "  set x = 3",
+                "  set x = 7",
+                "return main = 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn trait_methods_dispatch_on_receiver_type() {
+        let src = r#"
+        type Point = { x: i32, y: i32 }
+        type Size = { w: i32 }
+        trait Area { area(&self) -> i32 }
+        impl Area for Point { area(&self) -> i32 = 0 }
+        impl Area for Size { area(&self) -> i32 = self.w * 5 }
+        main() = {
+          p: Point = { x: 3, y: 4 }
+          s: Size = { w: 5 }
+          p.area() + s.area()
+        }
+        "#;
+        assert_eq!(run(src), Value::Int(25));
+    }
+
+    #[test]
+    fn builtin_atomics_share_cell() {
+        let src = r#"
+        main() = {
+          counter: atomic_i64 = atomic_new(1)
+          alias: atomic_i64 = copy counter
+          prev: i64 = atomic_add(&alias, 4)
+          swapped: bool = atomic_cas(&counter, 5, 9)
+          missed: bool = atomic_cas(&counter, 5, 0)
+          atomic_load(&counter) + copy prev
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(10));
+    }
+
+    #[test]
+    fn comparison_operators() {
+        let src = r#"
+        main() = {
+          a: i32 = 3
+          b: i32 = 4
+          copy a > copy b || copy a >= copy b || !(copy a <= copy b) || !(copy a != copy b)
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Bool(false));
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let src = r#"
+        main() = {
+          a: i32 = 12
+          b: i32 = 10
+          out: i32 = (copy a & copy b) + (copy a | copy b) + (copy a ^ copy b) + (1 << 4) + (copy a >> 2)
+          out
+        }
+        "#;
+        let v = run(src);
+        assert_eq!(v, Value::Int(8 + 14 + 6 + 16 + 3));
+    }
+
+    #[test]
+    fn arithmetic_wraps_at_the_target_width() {
+        let src = "main() = 2147483647 + 1";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let run_with = |target| {
+            let mut interp = Interpreter::new(1024 * 1024);
+            interp.set_target(target);
+            interp.load_program(&program).unwrap();
+            interp.run_main().unwrap()
+        };
+        let wrap = Target {
+            overflow: Overflow::Wrap,
+            ..Target::default()
+        };
+        assert_eq!(run_with(wrap), Value::Int(i32::MIN as i64));
+        let wide = Target {
+            int_model: frontend::target::IntModel::I64,
+            ..Target::default()
+        };
+        assert_eq!(run_with(wide), Value::Int(2147483648));
+    }
+
+    #[test]
+    fn overflow_traps_by_default() {
+        let run_err = |src: &str| {
+            let program = Parser::new(src).unwrap().parse_program().unwrap();
+            let mut interp = Interpreter::new(1024);
+            interp.load_program(&program).unwrap();
+            interp.run_main().unwrap_err().to_string()
+        };
+        assert_eq!(
+            run_err("main() = 2147483647 + 1"),
+            "integer overflow: 2147483647 + 1"
+        );
+        assert_eq!(
+            run_err("main() = -(-2147483648)"),
+            "integer overflow: -(-2147483648)"
+        );
+        assert_eq!(run_err("main() = 7 / (3 - 3)"), "division by zero");
+        assert_eq!(
+            run_err("main() = 1 << (40 - 8)"),
+            "shift amount 32 out of range for 32-bit integers"
+        );
+        // a negative literal is MIN itself, not MIN negated
+        assert_eq!(run("main() = -2147483648 / 2"), Value::Int(-1073741824));
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_failing_expression() {
+        let src = "half(n: i32) -> i32 = n / 2\nmain() = {\n  z: i32 = 0\n  half(4) + 1 / z\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        assert!(interp.run_main().is_err());
+        let span = interp.error_span().expect("span");
+        assert_eq!((span.line, span.col), (4, 13));
+        assert_eq!(&src[span.start..span.end], "1 / z");
+    }
+
+    #[test]
+    fn mut_refs_write_through_to_the_caller() {
+        let src = r#"
+        type Counter = { hits: i32, total: i32 }
+        bump(c: &mut Counter, n: i32) = {
+          c.hits = copy c.hits + 1
+          c.total = copy c.total + n
+        }
+        reset(c: &mut Counter) = { c = { hits: 0, total: 100 } }
+        set(x: &mut i32, v: i32) = { x = v }
+        main() = {
+          mut c: Counter = { hits: 0, total: 0 }
+          bump(&mut c, 5)
+          c.bump(6)
+          mut d: Counter = { hits: 1, total: 2 }
+          reset(&mut d)
+          set(&mut d.hits, 3)
+          copy c.hits * 100 + copy c.total + copy d.total + copy d.hits * 1000
+        }
+        "#;
+        assert_eq!(run(src), Value::Int(200 + 11 + 100 + 3000));
+    }
+
+    #[test]
+    fn concatenations_spill_from_the_arena_to_the_heap() {
+        let program = |body: &str| {
+            Parser::new(&format!(
+                "pad(s: Str) -> i32 = str_len(s + \"0123456789\")\n\
+                 main() = {{\n{body}\n}}"
+            ))
+            .unwrap()
+            .parse_program()
+            .unwrap()
+        };
+        // every call has its own arena, freed when it returns
+        let mut interp = Interpreter::new(32);
+        interp
+            .load_program(&program(
+                "pad(\"abcdefghi\") + pad(\"abcdefghi\") + pad(\"x\")",
+            ))
+            .unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(49));
+        assert_eq!(
+            interp.memory_use(),
+            MemoryUse {
+                arena_bytes: 20 + 20 + 12,
+                heap_bytes: 0
+            }
+        );
+        // main's arena is `arena_cap` bytes; what does not fit goes to the heap
+        interp
+            .load_program(&program(
+                "a: Str = \"0123456789\" + \"0123456789\"\n  str_len(a + \"!\")",
+            ))
+            .unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(21));
+        assert_eq!(
+            interp.memory_use(),
+            MemoryUse {
+                arena_bytes: 21,
+                heap_bytes: 22
+            }
+        );
+        // doubling a string past every arena, as C does, is not an error
+        let src = "dbl(s: Str, n: i32) -> Str = if copy n == 0 then s else dbl(copy s + s, n - 1)\nmain() = str_len(dbl(\"ab\", 19))";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(2 << 19));
+        assert!(interp.memory_use().heap_bytes > FUNCTION_ARENA_CAP);
+    }
+
+    #[test]
+    fn profiling_counts_calls_per_function() {
+        let src = r#"
+        fib(n: i32) -> i32 = if copy n < 2 then copy n else fib(copy n - 1) + fib(copy n - 2)
+        main() = fib(10)
+        "#;
+        let mut interp = Interpreter::from_source(src).unwrap();
+        assert!(interp.profile_report().is_none());
+        interp.enable_profiling();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(55));
+        let report = interp.profile_report().unwrap();
+        let mut calls: Vec<(&str, u64)> = report
+            .funcs
+            .iter()
+            .map(|p| (p.func.as_str(), p.calls))
+            .collect();
+        calls.sort_unstable();
+        assert_eq!(calls, [("fib", 177), ("main", 1)]);
+        assert!(report.funcs[0].total >= report.funcs[1].total);
+    }
+
+    #[test]
+    fn shared_refs_see_their_referee() {
+        // unchecked, so the borrow rules that keep `p` from changing under `r` are off
+        let src = r#"
+        type P = { x: i32, y: i32 }
+        type H = { r: &P, n: i32 }
+        first(a: &P, b: &P) -> &P = a
+        main() = {
+          mut p: P = { x: 1, y: 2 }
+          q: P = { x: 30, y: 40 }
+          r: &P = first(&p, &q)
+          h: H = { r: &p, n: 100 }
+          p.x = 5
+          copy r.x * 1000 + copy h.r.x * 10 + copy h.r.y
+        }
+        "#;
+        assert_eq!(run(src), Value::Int(5000 + 50 + 2));
+    }
+
+    #[test]
+    fn spawned_threads_talk_over_channels() {
+        let src = r#"
+        global jobs: Chan = channel()
+        global replies: Chan = channel()
+        global hits: atomic_i64 = atomic_new(0)
+        echo() -> Unit = {
+          job: Str = recv(&jobs)
+          n: i64 = atomic_add(&hits, 1)
+          send(&replies, job + "!")
+        }
+        main() = {
+          spawn(echo)
+          spawn(echo)
+          send(&jobs, "a")
+          send(&jobs, "bc")
+          first: Str = recv(&replies)
+          second: Str = recv(&replies)
+          str_len(first) + str_len(second) + atomic_load(&hits) * 10
+        }
+        "#;
+        assert_eq!(run(src), Value::Int(2 + 3 + 20));
+    }
+
+    #[test]
+    fn spawned_threads_write_to_the_configured_sinks() {
+        let src = r#"
+        global done: Chan = channel()
+        work() -> Unit = {
+          println("from thread")
+          log_warn("thread warning")
+          send(&done, "")
+          1 / 0
+        }
+        main() = {
+          spawn(work)
+          recv(&done)
+        }
+        "#;
+        let mut interp = Interpreter::from_source(src).unwrap();
+        let err = CaptureBuffer::default();
+        interp.set_stderr(err.clone());
+        interp.capture_stdout();
+        interp.run_main().unwrap();
+        assert_eq!(
+            interp.take_captured_stdout().as_deref(),
+            Some("from thread\n")
+        );
+        // the thread fails after its send; wait for its error report
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !err.text().contains("error in thread work") && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let err = err.text();
+        assert!(err.contains("thread warning"), "{err}");
+        assert!(
+            err.contains("error in thread work: division by zero"),
+            "{err}"
+        );
     }
 
-    fn assign_path(&mut self, path: &Path, value: Value) -> Result<(), RuntimeError> {
-        let (head, rest) = path
-            .0
-            .split_first()
-            .ok_or_else(|| RuntimeError::UnknownIdent("".into()))?;
-        let mut idx = self.scopes.len();
-        let mut binding_idx = None;
-        while idx > 0 {
-            idx -= 1;
-            if self.scopes[idx].contains_key(&head.0) {
-                binding_idx = Some(idx);
-                break;
-            }
+    #[test]
+    fn record_spread_replaces_listed_fields() {
+        let src = r#"
+        type Pt = { x: i32, y: i32, z: i32 }
+        main() = {
+          p: Pt = { x: 1, y: 2, z: 3 }
+          q: Pt = { ..p, y: 20 }
+          copy q.x * 100 + copy q.y + copy q.z * 1000
         }
-        let Some(scope_idx) = binding_idx else {
-            return Err(RuntimeError::UnknownIdent(head.0.clone()));
+        "#;
+        assert_eq!(run(src), Value::Int(3120));
+    }
+
+    #[test]
+    fn derived_show_renders_records() {
+        let src = r#"
+        @derive(show)
+        type P = { x: i32, on: bool, tag: Str }
+        main() = {
+          p: P = { x: -3, on: false, tag: "t" }
+          P_show(&p)
+        }
+        "#;
+        assert_eq!(
+            run(src),
+            Value::Str("P { x: -3, on: false, tag: \"t\" }".into())
+        );
+    }
+
+    #[test]
+    fn reload_keeps_globals_and_swaps_functions() {
+        let v1 = Parser::new(
+            r#"
+            global base: i32 = 1
+            step(x: i32) -> i32 = x + 1
+            main() = step(copy base)
+            "#,
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let v2 = Parser::new(
+            r#"
+            global base: i32 = 100
+            global extra: i32 = 10
+            step(x: i32) -> i32 = x * 50
+            main() = step(copy base) + copy extra
+            "#,
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&v1).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(2));
+        interp.reload_program(&v2).unwrap();
+        // `base` keeps its old value; only `extra` is freshly initialized
+        assert_eq!(interp.run_main().unwrap(), Value::Int(60));
+    }
+
+    #[test]
+    fn host_builtins_are_callable_and_typechecked() {
+        use frontend::typecheck::TypeChecker;
+        let mut interp = Interpreter::new(1024);
+        let sig = Signature {
+            params: vec![Type::Named(Ident("Str".into()))],
+            ret: Type::Named(Ident("i32".into())),
         };
-        let scope = self.scopes.get_mut(scope_idx).unwrap();
-        let binding = scope.get_mut(&head.0).unwrap();
-        if !binding.mutable {
-            return Err(RuntimeError::NotMutable(head.0.clone()));
+        interp.register_builtin("host_len", sig, |args| match args {
+            [Value::Str(s)] => Ok(Value::Int(s.len() as i64)),
+            _ => Err(RuntimeError::Type("host_len expects a Str".into())),
+        });
+        let mut checker = TypeChecker::new();
+        for (name, sig) in interp.host_signatures() {
+            checker.register_signature(name, sig);
         }
-        let Some(slot) = binding.value.as_mut() else {
-            return Err(RuntimeError::Moved(head.0.clone()));
+        let program = |src: &str| Parser::new(src).unwrap().parse_program().unwrap();
+        let ok = program("main() -> i32 = host_len(\"gaut\") + 1");
+        assert!(checker.check_program(&ok).is_ok());
+        assert!(checker
+            .check_program(&program("main() -> i32 = host_len(3)"))
+            .is_err());
+        interp.load_program(&ok).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(5));
+        // a host function replaces the interpreter's own of that name, but not print
+        let sig = Signature {
+            params: vec![Type::Named(Ident("Str".into()))],
+            ret: Type::Named(Ident("i32".into())),
         };
+        interp.register_builtin("str_len", sig.clone(), |_| Ok(Value::Int(7)));
+        interp.register_builtin("print", sig, |_| Ok(Value::Int(7)));
+        assert!(interp.host_signatures().all(|(name, _)| name != "print"));
+        interp
+            .load_program(&program("main() -> i32 = str_len(\"gaut\")"))
+            .unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(7));
+    }
 
-        if rest.is_empty() {
-            *slot = value;
-            return Ok(());
-        }
+    #[test]
+    fn runtime_errors_come_with_the_active_calls() {
+        let src = "inner(n: i32) -> i32 = 10 / n\n\
+                   outer(n: i32) -> i32 = inner(n - 1) + 1\n\
+                   main() -> i32 = outer(1)\n";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        assert!(matches!(
+            interp.run_main(),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        let trace = interp.backtrace().unwrap();
+        let frames: Vec<(&str, u32)> = trace
+            .frames
+            .iter()
+            .map(|f| (f.func.as_str(), f.at.line))
+            .collect();
+        assert_eq!(frames, [("inner", 1), ("outer", 2), ("main", 3)]);
+        // a successful run leaves no trace behind
+        let mut ok = Interpreter::from_source("main() -> i32 = 1").unwrap();
+        ok.run_main().unwrap();
+        assert!(ok.backtrace().is_none());
+    }
 
-        set_field(slot, rest, value)
+    #[test]
+    fn recursion_past_the_call_depth_limit_is_an_error() {
+        let src = "down(n: i32) -> i32 = if copy n == 0 then 0 else down(n - 1) + 1\n\
+                   main() -> i32 = down(30)\n";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.set_max_call_depth(20);
+        assert!(matches!(
+            interp.run_main(),
+            Err(RuntimeError::StackOverflow(20))
+        ));
+        assert_eq!(interp.backtrace().unwrap().frames.len(), 20);
+        interp.set_max_call_depth(40);
+        assert_eq!(interp.run_main().unwrap(), Value::Int(30));
     }
-}
 
-fn extract_field(val: Value, field: &str) -> Result<Value, RuntimeError> {
-    match val {
-        Value::Record(mut m) => m
-            .shift_remove(field)
-            .ok_or_else(|| RuntimeError::FieldNotFound(field.into())),
-        _ => Err(RuntimeError::Type("field access on non-record".into())),
+    #[test]
+    fn fuel_and_deadlines_stop_long_runs() {
+        let src =
+            "fib(n: i32) -> i32 = if copy n < 2 then copy n else fib(copy n - 1) + fib(n - 2)\n\
+                   main() -> i32 = fib(15)\n";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.set_fuel(Some(500));
+        assert!(matches!(
+            interp.run_main(),
+            Err(RuntimeError::FuelExhausted)
+        ));
+        assert_eq!(interp.remaining_fuel(), Some(0));
+        interp.set_fuel(Some(1_000_000));
+        assert_eq!(interp.run_main().unwrap(), Value::Int(610));
+        assert!(interp.remaining_fuel().is_some_and(|left| left < 1_000_000));
+        interp.set_fuel(None);
+        interp.set_deadline(Some(Instant::now()));
+        assert!(matches!(
+            interp.run_main(),
+            Err(RuntimeError::FuelExhausted)
+        ));
+        interp.set_deadline(None);
+        assert_eq!(interp.run_main().unwrap(), Value::Int(610));
     }
-}
 
-fn set_field(target: &mut Value, path: &[Ident], value: Value) -> Result<(), RuntimeError> {
-    if path.is_empty() {
-        *target = value;
-        return Ok(());
+    #[test]
+    fn observers_see_statements_calls_and_writes() {
+        struct Log(Rc<std::cell::RefCell<Vec<String>>>);
+        impl ExecObserver for Log {
+            fn on_stmt(&mut self, depth: u32, span: Span) {
+                self.0.borrow_mut().push(format!("{depth} stmt {span}"));
+            }
+            fn on_call(&mut self, depth: u32, func: &str, args: &[Value]) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("{depth} call {func} {args:?}"));
+            }
+            fn on_return(&mut self, depth: u32, func: &str, value: &Value) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("{depth} return {func} {value}"));
+            }
+            fn on_write(&mut self, depth: u32, target: &str, value: &Value) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("{depth} write {target} {value}"));
+            }
+        }
+        let src = "inc(n: i32) -> i32 = n + 1\n\
+                   main() -> i32 = {\n  \
+                   mut x: i32 = inc(1)\n  \
+                   x = 5\n  \
+                   copy x\n\
+                   }\n";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        let log = Rc::new(std::cell::RefCell::new(Vec::new()));
+        interp.add_observer(Box::new(Log(log.clone())));
+        assert_eq!(interp.run_main().unwrap(), Value::Int(5));
+        assert_eq!(
+            *log.borrow(),
+            [
+                "0 call main []",
+                "1 stmt 3:3",
+                "1 call inc [Int(1)]",
+                "1 return inc 2",
+                "1 write x 2",
+                "1 stmt 4:3",
+                "1 write x 5",
+                "0 return main 5",
+            ]
+        );
     }
-    match target {
-        Value::Record(ref mut m) => {
-            let key = path[0].0.clone();
-            if path.len() == 1 {
-                if let Some(slot) = m.get_mut(&key) {
-                    *slot = value;
-                    Ok(())
+
+    #[test]
+    fn the_debugger_stops_at_breakpoints_and_steps() {
+        /// Answers each pause with the next scripted action and notes where it stopped.
+        struct Script {
+            actions: Vec<DebugAction>,
+            stops: Rc<std::cell::RefCell<Vec<String>>>,
+        }
+        impl Debugger for Script {
+            fn paused(&mut self, pause: &mut Pause<'_>) -> DebugAction {
+                let locals: Vec<String> = pause
+                    .locals()
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect();
+                self.stops.borrow_mut().push(format!(
+                    "{}:{} {} [{}]",
+                    pause.file,
+                    pause.span.line,
+                    pause.func,
+                    locals.join(" ")
+                ));
+                if self.actions.is_empty() {
+                    DebugAction::Continue
                 } else {
-                    Err(RuntimeError::FieldNotFound(key))
+                    self.actions.remove(0)
                 }
-            } else {
-                let next = m
-                    .get_mut(&key)
-                    .ok_or_else(|| RuntimeError::FieldNotFound(key.clone()))?;
-                set_field(next, &path[1..], value)
             }
         }
-        _ => Err(RuntimeError::Type(
-            "assignment into non-record field".into(),
-        )),
+        let src = "inc(n: i32) -> i32 = n + 1\n\
+                   main() -> i32 = {\n  \
+                   a: i32 = 1\n  \
+                   b: i32 = inc(copy a)\n  \
+                   c: i32 = inc(copy b)\n  \
+                   copy c\n\
+                   }\n";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        let stops = Rc::new(std::cell::RefCell::new(Vec::new()));
+        interp.set_debugger(
+            Box::new(Script {
+                actions: vec![DebugAction::Step, DebugAction::Step, DebugAction::Next],
+                stops: stops.clone(),
+            }),
+            DebugAction::Continue,
+        );
+        interp.set_entry_file("app.gaut");
+        interp.set_breakpoint("app.gaut", 4);
+        interp.set_breakpoint("other.gaut", 3);
+        assert_eq!(interp.run_main().unwrap(), Value::Int(3));
+        assert_eq!(
+            *stops.borrow(),
+            [
+                "app.gaut:4 main [a=1]",
+                "app.gaut:1 inc [n=1]",
+                "app.gaut:5 main [a=1 b=2]",
+                "app.gaut:6 main [a=1 b=2 c=3]",
+            ]
+        );
     }
-}
 
-fn path_to_string(path: &Path) -> String {
-    path.0
-        .iter()
-        .map(|i| i.0.as_str())
-        .collect::<Vec<_>>()
-        .join(".")
-}
+    #[test]
+    fn declarations_and_expressions_can_be_fed_one_at_a_time() {
+        let mut interp = Interpreter::new(1024);
+        interp.eval_decl("global mut count: i32 = 1").unwrap();
+        interp.eval_decl("macro twice(x) = x * 2").unwrap();
+        interp.eval_decl("bump(n: i32) -> i32 = n + 1").unwrap();
+        assert_eq!(
+            interp.eval_expr_str("bump(twice(copy count))").unwrap(),
+            Value::Int(3)
+        );
+        // assignments to globals persist, and a redefinition replaces the old function
+        interp.eval_expr_str("{ count = 20\n 0 }").unwrap();
+        interp.eval_decl("bump(n: i32) -> i32 = n + 100").unwrap();
+        assert_eq!(
+            interp.eval_expr_str("bump(copy count)").unwrap(),
+            Value::Int(120)
+        );
+        assert!(interp.eval_expr_str("bump(1) bump(2)").is_err());
+        assert!(interp.eval_expr_str("missing(1)").is_err());
+        // both are checked before anything runs or changes
+        let err = interp
+            .eval_expr_str("{ count = 5\n bump(\"x\") }")
+            .unwrap_err();
+        assert!(matches!(err, RuntimeError::Type(_)), "{err}");
+        assert!(interp.eval_decl("bump(n: i32) -> Str = n").is_err());
+        assert_eq!(interp.eval_expr_str("bump(0)").unwrap(), Value::Int(100));
+        assert_eq!(interp.eval_expr_str("copy count").unwrap(), Value::Int(20));
+        // a checked program's declarations are there to build on too
+        let program = Parser::new("macro twice(x) = x * 2\nhalf(n: i32) -> i32 = n / 2")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let typed = frontend::typecheck::TypeChecker::new()
+            .check_program(&program)
+            .unwrap();
+        interp.load_typed(&typed).unwrap();
+        assert_eq!(
+            interp.eval_expr_str("half(twice(21))").unwrap(),
+            Value::Int(21)
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn reload_rejects_changed_global_type() {
+        let v1 = Parser::new("global g: i32 = 1 main() = copy g")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let v2 = Parser::new("global g: Str = \"x\" main() = 0")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&v1).unwrap();
+        let err = interp.reload_program(&v2).unwrap_err();
+        assert_eq!(err, RuntimeError::IncompatibleGlobal("g".into()));
+        assert_eq!(interp.run_main().unwrap(), Value::Int(1));
+    }
 
-    fn run(src: &str) -> Value {
-        let mut parser = Parser::new(src).unwrap();
-        let program = parser.parse_program().unwrap();
-        let mut interp = Interpreter::new(1024 * 1024);
-        interp.load_program(&program).unwrap();
-        interp.run_main().unwrap()
+    #[test]
+    fn a_declared_println_is_called_like_any_function() {
+        let src = "println(n: i32) -> i32 = copy n * 2\nmain() -> i32 = println(21)";
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.capture_stdout();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(42));
+        assert_eq!(interp.take_captured_stdout(), Some(String::new()));
     }
 
     #[test]
-    fn calc_example() {
+    fn captured_stdout() {
         let src = r#"
-        add(a: i32, b: i32) -> i32 = a + b
-
         main() = {
-          x: i32 = 10
-          y: i32 = 20
-          add(x, y)
+          print("a")
+          println("b")
+          0
         }
         "#;
-        let v = run(src);
-        assert_eq!(v, Value::Int(30));
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.capture_stdout();
+        interp.run_main().unwrap();
+        assert_eq!(interp.take_captured_stdout(), Some("ab\n".to_string()));
     }
 
     #[test]
-    fn record_ref_example() {
+    fn drop_hooks_run_for_unmoved_bindings() {
         let src = r#"
-        type Point = { x: i32, y: i32 }
-
-        length_x(p: &Point) -> i32 = p.x
-
+        type Handle = { name: Str }
+        impl Drop for Handle {
+          drop(&self) -> Unit = { done: Str = println(self.name) }
+        }
+        consume(h: Handle) -> i32 = 1
         main() = {
-          origin: Point = { x: 0, y: 0 }
-          length_x(&origin)
+          a: Handle = { name: "a" }
+          b: Handle = { name: "b" }
+          c: Handle = { name: "c" }
+          n: i32 = consume(b)
+          n
         }
         "#;
-        let v = run(src);
-        assert_eq!(v, Value::Int(0));
+        let mut interp = Interpreter::from_source(src).unwrap();
+        interp.capture_stdout();
+        interp.run_main().unwrap();
+        // `b` is dropped by `consume`; `c` and `a` at the end of main, last first
+        assert_eq!(interp.take_captured_stdout(), Some("b\nc\na\n".to_string()));
     }
 
     #[test]
-    fn inferred_return_function_eval() {
+    fn value_display_uses_language_syntax() {
         let src = r#"
-        id(x: i32) = x
-
         main() = {
-          id(42)
+          p: { x: i32, name: Str } = { x: 0, name: "a\"b" }
+          p
         }
         "#;
         let v = run(src);
-        assert_eq!(v, Value::Int(42));
+        assert_eq!(v.to_string(), r#"{ x: 0, name: "a\"b" }"#);
+        assert_eq!(Value::Unit.to_string(), "()");
+        assert_eq!(Value::Bytes(b"hi\n".to_vec()).to_string(), r#"b"hi\n""#);
     }
 
     #[test]
-    fn if_block_and_move() {
+    fn value_to_json() {
+        let mut fields = IndexMap::new();
+        fields.insert("ok".to_string(), Value::Bool(true));
+        fields.insert("data".to_string(), Value::Str("line\n".into()));
+        fields.insert("raw".to_string(), Value::Bytes(vec![1, 2]));
+        fields.insert("none".to_string(), Value::Unit);
+        let v = Value::Record(fields);
+        assert_eq!(
+            v.to_json(),
+            r#"{"ok":true,"data":"line\n","raw":[1,2],"none":null}"#
+        );
+    }
+
+    #[test]
+    fn indexing_is_bounds_checked() {
         let src = r#"
+        second(s: &Str) -> i32 = s[1]
         main() = {
-          x: i32 = 1
-          y: i32 = if x < 0 then 10 else 5
-          y
+          s: Str = "AZ"
+          s[0] + second(&s)
         }
         "#;
-        let v = run(src);
-        assert_eq!(v, Value::Int(5));
+        assert_eq!(run(src), Value::Int(65 + 90));
+
+        let program = Parser::new("main() = {\n s: Str = \"x\"\n s[1]\n}")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        assert!(matches!(
+            interp.run_main(),
+            Err(RuntimeError::IndexOutOfBounds { index: 1, len: 1 })
+        ));
     }
 
     #[test]
-    fn builtin_file_io_roundtrip() {
-        let unique = format!(
-            "gaut_interp_{}_{}.txt",
-            std::process::id(),
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
+    fn derived_json_round_trips() {
+        let src = r#"
+        @derive(json)
+        type Pt = { x: i32, y: i32 }
+        @derive(json)
+        type Tag = { label: Str, on: bool, at: Pt }
+        main() = {
+          t: Tag = { label: "a\"b", on: true, at: { x: 1, y: -2 } }
+          text: Str = Tag_to_json(&t)
+          back: Tag = Tag_from_json(copy text)
+          if copy back.on then text + back.label else ""
+        }
+        "#;
+        assert_eq!(
+            run(src),
+            Value::Str(r#"{"label":"a\"b","on":true,"at":{"x":1,"y":-2}}a"b"#.into())
         );
-        let path_buf = std::env::temp_dir().join(unique);
-        let path = path_buf
-            .to_string_lossy()
-            .replace('\\', "\\\\")
-            .replace('\"', "\\\"");
-
-        let src = format!(
-            r#"
-            main() = {{
-              _ok: bool = try_write_file("{path}", "hello")
-              res: ReadFileResult = try_read_file("{path}")
-              res.data
-            }}
-            "#
+        assert_eq!(
+            json_field(r#" {"a": [1, "}"], "b" : {"c": 2} }"#, "b"),
+            Ok(r#"{"c": 2}"#)
         );
-        let v = run(&src);
-        assert_eq!(v, Value::Str("hello".into()));
-        let _ = std::fs::remove_file(path_buf);
+        assert_eq!(
+            json_field(r#"{"a": 1}"#, "b"),
+            Err(RuntimeError::Json("missing field \"b\"".into()))
+        );
+        assert_eq!(json_unquote(r#""\u00e9\n""#).as_deref(), Some("é\n"));
     }
 
     #[test]
-    fn builtin_str_slice() {
+    fn slices_copy_a_checked_range() {
         let src = r#"
         main() = {
-          s: Str = "hello"
-          x: Str = str_slice(s, 1, 3)
-          x
+          s: Str = "hello world"
+          s[..5] + "/" + s[6..] + "/" + s[4..7] + "/" + s[3..3]
         }
         "#;
-        let v = run(src);
-        assert_eq!(v, Value::Str("ell".into()));
+        assert_eq!(run(src), Value::Str("hello/world/o w/".into()));
+
+        for (range, start, end) in [("2..1", 2, 1), ("0..4", 0, 4), ("-1..", -1, 3)] {
+            let src = format!("main() = {{\n s: Str = \"abc\"\n s[{range}]\n}}");
+            let program = Parser::new(&src).unwrap().parse_program().unwrap();
+            let mut interp = Interpreter::new(1024);
+            interp.load_program(&program).unwrap();
+            assert_eq!(
+                interp.run_main(),
+                Err(RuntimeError::SliceOutOfBounds { start, end, len: 3 })
+            );
+        }
+    }
+
+    #[test]
+    fn args_come_from_set_args() {
+        let program = Parser::new(
+            "main() = str_len(arg_at(1)) * 10 + arg_count() + str_len(arg_at(9)) + str_len(arg_at(-1))",
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.set_args(vec!["prog".into(), "abc".into()]);
+        interp.load_program(&program).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(32));
+    }
+
+    #[test]
+    fn run_main_with_args_keeps_the_program_path_first() {
+        let program = Parser::new("main() = bytes_to_str(args())")
+            .unwrap()
+            .parse_program()
+            .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.set_args(vec!["prog.gaut".into(), "stale".into()]);
+        interp.load_program(&program).unwrap();
+        let run = interp.run_main_with_args(vec!["-v".into(), "x y".into()]);
+        assert_eq!(run.unwrap(), Value::Str("prog.gaut\n-v\nx y".into()));
+        let run = interp.run_main_with_args(Vec::new());
+        assert_eq!(run.unwrap(), Value::Str("prog.gaut".into()));
+    }
+
+    #[test]
+    fn time_block_runs_its_body_once() {
+        let program = Parser::new(
+            "main() = {\n  mut n: i32 = 0\n  ms: i32 = time {\n    n = copy n + 1\n  }\n  if ms >= 0 then copy n else 9\n}",
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(1));
+    }
+
+    #[test]
+    fn dyn_parse_keeps_non_strings_as_json() {
+        let program = Parser::new(
+            r#"main() = {
+              d: Dyn = dyn_parse("{\"n\": [1, 2], \"s\": \"a\\\"b\"}")
+              dyn_set(&d, "extra", "x")
+              d
+            }"#,
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        let Value::Dyn(d) = interp.run_main().unwrap() else {
+            panic!("expected a Dyn");
+        };
+        assert_eq!(d.get("n").as_deref(), Some("[1, 2]"));
+        assert_eq!(d.get("s").as_deref(), Some("a\"b"));
+        assert_eq!(d.entries().len(), 3);
+    }
+
+    #[test]
+    fn block_buffering_holds_prints_until_flush() {
+        let program = Parser::new(
+            "main() = {\n  set_print_buffer(\"block\")\n  print(\"\")\n  flush()\n  print(\"\")\n  0\n}",
+        )
+        .unwrap()
+        .parse_program()
+        .unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        interp.config.print_buffer = PrintBuffer::Block;
+        interp.write_stdout("x");
+        assert_eq!(interp.pending_stdout, b"x");
+        interp.config.print_buffer = PrintBuffer::Line;
+        interp.run_main().unwrap();
+        assert!(interp.pending_stdout.is_empty());
+        assert_eq!(interp.config.print_buffer, PrintBuffer::Block);
+    }
+
+    #[test]
+    fn str_to_int_respects_the_int_model() {
+        let src = "main() = {\n  r: StrToIntResult = str_to_int(\"3000000000\")\n  r\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let run = |model| {
+            let mut interp = Interpreter::new(1024);
+            interp.set_target(Target {
+                int_model: model,
+                ..Target::default()
+            });
+            interp.load_program(&program).unwrap();
+            interp.run_main().unwrap().to_string()
+        };
+        assert_eq!(
+            run(frontend::target::IntModel::I32),
+            "{ ok: false, value: 0 }"
+        );
+        assert_eq!(
+            run(frontend::target::IntModel::I64),
+            "{ ok: true, value: 3000000000 }"
+        );
+    }
+
+    #[test]
+    fn bytes_push_writes_through_a_reborrow() {
+        let src = "fill(b: &mut Bytes, n: i32) -> Unit = {\n  bytes_push(b, copy n)\n  bytes_push(b, 300)\n}\nmain() = {\n  mut b: Bytes = bytes_from_str(\"a\")\n  fill(&mut b, 98)\n  bytes_at(copy b, 1) * 1000 + bytes_at(copy b, 2) + bytes_len(b) * 0\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        assert_eq!(
+            interp.run_main().unwrap(),
+            Value::Int(98 * 1000 + 300 % 256)
+        );
+    }
+
+    #[test]
+    fn overflow_builtins_use_the_int_model_bounds() {
+        let src = "main() = {\n  s: i64 = saturating_mul(3000000000, 4000000000)\n  c: CheckedInt = checked_sub(copy s, -1)\n  { w: wrapping_add(copy s, 1), sub: c, s: s }\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.set_target(Target {
+            int_model: frontend::target::IntModel::I64,
+            ..Target::default()
+        });
+        interp.load_program(&program).unwrap();
+        assert_eq!(
+            interp.run_main().unwrap().to_string(),
+            format!(
+                "{{ w: {}, sub: {{ ok: false, value: 0 }}, s: {} }}",
+                i64::MIN,
+                i64::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn exit_stops_the_run_with_its_code() {
+        let src = "stop(code: i32) -> i32 = {\n  exit(copy code)\n  1\n}\nmain() = {\n  set_print_buffer(\"block\")\n  print(\"before\")\n  stop(7)\n  println(\"after\")\n}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.load_program(&program).unwrap();
+        interp.capture_stdout();
+        assert!(matches!(interp.run_main(), Err(RuntimeError::Exit(7))));
+        assert_eq!(interp.take_captured_stdout().as_deref(), Some("before"));
+    }
+
+    #[test]
+    fn read_line_keeps_newlines_and_read_stdin_drains() {
+        let src = "main() = {
+  a: Str = read_line()
+  b: Str = read_line()
+  rest: Bytes = read_stdin()
+  c: Str = read_line()
+  str_len(a) * 1000 + str_len(b) * 100 + str_len(bytes_to_str(rest)) * 10 + str_len(c)
+}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.set_stdin(io::Cursor::new(b"hi\nthere\nxyz".to_vec()));
+        interp.load_program(&program).unwrap();
+        assert_eq!(interp.run_main().unwrap(), Value::Int(3630));
+    }
+
+    #[test]
+    fn sleep_ms_advances_now_millis() {
+        let src = "main() = {
+  start: i64 = now_millis()
+  sleep_ms(20)
+  sleep_ms(-1)
+  now_millis() - copy start
+}";
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024);
+        interp.set_target(Target {
+            int_model: frontend::target::IntModel::I64,
+            ..Target::default()
+        });
+        interp.load_program(&program).unwrap();
+        let Value::Int(waited) = interp.run_main().unwrap() else {
+            panic!("expected an int");
+        };
+        assert!((20..10_000).contains(&waited), "{waited}");
+    }
+
+    #[test]
+    fn tcp_builtins_talk_over_loopback() {
+        // connect completes against the listen backlog, so one thread plays both ends
+        let src = r#"main() = {
+  server: TcpListener = tcp_listen(0)
+  client: TcpConn = tcp_connect("127.0.0.1", tcp_port(&server))
+  conn: TcpConn = tcp_accept(&server)
+  sent: bool = tcp_write(&client, bytes_from_str("ping"))
+  got: Bytes = tcp_read(&conn)
+  tcp_close(&conn)
+  rest: Bytes = tcp_read(&client)
+  bytes_to_str(got) + int_to_str(bytes_len(rest))
+}"#;
+        let program = Parser::new(src).unwrap().parse_program().unwrap();
+        let mut interp = Interpreter::new(1024 * 1024);
+        interp.load_program(&program).unwrap();
+        match interp.run_main() {
+            Ok(v) => assert_eq!(v, Value::Str("ping0".into())),
+            // sandboxes without sockets
+            Err(RuntimeError::Net(e)) if e.contains("denied") => {}
+            Err(e) => panic!("{e}"),
+        }
     }
 }
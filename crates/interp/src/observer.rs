@@ -0,0 +1,26 @@
+#![forbid(unsafe_code)]
+
+use crate::Value;
+use frontend::ast::Span;
+
+/// Callbacks from the evaluator, for tools such as tracers, coverage and debuggers.
+/// Install one with [`Interpreter::add_observer`](crate::Interpreter::add_observer);
+/// every method does nothing unless overridden. `depth` is the number of calls active
+/// around the event, 0 inside global initializers.
+///
+/// Observers see values but cannot change them. To read results back after a run,
+/// keep them behind an `Rc<RefCell<_>>` shared with the host.
+pub trait ExecObserver {
+    /// A statement is about to run.
+    fn on_stmt(&mut self, _depth: u32, _span: Span) {}
+
+    /// `func` is called with `args`, which are already evaluated.
+    fn on_call(&mut self, _depth: u32, _func: &str, _args: &[Value]) {}
+
+    /// `func` returns `value`.
+    fn on_return(&mut self, _depth: u32, _func: &str, _value: &Value) {}
+
+    /// A binding is declared or a place is assigned; `target` is the written path,
+    /// such as `p.x`.
+    fn on_write(&mut self, _depth: u32, _target: &str, _value: &Value) {}
+}
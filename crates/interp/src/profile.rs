@@ -0,0 +1,122 @@
+#![forbid(unsafe_code)]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How often one function was called and how long it ran. `total` is wall-clock time
+/// from entry to return, including the functions it called; a recursive call made
+/// while the function is already running adds to `calls` but not to `total`, so no
+/// time is counted twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncProfile {
+    pub func: String,
+    pub calls: u64,
+    pub total: Duration,
+}
+
+/// What the profiler measured, the function with the most time first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileReport {
+    pub funcs: Vec<FuncProfile>,
+}
+
+impl fmt::Display for ProfileReport {
+    /// A table with one row per function: name, calls and total milliseconds.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .funcs
+            .iter()
+            .map(|p| p.func.len())
+            .chain(["function".len()])
+            .max()
+            .unwrap_or_default();
+        write!(
+            f,
+            "{:<width$} {:>10} {:>12}",
+            "function", "calls", "total ms"
+        )?;
+        for p in &self.funcs {
+            let millis = p.total.as_secs_f64() * 1000.0;
+            write!(f, "\n{:<width$} {:>10} {millis:>12.3}", p.func, p.calls)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    calls: u64,
+    total: Duration,
+    active: u32, // calls of the function currently running
+}
+
+/// Per-function counters, updated by the interpreter around every call.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    funcs: HashMap<String, Entry>,
+}
+
+impl Profiler {
+    /// `func` was entered; pass the returned instant to `exit` when it returns.
+    pub(crate) fn enter(&mut self, func: &str) -> Instant {
+        let entry = self.funcs.entry(func.to_string()).or_default();
+        entry.calls += 1;
+        entry.active += 1;
+        Instant::now()
+    }
+
+    /// `func`, entered at `start`, returned or failed.
+    pub(crate) fn exit(&mut self, func: &str, start: Instant) {
+        if let Some(entry) = self.funcs.get_mut(func) {
+            entry.active = entry.active.saturating_sub(1);
+            if entry.active == 0 {
+                entry.total += start.elapsed();
+            }
+        }
+    }
+
+    pub(crate) fn report(&self) -> ProfileReport {
+        let mut funcs: Vec<FuncProfile> = self
+            .funcs
+            .iter()
+            .map(|(func, e)| FuncProfile {
+                func: func.clone(),
+                calls: e.calls,
+                total: e.total,
+            })
+            .collect();
+        funcs.sort_by(|a, b| {
+            b.total
+                .cmp(&a.total)
+                .then(b.calls.cmp(&a.calls))
+                .then_with(|| a.func.cmp(&b.func))
+        });
+        ProfileReport { funcs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recursive_calls_count_their_time_once() {
+        let mut profiler = Profiler::default();
+        let outer = profiler.enter("walk");
+        let inner = profiler.enter("walk");
+        std::thread::sleep(Duration::from_millis(5));
+        profiler.exit("walk", inner);
+        let inner_only = profiler.report().funcs[0].total;
+        profiler.exit("walk", outer);
+        let report = profiler.report();
+        assert_eq!(inner_only, Duration::ZERO);
+        assert_eq!(report.funcs[0].calls, 2);
+        assert!(report.funcs[0].total >= Duration::from_millis(5));
+        let table = report.to_string();
+        assert!(
+            table.starts_with("function      calls     total ms\nwalk "),
+            "{table}"
+        );
+    }
+}
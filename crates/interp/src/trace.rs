@@ -0,0 +1,183 @@
+#![forbid(unsafe_code)]
+
+use std::fmt;
+use thiserror::Error;
+
+const MAGIC: &[u8; 6] = b"GTRC1\n";
+
+/// One step recorded by the interpreter in trace mode. Values are kept in their
+/// rendered form so a trace can be replayed without the program that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    Call {
+        depth: u32,
+        func: String,
+        args: Vec<String>,
+    },
+    Return {
+        depth: u32,
+        func: String,
+        value: String,
+    },
+    /// A `let`-style binding or an assignment; `target` is the written path (`p.x`).
+    Write {
+        depth: u32,
+        target: String,
+        value: String,
+    },
+}
+
+impl TraceEvent {
+    pub fn depth(&self) -> u32 {
+        match self {
+            TraceEvent::Call { depth, .. }
+            | TraceEvent::Return { depth, .. }
+            | TraceEvent::Write { depth, .. } => *depth,
+        }
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = "  ".repeat(self.depth() as usize);
+        match self {
+            TraceEvent::Call { func, args, .. } => {
+                write!(f, "{indent}call {func}({})", args.join(", "))
+            }
+            TraceEvent::Return { func, value, .. } => write!(f, "{indent}return {func} = {value}"),
+            TraceEvent::Write { target, value, .. } => write!(f, "{indent}set {target} = {value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TraceError {
+    #[error("not a gaut trace file")]
+    BadMagic,
+    #[error("trace file is truncated")]
+    Truncated,
+    #[error("unknown trace event tag {0}")]
+    UnknownTag(u8),
+}
+
+/// Encode events as `GTRC1\n` followed by `tag u8, depth u32, strings...`, where every
+/// integer is little-endian and every string is a `u32` length plus UTF-8 bytes.
+pub fn encode(events: &[TraceEvent]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    for event in events {
+        match event {
+            TraceEvent::Call { depth, func, args } => {
+                out.push(0);
+                out.extend(depth.to_le_bytes());
+                put_str(&mut out, func);
+                out.extend((args.len() as u32).to_le_bytes());
+                for a in args {
+                    put_str(&mut out, a);
+                }
+            }
+            TraceEvent::Return { depth, func, value } => {
+                out.push(1);
+                out.extend(depth.to_le_bytes());
+                put_str(&mut out, func);
+                put_str(&mut out, value);
+            }
+            TraceEvent::Write {
+                depth,
+                target,
+                value,
+            } => {
+                out.push(2);
+                out.extend(depth.to_le_bytes());
+                put_str(&mut out, target);
+                put_str(&mut out, value);
+            }
+        }
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<TraceEvent>, TraceError> {
+    let mut rest = bytes.strip_prefix(MAGIC).ok_or(TraceError::BadMagic)?;
+    let mut events = Vec::new();
+    while let Some((&tag, tail)) = rest.split_first() {
+        rest = tail;
+        let depth = take_u32(&mut rest)?;
+        let event = match tag {
+            0 => {
+                let func = take_str(&mut rest)?;
+                let count = take_u32(&mut rest)?;
+                let args = (0..count)
+                    .map(|_| take_str(&mut rest))
+                    .collect::<Result<_, _>>()?;
+                TraceEvent::Call { depth, func, args }
+            }
+            1 => TraceEvent::Return {
+                depth,
+                func: take_str(&mut rest)?,
+                value: take_str(&mut rest)?,
+            },
+            2 => TraceEvent::Write {
+                depth,
+                target: take_str(&mut rest)?,
+                value: take_str(&mut rest)?,
+            },
+            other => return Err(TraceError::UnknownTag(other)),
+        };
+        events.push(event);
+    }
+    Ok(events)
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as u32).to_le_bytes());
+    out.extend(s.as_bytes());
+}
+
+fn take_u32(rest: &mut &[u8]) -> Result<u32, TraceError> {
+    let (head, tail) = rest.split_first_chunk::<4>().ok_or(TraceError::Truncated)?;
+    *rest = tail;
+    Ok(u32::from_le_bytes(*head))
+}
+
+fn take_str(rest: &mut &[u8]) -> Result<String, TraceError> {
+    let len = take_u32(rest)? as usize;
+    if rest.len() < len {
+        return Err(TraceError::Truncated);
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(String::from_utf8_lossy(head).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let events = vec![
+            TraceEvent::Call {
+                depth: 0,
+                func: "add".into(),
+                args: vec!["1".into(), "2".into()],
+            },
+            TraceEvent::Write {
+                depth: 1,
+                target: "p.x".into(),
+                value: "\"hé\"".into(),
+            },
+            TraceEvent::Return {
+                depth: 0,
+                func: "add".into(),
+                value: "3".into(),
+            },
+        ];
+        let bytes = encode(&events);
+        assert_eq!(decode(&bytes).unwrap(), events);
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(TraceError::Truncated)
+        );
+        assert_eq!(decode(b"nope"), Err(TraceError::BadMagic));
+    }
+}
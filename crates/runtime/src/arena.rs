@@ -47,6 +47,17 @@ impl Arena {
         Ok(&mut self.buf[start..end])
     }
 
+    /// Bytes allocated so far; pass it to `release` to free everything allocated after.
+    pub fn used(&self) -> usize {
+        self.off
+    }
+
+    /// Free the allocations made since `used()` returned `mark`, like
+    /// `gaut_scope_leave` in the C runtime.
+    pub fn release(&mut self, mark: usize) {
+        self.off = self.off.min(mark);
+    }
+
     /// Reset the arena to an empty state; data remains but is considered invalid.
     pub fn reset(&mut self) {
         self.off = 0;
@@ -71,6 +82,19 @@ mod tests {
         assert_eq!(slice2.len(), 16);
     }
 
+    #[test]
+    fn release_frees_back_to_a_mark() {
+        let mut arena = Arena::with_capacity(16);
+        arena.alloc(4).expect("alloc 4");
+        let mark = arena.used();
+        arena.alloc(10).expect("alloc 10");
+        arena.release(mark);
+        assert_eq!(arena.remaining(), 12);
+        // a mark past the current offset frees nothing
+        arena.release(15);
+        assert_eq!(arena.used(), 4);
+    }
+
     #[test]
     fn overflow_errors() {
         let mut arena = Arena::with_capacity(4);
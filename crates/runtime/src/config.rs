@@ -0,0 +1,107 @@
+#![forbid(unsafe_code)]
+
+use crate::log::Logger;
+use std::str::FromStr;
+
+/// When print/println output reaches the process's stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrintBuffer {
+    /// Flushed at the end of every print/println call.
+    #[default]
+    Line,
+    /// Held until the buffer fills, `flush()` is called or the program ends.
+    Block,
+}
+
+impl PrintBuffer {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PrintBuffer::Line => "line",
+            PrintBuffer::Block => "block",
+        }
+    }
+}
+
+impl FromStr for PrintBuffer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(PrintBuffer::Line),
+            "block" => Ok(PrintBuffer::Block),
+            other => Err(format!(
+                "unknown print buffering '{other}' (expected line or block)"
+            )),
+        }
+    }
+}
+
+/// Process-level settings read from `GAUT_*` environment variables at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub arena_cap: usize,
+    pub logger: Logger,
+    pub print_buffer: PrintBuffer,
+}
+
+impl Config {
+    /// Defaults used when no environment overrides are present.
+    pub fn new(arena_cap: usize) -> Self {
+        Self {
+            arena_cap,
+            logger: Logger::default(),
+            print_buffer: PrintBuffer::default(),
+        }
+    }
+
+    /// Read `GAUT_ARENA_CAP`, `GAUT_LOG_LEVEL`, `GAUT_LOG_FORMAT` and `GAUT_PRINT_BUFFER`,
+    /// matching the C runtime.
+    /// Unparsable values keep their defaults.
+    pub fn from_env(default_arena_cap: usize) -> Self {
+        let arena_cap = std::env::var("GAUT_ARENA_CAP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&cap| cap > 0)
+            .unwrap_or(default_arena_cap);
+        Self {
+            arena_cap,
+            logger: Logger::from_env(),
+            print_buffer: std::env::var("GAUT_PRINT_BUFFER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Look up a setting by key for the `config_get` builtin. Known keys report the
+    /// effective value; any other key reads `GAUT_<KEY>` and is empty when unset.
+    pub fn get(&self, key: &str) -> String {
+        match key {
+            "arena_cap" => self.arena_cap.to_string(),
+            "log_level" => self.logger.min_level.as_str().to_string(),
+            "log_format" => if self.logger.json { "json" } else { "text" }.to_string(),
+            "print_buffer" => self.print_buffer.as_str().to_string(),
+            other => {
+                std::env::var(format!("GAUT_{}", other.to_ascii_uppercase())).unwrap_or_default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::LogLevel;
+
+    #[test]
+    fn known_keys_report_effective_values() {
+        let mut config = Config::new(4096);
+        config.logger.min_level = LogLevel::Warn;
+        assert_eq!(config.get("arena_cap"), "4096");
+        assert_eq!(config.get("log_level"), "warn");
+        assert_eq!(config.get("log_format"), "text");
+        assert_eq!(config.get("print_buffer"), "line");
+        assert_eq!("block".parse(), Ok(PrintBuffer::Block));
+        assert_eq!(config.get("surely_not_set_anywhere"), "");
+    }
+}
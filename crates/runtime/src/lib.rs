@@ -1,7 +1,13 @@
 #![forbid(unsafe_code)]
 
 pub mod arena;
+pub mod config;
+pub mod log;
 pub mod net;
+pub mod rand;
 
 pub use arena::{Arena, ArenaError};
+pub use config::{Config, PrintBuffer};
+pub use log::{LogLevel, Logger};
 pub use net::{Conn, Listener};
+pub use rand::Rng;
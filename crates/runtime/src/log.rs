@@ -0,0 +1,141 @@
+#![forbid(unsafe_code)]
+
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Severity of a log record, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            other => Err(format!(
+                "unknown log level '{other}' (expected info, warn or error)"
+            )),
+        }
+    }
+}
+
+/// Level-filtered logger writing one record per line to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Logger {
+    pub min_level: LogLevel,
+    pub json: bool,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self {
+            min_level: LogLevel::Info,
+            json: false,
+        }
+    }
+}
+
+impl Logger {
+    /// Read `GAUT_LOG_LEVEL` and `GAUT_LOG_FORMAT=json`, matching the C runtime.
+    /// Unknown levels fall back to info.
+    pub fn from_env() -> Self {
+        let min_level = std::env::var("GAUT_LOG_LEVEL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(LogLevel::Info);
+        let json = std::env::var("GAUT_LOG_FORMAT").is_ok_and(|v| v == "json");
+        Self { min_level, json }
+    }
+
+    /// Format a record, or `None` when it is below the minimum level.
+    pub fn format(&self, level: LogLevel, msg: &str, ts_ms: u128) -> Option<String> {
+        if level < self.min_level {
+            return None;
+        }
+        if self.json {
+            let mut out = format!(
+                "{{\"ts\":{ts_ms},\"level\":\"{}\",\"message\":\"",
+                level.as_str()
+            );
+            for c in msg.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push_str("\"}");
+            Some(out)
+        } else {
+            Some(format!("[{}] {msg}", level.as_str()))
+        }
+    }
+
+    /// Write a record to stderr if it passes the level filter.
+    pub fn log(&self, level: LogLevel, msg: &str) {
+        self.log_to(&mut std::io::stderr().lock(), level, msg);
+    }
+
+    /// [`Logger::log`] to another sink, such as an embedder's stderr.
+    pub fn log_to(&self, out: &mut dyn Write, level: LogLevel, msg: &str) {
+        let ts_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        if let Some(line) = self.format(level, msg, ts_ms) {
+            writeln!(out, "{line}").ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_below_min_level() {
+        let logger = Logger {
+            min_level: LogLevel::Warn,
+            json: false,
+        };
+        assert_eq!(logger.format(LogLevel::Info, "skip", 0), None);
+        assert_eq!(
+            logger.format(LogLevel::Error, "boom", 0).as_deref(),
+            Some("[error] boom")
+        );
+    }
+
+    #[test]
+    fn json_records_escape_message() {
+        let logger = Logger {
+            min_level: LogLevel::Info,
+            json: true,
+        };
+        assert_eq!(
+            logger.format(LogLevel::Warn, "say \"hi\"", 42).as_deref(),
+            Some(r#"{"ts":42,"level":"warn","message":"say \"hi\""}"#)
+        );
+    }
+}
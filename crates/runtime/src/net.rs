@@ -1,7 +1,7 @@
 #![forbid(unsafe_code)]
 
 use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
 
 /// Thin TCP listener wrapper.
 #[derive(Debug)]
@@ -22,6 +22,11 @@ impl Listener {
         })
     }
 
+    /// The bound port, e.g. the one the OS picked for port 0.
+    pub fn local_port(&self) -> std::io::Result<u16> {
+        Ok(self.inner.local_addr()?.port())
+    }
+
     pub fn accept(&self) -> std::io::Result<Conn> {
         let (stream, _) = self.inner.accept()?;
         stream.set_nodelay(true).ok();
@@ -30,6 +35,12 @@ impl Listener {
 }
 
 impl Conn {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true).ok();
+        Ok(Self { inner: stream })
+    }
+
     pub fn read(&mut self) -> std::io::Result<Vec<u8>> {
         let mut buf = vec![0u8; 4096];
         let n = self.inner.read(&mut buf)?;
@@ -40,6 +51,11 @@ impl Conn {
     pub fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
         self.inner.write_all(data)
     }
+
+    /// Close both directions; the peer reads end of stream.
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        self.inner.shutdown(Shutdown::Both)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,224 @@
+// Platform layer for the C runtime: everything that differs between POSIX and Windows
+// lives here, behind one small function each, so runtime.c stays portable C11.
+// Only runtime.c includes this header; generated programs never see it.
+#ifndef GAUT_PLATFORM_H
+#define GAUT_PLATFORM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#if defined(_WIN32)
+#define WIN32_LEAN_AND_MEAN
+#include <fcntl.h>
+#include <io.h>
+#include <winsock2.h>
+#include <ws2tcpip.h>
+#include <windows.h>
+typedef SOCKET gaut_platform_socket;
+#define GAUT_PLATFORM_BAD_SOCKET INVALID_SOCKET
+#else
+#include <errno.h>
+#include <netdb.h>
+#include <netinet/in.h>
+#include <pthread.h>
+#include <sys/socket.h>
+#include <time.h>
+#include <unistd.h>
+#if defined(__unix__) || defined(__APPLE__)
+#include <sys/resource.h>
+#endif
+typedef int gaut_platform_socket;
+#define GAUT_PLATFORM_BAD_SOCKET (-1)
+#endif
+
+// send() flags: a peer that went away is a failed write, not a SIGPIPE, where the
+// platform can say so per call.
+#if defined(MSG_NOSIGNAL)
+#define GAUT_PLATFORM_SEND_FLAGS MSG_NOSIGNAL
+#else
+#define GAUT_PLATFORM_SEND_FLAGS 0
+#endif
+
+// The main thread's stack size in bytes, or `fallback` when the platform cannot say.
+static inline size_t gaut_platform_stack_size(size_t fallback) {
+#if defined(_WIN32)
+    ULONG_PTR low = 0;
+    ULONG_PTR high = 0;
+    GetCurrentThreadStackLimits(&low, &high);
+    return high > low ? (size_t)(high - low) : fallback;
+#elif defined(__unix__) || defined(__APPLE__)
+    struct rlimit rl;
+    if (getrlimit(RLIMIT_STACK, &rl) == 0 && rl.rlim_cur != RLIM_INFINITY) {
+        return (size_t)rl.rlim_cur;
+    }
+    return fallback;
+#else
+    return fallback;
+#endif
+}
+
+// fopen with a UTF-8 path. The Windows CRT reads narrow paths in the ANSI code page, so
+// the path is widened and opened with _wfopen instead.
+static inline FILE* gaut_platform_fopen(const char* path, const char* mode) {
+#if defined(_WIN32)
+    wchar_t wpath[MAX_PATH * 4];
+    wchar_t wmode[8];
+    if (!MultiByteToWideChar(CP_UTF8, 0, path, -1, wpath, (int)(sizeof wpath / sizeof *wpath)) ||
+        !MultiByteToWideChar(CP_UTF8, 0, mode, -1, wmode, (int)(sizeof wmode / sizeof *wmode))) {
+        return NULL;
+    }
+    return _wfopen(wpath, wmode);
+#else
+    return fopen(path, mode);
+#endif
+}
+
+// Block the calling thread for `ms` (> 0) milliseconds, resuming after signals on POSIX.
+static inline void gaut_platform_sleep_ms(int64_t ms) {
+#if defined(_WIN32)
+    Sleep((DWORD)ms);
+#else
+    struct timespec ts = {(time_t)(ms / 1000), (long)(ms % 1000) * 1000000L};
+    while (nanosleep(&ts, &ts) != 0 && errno == EINTR) {
+    }
+#endif
+}
+
+// Read stdin as raw bytes; Windows otherwise turns "\r\n" into "\n" and stops at ^Z.
+static inline void gaut_platform_stdin_binary(void) {
+#if defined(_WIN32)
+    _setmode(_fileno(stdin), _O_BINARY);
+#endif
+}
+
+// Socket setup the net shims call before their first socket: WSAStartup on Windows,
+// nothing elsewhere. Safe to call repeatedly; false when sockets are unavailable.
+static inline bool gaut_platform_net_init(void) {
+#if defined(_WIN32)
+    static bool ready = false;
+    if (!ready) {
+        WSADATA data;
+        ready = WSAStartup(MAKEWORD(2, 2), &data) == 0;
+    }
+    return ready;
+#else
+    return true;
+#endif
+}
+
+static inline void gaut_platform_close_socket(gaut_platform_socket s) {
+#if defined(_WIN32)
+    closesocket(s);
+#else
+    close(s);
+#endif
+}
+
+// A mutex with one condition variable, for queues that threads wait on.
+typedef struct {
+#if defined(_WIN32)
+    SRWLOCK lock;
+    CONDITION_VARIABLE cond;
+#else
+    pthread_mutex_t lock;
+    pthread_cond_t cond;
+#endif
+} gaut_platform_monitor;
+
+static inline void gaut_platform_monitor_init(gaut_platform_monitor* m) {
+#if defined(_WIN32)
+    InitializeSRWLock(&m->lock);
+    InitializeConditionVariable(&m->cond);
+#else
+    pthread_mutex_init(&m->lock, NULL);
+    pthread_cond_init(&m->cond, NULL);
+#endif
+}
+
+static inline void gaut_platform_monitor_enter(gaut_platform_monitor* m) {
+#if defined(_WIN32)
+    AcquireSRWLockExclusive(&m->lock);
+#else
+    pthread_mutex_lock(&m->lock);
+#endif
+}
+
+static inline void gaut_platform_monitor_leave(gaut_platform_monitor* m) {
+#if defined(_WIN32)
+    ReleaseSRWLockExclusive(&m->lock);
+#else
+    pthread_mutex_unlock(&m->lock);
+#endif
+}
+
+// Release the monitor, which must be entered, until another thread notifies it; it is
+// entered again on return. Wakeups may be spurious, so callers wait in a loop.
+static inline void gaut_platform_monitor_wait(gaut_platform_monitor* m) {
+#if defined(_WIN32)
+    SleepConditionVariableSRW(&m->cond, &m->lock, INFINITE, 0);
+#else
+    pthread_cond_wait(&m->cond, &m->lock);
+#endif
+}
+
+static inline void gaut_platform_monitor_notify(gaut_platform_monitor* m) {
+#if defined(_WIN32)
+    WakeConditionVariable(&m->cond);
+#else
+    pthread_cond_signal(&m->cond);
+#endif
+}
+
+struct gaut_platform_thread {
+    void (*run)(void);
+};
+
+#if defined(_WIN32)
+static inline DWORD WINAPI gaut_platform_thread_main(LPVOID arg) {
+#else
+static inline void* gaut_platform_thread_main(void* arg) {
+#endif
+    void (*run)(void) = ((struct gaut_platform_thread*)arg)->run;
+    free(arg);
+    run();
+    return 0;
+}
+
+// Run `run` on a new detached thread with a stack as large as the main thread's, so the
+// stack guard's depth limit holds there too. False when the thread could not start.
+static inline bool gaut_platform_thread_start(void (*run)(void)) {
+    struct gaut_platform_thread* arg = (struct gaut_platform_thread*)malloc(sizeof *arg);
+    if (!arg) {
+        return false;
+    }
+    arg->run = run;
+    size_t stack = gaut_platform_stack_size(8u * 1024 * 1024);
+#if defined(_WIN32)
+    HANDLE thread = CreateThread(NULL, stack, gaut_platform_thread_main, arg,
+                                 STACK_SIZE_PARAM_IS_A_RESERVATION, NULL);
+    if (!thread) {
+        free(arg);
+        return false;
+    }
+    CloseHandle(thread);
+    return true;
+#else
+    pthread_attr_t attr;
+    pthread_t thread;
+    pthread_attr_init(&attr);
+    pthread_attr_setstacksize(&attr, stack);
+    pthread_attr_setdetachstate(&attr, PTHREAD_CREATE_DETACHED);
+    int err = pthread_create(&thread, &attr, gaut_platform_thread_main, arg);
+    pthread_attr_destroy(&attr);
+    if (err != 0) {
+        free(arg);
+        return false;
+    }
+    return true;
+#endif
+}
+
+#endif // GAUT_PLATFORM_H
@@ -1,13 +1,94 @@
 // Minimal C runtime for Gaut-generated programs.
 #include "runtime.h"
+#ifdef GAUT_GC
+// before platform.h, so that the collector learns of threads the runtime starts
+#define GC_THREADS
+#include <gc.h>
+#endif
+#include "platform.h"
+#include <ctype.h>
 #include <limits.h>
+#include <stdatomic.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
+#include <time.h>
 
 static int gaut_argc = 0;
 static char** gaut_argv = NULL;
 
+void gaut_gc_init(void) {
+#ifdef GAUT_GC
+    GC_INIT();
+#endif
+}
+
+void* gaut_heap_alloc(size_t size) {
+#ifdef GAUT_GC
+    return GC_MALLOC(size);
+#else
+    return malloc(size);
+#endif
+}
+
+static _Thread_local int32_t gaut_stack_depth = 0;
+static int32_t gaut_stack_limit = INT32_MAX;
+
+void gaut_stack_guard_init(size_t frame_bytes) {
+    size_t stack = gaut_platform_stack_size(8u * 1024 * 1024);
+    // leave a quarter of the stack for main's frame, libc and the runtime
+    size_t depth = (stack - stack / 4) / (frame_bytes ? frame_bytes : 1);
+    gaut_stack_limit = depth > INT32_MAX ? INT32_MAX : (int32_t)depth;
+    const char* env = getenv("GAUT_STACK_DEPTH");
+    if (env && *env) {
+        long v = strtol(env, NULL, 10);
+        if (v > 0 && v <= INT32_MAX) {
+            gaut_stack_limit = (int32_t)v;
+        }
+    }
+    if (gaut_stack_limit < 1) {
+        gaut_stack_limit = 1;
+    }
+}
+
+void gaut_overflow(const char* op, int64_t a, int64_t b) {
+    fprintf(stderr, "gaut integer overflow: %lld %s %lld\n", (long long)a, op, (long long)b);
+    fflush(stderr);
+    abort();
+}
+
+void gaut_overflow_neg(int64_t a) {
+    fprintf(stderr, "gaut integer overflow: -(%lld)\n", (long long)a);
+    fflush(stderr);
+    abort();
+}
+
+void gaut_div_zero(void) {
+    fprintf(stderr, "gaut division by zero\n");
+    fflush(stderr);
+    abort();
+}
+
+void gaut_shift_range(int64_t b, int bits) {
+    fprintf(stderr, "gaut shift amount %lld out of range for %d-bit integers\n", (long long)b,
+            bits);
+    fflush(stderr);
+    abort();
+}
+
+void gaut_stack_enter(const char* func) {
+    if (++gaut_stack_depth > gaut_stack_limit) {
+        fprintf(stderr, "gaut stack overflow in function %s (depth %d)\n", func,
+                (int)gaut_stack_depth);
+        fflush(stderr);
+        abort();
+    }
+}
+
+void gaut_stack_leave(void) {
+    gaut_stack_depth--;
+}
+
 gaut_arena gaut_arena_from_buffer(uint8_t* buf, size_t cap) {
     gaut_arena arena = {.buf = buf, .cap = cap, .off = 0};
     return arena;
@@ -58,7 +139,7 @@ static void* gaut_alloc_bytes(gaut_arena* arena, size_t size) {
             return ptr;
         }
     }
-    return malloc(size);
+    return gaut_heap_alloc(size);
 }
 
 static char* gaut_str_concat_inner(gaut_arena* arena, const char* a, const char* b) {
@@ -115,11 +196,15 @@ gaut_bytes gaut_bytes_concat_heap(const gaut_bytes* a, const gaut_bytes* b) {
     return gaut_bytes_concat_inner(NULL, a, b);
 }
 
+static bool gaut_print_block(void);
+
 void gaut_print(const char* s) {
     if (s) {
         fputs(s, stdout);
     }
-    fflush(stdout);
+    if (!gaut_print_block()) {
+        fflush(stdout);
+    }
 }
 
 void gaut_println(const char* s) {
@@ -127,6 +212,12 @@ void gaut_println(const char* s) {
         fputs(s, stdout);
     }
     fputc('\n', stdout);
+    if (!gaut_print_block()) {
+        fflush(stdout);
+    }
+}
+
+void gaut_flush(void) {
     fflush(stdout);
 }
 
@@ -134,7 +225,7 @@ char* gaut_read_file(const char* path) {
     if (!path) {
         return NULL;
     }
-    FILE* f = fopen(path, "rb");
+    FILE* f = gaut_platform_fopen(path, "rb");
     if (!f) {
         return NULL;
     }
@@ -151,7 +242,7 @@ char* gaut_read_file(const char* path) {
         fclose(f);
         return NULL;
     }
-    char* buf = (char*)malloc((size_t)len + 1);
+    char* buf = (char*)gaut_heap_alloc((size_t)len + 1);
     if (!buf) {
         fclose(f);
         return NULL;
@@ -166,7 +257,7 @@ int gaut_write_file(const char* path, const char* data) {
     if (!path || !data) {
         return -1;
     }
-    FILE* f = fopen(path, "wb");
+    FILE* f = gaut_platform_fopen(path, "wb");
     if (!f) {
         return -1;
     }
@@ -181,6 +272,126 @@ void gaut_args_init(int argc, char** argv) {
     gaut_argv = argv;
 }
 
+int32_t gaut_arg_count(void) {
+    return gaut_argv ? gaut_argc : 0;
+}
+
+char* gaut_arg_at(int64_t i) {
+    if (!gaut_argv || i < 0 || i >= gaut_argc || !gaut_argv[i]) {
+        return (char*)"";
+    }
+    return gaut_argv[i];
+}
+
+int64_t gaut_now_millis(void) {
+    struct timespec ts;
+    if (!timespec_get(&ts, TIME_UTC)) {
+        return 0;
+    }
+    return (int64_t)ts.tv_sec * 1000 + ts.tv_nsec / 1000000;
+}
+
+// Per thread, like the interpreter, where each spawned thread has its own generator.
+static _Thread_local uint64_t gaut_rand_state = 0;
+static _Thread_local bool gaut_rand_seeded = false;
+
+void gaut_seed_rand(int64_t seed) {
+    gaut_rand_state = (uint64_t)seed;
+    gaut_rand_seeded = true;
+}
+
+int64_t gaut_rand_range(int64_t lo, int64_t hi) {
+    if (!gaut_rand_seeded) {
+        struct timespec ts;
+        int64_t nanos = 0;
+        if (timespec_get(&ts, TIME_UTC)) {
+            nanos = (int64_t)ts.tv_sec * 1000000000 + ts.tv_nsec;
+        }
+        gaut_seed_rand(nanos);
+    }
+    if (hi <= lo) {
+        return lo;
+    }
+    gaut_rand_state += 0x9e3779b97f4a7c15ULL;
+    uint64_t z = gaut_rand_state;
+    z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9ULL;
+    z = (z ^ (z >> 27)) * 0x94d049bb133111ebULL;
+    z ^= z >> 31;
+    uint64_t span = (uint64_t)hi - (uint64_t)lo;
+    return (int64_t)((uint64_t)lo + z % span);
+}
+
+void gaut_sleep_ms(int64_t ms) {
+    if (ms > 0) {
+        gaut_platform_sleep_ms(ms);
+    }
+}
+
+// Copy `len` bytes of `buf` into a fresh allocation of `cap` bytes, releasing the old one.
+static void* gaut_heap_grow(void* buf, size_t len, size_t cap) {
+    void* out = gaut_heap_alloc(cap);
+    if (out && len > 0) {
+        memcpy(out, buf, len);
+    }
+#ifndef GAUT_GC
+    free(buf);
+#endif
+    return out;
+}
+
+char* gaut_read_line(void) {
+    // a prompt printed just before must be visible while we wait
+    fflush(stdout);
+    size_t cap = 64;
+    size_t len = 0;
+    char* buf = (char*)gaut_heap_alloc(cap);
+    if (!buf) {
+        return NULL;
+    }
+    int c;
+    while ((c = fgetc(stdin)) != EOF) {
+        if (len + 2 > cap) {
+            buf = (char*)gaut_heap_grow(buf, len, cap * 2);
+            if (!buf) {
+                return NULL;
+            }
+            cap *= 2;
+        }
+        buf[len++] = (char)c;
+        if (c == '\n') {
+            break;
+        }
+    }
+    buf[len] = '\0';
+    return buf;
+}
+
+gaut_bytes gaut_read_stdin(void) {
+    fflush(stdout);
+    gaut_platform_stdin_binary();
+    gaut_bytes out = {.ptr = NULL, .len = 0};
+    size_t cap = 4096;
+    uint8_t* buf = (uint8_t*)gaut_heap_alloc(cap);
+    if (!buf) {
+        return out;
+    }
+    size_t len = 0;
+    size_t n;
+    while ((n = fread(buf + len, 1, cap - len, stdin)) > 0) {
+        len += n;
+        if (len == cap) {
+            buf = (uint8_t*)gaut_heap_grow(buf, len, cap * 2);
+            if (!buf) {
+                return out;
+            }
+            cap *= 2;
+        }
+    }
+    out.ptr = buf;
+    out.len = len;
+    return out;
+}
+
 gaut_bytes gaut_args(void) {
     gaut_bytes out = {.ptr = NULL, .len = 0};
     if (gaut_argc <= 0 || !gaut_argv) {
@@ -198,7 +409,7 @@ gaut_bytes gaut_args(void) {
     if (total == 0) {
         return out;
     }
-    uint8_t* buf = (uint8_t*)malloc(total);
+    uint8_t* buf = (uint8_t*)gaut_heap_alloc(total);
     if (!buf) {
         return out;
     }
@@ -222,7 +433,7 @@ gaut_bytes gaut_args(void) {
 char* gaut_bytes_to_str(gaut_bytes b) {
     // Best-effort conversion: assume UTF-8 and ensure NUL termination.
     size_t len = b.len;
-    char* out = (char*)malloc(len + 1);
+    char* out = (char*)gaut_heap_alloc(len + 1);
     if (!out) {
         return NULL;
     }
@@ -233,6 +444,345 @@ char* gaut_bytes_to_str(gaut_bytes b) {
     return out;
 }
 
+char* gaut_int_to_str(int64_t n) {
+    char buf[24];
+    int len = snprintf(buf, sizeof(buf), "%lld", (long long)n);
+    char* out = (char*)gaut_heap_alloc((size_t)len + 1);
+    if (!out) {
+        return NULL;
+    }
+    memcpy(out, buf, (size_t)len + 1);
+    return out;
+}
+
+char* gaut_bool_to_str(bool b) {
+    const char* text = b ? "true" : "false";
+    char* out = (char*)gaut_heap_alloc(strlen(text) + 1);
+    if (!out) {
+        return NULL;
+    }
+    memcpy(out, text, strlen(text) + 1);
+    return out;
+}
+
+char* gaut_str_quote(const char* s) {
+    if (!s) {
+        s = "";
+    }
+    // worst case every byte becomes a two-byte escape
+    char* out = (char*)gaut_heap_alloc(strlen(s) * 2 + 3);
+    if (!out) {
+        return NULL;
+    }
+    char* w = out;
+    *w++ = '"';
+    for (const char* p = s; *p; p++) {
+        switch (*p) {
+        case '"':
+            *w++ = '\\';
+            *w++ = '"';
+            break;
+        case '\\':
+            *w++ = '\\';
+            *w++ = '\\';
+            break;
+        case '\n':
+            *w++ = '\\';
+            *w++ = 'n';
+            break;
+        case '\t':
+            *w++ = '\\';
+            *w++ = 't';
+            break;
+        case '\r':
+            *w++ = '\\';
+            *w++ = 'r';
+            break;
+        default:
+            *w++ = *p;
+        }
+    }
+    *w++ = '"';
+    *w = '\0';
+    return out;
+}
+
+bool gaut_str_to_int(const char* s, int64_t* out) {
+    if (!s) {
+        return false;
+    }
+    bool neg = *s == '-';
+    if (*s == '-' || *s == '+') {
+        s++;
+    }
+    if (!*s) {
+        return false;
+    }
+    uint64_t limit = neg ? (uint64_t)INT64_MAX + 1 : (uint64_t)INT64_MAX;
+    uint64_t v = 0;
+    for (; *s; s++) {
+        if (*s < '0' || *s > '9') {
+            return false;
+        }
+        uint64_t d = (uint64_t)(*s - '0');
+        if (v > (limit - d) / 10) {
+            return false;
+        }
+        v = v * 10 + d;
+    }
+    *out = neg ? (int64_t)(0 - v) : (int64_t)v;
+    return true;
+}
+
+char* gaut_json_quote(const char* s) {
+    if (!s) {
+        s = "";
+    }
+    // worst case every byte becomes a six-byte \u00XX escape
+    char* out = (char*)gaut_heap_alloc(strlen(s) * 6 + 3);
+    if (!out) {
+        return NULL;
+    }
+    char* w = out;
+    *w++ = '"';
+    for (const unsigned char* p = (const unsigned char*)s; *p; p++) {
+        switch (*p) {
+        case '"':
+            w += sprintf(w, "\\\"");
+            break;
+        case '\\':
+            w += sprintf(w, "\\\\");
+            break;
+        case '\n':
+            w += sprintf(w, "\\n");
+            break;
+        case '\r':
+            w += sprintf(w, "\\r");
+            break;
+        case '\t':
+            w += sprintf(w, "\\t");
+            break;
+        default:
+            if (*p < 0x20) {
+                w += sprintf(w, "\\u%04x", *p);
+            } else {
+                *w++ = (char)*p;
+            }
+        }
+    }
+    *w++ = '"';
+    *w = '\0';
+    return out;
+}
+
+static _Noreturn void gaut_json_fail(const char* what, const char* key) {
+    fprintf(stderr, "gaut json: %s \"%s\"\n", what, key);
+    abort();
+}
+
+static const char* gaut_json_ws(const char* p) {
+    while (*p && isspace((unsigned char)*p)) {
+        p++;
+    }
+    return p;
+}
+
+// One past the end of the JSON value at `p`, or NULL when it is malformed.
+static const char* gaut_json_skip(const char* p) {
+    if (*p == '"') {
+        for (p++; *p; p++) {
+            if (*p == '\\') {
+                if (!*++p) {
+                    return NULL;
+                }
+            } else if (*p == '"') {
+                return p + 1;
+            }
+        }
+        return NULL;
+    }
+    if (*p == '{' || *p == '[') {
+        size_t depth = 0;
+        while (*p) {
+            if (*p == '"') {
+                p = gaut_json_skip(p);
+                if (!p) {
+                    return NULL;
+                }
+                continue;
+            }
+            if (*p == '{' || *p == '[') {
+                depth++;
+            } else if ((*p == '}' || *p == ']') && --depth == 0) {
+                return p + 1;
+            }
+            p++;
+        }
+        return NULL;
+    }
+    const char* start = p;
+    while (*p && *p != ',' && *p != '}' && *p != ']' && !isspace((unsigned char)*p)) {
+        p++;
+    }
+    return p > start ? p : NULL;
+}
+
+// Decode the string literal [start, end) into a fresh heap string; NULL if malformed.
+static char* gaut_json_unquote(const char* start, const char* end) {
+    if (end - start < 2 || *start != '"' || end[-1] != '"') {
+        return NULL;
+    }
+    char* out = (char*)gaut_heap_alloc((size_t)(end - start));
+    if (!out) {
+        return NULL;
+    }
+    char* w = out;
+    for (const char* p = start + 1; p < end - 1; p++) {
+        if (*p != '\\') {
+            *w++ = *p;
+            continue;
+        }
+        switch (*++p) {
+        case '"':
+        case '\\':
+        case '/':
+            *w++ = *p;
+            break;
+        case 'b':
+            *w++ = '\b';
+            break;
+        case 'f':
+            *w++ = '\f';
+            break;
+        case 'n':
+            *w++ = '\n';
+            break;
+        case 'r':
+            *w++ = '\r';
+            break;
+        case 't':
+            *w++ = '\t';
+            break;
+        case 'u': {
+            unsigned cp = 0;
+            for (int k = 0; k < 4; k++) {
+                char c = *++p;
+                if (!isxdigit((unsigned char)c)) {
+                    return NULL;
+                }
+                cp = cp * 16 + (unsigned)(isdigit((unsigned char)c) ? c - '0' : (tolower(c) - 'a' + 10));
+            }
+            if (cp >= 0xd800 && cp < 0xe000) {
+                return NULL;
+            }
+            // UTF-8 encode; the six escape bytes always have room for it
+            if (cp < 0x80) {
+                *w++ = (char)cp;
+            } else if (cp < 0x800) {
+                *w++ = (char)(0xc0 | (cp >> 6));
+                *w++ = (char)(0x80 | (cp & 0x3f));
+            } else {
+                *w++ = (char)(0xe0 | (cp >> 12));
+                *w++ = (char)(0x80 | ((cp >> 6) & 0x3f));
+                *w++ = (char)(0x80 | (cp & 0x3f));
+            }
+            break;
+        }
+        default:
+            return NULL;
+        }
+    }
+    *w = '\0';
+    return out;
+}
+
+// The value of `key` in the object `doc` as [*start, return value).
+static const char* gaut_json_field(const char* doc, const char* key, const char** start) {
+    const char* p = gaut_json_ws(doc ? doc : "");
+    if (*p != '{') {
+        gaut_json_fail("malformed object looking up", key);
+    }
+    p = gaut_json_ws(p + 1);
+    if (*p == '}') {
+        gaut_json_fail("missing field", key);
+    }
+    for (;;) {
+        const char* name_end = gaut_json_skip(p);
+        char* name = name_end ? gaut_json_unquote(p, name_end) : NULL;
+        if (!name) {
+            gaut_json_fail("malformed object looking up", key);
+        }
+        p = gaut_json_ws(name_end);
+        if (*p != ':') {
+            gaut_json_fail("malformed object looking up", key);
+        }
+        const char* value = gaut_json_ws(p + 1);
+        const char* end = gaut_json_skip(value);
+        if (!end) {
+            gaut_json_fail("malformed object looking up", key);
+        }
+        if (strcmp(name, key) == 0) {
+            *start = value;
+            return end;
+        }
+        p = gaut_json_ws(end);
+        if (*p == ',') {
+            p = gaut_json_ws(p + 1);
+        } else if (*p == '}') {
+            gaut_json_fail("missing field", key);
+        } else {
+            gaut_json_fail("malformed object looking up", key);
+        }
+    }
+}
+
+int64_t gaut_json_get_int(const char* doc, const char* key) {
+    const char* start;
+    const char* end = gaut_json_field(doc, key, &start);
+    char* stop;
+    long long v = strtoll(start, &stop, 10);
+    if (stop != end || stop == start) {
+        gaut_json_fail("not an integer:", key);
+    }
+    return (int64_t)v;
+}
+
+bool gaut_json_get_bool(const char* doc, const char* key) {
+    const char* start;
+    const char* end = gaut_json_field(doc, key, &start);
+    size_t len = (size_t)(end - start);
+    if (len == 4 && strncmp(start, "true", 4) == 0) {
+        return true;
+    }
+    if (len == 5 && strncmp(start, "false", 5) == 0) {
+        return false;
+    }
+    gaut_json_fail("not a bool:", key);
+}
+
+char* gaut_json_get_str(const char* doc, const char* key) {
+    const char* start;
+    const char* end = gaut_json_field(doc, key, &start);
+    char* out = gaut_json_unquote(start, end);
+    if (!out) {
+        gaut_json_fail("not a string:", key);
+    }
+    return out;
+}
+
+char* gaut_json_get_raw(const char* doc, const char* key) {
+    const char* start;
+    const char* end = gaut_json_field(doc, key, &start);
+    size_t len = (size_t)(end - start);
+    char* out = (char*)gaut_heap_alloc(len + 1);
+    if (!out) {
+        return NULL;
+    }
+    memcpy(out, start, len);
+    out[len] = '\0';
+    return out;
+}
+
 int32_t gaut_str_len(const char* s) {
     if (!s) {
         return 0;
@@ -255,9 +805,60 @@ int32_t gaut_str_byte_at(const char* s, int32_t i) {
     return (int32_t)(unsigned char)s[i];
 }
 
+static int32_t gaut_index_checked(const uint8_t* p, size_t len, int64_t i) {
+    if (i < 0 || (uint64_t)i >= len) {
+        fprintf(stderr, "gaut index %lld out of bounds for length %zu\n", (long long)i, len);
+        abort();
+    }
+    return (int32_t)p[i];
+}
+
+int32_t gaut_str_index(const char* s, int64_t i) {
+    return gaut_index_checked((const uint8_t*)s, s ? strlen(s) : 0, i);
+}
+
+int32_t gaut_bytes_index(gaut_bytes b, int64_t i) {
+    return gaut_index_checked(b.ptr, b.len, i);
+}
+
+static size_t gaut_slice_checked(size_t len, int64_t start, int64_t* end, bool to_end) {
+    if (to_end) {
+        *end = (int64_t)len;
+    }
+    if (start < 0 || start > *end || (uint64_t)*end > len) {
+        fprintf(stderr, "gaut slice %lld..%lld out of bounds for length %zu\n", (long long)start,
+            (long long)*end, len);
+        abort();
+    }
+    return (size_t)(*end - start);
+}
+
+char* gaut_str_slice_checked(const char* s, int64_t start, int64_t end, bool to_end) {
+    if (!s) {
+        s = "";
+    }
+    size_t n = gaut_slice_checked(strlen(s), start, &end, to_end);
+    char* out = (char*)gaut_heap_alloc(n + 1);
+    if (!out) {
+        return NULL;
+    }
+    memcpy(out, s + start, n);
+    out[n] = '\0';
+    return out;
+}
+
+gaut_bytes gaut_bytes_slice(gaut_bytes b, int64_t start, int64_t end, bool to_end) {
+    size_t n = gaut_slice_checked(b.len, start, &end, to_end);
+    gaut_bytes out = { .ptr = (uint8_t*)gaut_heap_alloc(n ? n : 1), .len = n };
+    if (out.ptr && n) {
+        memcpy(out.ptr, b.ptr + start, n);
+    }
+    return out;
+}
+
 char* gaut_str_slice(const char* s, int32_t start, int32_t len) {
     if (!s || start < 0 || len < 0) {
-        char* out = (char*)malloc(1);
+        char* out = (char*)gaut_heap_alloc(1);
         if (out) {
             out[0] = '\0';
         }
@@ -272,7 +873,7 @@ char* gaut_str_slice(const char* s, int32_t start, int32_t len) {
     if (st + ln > n) {
         ln = n - st;
     }
-    char* out = (char*)malloc(ln + 1);
+    char* out = (char*)gaut_heap_alloc(ln + 1);
     if (!out) {
         return NULL;
     }
@@ -282,3 +883,675 @@ char* gaut_str_slice(const char* s, int32_t start, int32_t len) {
     out[ln] = '\0';
     return out;
 }
+
+int64_t gaut_bytes_len(gaut_bytes b) {
+    return (int64_t)b.len;
+}
+
+int32_t gaut_bytes_at(gaut_bytes b, int64_t i) {
+    if (i < 0 || (uint64_t)i >= b.len) {
+        return 0;
+    }
+    return (int32_t)b.ptr[i];
+}
+
+gaut_bytes gaut_bytes_slice_clamped(gaut_bytes b, int64_t start, int64_t len) {
+    if (start < 0 || len < 0) {
+        start = 0;
+        len = 0;
+    }
+    size_t st = (uint64_t)start > b.len ? b.len : (size_t)start;
+    size_t n = (uint64_t)len > b.len - st ? b.len - st : (size_t)len;
+    gaut_bytes out = { .ptr = (uint8_t*)gaut_heap_alloc(n ? n : 1), .len = n };
+    if (out.ptr && n) {
+        memcpy(out.ptr, b.ptr + st, n);
+    }
+    return out;
+}
+
+gaut_bytes gaut_bytes_from_str(const char* s) {
+    const size_t n = s ? strlen(s) : 0;
+    gaut_bytes out = { .ptr = (uint8_t*)gaut_heap_alloc(n ? n : 1), .len = n };
+    if (out.ptr && n) {
+        memcpy(out.ptr, s, n);
+    }
+    return out;
+}
+
+void gaut_bytes_push(gaut_bytes* b, int64_t byte) {
+    // the old buffer may be shared with a copy or live in an arena, so never grow it in place
+    uint8_t* ptr = (uint8_t*)gaut_heap_alloc(b->len + 1);
+    if (!ptr) {
+        fprintf(stderr, "gaut bytes_push: out of memory\n");
+        abort();
+    }
+    if (b->len) {
+        memcpy(ptr, b->ptr, b->len);
+    }
+    ptr[b->len] = (uint8_t)byte;
+    b->ptr = ptr;
+    b->len += 1;
+}
+
+bool gaut_str_contains(const char* s, const char* needle) {
+    return gaut_str_find(s, needle) >= 0;
+}
+
+int64_t gaut_str_find(const char* s, const char* needle) {
+    if (!s || !needle) {
+        return -1;
+    }
+    const char* at = strstr(s, needle);
+    return at ? (int64_t)(at - s) : -1;
+}
+
+// The same whitespace Rust's trim_ascii strips, so both backends agree (no '\v').
+static bool gaut_is_trim_space(char c) {
+    return c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\f';
+}
+
+char* gaut_str_trim(const char* s) {
+    if (!s) {
+        return gaut_str_slice("", 0, 0);
+    }
+    size_t start = 0;
+    size_t end = strlen(s);
+    while (start < end && gaut_is_trim_space(s[start])) {
+        start++;
+    }
+    while (end > start && gaut_is_trim_space(s[end - 1])) {
+        end--;
+    }
+    char* out = (char*)gaut_heap_alloc(end - start + 1);
+    if (!out) {
+        return NULL;
+    }
+    memcpy(out, s + start, end - start);
+    out[end - start] = '\0';
+    return out;
+}
+
+static char* gaut_str_map_case(const char* s, bool upper) {
+    const size_t n = s ? strlen(s) : 0;
+    char* out = (char*)gaut_heap_alloc(n + 1);
+    if (!out) {
+        return NULL;
+    }
+    for (size_t i = 0; i < n; i++) {
+        const char c = s[i];
+        if (upper && c >= 'a' && c <= 'z') {
+            out[i] = (char)(c - 'a' + 'A');
+        } else if (!upper && c >= 'A' && c <= 'Z') {
+            out[i] = (char)(c - 'A' + 'a');
+        } else {
+            out[i] = c;
+        }
+    }
+    out[n] = '\0';
+    return out;
+}
+
+char* gaut_str_to_upper(const char* s) {
+    return gaut_str_map_case(s, true);
+}
+
+char* gaut_str_to_lower(const char* s) {
+    return gaut_str_map_case(s, false);
+}
+
+enum { GAUT_LOG_INFO = 0, GAUT_LOG_WARN = 1, GAUT_LOG_ERROR = 2 };
+
+static const char* gaut_log_names[] = {"info", "warn", "error"};
+
+typedef struct {
+    bool ready;
+    size_t arena_cap;
+    int log_level;
+    bool log_json;
+    bool print_block;
+} gaut_config_t;
+
+static gaut_config_t gaut_config = {0};
+
+void gaut_config_init(void) {
+    gaut_config.ready = true;
+    gaut_config.arena_cap = GAUT_DEFAULT_ARENA_CAP;
+    const char* cap = getenv("GAUT_ARENA_CAP");
+    if (cap && *cap) {
+        char* end = NULL;
+        unsigned long long v = strtoull(cap, &end, 10);
+        if (end && *end == '\0' && v > 0) {
+            gaut_config.arena_cap = (size_t)v;
+        }
+    }
+    const char* level = getenv("GAUT_LOG_LEVEL");
+    gaut_config.log_level = GAUT_LOG_INFO;
+    if (level && strcmp(level, "warn") == 0) {
+        gaut_config.log_level = GAUT_LOG_WARN;
+    } else if (level && strcmp(level, "error") == 0) {
+        gaut_config.log_level = GAUT_LOG_ERROR;
+    }
+    const char* format = getenv("GAUT_LOG_FORMAT");
+    gaut_config.log_json = format && strcmp(format, "json") == 0;
+    const char* buffer = getenv("GAUT_PRINT_BUFFER");
+    gaut_config.print_block = buffer && strcmp(buffer, "block") == 0;
+    if (gaut_config.print_block) {
+        // a terminal would otherwise keep stdio's line buffering
+        setvbuf(stdout, NULL, _IOFBF, BUFSIZ);
+    }
+}
+
+static const gaut_config_t* gaut_config_current(void) {
+    if (!gaut_config.ready) {
+        gaut_config_init();
+    }
+    return &gaut_config;
+}
+
+static bool gaut_print_block(void) {
+    return gaut_config_current()->print_block;
+}
+
+bool gaut_set_print_buffer(const char* mode) {
+    bool block = mode && strcmp(mode, "block") == 0;
+    if (!block && !(mode && strcmp(mode, "line") == 0)) {
+        return false;
+    }
+    fflush(stdout);
+    gaut_config_current();
+    gaut_config.print_block = block;
+    return true;
+}
+
+size_t gaut_config_arena_cap(void) {
+    return gaut_config_current()->arena_cap;
+}
+
+gaut_arena gaut_main_arena(void) {
+    size_t cap = gaut_config_arena_cap();
+    uint8_t* buf = (uint8_t*)malloc(cap);
+    if (!buf) {
+        fprintf(stderr, "gaut config: cannot allocate a %zu-byte arena (GAUT_ARENA_CAP)\n", cap);
+        exit(1);
+    }
+    return gaut_arena_from_buffer(buf, cap);
+}
+
+char* gaut_config_get(const char* key) {
+    const gaut_config_t* cfg = gaut_config_current();
+    char buf[64];
+    const char* value = "";
+    if (!key) {
+        key = "";
+    }
+    if (strcmp(key, "arena_cap") == 0) {
+        snprintf(buf, sizeof(buf), "%zu", cfg->arena_cap);
+        value = buf;
+    } else if (strcmp(key, "log_level") == 0) {
+        value = gaut_log_names[cfg->log_level];
+    } else if (strcmp(key, "log_format") == 0) {
+        value = cfg->log_json ? "json" : "text";
+    } else if (strcmp(key, "print_buffer") == 0) {
+        value = cfg->print_block ? "block" : "line";
+    } else {
+        const size_t n = strlen(key);
+        char* name = (char*)malloc(n + 6);
+        if (name) {
+            memcpy(name, "GAUT_", 5);
+            for (size_t i = 0; i < n; i++) {
+                name[5 + i] = (char)toupper((unsigned char)key[i]);
+            }
+            name[5 + n] = '\0';
+            const char* env = getenv(name);
+            value = env ? env : "";
+            free(name);
+        }
+    }
+    const size_t len = strlen(value);
+    char* out = (char*)gaut_heap_alloc(len + 1);
+    if (out) {
+        memcpy(out, value, len + 1);
+    }
+    return out;
+}
+
+static void gaut_log(int level, const char* msg) {
+    const gaut_config_t* cfg = gaut_config_current();
+    if (level < cfg->log_level) {
+        return;
+    }
+    if (!msg) {
+        msg = "";
+    }
+    if (!cfg->log_json) {
+        fprintf(stderr, "[%s] %s\n", gaut_log_names[level], msg);
+        return;
+    }
+    struct timespec ts;
+    long long ts_ms = 0;
+    if (timespec_get(&ts, TIME_UTC)) {
+        ts_ms = (long long)ts.tv_sec * 1000 + ts.tv_nsec / 1000000;
+    }
+    fprintf(stderr, "{\"ts\":%lld,\"level\":\"%s\",\"message\":\"", ts_ms, gaut_log_names[level]);
+    for (const unsigned char* p = (const unsigned char*)msg; *p; p++) {
+        switch (*p) {
+        case '"':
+            fputs("\\\"", stderr);
+            break;
+        case '\\':
+            fputs("\\\\", stderr);
+            break;
+        case '\n':
+            fputs("\\n", stderr);
+            break;
+        case '\r':
+            fputs("\\r", stderr);
+            break;
+        case '\t':
+            fputs("\\t", stderr);
+            break;
+        default:
+            if (*p < 0x20) {
+                fprintf(stderr, "\\u%04x", *p);
+            } else {
+                fputc(*p, stderr);
+            }
+        }
+    }
+    fputs("\"}\n", stderr);
+}
+
+void gaut_log_info(const char* msg) {
+    gaut_log(GAUT_LOG_INFO, msg);
+}
+
+void gaut_log_warn(const char* msg) {
+    gaut_log(GAUT_LOG_WARN, msg);
+}
+
+void gaut_log_error(const char* msg) {
+    gaut_log(GAUT_LOG_ERROR, msg);
+}
+
+struct gaut_atomic_cell {
+    _Atomic int64_t value;
+};
+
+gaut_atomic_i64 gaut_atomic_new(int64_t v) {
+    gaut_atomic_i64 a = (gaut_atomic_i64)gaut_heap_alloc(sizeof(struct gaut_atomic_cell));
+    if (a) {
+        atomic_init(&a->value, v);
+    }
+    return a;
+}
+
+int64_t gaut_atomic_load(gaut_atomic_i64* a) {
+    return atomic_load(&(*a)->value);
+}
+
+void gaut_atomic_store(gaut_atomic_i64* a, int64_t v) {
+    atomic_store(&(*a)->value, v);
+}
+
+int64_t gaut_atomic_add(gaut_atomic_i64* a, int64_t delta) {
+    return atomic_fetch_add(&(*a)->value, delta);
+}
+
+int64_t gaut_atomic_add_trap(gaut_atomic_i64* a, int64_t delta) {
+    int64_t prev = atomic_load(&(*a)->value);
+    int64_t next;
+    do {
+        if (gaut_add_overflow_i64(prev, delta, &next)) {
+            gaut_overflow("+", prev, delta);
+        }
+    } while (!atomic_compare_exchange_weak(&(*a)->value, &prev, next));
+    return prev;
+}
+
+bool gaut_atomic_cas(gaut_atomic_i64* a, int64_t expected, int64_t desired) {
+    return atomic_compare_exchange_strong(&(*a)->value, &expected, desired);
+}
+
+// Open addressing with linear probing; the table doubles before it is half full.
+struct gaut_dyn_map {
+    size_t cap;
+    size_t len;
+    char** keys;
+    char** values;
+};
+
+static char* gaut_dyn_strdup(const char* s) {
+    size_t len = strlen(s ? s : "");
+    char* out = (char*)gaut_heap_alloc(len + 1);
+    if (!out) {
+        fprintf(stderr, "gaut dyn: out of memory\n");
+        abort();
+    }
+    memcpy(out, s ? s : "", len + 1);
+    return out;
+}
+
+static size_t gaut_dyn_slot(char** keys, size_t cap, const char* key) {
+    uint64_t h = 1469598103934665603ULL;
+    for (const unsigned char* p = (const unsigned char*)key; *p; p++) {
+        h = (h ^ *p) * 1099511628211ULL;
+    }
+    size_t i = (size_t)h & (cap - 1);
+    while (keys[i] && strcmp(keys[i], key) != 0) {
+        i = (i + 1) & (cap - 1);
+    }
+    return i;
+}
+
+static void gaut_dyn_alloc(gaut_dyn d, size_t cap) {
+    d->cap = cap;
+    d->keys = (char**)gaut_heap_alloc(cap * sizeof(char*));
+    d->values = (char**)gaut_heap_alloc(cap * sizeof(char*));
+    if (!d->keys || !d->values) {
+        fprintf(stderr, "gaut dyn: out of memory\n");
+        abort();
+    }
+    memset(d->keys, 0, cap * sizeof(char*));
+    memset(d->values, 0, cap * sizeof(char*));
+}
+
+gaut_dyn gaut_dyn_new(void) {
+    gaut_dyn d = (gaut_dyn)gaut_heap_alloc(sizeof(struct gaut_dyn_map));
+    if (!d) {
+        fprintf(stderr, "gaut dyn: out of memory\n");
+        abort();
+    }
+    d->len = 0;
+    gaut_dyn_alloc(d, 8);
+    return d;
+}
+
+void gaut_dyn_set(gaut_dyn d, const char* key, const char* value) {
+    key = key ? key : "";
+    if ((d->len + 1) * 2 > d->cap) {
+        char** keys = d->keys;
+        char** values = d->values;
+        size_t cap = d->cap;
+        gaut_dyn_alloc(d, cap * 2);
+        for (size_t i = 0; i < cap; i++) {
+            if (keys[i]) {
+                size_t j = gaut_dyn_slot(d->keys, d->cap, keys[i]);
+                d->keys[j] = keys[i];
+                d->values[j] = values[i];
+            }
+        }
+#ifndef GAUT_GC
+        free(keys);
+        free(values);
+#endif
+    }
+    size_t i = gaut_dyn_slot(d->keys, d->cap, key);
+    if (!d->keys[i]) {
+        d->keys[i] = gaut_dyn_strdup(key);
+        d->len++;
+    }
+    d->values[i] = gaut_dyn_strdup(value);
+}
+
+char* gaut_dyn_get(gaut_dyn d, const char* key) {
+    return d->values[gaut_dyn_slot(d->keys, d->cap, key ? key : "")];
+}
+
+gaut_dyn gaut_dyn_parse(const char* json) {
+    gaut_dyn d = gaut_dyn_new();
+    const char* p = gaut_json_ws(json ? json : "");
+    if (*p != '{') {
+        gaut_json_fail("malformed object for a", "Dyn");
+    }
+    p = gaut_json_ws(p + 1);
+    if (*p == '}') {
+        return d;
+    }
+    for (;;) {
+        const char* name_end = gaut_json_skip(p);
+        char* name = name_end ? gaut_json_unquote(p, name_end) : NULL;
+        if (!name) {
+            gaut_json_fail("malformed object for a", "Dyn");
+        }
+        p = gaut_json_ws(name_end);
+        if (*p != ':') {
+            gaut_json_fail("malformed object for a", "Dyn");
+        }
+        const char* value = gaut_json_ws(p + 1);
+        const char* end = gaut_json_skip(value);
+        if (!end) {
+            gaut_json_fail("malformed object for a", "Dyn");
+        }
+        char* text = gaut_json_unquote(value, end);
+        if (!text) {
+            size_t len = (size_t)(end - value);
+            text = (char*)gaut_heap_alloc(len + 1);
+            if (!text) {
+                gaut_json_fail("out of memory parsing a", "Dyn");
+            }
+            memcpy(text, value, len);
+            text[len] = '\0';
+        }
+        gaut_dyn_set(d, name, text);
+        p = gaut_json_ws(end);
+        if (*p == ',') {
+            p = gaut_json_ws(p + 1);
+        } else if (*p == '}') {
+            return d;
+        } else {
+            gaut_json_fail("malformed object for a", "Dyn");
+        }
+    }
+}
+
+struct gaut_tcp_socket {
+    gaut_platform_socket fd;
+};
+
+static _Noreturn void gaut_net_fail(const char* what, const char* detail) {
+    fprintf(stderr, "gaut net: %s %s\n", what, detail);
+    abort();
+}
+
+static struct gaut_tcp_socket* gaut_tcp_wrap(gaut_platform_socket fd) {
+    // sockets live until closed, so they never come from an arena
+    struct gaut_tcp_socket* s = (struct gaut_tcp_socket*)malloc(sizeof *s);
+    if (!s) {
+        gaut_platform_close_socket(fd);
+        gaut_net_fail("out of memory for a", "socket");
+    }
+    s->fd = fd;
+    return s;
+}
+
+static void gaut_port_text(int64_t port, char* out, size_t cap) {
+    if (port < 0 || port > 65535) {
+        snprintf(out, cap, "%lld", (long long)port);
+        gaut_net_fail("port out of range:", out);
+    }
+    snprintf(out, cap, "%d", (int)port);
+}
+
+gaut_tcp_listener gaut_tcp_listen(int64_t port) {
+    char text[32];
+    gaut_port_text(port, text, sizeof text);
+    if (!gaut_platform_net_init()) {
+        gaut_net_fail("sockets unavailable for tcp_listen", text);
+    }
+    gaut_platform_socket fd = socket(AF_INET, SOCK_STREAM, 0);
+    if (fd == GAUT_PLATFORM_BAD_SOCKET) {
+        gaut_net_fail("cannot listen on port", text);
+    }
+    int on = 1;
+    setsockopt(fd, SOL_SOCKET, SO_REUSEADDR, (const char*)&on, sizeof on);
+    struct sockaddr_in addr;
+    memset(&addr, 0, sizeof addr);
+    addr.sin_family = AF_INET;
+    addr.sin_addr.s_addr = htonl(INADDR_ANY);
+    addr.sin_port = htons((uint16_t)port);
+    if (bind(fd, (struct sockaddr*)&addr, sizeof addr) != 0 || listen(fd, 128) != 0) {
+        gaut_platform_close_socket(fd);
+        gaut_net_fail("cannot listen on port", text);
+    }
+    return gaut_tcp_wrap(fd);
+}
+
+int64_t gaut_tcp_port(gaut_tcp_listener l) {
+    struct sockaddr_in addr;
+    socklen_t len = sizeof addr;
+    if (getsockname(l->fd, (struct sockaddr*)&addr, &len) != 0) {
+        return 0;
+    }
+    return ntohs(addr.sin_port);
+}
+
+gaut_tcp_conn gaut_tcp_accept(gaut_tcp_listener l) {
+    fflush(stdout);
+    gaut_platform_socket fd = accept(l->fd, NULL, NULL);
+    if (fd == GAUT_PLATFORM_BAD_SOCKET) {
+        gaut_net_fail("accept failed on", "listener");
+    }
+    return gaut_tcp_wrap(fd);
+}
+
+gaut_tcp_conn gaut_tcp_connect(const char* host, int64_t port) {
+    char text[32];
+    gaut_port_text(port, text, sizeof text);
+    if (!gaut_platform_net_init()) {
+        gaut_net_fail("sockets unavailable for tcp_connect", host);
+    }
+    struct addrinfo hints;
+    memset(&hints, 0, sizeof hints);
+    hints.ai_family = AF_UNSPEC;
+    hints.ai_socktype = SOCK_STREAM;
+    struct addrinfo* found = NULL;
+    if (getaddrinfo(host, text, &hints, &found) != 0) {
+        gaut_net_fail("cannot resolve", host);
+    }
+    gaut_platform_socket fd = GAUT_PLATFORM_BAD_SOCKET;
+    for (struct addrinfo* a = found; a; a = a->ai_next) {
+        fd = socket(a->ai_family, a->ai_socktype, a->ai_protocol);
+        if (fd == GAUT_PLATFORM_BAD_SOCKET) {
+            continue;
+        }
+        if (connect(fd, a->ai_addr, (socklen_t)a->ai_addrlen) == 0) {
+            break;
+        }
+        gaut_platform_close_socket(fd);
+        fd = GAUT_PLATFORM_BAD_SOCKET;
+    }
+    freeaddrinfo(found);
+    if (fd == GAUT_PLATFORM_BAD_SOCKET) {
+        gaut_net_fail("cannot connect to", host);
+    }
+    return gaut_tcp_wrap(fd);
+}
+
+gaut_bytes gaut_tcp_read(gaut_tcp_conn c) {
+    fflush(stdout);
+    gaut_bytes out = {.ptr = NULL, .len = 0};
+    if (c->fd == GAUT_PLATFORM_BAD_SOCKET) {
+        return out;
+    }
+    uint8_t* buf = (uint8_t*)gaut_heap_alloc(4096);
+    if (!buf) {
+        return out;
+    }
+    int n = (int)recv(c->fd, (char*)buf, 4096, 0);
+    if (n > 0) {
+        out.ptr = buf;
+        out.len = (size_t)n;
+    }
+    return out;
+}
+
+bool gaut_tcp_write(gaut_tcp_conn c, gaut_bytes data) {
+    size_t sent = 0;
+    while (c->fd != GAUT_PLATFORM_BAD_SOCKET && sent < data.len) {
+        int n = (int)send(c->fd, (const char*)data.ptr + sent, (int)(data.len - sent),
+                          GAUT_PLATFORM_SEND_FLAGS);
+        if (n <= 0) {
+            return false;
+        }
+        sent += (size_t)n;
+    }
+    return sent == data.len;
+}
+
+void gaut_tcp_close(gaut_tcp_conn c) {
+    if (c->fd != GAUT_PLATFORM_BAD_SOCKET) {
+        gaut_platform_close_socket(c->fd);
+        c->fd = GAUT_PLATFORM_BAD_SOCKET;
+    }
+}
+
+void gaut_spawn(void (*f)(void)) {
+    fflush(stdout);
+    if (!gaut_platform_thread_start(f)) {
+        fprintf(stderr, "gaut thread: cannot start a thread\n");
+        abort();
+    }
+}
+
+struct gaut_chan_msg {
+    char* text;
+    struct gaut_chan_msg* next;
+};
+
+struct gaut_chan_queue {
+    gaut_platform_monitor monitor;
+    struct gaut_chan_msg* head;
+    struct gaut_chan_msg* tail;
+};
+
+gaut_chan gaut_chan_new(void) {
+    gaut_chan c = (gaut_chan)gaut_heap_alloc(sizeof(struct gaut_chan_queue));
+    if (!c) {
+        fprintf(stderr, "gaut thread: out of memory\n");
+        abort();
+    }
+    gaut_platform_monitor_init(&c->monitor);
+    c->head = NULL;
+    c->tail = NULL;
+    return c;
+}
+
+void gaut_chan_send(gaut_chan c, const char* msg) {
+    // the sender's copy may live in its arena, which is gone by the time it is read
+    struct gaut_chan_msg* m = (struct gaut_chan_msg*)gaut_heap_alloc(sizeof *m);
+    char* text = gaut_str_concat_heap(msg, "");
+    if (!m || !text) {
+        fprintf(stderr, "gaut thread: out of memory\n");
+        abort();
+    }
+    m->text = text;
+    m->next = NULL;
+    gaut_platform_monitor_enter(&c->monitor);
+    if (c->tail) {
+        c->tail->next = m;
+    } else {
+        c->head = m;
+    }
+    c->tail = m;
+    gaut_platform_monitor_notify(&c->monitor);
+    gaut_platform_monitor_leave(&c->monitor);
+}
+
+char* gaut_chan_recv(gaut_chan c) {
+    fflush(stdout);
+    gaut_platform_monitor_enter(&c->monitor);
+    while (!c->head) {
+        gaut_platform_monitor_wait(&c->monitor);
+    }
+    struct gaut_chan_msg* m = c->head;
+    c->head = m->next;
+    if (!c->head) {
+        c->tail = NULL;
+    }
+    gaut_platform_monitor_leave(&c->monitor);
+    char* text = m->text;
+#ifndef GAUT_GC
+    free(m);
+#endif
+    return text;
+}
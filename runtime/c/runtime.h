@@ -9,6 +9,140 @@
 
 #define GAUT_DEFAULT_ARENA_CAP 65536
 
+// Default-integer arithmetic under `--overflow wrap` (and shifts always) wraps (two's
+// complement) like the interpreter instead of overflowing into undefined behavior.
+// Macros so global initializers stay constant.
+#define GAUT_ADD_I32(a, b) ((int32_t)((uint32_t)(a) + (uint32_t)(b)))
+#define GAUT_SUB_I32(a, b) ((int32_t)((uint32_t)(a) - (uint32_t)(b)))
+#define GAUT_MUL_I32(a, b) ((int32_t)((uint32_t)(a) * (uint32_t)(b)))
+#define GAUT_SHL_I32(a, b) ((int32_t)((uint32_t)(a) << (b)))
+#define GAUT_NEG_I32(a) ((int32_t)(0u - (uint32_t)(a)))
+#define GAUT_ADD_I64(a, b) ((int64_t)((uint64_t)(a) + (uint64_t)(b)))
+#define GAUT_SUB_I64(a, b) ((int64_t)((uint64_t)(a) - (uint64_t)(b)))
+#define GAUT_MUL_I64(a, b) ((int64_t)((uint64_t)(a) * (uint64_t)(b)))
+#define GAUT_SHL_I64(a, b) ((int64_t)((uint64_t)(a) << (b)))
+#define GAUT_NEG_I64(a) ((int64_t)(0u - (uint64_t)(a)))
+
+// Aborts with "division by zero" like the interpreter's runtime error.
+void gaut_div_zero(void);
+
+// A shift count checked against the default integer's width; out of range it aborts
+// like the interpreter's "shift amount out of range" error instead of shifting into
+// undefined behavior.
+void gaut_shift_range(int64_t b, int bits);
+static inline int64_t gaut_shift_count(int64_t b, int bits) {
+    if (b < 0 || b >= bits) {
+        gaut_shift_range(b, bits);
+    }
+    return b;
+}
+
+// MIN / -1 wraps to MIN; a zero divisor aborts in both overflow modes.
+static inline int32_t gaut_div_i32(int32_t a, int32_t b) {
+    if (b == 0) {
+        gaut_div_zero();
+    }
+    return b == -1 ? GAUT_NEG_I32(a) : a / b;
+}
+static inline int64_t gaut_div_i64(int64_t a, int64_t b) {
+    if (b == 0) {
+        gaut_div_zero();
+    }
+    return b == -1 ? GAUT_NEG_I64(a) : a / b;
+}
+
+// The default `--overflow trap` mode: a result outside the integer model aborts with
+// "integer overflow: a op b" like the interpreter's runtime error.
+void gaut_overflow(const char* op, int64_t a, int64_t b);
+void gaut_overflow_neg(int64_t a);
+
+// `r = a op b`, wrapping; true when the exact result does not fit. The compiler's
+// overflow builtins where it has them, plain C elsewhere (MSVC, `--ansi` builds).
+#if defined(__GNUC__) || defined(__clang__)
+#define GAUT_OVERFLOW_OP(name, t, builtin)                                                  \
+    static inline bool name(t a, t b, t* r) { return builtin(a, b, r); }
+GAUT_OVERFLOW_OP(gaut_add_overflow_i32, int32_t, __builtin_add_overflow)
+GAUT_OVERFLOW_OP(gaut_sub_overflow_i32, int32_t, __builtin_sub_overflow)
+GAUT_OVERFLOW_OP(gaut_mul_overflow_i32, int32_t, __builtin_mul_overflow)
+GAUT_OVERFLOW_OP(gaut_add_overflow_i64, int64_t, __builtin_add_overflow)
+GAUT_OVERFLOW_OP(gaut_sub_overflow_i64, int64_t, __builtin_sub_overflow)
+GAUT_OVERFLOW_OP(gaut_mul_overflow_i64, int64_t, __builtin_mul_overflow)
+#undef GAUT_OVERFLOW_OP
+#else
+#define GAUT_OVERFLOW_OP_I32(name, op, wrap)                                                \
+    static inline bool name(int32_t a, int32_t b, int32_t* r) {                            \
+        int64_t wide = (int64_t)a op b;                                                    \
+        *r = wrap(a, b);                                                                   \
+        return wide < INT32_MIN || wide > INT32_MAX;                                       \
+    }
+GAUT_OVERFLOW_OP_I32(gaut_add_overflow_i32, +, GAUT_ADD_I32)
+GAUT_OVERFLOW_OP_I32(gaut_sub_overflow_i32, -, GAUT_SUB_I32)
+GAUT_OVERFLOW_OP_I32(gaut_mul_overflow_i32, *, GAUT_MUL_I32)
+#undef GAUT_OVERFLOW_OP_I32
+static inline bool gaut_add_overflow_i64(int64_t a, int64_t b, int64_t* r) {
+    *r = GAUT_ADD_I64(a, b);
+    return (b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b);
+}
+static inline bool gaut_sub_overflow_i64(int64_t a, int64_t b, int64_t* r) {
+    *r = GAUT_SUB_I64(a, b);
+    return (b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b);
+}
+static inline bool gaut_mul_overflow_i64(int64_t a, int64_t b, int64_t* r) {
+    *r = GAUT_MUL_I64(a, b);
+    return a != 0 && b != 0 &&
+           ((a == -1 && b == INT64_MIN) || (b == -1 && a == INT64_MIN) ||
+            (a != -1 && b != -1 && *r / b != a));
+}
+#endif
+
+#define GAUT_TRAP_OP(name, t, check, op)                                                    \
+    static inline t name(t a, t b) {                                                       \
+        t r;                                                                               \
+        if (check(a, b, &r)) {                                                             \
+            gaut_overflow(op, a, b);                                                       \
+        }                                                                                  \
+        return r;                                                                          \
+    }
+GAUT_TRAP_OP(gaut_trap_add_i32, int32_t, gaut_add_overflow_i32, "+")
+GAUT_TRAP_OP(gaut_trap_sub_i32, int32_t, gaut_sub_overflow_i32, "-")
+GAUT_TRAP_OP(gaut_trap_mul_i32, int32_t, gaut_mul_overflow_i32, "*")
+GAUT_TRAP_OP(gaut_trap_add_i64, int64_t, gaut_add_overflow_i64, "+")
+GAUT_TRAP_OP(gaut_trap_sub_i64, int64_t, gaut_sub_overflow_i64, "-")
+GAUT_TRAP_OP(gaut_trap_mul_i64, int64_t, gaut_mul_overflow_i64, "*")
+#undef GAUT_TRAP_OP
+
+static inline int32_t gaut_trap_neg_i32(int32_t a) {
+    if (a == INT32_MIN) {
+        gaut_overflow_neg(a);
+    }
+    return -a;
+}
+static inline int64_t gaut_trap_neg_i64(int64_t a) {
+    if (a == INT64_MIN) {
+        gaut_overflow_neg(a);
+    }
+    return -a;
+}
+// MIN / -1 is the one quotient that does not fit.
+static inline int32_t gaut_trap_div_i32(int32_t a, int32_t b) {
+    if (b == 0) {
+        gaut_div_zero();
+    }
+    if (b == -1 && a == INT32_MIN) {
+        gaut_overflow("/", a, b);
+    }
+    return a / b;
+}
+static inline int64_t gaut_trap_div_i64(int64_t a, int64_t b) {
+    if (b == 0) {
+        gaut_div_zero();
+    }
+    if (b == -1 && a == INT64_MIN) {
+        gaut_overflow("/", a, b);
+    }
+    return a / b;
+}
+
 typedef struct {
     uint8_t* buf;
     size_t cap;
@@ -24,6 +158,33 @@ typedef struct {
     size_t len;
 } gaut_bytes;
 
+// Opaque shared counter; the C11 _Atomic storage lives in runtime.c.
+typedef struct gaut_atomic_cell* gaut_atomic_i64;
+
+// Opaque shared string map behind the `Dyn` type; copies alias the same table.
+typedef struct gaut_dyn_map* gaut_dyn;
+
+// Opaque TCP sockets behind `TcpListener` and `TcpConn`; copies alias the same socket.
+typedef struct gaut_tcp_socket* gaut_tcp_listener;
+typedef struct gaut_tcp_socket* gaut_tcp_conn;
+
+// Opaque message queue behind the `Chan` type; copies, in any thread, share the queue.
+typedef struct gaut_chan_queue* gaut_chan;
+
+// Heap storage for values that outlive an arena scope. When runtime.c is built with
+// -DGAUT_GC (and linked with -lgc) it comes from the Boehm collector and is never freed
+// explicitly; otherwise it is plain malloc. gaut_gc_init must run first in main.
+void gaut_gc_init(void);
+void* gaut_heap_alloc(size_t size);
+
+// Recursion guard emitted by `--stack-guard`: every function calls gaut_stack_enter on
+// entry and gaut_stack_leave on exit. The depth limit is the stack rlimit divided by
+// frame_bytes (overridable with GAUT_STACK_DEPTH); exceeding it prints
+// "gaut stack overflow in function X" and aborts.
+void gaut_stack_guard_init(size_t frame_bytes);
+void gaut_stack_enter(const char* func);
+void gaut_stack_leave(void);
+
 gaut_arena gaut_arena_from_buffer(uint8_t* buf, size_t cap);
 gaut_scope gaut_scope_enter(gaut_arena* arena);
 void gaut_scope_leave(gaut_arena* arena, gaut_scope scope);
@@ -33,15 +194,124 @@ char* gaut_str_concat_arena(gaut_arena* arena, const char* a, const char* b);
 char* gaut_str_concat_heap(const char* a, const char* b);
 gaut_bytes gaut_bytes_concat_arena(gaut_arena* arena, const gaut_bytes* a, const gaut_bytes* b);
 gaut_bytes gaut_bytes_concat_heap(const gaut_bytes* a, const gaut_bytes* b);
+// Both flush stdout after writing unless block buffering is on (GAUT_PRINT_BUFFER=block
+// or gaut_set_print_buffer("block")); gaut_flush writes out whatever is held.
 void gaut_print(const char* s);
 void gaut_println(const char* s);
+void gaut_flush(void);
+bool gaut_set_print_buffer(const char* mode);
 char* gaut_read_file(const char* path);
 int gaut_write_file(const char* path, const char* data);
+// Both flush stdout first so prompts show. gaut_read_line returns the next line of stdin
+// with its '\n' ("" at end of input); gaut_read_stdin returns everything left.
+char* gaut_read_line(void);
+gaut_bytes gaut_read_stdin(void);
 void gaut_args_init(int argc, char** argv);
 gaut_bytes gaut_args(void);
+// argv one entry at a time, argv[0] first; an index outside argv reads as ""
+int32_t gaut_arg_count(void);
+char* gaut_arg_at(int64_t i);
+// wall-clock milliseconds; only differences are meaningful (`time { ... }`)
+int64_t gaut_now_millis(void);
+// Pause the calling thread for at least `ms` milliseconds; negative values return at once.
+void gaut_sleep_ms(int64_t ms);
+// SplitMix64, step for step like the interpreter's runtime::Rng. Until the first
+// gaut_seed_rand the generator is seeded from the clock.
+void gaut_seed_rand(int64_t seed);
+// A number in [lo, hi); lo when the range is empty.
+int64_t gaut_rand_range(int64_t lo, int64_t hi);
 char* gaut_bytes_to_str(gaut_bytes b);
+char* gaut_int_to_str(int64_t n);
+// `to_str`: a bool as `true`/`false`, and a string as a quoted literal escaping
+// `"`, `\`, newline, tab and carriage return, like the interpreter's value display
+char* gaut_bool_to_str(bool b);
+char* gaut_str_quote(const char* s);
+// Parse an optionally signed run of decimal digits filling the whole string; false on
+// anything else, including surrounding whitespace and int64 overflow.
+bool gaut_str_to_int(const char* s, int64_t* out);
+// JSON for `@derive(json)`: a quoted string literal, and field lookups in an object
+// that print the problem to stderr and abort on a missing key or a mistyped value
+char* gaut_json_quote(const char* s);
+int64_t gaut_json_get_int(const char* doc, const char* key);
+bool gaut_json_get_bool(const char* doc, const char* key);
+char* gaut_json_get_str(const char* doc, const char* key);
+char* gaut_json_get_raw(const char* doc, const char* key);
 int32_t gaut_str_len(const char* s);
 int32_t gaut_str_byte_at(const char* s, int32_t i);
+// `s[i]` / `b[i]`: print the index and length to stderr and abort when out of range
+int32_t gaut_str_index(const char* s, int64_t i);
+int32_t gaut_bytes_index(gaut_bytes b, int64_t i);
+// `s[a..b]`: a heap copy of the range, aborting unless 0 <= start <= end <= length;
+// `to_end` stands for an omitted upper bound
+char* gaut_str_slice_checked(const char* s, int64_t start, int64_t end, bool to_end);
+gaut_bytes gaut_bytes_slice(gaut_bytes b, int64_t start, int64_t end, bool to_end);
 char* gaut_str_slice(const char* s, int32_t start, int32_t len);
+// Lenient like the str_ helpers: an index outside `b` reads as 0 and slices clamp to
+// the bytes there are. gaut_bytes_push appends the low 8 bits of `byte` by copying.
+int64_t gaut_bytes_len(gaut_bytes b);
+int32_t gaut_bytes_at(gaut_bytes b, int64_t i);
+gaut_bytes gaut_bytes_slice_clamped(gaut_bytes b, int64_t start, int64_t len);
+gaut_bytes gaut_bytes_from_str(const char* s);
+void gaut_bytes_push(gaut_bytes* b, int64_t byte);
+// Byte offset of the first occurrence of `needle` (0 for ""), or -1.
+bool gaut_str_contains(const char* s, const char* needle);
+int64_t gaut_str_find(const char* s, const char* needle);
+// Heap copies: ASCII whitespace trimmed from both ends, or ASCII letters case-mapped
+// (other bytes, including UTF-8 sequences, are copied unchanged).
+char* gaut_str_trim(const char* s);
+char* gaut_str_to_upper(const char* s);
+char* gaut_str_to_lower(const char* s);
+// Startup configuration read from GAUT_ARENA_CAP, GAUT_LOG_LEVEL (info|warn|error)
+// and GAUT_LOG_FORMAT (text|json). gaut_config_get("key") reports known settings and
+// falls back to the GAUT_<KEY> environment variable ("" when unset).
+void gaut_config_init(void);
+size_t gaut_config_arena_cap(void);
+char* gaut_config_get(const char* key);
+// main's arena, sized by GAUT_ARENA_CAP; exits with a `gaut config:` message if the
+// buffer cannot be allocated.
+gaut_arena gaut_main_arena(void);
+
+// Log records go to stderr, filtered and formatted according to the config.
+void gaut_log_info(const char* msg);
+void gaut_log_warn(const char* msg);
+void gaut_log_error(const char* msg);
+
+// `atomic_i64` cells, called directly for the gaut builtins of the same names without
+// the prefix. gaut_atomic_add wraps; gaut_atomic_add_trap aborts like the overflow
+// traps instead of storing a wrapped sum.
+gaut_atomic_i64 gaut_atomic_new(int64_t v);
+int64_t gaut_atomic_load(gaut_atomic_i64* a);
+void gaut_atomic_store(gaut_atomic_i64* a, int64_t v);
+int64_t gaut_atomic_add(gaut_atomic_i64* a, int64_t delta);
+int64_t gaut_atomic_add_trap(gaut_atomic_i64* a, int64_t delta);
+bool gaut_atomic_cas(gaut_atomic_i64* a, int64_t expected, int64_t desired);
+
+// `Dyn` maps. gaut_dyn_parse reads a JSON object, storing string members decoded and
+// any other member as its JSON text; a malformed object aborts like the JSON helpers.
+// gaut_dyn_get returns NULL for a missing key.
+gaut_dyn gaut_dyn_new(void);
+gaut_dyn gaut_dyn_parse(const char* json);
+void gaut_dyn_set(gaut_dyn d, const char* key, const char* value);
+char* gaut_dyn_get(gaut_dyn d, const char* key);
+
+// TCP over the platform's sockets. Failing to listen, accept or connect aborts with a
+// "gaut net:" message like the JSON helpers; gaut_tcp_read returns at most 4096 bytes
+// and empty bytes at end of stream or on error, and gaut_tcp_write reports whether
+// every byte was sent.
+gaut_tcp_listener gaut_tcp_listen(int64_t port);
+int64_t gaut_tcp_port(gaut_tcp_listener l);
+gaut_tcp_conn gaut_tcp_accept(gaut_tcp_listener l);
+gaut_tcp_conn gaut_tcp_connect(const char* host, int64_t port);
+gaut_bytes gaut_tcp_read(gaut_tcp_conn c);
+bool gaut_tcp_write(gaut_tcp_conn c, gaut_bytes data);
+void gaut_tcp_close(gaut_tcp_conn c);
+
+// Threads and channels. gaut_spawn starts `f` on a detached thread, aborting with a
+// "gaut thread:" message when it cannot; the program ends when main returns. Each
+// message is copied to the heap, and gaut_chan_recv waits for the oldest one.
+void gaut_spawn(void (*f)(void));
+gaut_chan gaut_chan_new(void);
+void gaut_chan_send(gaut_chan c, const char* msg);
+char* gaut_chan_recv(gaut_chan c);
 
 #endif // GAUT_RUNTIME_H
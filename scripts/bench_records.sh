@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Build examples/big_record.gaut (16-field records returned through two calls per
+# leaf) with -O2 and time a few runs. Set CC to compare compilers; pass a different
+# .gaut file as $1 to benchmark another program.
+
+ROOT=$(cd -- "$(dirname "$0")/.." && pwd)
+OUT="$ROOT/target/bench"
+SRC="${1:-$ROOT/examples/big_record.gaut}"
+CC="${CC:-clang}"
+RUNS="${RUNS:-5}"
+mkdir -p "$OUT"
+
+name=$(basename "$SRC" .gaut)
+cargo run -q -p cli -- --emit-c "$OUT/$name.c" "$SRC"
+"$CC" -std=gnu11 -O2 -I"$ROOT/runtime/c" "$OUT/$name.c" "$ROOT/runtime/c/runtime.c" \
+  -o "$OUT/$name"
+
+echo "==> $name x$RUNS ($CC -O2)"
+for _ in $(seq "$RUNS"); do
+  start=$(date +%s%N)
+  "$OUT/$name" >/dev/null || true
+  end=$(date +%s%N)
+  echo "   $(( (end - start) / 1000000 )) ms"
+done